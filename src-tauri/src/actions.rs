@@ -0,0 +1,451 @@
+use crate::connectivity;
+use crate::images::extract_image_urls;
+use crate::proxy::unproxied_url;
+use crate::shared::{absolutize_url, logic_extract_article, logic_fetch_raw_html, logic_fetch_share_metadata, with_protocol_for, ArticleOptions, ProxyState};
+use crate::single_file::{logic_build_article_html, HtmlExportOptions};
+use crate::versions;
+use futures_util::StreamExt;
+use reqwest::header;
+use scraper::{Html, Selector};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::path::{Component, Path, PathBuf};
+use tokio::io::AsyncWriteExt;
+use tokio::time::Duration;
+use url::Url;
+
+/// Event emitted as an article action goes. The payload is an `ArticleActionProgress`.
+pub const ARTICLE_ACTION_PROGRESS_EVENT: &str = "article-action-progress";
+
+/// Event emitted when an action recorded offline has run. The payload is a `QueuedActionOutcome`.
+pub const QUEUED_ACTION_DONE_EVENT: &str = "queued-action-done";
+
+/// Per-file timeout of `download_media`
+const MEDIA_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Largest media file downloaded; bigger ones are skipped
+const MAX_MEDIA_BYTES: u64 = 500 * 1024 * 1024;
+
+/// Query parameters dropped by `copy_clean_url` (plus every `utm_*`)
+const TRACKING_PARAMS: &[&str] = &["fbclid", "gclid", "dclid", "msclkid", "mc_cid", "mc_eid", "igshid", "yclid", "_hsenc", "_hsmi", "ref_src", "ref_url", "cmpid", "xtor"];
+
+/// Actions of the action bar injected in proxied pages
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ArticleAction {
+    /// Standalone HTML file of the article (see `single_file`)
+    SaveOffline,
+    /// Tracks the article's versions as starred and stores the current one
+    StarArchive,
+    /// URL of the configured share service, for the app to open
+    Share,
+    /// Every image, video and audio file of the page, to the download directory
+    DownloadMedia,
+    /// The article URL without tracking parameters, for the app to copy
+    CopyCleanUrl,
+}
+
+impl ArticleAction {
+    fn name(self) -> &'static str {
+        match self {
+            Self::SaveOffline => "save_offline",
+            Self::StarArchive => "star_archive",
+            Self::Share => "share",
+            Self::DownloadMedia => "download_media",
+            Self::CopyCleanUrl => "copy_clean_url",
+        }
+    }
+}
+
+/// Action bar and action settings
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ArticleActionConfig {
+    /// The bar is injected in proxied pages, hidden until `TOGGLE_ACTION_BAR` (or Alt+Shift+A
+    /// inside the page)
+    pub action_bar: bool,
+    /// Share service URL, `{url}` and `{title}` replaced by the encoded article URL and title
+    /// (e.g. `https://getpocket.com/save?url={url}&title={title}`)
+    pub share_url_template: Option<String>,
+    /// Where `save_offline` and `download_media` write. Paths given to an action must resolve
+    /// inside it.
+    pub download_dir: Option<String>,
+}
+
+/// Optional parameters of an action
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct ArticleActionParams {
+    /// File written by `save_offline`, within the download directory
+    pub path: Option<String>,
+    /// Directory written to, within the download directory
+    pub dir: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ArticleActionProgress {
+    pub session_id: String,
+    pub action: ArticleAction,
+    /// What is being done ("extracting", "downloading", ...)
+    pub step: &'static str,
+    pub done: usize,
+    pub total: usize,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ArticleActionResult {
+    pub action: ArticleAction,
+    /// Original URL of the session's article
+    pub url: String,
+    /// File written (`save_offline`) or directory written to (`download_media`)
+    pub path: Option<String>,
+    /// For the app to open (`share`)
+    pub open_url: Option<String>,
+    /// For the app to put on the clipboard (`copy_clean_url`)
+    pub clipboard: Option<String>,
+    /// Files written
+    pub files: usize,
+    /// Files that couldn't be downloaded
+    pub failed: usize,
+}
+
+/// Outcome of an action recorded offline, run once back online
+#[derive(Debug, Clone, Serialize)]
+pub struct QueuedActionOutcome {
+    pub session_id: String,
+    pub action: ArticleAction,
+    pub result: Option<ArticleActionResult>,
+    pub error: Option<String>,
+}
+
+impl ArticleActionResult {
+    fn new(action: ArticleAction, url: &Url) -> Self {
+        Self { action, url: url.to_string(), path: None, open_url: None, clipboard: None, files: 0, failed: 0 }
+    }
+}
+
+pub fn logic_get_article_action_config(state: &ProxyState) -> ArticleActionConfig {
+    ArticleActionConfig::clone(&state.article_actions.load())
+}
+
+/// The action bar setting applies to pages proxied from now on
+pub fn logic_set_article_action_config(config: ArticleActionConfig, state: &ProxyState) -> Result<(), String> {
+    if let Some(template) = &config.share_url_template {
+        let sample = template.replace("{url}", "x").replace("{title}", "x");
+        if !template.contains("{url}") || Url::parse(&sample).is_err() {
+            return Err(format!("Share URL template must be a URL containing {{url}}, got '{}'", template));
+        }
+    }
+    println!("[actions::set_article_action_config] Action bar {}", if config.action_bar { "on" } else { "off" });
+    state.article_actions.store(std::sync::Arc::new(config));
+    Ok(())
+}
+
+fn is_local(url: &Url) -> bool {
+    matches!(url.host_str(), Some("localhost" | "127.0.0.1"))
+}
+
+/// Original URL of the article a page load shows: the upstream URL of its features report,
+/// else the article being proxied. Proxy URLs are never returned.
+fn session_url(session_id: &str, state: &ProxyState) -> Result<Url, String> {
+    let reported = state.page_reports.lock().unwrap().page_url(session_id);
+    let url = match reported {
+        Some(page_url) => unproxied_url(&page_url, state),
+        None => state.base_url.load().to_string(),
+    };
+    let url = Url::parse(&url).map_err(|e| format!("No article URL for session {}: {}", session_id, e))?;
+    if is_local(&url) || !matches!(url.scheme(), "http" | "https") {
+        return Err(format!("No article URL for session {}", session_id));
+    }
+    Ok(url)
+}
+
+/// `url` without tracking parameters and fragment
+pub fn clean_url(url: &Url) -> Url {
+    let mut clean = url.clone();
+    let kept: Vec<(String, String)> = url
+        .query_pairs()
+        .filter(|(key, _)| {
+            let key = key.to_ascii_lowercase();
+            !key.starts_with("utm_") && !TRACKING_PARAMS.contains(&key.as_str())
+        })
+        .map(|(key, value)| (key.into_owned(), value.into_owned()))
+        .collect();
+    if kept.is_empty() {
+        clean.set_query(None);
+    } else {
+        clean.query_pairs_mut().clear().extend_pairs(kept);
+    }
+    clean.set_fragment(None);
+    clean
+}
+
+/// File name for `url` (last path segment, else the host), reduced to safe characters
+fn file_name_for(url: &Url, default_extension: &str) -> String {
+    let segment = url.path_segments().and_then(|mut segments| segments.next_back().map(str::to_string)).filter(|s| !s.is_empty());
+    let raw = segment.unwrap_or_else(|| url.host_str().unwrap_or("article").to_string());
+    let raw = urlencoding::decode(&raw).map(|s| s.into_owned()).unwrap_or(raw);
+    let mut name: String = raw.chars().map(|c| if c.is_alphanumeric() || matches!(c, '.' | '-' | '_') { c } else { '_' }).take(100).collect();
+    name = name.trim_start_matches('.').to_string();
+    if name.is_empty() {
+        name = "file".to_string();
+    }
+    if !name.contains('.') {
+        name.push_str(default_extension);
+    }
+    name
+}
+
+/// `dir/name`, with a counter before the extension if taken
+fn unique_path(dir: &Path, name: &str, taken: &mut HashSet<PathBuf>) -> PathBuf {
+    let (stem, extension) = name.rsplit_once('.').map_or((name, ""), |(stem, extension)| (stem, extension));
+    let mut path = dir.join(name);
+    let mut n = 1;
+    // `symlink_metadata`, so a dangling link isn't taken for a free name and written through
+    while taken.contains(&path) || path.symlink_metadata().is_ok() {
+        path = dir.join(format!("{}-{}.{}", stem, n, extension));
+        n += 1;
+    }
+    taken.insert(path.clone());
+    path
+}
+
+/// `given` resolved inside the configured download directory: relative to it, or absolute
+/// under it. `..` segments are refused, and the deepest existing part of the path is
+/// canonicalized before the check so a symlink can't lead out of the directory either.
+fn confined_path(given: &Path, state: &ProxyState) -> Result<PathBuf, String> {
+    let root = state.article_actions.load().download_dir.clone().ok_or("No download directory configured")?;
+    let root = std::fs::canonicalize(&root).map_err(|e| format!("Download directory {}: {}", root, e))?;
+    if given.components().any(|component| component == Component::ParentDir) {
+        return Err(format!("{} is outside the download directory", given.display()));
+    }
+    let path = root.join(given);
+    let existing = path.ancestors().find(|ancestor| ancestor.symlink_metadata().is_ok()).unwrap_or(root.as_path());
+    let resolved = std::fs::canonicalize(existing).map_err(|e| format!("{}: {}", existing.display(), e))?;
+    if !resolved.starts_with(&root) {
+        return Err(format!("{} is outside the download directory", given.display()));
+    }
+    let rest = path.strip_prefix(existing).unwrap_or(Path::new(""));
+    Ok(if rest.as_os_str().is_empty() { resolved } else { resolved.join(rest) })
+}
+
+/// Image, video and audio URLs of a page, page order, without duplicates
+fn media_urls(html: &str, base: &Url) -> Vec<String> {
+    let mut urls = extract_image_urls(html, base);
+    let document = Html::parse_document(html);
+    let selector = Selector::parse("video[src], video source[src], audio[src], audio source[src]").unwrap();
+    let mut seen: HashSet<String> = urls.iter().cloned().collect();
+    for element in document.select(&selector) {
+        if let Some(url) = element.value().attr("src").and_then(|src| absolutize_url(src, base)) {
+            if !url.starts_with("data:") && seen.insert(url.clone()) {
+                urls.push(url);
+            }
+        }
+    }
+    urls.retain(|url| url.starts_with("http://") || url.starts_with("https://"));
+    urls
+}
+
+async fn download(client: &reqwest::Client, url: &str, referer: &str, path: &Path) -> Result<(), String> {
+    let response = client.get(url).header(header::REFERER, referer).send().await.map_err(|e| e.to_string())?;
+    if !response.status().is_success() {
+        return Err(format!("HTTP {}", response.status()));
+    }
+    if response.content_length().is_some_and(|len| len > MAX_MEDIA_BYTES) {
+        return Err("too large".into());
+    }
+    let mut file = tokio::fs::File::create(path).await.map_err(|e| e.to_string())?;
+    let mut written = 0u64;
+    let mut body = response.bytes_stream();
+    while let Some(chunk) = body.next().await {
+        let chunk = chunk.map_err(|e| e.to_string())?;
+        written += chunk.len() as u64;
+        if written > MAX_MEDIA_BYTES {
+            drop(file);
+            let _ = tokio::fs::remove_file(path).await;
+            return Err("too large".into());
+        }
+        file.write_all(&chunk).await.map_err(|e| e.to_string())?;
+    }
+    Ok(())
+}
+
+/// Runs `action` on the article of page load `session_id`, always on its original URL (see
+/// `session_url`). `progress` is called as each step starts.
+pub async fn logic_perform_article_action(
+    session_id: String,
+    action: ArticleAction,
+    params: ArticleActionParams,
+    state: &ProxyState,
+    progress: impl Fn(ArticleActionProgress),
+) -> Result<ArticleActionResult, String> {
+    let url = session_url(&session_id, state)?;
+    let report = |step: &'static str, done: usize, total: usize| progress(ArticleActionProgress { session_id: session_id.clone(), action, step, done, total });
+    let mut result = ArticleActionResult::new(action, &url);
+    println!("[actions::perform_article_action] {} on {}", action.name(), url);
+
+    // Offline, the clean URL is still at hand (without looking up the canonical one); other
+    // actions run once back online
+    if connectivity::is_offline(state) {
+        if action == ArticleAction::CopyCleanUrl {
+            result.clipboard = Some(clean_url(&url).to_string());
+            return Ok(result);
+        }
+        connectivity::queue_action(&session_id, action, params, state);
+        return Err(connectivity::OFFLINE_QUEUED.to_string());
+    }
+
+    match action {
+        ArticleAction::SaveOffline => {
+            let given = match params.path {
+                Some(path) => PathBuf::from(path),
+                None => PathBuf::from(params.dir.unwrap_or_default()).join(file_name_for(&url, ".html")),
+            };
+            let path = confined_path(&given, state)?;
+            report("extracting", 0, 2);
+            let article = logic_build_article_html(url.to_string(), HtmlExportOptions::default(), state).await?;
+            report("writing", 1, 2);
+            tokio::fs::write(&path, &article.html).await.map_err(|e| format!("Writing {}: {}", path.display(), e))?;
+            report("done", 2, 2);
+            result.path = Some(path.display().to_string());
+            result.files = 1;
+        }
+        ArticleAction::StarArchive => {
+            report("extracting", 0, 1);
+            versions::logic_track_article_versions(url.to_string(), true, state);
+            // Extraction stores the version of a tracked article
+            logic_extract_article(url.to_string(), ArticleOptions::default(), state).await?;
+            report("done", 1, 1);
+        }
+        ArticleAction::Share => {
+            let template = state.article_actions.load().share_url_template.clone().ok_or("No share service configured")?;
+            report("fetching", 0, 1);
+            let title = logic_fetch_share_metadata(url.to_string(), state).await.ok().and_then(|meta| meta.title).unwrap_or_default();
+            let clean = clean_url(&url);
+            result.open_url = Some(template.replace("{url}", &urlencoding::encode(clean.as_str())).replace("{title}", &urlencoding::encode(&title)));
+            report("done", 1, 1);
+        }
+        ArticleAction::DownloadMedia => {
+            let dir = confined_path(Path::new(params.dir.as_deref().unwrap_or_default()), state)?;
+            tokio::fs::create_dir_all(&dir).await.map_err(|e| format!("Creating {}: {}", dir.display(), e))?;
+            report("fetching", 0, 0);
+            let html = logic_fetch_raw_html(url.to_string(), None, None, state).await?;
+            let urls = media_urls(&html, &url);
+            let client = with_protocol_for(reqwest::Client::builder(), &url, state)
+                .cookie_store(true)
+                .cookie_provider(state.cookie_jar.clone())
+                .redirect(reqwest::redirect::Policy::limited(10))
+                .timeout(MEDIA_TIMEOUT)
+                .build()
+                .map_err(|e| e.to_string())?;
+            let mut taken = HashSet::new();
+            for (index, media_url) in urls.iter().enumerate() {
+                report("downloading", index, urls.len());
+                let path = match Url::parse(media_url) {
+                    Ok(parsed) => unique_path(&dir, &file_name_for(&parsed, ".bin"), &mut taken),
+                    Err(_) => {
+                        result.failed += 1;
+                        continue;
+                    }
+                };
+                match download(&client, media_url, url.as_str(), &path).await {
+                    Ok(()) => result.files += 1,
+                    Err(e) => {
+                        println!("[actions::perform_article_action] {}: {}", media_url, e);
+                        result.failed += 1;
+                    }
+                }
+            }
+            report("done", urls.len(), urls.len());
+            result.path = Some(dir.display().to_string());
+        }
+        ArticleAction::CopyCleanUrl => {
+            report("fetching", 0, 1);
+            // The canonical URL, when the page names one on its own site, is the cleanest
+            let canonical = logic_fetch_share_metadata(url.to_string(), state)
+                .await
+                .ok()
+                .and_then(|meta| Url::parse(&meta.canonical_url).ok())
+                .filter(|canonical| canonical.host_str() == url.host_str() && !is_local(canonical));
+            result.clipboard = Some(clean_url(canonical.as_ref().unwrap_or(&url)).to_string());
+            report("done", 1, 1);
+        }
+    }
+    Ok(result)
+}
+
+/// Runs the actions recorded while offline, oldest first, reporting each through `done`.
+/// Called when the app is back online; actions still failing for lack of network are queued again.
+pub async fn logic_retry_queued_actions(state: &ProxyState, progress: impl Fn(ArticleActionProgress), done: impl Fn(QueuedActionOutcome)) {
+    let queued = connectivity::take_queued_actions(state);
+    if !queued.is_empty() {
+        println!("[actions::retry_queued_actions] Running {} actions recorded offline", queued.len());
+    }
+    for queued in queued {
+        let outcome = logic_perform_article_action(queued.session_id.clone(), queued.action, queued.params, state, &progress).await;
+        let (result, error) = match outcome {
+            Ok(result) => (Some(result), None),
+            Err(e) => (None, Some(e)),
+        };
+        done(QueuedActionOutcome { session_id: queued.session_id, action: queued.action, result, error });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    /// Fresh directory under the system temp dir, removed by the caller
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("actions-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::canonicalize(dir).unwrap()
+    }
+
+    fn state_with_download_dir(dir: &Path) -> ProxyState {
+        let state = ProxyState::default();
+        state.article_actions.store(Arc::new(ArticleActionConfig { download_dir: Some(dir.display().to_string()), ..ArticleActionConfig::default() }));
+        state
+    }
+
+    #[test]
+    fn action_paths_stay_in_the_download_directory() {
+        let scratch = scratch_dir("confined");
+        let downloads = scratch.join("downloads");
+        std::fs::create_dir_all(downloads.join("saved")).unwrap();
+        let state = state_with_download_dir(&downloads);
+
+        assert_eq!(confined_path(Path::new(""), &state).unwrap(), downloads);
+        assert_eq!(confined_path(Path::new("saved/article.html"), &state).unwrap(), downloads.join("saved/article.html"));
+        assert_eq!(confined_path(Path::new("new/dir"), &state).unwrap(), downloads.join("new/dir"));
+        assert_eq!(confined_path(&downloads.join("article.html"), &state).unwrap(), downloads.join("article.html"));
+
+        assert!(confined_path(Path::new("../outside.html"), &state).is_err());
+        assert!(confined_path(Path::new("saved/../../outside.html"), &state).is_err());
+        assert!(confined_path(&scratch.join("outside.html"), &state).is_err());
+        assert!(confined_path(Path::new("/etc/cron.d/article"), &state).is_err());
+
+        #[cfg(unix)]
+        {
+            // Links out of the directory, to a directory or a file, are refused
+            std::os::unix::fs::symlink(&scratch, downloads.join("escape")).unwrap();
+            assert!(confined_path(Path::new("escape/outside.html"), &state).is_err());
+            assert!(confined_path(Path::new("escape/new/dir"), &state).is_err());
+            std::fs::write(scratch.join("target.html"), "").unwrap();
+            std::os::unix::fs::symlink(scratch.join("target.html"), downloads.join("link.html")).unwrap();
+            assert!(confined_path(Path::new("link.html"), &state).is_err());
+            // A link that stays inside is fine
+            std::os::unix::fs::symlink(downloads.join("saved"), downloads.join("inside")).unwrap();
+            assert_eq!(confined_path(Path::new("inside/a.html"), &state).unwrap(), downloads.join("saved/a.html"));
+        }
+
+        std::fs::remove_dir_all(&scratch).unwrap();
+    }
+
+    #[test]
+    fn nothing_is_written_without_a_download_directory() {
+        assert!(confined_path(Path::new("/tmp/article.html"), &ProxyState::default()).is_err());
+    }
+}
@@ -0,0 +1,133 @@
+use axum::http::response::Builder;
+use axum::http::{header, HeaderMap, HeaderName, HeaderValue};
+use axum::response::Response;
+
+/// Cache lifetime of passthrough resources whose upstream says nothing about caching
+const PASSTHROUGH_DEFAULT: &str = "private, max-age=300";
+
+/// Upstream headers that describe the upstream body, dropped when we serve something else
+const UPSTREAM_FRESHNESS_HEADERS: [HeaderName; 4] = [header::ETAG, header::LAST_MODIFIED, header::EXPIRES, header::AGE];
+
+/// What a response of the local server is, which decides how long the webview may keep it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CacheClass {
+    /// Never changes under its URL (site icons fetched upstream)
+    Immutable,
+    /// Stand-in replaced once the real resource is available (monogram icons)
+    Generated,
+    /// Page or stylesheet rewritten by the proxy: the output depends on settings that can change
+    Rewritten,
+    /// Upstream resource streamed as is: its caching headers and validators are kept
+    Passthrough,
+    /// Auth prompts, error pages, one-shot transfers and API responses (stats, exports)
+    NoStore,
+}
+
+impl CacheClass {
+    /// `Cache-Control` sent for the class. `None` for passthrough resources, which keep upstream's.
+    pub fn cache_control(self) -> Option<&'static str> {
+        match self {
+            CacheClass::Immutable => Some("public, max-age=604800, immutable"),
+            CacheClass::Generated => Some("public, max-age=86400"),
+            CacheClass::Rewritten => Some("private, max-age=60"),
+            CacheClass::Passthrough => None,
+            CacheClass::NoStore => Some("no-store"),
+        }
+    }
+}
+
+/// Sets the caching headers of `class` on a response
+pub fn apply_cache_class(headers: &mut HeaderMap, class: CacheClass) {
+    match class.cache_control() {
+        Some(cache_control) => {
+            for name in &UPSTREAM_FRESHNESS_HEADERS {
+                headers.remove(name);
+            }
+            headers.remove(header::PRAGMA);
+            headers.insert(header::CACHE_CONTROL, HeaderValue::from_static(cache_control));
+        }
+        None => {
+            if !headers.contains_key(header::CACHE_CONTROL) && !headers.contains_key(header::EXPIRES) {
+                headers.insert(header::CACHE_CONTROL, HeaderValue::from_static(PASSTHROUGH_DEFAULT));
+            }
+        }
+    }
+}
+
+/// `apply_cache_class` for a response being built, once its upstream headers are copied
+pub fn with_cache_class(mut builder: Builder, class: CacheClass) -> Builder {
+    if let Some(headers) = builder.headers_mut() {
+        apply_cache_class(headers, class);
+    }
+    builder
+}
+
+/// Response middleware: responses that didn't pick a class (API results, errors) aren't stored
+pub async fn no_store_by_default(mut response: Response) -> Response {
+    if !response.headers().contains_key(header::CACHE_CONTROL) {
+        apply_cache_class(response.headers_mut(), CacheClass::NoStore);
+    }
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Headers of an upstream response with validators and a caching policy of its own
+    fn upstream_headers() -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(header::CACHE_CONTROL, HeaderValue::from_static("public, max-age=31536000"));
+        headers.insert(header::ETAG, HeaderValue::from_static("\"v1\""));
+        headers.insert(header::LAST_MODIFIED, HeaderValue::from_static("Tue, 06 Oct 2026 08:00:00 GMT"));
+        headers.insert(header::EXPIRES, HeaderValue::from_static("Wed, 06 Oct 2027 08:00:00 GMT"));
+        headers.insert(header::AGE, HeaderValue::from_static("120"));
+        headers.insert(header::PRAGMA, HeaderValue::from_static("no-cache"));
+        headers
+    }
+
+    #[test]
+    fn each_class_sets_its_cache_control_and_drops_upstream_freshness() {
+        for (class, expected) in [
+            (CacheClass::Immutable, "public, max-age=604800, immutable"),
+            (CacheClass::Generated, "public, max-age=86400"),
+            (CacheClass::Rewritten, "private, max-age=60"),
+            (CacheClass::NoStore, "no-store"),
+        ] {
+            let mut headers = upstream_headers();
+            apply_cache_class(&mut headers, class);
+            assert_eq!(headers.get_all(header::CACHE_CONTROL).iter().collect::<Vec<_>>(), [expected], "{:?}", class);
+            for name in [header::ETAG, header::LAST_MODIFIED, header::EXPIRES, header::AGE, header::PRAGMA] {
+                assert!(!headers.contains_key(&name), "{:?} kept {}", class, name);
+            }
+        }
+    }
+
+    #[test]
+    fn passthrough_keeps_upstream_caching_and_defaults_when_there_is_none() {
+        let mut headers = upstream_headers();
+        apply_cache_class(&mut headers, CacheClass::Passthrough);
+        assert_eq!(headers, upstream_headers());
+
+        let mut expires_only = HeaderMap::new();
+        expires_only.insert(header::EXPIRES, HeaderValue::from_static("Wed, 06 Oct 2027 08:00:00 GMT"));
+        apply_cache_class(&mut expires_only, CacheClass::Passthrough);
+        assert!(!expires_only.contains_key(header::CACHE_CONTROL));
+
+        let mut headers = HeaderMap::new();
+        headers.insert(header::ETAG, HeaderValue::from_static("\"v1\""));
+        apply_cache_class(&mut headers, CacheClass::Passthrough);
+        assert_eq!(headers.get(header::CACHE_CONTROL).unwrap(), PASSTHROUGH_DEFAULT);
+        assert_eq!(headers.get(header::ETAG).unwrap(), "\"v1\"");
+    }
+
+    #[tokio::test]
+    async fn responses_without_a_class_are_not_stored() {
+        let unclassified = no_store_by_default(Response::new(axum::body::Body::empty())).await;
+        assert_eq!(unclassified.headers().get(header::CACHE_CONTROL).unwrap(), "no-store");
+
+        let classified = with_cache_class(Response::builder(), CacheClass::Rewritten).body(axum::body::Body::empty()).unwrap();
+        let classified = no_store_by_default(classified).await;
+        assert_eq!(classified.headers().get(header::CACHE_CONTROL).unwrap(), "private, max-age=60");
+    }
+}
@@ -0,0 +1,169 @@
+use lol_html::{element, rewrite_str, RewriteStrSettings};
+use scraper::{ElementRef, Html, Selector};
+use serde::Serialize;
+use std::cell::Cell;
+
+/// Attribute carrying the kind of a classified block in the content
+pub const BLOCK_KIND_ATTRIBUTE: &str = "data-block-kind";
+
+/// Elements that can hold a pull-quote, callout or takeaway panel. The same selector is run
+/// by scraper and lol_html, so both see the candidates in the same order.
+const CANDIDATES: &str = r#"aside, blockquote, div, section, figure, p[role="note"]"#;
+
+/// Class/id fragments of each kind, checked in this order (takeaways are often styled as callouts)
+const TAKEAWAY_MARKERS: &[&str] = &["takeaway", "key-point", "keypoint", "key_point", "tldr", "tl-dr", "at-a-glance", "highlights", "in-brief"];
+const PULL_QUOTE_MARKERS: &[&str] = &["pullquote", "pull-quote", "pull_quote", "pullout", "blockquote--pull"];
+const CALLOUT_MARKERS: &[&str] = &["callout", "admonition", "infobox", "info-box", "factbox", "fact-box", "sidebar", "boxout", "note-box"];
+
+/// Heading openings of a key-takeaway panel without a telling class
+const TAKEAWAY_HEADINGS: &[&str] = &["key takeaway", "takeaway", "tl;dr", "tldr", "in brief", "at a glance", "key points", "the big picture", "what to know"];
+
+/// Longest text of an `<aside>` that only quotes the article to count as a pull-quote
+const MAX_PULL_QUOTE_CHARS: usize = 300;
+
+/// Characters of text kept in a block's label
+const MAX_LABEL_CHARS: usize = 120;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BlockKind {
+    /// Excerpt of the article repeated in large type
+    PullQuote,
+    /// Box set apart from the text: note, fact box, sidebar
+    Callout,
+    /// Summary panel of the main points
+    KeyTakeaway,
+}
+
+impl BlockKind {
+    fn name(self) -> &'static str {
+        match self {
+            BlockKind::PullQuote => "pull-quote",
+            BlockKind::Callout => "callout",
+            BlockKind::KeyTakeaway => "key-takeaway",
+        }
+    }
+}
+
+/// A classified block of the content, marked there with `data-block-kind`
+#[derive(Debug, Clone, Serialize)]
+pub struct ClassifiedBlock {
+    pub kind: BlockKind,
+    /// Id of the element in the content
+    pub id: String,
+    /// Start of the block's text
+    pub label: Option<String>,
+}
+
+fn label(text: &str) -> Option<String> {
+    let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    match text.char_indices().nth(MAX_LABEL_CHARS) {
+        _ if text.is_empty() => None,
+        Some((end, _)) => Some(format!("{}…", text[..end].trim_end())),
+        None => Some(text),
+    }
+}
+
+/// Lowercased class and id of an element, for marker lookups
+fn class_and_id(el: &ElementRef) -> String {
+    format!("{} {}", el.value().attr("class").unwrap_or(""), el.value().id().unwrap_or("")).to_ascii_lowercase()
+}
+
+fn has_marker(names: &str, markers: &[&str]) -> bool {
+    markers.iter().any(|marker| names.contains(marker))
+}
+
+fn opening_heading(el: &ElementRef) -> Option<String> {
+    let selector = Selector::parse("h2, h3, h4, h5, h6, strong, b, p").unwrap();
+    el.select(&selector).next().map(|heading| heading.text().collect::<String>().trim().to_lowercase())
+}
+
+/// Kind of a candidate element from its markup: class/id markers first, then the shape of
+/// `<aside>`s and `role="note"` elements
+fn classify(el: &ElementRef) -> Option<BlockKind> {
+    let names = class_and_id(el);
+    let tag = el.value().name();
+    if has_marker(&names, TAKEAWAY_MARKERS) {
+        return Some(BlockKind::KeyTakeaway);
+    }
+    if has_marker(&names, PULL_QUOTE_MARKERS) {
+        return Some(BlockKind::PullQuote);
+    }
+    if has_marker(&names, CALLOUT_MARKERS) {
+        return Some(BlockKind::Callout);
+    }
+    let is_note = el.value().attr("role") == Some("note");
+    if tag != "aside" && !is_note {
+        return None;
+    }
+    if opening_heading(el).is_some_and(|heading| TAKEAWAY_HEADINGS.iter().any(|start| heading.starts_with(start))) {
+        return Some(BlockKind::KeyTakeaway);
+    }
+    let quote = Selector::parse("blockquote, q").unwrap();
+    let text_len = el.text().map(|t| t.trim().chars().count()).sum::<usize>();
+    if tag == "aside" && el.select(&quote).next().is_some() && text_len <= MAX_PULL_QUOTE_CHARS {
+        return Some(BlockKind::PullQuote);
+    }
+    Some(BlockKind::Callout)
+}
+
+/// Marks pull-quotes, callouts and key-takeaway panels of extracted content with
+/// `data-block-kind` (and an id when they have none). Blocks nested in a classified block
+/// aren't classified. The content is otherwise unchanged.
+pub fn classify_blocks(html: &str) -> Result<String, String> {
+    let document = Html::parse_fragment(html);
+    let candidates = Selector::parse(CANDIDATES).unwrap();
+    let mut classified: Vec<ElementRef> = Vec::new();
+    let mut kinds: Vec<Option<BlockKind>> = Vec::new();
+    for el in document.select(&candidates) {
+        let nested = el.ancestors().any(|ancestor| classified.iter().any(|block| block.id() == ancestor.id()));
+        let kind = if nested { None } else { classify(&el) };
+        if kind.is_some() {
+            classified.push(el);
+        }
+        kinds.push(kind);
+    }
+    if classified.is_empty() {
+        return Ok(html.to_string());
+    }
+
+    let index = Cell::new(0);
+    let counter = Cell::new(0);
+    rewrite_str(
+        html,
+        RewriteStrSettings {
+            element_content_handlers: vec![element!(CANDIDATES, |el| {
+                let kind = kinds.get(index.get()).copied().flatten();
+                index.set(index.get() + 1);
+                if let Some(kind) = kind {
+                    el.set_attribute(BLOCK_KIND_ATTRIBUTE, kind.name())?;
+                    if el.get_attribute("id").is_none_or(|id| id.trim().is_empty()) {
+                        counter.set(counter.get() + 1);
+                        el.set_attribute("id", &format!("{}-{}", kind.name(), counter.get()))?;
+                    }
+                }
+                Ok(())
+            })],
+            ..RewriteStrSettings::default()
+        },
+    )
+    .map_err(|e| e.to_string())
+}
+
+/// Blocks marked by `classify_blocks`, in document order
+pub fn classified_blocks(html: &str) -> Vec<ClassifiedBlock> {
+    let document = Html::parse_fragment(html);
+    let selector = Selector::parse(&format!("[{}]", BLOCK_KIND_ATTRIBUTE)).unwrap();
+    document
+        .select(&selector)
+        .filter_map(|el| {
+            let kind = match el.value().attr(BLOCK_KIND_ATTRIBUTE)? {
+                "pull-quote" => BlockKind::PullQuote,
+                "callout" => BlockKind::Callout,
+                "key-takeaway" => BlockKind::KeyTakeaway,
+                _ => return None,
+            };
+            Some(ClassifiedBlock { kind, id: el.value().id()?.to_string(), label: label(&el.text().collect::<Vec<_>>().join(" ")) })
+        })
+        .collect()
+}
@@ -0,0 +1,226 @@
+use crate::connectivity;
+use crate::shared::{read_text_limited, ProxyState};
+use crate::startup::{self, Component};
+use base64::Engine;
+use flate2::read::GzDecoder;
+use ring::signature::{UnparsedPublicKey, ED25519};
+use serde::{Deserialize, Serialize};
+use std::io::Read;
+use std::sync::{Arc, OnceLock};
+use tokio::time::Duration;
+
+/// Starter catalog shipped with the app (gzipped JSON)
+const BUNDLED_CATALOG: &[u8] = include_bytes!("../assets/feed_catalog.json.gz");
+
+/// Ed25519 key (base64) that signs remote catalogs, set at build time. Without it,
+/// `update_catalog` is disabled and the bundled catalog is used.
+const CATALOG_PUBLIC_KEY: Option<&str> = option_env!("FEED_CATALOG_PUBLIC_KEY");
+
+/// Timeout for downloading a remote catalog
+const CATALOG_FETCH_TIMEOUT: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum UpdateFrequency {
+    Hourly,
+    Daily,
+    Weekly,
+    Monthly,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CatalogEntry {
+    pub title: String,
+    /// Feed URL
+    pub url: String,
+    pub site_url: Option<String>,
+    pub description: String,
+    pub category: String,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// Language tag of the feed's content
+    pub language: String,
+    pub update_frequency: UpdateFrequency,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FeedCatalog {
+    /// Increases with every published catalog; updates must carry a higher one
+    pub version: u32,
+    /// Date the catalog was last curated
+    pub updated: String,
+    pub entries: Vec<CatalogEntry>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CatalogCategory {
+    pub name: String,
+    pub count: usize,
+}
+
+/// Remote catalog as served by `update_catalog`'s URL: the catalog JSON and its Ed25519
+/// signature, both base64-encoded so the signed bytes are exactly the ones parsed
+#[derive(Deserialize)]
+struct SignedCatalog {
+    payload: String,
+    signature: String,
+}
+
+fn bundled_catalog() -> Result<Arc<FeedCatalog>, String> {
+    static BUNDLED: OnceLock<Result<Arc<FeedCatalog>, String>> = OnceLock::new();
+    BUNDLED
+        .get_or_init(|| {
+            let mut json = String::new();
+            GzDecoder::new(BUNDLED_CATALOG)
+                .read_to_string(&mut json)
+                .map_err(|e| format!("Bundled feed catalog is not valid gzip: {}", e))?;
+            let catalog: FeedCatalog = serde_json::from_str(&json)
+                .map_err(|e| format!("Bundled feed catalog is invalid at line {}, column {}: {}", e.line(), e.column(), e))?;
+            Ok(Arc::new(catalog))
+        })
+        .clone()
+}
+
+/// Catalog in use: the last successfully updated one, the bundled one otherwise.
+/// Fails only when no catalog was updated and the bundled one is corrupted.
+pub fn current_catalog(state: &ProxyState) -> Result<Arc<FeedCatalog>, String> {
+    match state.feed_catalog.load_full() {
+        Some(catalog) => Ok(catalog),
+        None => bundled_catalog(),
+    }
+}
+
+/// Score of `entry` for lowercase query terms, `None` when a term matches nothing.
+/// Title matches weigh most, then tags, then the description and URL.
+fn match_score(entry: &CatalogEntry, terms: &[String]) -> Option<usize> {
+    let title = entry.title.to_lowercase();
+    let description = entry.description.to_lowercase();
+    let url = entry.url.to_lowercase();
+    let tags: Vec<String> = entry.tags.iter().map(|tag| tag.to_lowercase()).collect();
+
+    terms.iter().try_fold(0, |score, term| {
+        let term_score = if title.contains(term.as_str()) {
+            3
+        } else if tags.iter().any(|tag| tag.contains(term.as_str())) || entry.category.to_lowercase().contains(term.as_str()) {
+            2
+        } else if description.contains(term.as_str()) || url.contains(term.as_str()) {
+            1
+        } else {
+            return None;
+        };
+        Some(score + term_score)
+    })
+}
+
+/// Searches titles, tags, categories and descriptions of the catalog, best matches first.
+/// An empty query lists every entry (of `category`, if given). Entries should be checked
+/// with `fetch_feed` before subscribing.
+pub fn logic_search_feed_catalog(query: String, category: Option<String>, state: &ProxyState) -> Result<Vec<CatalogEntry>, String> {
+    let catalog = current_catalog(state).map_err(|e| startup::unavailable(Component::FeedCatalog, &e))?;
+    let terms: Vec<String> = query.split_whitespace().map(str::to_lowercase).collect();
+
+    let mut matches: Vec<(usize, &CatalogEntry)> = catalog
+        .entries
+        .iter()
+        .filter(|entry| category.as_deref().is_none_or(|category| entry.category.eq_ignore_ascii_case(category)))
+        .filter_map(|entry| Some((match_score(entry, &terms)?, entry)))
+        .collect();
+    matches.sort_by(|(a_score, a), (b_score, b)| b_score.cmp(a_score).then_with(|| a.title.to_lowercase().cmp(&b.title.to_lowercase())));
+    Ok(matches.into_iter().map(|(_, entry)| entry.clone()).collect())
+}
+
+/// Catalog categories with their number of feeds, in catalog order
+pub fn logic_list_catalog_categories(state: &ProxyState) -> Result<Vec<CatalogCategory>, String> {
+    let catalog = current_catalog(state).map_err(|e| startup::unavailable(Component::FeedCatalog, &e))?;
+    let mut categories: Vec<CatalogCategory> = Vec::new();
+    for entry in &catalog.entries {
+        match categories.iter_mut().find(|category| category.name == entry.category) {
+            Some(category) => category.count += 1,
+            None => categories.push(CatalogCategory { name: entry.category.clone(), count: 1 }),
+        }
+    }
+    Ok(categories)
+}
+
+/// Decodes a signed catalog, checking its signature against `public_key` (base64)
+fn verify_catalog(body: &str, public_key: &str) -> Result<FeedCatalog, String> {
+    let engine = base64::engine::general_purpose::STANDARD;
+    let signed: SignedCatalog = serde_json::from_str(body).map_err(|e| format!("Invalid signed catalog: {}", e))?;
+    let payload = engine.decode(signed.payload.trim()).map_err(|e| format!("Invalid catalog payload: {}", e))?;
+    let signature = engine.decode(signed.signature.trim()).map_err(|e| format!("Invalid catalog signature: {}", e))?;
+    let public_key = engine.decode(public_key.trim()).map_err(|e| format!("Invalid catalog public key: {}", e))?;
+
+    UnparsedPublicKey::new(&ED25519, &public_key)
+        .verify(&payload, &signature)
+        .map_err(|_| "Catalog signature verification failed".to_string())?;
+
+    let catalog: FeedCatalog = serde_json::from_slice(&payload).map_err(|e| format!("Invalid catalog: {}", e))?;
+    if catalog.entries.is_empty() {
+        return Err("Catalog has no entries".into());
+    }
+    Ok(catalog)
+}
+
+/// Replaces the catalog with the signed one at `url`. The catalog in use is kept when the
+/// download or the signature check fails, or when the downloaded catalog is not newer.
+pub async fn logic_update_catalog(url: String, state: &ProxyState) -> Result<usize, String> {
+    let public_key = CATALOG_PUBLIC_KEY.ok_or("Catalog updates are not configured in this build")?;
+    connectivity::ensure_online(state)?;
+
+    let client = reqwest::Client::builder().timeout(CATALOG_FETCH_TIMEOUT).build().map_err(|e| e.to_string())?;
+    let response = client.get(&url).send().await.map_err(|e| e.to_string())?;
+    if !response.status().is_success() {
+        return Err(format!("Catalog download failed with HTTP {}", response.status().as_u16()));
+    }
+    let body = read_text_limited(response, state.max_body_size()).await?;
+
+    let catalog = verify_catalog(&body, public_key)?;
+    println!("[catalog::update_catalog] Catalog {} ({}) loaded from {}: {} feeds", catalog.version, catalog.updated, url, catalog.entries.len());
+    install_catalog(catalog, state)
+}
+
+/// Puts `catalog` in use unless it is not newer than the current one, so a replayed
+/// older catalog (validly signed at the time) cannot roll the feeds back
+fn install_catalog(catalog: FeedCatalog, state: &ProxyState) -> Result<usize, String> {
+    let in_use = current_catalog(state).map(|current| current.version).unwrap_or(0);
+    if catalog.version <= in_use {
+        return Err(format!("Catalog version {} is not newer than the one in use ({})", catalog.version, in_use));
+    }
+    let count = catalog.entries.len();
+    state.feed_catalog.store(Some(Arc::new(catalog)));
+    startup::record(Component::FeedCatalog, &Ok(()), state);
+    Ok(count)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn catalog_version(version: u32) -> FeedCatalog {
+        FeedCatalog { version, ..(*bundled_catalog().unwrap()).clone() }
+    }
+
+    #[test]
+    fn bundled_catalog_has_a_few_feeds_in_every_category() {
+        let state = ProxyState::default();
+        let categories = logic_list_catalog_categories(&state).unwrap();
+        assert!(categories.iter().map(|category| category.count).sum::<usize>() >= 300);
+        for category in &categories {
+            assert!(category.count >= 15, "{} has only {} feeds", category.name, category.count);
+        }
+    }
+
+    #[test]
+    fn only_newer_catalogs_replace_the_one_in_use() {
+        let state = ProxyState::default();
+        let bundled = bundled_catalog().unwrap().version;
+
+        assert!(install_catalog(catalog_version(bundled - 1), &state).is_err());
+        assert!(install_catalog(catalog_version(bundled), &state).is_err());
+        assert_eq!(current_catalog(&state).unwrap().version, bundled);
+
+        install_catalog(catalog_version(bundled + 1), &state).unwrap();
+        assert_eq!(current_catalog(&state).unwrap().version, bundled + 1);
+        assert!(install_catalog(catalog_version(bundled + 1), &state).is_err());
+    }
+}
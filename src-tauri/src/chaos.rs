@@ -0,0 +1,393 @@
+use crate::shared::{ProxyState, BODY_TOO_LARGE};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use tokio::time::Duration;
+use url::Url;
+
+/// Environment variable that allows chaos mode in release builds
+pub const CHAOS_ENV_FLAG: &str = "FEED_READER_ALLOW_CHAOS";
+
+/// URL scheme of the built-in fixture pages (`chaos://paywall`, `chaos://js-shell`, ...)
+pub const FIXTURE_SCHEME: &str = "chaos";
+
+/// Failure-injection settings applied at the shared fetch layer and in the proxy handlers.
+/// Rates are probabilities between 0.0 and 1.0, rolled per request.
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct ChaosProfile {
+    /// Fixed delay added before every request
+    pub latency_ms: u64,
+    /// Additional random delay, up to this many milliseconds
+    pub jitter_ms: u64,
+    /// Chance of failing the request as if the connection was reset
+    pub reset_rate: f64,
+    /// Chance of starting a burst of 429/503 responses
+    pub error_rate: f64,
+    /// Number of consecutive requests failed once a burst starts
+    pub error_burst: u32,
+    /// Chance of cutting the response body short
+    pub truncate_rate: f64,
+    /// Chance of lying about the content type or charset
+    pub mislabel_rate: f64,
+    /// Hosts (subdomains included) chaos applies to. Empty means every host.
+    pub domains: Vec<String>,
+}
+
+impl ChaosProfile {
+    /// Built-in profiles: "slow", "flaky" and "hostile"
+    pub fn preset(name: &str) -> Option<Self> {
+        let profile = match name {
+            "slow" => Self { latency_ms: 2_000, jitter_ms: 3_000, ..Self::default() },
+            "flaky" => Self {
+                latency_ms: 200,
+                jitter_ms: 800,
+                reset_rate: 0.15,
+                error_rate: 0.1,
+                error_burst: 3,
+                ..Self::default()
+            },
+            "hostile" => Self {
+                latency_ms: 500,
+                jitter_ms: 1_500,
+                reset_rate: 0.2,
+                error_rate: 0.2,
+                error_burst: 5,
+                truncate_rate: 0.25,
+                mislabel_rate: 0.25,
+                ..Self::default()
+            },
+            _ => return None,
+        };
+        Some(profile)
+    }
+
+    fn applies_to(&self, url: &Url) -> bool {
+        if url.scheme() == FIXTURE_SCHEME || self.domains.is_empty() {
+            return true;
+        }
+        let host = url.host_str().unwrap_or("").to_ascii_lowercase();
+        self.domains.iter().any(|domain| {
+            let domain = domain.trim().trim_start_matches('.').to_ascii_lowercase();
+            host == domain || host.ends_with(&format!(".{}", domain))
+        })
+    }
+}
+
+/// Either the name of a built-in profile or a full custom profile
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub enum ChaosProfileSpec {
+    Preset(String),
+    Custom(ChaosProfile),
+}
+
+/// Chaos profile currently in effect, with the state of an ongoing error burst
+pub struct ActiveChaos {
+    profile: ChaosProfile,
+    burst_remaining: u32,
+}
+
+/// A failure injected before a request reaches the network
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChaosFault {
+    ConnectionReset,
+    Status(u16),
+}
+
+impl fmt::Display for ChaosFault {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::ConnectionReset => write!(f, "CHAOS: connection reset by peer"),
+            Self::Status(status) => write!(f, "CHAOS: HTTP {}", status),
+        }
+    }
+}
+
+/// Chaos mode is available in debug builds, or in release builds started with `CHAOS_ENV_FLAG` set
+pub fn chaos_allowed() -> bool {
+    cfg!(debug_assertions) || std::env::var_os(CHAOS_ENV_FLAG).is_some()
+}
+
+pub fn enable_chaos(spec: ChaosProfileSpec, state: &ProxyState) -> Result<ChaosProfile, String> {
+    if !chaos_allowed() {
+        return Err(format!("Chaos mode is disabled in release builds (set {} to allow it)", CHAOS_ENV_FLAG));
+    }
+
+    let profile = match spec {
+        ChaosProfileSpec::Preset(name) => {
+            ChaosProfile::preset(&name).ok_or_else(|| format!("Unknown chaos profile: {}", name))?
+        }
+        ChaosProfileSpec::Custom(profile) => profile,
+    };
+
+    println!("[chaos] Enabled: {:?}", profile);
+    let mut chaos = state.chaos.lock().unwrap();
+    *chaos = Some(ActiveChaos { profile: profile.clone(), burst_remaining: 0 });
+    Ok(profile)
+}
+
+pub fn disable_chaos(state: &ProxyState) {
+    let mut chaos = state.chaos.lock().unwrap();
+    if chaos.take().is_some() {
+        println!("[chaos] Disabled");
+    }
+}
+
+/// Returns a uniformly distributed number in [0, 1)
+fn random_unit() -> f64 {
+    let bits = (uuid::Uuid::new_v4().as_u128() >> 75) as u64;
+    bits as f64 / (1u64 << 53) as f64
+}
+
+fn roll(rate: f64) -> bool {
+    rate > 0.0 && random_unit() < rate
+}
+
+/// Profile for `url`, if chaos is enabled and scoped to it
+fn profile_for(url: &Url, state: &ProxyState) -> Option<ChaosProfile> {
+    let chaos = state.chaos.lock().unwrap();
+    chaos
+        .as_ref()
+        .filter(|active| active.profile.applies_to(url))
+        .map(|active| active.profile.clone())
+}
+
+/// Whether chaos is enabled and scoped to `url`
+pub fn is_active_for(url: &Url, state: &ProxyState) -> bool {
+    profile_for(url, state).is_some()
+}
+
+/// Delays the request and possibly fails it. Call right before sending an upstream request.
+pub async fn inject_request_faults(url: &Url, state: &ProxyState) -> Result<(), ChaosFault> {
+    let Some(profile) = profile_for(url, state) else {
+        return Ok(());
+    };
+
+    let delay = profile.latency_ms + (random_unit() * profile.jitter_ms as f64) as u64;
+    if delay > 0 {
+        tokio::time::sleep(Duration::from_millis(delay)).await;
+    }
+
+    let fault = {
+        let mut chaos = state.chaos.lock().unwrap();
+        let Some(active) = chaos.as_mut() else {
+            return Ok(());
+        };
+        if active.burst_remaining == 0 && roll(profile.error_rate) {
+            active.burst_remaining = profile.error_burst.max(1);
+        }
+        if active.burst_remaining > 0 {
+            active.burst_remaining -= 1;
+            Some(ChaosFault::Status(if roll(0.5) { 429 } else { 503 }))
+        } else if roll(profile.reset_rate) {
+            Some(ChaosFault::ConnectionReset)
+        } else {
+            None
+        }
+    };
+
+    match fault {
+        Some(fault) => {
+            println!("[chaos] {} for {}", fault, url);
+            Err(fault)
+        }
+        None => Ok(()),
+    }
+}
+
+/// Possibly replaces an upstream content type with a wrong one
+pub fn mangle_content_type(url: &Url, content_type: String, state: &ProxyState) -> String {
+    match profile_for(url, state) {
+        Some(profile) if roll(profile.mislabel_rate / 2.0) => {
+            println!("[chaos] Mislabeling '{}' as application/octet-stream for {}", content_type, url);
+            "application/octet-stream".to_string()
+        }
+        _ => content_type,
+    }
+}
+
+/// Possibly truncates a decoded body, or decodes it again with the wrong charset
+pub fn mangle_body(url: &Url, body: String, state: &ProxyState) -> String {
+    let Some(profile) = profile_for(url, state) else {
+        return body;
+    };
+
+    let mut body = body;
+    if roll(profile.truncate_rate) {
+        let mut cut = (body.len() as f64 * random_unit()) as usize;
+        while !body.is_char_boundary(cut) {
+            cut -= 1;
+        }
+        println!("[chaos] Truncating body of {} to {} of {} bytes", url, cut, body.len());
+        body.truncate(cut);
+    }
+    if roll(profile.mislabel_rate / 2.0) {
+        println!("[chaos] Decoding {} as windows-1252", url);
+        let (decoded, _, _) = encoding_rs::WINDOWS_1252.decode(body.as_bytes());
+        body = decoded.into_owned();
+    }
+    body
+}
+
+// --- Fixture Pages ---
+
+const PAYWALL_FIXTURE: &str = r#"<!DOCTYPE html>
+<html><head><title>Paywalled article</title></head>
+<body>
+<article>
+<h1>Subscribers only</h1>
+<p>The first paragraph of this story is free to read, like on most metered news sites.</p>
+<div class="paywall" style="position: fixed; inset: 0; z-index: 9999; background: white">
+<p>Subscribe to continue reading.</p><a href="/subscribe">Subscribe</a>
+</div>
+</article>
+</body></html>"#;
+
+const JS_SHELL_FIXTURE: &str = r#"<!DOCTYPE html><html><head></head><body></body></html>"#;
+
+const BROKEN_IMAGES_FIXTURE: &str = r#"<!DOCTYPE html>
+<html><head><title>Broken images</title></head>
+<body>
+<article>
+<h1>Every image on this page is broken</h1>
+<p>This page references images that do not resolve, point to unreachable hosts, or are not images at all.
+It exercises the placeholder and retry states of the reader view.</p>
+<img src="chaos://broken-images/missing.jpg" alt="Missing image">
+<img src="http://127.0.0.1:9/unreachable.png" alt="Unreachable host">
+<img src="data:image/png;base64,bm90IGFuIGltYWdl" alt="Corrupted data URI">
+<img alt="No source">
+<p>The article text continues after the images so extraction still has something to work with.</p>
+</article>
+</body></html>"#;
+
+/// Script-rendered page whose text is only in its AMP version, `chaos://amp-article`
+const AMP_SHELL_FIXTURE: &str = r#"<!DOCTYPE html>
+<html><head><title>Script-rendered article</title>
+<link rel="amphtml" href="chaos://amp-article/">
+<script src="/static/app.bundle.js" defer></script>
+</head>
+<body>
+<div id="root"></div>
+<noscript>Please enable JavaScript to read this article.</noscript>
+<script>window.__INITIAL_STATE__ = {"article": null};</script>
+</body></html>"#;
+
+const AMP_ARTICLE_FIXTURE: &str = r#"<!DOCTYPE html>
+<html amp><head><title>Script-rendered article</title>
+<link rel="canonical" href="chaos://amp-shell/">
+</head>
+<body>
+<article>
+<h1>Read through the AMP version</h1>
+<p>News sites that render their articles with scripts often publish a static AMP version as well.
+The reader falls back to it when the page itself gives nothing to extract.</p>
+<amp-img src="chaos://amp-article/photo.jpg" width="800" height="450" layout="responsive" alt="AMP image"></amp-img>
+<p>AMP images are turned into regular images so they survive extraction, and the text after them
+is kept like on any other article page.</p>
+</article>
+</body></html>"#;
+
+/// Script-rendered news page whose text is only in its JSON-LD `articleBody`, next to a
+/// malformed JSON-LD block (no AMP version to fall back on)
+const JSON_LD_FIXTURE: &str = r#"<!DOCTYPE html>
+<html><head><title>Text shipped in structured data</title>
+<script type="application/ld+json">{"@context": "https://schema.org", "@type": "BreadcrumbList",</script>
+<script type="application/ld+json">
+{
+  "@context": "https://schema.org",
+  "@graph": [
+    {"@type": "Organization", "name": "Chaos Daily", "url": "chaos://json-ld/"},
+    {"@type": "WebSite", "name": "Chaos Daily"},
+    {
+      "@type": ["NewsArticle", "ReportageNewsArticle"],
+      "headline": "Text shipped in structured data",
+      "author": {"@type": "Person", "name": "Jane Doe"},
+      "datePublished": "2024-03-01T08:00:00Z",
+      "articleBody": "Some newspapers render their articles in the browser, so the page the reader downloads has nothing for readability to extract.\n\nThe complete text is still there, in the JSON-LD block search engines read: its articleBody property holds every paragraph as plain text, separated by blank lines.\n\nThe reader turns those paragraphs back into HTML when extraction comes back empty or with only a few lines, such as a teaser or a cookie notice.\n\nA malformed JSON-LD block on the same page, like the truncated breadcrumb list above, is skipped without aborting the extraction."
+    }
+  ]
+}
+</script>
+<script src="/static/app.bundle.js" defer></script>
+</head>
+<body>
+<div id="root"></div>
+<noscript>Please enable JavaScript to read this article.</noscript>
+</body></html>"#;
+
+/// Pages of the `chaos://paged` article
+const PAGED_FIXTURE_PAGES: usize = 3;
+
+/// Page `chaos://paged/<n>` of an article split in three. Every page repeats the site header
+/// and footer; the last one links back to the first, like sites whose pager wraps around.
+fn paged_fixture(path: &str) -> Option<String> {
+    let page: usize = match path.trim_matches('/') {
+        "" => 1,
+        number => number.parse().ok().filter(|page| (2..=PAGED_FIXTURE_PAGES).contains(page))?,
+    };
+    let next = if page < PAGED_FIXTURE_PAGES { format!("chaos://paged/{}", page + 1) } else { "chaos://paged/".to_string() };
+    let mut html = format!("<!DOCTYPE html><html><head><title>Paged article, page {}</title>", page);
+    if page < PAGED_FIXTURE_PAGES {
+        html.push_str(&format!(r#"<link rel="next" href="{}">"#, next));
+    }
+    html.push_str("</head><body><article><p>The Chaos Gazette, long reads about failure.</p>");
+    for paragraph in 1..=3 {
+        html.push_str(&format!(
+            "<p>Page {} paragraph {}: long-form sites split their stories across pages, and the reader stitches them back together.</p>",
+            page, paragraph
+        ));
+    }
+    html.push_str(&format!(
+        r#"<p>Thanks for reading the Chaos Gazette.</p></article><nav class="pagination"><a class="next" href="{}">Next page</a></nav></body></html>"#,
+        next
+    ));
+    Some(html)
+}
+
+/// Paragraphs in the generated `chaos://huge` page (a few MiB of text)
+const HUGE_FIXTURE_PARAGRAPHS: usize = 20_000;
+
+fn huge_fixture() -> String {
+    let mut html = String::from("<!DOCTYPE html><html><head><title>Huge page</title></head><body><article><h1>Huge page</h1>");
+    for i in 0..HUGE_FIXTURE_PARAGRAPHS {
+        html.push_str(&format!(
+            "<p>Paragraph {} of a deliberately oversized page, used to exercise body size limits and slow rendering.</p>",
+            i + 1
+        ));
+    }
+    html.push_str("</article></body></html>");
+    html
+}
+
+/// Serves the built-in fixture for a `chaos://` URL. Returns `None` for any other URL,
+/// and an error for unknown fixtures, when chaos mode isn't allowed, or when the page
+/// exceeds `max_body_size`.
+pub fn fixture(url: &Url, state: &ProxyState) -> Option<Result<String, String>> {
+    if url.scheme() != FIXTURE_SCHEME {
+        return None;
+    }
+    if !chaos_allowed() {
+        return Some(Err(format!("Chaos fixtures are disabled in release builds (set {} to allow them)", CHAOS_ENV_FLAG)));
+    }
+
+    let html = match url.host_str().unwrap_or("") {
+        "paywall" => PAYWALL_FIXTURE.to_string(),
+        "js-shell" => JS_SHELL_FIXTURE.to_string(),
+        "huge" => huge_fixture(),
+        "broken-images" => BROKEN_IMAGES_FIXTURE.to_string(),
+        "amp-shell" => AMP_SHELL_FIXTURE.to_string(),
+        "amp-article" => AMP_ARTICLE_FIXTURE.to_string(),
+        "json-ld" => JSON_LD_FIXTURE.to_string(),
+        "paged" => match paged_fixture(url.path()) {
+            Some(html) => html,
+            None => return Some(Err(format!("Unknown chaos fixture page: {}", url))),
+        },
+        other => return Some(Err(format!("Unknown chaos fixture: {}", other))),
+    };
+
+    let max_body_size = state.max_body_size();
+    if html.len() > max_body_size {
+        return Some(Err(format!("{}:{}", BODY_TOO_LARGE, max_body_size)));
+    }
+    Some(Ok(mangle_body(url, html, state)))
+}
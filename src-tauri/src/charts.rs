@@ -0,0 +1,325 @@
+use crate::connectivity;
+use crate::shared::{escape_html, host_in_domain, host_of_domain_key, unescape_html, MutationReport, ProxyState};
+use futures_util::stream::{self, StreamExt};
+use lol_html::errors::RewritingError;
+use lol_html::html_content::{ContentType, Element};
+use lol_html::{element, rewrite_str, RewriteStrSettings};
+use reqwest::header;
+use std::cell::{Cell, RefCell};
+use std::collections::HashSet;
+use std::sync::atomic::Ordering;
+use tokio::time::Duration;
+use url::Url;
+
+/// Timeout of the request checking that a chart's static image exists
+const IMAGE_CHECK_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Charts whose image is checked at the same time
+const IMAGE_CHECK_CONCURRENCY: usize = 4;
+
+/// Chart services whose embeds are swapped for a static image
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ChartProvider {
+    Datawrapper,
+    Flourish,
+}
+
+impl ChartProvider {
+    fn name(self) -> &'static str {
+        match self {
+            ChartProvider::Datawrapper => "datawrapper",
+            ChartProvider::Flourish => "flourish",
+        }
+    }
+}
+
+/// A recognized chart embed: the interactive version and the static image its service publishes.
+/// The image URL follows the service's naming convention, so it may not exist for every chart.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct ChartEmbed {
+    provider: ChartProvider,
+    live_url: String,
+    image_url: String,
+}
+
+/// Datawrapper (`datawrapper.dwcdn.net/{id}/{version}/`) and Flourish
+/// (`flo.uri.sh/visualisation/{id}/embed`, `public.flourish.studio/story/{id}/`) embed URLs
+fn chart_embed(src: &str) -> Option<ChartEmbed> {
+    let url = Url::parse(src.trim()).ok()?;
+    let segments: Vec<&str> = url.path_segments()?.filter(|segment| !segment.is_empty()).collect();
+    match url.host_str()? {
+        "datawrapper.dwcdn.net" => {
+            let id = segments.first().filter(|id| id.chars().all(|c| c.is_ascii_alphanumeric()))?;
+            let chart = match segments.get(1).filter(|version| version.chars().all(|c| c.is_ascii_digit())) {
+                Some(version) => format!("https://datawrapper.dwcdn.net/{}/{}/", id, version),
+                None => format!("https://datawrapper.dwcdn.net/{}/", id),
+            };
+            Some(ChartEmbed { provider: ChartProvider::Datawrapper, image_url: format!("{}full.png", chart), live_url: chart })
+        }
+        "flo.uri.sh" | "public.flourish.studio" => flourish_embed(&segments),
+        _ => None,
+    }
+}
+
+/// `visualisation/{id}` or `story/{id}`, as found in Flourish URLs and `data-src` attributes
+fn flourish_embed(segments: &[&str]) -> Option<ChartEmbed> {
+    let kind = segments.first().filter(|kind| matches!(**kind, "visualisation" | "story"))?;
+    let id = segments.get(1).filter(|id| !id.is_empty() && id.chars().all(|c| c.is_ascii_digit()))?;
+    Some(ChartEmbed {
+        provider: ChartProvider::Flourish,
+        live_url: format!("https://public.flourish.studio/{}/{}/", kind, id),
+        image_url: format!("https://public.flourish.studio/{}/{}/thumbnail", kind, id),
+    })
+}
+
+/// Static image linking to the live chart, in place of its embed. Without `with_image` (the
+/// service has no image for this chart), only the caption linking to the live chart is kept.
+fn snapshot_html(embed: &ChartEmbed, title: Option<&str>, with_image: bool) -> String {
+    let title = title.map(str::trim).filter(|title| !title.is_empty()).unwrap_or("Interactive chart");
+    let image = if with_image {
+        format!(r#"<a href="{}"><img src="{}" alt="{}"></a>"#, escape_html(&embed.live_url), escape_html(&embed.image_url), escape_html(title))
+    } else {
+        String::new()
+    };
+    format!(
+        r#"<figure data-chart-embed="{}">{}<figcaption><a href="{}">{}</a></figcaption></figure>"#,
+        embed.provider.name(),
+        image,
+        escape_html(&embed.live_url),
+        escape_html(title)
+    )
+}
+
+/// Runs `on_embed` on every chart embed of `html`: iframes, and Flourish's script placeholders
+fn rewrite_chart_embeds(html: &str, on_embed: impl Fn(&mut Element, ChartEmbed)) -> Result<String, RewritingError> {
+    rewrite_str(
+        html,
+        RewriteStrSettings {
+            element_content_handlers: vec![
+                element!("iframe[src], iframe[data-src]", |el| {
+                    let src = el.get_attribute("src").filter(|src| src.starts_with("http")).or_else(|| el.get_attribute("data-src"));
+                    if let Some(embed) = src.map(|src| unescape_html(&src)).as_deref().and_then(chart_embed) {
+                        on_embed(el, embed);
+                    }
+                    Ok(())
+                }),
+                element!("div.flourish-embed[data-src]", |el| {
+                    let src = el.get_attribute("data-src").unwrap_or_default();
+                    let segments: Vec<&str> = src.split('/').filter(|segment| !segment.is_empty()).collect();
+                    if let Some(embed) = flourish_embed(&segments) {
+                        on_embed(el, embed);
+                    }
+                    Ok(())
+                }),
+            ],
+            ..RewriteStrSettings::default()
+        },
+    )
+}
+
+/// Whether the static image of `embed` exists, asked once per chart with a HEAD request and
+/// then remembered. Failed requests are not remembered, so the chart is checked again later.
+async fn image_exists(client: &reqwest::Client, embed: &ChartEmbed, state: &ProxyState) -> bool {
+    if let Some(exists) = state.chart_images.get(&embed.live_url) {
+        return *exists;
+    }
+    if connectivity::is_offline(state) {
+        return false;
+    }
+
+    let response = client.head(&embed.image_url).header(header::USER_AGENT, state.next_user_agent()).send().await;
+    let exists = match response {
+        Ok(response) => {
+            let content_type = response.headers().get(header::CONTENT_TYPE).and_then(|v| v.to_str().ok()).unwrap_or("");
+            response.status().is_success() && content_type.starts_with("image/")
+        }
+        Err(e) => {
+            println!("[charts::image_exists] {} not checked: {}", embed.image_url, e);
+            return false;
+        }
+    };
+    if !exists {
+        println!("[charts::image_exists] No static image for {}", embed.live_url);
+    }
+    state.chart_images.insert(embed.live_url.clone(), exists);
+    exists
+}
+
+/// Replaces Datawrapper and Flourish embeds by the static image their service publishes,
+/// linked to the live chart, or by a link to the live chart when the chart has no image.
+/// Readability drops iframes, so without this the chart is lost. Returns the input unchanged
+/// if rewriting fails.
+pub async fn replace_chart_embeds(html: &str, state: &ProxyState) -> String {
+    if !html.contains("datawrapper") && !html.contains("flourish") && !html.contains("flo.uri.sh") {
+        return html.to_string();
+    }
+
+    let found = RefCell::new(Vec::new());
+    if let Err(e) = rewrite_chart_embeds(html, |_, embed| found.borrow_mut().push(embed)) {
+        println!("[charts::replace_chart_embeds] Rewriting failed, keeping original HTML: {}", e);
+        return html.to_string();
+    }
+    let mut seen = HashSet::new();
+    let embeds: Vec<ChartEmbed> = found.into_inner().into_iter().filter(|embed| seen.insert(embed.live_url.clone())).collect();
+    if embeds.is_empty() {
+        return html.to_string();
+    }
+
+    let client = match reqwest::Client::builder().timeout(IMAGE_CHECK_TIMEOUT).build() {
+        Ok(client) => client,
+        Err(e) => {
+            println!("[charts::replace_chart_embeds] No client to check chart images, keeping original HTML: {}", e);
+            return html.to_string();
+        }
+    };
+    let with_image: HashSet<String> = stream::iter(embeds)
+        .map(|embed| {
+            let client = &client;
+            async move { image_exists(client, &embed, state).await.then_some(embed.live_url) }
+        })
+        .buffer_unordered(IMAGE_CHECK_CONCURRENCY)
+        .filter_map(|live_url| async { live_url })
+        .collect()
+        .await;
+
+    let replaced = Cell::new(0usize);
+    let result = rewrite_chart_embeds(html, |el, embed| {
+        // Attribute values come as written in the page, entities included
+        let title = el.get_attribute("title").or_else(|| el.get_attribute("aria-label")).map(|title| unescape_html(&title));
+        el.replace(&snapshot_html(&embed, title.as_deref(), with_image.contains(&embed.live_url)), ContentType::Html);
+        replaced.set(replaced.get() + 1);
+    });
+
+    match result {
+        Ok(output) => {
+            println!("[charts::replace_chart_embeds] Replaced {} chart embeds, {} charts with an image", replaced.get(), with_image.len());
+            output
+        }
+        Err(e) => {
+            println!("[charts::replace_chart_embeds] Rewriting failed, keeping original HTML: {}", e);
+            html.to_string()
+        }
+    }
+}
+
+/// Whether chart embeds of articles at `url` are replaced: the feature is on and the host
+/// (or a parent domain) didn't opt out
+pub fn snapshots_enabled_for(url: &Url, state: &ProxyState) -> bool {
+    if !state.chart_snapshots.load(Ordering::Relaxed) {
+        return false;
+    }
+    let host = url.host_str().unwrap_or("").to_ascii_lowercase();
+    !state.chart_snapshot_opt_outs.iter().any(|opted_out| host_in_domain(&host, &opted_out))
+}
+
+/// Turns chart snapshots on or off for every article when `domain` is `None`, else for
+/// `domain` and its subdomains (some charts mean nothing as a still image)
+pub fn logic_set_chart_snapshots(domain: Option<String>, enabled: bool, state: &ProxyState) -> Result<(), String> {
+    let Some(domain) = domain else {
+        println!("[charts::set_chart_snapshots] all hosts -> {}", enabled);
+        state.chart_snapshots.store(enabled, Ordering::Relaxed);
+        return Ok(());
+    };
+
+    let host = host_of_domain_key(&domain);
+    if host.is_empty() {
+        return Err("Domain is required".into());
+    }
+    println!("[charts::set_chart_snapshots] {} -> {}", host, enabled);
+    if enabled {
+        state.chart_snapshot_opt_outs.remove(&host);
+    } else {
+        state.chart_snapshot_opt_outs.insert(host);
+    }
+    Ok(())
+}
+
+/// Removes the chart snapshot opt-outs of `domain` and its subdomains
+pub fn clear_chart_opt_outs_for_domain(domain: &str, dry_run: bool, state: &ProxyState) -> MutationReport {
+    let mut report = MutationReport::new(dry_run);
+    let host = host_of_domain_key(domain);
+
+    let matching: Vec<String> = state.chart_snapshot_opt_outs.iter().map(|key| key.clone()).filter(|key| host_in_domain(key, &host)).collect();
+    for key in matching {
+        report.record("chart_snapshot_opt_outs", key.clone(), None);
+        if !dry_run {
+            state.chart_snapshot_opt_outs.remove(&key);
+        }
+    }
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{check_clear_for_domain, serve};
+    use axum::http::StatusCode;
+    use axum::routing::get;
+    use axum::Router;
+    use std::sync::atomic::AtomicUsize;
+    use std::sync::Arc;
+
+    const ARTICLE: &str = r#"<article><p>Turnout by region:</p>
+<iframe title="Turnout by region" src="https://datawrapper.dwcdn.net/aB3dE/4/" height="400"></iframe>
+<p>And the seats won:</p>
+<div class="flourish-embed" data-src="visualisation/1234567"></div></article>"#;
+
+    #[tokio::test]
+    async fn charts_without_a_static_image_keep_only_the_link() {
+        let state = ProxyState::default();
+        state.chart_images.insert("https://datawrapper.dwcdn.net/aB3dE/4/".into(), true);
+        state.chart_images.insert("https://public.flourish.studio/visualisation/1234567/".into(), false);
+
+        let html = replace_chart_embeds(ARTICLE, &state).await;
+        assert!(!html.contains("<iframe") && !html.contains("flourish-embed"), "{}", html);
+        assert!(html.contains(r#"<img src="https://datawrapper.dwcdn.net/aB3dE/4/full.png" alt="Turnout by region">"#), "{}", html);
+        assert!(!html.contains("thumbnail"), "{}", html);
+        assert!(
+            html.contains(r#"<figure data-chart-embed="flourish"><figcaption><a href="https://public.flourish.studio/visualisation/1234567/">Interactive chart</a></figcaption></figure>"#),
+            "{}",
+            html
+        );
+    }
+
+    #[tokio::test]
+    async fn chart_images_are_checked_once_per_chart() {
+        let hits = Arc::new(AtomicUsize::new(0));
+        let counted = hits.clone();
+        let app = Router::new()
+            .route(
+                "/chart/full.png",
+                get(move || {
+                    counted.fetch_add(1, Ordering::SeqCst);
+                    async { ([(header::CONTENT_TYPE, "image/png")], "png") }
+                }),
+            )
+            .route("/missing/full.png", get(|| async { StatusCode::NOT_FOUND }))
+            .route("/page/full.png", get(|| async { ([(header::CONTENT_TYPE, "text/html")], "<html></html>") }));
+        let addr = serve(app).await;
+        let chart = |path: &str| ChartEmbed {
+            provider: ChartProvider::Datawrapper,
+            live_url: format!("http://{}/{}/", addr, path),
+            image_url: format!("http://{}/{}/full.png", addr, path),
+        };
+
+        let state = ProxyState::default();
+        let client = reqwest::Client::new();
+        assert!(image_exists(&client, &chart("chart"), &state).await);
+        assert!(image_exists(&client, &chart("chart"), &state).await);
+        assert_eq!(hits.load(Ordering::SeqCst), 1);
+        assert!(!image_exists(&client, &chart("missing"), &state).await);
+        assert!(!image_exists(&client, &chart("page"), &state).await);
+        assert_eq!(state.chart_images.len(), 3);
+    }
+
+    #[test]
+    fn clears_the_opt_outs_of_a_domain() {
+        check_clear_for_domain(
+            |state, host| {
+                state.chart_snapshot_opt_outs.insert(host.to_string());
+            },
+            clear_chart_opt_outs_for_domain,
+            |state| state.chart_snapshot_opt_outs.iter().map(|key| key.clone()).collect(),
+        );
+    }
+}
@@ -0,0 +1,127 @@
+use crate::shared::ProxyState;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use serde::{Deserialize, Serialize};
+use std::io::{Read, Write};
+
+/// First bytes of a zstd frame. They aren't valid UTF-8 (0xB5 can't follow an ASCII byte),
+/// so compressed entries are told from entries stored as plain text without a flag.
+const ZSTD_MAGIC: [u8; 4] = [0x28, 0xB5, 0x2F, 0xFD];
+
+/// Compression of stored article HTML (versions, rendered extractions) and of large one-shot
+/// transfer bodies
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CompressionConfig {
+    /// Entries written from now on are compressed. Entries already stored stay readable either
+    /// way, and plain ones are compressed when their store is next written to.
+    pub enabled: bool,
+    /// zstd level, 1 (fastest) to 22
+    pub level: i32,
+    /// Entries smaller than this are stored as is
+    pub min_size: usize,
+    /// Handle transfers at least `min_size` long are parked gzipped and served with
+    /// `Content-Encoding: gzip`
+    pub compress_transfers: bool,
+}
+
+impl Default for CompressionConfig {
+    fn default() -> Self {
+        Self { enabled: true, level: 3, min_size: 1024, compress_transfers: true }
+    }
+}
+
+/// Text as stored: a zstd frame, or the UTF-8 bytes of an entry stored uncompressed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StoredText {
+    bytes: Vec<u8>,
+    /// Length of the text, in bytes
+    raw_len: usize,
+}
+
+impl StoredText {
+    /// Stores `text`, compressed when `config` asks for it and it's worth it
+    pub fn encode(text: &str, config: &CompressionConfig) -> Self {
+        if config.enabled && text.len() >= config.min_size {
+            match zstd::bulk::compress(text.as_bytes(), config.level) {
+                Ok(bytes) if bytes.len() < text.len() => return Self { bytes, raw_len: text.len() },
+                Ok(_) => {}
+                Err(e) => println!("[compression::encode] Storing {} bytes uncompressed: {}", text.len(), e),
+            }
+        }
+        Self { bytes: text.as_bytes().to_vec(), raw_len: text.len() }
+    }
+
+    pub fn decode(&self) -> Result<String, String> {
+        if !self.is_compressed() {
+            return String::from_utf8(self.bytes.clone()).map_err(|e| e.to_string());
+        }
+        let bytes = zstd::bulk::decompress(&self.bytes, self.raw_len).map_err(|e| format!("Corrupted compressed entry: {}", e))?;
+        String::from_utf8(bytes).map_err(|e| e.to_string())
+    }
+
+    pub fn is_compressed(&self) -> bool {
+        self.bytes.starts_with(&ZSTD_MAGIC)
+    }
+
+    /// Bytes held in memory
+    pub fn stored_len(&self) -> usize {
+        self.bytes.len()
+    }
+
+    /// Bytes of the text once decoded
+    pub fn raw_len(&self) -> usize {
+        self.raw_len
+    }
+
+    /// Compresses an entry stored as plain text when `config` now asks for it. Compressed
+    /// entries are left alone. Returns whether the entry changed.
+    pub fn migrate(&mut self, config: &CompressionConfig) -> bool {
+        if self.is_compressed() || !config.enabled || self.raw_len < config.min_size {
+            return false;
+        }
+        let Ok(text) = self.decode() else {
+            return false;
+        };
+        let encoded = Self::encode(&text, config);
+        let changed = encoded.is_compressed();
+        *self = encoded;
+        changed
+    }
+}
+
+/// How many times smaller a store's entries are than their text, for maintenance reports
+pub fn compression_ratio(stored: usize, raw: usize) -> Option<f64> {
+    (stored > 0).then(|| raw as f64 / stored as f64)
+}
+
+/// `body` gzipped for a one-shot transfer, when `config` asks for it. Webviews decode gzip
+/// natively, which zstd isn't yet everywhere.
+pub fn gzip_transfer(body: &[u8], config: &CompressionConfig) -> Option<Vec<u8>> {
+    if !config.compress_transfers || body.len() < config.min_size {
+        return None;
+    }
+    let mut encoder = GzEncoder::new(Vec::new(), flate2::Compression::fast());
+    encoder.write_all(body).ok()?;
+    encoder.finish().ok().filter(|gzipped| gzipped.len() < body.len())
+}
+
+pub fn gunzip(bytes: &[u8]) -> Result<Vec<u8>, String> {
+    let mut body = Vec::new();
+    GzDecoder::new(bytes).read_to_end(&mut body).map_err(|e| e.to_string())?;
+    Ok(body)
+}
+
+pub fn logic_get_compression_config(state: &ProxyState) -> CompressionConfig {
+    **state.compression.load()
+}
+
+pub fn logic_set_compression_config(config: CompressionConfig, state: &ProxyState) -> Result<(), String> {
+    let levels = zstd::compression_level_range();
+    if !levels.contains(&config.level) || config.level < 1 {
+        return Err(format!("Compression level must be between 1 and {}, got {}", levels.end(), config.level));
+    }
+    println!("[compression::set_compression_config] Compression {} (level {})", if config.enabled { "enabled" } else { "disabled" }, config.level);
+    state.compression.store(std::sync::Arc::new(config));
+    Ok(())
+}
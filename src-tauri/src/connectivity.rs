@@ -0,0 +1,187 @@
+use crate::actions::{ArticleAction, ArticleActionParams};
+use crate::shared::ProxyState;
+use crate::supervisor::{self, Heartbeat, RestartPolicy};
+use serde::Serialize;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::sync::watch;
+
+/// Event emitted when the app goes offline or back online. The payload is a `ConnectivityStatus`.
+pub const CONNECTIVITY_CHANGED_EVENT: &str = "connectivity-changed";
+
+/// Error returned by network features in offline mode when nothing cached can stand in
+pub const OFFLINE: &str = "OFFLINE";
+
+/// Error returned by article actions recorded in offline mode, to run once back online
+pub const OFFLINE_QUEUED: &str = "OFFLINE_QUEUED";
+
+/// Name of the supervised connectivity probe
+pub const PROBE_TASK: &str = "connectivity_probe";
+
+/// Answers 204 without a body: any HTTP response means the network is reachable
+const PROBE_URL: &str = "http://connectivitycheck.gstatic.com/generate_204";
+
+const PROBE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Probe interval while online, and while offline (to notice the network coming back sooner)
+const PROBE_INTERVAL_ONLINE: Duration = Duration::from_secs(60);
+const PROBE_INTERVAL_OFFLINE: Duration = Duration::from_secs(15);
+
+/// Failed probes in a row before the app goes offline, successful ones before it's back
+/// online: a single dropped probe doesn't flip the state
+const OFFLINE_AFTER_FAILURES: u32 = 2;
+const ONLINE_AFTER_SUCCESSES: u32 = 2;
+
+/// Article actions kept for later while offline; the oldest are dropped past this
+const MAX_QUEUED_ACTIONS: usize = 50;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct ConnectivityStatus {
+    /// Network features short-circuit: caches are served, other requests fail with `OFFLINE`
+    pub offline: bool,
+    /// Offline because of `set_offline_mode`, as opposed to the probe
+    pub forced: bool,
+    /// Unix time (ms) of the last change
+    pub since: u64,
+}
+
+/// An article action recorded in offline mode
+#[derive(Debug, Clone, Serialize)]
+pub struct QueuedAction {
+    pub session_id: String,
+    pub action: ArticleAction,
+    pub params: ArticleActionParams,
+    /// Unix time (ms) the action was asked for
+    pub queued_at: u64,
+}
+
+pub struct ConnectivityStore {
+    forced: bool,
+    /// Probe verdict, with hysteresis
+    detected_offline: bool,
+    /// Probes in a row contradicting `detected_offline`
+    streak: u32,
+    queued: Vec<QueuedAction>,
+    status: watch::Sender<ConnectivityStatus>,
+}
+
+impl Default for ConnectivityStore {
+    fn default() -> Self {
+        Self {
+            forced: false,
+            detected_offline: false,
+            streak: 0,
+            queued: Vec::new(),
+            status: watch::channel(ConnectivityStatus { offline: false, forced: false, since: now_millis() }).0,
+        }
+    }
+}
+
+impl ConnectivityStore {
+    /// Publishes the status when it changed
+    fn publish(&self) {
+        let offline = self.forced || self.detected_offline;
+        self.status.send_if_modified(|status| {
+            if status.offline == offline && status.forced == self.forced {
+                return false;
+            }
+            *status = ConnectivityStatus { offline, forced: self.forced, since: now_millis() };
+            true
+        });
+    }
+}
+
+fn now_millis() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_millis() as u64).unwrap_or(0)
+}
+
+pub fn is_offline(state: &ProxyState) -> bool {
+    state.connectivity.lock().unwrap().status.borrow().offline
+}
+
+/// `Err(OFFLINE)` in offline mode, for network entry points with no cache to fall back on
+pub fn ensure_online(state: &ProxyState) -> Result<(), String> {
+    if is_offline(state) {
+        Err(OFFLINE.to_string())
+    } else {
+        Ok(())
+    }
+}
+
+/// Status changes from now on, starting with the current status. The app forwards them to the
+/// frontend as `CONNECTIVITY_CHANGED_EVENT` and retries queued actions when back online.
+pub fn subscribe(state: &ProxyState) -> watch::Receiver<ConnectivityStatus> {
+    state.connectivity.lock().unwrap().status.subscribe()
+}
+
+pub fn logic_get_connectivity(state: &ProxyState) -> ConnectivityStatus {
+    *state.connectivity.lock().unwrap().status.borrow()
+}
+
+/// Goes offline right away when `enabled`, without waiting for requests to time out. Turning
+/// it off leaves connectivity to the probe.
+pub fn logic_set_offline_mode(enabled: bool, state: &ProxyState) -> ConnectivityStatus {
+    println!("[connectivity::set_offline_mode] {}", enabled);
+    let mut store = state.connectivity.lock().unwrap();
+    store.forced = enabled;
+    store.publish();
+    let status = *store.status.borrow();
+    status
+}
+
+/// Takes a probe result into account: the state flips after `OFFLINE_AFTER_FAILURES` failures
+/// or `ONLINE_AFTER_SUCCESSES` successes in a row
+pub fn record_probe(reachable: bool, state: &ProxyState) {
+    let mut store = state.connectivity.lock().unwrap();
+    if reachable != store.detected_offline {
+        store.streak = 0;
+        return;
+    }
+    store.streak += 1;
+    let needed = if store.detected_offline { ONLINE_AFTER_SUCCESSES } else { OFFLINE_AFTER_FAILURES };
+    if store.streak >= needed {
+        store.detected_offline = !reachable;
+        store.streak = 0;
+        println!("[connectivity::probe] Network {}", if reachable { "reachable again" } else { "unreachable" });
+        store.publish();
+    }
+}
+
+/// Starts the connectivity probe as a supervised task
+pub fn start_probe(state: &ProxyState) {
+    let probe_state = state.clone();
+    supervisor::supervise(&state.supervisor, PROBE_TASK, RestartPolicy::default(), move |heartbeat| run_probe(heartbeat, probe_state.clone()));
+}
+
+/// Probes connectivity until the app quits. Nothing is sent while offline mode is forced.
+async fn run_probe(heartbeat: Heartbeat, state: ProxyState) -> Result<(), String> {
+    let client = reqwest::Client::builder().timeout(PROBE_TIMEOUT).build().map_err(|e| e.to_string())?;
+    loop {
+        heartbeat.beat();
+        let forced = state.connectivity.lock().unwrap().forced;
+        if !forced {
+            let reachable = client.head(PROBE_URL).send().await.is_ok();
+            record_probe(reachable, &state);
+        }
+        let interval = if is_offline(&state) { PROBE_INTERVAL_OFFLINE } else { PROBE_INTERVAL_ONLINE };
+        tokio::time::sleep(interval).await;
+    }
+}
+
+/// Records an article action asked for while offline, to be run once back online
+pub fn queue_action(session_id: &str, action: ArticleAction, params: ArticleActionParams, state: &ProxyState) {
+    let mut store = state.connectivity.lock().unwrap();
+    if store.queued.len() >= MAX_QUEUED_ACTIONS {
+        store.queued.remove(0);
+    }
+    store.queued.push(QueuedAction { session_id: session_id.to_string(), action, params, queued_at: now_millis() });
+}
+
+/// Actions recorded while offline, oldest first, emptying the queue
+pub fn take_queued_actions(state: &ProxyState) -> Vec<QueuedAction> {
+    std::mem::take(&mut state.connectivity.lock().unwrap().queued)
+}
+
+/// Actions waiting for the network, oldest first
+pub fn logic_list_queued_actions(state: &ProxyState) -> Vec<QueuedAction> {
+    state.connectivity.lock().unwrap().queued.clone()
+}
@@ -0,0 +1,718 @@
+use crate::dates;
+use crate::shared::{host_in_domain, host_of_domain_key, origin_of, MutationReport, ProxyState};
+use dashmap::DashMap;
+use reqwest::header::{self, HeaderName, HeaderValue};
+use reqwest::redirect::{Attempt, Policy};
+use reqwest::{Method, StatusCode};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use url::Url;
+
+/// Version of the credential keys: 1 is whatever the frontend sent (host, host:port, URL...),
+/// 2 is the origin requests are looked up with (`https://host[:port]`)
+pub const CREDENTIAL_STORE_VERSION: u32 = 2;
+
+/// Redirects followed by clients sending the stored credentials
+pub const MAX_REDIRECTS: usize = 10;
+
+/// Audit entries kept, oldest dropped first
+const MAX_AUDIT_ENTRIES: usize = 500;
+
+/// Headers a custom header credential can't be sent as: the client sets them itself
+const RESERVED_AUTH_HEADERS: [HeaderName; 5] = [header::HOST, header::CONTENT_LENGTH, header::TRANSFER_ENCODING, header::CONNECTION, header::COOKIE];
+
+/// How requests to an origin authenticate. Never serialized back: secrets stay in the backend.
+/// The frontend sends `{"basic": {"user", "pass"}}`, `{"bearer": "token"}` or
+/// `{"header": {"name", "value"}}`.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AuthMethod {
+    /// HTTP Basic Auth
+    Basic { user: String, pass: String },
+    /// `Authorization: Bearer <token>`
+    Bearer(String),
+    /// A header of the site's own, such as `X-API-Key`
+    Header { name: String, value: String },
+}
+
+impl AuthMethod {
+    /// Name of the method for logs and provenance, never the credentials
+    pub fn kind(&self) -> &'static str {
+        match self {
+            AuthMethod::Basic { .. } => "basic_auth",
+            AuthMethod::Bearer(_) => "bearer_token",
+            AuthMethod::Header { .. } => "custom_header",
+        }
+    }
+
+    /// What identifies the credentials in conflict views without revealing them: the
+    /// username, or the kind of token
+    fn label(&self) -> String {
+        match self {
+            AuthMethod::Basic { user, .. } => user.clone(),
+            AuthMethod::Bearer(_) => "bearer token".to_string(),
+            AuthMethod::Header { name, .. } => format!("{} header", name),
+        }
+    }
+
+    /// Rejects credentials that can't be sent: empty tokens, invalid or reserved header names
+    pub fn validate(&self) -> Result<(), String> {
+        match self {
+            AuthMethod::Basic { .. } => Ok(()),
+            AuthMethod::Bearer(token) => {
+                if token.trim().is_empty() {
+                    return Err("Bearer token is empty".into());
+                }
+                HeaderValue::from_str(&format!("Bearer {}", token)).map(|_| ()).map_err(|_| "Bearer token can't be sent as a header".into())
+            }
+            AuthMethod::Header { name, value } => {
+                let name = HeaderName::from_bytes(name.trim().as_bytes()).map_err(|_| format!("Invalid header name '{}'", name))?;
+                if RESERVED_AUTH_HEADERS.contains(&name) {
+                    return Err(format!("'{}' can't carry credentials", name));
+                }
+                HeaderValue::from_str(value).map(|_| ()).map_err(|_| format!("Value of '{}' can't be sent as a header", name))
+            }
+        }
+    }
+
+    /// Adds the credentials to an upstream request
+    pub fn apply(&self, request: reqwest::RequestBuilder) -> reqwest::RequestBuilder {
+        match self {
+            AuthMethod::Basic { user, pass } => request.basic_auth(user, Some(pass)),
+            AuthMethod::Bearer(token) => request.bearer_auth(token),
+            AuthMethod::Header { name, value } => request.header(name.trim(), value.as_str()),
+        }
+    }
+}
+
+/// Header of a custom header credential of `first`'s origin that a request redirected to
+/// `next` would take to another host or port. reqwest drops `Authorization` and cookies on
+/// such hops, but not headers of the site's own.
+fn custom_header_leaving(first: &Url, next: &Url, credentials: &DashMap<String, AuthMethod>) -> Option<HeaderName> {
+    if next.host_str() == first.host_str() && next.port_or_known_default() == first.port_or_known_default() {
+        return None;
+    }
+    match credentials.get(&origin_of(first)).as_deref() {
+        Some(AuthMethod::Header { name, .. }) => HeaderName::from_bytes(name.trim().as_bytes()).ok(),
+        _ => None,
+    }
+}
+
+/// Whether a redirect policy must stop at `attempt` rather than let reqwest follow it: the
+/// hop would take a custom header credential off its origin. `execute` follows it without.
+pub fn stops_custom_header(attempt: &Attempt, credentials: &DashMap<String, AuthMethod>) -> bool {
+    attempt.previous().first().is_some_and(|first| custom_header_leaving(first, attempt.url(), credentials).is_some())
+}
+
+/// `Policy::limited(MAX_REDIRECTS)` for clients sending the stored credentials, stopping
+/// where `stops_custom_header` says so. Requests go through `execute`.
+pub fn redirect_policy(state: &ProxyState) -> Policy {
+    let credentials = state.auth_credentials.clone();
+    Policy::custom(move |attempt| {
+        if attempt.previous().len() > MAX_REDIRECTS {
+            attempt.error("too many redirects")
+        } else if stops_custom_header(&attempt, &credentials) {
+            attempt.stop()
+        } else {
+            attempt.follow()
+        }
+    })
+}
+
+/// Runs `request` on a client whose redirect policy stops with `stops_custom_header`, and
+/// follows the redirects stopped at, without the custom header (nor the headers reqwest
+/// drops on a hop to another host). The rest of each chain is left to the policy.
+pub async fn execute(client: &reqwest::Client, mut request: reqwest::Request, state: &ProxyState) -> reqwest::Result<reqwest::Response> {
+    let first = request.url().clone();
+    let mut hops = 0;
+    loop {
+        let retry = request.try_clone();
+        let response = client.execute(request).await?;
+        let location = response
+            .headers()
+            .get(header::LOCATION)
+            .and_then(|location| location.to_str().ok())
+            .and_then(|location| response.url().join(location).ok())
+            .filter(|_| response.status().is_redirection() && hops < MAX_REDIRECTS);
+        let (Some(location), Some(mut next)) = (location, retry) else {
+            return Ok(response);
+        };
+        let Some(name) = custom_header_leaving(&first, &location, &state.auth_credentials) else {
+            return Ok(response);
+        };
+        println!("[credentials::execute] Following the redirect to {} without the {} header", location, name);
+        for name in [name, header::AUTHORIZATION, header::COOKIE, header::PROXY_AUTHORIZATION, header::HOST] {
+            next.headers_mut().remove(name);
+        }
+        // Like reqwest: a form posted and redirected is followed with a GET
+        if matches!(response.status(), StatusCode::MOVED_PERMANENTLY | StatusCode::FOUND | StatusCode::SEE_OTHER) && !matches!(*next.method(), Method::GET | Method::HEAD) {
+            *next.method_mut() = Method::GET;
+            *next.body_mut() = None;
+            next.headers_mut().remove(header::CONTENT_TYPE);
+            next.headers_mut().remove(header::CONTENT_LENGTH);
+        }
+        *next.url_mut() = location;
+        request = next;
+        hops += 1;
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CredentialAuditAction {
+    /// Already an origin key
+    Kept,
+    /// Moved to its origin key
+    Rekeyed,
+    /// Several old keys held the same credentials for one origin: kept once
+    Merged,
+    /// Several old keys held different credentials for one origin: left for the user to pick
+    Collision,
+    /// Not a host or URL: left under its old key
+    Invalid,
+    /// The user picked the credentials of a collision
+    Resolved,
+    Confirmed,
+    RolledBack,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CredentialAuditEntry {
+    /// Unix time (ms)
+    pub at: i64,
+    pub action: CredentialAuditAction,
+    pub old_key: Option<String>,
+    pub new_key: Option<String>,
+    pub detail: Option<String>,
+}
+
+/// Old keys that map to the same origin with different credentials. Passwords and tokens
+/// stay in the backend; the UI picks by old key.
+#[derive(Debug, Clone, Serialize)]
+pub struct CredentialConflict {
+    pub origin: String,
+    /// Old key of each candidate, with its username (or the kind of token it holds)
+    pub candidates: Vec<(String, String)>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CredentialMigrationReport {
+    pub from_version: u32,
+    pub to_version: u32,
+    /// Old key to new key, for the frontend to persist
+    pub rekeyed: BTreeMap<String, String>,
+    pub conflicts: Vec<CredentialConflict>,
+    /// Keys that aren't hosts or URLs, left as they were
+    pub invalid: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CredentialAudit {
+    pub version: u32,
+    /// The pre-migration store is kept until `confirm_credential_migration`
+    pub pending_confirmation: bool,
+    pub conflicts: Vec<CredentialConflict>,
+    /// Oldest first
+    pub entries: Vec<CredentialAuditEntry>,
+}
+
+pub struct CredentialMigration {
+    version: u32,
+    /// Store as it was before migrating, until confirmed
+    backup: Option<HashMap<String, AuthMethod>>,
+    /// Keyed by origin, candidates by old key
+    conflicts: BTreeMap<String, BTreeMap<String, AuthMethod>>,
+    audit: Vec<CredentialAuditEntry>,
+}
+
+impl Default for CredentialMigration {
+    fn default() -> Self {
+        Self { version: 1, backup: None, conflicts: BTreeMap::new(), audit: Vec::new() }
+    }
+}
+
+impl CredentialMigration {
+    fn log(&mut self, action: CredentialAuditAction, old_key: Option<&str>, new_key: Option<&str>, detail: Option<String>) {
+        self.audit.push(CredentialAuditEntry {
+            at: dates::now_millis(),
+            action,
+            old_key: old_key.map(str::to_string),
+            new_key: new_key.map(str::to_string),
+            detail,
+        });
+        if self.audit.len() > MAX_AUDIT_ENTRIES {
+            let excess = self.audit.len() - MAX_AUDIT_ENTRIES;
+            self.audit.drain(..excess);
+        }
+    }
+
+    fn conflict_views(&self) -> Vec<CredentialConflict> {
+        self.conflicts
+            .iter()
+            .map(|(origin, candidates)| CredentialConflict {
+                origin: origin.clone(),
+                candidates: candidates.iter().map(|(old_key, credentials)| (old_key.clone(), credentials.label())).collect(),
+            })
+            .collect()
+    }
+}
+
+/// Origin a credential key stands for. Bare hosts (`example.com`, `example.com:8443`) are
+/// taken as HTTPS, except on port 80; paths, user info, case and default ports are dropped.
+pub fn origin_key(key: &str) -> Result<String, String> {
+    let key = key.trim();
+    if key.is_empty() {
+        return Err("Empty key".into());
+    }
+    let url = if key.contains("://") {
+        Url::parse(key).map_err(|e| e.to_string())?
+    } else {
+        let url = Url::parse(&format!("https://{}", key)).map_err(|e| e.to_string())?;
+        match url.port() {
+            Some(80) => Url::parse(&format!("http://{}", key)).map_err(|e| e.to_string())?,
+            _ => url,
+        }
+    };
+    if !matches!(url.scheme(), "http" | "https") || url.host_str().is_none_or(str::is_empty) {
+        return Err(format!("'{}' is not an http(s) origin", key));
+    }
+    Ok(origin_of(&url))
+}
+
+/// Moves every credential to its origin key. Keys mapping to one origin with the same
+/// credentials are merged; with different ones they're set aside as a conflict for the user
+/// to resolve, unless one of them already was the origin key, which keeps working meanwhile.
+/// The old store is kept until confirmed or rolled back. Running it again once migrated
+/// changes nothing.
+pub fn logic_migrate_credentials(state: &ProxyState) -> CredentialMigrationReport {
+    let mut migration = state.credential_migration.lock().unwrap();
+    let from_version = migration.version;
+    let mut report = CredentialMigrationReport {
+        from_version,
+        to_version: CREDENTIAL_STORE_VERSION,
+        rekeyed: BTreeMap::new(),
+        conflicts: Vec::new(),
+        invalid: Vec::new(),
+    };
+    if from_version >= CREDENTIAL_STORE_VERSION {
+        report.conflicts = migration.conflict_views();
+        return report;
+    }
+
+    // `set_proxy_auth` waits on the migration lock, so the snapshot can't miss a write
+    let old: HashMap<String, AuthMethod> = state.auth_credentials.iter().map(|entry| (entry.key().clone(), entry.value().clone())).collect();
+    let mut by_origin: BTreeMap<String, BTreeMap<String, AuthMethod>> = BTreeMap::new();
+    for (old_key, credentials) in &old {
+        match origin_key(old_key) {
+            Ok(origin) => {
+                by_origin.entry(origin).or_default().insert(old_key.clone(), credentials.clone());
+            }
+            Err(e) => {
+                migration.log(CredentialAuditAction::Invalid, Some(old_key), None, Some(e));
+                report.invalid.push(old_key.clone());
+            }
+        }
+    }
+    report.invalid.sort();
+
+    for (origin, candidates) in by_origin {
+        let mut distinct: Vec<&AuthMethod> = candidates.values().collect();
+        distinct.sort();
+        distinct.dedup();
+        for old_key in candidates.keys().filter(|old_key| **old_key != origin) {
+            state.auth_credentials.remove(old_key);
+        }
+
+        if distinct.len() > 1 {
+            // The exact key keeps its credentials; the others wait for the user's choice
+            match candidates.get(&origin) {
+                Some(credentials) => {
+                    state.auth_credentials.insert(origin.clone(), credentials.clone());
+                }
+                None => {
+                    state.auth_credentials.remove(&origin);
+                }
+            }
+            let keys: Vec<&str> = candidates.keys().map(String::as_str).collect();
+            migration.log(CredentialAuditAction::Collision, None, Some(&origin), Some(format!("from {}", keys.join(", "))));
+            migration.conflicts.insert(origin, candidates);
+            continue;
+        }
+
+        let credentials = distinct[0].clone();
+        state.auth_credentials.insert(origin.clone(), credentials);
+        for old_key in candidates.keys() {
+            let action = match (old_key == &origin, candidates.len()) {
+                (true, _) => CredentialAuditAction::Kept,
+                (false, 1) => CredentialAuditAction::Rekeyed,
+                (false, _) => CredentialAuditAction::Merged,
+            };
+            migration.log(action, Some(old_key), Some(&origin), None);
+            if old_key != &origin {
+                report.rekeyed.insert(old_key.clone(), origin.clone());
+            }
+        }
+    }
+
+    migration.version = CREDENTIAL_STORE_VERSION;
+    migration.backup = Some(old);
+    report.conflicts = migration.conflict_views();
+    println!(
+        "[credentials::migrate_credentials] v{} -> v{}: {} rekeyed, {} conflicts, {} invalid",
+        from_version,
+        CREDENTIAL_STORE_VERSION,
+        report.rekeyed.len(),
+        report.conflicts.len(),
+        report.invalid.len()
+    );
+    report
+}
+
+/// Uses the credentials stored under `old_key` for the conflicting `origin`
+pub fn logic_resolve_credential_conflict(origin: String, old_key: String, state: &ProxyState) -> Result<(), String> {
+    let mut migration = state.credential_migration.lock().unwrap();
+    let candidates = migration.conflicts.get(&origin).ok_or_else(|| format!("No credential conflict for {}", origin))?;
+    let credentials = candidates.get(&old_key).cloned().ok_or_else(|| format!("'{}' is not a candidate for {}", old_key, origin))?;
+    migration.conflicts.remove(&origin);
+    state.auth_credentials.insert(origin.clone(), credentials);
+    migration.log(CredentialAuditAction::Resolved, Some(&old_key), Some(&origin), None);
+    println!("[credentials::resolve_credential_conflict] {} uses the credentials of {}", origin, old_key);
+    Ok(())
+}
+
+/// Drops the pre-migration store: the migration can no longer be rolled back
+pub fn logic_confirm_credential_migration(state: &ProxyState) -> Result<(), String> {
+    let mut migration = state.credential_migration.lock().unwrap();
+    if migration.backup.take().is_none() {
+        return Err("No credential migration to confirm".into());
+    }
+    migration.log(CredentialAuditAction::Confirmed, None, None, None);
+    Ok(())
+}
+
+/// Puts the pre-migration store back, as it was, and forgets the conflicts. Credentials set
+/// since the migration are dropped with the rest.
+pub fn logic_rollback_credential_migration(state: &ProxyState) -> Result<(), String> {
+    let mut migration = state.credential_migration.lock().unwrap();
+    let backup = migration.backup.take().ok_or("No credential migration to roll back")?;
+    state.auth_credentials.clear();
+    for (key, credentials) in backup {
+        state.auth_credentials.insert(key, credentials);
+    }
+    migration.conflicts.clear();
+    migration.version = 1;
+    migration.log(CredentialAuditAction::RolledBack, None, None, None);
+    println!("[credentials::rollback_credential_migration] Restored {} credentials", state.auth_credentials.len());
+    Ok(())
+}
+
+pub fn logic_get_credential_audit(state: &ProxyState) -> CredentialAudit {
+    let migration = state.credential_migration.lock().unwrap();
+    CredentialAudit {
+        version: migration.version,
+        pending_confirmation: migration.backup.is_some(),
+        conflicts: migration.conflict_views(),
+        entries: migration.audit.clone(),
+    }
+}
+
+/// Stores credentials for `domain`: under its origin once the store is migrated, as given
+/// before (the frontend restores its saved keys, then migrates them). They replace any
+/// credentials of another method.
+pub fn logic_set_auth(domain: String, method: AuthMethod, state: &ProxyState) -> Result<(), String> {
+    method.validate()?;
+    let migration = state.credential_migration.lock().unwrap();
+    let key = if migration.version >= CREDENTIAL_STORE_VERSION { origin_key(&domain)? } else { domain };
+    println!("Set {} credentials for domain: {}", method.kind(), key);
+    state.auth_credentials.insert(key, method);
+    Ok(())
+}
+
+/// `set_auth` with Basic Auth credentials
+pub fn logic_set_proxy_auth(domain: String, username: String, password: String, state: &ProxyState) -> Result<(), String> {
+    logic_set_auth(domain, AuthMethod::Basic { user: username, pass: password }, state)
+}
+
+/// Forgets the conflicts and pre-migration credentials of `domain` (subdomains included)
+pub fn clear_credential_migration_for_domain(domain: &str, dry_run: bool, state: &ProxyState) -> MutationReport {
+    let mut report = MutationReport::new(dry_run);
+    let host = host_of_domain_key(domain);
+    let mut migration = state.credential_migration.lock().unwrap();
+
+    let conflicts: Vec<String> = migration.conflicts.keys().filter(|origin| host_in_domain(&host_of_domain_key(origin), &host)).cloned().collect();
+    for origin in conflicts {
+        report.record("credential_conflicts", origin.clone(), None);
+        if !dry_run {
+            migration.conflicts.remove(&origin);
+        }
+    }
+    if let Some(backup) = migration.backup.as_mut() {
+        let on_domain = |key: &str| origin_key(key).map(|origin| host_in_domain(&host_of_domain_key(&origin), &host)).unwrap_or(false);
+        let mut keys: Vec<String> = backup.keys().filter(|key| on_domain(key)).cloned().collect();
+        keys.sort();
+        for key in keys {
+            report.record("credential_backup", key.clone(), None);
+            if !dry_run {
+                backup.remove(&key);
+            }
+        }
+    }
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::check_clear_for_domain;
+
+    fn basic(user: &str) -> AuthMethod {
+        AuthMethod::Basic { user: user.to_string(), pass: format!("{}-secret", user) }
+    }
+
+    fn store(state: &ProxyState) -> BTreeMap<String, AuthMethod> {
+        state.auth_credentials.iter().map(|entry| (entry.key().clone(), entry.value().clone())).collect()
+    }
+
+    fn actions(state: &ProxyState) -> Vec<(CredentialAuditAction, Option<String>, Option<String>)> {
+        logic_get_credential_audit(state).entries.into_iter().map(|entry| (entry.action, entry.old_key, entry.new_key)).collect()
+    }
+
+    /// A version 1 store with every key format the frontend has sent, and two collisions: one
+    /// with the exact origin key among the candidates, one without
+    fn legacy_store() -> ProxyState {
+        let state = ProxyState::default();
+        for (key, credentials) in [
+            ("example.com", basic("ana")),
+            ("https://example.com/account/login?next=/", basic("ana")),
+            ("news.example.com:443", AuthMethod::Bearer("t0ken".into())),
+            ("http://intranet.example.com:8080/wiki", basic("ops")),
+            ("https://shop.example.com", basic("ben")),
+            ("shop.example.com", basic("carla")),
+            ("blog.example.com", basic("dev")),
+            ("https://BLOG.example.com/admin", AuthMethod::Header { name: "X-Api-Key".into(), value: "k".into() }),
+            ("not a host", basic("eve")),
+        ] {
+            state.auth_credentials.insert(key.to_string(), credentials);
+        }
+        state
+    }
+
+    #[test]
+    fn every_key_format_maps_to_its_origin() {
+        let cases = [
+            ("example.com", "https://example.com"),
+            ("  Example.COM  ", "https://example.com"),
+            ("example.com:8443", "https://example.com:8443"),
+            ("example.com:443", "https://example.com"),
+            ("example.com:80", "http://example.com"),
+            ("https://example.com/", "https://example.com"),
+            ("https://example.com:443/members?next=/", "https://example.com"),
+            ("http://example.com:8080/path", "http://example.com:8080"),
+            ("HTTPS://user:pw@Example.com", "https://example.com"),
+            ("[::1]:8080", "https://[::1]:8080"),
+            ("http://[::1]", "http://[::1]"),
+            ("bücher.example", "https://xn--bcher-kva.example"),
+        ];
+        for (key, origin) in cases {
+            assert_eq!(origin_key(key).as_deref(), Ok(origin), "{}", key);
+        }
+        for key in ["", "   ", "ftp://example.com", "file:///etc/hosts", "not a host", "https://"] {
+            assert!(origin_key(key).is_err(), "{} was accepted", key);
+        }
+    }
+
+    #[test]
+    fn migration_rekeys_merges_and_sets_collisions_aside() {
+        let state = legacy_store();
+        let report = logic_migrate_credentials(&state);
+        assert_eq!((report.from_version, report.to_version), (1, CREDENTIAL_STORE_VERSION));
+
+        let rekeyed: Vec<(&str, &str)> = report.rekeyed.iter().map(|(old, new)| (old.as_str(), new.as_str())).collect();
+        assert_eq!(
+            rekeyed,
+            [
+                ("example.com", "https://example.com"),
+                ("http://intranet.example.com:8080/wiki", "http://intranet.example.com:8080"),
+                ("https://example.com/account/login?next=/", "https://example.com"),
+                ("news.example.com:443", "https://news.example.com"),
+            ]
+        );
+        assert_eq!(report.invalid, ["not a host"]);
+
+        // The exact key keeps working during its conflict; the other conflict has no credentials
+        let conflicts: Vec<(String, Vec<(String, String)>)> = report.conflicts.into_iter().map(|conflict| (conflict.origin, conflict.candidates)).collect();
+        let candidate = |key: &str, label: &str| (key.to_string(), label.to_string());
+        assert_eq!(
+            conflicts,
+            [
+                ("https://blog.example.com".into(), vec![candidate("blog.example.com", "dev"), candidate("https://BLOG.example.com/admin", "X-Api-Key header")]),
+                ("https://shop.example.com".into(), vec![candidate("https://shop.example.com", "ben"), candidate("shop.example.com", "carla")]),
+            ]
+        );
+        assert_eq!(
+            store(&state),
+            BTreeMap::from([
+                ("http://intranet.example.com:8080".to_string(), basic("ops")),
+                ("https://example.com".to_string(), basic("ana")),
+                ("https://news.example.com".to_string(), AuthMethod::Bearer("t0ken".into())),
+                ("https://shop.example.com".to_string(), basic("ben")),
+                ("not a host".to_string(), basic("eve")),
+            ])
+        );
+
+        let logged = actions(&state);
+        let count = |action: CredentialAuditAction| logged.iter().filter(|(logged, _, _)| *logged == action).count();
+        assert_eq!(
+            [CredentialAuditAction::Rekeyed, CredentialAuditAction::Merged, CredentialAuditAction::Collision, CredentialAuditAction::Invalid, CredentialAuditAction::Kept].map(count),
+            [2, 2, 2, 1, 0]
+        );
+        assert!(logged.contains(&(CredentialAuditAction::Merged, Some("example.com".into()), Some("https://example.com".into()))));
+        assert!(logic_get_credential_audit(&state).pending_confirmation);
+    }
+
+    #[test]
+    fn migration_runs_once() {
+        let state = legacy_store();
+        logic_migrate_credentials(&state);
+        let migrated = store(&state);
+        let logged = actions(&state).len();
+
+        let again = logic_migrate_credentials(&state);
+        assert_eq!(again.from_version, CREDENTIAL_STORE_VERSION);
+        assert!(again.rekeyed.is_empty() && again.invalid.is_empty());
+        assert_eq!(again.conflicts.len(), 2);
+        assert_eq!(store(&state), migrated);
+        assert_eq!(actions(&state).len(), logged);
+
+        // A store already keyed by origin is kept as is
+        let state = ProxyState::default();
+        state.auth_credentials.insert("https://example.com".into(), basic("ana"));
+        let report = logic_migrate_credentials(&state);
+        assert!(report.rekeyed.is_empty() && report.conflicts.is_empty());
+        assert_eq!(actions(&state), [(CredentialAuditAction::Kept, Some("https://example.com".into()), Some("https://example.com".into()))]);
+    }
+
+    #[test]
+    fn conflicts_are_resolved_by_old_key() {
+        let state = legacy_store();
+        logic_migrate_credentials(&state);
+
+        assert!(logic_resolve_credential_conflict("https://blog.example.com".into(), "shop.example.com".into(), &state).is_err());
+        assert!(logic_resolve_credential_conflict("https://news.example.com".into(), "news.example.com:443".into(), &state).is_err());
+        logic_resolve_credential_conflict("https://blog.example.com".into(), "blog.example.com".into(), &state).unwrap();
+        assert_eq!(state.auth_credentials.get("https://blog.example.com").map(|entry| entry.value().clone()), Some(basic("dev")));
+        assert_eq!(logic_get_credential_audit(&state).conflicts.len(), 1);
+        assert_eq!(actions(&state).last().unwrap().0, CredentialAuditAction::Resolved);
+    }
+
+    #[test]
+    fn rollback_restores_the_legacy_store_until_confirmed() {
+        let state = legacy_store();
+        let legacy = store(&state);
+        logic_migrate_credentials(&state);
+        // Written after the migration, so keyed by origin, and dropped by the rollback
+        logic_set_auth("wiki.example.com".into(), basic("fay"), &state).unwrap();
+        assert!(state.auth_credentials.contains_key("https://wiki.example.com"));
+
+        logic_rollback_credential_migration(&state).unwrap();
+        assert_eq!(store(&state), legacy);
+        let audit = logic_get_credential_audit(&state);
+        assert_eq!((audit.version, audit.pending_confirmation, audit.conflicts.len()), (1, false, 0));
+        assert!(logic_rollback_credential_migration(&state).is_err());
+
+        // Before migrating, keys are stored as given
+        logic_set_auth("wiki.example.com".into(), basic("fay"), &state).unwrap();
+        assert!(state.auth_credentials.contains_key("wiki.example.com"));
+
+        // Migrated again and confirmed, the backup is gone
+        let report = logic_migrate_credentials(&state);
+        assert_eq!(report.rekeyed.get("wiki.example.com").map(String::as_str), Some("https://wiki.example.com"));
+        logic_confirm_credential_migration(&state).unwrap();
+        assert!(!logic_get_credential_audit(&state).pending_confirmation);
+        assert!(logic_rollback_credential_migration(&state).is_err());
+        assert!(logic_confirm_credential_migration(&state).is_err());
+        let logged: Vec<CredentialAuditAction> = actions(&state).into_iter().map(|(action, _, _)| action).collect();
+        assert!(logged.ends_with(&[CredentialAuditAction::Confirmed]));
+        assert!(logged.contains(&CredentialAuditAction::RolledBack));
+    }
+
+    #[test]
+    fn clears_the_migration_leftovers_of_a_domain() {
+        check_clear_for_domain(
+            |state, host| {
+                let mut migration = state.credential_migration.lock().unwrap();
+                migration.backup.get_or_insert_with(HashMap::new).insert(host.to_string(), AuthMethod::Bearer("token".into()));
+                migration.conflicts.insert(format!("https://{}", host), BTreeMap::new());
+            },
+            clear_credential_migration_for_domain,
+            |state| {
+                let migration = state.credential_migration.lock().unwrap();
+                let backup = migration.backup.iter().flat_map(|backup| backup.keys().map(|key| format!("backup {}", key)));
+                backup.chain(migration.conflicts.keys().map(|origin| format!("conflict {}", origin))).collect()
+            },
+        );
+    }
+
+    #[tokio::test]
+    async fn custom_header_credentials_are_not_sent_to_the_host_redirected_to() {
+        use crate::shared::{logic_fetch_article, logic_fetch_raw_html, ArticleOptions};
+        use axum::http::HeaderMap;
+        use axum::response::Redirect;
+        use axum::routing::get;
+        use axum::Router;
+        use std::sync::{Arc, Mutex};
+
+        // Path requested and the `X-API-Key` it came with, on either host
+        type Seen = Arc<Mutex<Vec<(String, Option<String>)>>>;
+        let seen = Seen::default();
+        let record = |seen: &Seen, path: &str, headers: &HeaderMap| {
+            let key = headers.get("x-api-key").and_then(|value| value.to_str().ok()).map(str::to_string);
+            seen.lock().unwrap().push((path.to_string(), key));
+        };
+
+        let other_seen = seen.clone();
+        let other = crate::test_support::serve(Router::new().route(
+            "/landing",
+            get(move |headers: HeaderMap| async move {
+                record(&other_seen, "other/landing", &headers);
+                axum::response::Html("<html><body><article><p>Landed on the other host.</p></article></body></html>")
+            }),
+        ))
+        .await;
+        let (start_seen, moved_seen) = (seen.clone(), seen.clone());
+        let origin = crate::test_support::serve(
+            Router::new()
+                .route(
+                    "/start",
+                    get(move |headers: HeaderMap| async move {
+                        record(&start_seen, "origin/start", &headers);
+                        Redirect::to("/moved")
+                    }),
+                )
+                .route(
+                    "/moved",
+                    get(move |headers: HeaderMap| async move {
+                        record(&moved_seen, "origin/moved", &headers);
+                        Redirect::temporary(&format!("http://{}/landing", other))
+                    }),
+                ),
+        )
+        .await;
+
+        let state = ProxyState::default();
+        state.auth_credentials.insert(format!("http://{}", origin), AuthMethod::Header { name: "X-API-Key".into(), value: "secret".into() });
+        let secret = Some("secret".to_string());
+
+        let html = logic_fetch_raw_html(format!("http://{}/start", origin), Some(5), None, &state).await.unwrap();
+        assert!(html.contains("Landed on the other host"), "{}", html);
+        assert_eq!(
+            std::mem::take(&mut *seen.lock().unwrap()),
+            vec![("origin/start".to_string(), secret.clone()), ("origin/moved".to_string(), secret.clone()), ("other/landing".to_string(), None)]
+        );
+
+        let _ = logic_fetch_article(format!("http://{}/start", origin), ArticleOptions::default(), &state).await;
+        let seen = seen.lock().unwrap().clone();
+        assert!(seen.contains(&("other/landing".to_string(), None)), "{:?}", seen);
+        assert!(seen.iter().all(|(path, key)| path.starts_with("origin/") == (key == &secret)), "{:?}", seen);
+    }
+}
@@ -0,0 +1,746 @@
+use crate::shared::{host_in_domain, host_of_domain_key, MutationReport, ProxyState};
+use regex::Regex;
+use serde::Serialize;
+use std::sync::LazyLock;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const MINUTE_MS: i64 = 60_000;
+const HOUR_MS: i64 = 3_600_000;
+const DAY_MS: i64 = 86_400_000;
+
+/// First-seen times kept; past this, the oldest half is forgotten
+const FIRST_SEEN_LIMIT: usize = 10_000;
+
+/// How much of a parsed date comes from the string itself
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DateConfidence {
+    /// Date and time with a zone (RFC 3339, RFC 2822, Unix time)
+    High,
+    /// Full date, but no time or no zone (taken as UTC)
+    Medium,
+    /// Guessed in part: missing year, relative phrase ("il y a 3 heures"), time of day only
+    Low,
+    /// Not parsed: when the app first saw the item, so it keeps its place between refreshes
+    FirstSeen,
+}
+
+/// A date normalized to UTC, with the string it was read from
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ParsedDate {
+    /// Unix time (ms)
+    pub timestamp: i64,
+    /// RFC 3339, UTC (`2024-05-01T10:30:00Z`)
+    pub utc: String,
+    pub confidence: DateConfidence,
+    pub original: String,
+}
+
+impl ParsedDate {
+    pub fn new(timestamp: i64, confidence: DateConfidence, original: &str) -> Self {
+        Self { timestamp, utc: format_utc(timestamp), confidence, original: original.to_string() }
+    }
+}
+
+pub fn now_millis() -> i64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_millis() as i64).unwrap_or(0)
+}
+
+/// Days since the epoch of a proleptic Gregorian date (Howard Hinnant's days_from_civil)
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let y = if month <= 2 { year - 1 } else { year };
+    let era = y.div_euclid(400);
+    let yoe = y - era * 400;
+    let doy = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146097 + doe - 719468
+}
+
+/// Year, month and day of a day count since the epoch (Howard Hinnant's civil_from_days)
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    (yoe + era * 400 + i64::from(month <= 2), month, day)
+}
+
+fn days_in_month(year: i64, month: i64) -> i64 {
+    days_from_civil(year + i64::from(month == 12), month % 12 + 1, 1) - days_from_civil(year, month, 1)
+}
+
+/// Unix time (ms) of a UTC date and time, `None` when the date doesn't exist
+fn timestamp_of(year: i64, month: i64, day: i64, time_ms: i64) -> Option<i64> {
+    if !(1..=12).contains(&month) || day < 1 || day > days_in_month(year, month) {
+        return None;
+    }
+    Some(days_from_civil(year, month, day) * DAY_MS + time_ms)
+}
+
+/// Publication dates before this year are misparsed values or placeholders (epoch, `0001-01-01`)
+const EARLIEST_PUBLICATION_YEAR: i64 = 1990;
+
+/// Whether `timestamp` can be a publication date as of `now`: from `EARLIEST_PUBLICATION_YEAR`
+/// on, and no more than a day ahead (zone mistakes aside, pages aren't published in the future)
+pub fn is_plausible_publication(timestamp: i64, now: i64) -> bool {
+    timestamp >= days_from_civil(EARLIEST_PUBLICATION_YEAR, 1, 1) * DAY_MS && timestamp <= now + DAY_MS
+}
+
+/// RFC 3339 form of a Unix time (ms), UTC
+pub fn format_utc(timestamp: i64) -> String {
+    let (year, month, day) = civil_from_days(timestamp.div_euclid(DAY_MS));
+    let seconds = timestamp.rem_euclid(DAY_MS) / 1000;
+    format!("{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z", year, month, day, seconds / 3600, seconds / 60 % 60, seconds % 60)
+}
+
+/// Unix time (ms) of an ISO 8601 date-time (`2024-05-01T12:30:00+02:00`, `2024-05-01 12:30Z`,
+/// `2024-05-01`). Without an offset the time is taken as UTC.
+pub fn parse_iso8601(value: &str) -> Option<i64> {
+    let value = value.trim();
+    let number = |s: &str| s.parse::<i64>().ok().filter(|_| s.bytes().all(|b| b.is_ascii_digit()));
+    // Byte 10 can fall inside a character ("lundi 5 février 2024"): not a date this parser reads
+    let (date, rest) = value.split_at_checked(value.len().min(10))?;
+    let mut parts = date.split('-');
+    let (year, month, day) = (number(parts.next()?)?, number(parts.next()?)?, number(parts.next()?)?);
+    if !(1..=12).contains(&month) || day < 1 || day > days_in_month(year, month) {
+        return None;
+    }
+
+    let mut millis = 0;
+    let mut offset_minutes = 0;
+    if let Some(time) = rest.strip_prefix(['T', 't', ' ']) {
+        let zone_start = time.find(['Z', 'z', '+', '-']).unwrap_or(time.len());
+        let (clock, zone) = time.split_at(zone_start);
+        let (clock, fraction) = clock.split_once('.').unwrap_or((clock, ""));
+        let mut fields = clock.split(':');
+        let hour = number(fields.next()?)?;
+        let minute = number(fields.next()?)?;
+        let second = fields.next().map_or(Some(0), number)?;
+        if hour > 23 || minute > 59 || second > 60 {
+            return None;
+        }
+        let fraction_ms = fraction.get(..fraction.len().min(3)).filter(|f| !f.is_empty()).map_or(Some(0), |f| {
+            number(f).map(|ms| ms * 10i64.pow(3 - f.len() as u32))
+        })?;
+        millis = ((hour * 60 + minute) * 60 + second) * 1000 + fraction_ms;
+        if let Some(sign) = zone.chars().next().filter(|c| matches!(c, '+' | '-')) {
+            let zone = zone[1..].replace(':', "");
+            let hours = number(zone.get(..2)?)?;
+            let minutes = zone.get(2..).filter(|m| !m.is_empty()).map_or(Some(0), number)?;
+            offset_minutes = (hours * 60 + minutes) * if sign == '-' { -1 } else { 1 };
+        }
+    } else if !rest.is_empty() {
+        return None;
+    }
+
+    Some(days_from_civil(year, month, day) * DAY_MS + millis - offset_minutes * MINUTE_MS)
+}
+
+/// Offsets of zone names, in minutes: RFC 822's, plus the ones feeds use anyway
+const ZONES: &[(&str, i64)] = &[
+    ("gmt", 0), ("ut", 0), ("utc", 0), ("z", 0), ("wet", 0), ("bst", 60), ("ist", 330), ("cet", 60), ("mez", 60), ("cest", 120),
+    ("mesz", 120), ("eet", 120), ("eest", 180), ("west", 60), ("msk", 180), ("est", -300), ("edt", -240), ("cst", -360),
+    ("cdt", -300), ("mst", -420), ("mdt", -360), ("pst", -480), ("pdt", -420), ("akst", -540), ("akdt", -480), ("hst", -600),
+    ("jst", 540), ("kst", 540), ("hkt", 480), ("sgt", 480), ("awst", 480), ("acst", 570), ("aest", 600), ("aedt", 660),
+    ("nzst", 720), ("nzdt", 780),
+];
+
+/// Offset in minutes of a zone token: a name, `+0200`, `-05:00`, `GMT+2`, `UTC+05:30`
+fn zone_offset(token: &str) -> Option<i64> {
+    let token = token.trim_matches(|c: char| c == '(' || c == ')').to_ascii_lowercase();
+    if let Some((_, minutes)) = ZONES.iter().find(|(name, _)| *name == token) {
+        return Some(*minutes);
+    }
+    let numeric = token.strip_prefix("gmt").or_else(|| token.strip_prefix("utc")).unwrap_or(&token);
+    let sign = match numeric.chars().next()? {
+        '+' => 1,
+        '-' => -1,
+        _ => return None,
+    };
+    let digits = numeric[1..].replace(':', "");
+    if digits.is_empty() || digits.len() > 4 || !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let (hours, minutes) = match digits.len() {
+        1 | 2 => (digits.parse::<i64>().ok()?, 0),
+        3 => (digits[..1].parse::<i64>().ok()?, digits[1..].parse::<i64>().ok()?),
+        _ => (digits[..2].parse::<i64>().ok()?, digits[2..].parse::<i64>().ok()?),
+    };
+    (hours <= 14 && minutes < 60).then_some(sign * (hours * 60 + minutes))
+}
+
+/// Unix time (ms) of an RFC 2822 date (`Tue, 10 Jun 2003 04:00:00 GMT`, `10 Jun 03 04:00 +0200`),
+/// with whether it had a zone
+pub fn parse_rfc2822(value: &str) -> Option<(i64, bool)> {
+    let value = value.split_once(',').map_or(value, |(_, rest)| rest);
+    let mut fields = value.split_whitespace();
+    let day: i64 = fields.next()?.parse().ok()?;
+    let month_name = fields.next()?.to_ascii_lowercase();
+    let month = MONTH_NAMES[0].1.iter().position(|month| month_name.len() >= 3 && month.starts_with(month_name.trim_end_matches('.')))? as i64 + 1;
+    let year: i64 = fields.next()?.parse().ok()?;
+    let year = match year {
+        0..=49 => 2000 + year,
+        50..=99 => 1900 + year,
+        _ => year,
+    };
+    let time = match fields.next() {
+        Some(time) => time_of(time, None)?,
+        None => 0,
+    };
+    let zone = fields.next();
+    let offset = match zone {
+        Some(zone) => zone_offset(zone)?,
+        None => 0,
+    };
+    Some((timestamp_of(year, month, day, time)? - offset * MINUTE_MS, zone.is_some()))
+}
+
+/// Month names by language, January first. Abbreviations are matched as prefixes.
+const MONTH_NAMES: &[(&str, [&str; 12])] = &[
+    ("en", ["january", "february", "march", "april", "may", "june", "july", "august", "september", "october", "november", "december"]),
+    ("fr", ["janvier", "février", "mars", "avril", "mai", "juin", "juillet", "août", "septembre", "octobre", "novembre", "décembre"]),
+    ("de", ["januar", "februar", "märz", "april", "mai", "juni", "juli", "august", "september", "oktober", "november", "dezember"]),
+    ("es", ["enero", "febrero", "marzo", "abril", "mayo", "junio", "julio", "agosto", "septiembre", "octubre", "noviembre", "diciembre"]),
+    ("it", ["gennaio", "febbraio", "marzo", "aprile", "maggio", "giugno", "luglio", "agosto", "settembre", "ottobre", "novembre", "dicembre"]),
+    ("pt", ["janeiro", "fevereiro", "março", "abril", "maio", "junho", "julho", "agosto", "setembro", "outubro", "novembro", "dezembro"]),
+    ("nl", ["januari", "februari", "maart", "april", "mei", "juni", "juli", "augustus", "september", "oktober", "november", "december"]),
+];
+
+/// Spellings that aren't a prefix of the full name
+const MONTH_ALIASES: &[(&str, i64)] =
+    &[("sept", 9), ("fevrier", 2), ("fevr", 2), ("aout", 8), ("decembre", 12), ("dec", 12), ("maerz", 3), ("marz", 3), ("mrz", 3), ("marco", 3)];
+
+/// Month (1-12) of a token in `languages` first, then in every known language
+fn month_of(token: &str, languages: &[String]) -> Option<i64> {
+    let token = token.trim_end_matches('.');
+    if token.chars().count() < 3 {
+        return None;
+    }
+    if let Some((_, month)) = MONTH_ALIASES.iter().find(|(alias, _)| *alias == token) {
+        return Some(*month);
+    }
+    let preferred = MONTH_NAMES.iter().filter(|(language, _)| languages.iter().any(|wanted| wanted == language));
+    let others = MONTH_NAMES.iter().filter(|(language, _)| !languages.iter().any(|wanted| wanted == language));
+    preferred.chain(others).find_map(|(_, names)| {
+        let matching: Vec<usize> = (0..12).filter(|&index| names[index] == token || names[index].starts_with(token)).collect();
+        // "jui" could be juin or juillet: ambiguous abbreviations are skipped
+        (matching.len() == 1).then(|| matching[0] as i64 + 1)
+    })
+}
+
+/// Milliseconds since midnight of a time token (`14:05`, `14:05:30`, `14h05`, `14h`, `2pm`,
+/// `2:30pm`), with the am/pm token that may follow it
+fn time_of(token: &str, next: Option<&str>) -> Option<i64> {
+    static TIME: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^(\d{1,2})(?:[:h](\d{2})?(?::(\d{2}))?)?(am|pm|a\.m\.|p\.m\.)?$").unwrap());
+    let captures = TIME.captures(token)?;
+    let has_separator = token.contains([':', 'h']);
+    let meridiem = captures.get(4).map(|m| m.as_str()).or_else(|| next.filter(|next| matches!(*next, "am" | "pm" | "a.m." | "p.m.")));
+    if !has_separator && meridiem.is_none() {
+        return None;
+    }
+    let mut hour: i64 = captures[1].parse().ok()?;
+    let minute: i64 = captures.get(2).map_or(Some(0), |m| m.as_str().parse().ok())?;
+    let second: i64 = captures.get(3).map_or(Some(0), |m| m.as_str().parse().ok())?;
+    match meridiem.map(|m| m.starts_with('p')) {
+        Some(true) if hour < 12 => hour += 12,
+        Some(false) if hour == 12 => hour = 0,
+        _ => {}
+    }
+    (hour < 24 && minute < 60 && second < 61).then_some(((hour * 60 + minute) * 60 + second) * 1000)
+}
+
+/// Tokens of a date string: lowercased, split on whitespace and commas, with `de`, `the`,
+/// `at`, `à`, `um`... left in (they are skipped as unknown words)
+fn tokens(value: &str) -> Vec<String> {
+    value
+        .to_lowercase()
+        .split(|c: char| c.is_whitespace() || c == ',' || c == '|' || c == '/')
+        .map(|token| token.trim_matches(|c: char| c == '(' || c == ')' || c == '·' || c == '—' || c == '-' || c == '–').to_string())
+        .filter(|token| !token.is_empty())
+        .collect()
+}
+
+/// Day of month of a token (`5`, `5th`, `1er`, `5.`, `05`)
+fn day_of(token: &str) -> Option<i64> {
+    let digits = token.trim_end_matches(['.', 'º', 'ª']);
+    let digits = ["st", "nd", "rd", "th", "er", "e"].iter().find_map(|suffix| digits.strip_suffix(suffix)).unwrap_or(digits);
+    if digits.is_empty() || digits.len() > 2 || !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    digits.parse().ok().filter(|day| (1..=31).contains(day))
+}
+
+fn year_of(token: &str) -> Option<i64> {
+    let digits = token.trim_end_matches('.');
+    (digits.len() == 4 && digits.bytes().all(|b| b.is_ascii_digit())).then(|| digits.parse().ok()).flatten().filter(|year| (1900..=2200).contains(year))
+}
+
+/// Parts of a date written with words: `May 5, 2024 3:45 PM EDT`, `5 mai 2024 à 14h05`,
+/// `Mittwoch, 5. Juni 2024`, `5 de junio de 2024`
+struct WrittenDate {
+    year: Option<i64>,
+    month: i64,
+    day: i64,
+    time: Option<i64>,
+    zone: Option<i64>,
+}
+
+fn written_date(value: &str, languages: &[String]) -> Option<WrittenDate> {
+    let tokens = tokens(value);
+    let (mut year, mut month, mut day, mut time, mut zone) = (None, None, None, None, None);
+    for (index, token) in tokens.iter().enumerate() {
+        let next = tokens.get(index + 1).map(String::as_str);
+        if year.is_none() {
+            if let Some(found) = year_of(token) {
+                year = Some(found);
+                continue;
+            }
+        }
+        if time.is_none() {
+            if let Some(found) = time_of(token, next) {
+                time = Some(found);
+                continue;
+            }
+        }
+        if month.is_none() {
+            if let Some(found) = month_of(token, languages) {
+                month = Some(found);
+                continue;
+            }
+        }
+        if day.is_none() {
+            if let Some(found) = day_of(token) {
+                day = Some(found);
+                continue;
+            }
+        }
+        if zone.is_none() && time.is_some() {
+            zone = zone_offset(token);
+        }
+    }
+    Some(WrittenDate { year, month: month?, day: day?, time, zone })
+}
+
+/// Numeric dates: `2024/05/01`, `01/05/2024` (day first unless a part can't be a month or the
+/// preferred language is English), `01.05.2024`, `01-05-2024`, with an optional time
+fn numeric_date(value: &str, languages: &[String]) -> Option<(i64, i64, i64, Option<i64>, bool)> {
+    static NUMERIC: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^(\d{1,4})[/.\-](\d{1,2})[/.\-](\d{1,4})\.?(?:[\sT,]+(?:à\s+|at\s+|um\s+)?(.+))?$").unwrap());
+    let captures = NUMERIC.captures(value)?;
+    let parts: Vec<i64> = (1..=3).map(|index| captures[index].parse().ok()).collect::<Option<_>>()?;
+    let time = match captures.get(4) {
+        Some(rest) => {
+            let rest = tokens(rest.as_str());
+            Some(time_of(rest.first()?, rest.get(1).map(String::as_str))?)
+        }
+        None => None,
+    };
+    if captures[1].len() == 4 {
+        return Some((parts[0], parts[1], parts[2], time, false));
+    }
+    if captures[3].len() != 4 {
+        return None;
+    }
+    let (first, second, year) = (parts[0], parts[1], parts[2]);
+    let month_first = languages.first().is_some_and(|language| language == "en");
+    match (first > 12, second > 12) {
+        (true, _) => Some((year, second, first, time, false)),
+        (_, true) => Some((year, first, second, time, false)),
+        _ if first == second => Some((year, first, second, time, false)),
+        _ if month_first => Some((year, first, second, time, true)),
+        _ => Some((year, second, first, time, true)),
+    }
+}
+
+/// Words counting as one in relative phrases ("an hour ago", "il y a une heure")
+const ONE_WORDS: &[&str] = &["a", "an", "one", "un", "une", "ein", "eine", "einer", "einem", "einen", "uno", "una", "um", "uma", "een"];
+
+/// Units of relative phrases, in milliseconds (months and years approximated)
+const RELATIVE_UNITS: &[(&[&str], i64)] = &[
+    (&["s", "sec", "secs", "second", "seconds", "seconde", "secondes", "sekunde", "sekunden", "segundo", "segundos", "secondo", "secondi", "seconden"], 1000),
+    (&["m", "min", "mins", "minute", "minutes", "minuten", "minuto", "minutos", "minuti", "minuut"], MINUTE_MS),
+    (&["h", "hr", "hrs", "hour", "hours", "heure", "heures", "stunde", "stunden", "std", "hora", "horas", "ora", "ore", "uur"], HOUR_MS),
+    (&["d", "day", "days", "jour", "jours", "j", "tag", "tage", "tagen", "día", "días", "dia", "dias", "giorno", "giorni", "dag", "dagen"], DAY_MS),
+    (&["w", "wk", "wks", "week", "weeks", "semaine", "semaines", "woche", "wochen", "semana", "semanas", "settimana", "settimane", "weken"], 7 * DAY_MS),
+    (&["mo", "mos", "month", "months", "mois", "monat", "monate", "monaten", "mes", "meses", "mês", "mese", "mesi", "maand", "maanden"], 30 * DAY_MS),
+    (&["y", "yr", "yrs", "year", "years", "an", "ans", "année", "années", "jahr", "jahre", "jahren", "año", "años", "ano", "anos", "anno", "anni", "jaar"], 365 * DAY_MS),
+];
+
+/// Days before the reference of the named days, longest phrases first
+const NAMED_DAYS: &[(&str, i64)] = &[
+    ("day before yesterday", 2), ("avant-hier", 2), ("vorgestern", 2), ("anteayer", 2), ("l'altro ieri", 2), ("anteontem", 2),
+    ("eergisteren", 2), ("yesterday", 1), ("hier", 1), ("gestern", 1), ("ayer", 1), ("ieri", 1), ("ontem", 1), ("gisteren", 1),
+    ("today", 0), ("aujourd'hui", 0), ("aujourd’hui", 0), ("heute", 0), ("hoy", 0), ("oggi", 0), ("hoje", 0), ("vandaag", 0),
+];
+
+/// Phrases meaning "now"
+const NOW_PHRASES: &[&str] = &["just now", "now", "à l'instant", "à l’instant", "maintenant", "gerade eben", "jetzt", "ahora", "adesso", "agora", "zojuist", "nu"];
+
+/// Unix time (ms) of a phrase relative to `reference`: `3 hours ago`, `il y a 2 jours`,
+/// `vor 5 Minuten`, `hace una hora`, `3 ore fa`, `há 2 dias`, `2 uur geleden`, `5m`,
+/// `yesterday at 10:30`, `hier à 14h05`
+fn relative_date(value: &str, reference: i64) -> Option<i64> {
+    static RELATIVE: LazyLock<Regex> = LazyLock::new(|| {
+        Regex::new(r"^(?:(il y a|vor|hace|há)\s+)?(?:about\s+|environ\s+|etwa\s+|cerca de\s+)?(\d+|[a-z]+)\s*([a-zà-ÿ]+)\.?(?:\s+(ago|fa|geleden))?$").unwrap()
+    });
+    let lower = value.to_lowercase();
+    let lower = lower.trim().trim_end_matches('.');
+    if NOW_PHRASES.contains(&lower) {
+        return Some(reference);
+    }
+    if let Some((phrase, days)) = NAMED_DAYS.iter().find(|(phrase, _)| lower.starts_with(phrase)) {
+        let rest = tokens(&lower[phrase.len()..]);
+        let time = rest.iter().enumerate().find_map(|(index, token)| time_of(token, rest.get(index + 1).map(String::as_str)));
+        return Some(match time {
+            Some(time) => (reference.div_euclid(DAY_MS) - days) * DAY_MS + time,
+            None => reference - days * DAY_MS,
+        });
+    }
+
+    let captures = RELATIVE.captures(lower)?;
+    let count = &captures[2];
+    let count = if count.bytes().all(|b| b.is_ascii_digit()) { count.parse::<i64>().ok()? } else if ONE_WORDS.contains(&count) { 1 } else { return None };
+    let unit = RELATIVE_UNITS.iter().find(|(names, _)| names.contains(&&captures[3])).map(|(_, ms)| *ms)?;
+    // A bare "5m" is how social feeds write "5 minutes ago"; a longer word needs "ago"
+    let compact = captures[3].len() <= 2 && !lower.contains(' ');
+    if captures.get(1).is_none() && captures.get(4).is_none() && !compact {
+        return None;
+    }
+    Some(reference - count * unit)
+}
+
+/// Primary language subtags of an `Accept-Language` value, in order (`fr-FR,fr;q=0.8,en` →
+/// `fr`, `en`)
+pub fn languages_of(accept_language: &str) -> Vec<String> {
+    let mut languages: Vec<String> = Vec::new();
+    for entry in accept_language.split(',') {
+        let tag = entry.split(';').next().unwrap_or("").trim();
+        let primary = tag.split(['-', '_']).next().unwrap_or("").to_ascii_lowercase();
+        if !primary.is_empty() && primary != "*" && !languages.contains(&primary) {
+            languages.push(primary);
+        }
+    }
+    languages
+}
+
+/// Parses a date as published by feeds and articles, whatever its format. In order: RFC 3339
+/// and ISO 8601, RFC 2822 (any zone name), Unix time, numeric dates, relative phrases, dates
+/// with month names in `languages` (then in every known language), and time of day only.
+/// Missing years and relative phrases are resolved against `reference` (the fetch time, Unix ms).
+pub fn parse_date(value: &str, reference: i64, languages: &[String]) -> Option<ParsedDate> {
+    let original = value;
+    let value = value.split_whitespace().collect::<Vec<_>>().join(" ");
+    if value.is_empty() {
+        return None;
+    }
+    let found = |timestamp: i64, confidence| Some(ParsedDate::new(timestamp, confidence, original));
+
+    if let Some(timestamp) = parse_iso8601(&value) {
+        let time = &value[value.len().min(10)..];
+        let zoned = time.ends_with(['Z', 'z']) || time.get(1..).is_some_and(|time| time.contains(['+', '-']));
+        return found(timestamp, if zoned { DateConfidence::High } else { DateConfidence::Medium });
+    }
+    // ISO 8601 followed by a zone name: `2024-05-01 12:30:00 UTC`
+    if let Some((iso, zone)) = value.rsplit_once(' ') {
+        if let (Some(timestamp), Some(offset)) = (parse_iso8601(iso), zone_offset(zone)) {
+            return found(timestamp - offset * MINUTE_MS, DateConfidence::High);
+        }
+    }
+    if let Some((timestamp, zoned)) = parse_rfc2822(&value) {
+        return found(timestamp, if zoned { DateConfidence::High } else { DateConfidence::Medium });
+    }
+    if value.bytes().all(|b| b.is_ascii_digit()) {
+        return match value.len() {
+            10 => found(value.parse::<i64>().ok()? * 1000, DateConfidence::High),
+            13 => found(value.parse().ok()?, DateConfidence::High),
+            _ => None,
+        };
+    }
+    if let Some((year, month, day, time, ambiguous)) = numeric_date(&value, languages) {
+        let confidence = if ambiguous { DateConfidence::Low } else { DateConfidence::Medium };
+        return found(timestamp_of(year, month, day, time.unwrap_or(0))?, confidence);
+    }
+
+    // Before month names: "ago" would be read as agosto
+    if let Some(timestamp) = relative_date(&value, reference) {
+        return found(timestamp, DateConfidence::Low);
+    }
+    if let Some(date) = written_date(&value, languages) {
+        let time = date.time.unwrap_or(0) - date.zone.unwrap_or(0) * MINUTE_MS;
+        let confidence = match (date.year, date.time, date.zone) {
+            (None, _, _) => DateConfidence::Low,
+            (Some(_), Some(_), Some(_)) => DateConfidence::High,
+            _ => DateConfidence::Medium,
+        };
+        let timestamp = match date.year {
+            Some(year) => timestamp_of(year, date.month, date.day, time)?,
+            // "May 5": this year, unless that's more than a week ahead
+            None => {
+                let (year, _, _) = civil_from_days(reference.div_euclid(DAY_MS));
+                match timestamp_of(year, date.month, date.day, time) {
+                    Some(timestamp) if timestamp <= reference + 7 * DAY_MS => timestamp,
+                    _ => timestamp_of(year - 1, date.month, date.day, time)?,
+                }
+            }
+        };
+        return found(timestamp, confidence);
+    }
+
+    // Time of day only (live blogs): today, or yesterday when that's ahead of the reference.
+    // A lone "14h" is left to relative phrases, where it means 14 hours ago.
+    let words = tokens(&value);
+    let compact_hours = words.len() == 1 && words[0].ends_with('h');
+    if (1..=3).contains(&words.len()) && !compact_hours {
+        if let Some(time) = time_of(&words[0], words.get(1).map(String::as_str)) {
+            let zone = words.last().filter(|_| words.len() > 1).and_then(|last| zone_offset(last)).unwrap_or(0);
+            let today = reference.div_euclid(DAY_MS) * DAY_MS + time - zone * MINUTE_MS;
+            return found(if today > reference + HOUR_MS { today - DAY_MS } else { today }, DateConfidence::Low);
+        }
+    }
+
+    None
+}
+
+/// Unix time (ms) of a date string, for ordering: `parse_date` against the current time
+pub fn timestamp_of_date(value: &str) -> Option<i64> {
+    parse_date(value, now_millis(), &[]).map(|date| date.timestamp)
+}
+
+/// Date of a feed item that has none the parser understands: when the app first saw it, so
+/// the item keeps its place across refreshes. `item_key` is its guid, link or title, `original`
+/// the date it has, if any.
+pub fn first_seen(feed_url: &str, item_key: &str, original: &str, now: i64, state: &ProxyState) -> ParsedDate {
+    let key = format!("{}\n{}", feed_url, item_key);
+    let timestamp = *state.first_seen.entry(key).or_insert(now);
+    if state.first_seen.len() > FIRST_SEEN_LIMIT {
+        let mut times: Vec<i64> = state.first_seen.iter().map(|entry| *entry.value()).collect();
+        times.sort_unstable();
+        let cutoff = times[times.len() / 2];
+        state.first_seen.retain(|_, seen| *seen >= cutoff);
+    }
+    ParsedDate::new(timestamp, DateConfidence::FirstSeen, original)
+}
+
+/// Forgets when the items of feeds on `domain` (subdomains included) were first seen
+pub fn clear_first_seen_for_domain(domain: &str, dry_run: bool, state: &ProxyState) -> MutationReport {
+    let mut report = MutationReport::new(dry_run);
+    let host = host_of_domain_key(domain);
+    let matching: Vec<String> = state
+        .first_seen
+        .iter()
+        .filter(|entry| {
+            let feed_url = entry.key().split('\n').next().unwrap_or("");
+            url::Url::parse(feed_url).ok().and_then(|url| url.host_str().map(|feed_host| host_in_domain(feed_host, &host))).unwrap_or(false)
+        })
+        .map(|entry| entry.key().clone())
+        .collect();
+    for key in matching {
+        report.record("first_seen", key.clone(), None);
+        if !dry_run {
+            state.first_seen.remove(&key);
+        }
+    }
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use DateConfidence::{FirstSeen, High, Low, Medium};
+
+    /// Saturday 15 June 2024, 12:00 UTC
+    const REFERENCE: i64 = 1_718_452_800_000;
+
+    fn languages(accept_language: &str) -> Vec<String> {
+        languages_of(accept_language)
+    }
+
+    #[test]
+    fn parses_real_world_samples() {
+        let cases: &[(&str, &str, &str, DateConfidence)] = &[
+            // RFC 3339 / ISO 8601
+            ("2024-05-01T10:30:00Z", "", "2024-05-01T10:30:00Z", High),
+            ("2024-05-01T12:30:00+02:00", "", "2024-05-01T10:30:00Z", High),
+            ("2024-05-01T05:30:00-0500", "", "2024-05-01T10:30:00Z", High),
+            ("2024-05-01t10:30:00.250z", "", "2024-05-01T10:30:00Z", High),
+            ("2024-05-01 10:30Z", "", "2024-05-01T10:30:00Z", High),
+            ("2024-05-01T10:30:00", "", "2024-05-01T10:30:00Z", Medium),
+            ("2024-05-01", "", "2024-05-01T00:00:00Z", Medium),
+            ("  2024-05-01T10:30:00Z\n", "", "2024-05-01T10:30:00Z", High),
+            ("2024-05-01 12:30:00 CEST", "", "2024-05-01T10:30:00Z", High),
+            ("2024-05-01 10:30:00 UTC", "", "2024-05-01T10:30:00Z", High),
+            // RFC 822 / 2822, standard and nonstandard zones
+            ("Wed, 01 May 2024 10:30:00 GMT", "", "2024-05-01T10:30:00Z", High),
+            ("Wed, 01 May 2024 12:30:00 +0200", "", "2024-05-01T10:30:00Z", High),
+            ("Wed, 01 May 2024 06:30:00 EDT", "", "2024-05-01T10:30:00Z", High),
+            ("Wed, 01 May 2024 12:30:00 CEST", "", "2024-05-01T10:30:00Z", High),
+            ("Wed, 01 May 2024 19:30:00 JST", "", "2024-05-01T10:30:00Z", High),
+            ("Wed, 01 May 2024 12:30:00 GMT+2", "", "2024-05-01T10:30:00Z", High),
+            ("Wed, 01 May 2024 16:00:00 UTC+05:30", "", "2024-05-01T10:30:00Z", High),
+            ("Wed, 01 May 2024 12:30:00 (CEST)", "", "2024-05-01T10:30:00Z", High),
+            ("1 May 24 10:30 Z", "", "2024-05-01T10:30:00Z", High),
+            ("Wed, 01 May 2024 10:30:00", "", "2024-05-01T10:30:00Z", Medium),
+            ("Wed, 01 Sept. 2024 10:30:00 GMT", "", "2024-09-01T10:30:00Z", High),
+            // An unknown zone name is skipped like any unknown word: the time is taken as UTC
+            ("Wed, 01 May 2024 10:30:00 XYZ", "", "2024-05-01T10:30:00Z", Medium),
+            // Unix time
+            ("1714559400", "", "2024-05-01T10:30:00Z", High),
+            ("1714559400000", "", "2024-05-01T10:30:00Z", High),
+            // Numeric dates
+            ("2024/05/01", "", "2024-05-01T00:00:00Z", Medium),
+            ("01/05/2024", "fr", "2024-05-01T00:00:00Z", Low),
+            ("05/01/2024", "en-US", "2024-05-01T00:00:00Z", Low),
+            ("25/12/2023", "en-US", "2023-12-25T00:00:00Z", Medium),
+            ("01.05.2024 14:05", "de", "2024-05-01T14:05:00Z", Low),
+            ("01/05/2024 à 14h05", "fr", "2024-05-01T14:05:00Z", Low),
+            // Relative phrases
+            ("il y a 3 heures", "fr", "2024-06-15T09:00:00Z", Low),
+            ("il y a une heure", "fr", "2024-06-15T11:00:00Z", Low),
+            ("3 hours ago", "", "2024-06-15T09:00:00Z", Low),
+            ("an hour ago", "", "2024-06-15T11:00:00Z", Low),
+            ("vor 5 Minuten", "de", "2024-06-15T11:55:00Z", Low),
+            ("hace 2 días", "es", "2024-06-13T12:00:00Z", Low),
+            ("3 ore fa", "it", "2024-06-15T09:00:00Z", Low),
+            ("2 uur geleden", "nl", "2024-06-15T10:00:00Z", Low),
+            ("5m", "", "2024-06-15T11:55:00Z", Low),
+            ("just now", "", "2024-06-15T12:00:00Z", Low),
+            ("yesterday at 10:30", "", "2024-06-14T10:30:00Z", Low),
+            ("hier à 14h05", "fr", "2024-06-14T14:05:00Z", Low),
+            ("aujourd’hui", "fr", "2024-06-15T12:00:00Z", Low),
+            // Month names, any language
+            ("May 5, 2024 3:45 PM EDT", "", "2024-05-05T19:45:00Z", High),
+            ("May 5, 2024", "", "2024-05-05T00:00:00Z", Medium),
+            ("5th May 2024", "", "2024-05-05T00:00:00Z", Medium),
+            ("5 mai 2024 à 14h05", "fr", "2024-05-05T14:05:00Z", Medium),
+            ("lundi 5 février 2024", "fr", "2024-02-05T00:00:00Z", Medium),
+            ("1er août 2024", "fr", "2024-08-01T00:00:00Z", Medium),
+            ("5 fevrier 2024", "", "2024-02-05T00:00:00Z", Medium),
+            ("Mittwoch, 5. Juni 2024", "de", "2024-06-05T00:00:00Z", Medium),
+            ("5. März 2024, 14:05 Uhr", "de", "2024-03-05T14:05:00Z", Medium),
+            ("5 de junio de 2024", "es", "2024-06-05T00:00:00Z", Medium),
+            ("5 maggio 2024", "it", "2024-05-05T00:00:00Z", Medium),
+            ("5 de março de 2024", "pt", "2024-03-05T00:00:00Z", Medium),
+            ("5 mei 2024", "nl", "2024-05-05T00:00:00Z", Medium),
+            // Missing year: this year, or last year when more than a week ahead
+            ("May 5", "", "2024-05-05T00:00:00Z", Low),
+            ("June 20", "", "2024-06-20T00:00:00Z", Low),
+            ("December 24", "", "2023-12-24T00:00:00Z", Low),
+            // Time of day only
+            ("10:30", "", "2024-06-15T10:30:00Z", Low),
+            ("11:30 pm", "", "2024-06-14T23:30:00Z", Low),
+        ];
+        for (input, accept_language, utc, confidence) in cases {
+            let parsed = parse_date(input, REFERENCE, &languages(accept_language)).unwrap_or_else(|| panic!("{:?} not parsed", input));
+            assert_eq!((parsed.utc.as_str(), parsed.confidence), (*utc, *confidence), "{:?}", input);
+            assert_eq!(parsed.original, *input);
+        }
+    }
+
+    #[test]
+    fn rejects_what_is_not_a_date() {
+        let cases = [
+            "",
+            "   ",
+            "not a date",
+            "2024-13-01",
+            "2024-02-30T10:00:00",
+            "2024-05-01T25:00:00Z",
+            "2024-05-01Tnoon",
+            "Wed, 31 Jun 2024 10:00:00 GMT",
+            "123456",
+            "ago",
+            "il y a",
+            "jui 2024",
+        ];
+        for input in cases {
+            assert_eq!(parse_date(input, REFERENCE, &[]), None, "{:?}", input);
+        }
+    }
+
+    #[test]
+    fn non_ascii_input_does_not_panic() {
+        let cases = [
+            "lundi 5 février 2024",
+            "2024年5月1日",
+            "2024年5月1日 10:30",
+            "١٥ يونيو ٢٠٢٤",
+            "15 июня 2024",
+            "2024-05-0é",
+            "2024-05-01T10:30:00+02:0é",
+            "éééééééééééé",
+            "🗓️ 5 mai 2024",
+            "Wed, 01 May 2024 10:30:00 +02é0",
+            "il y a 3 heures—",
+        ];
+        for input in cases {
+            let _ = parse_date(input, REFERENCE, &languages("fr, ja"));
+            let _ = parse_iso8601(input);
+            let _ = parse_rfc2822(input);
+        }
+        assert_eq!(parse_iso8601("2024年5月1日"), None);
+        assert_eq!(parse_date("🗓️ 5 mai 2024", REFERENCE, &[]).map(|date| date.utc), Some("2024-05-05T00:00:00Z".to_string()));
+    }
+
+    #[test]
+    fn preferred_language_decides_ambiguous_dates() {
+        let french = parse_date("03/04/2024", REFERENCE, &languages("fr-FR,fr;q=0.9,en;q=0.8")).unwrap();
+        let english = parse_date("03/04/2024", REFERENCE, &languages("en-US,en;q=0.9")).unwrap();
+        assert_eq!(french.utc, "2024-04-03T00:00:00Z");
+        assert_eq!(english.utc, "2024-03-04T00:00:00Z");
+    }
+
+    #[test]
+    fn civil_round_trip() {
+        for days in [-719_468, -1, 0, 1, 11_016, 19_844, 19_782, 2_932_896] {
+            let (year, month, day) = civil_from_days(days);
+            assert_eq!(days_from_civil(year, month, day), days);
+        }
+        assert_eq!(days_in_month(2024, 2), 29);
+        assert_eq!(days_in_month(2023, 2), 28);
+        assert_eq!(days_in_month(2000, 2), 29);
+        assert_eq!(days_in_month(1900, 2), 28);
+        assert_eq!(days_in_month(2024, 12), 31);
+        assert_eq!(format_utc(REFERENCE), "2024-06-15T12:00:00Z");
+        assert_eq!(format_utc(-1000), "1969-12-31T23:59:59Z");
+    }
+
+    #[test]
+    fn plausible_publication_dates() {
+        assert!(is_plausible_publication(REFERENCE, REFERENCE));
+        assert!(is_plausible_publication(REFERENCE + HOUR_MS, REFERENCE));
+        assert!(!is_plausible_publication(REFERENCE + 2 * DAY_MS, REFERENCE));
+        assert!(!is_plausible_publication(0, REFERENCE));
+        assert!(!is_plausible_publication(parse_iso8601("0001-01-01").unwrap(), REFERENCE));
+    }
+
+    #[test]
+    fn first_seen_is_stable_and_cleared_per_domain() {
+        let state = ProxyState::default();
+        let first = first_seen("https://blog.example.com/feed", "item-1", "someday", REFERENCE, &state);
+        let again = first_seen("https://blog.example.com/feed", "item-1", "someday", REFERENCE + DAY_MS, &state);
+        assert_eq!(first.timestamp, REFERENCE);
+        assert_eq!(again.timestamp, REFERENCE);
+        assert_eq!(again.confidence, FirstSeen);
+        first_seen("https://other.org/feed", "item-1", "", REFERENCE, &state);
+
+        let report = clear_first_seen_for_domain("example.com", true, &state);
+        assert_eq!(report.count, 1);
+        assert_eq!(state.first_seen.len(), 2);
+        clear_first_seen_for_domain("example.com", false, &state);
+        assert_eq!(state.first_seen.len(), 1);
+    }
+
+    #[test]
+    fn clears_the_first_seen_dates_of_a_domain() {
+        crate::test_support::check_clear_for_domain(
+            |state, host| {
+                state.first_seen.insert(format!("https://{}/feed\nitem-1", host), REFERENCE);
+            },
+            clear_first_seen_for_domain,
+            |state| state.first_seen.iter().map(|entry| entry.key().clone()).collect(),
+        );
+    }
+}
@@ -0,0 +1,144 @@
+use crate::chaos;
+use crate::charts::{clear_chart_opt_outs_for_domain, snapshots_enabled_for};
+use crate::host_stats::{clear_host_stats_for_domain, logic_get_host_stats, HostStats};
+use crate::icons::clear_icons_for_domain;
+use crate::latency::clear_latency_for_domain;
+use crate::element_filters::clear_element_filters_for_domain;
+use crate::dates::clear_first_seen_for_domain;
+use crate::translation::clear_translation_for_domain;
+use crate::extractors::clear_extractor_comparison_for_domain;
+use crate::unread::clear_unread_for_domain;
+use crate::credentials::clear_credential_migration_for_domain;
+use crate::feed::clear_seen_items_for_domain;
+use crate::mixed_content::clear_https_support_for_domain;
+use crate::preconnect::clear_preconnect_for_domain;
+use crate::rendered::clear_rendered_for_domain;
+use crate::resource_cache::clear_resource_cache_for_domain;
+use crate::shared::{
+    accept_language_for, clear_accept_language_for_domain, clear_auth_for_domain, clear_crawler_retry_for_domain, clear_http1_override_for_domain,
+    clear_rendering_override_for_domain, forces_http1, host_of_domain_key, logic_clear_cookies, requires_rendering, retries_as_crawler, MutationReport,
+    ProxyState,
+};
+use crate::site_config::{clear_site_configs_for_domain, config_key_for};
+use crate::versions::clear_versions_for_domain;
+use serde::Serialize;
+use url::Url;
+
+/// Everything the backend holds for a domain. Secrets are never returned: credentials and
+/// cookies are reported by key only.
+#[derive(Debug, Serialize)]
+pub struct DomainProfile {
+    /// Normalized host the profile was computed for
+    pub domain: String,
+    /// Origins on the domain with stored credentials
+    pub auth_origins: Vec<String>,
+    /// Cookies as `name@domain/path`, without their values
+    pub cookies: Vec<String>,
+    /// Key of the ftr-site-config rules applying to the domain (may be a parent wildcard)
+    pub site_config_rule: Option<String>,
+    /// Rule keys on the domain that `reset_domain` would remove
+    pub site_config_keys: Vec<String>,
+    pub has_cached_icon: bool,
+    /// `Accept-Language` override applying to the domain (may be set on a parent domain)
+    pub accept_language: Option<String>,
+    /// Articles skip extraction and go straight to the rendered proxy path (may be set on a parent domain)
+    pub requires_rendering: bool,
+    /// Teaser-length extractions are retried as Googlebot (may be set on a parent domain)
+    pub crawler_retry: bool,
+    /// Requests stay on HTTP/1.1 (set on the domain, a parent domain, or globally)
+    pub force_http1: bool,
+    /// Chart embeds are replaced by static images (the feature is on and no opt-out applies)
+    pub chart_snapshots: bool,
+    /// Chaos mode is enabled and scoped to this domain
+    pub chaos_active: bool,
+    /// Extraction outcomes of the domain's host
+    pub extraction_stats: Option<HostStats>,
+}
+
+/// Runs every per-domain store's clear operation. Each store lists its matches and removes
+/// them under its own lock; with `dry_run` nothing is removed and the report is a preview.
+/// New per-domain stores must be added here so the profile and reset stay complete.
+fn clear_domain_stores(domain: &str, dry_run: bool, state: &ProxyState) -> MutationReport {
+    let mut report = MutationReport::new(dry_run);
+    report.merge(clear_auth_for_domain(domain, dry_run, state));
+    report.merge(logic_clear_cookies(Some(domain.to_string()), dry_run, state));
+    report.merge(clear_site_configs_for_domain(domain, dry_run, state));
+    report.merge(clear_icons_for_domain(domain, dry_run, state));
+    report.merge(clear_accept_language_for_domain(domain, dry_run, state));
+    report.merge(clear_rendering_override_for_domain(domain, dry_run, state));
+    report.merge(clear_crawler_retry_for_domain(domain, dry_run, state));
+    report.merge(clear_http1_override_for_domain(domain, dry_run, state));
+    report.merge(clear_https_support_for_domain(domain, dry_run, state));
+    report.merge(clear_versions_for_domain(domain, dry_run, state));
+    report.merge(clear_rendered_for_domain(domain, dry_run, state));
+    report.merge(clear_host_stats_for_domain(domain, dry_run, state));
+    report.merge(clear_latency_for_domain(domain, dry_run, state));
+    report.merge(clear_element_filters_for_domain(domain, dry_run, state));
+    report.merge(clear_first_seen_for_domain(domain, dry_run, state));
+    report.merge(clear_translation_for_domain(domain, dry_run, state));
+    report.merge(clear_extractor_comparison_for_domain(domain, dry_run, state));
+    report.merge(clear_unread_for_domain(domain, dry_run, state));
+    report.merge(clear_credential_migration_for_domain(domain, dry_run, state));
+    report.merge(clear_seen_items_for_domain(domain, dry_run, state));
+    report.merge(clear_chart_opt_outs_for_domain(domain, dry_run, state));
+    report.merge(clear_preconnect_for_domain(domain, dry_run, state));
+    report.merge(clear_resource_cache_for_domain(domain, dry_run, state));
+    report
+}
+
+pub fn logic_get_domain_profile(domain: String, state: &ProxyState) -> DomainProfile {
+    let host = host_of_domain_key(&domain);
+    let stored = clear_domain_stores(&host, true, state);
+
+    let url = Url::parse(&format!("https://{}/", host)).ok();
+    let chaos_active = url.as_ref().is_some_and(|url| chaos::is_active_for(url, state));
+    let requires_rendering = url.as_ref().is_some_and(|url| requires_rendering(url, state));
+    let crawler_retry = url.as_ref().is_some_and(|url| retries_as_crawler(url, state));
+    let force_http1 = url.as_ref().is_some_and(|url| forces_http1(url, state));
+    let chart_snapshots = url.as_ref().is_some_and(|url| snapshots_enabled_for(url, state));
+    let accept_language = url.as_ref().and_then(|url| {
+        let value = accept_language_for(url, state, "");
+        (!value.is_empty()).then_some(value)
+    });
+
+    DomainProfile {
+        auth_origins: stored.keys("auth_credentials"),
+        cookies: stored.keys("cookies"),
+        site_config_rule: config_key_for(&host, state),
+        site_config_keys: stored.keys("site_configs"),
+        has_cached_icon: !stored.keys("icon_cache").is_empty(),
+        accept_language,
+        requires_rendering,
+        crawler_retry,
+        force_http1,
+        chart_snapshots,
+        chaos_active,
+        extraction_stats: logic_get_host_stats(host.clone(), state).ok().filter(|stats| stats.attempts > 0),
+        domain: host,
+    }
+}
+
+/// Clears everything stored for `domain` (subdomains included) across all stores
+pub fn logic_reset_domain(domain: String, dry_run: bool, state: &ProxyState) -> MutationReport {
+    let host = host_of_domain_key(&domain);
+    let report = clear_domain_stores(&host, dry_run, state);
+    if !dry_run {
+        println!("[domains::reset_domain] Cleared {} entries for {}", report.count, host);
+    }
+    report
+}
+
+/// Removes the credentials (pre-migration copies included) and cookies of `domain` and its
+/// subdomains, for a user wiping a site they logged into on a shared device. Returns whether
+/// anything was removed.
+pub fn logic_forget_domain(domain: String, state: &ProxyState) -> Result<bool, String> {
+    let host = host_of_domain_key(&domain);
+    if host.is_empty() {
+        return Err("Domain is required".into());
+    }
+    let mut report = clear_auth_for_domain(&host, false, state);
+    report.merge(clear_credential_migration_for_domain(&host, false, state));
+    report.merge(logic_clear_cookies(Some(host.clone()), false, state));
+    println!("[domains::forget_domain] Removed {} credentials and cookies for {}", report.count, host);
+    Ok(report.count > 0)
+}
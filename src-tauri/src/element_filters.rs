@@ -0,0 +1,510 @@
+use crate::callouts::BLOCK_KIND_ATTRIBUTE;
+use crate::shared::{host_in_domain, host_of_domain_key, logic_fetch_raw_html, MutationReport, ProxyState};
+use lol_html::{element, rewrite_str, RewriteStrSettings};
+use regex::Regex;
+use scraper::{ElementRef, Html, Selector};
+use serde::{Deserialize, Serialize};
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::sync::LazyLock;
+use std::time::{SystemTime, UNIX_EPOCH};
+use url::Url;
+
+/// Version of the exported element filters
+pub const ELEMENT_FILTERS_VERSION: u32 = 1;
+
+/// Classes combined in a derived selector
+const MAX_SELECTOR_CLASSES: usize = 3;
+
+/// Characters of text kept as a filter's text anchor
+const MAX_ANCHOR_CHARS: usize = 40;
+
+/// Matches on a sample page above which a filter is reported as overbroad
+const MAX_PREVIEW_MATCHES: usize = 5;
+
+/// Share of a sample page's text above which a filter is reported as overbroad
+const MAX_PREVIEW_TEXT_SHARE: f64 = 0.3;
+
+/// Attributes that name what an element is rather than how it looks, in order of preference
+const STABLE_ATTRIBUTES: &[&str] = &["data-testid", "data-test", "data-component", "data-module", "data-widget", "itemprop", "role", "aria-label"];
+
+/// Utility classes (Tailwind, Bootstrap...) describing presentation only. Matched against the
+/// class without its `-value` suffix.
+const UTILITY_PREFIXES: &[&str] = &[
+    "p", "px", "py", "pt", "pb", "pl", "pr", "m", "mx", "my", "mt", "mb", "ml", "mr", "w", "h", "min-w", "min-h", "max-w", "max-h",
+    "gap", "space-x", "space-y", "text", "bg", "border", "rounded", "shadow", "font", "leading", "tracking", "z", "opacity", "top",
+    "left", "right", "bottom", "inset", "grid-cols", "col-span", "row-span", "order", "basis", "grow", "shrink", "items", "justify",
+    "self", "place", "overflow", "d", "col", "row", "g", "flex", "grid", "align", "float", "fill", "stroke", "ring",
+    "divide", "transition", "duration", "ease", "translate", "scale", "rotate", "cursor", "select", "line-clamp", "aspect",
+];
+const UTILITY_CLASSES: &[&str] = &[
+    "flex", "grid", "block", "inline", "inline-block", "inline-flex", "hidden", "relative", "absolute", "fixed", "sticky", "static",
+    "container", "clearfix", "row", "col", "sr-only", "truncate", "italic", "underline", "uppercase", "lowercase", "capitalize",
+    "visible", "invisible", "active", "open", "show", "fade", "in", "mx-auto", "w-full", "h-full", "border", "rounded", "shadow",
+    "ring", "outline", "grow", "shrink", "transition", "transform", "antialiased",
+];
+
+/// Class prefixes of CSS-in-JS libraries, whose class names are generated per build
+const GENERATED_CLASS_PREFIXES: &[&str] = &["css-", "sc-", "jsx-", "emotion-", "styled-", "svelte-", "makeStyles-", "jss"];
+
+/// A segment of a generated name: 5+ characters mixing letters and digits (`a1b2c`, `3xYz1`)
+static HASH_SEGMENT: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^(?:[A-Za-z]*[0-9][A-Za-z0-9]*[A-Za-z][A-Za-z0-9]*|[0-9]+[A-Za-z][A-Za-z0-9]*)$").unwrap());
+
+static CSS_IDENTIFIER: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^-?[A-Za-z_][A-Za-z0-9_-]*$").unwrap());
+
+/// An element the user asked never to see again on a domain
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ElementFilter {
+    /// CSS selector (the subset both scraper and lol_html understand)
+    pub selector: String,
+    /// Lowercased start of the element's first text, required on top of the selector when
+    /// the selector alone would be too broad
+    pub text_anchor: Option<String>,
+    /// Unix time (ms) the filter was added
+    #[serde(default)]
+    pub created_at: u64,
+}
+
+/// Element filters as exported, so the app can keep them with its domain settings
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ElementFiltersExport {
+    pub version: u32,
+    /// Keyed by domain (subdomains included)
+    pub domains: HashMap<String, Vec<ElementFilter>>,
+}
+
+/// What a filter would remove on a sample page
+#[derive(Debug, Clone, Serialize)]
+pub struct FilterPreview {
+    pub matches: usize,
+    /// Characters of text in the matched elements
+    pub matched_chars: usize,
+    pub page_chars: usize,
+    /// Too many matches or too much of the page's text: the selector is likely too broad
+    pub overbroad: bool,
+    /// Start of the text of the first matches
+    pub samples: Vec<String>,
+}
+
+fn now_millis() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_millis() as u64).unwrap_or(0)
+}
+
+fn collapsed_text(el: &ElementRef) -> String {
+    el.text().collect::<Vec<_>>().join(" ").split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Whether an id or class looks generated: per-build hashes, per-article numbers
+fn is_generated_name(name: &str) -> bool {
+    if name.len() > 40 || name.chars().all(|c| c.is_ascii_digit()) || GENERATED_CLASS_PREFIXES.iter().any(|prefix| name.starts_with(prefix)) {
+        return true;
+    }
+    // CSS modules: `Component_name__hash`
+    if name.split_once("__").is_some_and(|(_, suffix)| suffix.chars().any(|c| c.is_ascii_digit())) {
+        return true;
+    }
+    let mut digits = 0;
+    for c in name.chars() {
+        digits = if c.is_ascii_digit() { digits + 1 } else { 0 };
+        if digits >= 3 {
+            return true;
+        }
+    }
+    name.split(['-', '_']).any(|segment| segment.len() >= 5 && HASH_SEGMENT.is_match(segment)) || (name.ends_with(|c: char| c.is_ascii_digit()) && name.contains('-'))
+}
+
+fn is_utility_class(class: &str) -> bool {
+    if class.contains([':', '/', '[', '.', '!']) || UTILITY_CLASSES.contains(&class) {
+        return true;
+    }
+    let class = class.trim_start_matches('-');
+    UTILITY_PREFIXES.iter().any(|prefix| class.strip_prefix(prefix).is_some_and(|rest| rest.starts_with('-')))
+        // State classes (`is-open`, `has-image`)
+        || class.starts_with("is-")
+        || class.starts_with("has-")
+}
+
+fn stable_id(el: &ElementRef) -> Option<String> {
+    let id = el.value().id()?.trim();
+    // Ids added by `classify_blocks` are numbered per article
+    let generated_by_us = el.value().attr(BLOCK_KIND_ATTRIBUTE).is_some_and(|kind| id.starts_with(kind));
+    (CSS_IDENTIFIER.is_match(id) && !is_generated_name(id) && !generated_by_us).then(|| id.to_string())
+}
+
+fn stable_classes(el: &ElementRef) -> Vec<String> {
+    let mut classes: Vec<String> = Vec::new();
+    for class in el.value().classes() {
+        if CSS_IDENTIFIER.is_match(class) && !is_generated_name(class) && !is_utility_class(class) && !classes.iter().any(|c| c == class) {
+            classes.push(class.to_string());
+        }
+    }
+    classes.truncate(MAX_SELECTOR_CLASSES);
+    classes
+}
+
+fn stable_attribute(el: &ElementRef) -> Option<String> {
+    STABLE_ATTRIBUTES.iter().find_map(|attribute| {
+        let value = el.value().attr(attribute)?.trim();
+        (!value.is_empty() && !value.contains(['"', '\\']) && !is_generated_name(value)).then(|| format!("[{}=\"{}\"]", attribute, value))
+    })
+}
+
+/// Filter for the element whose `outerHTML` is `html`: its id when it looks hand-written,
+/// otherwise its tag with the classes that aren't utility or generated ones and a naming
+/// attribute (`data-testid`, `role`...). A selector made of the tag alone, or a role, gets
+/// the element's first text as anchor.
+pub fn derive_filter(html: &str) -> Result<ElementFilter, String> {
+    let fragment = Html::parse_fragment(html);
+    let any = Selector::parse("*").unwrap();
+    let el = fragment
+        .select(&any)
+        .find(|el| el.value().name() != "html")
+        .ok_or_else(|| "No element in the selected markup".to_string())?;
+    let tag = el.value().name().to_ascii_lowercase();
+    if matches!(tag.as_str(), "body" | "head" | "main") {
+        return Err(format!("Refusing to filter the whole <{}>", tag));
+    }
+
+    if let Some(id) = stable_id(&el) {
+        return Ok(ElementFilter { selector: format!("{}#{}", tag, id), text_anchor: None, created_at: now_millis() });
+    }
+
+    let classes = stable_classes(&el);
+    let attribute = stable_attribute(&el);
+    let mut selector = tag.clone();
+    for class in &classes {
+        selector.push('.');
+        selector.push_str(class);
+    }
+    let named_by_attribute = attribute.as_deref().is_some_and(|attribute| !attribute.starts_with("[role="));
+    if let Some(attribute) = &attribute {
+        selector.push_str(attribute);
+    }
+
+    let text_anchor = if classes.is_empty() && !named_by_attribute {
+        // The first text (usually a heading like "Related posts") is what stays the same
+        // from page to page, unlike the rest of the block
+        let first_text = el.text().map(|text| text.split_whitespace().collect::<Vec<_>>().join(" ")).find(|text| !text.is_empty()).unwrap_or_default();
+        let anchor: String = first_text.to_lowercase().chars().take(MAX_ANCHOR_CHARS).collect();
+        if anchor.trim().is_empty() {
+            return Err("The element has no stable id, class or text to recognize it by".into());
+        }
+        Some(anchor.trim_end().to_string())
+    } else {
+        None
+    };
+    Ok(ElementFilter { selector, text_anchor, created_at: now_millis() })
+}
+
+fn matches_anchor(el: &ElementRef, anchor: Option<&str>) -> bool {
+    anchor.is_none_or(|anchor| collapsed_text(el).to_lowercase().starts_with(anchor))
+}
+
+fn parse_selector(selector: &str) -> Result<Selector, String> {
+    selector.parse::<lol_html::Selector>().map_err(|e| format!("Unsupported selector '{}': {}", selector, e))?;
+    Selector::parse(selector).map_err(|e| format!("Invalid selector '{}': {:?}", selector, e))
+}
+
+/// Removes the elements matching `filters`. Filters with a text anchor are matched with
+/// scraper first, then removed by lol_html running the same selector, so both see the
+/// matches in the same order.
+pub fn remove_filtered_elements(html: &str, filters: &[ElementFilter]) -> Result<String, String> {
+    if filters.is_empty() {
+        return Ok(html.to_string());
+    }
+    let document = Html::parse_document(html);
+    let mut removals: Vec<(&str, Option<Vec<bool>>)> = Vec::new();
+    for filter in filters {
+        let selector = parse_selector(&filter.selector)?;
+        let keep = filter
+            .text_anchor
+            .as_deref()
+            .map(|anchor| document.select(&selector).map(|el| matches_anchor(&el, Some(anchor))).collect::<Vec<bool>>());
+        if keep.as_ref().is_none_or(|matches| matches.contains(&true)) && document.select(&selector).next().is_some() {
+            removals.push((&filter.selector, keep));
+        }
+    }
+    if removals.is_empty() {
+        return Ok(html.to_string());
+    }
+
+    let counters: Vec<Cell<usize>> = removals.iter().map(|_| Cell::new(0)).collect();
+    let handlers = removals
+        .iter()
+        .zip(&counters)
+        .map(|((selector, matches), counter)| {
+            element!(selector, move |el| {
+                let index = counter.replace(counter.get() + 1);
+                if matches.as_ref().is_none_or(|matches| matches.get(index).copied().unwrap_or(false)) {
+                    el.remove();
+                }
+                Ok(())
+            })
+        })
+        .collect();
+    rewrite_str(html, RewriteStrSettings { element_content_handlers: handlers, ..RewriteStrSettings::default() }).map_err(|e| e.to_string())
+}
+
+/// Filters stored for `host`'s domain and its parent domains
+pub fn filters_for(host: &str, state: &ProxyState) -> Vec<ElementFilter> {
+    let host = host.to_ascii_lowercase();
+    state
+        .element_filters
+        .iter()
+        .filter(|entry| host_in_domain(&host, entry.key()))
+        .flat_map(|entry| entry.value().clone())
+        .collect()
+}
+
+/// `remove_filtered_elements` with the filters of `url`'s host. The HTML is returned
+/// unchanged when a filter can't be applied.
+pub fn apply_element_filters(html: &str, url: &Url, state: &ProxyState) -> String {
+    let filters = url.host_str().map(|host| filters_for(host, state)).unwrap_or_default();
+    if filters.is_empty() {
+        return html.to_string();
+    }
+    match remove_filtered_elements(html, &filters) {
+        Ok(filtered) => filtered,
+        Err(e) => {
+            println!("[element_filters::apply_element_filters] Filters of {} not applied: {}", url, e);
+            html.to_string()
+        }
+    }
+}
+
+/// Derives a filter from the `outerHTML` of an element and stores it for `domain`
+pub fn logic_add_element_filter(domain: String, html: String, state: &ProxyState) -> Result<ElementFilter, String> {
+    let domain = host_of_domain_key(&domain);
+    if domain.is_empty() {
+        return Err("Domain is required".into());
+    }
+    let filter = derive_filter(&html)?;
+    parse_selector(&filter.selector)?;
+
+    let mut filters = state.element_filters.entry(domain.clone()).or_default();
+    if let Some(existing) = filters.iter().find(|existing| existing.selector == filter.selector && existing.text_anchor == filter.text_anchor) {
+        return Ok(existing.clone());
+    }
+    println!("[element_filters::add_element_filter] {}: {} {:?}", domain, filter.selector, filter.text_anchor);
+    filters.push(filter.clone());
+    Ok(filter)
+}
+
+/// Removes the filters of `domain` with `selector`. Returns whether one was removed.
+pub fn logic_remove_element_filter(domain: String, selector: String, state: &ProxyState) -> bool {
+    let domain = host_of_domain_key(&domain);
+    let Some(mut filters) = state.element_filters.get_mut(&domain) else {
+        return false;
+    };
+    let before = filters.len();
+    filters.retain(|filter| filter.selector != selector);
+    let removed = filters.len() < before;
+    let empty = filters.is_empty();
+    drop(filters);
+    if empty {
+        state.element_filters.remove(&domain);
+    }
+    removed
+}
+
+/// What `selector` (with an optional text anchor) would remove on `sample_url`, a page of `domain`
+pub async fn logic_preview_filter(
+    domain: String,
+    selector: String,
+    text_anchor: Option<String>,
+    sample_url: String,
+    state: &ProxyState,
+) -> Result<FilterPreview, String> {
+    let domain = host_of_domain_key(&domain);
+    let url = Url::parse(&sample_url).map_err(|e| e.to_string())?;
+    if !url.host_str().is_some_and(|host| host_in_domain(&host.to_ascii_lowercase(), &domain)) {
+        return Err(format!("{} is not a page of {}", sample_url, domain));
+    }
+    let parsed = parse_selector(&selector)?;
+    let text_anchor = text_anchor.map(|anchor| anchor.trim().to_lowercase()).filter(|anchor| !anchor.is_empty());
+
+    let html = logic_fetch_raw_html(sample_url, None, None, state).await?;
+    let document = Html::parse_document(&html);
+    let body = Selector::parse("body").unwrap();
+    let page_chars = document.select(&body).next().map(|body| collapsed_text(&body).chars().count()).unwrap_or(0);
+
+    let matched: Vec<ElementRef> = document.select(&parsed).filter(|el| matches_anchor(el, text_anchor.as_deref())).collect();
+    // Matches nested in other matches would be counted twice
+    let outer: Vec<&ElementRef> = matched.iter().filter(|el| !el.ancestors().any(|ancestor| matched.iter().any(|m| m.id() == ancestor.id()))).collect();
+    let matched_chars: usize = outer.iter().map(|el| collapsed_text(el).chars().count()).sum();
+    let samples = outer.iter().take(3).map(|el| collapsed_text(el).chars().take(80).collect()).collect();
+
+    Ok(FilterPreview {
+        matches: matched.len(),
+        matched_chars,
+        page_chars,
+        overbroad: matched.len() > MAX_PREVIEW_MATCHES || (page_chars > 0 && matched_chars as f64 > page_chars as f64 * MAX_PREVIEW_TEXT_SHARE),
+        samples,
+    })
+}
+
+pub fn logic_export_element_filters(state: &ProxyState) -> ElementFiltersExport {
+    ElementFiltersExport {
+        version: ELEMENT_FILTERS_VERSION,
+        domains: state.element_filters.iter().map(|entry| (entry.key().clone(), entry.value().clone())).collect(),
+    }
+}
+
+/// Replaces the element filters, e.g. when restoring the domain settings at startup.
+/// Filters with a selector that can't be applied are dropped.
+pub fn logic_import_element_filters(export: ElementFiltersExport, state: &ProxyState) -> Result<usize, String> {
+    if export.version > ELEMENT_FILTERS_VERSION {
+        return Err(format!("Element filters version {} is newer than supported ({})", export.version, ELEMENT_FILTERS_VERSION));
+    }
+    state.element_filters.clear();
+    let mut count = 0;
+    for (domain, filters) in export.domains {
+        let domain = host_of_domain_key(&domain);
+        let filters: Vec<ElementFilter> = filters.into_iter().filter(|filter| parse_selector(&filter.selector).is_ok()).collect();
+        if domain.is_empty() || filters.is_empty() {
+            continue;
+        }
+        count += filters.len();
+        state.element_filters.insert(domain, filters);
+    }
+    println!("[element_filters::import_element_filters] Imported {} filters", count);
+    Ok(count)
+}
+
+/// Removes the filters of `domain` and its subdomains
+pub fn clear_element_filters_for_domain(domain: &str, dry_run: bool, state: &ProxyState) -> MutationReport {
+    let mut report = MutationReport::new(dry_run);
+    let host = host_of_domain_key(domain);
+    let matching: Vec<String> = state.element_filters.iter().map(|entry| entry.key().clone()).filter(|key| host_in_domain(key, &host)).collect();
+    for key in matching {
+        report.record("element_filters", key.clone(), None);
+        if !dry_run {
+            state.element_filters.remove(&key);
+        }
+    }
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{check_clear_for_domain, Rng};
+
+    #[test]
+    fn clears_the_filters_of_a_domain() {
+        check_clear_for_domain(
+            |state, host| {
+                state.element_filters.insert(host.to_string(), Vec::new());
+            },
+            clear_element_filters_for_domain,
+            |state| state.element_filters.iter().map(|entry| entry.key().clone()).collect(),
+        );
+    }
+
+    /// Presentation classes as Tailwind and Bootstrap write them
+    const UTILITIES: &[&str] = &[
+        "p-4", "px-2", "mt-8", "mb-0", "-mt-2", "!p-0", "text-sm", "text-gray-500", "bg-white", "md:flex", "lg:w-1/3", "hover:underline",
+        "w-[320px]", "rounded-lg", "rounded", "shadow", "border", "border-t", "flex", "hidden", "gap-4", "items-center", "d-none",
+        "col-md-6", "space-y-2", "font-semibold", "leading-6", "sr-only", "is-active", "has-image", "max-w-prose", "z-10",
+    ];
+
+    /// A per-build class name: CSS-in-JS prefixes, CSS modules suffixes, bare hashes
+    fn generated_class(rng: &mut Rng) -> String {
+        let hash: String = (0..6)
+            .map(|i| {
+                let alphabet = if i % 2 == 0 { "abcdefghijkmnpqrstuvwxyzABCDEFGH" } else { "0123456789" };
+                alphabet.as_bytes()[rng.below(alphabet.len())] as char
+            })
+            .collect();
+        match rng.below(6) {
+            0 => format!("css-{}", hash.to_lowercase()),
+            1 => format!("sc-{}", hash),
+            2 => format!("Bio_root__{}", hash),
+            3 => format!("jsx-{}", rng.next() % 1_000_000_000),
+            4 => format!("svelte-{}", hash.to_lowercase()),
+            _ => hash,
+        }
+    }
+
+    /// `classes` mixed with random utility and generated classes, shuffled
+    fn noisy_classes(classes: &[&str], rng: &mut Rng) -> String {
+        let mut all: Vec<String> = classes.iter().map(|class| class.to_string()).collect();
+        for _ in 0..rng.below(6) {
+            all.push(rng.pick(UTILITIES).to_string());
+        }
+        for _ in 0..rng.below(3) {
+            all.push(generated_class(rng));
+        }
+        for i in (1..all.len()).rev() {
+            all.swap(i, rng.below(i + 1));
+        }
+        all.join(" ")
+    }
+
+    #[test]
+    fn names_are_told_apart() {
+        for name in ["author-bio", "related-posts", "newsletter-signup", "article__footer", "post-meta", "share-buttons", "sidebar", "promo2", "h2-title"] {
+            assert!(!is_generated_name(name) && !is_utility_class(name), "{}", name);
+        }
+        for name in ["css-1x2y3z", "sc-AbCdE", "Bio_root__a1b2c", "jsx-123456789", "x7k2p9", "post-12345", "ember123", "block-7", "9f8e7d6c"] {
+            assert!(is_generated_name(name), "{}", name);
+        }
+        for class in UTILITIES {
+            assert!(is_utility_class(class) || is_generated_name(class), "{}", class);
+        }
+    }
+
+    #[test]
+    fn randomized_classes_derive_the_same_selector() {
+        for seed in 1..=300 {
+            let mut rng = Rng::new(seed);
+            let render = |rng: &mut Rng| {
+                format!(r#"<aside class="{}"><h3>About the author</h3><p>Writes things.</p></aside>"#, noisy_classes(&["author-bio", "card"], rng))
+            };
+            let first = derive_filter(&render(&mut rng)).unwrap();
+            let second = derive_filter(&render(&mut rng)).unwrap();
+            // Classes keep their order of appearance, which changes from render to render
+            let mut classes: Vec<&str> = first.selector.trim_start_matches("aside.").split('.').collect();
+            classes.sort();
+            assert_eq!(classes, ["author-bio", "card"], "seed {}: {}", seed, first.selector);
+            assert_eq!(first.text_anchor, None);
+
+            // Whatever order it was learned in, the filter finds the block on another render
+            let page = format!(
+                r#"<html><body><article><p class="{}">Story.</p>{}<aside class="{}"><h3>Related</h3></aside></article></body></html>"#,
+                noisy_classes(&[], &mut rng),
+                render(&mut rng),
+                noisy_classes(&["card"], &mut rng)
+            );
+            let filtered = remove_filtered_elements(&page, &[second]).unwrap();
+            assert!(!filtered.contains("About the author") && filtered.contains("Related") && filtered.contains("Story."), "seed {}: {}", seed, filtered);
+        }
+    }
+
+    #[test]
+    fn blocks_without_stable_classes_are_anchored_on_their_text() {
+        for seed in 1..=200 {
+            let mut rng = Rng::new(seed);
+            let block = |heading: &str, rng: &mut Rng| format!(r#"<section class="{}"><h2>{}</h2><ul><li>Post</li></ul></section>"#, noisy_classes(&[], rng), heading);
+            let filter = derive_filter(&block("Related  posts", &mut rng)).unwrap();
+            assert_eq!(filter.selector, "section", "seed {}", seed);
+            assert_eq!(filter.text_anchor.as_deref(), Some("related posts"));
+
+            let page = format!("<html><body>{}{}</body></html>", block("Comments", &mut rng), block("Related posts", &mut rng));
+            let filtered = remove_filtered_elements(&page, &[filter]).unwrap();
+            assert!(filtered.contains("Comments") && !filtered.contains("Related posts"), "seed {}: {}", seed, filtered);
+        }
+    }
+
+    #[test]
+    fn ids_and_naming_attributes_come_first_when_stable() {
+        let selector = |html: &str| derive_filter(html).map(|filter| (filter.selector, filter.text_anchor));
+        assert_eq!(selector(r#"<div id="newsletter" class="p-4 css-1a2b3c">Sign up</div>"#), Ok(("div#newsletter".into(), None)));
+        assert_eq!(selector(r#"<div id="ember1234" class="promo">Sign up</div>"#), Ok(("div.promo".into(), None)));
+        assert_eq!(selector(r#"<div id="post-12345" data-testid="share-bar" class="flex">Share</div>"#), Ok(("div[data-testid=\"share-bar\"]".into(), None)));
+        assert_eq!(selector(r#"<div data-testid="a1b2c3d4" class="mt-2">Share</div>"#), Ok(("div".into(), Some("share".into()))));
+        assert_eq!(selector(r#"<nav role="navigation" class="sc-xYz12">Menu links</nav>"#), Ok(("nav[role=\"navigation\"]".into(), Some("menu links".into()))));
+        assert!(selector(r#"<div class="p-4 css-1a2b3c"><img src="a.png"></div>"#).is_err());
+        assert!(selector("<main>everything</main>").is_err());
+    }
+}
@@ -0,0 +1,222 @@
+use crate::shared::{escape_html, unescape_html};
+use lol_html::html_content::{ContentType, Element};
+use lol_html::{element, rewrite_str, RewriteStrSettings};
+use std::cell::RefCell;
+use url::Url;
+
+/// Attribute of the placeholders standing in for embeds during extraction, set to the
+/// embed's index in `ProtectedEmbeds::embeds`
+const PLACEHOLDER_ATTRIBUTE: &str = "data-embed-placeholder";
+
+/// Embed players kept in reader mode: (provider, host, path prefix)
+const KNOWN_EMBEDS: &[(&str, &str, &str)] = &[
+    ("YouTube", "youtube.com", "/embed/"),
+    ("YouTube", "youtube-nocookie.com", "/embed/"),
+    ("Vimeo", "player.vimeo.com", "/video/"),
+    ("Dailymotion", "dailymotion.com", "/embed/"),
+    ("Dailymotion", "geo.dailymotion.com", "/player"),
+    ("Twitter", "platform.twitter.com", "/embed/"),
+];
+
+/// An embed iframe set aside during extraction
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProtectedEmbed {
+    pub provider: &'static str,
+    pub src: String,
+    /// The iframe's markup, event handler attributes left out
+    pub iframe: String,
+}
+
+/// Page with its known embeds replaced by placeholders, and the embeds to put back
+#[derive(Debug, Clone, Default)]
+pub struct ProtectedEmbeds {
+    pub html: String,
+    pub embeds: Vec<ProtectedEmbed>,
+}
+
+/// Provider of the embed player at `url`, if it's one of `KNOWN_EMBEDS`
+pub fn embed_provider(url: &Url) -> Option<&'static str> {
+    if !matches!(url.scheme(), "http" | "https") {
+        return None;
+    }
+    let host = url.host_str()?.to_ascii_lowercase();
+    let host = host.strip_prefix("www.").unwrap_or(&host);
+    KNOWN_EMBEDS.iter().find(|(_, embed_host, path)| host == *embed_host && url.path().starts_with(path)).map(|(provider, _, _)| *provider)
+}
+
+/// Provider and absolute URL of a known embed player, `src` resolved against the page
+/// (embeds are often protocol-relative)
+fn known_embed(src: &str, base: &Url) -> Option<(&'static str, Url)> {
+    let url = base.join(src.trim()).ok()?;
+    Some((embed_provider(&url)?, url))
+}
+
+/// Markup of `el`, an iframe, with `src` as its source (lazy-loaded embeds only have
+/// `data-src`). Event handlers and `srcdoc` are dropped.
+fn iframe_markup(el: &Element, src: &str) -> String {
+    let mut markup = format!("<iframe src=\"{}\"", escape_html(src));
+    for attribute in el.attributes() {
+        let name = attribute.name();
+        if matches!(name.as_str(), "src" | "data-src" | "srcdoc") || name.starts_with("on") {
+            continue;
+        }
+        // Values come as written in the page, entities included
+        markup.push_str(&format!(" {}=\"{}\"", name, escape_html(&unescape_html(&attribute.value()))));
+    }
+    markup.push_str("></iframe>");
+    markup
+}
+
+/// Replaces YouTube, Vimeo, Dailymotion and Twitter embed iframes by placeholder divs that
+/// survive readability (which drops iframes), to be turned back into the iframes by
+/// `restore_embeds` after extraction. Other iframes are left to readability.
+pub fn protect_embeds(html: &str, base: &Url) -> ProtectedEmbeds {
+    let embeds = RefCell::new(Vec::new());
+    let result = rewrite_str(
+        html,
+        RewriteStrSettings {
+            element_content_handlers: vec![element!("iframe[src], iframe[data-src]", |el| {
+                let src = el.get_attribute("src").filter(|src| !src.trim().is_empty() && src != "about:blank").or_else(|| el.get_attribute("data-src"));
+                let Some((provider, src)) = src.and_then(|src| known_embed(&unescape_html(&src), base)) else {
+                    return Ok(());
+                };
+                let src = src.to_string();
+                let mut embeds = embeds.borrow_mut();
+                let placeholder = format!(
+                    r#"<div {}="{}"><p><a href="{}">{} embed</a></p></div>"#,
+                    PLACEHOLDER_ATTRIBUTE,
+                    embeds.len(),
+                    escape_html(&src),
+                    provider
+                );
+                embeds.push(ProtectedEmbed { provider, iframe: iframe_markup(el, &src), src });
+                el.replace(&placeholder, ContentType::Html);
+                Ok(())
+            })],
+            ..RewriteStrSettings::default()
+        },
+    );
+
+    match result {
+        Ok(output) => {
+            let embeds = embeds.into_inner();
+            if !embeds.is_empty() {
+                println!("[embeds::protect_embeds] {} embeds set aside", embeds.len());
+            }
+            ProtectedEmbeds { html: output, embeds }
+        }
+        Err(e) => {
+            println!("[embeds::protect_embeds] Rewriting failed, keeping original HTML: {}", e);
+            ProtectedEmbeds { html: html.to_string(), embeds: Vec::new() }
+        }
+    }
+}
+
+/// Puts the embeds set aside by `protect_embeds` back in extracted `content`, in place of
+/// their placeholders. Any other iframe is removed.
+pub fn restore_embeds(content: &str, embeds: &[ProtectedEmbed]) -> Result<String, String> {
+    rewrite_str(
+        content,
+        RewriteStrSettings {
+            element_content_handlers: vec![
+                element!("iframe", |el| {
+                    el.remove();
+                    Ok(())
+                }),
+                element!("[data-embed-placeholder]", |el| {
+                    let embed = el.get_attribute(PLACEHOLDER_ATTRIBUTE).and_then(|index| index.parse::<usize>().ok()).and_then(|index| embeds.get(index));
+                    match embed {
+                        Some(embed) => el.replace(&format!(r#"<div data-embed="{}">{}</div>"#, embed.provider.to_ascii_lowercase(), embed.iframe), ContentType::Html),
+                        None => el.remove_attribute(PLACEHOLDER_ATTRIBUTE),
+                    }
+                    Ok(())
+                }),
+            ],
+            ..RewriteStrSettings::default()
+        },
+    )
+    .map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::shared::{logic_extract_article, ArticleOptions, ProxyState};
+    use crate::test_support::serve_html;
+
+    const PAGE: &str = r#"<html><head><title>Embeds</title></head><body><article>
+<h1>Embeds</h1>
+<p>The first paragraph of an article that is mostly a video, long enough to be kept by the extraction step.</p>
+<iframe width="560" height="315" src="//www.youtube.com/embed/abc?start=5&amp;rel=0" allowfullscreen onload="track()"></iframe>
+<p>A second paragraph between the players, with a few more words so the text reads like a real article.</p>
+<iframe data-src="https://player.vimeo.com/video/42" src="about:blank" title="A &quot;quoted&quot; title"></iframe>
+<iframe src="https://ads.example/frame"></iframe>
+<iframe src="https://platform.twitter.com/embed/Tweet.html?id=1" srcdoc="<script>x()</script>"></iframe>
+<p>A closing paragraph after the embeds, so they sit in the middle of the article rather than at its end.</p>
+</article></body></html>"#;
+
+    fn base() -> Url {
+        Url::parse("https://news.example/story").unwrap()
+    }
+
+    #[test]
+    fn known_embeds_are_protected() {
+        let protected = protect_embeds(PAGE, &base());
+        let providers: Vec<&str> = protected.embeds.iter().map(|embed| embed.provider).collect();
+        assert_eq!(providers, ["YouTube", "Vimeo", "Twitter"]);
+        assert_eq!(protected.embeds[0].src, "https://www.youtube.com/embed/abc?start=5&rel=0");
+        assert_eq!(protected.embeds[1].src, "https://player.vimeo.com/video/42");
+        assert!(!protected.html.contains("youtube.com/embed/abc\""));
+        assert_eq!(protected.html.matches(PLACEHOLDER_ATTRIBUTE).count(), 3);
+        // Unknown iframes are left to readability
+        assert!(protected.html.contains("https://ads.example/frame"));
+    }
+
+    #[test]
+    fn placeholders_round_trip() {
+        let protected = protect_embeds(PAGE, &base());
+        let restored = restore_embeds(&protected.html, &protected.embeds).unwrap();
+        assert!(restored.contains(r#"<div data-embed="youtube"><iframe src="https://www.youtube.com/embed/abc?start=5&amp;rel=0" width="560" height="315" allowfullscreen=""></iframe></div>"#), "{}", restored);
+        assert!(restored.contains(r#"<div data-embed="vimeo"><iframe src="https://player.vimeo.com/video/42" title="A &quot;quoted&quot; title"></iframe></div>"#), "{}", restored);
+        assert!(restored.contains(r#"<div data-embed="twitter"><iframe src="https://platform.twitter.com/embed/Tweet.html?id=1"></iframe></div>"#), "{}", restored);
+        assert!(!restored.contains("onload") && !restored.contains("srcdoc"));
+        // Iframes other than the restored embeds are removed
+        assert!(!restored.contains("ads.example"));
+        assert!(!restored.contains(PLACEHOLDER_ATTRIBUTE));
+    }
+
+    #[test]
+    fn unknown_placeholders_lose_their_marker() {
+        let restored = restore_embeds(r#"<div data-embed-placeholder="7"><p>kept</p></div>"#, &[]).unwrap();
+        assert_eq!(restored, "<div><p>kept</p></div>");
+    }
+
+    #[test]
+    fn embed_providers() {
+        let provider = |url: &str| embed_provider(&Url::parse(url).unwrap());
+        assert_eq!(provider("https://www.youtube-nocookie.com/embed/x"), Some("YouTube"));
+        assert_eq!(provider("https://geo.dailymotion.com/player.html?video=x"), Some("Dailymotion"));
+        assert_eq!(provider("https://youtube.com/watch?v=x"), None);
+        assert_eq!(provider("https://evil.example/embed/youtube.com"), None);
+        assert_eq!(provider("ftp://youtube.com/embed/x"), None);
+    }
+
+    /// The placeholders go through the real extraction: a readability upgrade dropping them
+    /// fails here rather than silently losing the embeds
+    #[tokio::test]
+    async fn embeds_survive_extraction() {
+        let addr = serve_html(&[("/story", PAGE.to_string())]).await;
+        let state = ProxyState::default();
+        let options = ArticleOptions { keep_embeds: true, ..ArticleOptions::default() };
+        let article = logic_extract_article(format!("http://{}/story", addr), options, &state).await.unwrap();
+        let content = article.content.unwrap();
+        for provider in ["youtube", "vimeo", "twitter"] {
+            assert!(content.contains(&format!("<div data-embed=\"{}\"><iframe", provider)), "{} missing:\n{}", provider, content);
+        }
+        assert!(!content.contains("ads.example"));
+        assert!(article.provenance.unwrap().processors.contains(&"keep_embeds".to_string()));
+
+        let without = logic_extract_article(format!("http://{}/story", addr), ArticleOptions::default(), &state).await.unwrap();
+        assert!(!without.content.unwrap_or_default().contains("data-embed"));
+    }
+}
@@ -0,0 +1,811 @@
+use crate::proxy::unproxied_url;
+use crate::shared::{absolutize_url, logic_extract_article, ArticleOptions, ProxyState, FALLBACK_SIGNAL, LAZY_IMAGE_ATTRIBUTES};
+use lol_html::{element, rewrite_str, RewriteStrSettings};
+use scraper::{ElementRef, Html, Node};
+use regex::Regex;
+use serde::Deserialize;
+use std::sync::LazyLock;
+use url::Url;
+
+/// Stands for a `<br>` while inline text is being whitespace-collapsed
+const LINE_BREAK_MARK: char = '\u{E000}';
+
+/// RST heading underline characters, by heading level
+const RST_UNDERLINES: &[char] = &['=', '-', '~', '^', '"', '\''];
+
+/// Elements dropped from exports along with their content
+const SKIPPED_ELEMENTS: &[&str] = &["script", "style", "noscript", "template", "svg", "button", "form", "iframe", "object", "video", "audio"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    Markdown,
+    AsciiDoc,
+    Rst,
+}
+
+/// Inline content of a block
+#[derive(Debug, Clone, PartialEq)]
+pub enum Inline {
+    Text(String),
+    Strong(Vec<Inline>),
+    Emphasis(Vec<Inline>),
+    Code(String),
+    Link { href: String, content: Vec<Inline> },
+    Image { src: String, alt: String },
+    LineBreak,
+}
+
+/// Format-independent structure of an article. Every export format renders from this, so a
+/// single extraction can feed any of them.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Block {
+    Heading { level: u8, content: Vec<Inline> },
+    Paragraph(Vec<Inline>),
+    List { ordered: bool, items: Vec<Vec<Block>> },
+    Code { language: Option<String>, code: String },
+    Quote(Vec<Block>),
+    /// Standalone image (alone in its paragraph, or a `<figure>`)
+    Image { src: String, alt: String, caption: Vec<Inline> },
+    Rule,
+}
+
+// --- HTML to Blocks ---
+
+/// Image URL of an `<img>`, preferring lazy-loading attributes over placeholders, made absolute
+fn image_src(el: &ElementRef, base: &Url) -> Option<String> {
+    let element = el.value();
+    LAZY_IMAGE_ATTRIBUTES
+        .iter()
+        .filter_map(|attr| element.attr(attr))
+        .chain(element.attr("src"))
+        .map(str::trim)
+        .find(|src| !src.is_empty() && !src.starts_with("data:"))
+        .and_then(|src| absolutize_url(src, base))
+}
+
+/// `language-x` / `lang-x` class of a code block or its `<code>` child
+fn code_language(el: &ElementRef) -> Option<String> {
+    let code = el.children().filter_map(ElementRef::wrap).find(|child| child.value().name() == "code");
+    [Some(*el), code].into_iter().flatten().find_map(|el| {
+        el.value().classes().find_map(|class| {
+            class.strip_prefix("language-").or_else(|| class.strip_prefix("lang-")).filter(|l| !l.is_empty()).map(str::to_string)
+        })
+    })
+}
+
+/// Appends the inline content of `el`'s children to `out`. Block elements met inside inline
+/// content are flattened.
+fn collect_inlines(el: &ElementRef, base: &Url, out: &mut Vec<Inline>) {
+    for child in el.children() {
+        match child.value() {
+            Node::Text(text) => out.push(Inline::Text(text.to_string())),
+            Node::Element(_) => {
+                if let Some(child) = ElementRef::wrap(child) {
+                    collect_inline_element(&child, base, out);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+fn collect_inline_element(el: &ElementRef, base: &Url, out: &mut Vec<Inline>) {
+    let name = el.value().name();
+    if SKIPPED_ELEMENTS.contains(&name) {
+        return;
+    }
+
+    let children = || {
+        let mut content = Vec::new();
+        collect_inlines(el, base, &mut content);
+        content
+    };
+    match name {
+        "strong" | "b" => out.push(Inline::Strong(children())),
+        "em" | "i" | "cite" => out.push(Inline::Emphasis(children())),
+        "code" | "kbd" | "samp" | "tt" => out.push(Inline::Code(el.text().collect())),
+        "br" => out.push(Inline::LineBreak),
+        "img" => {
+            if let Some(src) = image_src(el, base) {
+                out.push(Inline::Image { src, alt: el.value().attr("alt").unwrap_or("").trim().to_string() });
+            }
+        }
+        "a" => {
+            let href = el.value().attr("href").map(str::trim).filter(|href| !href.starts_with('#') && !href.starts_with("javascript:"));
+            match href.and_then(|href| absolutize_url(href, base)) {
+                Some(href) => out.push(Inline::Link { href, content: children() }),
+                None => out.extend(children()),
+            }
+        }
+        "p" | "div" | "li" | "tr" | "h1" | "h2" | "h3" | "h4" | "h5" | "h6" | "td" | "th" => {
+            out.push(Inline::Text(" ".into()));
+            collect_inlines(el, base, out);
+            out.push(Inline::Text(" ".into()));
+        }
+        _ => collect_inlines(el, base, out),
+    }
+}
+
+fn has_text(inlines: &[Inline]) -> bool {
+    inlines.iter().any(|inline| match inline {
+        Inline::Text(text) | Inline::Code(text) => !text.trim().is_empty(),
+        Inline::Strong(content) | Inline::Emphasis(content) | Inline::Link { content, .. } => has_text(content),
+        Inline::Image { .. } => true,
+        Inline::LineBreak => false,
+    })
+}
+
+/// Collects blocks, gathering loose inline content into paragraphs
+struct BlockBuilder<'a> {
+    base: &'a Url,
+    blocks: Vec<Block>,
+    inline: Vec<Inline>,
+}
+
+impl<'a> BlockBuilder<'a> {
+    fn new(base: &'a Url) -> Self {
+        Self { base, blocks: Vec::new(), inline: Vec::new() }
+    }
+
+    fn finish(mut self) -> Vec<Block> {
+        self.flush();
+        self.blocks
+    }
+
+    /// Turns the pending inline content into a paragraph, or a standalone image when that's all it holds
+    fn flush(&mut self) {
+        let inline = std::mem::take(&mut self.inline);
+        if !has_text(&inline) {
+            return;
+        }
+        let mut images = inline.iter().filter(|i| !matches!(i, Inline::Text(t) if t.trim().is_empty()) && !matches!(i, Inline::LineBreak));
+        if let (Some(Inline::Image { src, alt }), None) = (images.next(), images.next()) {
+            self.blocks.push(Block::Image { src: src.clone(), alt: alt.clone(), caption: Vec::new() });
+            return;
+        }
+        self.blocks.push(Block::Paragraph(inline));
+    }
+
+    fn push(&mut self, block: Block) {
+        self.flush();
+        self.blocks.push(block);
+    }
+
+    fn children(&mut self, el: &ElementRef) {
+        for child in el.children() {
+            match child.value() {
+                Node::Text(text) => self.inline.push(Inline::Text(text.to_string())),
+                Node::Element(_) => {
+                    if let Some(child) = ElementRef::wrap(child) {
+                        self.element(&child);
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn blocks_of(&self, el: &ElementRef) -> Vec<Block> {
+        let mut builder = BlockBuilder::new(self.base);
+        builder.children(el);
+        builder.finish()
+    }
+
+    fn element(&mut self, el: &ElementRef) {
+        let name = el.value().name();
+        if SKIPPED_ELEMENTS.contains(&name) {
+            return;
+        }
+
+        match name {
+            "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => {
+                let mut content = Vec::new();
+                collect_inlines(el, self.base, &mut content);
+                if has_text(&content) {
+                    self.push(Block::Heading { level: name[1..].parse().unwrap_or(1), content });
+                }
+            }
+            "ul" | "ol" => {
+                let items: Vec<Vec<Block>> = el
+                    .children()
+                    .filter_map(ElementRef::wrap)
+                    .filter(|child| child.value().name() == "li")
+                    .map(|li| self.blocks_of(&li))
+                    .filter(|blocks| !blocks.is_empty())
+                    .collect();
+                if !items.is_empty() {
+                    self.push(Block::List { ordered: name == "ol", items });
+                }
+            }
+            "pre" => {
+                let code: String = el.text().collect();
+                let code = code.trim_matches('\n').to_string();
+                if !code.trim().is_empty() {
+                    self.push(Block::Code { language: code_language(el), code });
+                }
+            }
+            "blockquote" => {
+                let blocks = self.blocks_of(el);
+                if !blocks.is_empty() {
+                    self.push(Block::Quote(blocks));
+                }
+            }
+            "figure" => {
+                let img = scraper::Selector::parse("img").unwrap();
+                let caption = scraper::Selector::parse("figcaption").unwrap();
+                match el.select(&img).next().and_then(|img| Some((image_src(&img, self.base)?, img))) {
+                    Some((src, img)) => {
+                        let mut content = Vec::new();
+                        if let Some(figcaption) = el.select(&caption).next() {
+                            collect_inlines(&figcaption, self.base, &mut content);
+                        }
+                        let alt = img.value().attr("alt").unwrap_or("").trim().to_string();
+                        self.push(Block::Image { src, alt, caption: content });
+                    }
+                    None => {
+                        self.flush();
+                        self.children(el);
+                        self.flush();
+                    }
+                }
+            }
+            "hr" => self.push(Block::Rule),
+            "p" | "div" | "section" | "article" | "main" | "header" | "footer" | "aside" | "nav" | "table" | "thead" | "tbody"
+            | "tfoot" | "tr" | "dl" | "dt" | "dd" | "details" | "summary" | "figcaption" | "address" | "center" => {
+                self.flush();
+                self.children(el);
+                self.flush();
+            }
+            "td" | "th" => {
+                self.children(el);
+                self.inline.push(Inline::Text(" ".into()));
+            }
+            _ => collect_inline_element(el, self.base, &mut self.inline),
+        }
+    }
+}
+
+/// Block model of extracted article HTML. Links and images are made absolute against `base`.
+pub fn html_to_blocks(html: &str, base: &Url) -> Vec<Block> {
+    let fragment = Html::parse_fragment(html);
+    let mut builder = BlockBuilder::new(base);
+    builder.children(&fragment.root_element());
+    builder.finish()
+}
+
+// --- Inline Rendering ---
+
+fn escape_text(text: &str, format: ExportFormat) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match (format, c) {
+            (ExportFormat::Markdown, '\\' | '*' | '_' | '`' | '[' | ']' | '<' | '>') => {
+                escaped.push('\\');
+                escaped.push(c);
+            }
+            // Character references are left alone by AsciiDoc's inline formatting
+            (ExportFormat::AsciiDoc, '*' | '_' | '`' | '#' | '+' | '[' | ']') => escaped.push_str(&format!("&#{};", c as u32)),
+            (ExportFormat::Rst, '\\' | '*' | '_' | '`' | '|') => {
+                escaped.push('\\');
+                escaped.push(c);
+            }
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Plain text of inline content, for places where markup can't nest (RST, image captions)
+pub fn plain_text(inlines: &[Inline]) -> String {
+    inlines
+        .iter()
+        .map(|inline| match inline {
+            Inline::Text(text) | Inline::Code(text) => text.clone(),
+            Inline::Strong(content) | Inline::Emphasis(content) | Inline::Link { content, .. } => plain_text(content),
+            Inline::Image { alt, .. } => alt.clone(),
+            Inline::LineBreak => " ".into(),
+        })
+        .collect()
+}
+
+fn collapse_whitespace(text: &str) -> String {
+    text.split(|c: char| c.is_whitespace() && c != LINE_BREAK_MARK).filter(|word| !word.is_empty()).collect::<Vec<_>>().join(" ")
+}
+
+/// Writes inline content in one format, tracking what's needed to keep RST markup recognized
+struct InlineWriter {
+    format: ExportFormat,
+    out: String,
+    /// RST markup just ended: a following word character must be separated with `\ `
+    after_markup: bool,
+}
+
+impl InlineWriter {
+    fn text(&mut self, text: &str) {
+        if self.after_markup && text.starts_with(|c: char| c.is_alphanumeric()) {
+            self.out.push_str("\\ ");
+        }
+        self.after_markup = false;
+        self.out.push_str(text);
+    }
+
+    /// Markup wrapping `content`. Surrounding spaces are moved outside the markers.
+    fn markup(&mut self, open: &str, content: &str, close: &str) {
+        let trimmed = content.trim();
+        if trimmed.is_empty() {
+            self.text(content);
+            return;
+        }
+        if content.starts_with(char::is_whitespace) {
+            self.text(" ");
+        }
+        if self.format == ExportFormat::Rst && self.out.ends_with(|c: char| c.is_alphanumeric()) {
+            self.out.push_str("\\ ");
+        }
+        self.out.push_str(open);
+        self.out.push_str(trimmed);
+        self.out.push_str(close);
+        self.after_markup = self.format == ExportFormat::Rst;
+        if content.ends_with(char::is_whitespace) {
+            self.text(" ");
+        }
+    }
+
+    fn nested(&self, inlines: &[Inline]) -> String {
+        match self.format {
+            ExportFormat::Rst => escape_text(&plain_text(inlines), self.format),
+            _ => {
+                let mut writer = InlineWriter { format: self.format, out: String::new(), after_markup: false };
+                writer.write(inlines);
+                writer.out
+            }
+        }
+    }
+
+    /// Link label, the URL itself when the link has no text
+    fn link_text(&self, content: &[Inline], href: &str) -> String {
+        let text = self.nested(content);
+        if text.trim().is_empty() { escape_text(href, self.format) } else { text }
+    }
+
+    fn write(&mut self, inlines: &[Inline]) {
+        for inline in inlines {
+            match (self.format, inline) {
+                (_, Inline::Text(text)) => self.text(&escape_text(text, self.format)),
+                (_, Inline::LineBreak) => self.text(&LINE_BREAK_MARK.to_string()),
+                (ExportFormat::Markdown, Inline::Strong(content)) => self.markup("**", &self.nested(content), "**"),
+                (ExportFormat::Markdown, Inline::Emphasis(content)) => self.markup("*", &self.nested(content), "*"),
+                (ExportFormat::AsciiDoc, Inline::Strong(content)) => self.markup("**", &self.nested(content), "**"),
+                (ExportFormat::AsciiDoc, Inline::Emphasis(content)) => self.markup("__", &self.nested(content), "__"),
+                (ExportFormat::Rst, Inline::Strong(content)) => self.markup("**", &self.nested(content), "**"),
+                (ExportFormat::Rst, Inline::Emphasis(content)) => self.markup("*", &self.nested(content), "*"),
+                (ExportFormat::Markdown, Inline::Code(code)) => {
+                    let fence = "`".repeat(longest_run(code, '`') + 1);
+                    let padding = if code.starts_with('`') || code.ends_with('`') { " " } else { "" };
+                    self.markup(&format!("{}{}", fence, padding), &collapse_whitespace(code), &format!("{}{}", padding, fence));
+                }
+                (ExportFormat::AsciiDoc, Inline::Code(code)) => self.markup("`+", &collapse_whitespace(code), "+`"),
+                (ExportFormat::Rst, Inline::Code(code)) => self.markup("``", &collapse_whitespace(code), "``"),
+                (ExportFormat::Markdown, Inline::Link { href, content }) => {
+                    let text = self.link_text(content, href);
+                    self.markup("[", &text, &format!("]({})", markdown_url(href)));
+                }
+                (ExportFormat::AsciiDoc, Inline::Link { href, content }) => {
+                    let text = self.link_text(content, href);
+                    self.markup(&format!("link:{}[", asciidoc_url(href)), &text.replace(']', "\\]"), "]");
+                }
+                (ExportFormat::Rst, Inline::Link { href, content }) => {
+                    let text = self.link_text(content, href).replace('<', "\\<");
+                    self.markup("`", &format!("{} <{}>", text.trim(), href), "`__");
+                }
+                (ExportFormat::Markdown, Inline::Image { src, alt }) => {
+                    self.markup("![", &format!("{}]({})", escape_text(alt, self.format), markdown_url(src)), "")
+                }
+                (ExportFormat::AsciiDoc, Inline::Image { src, alt }) => {
+                    self.markup(&format!("image:{}[", asciidoc_url(src)), &format!("{}]", alt.replace(']', "\\]")), "")
+                }
+                // RST has no inline images without substitutions: link to it instead
+                (ExportFormat::Rst, Inline::Image { src, alt }) => {
+                    let text = if alt.is_empty() { "image" } else { alt };
+                    self.markup("`", &format!("{} <{}>", escape_text(text, self.format), src), "`__");
+                }
+            }
+        }
+    }
+}
+
+fn longest_run(text: &str, c: char) -> usize {
+    text.split(|other| other != c).map(str::len).max().unwrap_or(0)
+}
+
+fn markdown_url(url: &str) -> String {
+    url.replace(' ', "%20").replace('(', "%28").replace(')', "%29")
+}
+
+fn asciidoc_url(url: &str) -> String {
+    url.replace(' ', "%20").replace('[', "%5B").replace(']', "%5D")
+}
+
+/// Inline content as a single line of `format` (line breaks excepted)
+fn render_inlines(inlines: &[Inline], format: ExportFormat) -> String {
+    let mut writer = InlineWriter { format, out: String::new(), after_markup: false };
+    writer.write(inlines);
+    let text = collapse_whitespace(&writer.out);
+    let line_break = match format {
+        ExportFormat::Markdown => "\\\n",
+        ExportFormat::AsciiDoc => " +\n",
+        ExportFormat::Rst => "\n",
+    };
+    text.split(LINE_BREAK_MARK).map(str::trim).filter(|line| !line.is_empty()).collect::<Vec<_>>().join(line_break)
+}
+
+// --- Block Rendering ---
+
+/// Prefixes the first line of `text` with `first` and the others with `rest`. Blank lines stay empty.
+fn indent(text: &str, first: &str, rest: &str) -> String {
+    text.lines()
+        .enumerate()
+        .map(|(index, line)| match (index, line.is_empty()) {
+            (_, true) => String::new(),
+            (0, false) => format!("{}{}", first, line),
+            (_, false) => format!("{}{}", rest, line),
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn markdown_block(block: &Block) -> String {
+    let format = ExportFormat::Markdown;
+    match block {
+        Block::Heading { level, content } => format!("{} {}", "#".repeat(*level as usize), render_inlines(content, format)),
+        Block::Paragraph(content) => render_inlines(content, format),
+        Block::List { ordered, items } => items
+            .iter()
+            .enumerate()
+            .map(|(index, item)| {
+                let marker = if *ordered { format!("{}. ", index + 1) } else { "- ".to_string() };
+                let mut body = String::new();
+                for (index, block) in item.iter().enumerate() {
+                    if index > 0 {
+                        // Keep the list tight when an item is just text and a nested list
+                        let tight = matches!((&item[index - 1], block), (Block::Paragraph(_), Block::List { .. }));
+                        body.push_str(if tight { "\n" } else { "\n\n" });
+                    }
+                    body.push_str(&markdown_block(block));
+                }
+                indent(&body, &marker, &" ".repeat(marker.len()))
+            })
+            .collect::<Vec<_>>()
+            .join("\n"),
+        Block::Code { language, code } => {
+            let fence = "`".repeat(longest_run(code, '`').max(2) + 1);
+            format!("{}{}\n{}\n{}", fence, language.as_deref().unwrap_or(""), code, fence)
+        }
+        Block::Quote(blocks) => {
+            let body = blocks.iter().map(markdown_block).collect::<Vec<_>>().join("\n\n");
+            body.lines().map(|line| if line.is_empty() { ">".to_string() } else { format!("> {}", line) }).collect::<Vec<_>>().join("\n")
+        }
+        Block::Image { src, alt, caption } => {
+            let caption = plain_text(caption);
+            let caption = collapse_whitespace(&caption);
+            if caption.is_empty() {
+                format!("![{}]({})", escape_text(alt, format), markdown_url(src))
+            } else {
+                format!("![{}]({} \"{}\")", escape_text(alt, format), markdown_url(src), caption.replace('"', "\\\""))
+            }
+        }
+        Block::Rule => "---".into(),
+    }
+}
+
+fn asciidoc_block(block: &Block, list_depth: usize) -> String {
+    let format = ExportFormat::AsciiDoc;
+    match block {
+        // A single `=` is the document title
+        Block::Heading { level, content } => format!("{} {}", "=".repeat((*level as usize + 1).min(6)), render_inlines(content, format)),
+        Block::Paragraph(content) => render_inlines(content, format),
+        Block::List { ordered, items } => {
+            let marker = if *ordered { ".".repeat(list_depth + 1) } else { "*".repeat(list_depth + 1) };
+            items
+                .iter()
+                .map(|item| {
+                    let mut text = String::new();
+                    for (index, block) in item.iter().enumerate() {
+                        let rendered = asciidoc_block(block, list_depth + 1);
+                        match (index, block) {
+                            (0, Block::Paragraph(_)) => text.push_str(&format!("{} {}", marker, rendered)),
+                            (0, _) => text.push_str(&format!("{} {{empty}}\n+\n{}", marker, rendered)),
+                            // Nested lists attach to the item on their own
+                            (_, Block::List { .. }) => text.push_str(&format!("\n{}", rendered)),
+                            _ => text.push_str(&format!("\n+\n{}", rendered)),
+                        }
+                    }
+                    text
+                })
+                .collect::<Vec<_>>()
+                .join("\n")
+        }
+        Block::Code { language, code } => {
+            let delimiter = "-".repeat(code.lines().filter(|l| !l.is_empty() && l.chars().all(|c| c == '-')).map(str::len).max().unwrap_or(0).max(3) + 1);
+            let header = language.as_ref().map(|language| format!("[source,{}]\n", language)).unwrap_or_default();
+            format!("{}{}\n{}\n{}", header, delimiter, code, delimiter)
+        }
+        Block::Quote(blocks) => {
+            let body = blocks.iter().map(|block| asciidoc_block(block, 0)).collect::<Vec<_>>().join("\n\n");
+            format!("____\n{}\n____", body)
+        }
+        Block::Image { src, alt, caption } => {
+            let caption = render_inlines(caption, format);
+            let title = if caption.is_empty() { String::new() } else { format!(".{}\n", caption.replace('\n', " ")) };
+            format!("{}image::{}[{}]", title, asciidoc_url(src), alt.replace(']', "\\]"))
+        }
+        Block::Rule => "'''".into(),
+    }
+}
+
+fn rst_block(block: &Block) -> String {
+    let format = ExportFormat::Rst;
+    match block {
+        Block::Heading { level, content } => {
+            let title = render_inlines(content, format).replace('\n', " ");
+            let underline = RST_UNDERLINES[(*level as usize).clamp(1, RST_UNDERLINES.len()) - 1];
+            format!("{}\n{}", title, underline.to_string().repeat(title.chars().count()))
+        }
+        Block::Paragraph(content) => {
+            let text = render_inlines(content, format);
+            // Line breaks need a line block in RST
+            if text.contains('\n') { indent(&text, "| ", "| ") } else { text }
+        }
+        Block::List { ordered, items } => {
+            let marker = if *ordered { "#. " } else { "- " };
+            items
+                .iter()
+                .map(|item| {
+                    let body = item.iter().map(rst_block).collect::<Vec<_>>().join("\n\n");
+                    indent(&body, marker, &" ".repeat(marker.len()))
+                })
+                .collect::<Vec<_>>()
+                .join(if items.iter().any(|item| item.len() > 1) { "\n\n" } else { "\n" })
+        }
+        Block::Code { language, code } => {
+            let directive = match language {
+                Some(language) => format!(".. code-block:: {}", language),
+                None => "::".to_string(),
+            };
+            format!("{}\n\n{}", directive, indent(code, "    ", "    "))
+        }
+        Block::Quote(blocks) => {
+            let body = blocks.iter().map(rst_block).collect::<Vec<_>>().join("\n\n");
+            // The empty comment keeps the quote from being read as part of a preceding indented block
+            format!("..\n\n{}", indent(&body, "    ", "    "))
+        }
+        Block::Image { src, alt, caption } => {
+            let caption = render_inlines(caption, format);
+            let directive = if caption.is_empty() { "image" } else { "figure" };
+            let mut text = format!(".. {}:: {}", directive, src);
+            if !alt.is_empty() {
+                text.push_str(&format!("\n   :alt: {}", collapse_whitespace(alt)));
+            }
+            if !caption.is_empty() {
+                text.push_str(&format!("\n\n{}", indent(&caption, "   ", "   ")));
+            }
+            text
+        }
+        Block::Rule => "----".into(),
+    }
+}
+
+/// Renders the block model in `format`
+pub fn render_blocks(blocks: &[Block], format: ExportFormat) -> String {
+    let rendered: Vec<String> = blocks
+        .iter()
+        .map(|block| match format {
+            ExportFormat::Markdown => markdown_block(block),
+            ExportFormat::AsciiDoc => asciidoc_block(block, 0),
+            ExportFormat::Rst => rst_block(block),
+        })
+        .filter(|text| !text.trim().is_empty())
+        .collect();
+    format!("{}\n", rendered.join("\n\n"))
+}
+
+/// Converts extracted article HTML to `format`, resolving links and images against `base`
+pub fn convert_html(html: &str, base: &Url, format: ExportFormat) -> String {
+    render_blocks(&html_to_blocks(html, base), format)
+}
+
+/// Extracts the article at `url` and converts it to `format`. Fails with `FALLBACK_SIGNAL`
+/// when the page can't be extracted.
+pub async fn logic_fetch_article_as(url: String, options: ArticleOptions, format: ExportFormat, state: &ProxyState) -> Result<String, String> {
+    let base = Url::parse(&url).map_err(|e| e.to_string())?;
+    let content = logic_extract_article(url.clone(), options, state).await?.content.ok_or_else(|| FALLBACK_SIGNAL.to_string())?;
+    let converted = convert_html(&content, &base, format);
+    println!("[export::fetch_article_as] {} converted to {:?} ({} bytes)", url, format, converted.len());
+    Ok(converted)
+}
+
+// --- Fragments ---
+
+/// Elements without end tag
+const VOID_ELEMENTS: &[&str] = &["area", "base", "br", "col", "embed", "hr", "img", "input", "link", "meta", "source", "track", "wbr"];
+
+/// Attributes kept in clean HTML; everything else (classes, styles, ids, handlers) is app or
+/// site presentation
+const FRAGMENT_ATTRIBUTES: &[&str] = &["href", "src", "srcset", "alt", "title", "cite", "datetime", "colspan", "rowspan", "start", "lang", "dir"];
+
+/// Attributes holding a single URL, unproxied and made absolute in clean HTML
+const FRAGMENT_URL_ATTRIBUTES: &[&str] = &["href", "src", "cite"];
+
+/// What `convert_fragment` produces
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FragmentFormat {
+    /// Semantic HTML without presentation attributes
+    Html,
+    Markdown,
+    /// Text only, links followed by their URL
+    Text,
+}
+
+/// Start and end tags of a fragment
+static FRAGMENT_TAG: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"<(/?)([a-zA-Z][a-zA-Z0-9]*)[^>]*>").unwrap());
+
+/// Elements parsed away outside their parent, with the markup that restores it
+const FRAGMENT_CONTEXTS: &[(&str, &str)] = &[("li", "<ul>"), ("td", "<table><tr>"), ("th", "<table><tr>"), ("tr", "<table>")];
+
+/// Markup of a selection made parseable: a selection starting mid-element (`text</code></pre>`,
+/// `item</li><li>...`) gets the elements it starts in reopened, and list items or table cells
+/// cut from their list or table get one. The parser then closes whatever is left open.
+fn balanced_fragment(html: &str) -> String {
+    let mut open: Vec<String> = Vec::new();
+    let mut unmatched: Vec<String> = Vec::new();
+    let mut outermost: Option<String> = None;
+    for tag in FRAGMENT_TAG.captures_iter(html) {
+        let name = tag[2].to_ascii_lowercase();
+        if &tag[1] == "/" {
+            match open.iter().rposition(|other| *other == name) {
+                Some(index) => open.truncate(index),
+                None => unmatched.push(name),
+            }
+        } else if !tag[0].ends_with("/>") && !VOID_ELEMENTS.contains(&name.as_str()) {
+            if open.is_empty() && unmatched.is_empty() && outermost.is_none() {
+                outermost = Some(name.clone());
+            }
+            open.push(name);
+        }
+    }
+    let outermost = unmatched.last().cloned().or(outermost);
+    let context = FRAGMENT_CONTEXTS.iter().find(|(name, _)| outermost.as_deref() == Some(name)).map_or("", |(_, context)| context);
+    let reopened: String = unmatched.iter().rev().map(|name| format!("<{}>", name)).collect();
+    let fragment = Html::parse_fragment(&format!("{}{}{}", context, reopened, html));
+    fragment.root_element().inner_html()
+}
+
+/// `srcset` with each candidate URL unproxied and made absolute
+fn clean_srcset(srcset: &str, base: &Url, state: &ProxyState) -> String {
+    srcset
+        .split(',')
+        .filter_map(|candidate| {
+            let mut parts = candidate.split_whitespace();
+            let url = absolutize_url(&unproxied_url(parts.next()?, state), base)?;
+            Some(std::iter::once(url).chain(parts.map(str::to_string)).collect::<Vec<_>>().join(" "))
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Selection HTML without scripts, presentation attributes or proxy URLs. Lazy-loaded images
+/// get their real URL as `src`, and code blocks keep their `language-*` class.
+fn clean_fragment_html(html: &str, base: &Url, state: &ProxyState) -> Result<String, String> {
+    rewrite_str(
+        &balanced_fragment(html),
+        RewriteStrSettings {
+            element_content_handlers: vec![
+                element!(SKIPPED_ELEMENTS.join(", "), |el| {
+                    el.remove();
+                    Ok(())
+                }),
+                element!("img", |el| {
+                    let lazy = LAZY_IMAGE_ATTRIBUTES.iter().filter_map(|attr| el.get_attribute(attr)).find(|src| !src.trim().is_empty());
+                    if let Some(src) = lazy {
+                        el.set_attribute("src", &src)?;
+                    }
+                    Ok(())
+                }),
+                element!("*", |el| {
+                    let language = el
+                        .get_attribute("class")
+                        .and_then(|classes| classes.split_whitespace().find(|c| c.starts_with("language-") || c.starts_with("lang-")).map(str::to_string));
+                    let names: Vec<String> = el.attributes().iter().map(|attr| attr.name()).collect();
+                    for name in names {
+                        if !FRAGMENT_ATTRIBUTES.contains(&name.as_str()) {
+                            el.remove_attribute(&name);
+                        } else if FRAGMENT_URL_ATTRIBUTES.contains(&name.as_str()) {
+                            let value = el.get_attribute(&name).unwrap_or_default();
+                            let value = value.trim();
+                            // Same-page anchors mean nothing once copied elsewhere
+                            match absolutize_url(&unproxied_url(value, state), base).filter(|_| !value.starts_with('#') && !value.starts_with("javascript:")) {
+                                Some(url) => el.set_attribute(&name, &url)?,
+                                None => el.remove_attribute(&name),
+                            }
+                        } else if name == "srcset" {
+                            let srcset = clean_srcset(&el.get_attribute("srcset").unwrap_or_default(), base, state);
+                            el.set_attribute("srcset", &srcset)?;
+                        }
+                    }
+                    if let Some(language) = language {
+                        el.set_attribute("class", &language)?;
+                    }
+                    Ok(())
+                }),
+            ],
+            ..RewriteStrSettings::default()
+        },
+    )
+    .map_err(|e| e.to_string())
+}
+
+/// Inline content as text: whitespace collapsed, line breaks kept, links followed by their
+/// URL unless their text is the URL
+fn text_inlines(inlines: &[Inline]) -> String {
+    fn write(inlines: &[Inline], out: &mut String) {
+        for inline in inlines {
+            match inline {
+                Inline::Text(text) | Inline::Code(text) => out.push_str(text),
+                Inline::Strong(content) | Inline::Emphasis(content) => write(content, out),
+                Inline::Link { href, content } => {
+                    let text = collapse_whitespace(&plain_text(content));
+                    write(content, out);
+                    if text.is_empty() {
+                        out.push_str(href);
+                    } else if text != *href {
+                        out.push_str(&format!(" ({})", href));
+                    }
+                }
+                Inline::Image { alt, .. } => out.push_str(alt),
+                Inline::LineBreak => out.push(LINE_BREAK_MARK),
+            }
+        }
+    }
+    let mut out = String::new();
+    write(inlines, &mut out);
+    collapse_whitespace(&out).split(LINE_BREAK_MARK).map(str::trim).filter(|line| !line.is_empty()).collect::<Vec<_>>().join("\n")
+}
+
+/// A block read top to bottom as text: headings and paragraphs as lines, list items with
+/// their marker, code verbatim, images as their caption or alt text
+fn text_block(block: &Block) -> String {
+    match block {
+        Block::Heading { content, .. } | Block::Paragraph(content) => text_inlines(content),
+        Block::List { ordered, items } => items
+            .iter()
+            .enumerate()
+            .map(|(index, item)| {
+                let marker = if *ordered { format!("{}. ", index + 1) } else { "- ".to_string() };
+                let body = item.iter().map(text_block).filter(|text| !text.is_empty()).collect::<Vec<_>>().join("\n");
+                indent(&body, &marker, &" ".repeat(marker.len()))
+            })
+            .collect::<Vec<_>>()
+            .join("\n"),
+        Block::Code { code, .. } => code.clone(),
+        Block::Quote(blocks) => blocks.iter().map(text_block).filter(|text| !text.is_empty()).collect::<Vec<_>>().join("\n\n"),
+        Block::Image { alt, caption, .. } => Some(text_inlines(caption)).filter(|caption| !caption.is_empty()).unwrap_or_else(|| alt.clone()),
+        Block::Rule => String::new(),
+    }
+}
+
+/// Converts HTML copied from a selection (of the reader view or of a proxied page) for the
+/// clipboard. The fragment may start or end mid-element. Proxy URLs are turned back into the
+/// original ones, and relative URLs resolved against the proxied article.
+pub fn logic_convert_fragment(html: String, format: FragmentFormat, state: &ProxyState) -> Result<String, String> {
+    let base = state.base_url.load_full();
+    let clean = clean_fragment_html(&html, &base, state)?;
+    Ok(match format {
+        FragmentFormat::Html => clean,
+        FragmentFormat::Markdown => render_blocks(&html_to_blocks(&clean, &base), ExportFormat::Markdown),
+        FragmentFormat::Text => {
+            let blocks: Vec<String> = html_to_blocks(&clean, &base).iter().map(text_block).filter(|text| !text.trim().is_empty()).collect();
+            format!("{}\n", blocks.join("\n\n"))
+        }
+    })
+}
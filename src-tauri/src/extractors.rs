@@ -0,0 +1,593 @@
+use crate::shared::{host_in_domain, host_of_domain_key, largest_text_container, MutationReport, ProxyState, ReadabilityConfig};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::io::Cursor;
+use std::sync::Mutex;
+use url::Url;
+
+/// Comparisons on a domain before its extractor is picked from them
+const MIN_SAMPLES_FOR_CHOICE: u32 = 5;
+
+/// Share of a domain's comparisons a backend must win to be picked over the default
+const AUTO_SELECT_WIN_RATE: f64 = 0.6;
+
+/// Score difference under which a comparison is a tie
+const TIE_MARGIN: f64 = 0.02;
+
+/// Share of the page text an extraction is expected to keep at most: above it, the extra text
+/// is mostly navigation and footers, which the other metrics catch
+const FULL_COVERAGE_RATIO: f64 = 0.5;
+
+/// Phrases of page chrome that shouldn't make it into an article
+const BOILERPLATE_PHRASES: &[&str] = &[
+    "subscribe to our newsletter",
+    "sign up for our newsletter",
+    "all rights reserved",
+    "accept cookies",
+    "cookie policy",
+    "privacy policy",
+    "terms of service",
+    "related articles",
+    "recommended for you",
+    "share this article",
+    "follow us on",
+    "leave a comment",
+    "skip to content",
+    "advertisement",
+];
+
+/// Extraction algorithm run on a page once its site rules and clean-up passes are applied
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ExtractorBackend {
+    /// The readability crate
+    #[default]
+    Readability,
+    /// The container whose direct paragraphs hold the most text (`largest_text_container`)
+    DenseContainer,
+}
+
+impl ExtractorBackend {
+    pub fn other(self) -> Self {
+        match self {
+            Self::Readability => Self::DenseContainer,
+            Self::DenseContainer => Self::Readability,
+        }
+    }
+
+    /// Step name in provenance and deadline reports
+    pub fn step(self) -> &'static str {
+        match self {
+            Self::Readability => "readability",
+            Self::DenseContainer => "dense_container",
+        }
+    }
+
+    /// Content and text length (in chars) extracted from `html`
+    pub fn extract(self, html: &str, url: &Url, config: &ReadabilityConfig) -> Option<(String, usize)> {
+        self.extract_titled(html, url, config).0
+    }
+
+    /// `extract`, with the page title readability found (the other backend finds none)
+    fn extract_titled(self, html: &str, url: &Url, config: &ReadabilityConfig) -> (Option<(String, usize)>, Option<String>) {
+        match self {
+            Self::Readability => readability_content(html, url, config.min_extracted_html_len),
+            Self::DenseContainer => (largest_text_container(html), None),
+        }
+    }
+}
+
+fn readability_content(html: &str, url: &Url, min_html_len: usize) -> (Option<(String, usize)>, Option<String>) {
+    let mut cursor = Cursor::new(html.as_bytes());
+    let Ok(product) = readability::extractor::extract(&mut cursor, url) else {
+        return (None, None);
+    };
+    let title = Some(product.title.trim().to_string()).filter(|title| !title.is_empty());
+    let content = product.content.trim();
+
+    // Minimal HTML documents come back from JS-heavy pages
+    let is_empty_shell = content.is_empty()
+        || (content.len() < min_html_len
+            && (content.contains("<head></head>") || content == "<!DOCTYPE html><html><head></head><body></body></html>"));
+    if is_empty_shell {
+        return (None, title);
+    }
+    (Some((product.content, product.text.trim().chars().count())), title)
+}
+
+/// How well an extraction kept the article and left the page chrome out, measured against the
+/// page it came from
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ExtractionQuality {
+    /// Visible text of the extraction, in chars
+    pub text_len: usize,
+    /// Visible text of the page body, in chars
+    pub page_text_len: usize,
+    pub text_ratio: f64,
+    pub images: usize,
+    pub page_images: usize,
+    /// Share of the page images kept (1 when the page has none)
+    pub image_retention: f64,
+    /// Share of the extracted text inside links
+    pub link_density: f64,
+    /// Boilerplate phrases found in the extracted text
+    pub boilerplate_phrases: usize,
+    /// 0 to 1, higher is better
+    pub score: f64,
+}
+
+/// Text length, image count, linked text length and lowercased text of the first `selector`
+/// element of `html`
+fn measure(html: &str, selector: &str) -> (usize, usize, usize, String) {
+    let document = scraper::Html::parse_document(html);
+    let root = scraper::Selector::parse(selector).unwrap();
+    let images = scraper::Selector::parse("img").unwrap();
+    let links = scraper::Selector::parse("a").unwrap();
+    let chars = |text: String| text.split_whitespace().map(|word| word.chars().count() + 1).sum::<usize>();
+
+    let Some(root) = document.select(&root).next() else {
+        return (0, 0, 0, String::new());
+    };
+    let text = root.text().collect::<Vec<_>>().join(" ");
+    let linked: usize = root.select(&links).map(|link| chars(link.text().collect::<Vec<_>>().join(" "))).sum();
+    (chars(text.clone()), root.select(&images).count(), linked, text.to_lowercase())
+}
+
+/// Quality metrics of `content` extracted from `page_html`, independent of the backend that
+/// produced it
+pub fn quality_of(content: &str, page_html: &str) -> ExtractionQuality {
+    let (page_text_len, page_images, _, _) = measure(page_html, "body");
+    let (text_len, images, linked, text) = measure(content, "body");
+
+    let text_ratio = if page_text_len == 0 { 0.0 } else { text_len as f64 / page_text_len as f64 };
+    let image_retention = if page_images == 0 { 1.0 } else { (images as f64 / page_images as f64).min(1.0) };
+    let link_density = if text_len == 0 { 0.0 } else { (linked as f64 / text_len as f64).min(1.0) };
+    let boilerplate_phrases = BOILERPLATE_PHRASES.iter().filter(|phrase| text.contains(*phrase)).count();
+
+    let coverage = (text_ratio / FULL_COVERAGE_RATIO).min(1.0);
+    let score = if text_len == 0 {
+        0.0
+    } else {
+        0.5 * coverage + 0.2 * image_retention + 0.2 * (1.0 - link_density) + 0.1 * (1.0 - (boilerplate_phrases as f64 / 3.0).min(1.0))
+    };
+    ExtractionQuality { text_len, page_text_len, text_ratio, images, page_images, image_retention, link_density, boilerplate_phrases, score }
+}
+
+/// Comparison sampling and automatic choice
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ExtractorComparisonConfig {
+    /// Share of extractions (0 to 1) on which both backends are run and compared
+    pub sample_rate: f64,
+    /// Domains use the backend winning their comparisons instead of readability
+    pub auto_select: bool,
+}
+
+impl Default for ExtractorComparisonConfig {
+    fn default() -> Self {
+        Self { sample_rate: 0.0, auto_select: true }
+    }
+}
+
+/// Comparisons run on a domain
+#[derive(Debug, Clone, Default)]
+pub struct DomainComparison {
+    pub samples: u32,
+    pub readability_wins: u32,
+    pub dense_container_wins: u32,
+    pub ties: u32,
+    readability_score: f64,
+    dense_container_score: f64,
+}
+
+impl DomainComparison {
+    fn record(&mut self, readability: &ExtractionQuality, dense_container: &ExtractionQuality) {
+        self.samples += 1;
+        self.readability_score += readability.score;
+        self.dense_container_score += dense_container.score;
+        match readability.score - dense_container.score {
+            difference if difference > TIE_MARGIN => self.readability_wins += 1,
+            difference if difference < -TIE_MARGIN => self.dense_container_wins += 1,
+            _ => self.ties += 1,
+        }
+    }
+
+    fn win_rate(&self, backend: ExtractorBackend) -> f64 {
+        if self.samples == 0 {
+            return 0.0;
+        }
+        let wins = match backend {
+            ExtractorBackend::Readability => self.readability_wins,
+            ExtractorBackend::DenseContainer => self.dense_container_wins,
+        };
+        wins as f64 / self.samples as f64
+    }
+
+    /// Backend the comparisons favor, once there are enough of them and one backend wins
+    /// clearly
+    pub fn preferred(&self) -> Option<ExtractorBackend> {
+        if self.samples < MIN_SAMPLES_FOR_CHOICE {
+            return None;
+        }
+        [ExtractorBackend::Readability, ExtractorBackend::DenseContainer]
+            .into_iter()
+            .find(|backend| self.win_rate(*backend) >= AUTO_SELECT_WIN_RATE)
+    }
+}
+
+#[derive(Default)]
+pub struct ExtractorStore {
+    config: ExtractorComparisonConfig,
+    /// Keyed by host
+    domains: HashMap<String, DomainComparison>,
+    /// Manual choices, keyed by domain (subdomains included)
+    overrides: HashMap<String, ExtractorBackend>,
+    /// Extractions seen, for spreading the sampled ones
+    extractions: u64,
+}
+
+/// What `extract_content` runs on a page: the active backend, and whether both backends are
+/// compared on it. The comparison is kept until `record_comparison`, and the page title
+/// readability found until `title`.
+#[derive(Default)]
+pub struct ExtractorRun {
+    pub backend: ExtractorBackend,
+    pub compare: bool,
+    comparison: Mutex<Option<(ExtractionQuality, ExtractionQuality)>>,
+    title: Mutex<Option<String>>,
+}
+
+impl ExtractorRun {
+    /// Runs `backend` on `html`, keeping the title it found
+    pub fn extract(&self, backend: ExtractorBackend, html: &str, url: &Url, config: &ReadabilityConfig) -> Option<(String, usize)> {
+        let (extracted, title) = backend.extract_titled(html, url, config);
+        if title.is_some() {
+            *self.title.lock().unwrap() = title;
+        }
+        extracted
+    }
+
+    /// Title of the page according to readability, if it ran
+    pub fn title(&self) -> Option<String> {
+        self.title.lock().unwrap().clone()
+    }
+
+    /// Keeps the qualities of the readability and dense container extractions of the page
+    pub fn compared(&self, readability: ExtractionQuality, dense_container: ExtractionQuality) {
+        *self.comparison.lock().unwrap() = Some((readability, dense_container));
+    }
+}
+
+fn host_of(url: &Url) -> String {
+    url.host_str().unwrap_or_default().to_ascii_lowercase()
+}
+
+/// Backend used on `host`: the manual override, else the one its comparisons favor
+fn active_backend(host: &str, store: &ExtractorStore) -> ExtractorBackend {
+    let overridden = store
+        .overrides
+        .iter()
+        .filter(|(domain, _)| host_in_domain(host, domain))
+        .max_by_key(|(domain, _)| domain.len())
+        .map(|(_, backend)| *backend);
+    overridden
+        .or_else(|| store.config.auto_select.then(|| store.domains.get(host).and_then(DomainComparison::preferred)).flatten())
+        .unwrap_or_default()
+}
+
+/// Backend for an extraction of `url`, and whether it's one of the sampled comparisons
+pub fn run_for(url: &Url, state: &ProxyState) -> ExtractorRun {
+    let mut store = state.extractors.lock().unwrap();
+    let rate = store.config.sample_rate.clamp(0.0, 1.0);
+    let seen = store.extractions;
+    store.extractions += 1;
+    // Every 1/rate-th extraction, spread evenly rather than at random
+    let compare = ((seen + 1) as f64 * rate).floor() > (seen as f64 * rate).floor();
+    ExtractorRun { backend: active_backend(&host_of(url), &store), compare, ..ExtractorRun::default() }
+}
+
+/// Adds the comparison made during the extraction of `url`, if any, to its domain's results
+pub fn record_comparison(url: &Url, run: &ExtractorRun, state: &ProxyState) {
+    let Some((readability, dense_container)) = run.comparison.lock().unwrap().take() else {
+        return;
+    };
+    println!(
+        "[extractors::record_comparison] {}: readability {:.2}, dense_container {:.2}",
+        url, readability.score, dense_container.score
+    );
+    let mut store = state.extractors.lock().unwrap();
+    store.domains.entry(host_of(url)).or_default().record(&readability, &dense_container);
+}
+
+/// Runs `run.backend` on `html` and, when the run is sampled, the other backend too, keeping
+/// both qualities measured against `html`
+pub fn extract_with(run: &ExtractorRun, html: &str, url: &Url, config: &ReadabilityConfig) -> Option<(String, usize)> {
+    let extracted = run.extract(run.backend, html, url, config);
+    if run.compare {
+        let other = run.extract(run.backend.other(), html, url, config);
+        let quality = |extracted: &Option<(String, usize)>| extracted.as_ref().map(|(content, _)| quality_of(content, html)).unwrap_or_default();
+        let (readability, dense_container) = match run.backend {
+            ExtractorBackend::Readability => (quality(&extracted), quality(&other)),
+            ExtractorBackend::DenseContainer => (quality(&other), quality(&extracted)),
+        };
+        run.compared(readability, dense_container);
+    }
+    extracted
+}
+
+/// Comparison results of a domain
+#[derive(Debug, Clone, Serialize)]
+pub struct DomainExtractorSummary {
+    pub domain: String,
+    pub samples: u32,
+    pub readability_win_rate: f64,
+    pub dense_container_win_rate: f64,
+    pub tie_rate: f64,
+    pub readability_mean_score: f64,
+    pub dense_container_mean_score: f64,
+    /// Backend used for the domain's extractions
+    pub active: ExtractorBackend,
+    pub overridden: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ExtractorComparison {
+    pub config: ExtractorComparisonConfig,
+    /// Most sampled first
+    pub domains: Vec<DomainExtractorSummary>,
+    /// Manual choices, keyed by domain
+    pub overrides: HashMap<String, ExtractorBackend>,
+}
+
+/// Win rates of each backend per domain, with the backend each domain uses
+pub fn logic_get_extractor_comparison(state: &ProxyState) -> ExtractorComparison {
+    let store = state.extractors.lock().unwrap();
+    let mean = |total: f64, samples: u32| if samples == 0 { 0.0 } else { total / samples as f64 };
+    let mut domains: Vec<DomainExtractorSummary> = store
+        .domains
+        .iter()
+        .map(|(host, comparison)| DomainExtractorSummary {
+            domain: host.clone(),
+            samples: comparison.samples,
+            readability_win_rate: comparison.win_rate(ExtractorBackend::Readability),
+            dense_container_win_rate: comparison.win_rate(ExtractorBackend::DenseContainer),
+            tie_rate: mean(comparison.ties as f64, comparison.samples),
+            readability_mean_score: mean(comparison.readability_score, comparison.samples),
+            dense_container_mean_score: mean(comparison.dense_container_score, comparison.samples),
+            active: active_backend(host, &store),
+            overridden: store.overrides.keys().any(|domain| host_in_domain(host, domain)),
+        })
+        .collect();
+    domains.sort_by(|a, b| b.samples.cmp(&a.samples).then_with(|| a.domain.cmp(&b.domain)));
+    ExtractorComparison { config: store.config.clone(), domains, overrides: store.overrides.clone() }
+}
+
+pub fn logic_set_extractor_comparison_config(config: ExtractorComparisonConfig, state: &ProxyState) -> Result<(), String> {
+    if !(0.0..=1.0).contains(&config.sample_rate) {
+        return Err(format!("sample_rate must be between 0 and 1, got {}", config.sample_rate));
+    }
+    println!("[extractors::set_extractor_comparison_config] Sample rate {}, auto select {}", config.sample_rate, config.auto_select);
+    state.extractors.lock().unwrap().config = config;
+    Ok(())
+}
+
+/// Forces `backend` on `domain` and its subdomains, or goes back to the automatic choice
+pub fn logic_set_domain_extractor(domain: String, backend: Option<ExtractorBackend>, state: &ProxyState) -> Result<(), String> {
+    let domain = host_of_domain_key(&domain);
+    if domain.is_empty() {
+        return Err("Domain is required".into());
+    }
+    println!("[extractors::set_domain_extractor] {} -> {:?}", domain, backend);
+    let mut store = state.extractors.lock().unwrap();
+    match backend {
+        Some(backend) => store.overrides.insert(domain, backend),
+        None => store.overrides.remove(&domain),
+    };
+    Ok(())
+}
+
+/// Removes the comparisons and extractor overrides of `domain` and its subdomains
+pub fn clear_extractor_comparison_for_domain(domain: &str, dry_run: bool, state: &ProxyState) -> MutationReport {
+    let mut report = MutationReport::new(dry_run);
+    let host = host_of_domain_key(domain);
+    let mut store = state.extractors.lock().unwrap();
+
+    let mut compared: Vec<String> = store.domains.keys().filter(|key| host_in_domain(key, &host)).cloned().collect();
+    compared.sort();
+    for key in compared {
+        report.record("extractor_comparisons", key.clone(), None);
+        if !dry_run {
+            store.domains.remove(&key);
+        }
+    }
+    let mut overridden: Vec<String> = store.overrides.keys().filter(|key| host_in_domain(key, &host)).cloned().collect();
+    overridden.sort();
+    for key in overridden {
+        report.record("extractor_overrides", key.clone(), None);
+        if !dry_run {
+            store.overrides.remove(&key);
+        }
+    }
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::check_clear_for_domain;
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!((actual - expected).abs() < 1e-3, "{} instead of {}", actual, expected);
+    }
+
+    /// A page of `paragraphs` article paragraphs and two images, inside navigation links and a
+    /// footer of boilerplate
+    fn page(paragraphs: &str) -> String {
+        let nav: String = ["Home", "World", "Business", "Sport", "Culture", "Travel", "Opinion", "Video"]
+            .iter()
+            .map(|section| format!("<a href=\"/{}\">{}</a> ", section.to_lowercase(), section))
+            .collect();
+        format!(
+            "<html><body><nav>{}</nav><article>{}</article><footer>Subscribe to our newsletter. Privacy policy. Terms of service. All rights reserved.</footer></body></html>",
+            nav, paragraphs
+        )
+    }
+
+    fn article() -> String {
+        let paragraph = "<p>The harbour authority published its tide tables for the year, and the spring tides arrive earlier than usual along the whole coast.</p>";
+        format!("{}<img src=\"a.jpg\">{}<img src=\"b.jpg\">{}", paragraph.repeat(3), paragraph.repeat(3), paragraph.repeat(2))
+    }
+
+    fn scored(score: f64) -> ExtractionQuality {
+        ExtractionQuality { score, ..ExtractionQuality::default() }
+    }
+
+    fn compared(results: &[(f64, f64)]) -> DomainComparison {
+        let mut comparison = DomainComparison::default();
+        for (readability, dense_container) in results {
+            comparison.record(&scored(*readability), &scored(*dense_container));
+        }
+        comparison
+    }
+
+    #[test]
+    fn quality_measures_text_images_links_and_boilerplate() {
+        let content = r#"<p>alpha beta</p><p><a href="/x">gamma</a> delta</p><img src="a.jpg">"#;
+        let page = format!(r#"<body><nav><a href="/">home</a></nav>{}<img src="b.jpg"><footer>all rights reserved</footer></body>"#, content);
+
+        // Text is counted in chars, a space after each word
+        let quality = quality_of(content, &page);
+        assert_eq!((quality.text_len, quality.page_text_len), (23, 48));
+        assert_eq!((quality.images, quality.page_images), (1, 2));
+        assert_close(quality.text_ratio, 23.0 / 48.0);
+        assert_close(quality.image_retention, 0.5);
+        assert_close(quality.link_density, 6.0 / 23.0);
+        assert_eq!(quality.boilerplate_phrases, 0);
+        assert_close(quality.score, 0.5 * (23.0 / 48.0 / FULL_COVERAGE_RATIO) + 0.2 * 0.5 + 0.2 * (1.0 - 6.0 / 23.0) + 0.1);
+
+        // The whole page keeps everything, chrome included
+        let everything = quality_of(&page, &page);
+        assert_close(everything.text_ratio, 1.0);
+        assert_close(everything.image_retention, 1.0);
+        assert_eq!(everything.boilerplate_phrases, 1);
+    }
+
+    #[test]
+    fn quality_edge_cases() {
+        // Nothing extracted scores 0, whatever the other metrics say
+        let empty = quality_of("", &page(&article()));
+        assert_eq!((empty.text_len, empty.score), (0, 0.0));
+        assert_close(empty.link_density, 0.0);
+
+        // A page without images doesn't penalize an extraction without images
+        let text = "<p>Only words here, and no pictures at all.</p>";
+        assert_close(quality_of(text, &format!("<body>{}</body>", text)).image_retention, 1.0);
+
+        // Extra copies of images don't push retention past 1
+        let doubled = r#"<p>Photo</p><img src="a.jpg"><img src="a.jpg">"#;
+        assert_close(quality_of(doubled, r#"<body><p>Photo</p><img src="a.jpg"></body>"#).image_retention, 1.0);
+
+        // Past three boilerplate phrases the penalty doesn't grow
+        let chrome = "<p>Subscribe to our newsletter. Privacy policy. Terms of service. All rights reserved. Follow us on social media.</p>";
+        let quality = quality_of(chrome, &format!("<body>{}</body>", chrome));
+        assert_eq!(quality.boilerplate_phrases, 5);
+        assert_close(quality.score, 0.5 + 0.2 + 0.2);
+    }
+
+    #[test]
+    fn quality_ranks_the_article_over_the_whole_page_and_a_teaser() {
+        let page = page(&article());
+        let article = quality_of(&article(), &page);
+        let whole_page = quality_of(&page, &page);
+        let teaser = quality_of("<p>The harbour authority published its tide tables for the year.</p>", &page);
+
+        assert_close(article.score, 1.0);
+        assert!(article.score > whole_page.score + TIE_MARGIN, "{:?} vs {:?}", article, whole_page);
+        assert!(whole_page.score > teaser.score + TIE_MARGIN, "{:?} vs {:?}", whole_page, teaser);
+        assert_eq!(whole_page.boilerplate_phrases, 4);
+        assert!(whole_page.link_density > 0.0);
+        assert_close(teaser.image_retention, 0.0);
+    }
+
+    #[test]
+    fn comparisons_count_wins_and_ties() {
+        let comparison = compared(&[(0.9, 0.5), (0.5, 0.9), (0.70, 0.69), (0.69, 0.70), (0.8, 0.8)]);
+        assert_eq!((comparison.samples, comparison.readability_wins, comparison.dense_container_wins, comparison.ties), (5, 1, 1, 3));
+        assert_close(comparison.win_rate(ExtractorBackend::Readability), 0.2);
+        assert_close(DomainComparison::default().win_rate(ExtractorBackend::Readability), 0.0);
+    }
+
+    #[test]
+    fn a_backend_is_preferred_after_enough_clear_wins() {
+        let dense_wins = (0.4, 0.8);
+        let readability_wins = (0.8, 0.4);
+        let tie = (0.6, 0.6);
+
+        // Four wins out of four aren't enough samples yet
+        assert_eq!(compared(&[dense_wins; 4]).preferred(), None);
+        assert_eq!(compared(&[dense_wins; 5]).preferred(), Some(ExtractorBackend::DenseContainer));
+        // Three wins out of five reach the win rate; two out of five with ties don't
+        assert_eq!(compared(&[readability_wins, readability_wins, readability_wins, dense_wins, dense_wins]).preferred(), Some(ExtractorBackend::Readability));
+        assert_eq!(compared(&[dense_wins, dense_wins, tie, tie, readability_wins]).preferred(), None);
+    }
+
+    #[test]
+    fn overrides_win_over_comparisons_and_the_most_specific_applies() {
+        let state = ProxyState::default();
+        state.extractors.lock().unwrap().domains.insert("news.example.com".into(), compared(&[(0.4, 0.8); 5]));
+        let backend = |host: &str| active_backend(host, &state.extractors.lock().unwrap());
+        assert_eq!(backend("news.example.com"), ExtractorBackend::DenseContainer);
+        assert_eq!(backend("blog.example.com"), ExtractorBackend::Readability);
+
+        logic_set_extractor_comparison_config(ExtractorComparisonConfig { auto_select: false, ..ExtractorComparisonConfig::default() }, &state).unwrap();
+        assert_eq!(backend("news.example.com"), ExtractorBackend::Readability);
+        logic_set_extractor_comparison_config(ExtractorComparisonConfig::default(), &state).unwrap();
+
+        logic_set_domain_extractor("https://example.com".into(), Some(ExtractorBackend::Readability), &state).unwrap();
+        assert_eq!(backend("news.example.com"), ExtractorBackend::Readability);
+        logic_set_domain_extractor("blog.example.com".into(), Some(ExtractorBackend::DenseContainer), &state).unwrap();
+        assert_eq!(backend("blog.example.com"), ExtractorBackend::DenseContainer);
+        assert_eq!(backend("www.example.com"), ExtractorBackend::Readability);
+
+        // Back to automatic
+        logic_set_domain_extractor("example.com".into(), None, &state).unwrap();
+        assert_eq!(backend("news.example.com"), ExtractorBackend::DenseContainer);
+
+        let summary = logic_get_extractor_comparison(&state);
+        assert_eq!(summary.domains.len(), 1);
+        assert_eq!((summary.domains[0].active, summary.domains[0].overridden), (ExtractorBackend::DenseContainer, false));
+        assert_close(summary.domains[0].dense_container_win_rate, 1.0);
+        assert_close(summary.domains[0].readability_mean_score, 0.4);
+    }
+
+    #[test]
+    fn sampled_comparisons_are_spread_evenly() {
+        let url = Url::parse("https://news.example.com/story").unwrap();
+        for (rate, expected) in [(0.0, 0), (0.25, 25), (1.0 / 3.0, 33), (1.0, 100)] {
+            let state = ProxyState::default();
+            logic_set_extractor_comparison_config(ExtractorComparisonConfig { sample_rate: rate, ..ExtractorComparisonConfig::default() }, &state).unwrap();
+            let sampled: Vec<bool> = (0..100).map(|_| run_for(&url, &state).compare).collect();
+            assert_eq!(sampled.iter().filter(|compare| **compare).count(), expected, "rate {}", rate);
+            if rate == 0.25 {
+                assert!(sampled.chunks(4).all(|chunk| chunk.iter().filter(|compare| **compare).count() == 1));
+            }
+        }
+        assert!(logic_set_extractor_comparison_config(ExtractorComparisonConfig { sample_rate: 1.5, ..ExtractorComparisonConfig::default() }, &ProxyState::default()).is_err());
+    }
+
+    #[test]
+    fn clears_the_comparisons_and_overrides_of_a_domain() {
+        check_clear_for_domain(
+            |state, host| {
+                logic_set_domain_extractor(host.to_string(), Some(ExtractorBackend::DenseContainer), state).unwrap();
+                state.extractors.lock().unwrap().domains.insert(host.to_string(), DomainComparison::default());
+            },
+            clear_extractor_comparison_for_domain,
+            |state| {
+                let store = state.extractors.lock().unwrap();
+                let compared = store.domains.keys().map(|key| format!("compared {}", key));
+                compared.chain(store.overrides.keys().map(|key| format!("override {}", key))).collect()
+            },
+        );
+    }
+}
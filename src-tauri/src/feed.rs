@@ -0,0 +1,1007 @@
+use crate::translation::{translate_feed_items, ItemTranslation};
+use crate::dates::{self, first_seen, timestamp_of_date, ParsedDate};
+use crate::unread;
+use crate::shared::{
+    accept_language_for, clean_embedded_html, count_words, escape_html, host_in_domain, host_of_domain_key, logic_fetch_raw_html, unescape_html, MutationReport, ProxyState,
+    DEFAULT_ARTICLE_ACCEPT_LANGUAGE,
+};
+use quick_xml::events::{BytesRef, BytesStart, Event};
+use quick_xml::Reader;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{HashMap, HashSet};
+use url::Url;
+
+/// Hosts whose links are videos even without an enclosure
+const VIDEO_HOSTS: &[&str] = &["youtube.com", "youtu.be", "vimeo.com", "dailymotion.com", "twitch.tv", "tiktok.com"];
+
+/// Items with an image and at most this many words of text are shown as photos
+const PHOTO_MAX_WORDS: usize = 60;
+
+/// Words below which an item's best body is taken for a teaser
+const NEEDS_FETCH_WORDS: usize = 150;
+
+/// Score added per paragraph, heading, list item or image of a body
+const BLOCK_SCORE: usize = 15;
+
+/// Block elements counted towards a body's markup richness
+const RICH_MARKUP: &[&str] = &["<p>", "<p ", "<h2", "<h3", "<h4", "<li", "<img", "<figure", "<blockquote", "<pre"];
+
+/// Endings of bodies cut short by the feed ("Continue reading", WordPress's `[…]`)
+const TRUNCATION_MARKERS: &[&str] = &["[…]", "[...]", "…", "...", "continue reading", "read more", "lire la suite", "la suite", "weiterlesen"];
+
+/// Items remembered per feed by the seen-items store; past this only the ones still in the
+/// feed are kept
+const MAX_SEEN_ITEMS_PER_FEED: usize = 1000;
+
+/// Hex characters of an item fingerprint
+const FINGERPRINT_LEN: usize = 16;
+
+/// Card the frontend renders for an item
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub enum FeedItemKind {
+    #[default]
+    Article,
+    /// Audio enclosure or audio `media:content`
+    Podcast,
+    /// Video enclosure, video `media:content`, or a link to a video host
+    Video,
+    /// Link-blog item: a URL and no body
+    Link,
+    /// Image with little or no text
+    Photo,
+}
+
+/// Attached media, from `<enclosure>`, Atom `rel="enclosure"` links or `media:content`
+#[derive(Debug, Clone, Serialize)]
+pub struct Enclosure {
+    pub url: String,
+    pub mime_type: Option<String>,
+    /// `medium` of `media:content` (image, audio, video, ...)
+    pub medium: Option<String>,
+    pub length: Option<u64>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct FeedItem {
+    pub title: Option<String>,
+    pub link: Option<String>,
+    pub guid: Option<String>,
+    /// Date as written in the feed
+    pub pub_date: Option<String>,
+    /// `pub_date` in UTC, read in the feed's languages by `fetch_feed`. Items without a
+    /// readable date get the time they were first seen.
+    pub date: Option<ParsedDate>,
+    /// `description` (RSS) or `summary` (Atom)
+    pub summary: Option<String>,
+    /// Richest body of the item (`content:encoded`, Atom `content`, `description`...), scripts
+    /// removed and URLs made absolute by `fetch_feed`
+    pub content: Option<String>,
+    /// Element `content` was taken from
+    pub content_source: Option<String>,
+    /// The item's other bodies, `description`/`summary` left out (see `summary`)
+    pub alternatives: Vec<ContentCandidate>,
+    /// Even the best body looks like a teaser: the article should be fetched for reading
+    pub needs_fetch: bool,
+    pub enclosures: Vec<Enclosure>,
+    pub kind: FeedItemKind,
+    /// Title and summary in the reader's language, for feeds set to always translate
+    pub translation: Option<ItemTranslation>,
+    /// Stable id: the guid when it's unique in the feed, else a fingerprint of the link and title
+    pub id: String,
+    /// Position in the document
+    pub source_order: usize,
+    /// Position in `items`: newest first, ties broken by first-seen order, then document order
+    pub sorted_order: usize,
+    /// Title or body changed since the item was first seen under this id
+    pub updated: bool,
+}
+
+/// A body of a feed item
+#[derive(Debug, Clone, Serialize)]
+pub struct ContentCandidate {
+    /// Element it came from (`content:encoded`, `media:description`, ...)
+    pub source: String,
+    pub html: String,
+    pub words: usize,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Feed {
+    pub title: Option<String>,
+    pub link: Option<String>,
+    pub items: Vec<FeedItem>,
+    /// More items are available beyond this page, at `next_page_url`
+    pub has_more: bool,
+    /// Atom/RSS `<link rel="next">` or JSON Feed `next_url`
+    pub next_page_url: Option<String>,
+    /// Total number of items the feed reports across all pages (`opensearch:totalResults`)
+    pub total_items: Option<u64>,
+    /// Items left out because of `max_items`
+    pub dropped_items: usize,
+}
+
+/// Items of a feed opened as an article that are returned as a preview
+const FEED_PREVIEW_ITEMS: usize = 5;
+
+/// A feed found where an article was expected (its URL pasted into the article view), so the
+/// frontend can offer to subscribe
+#[derive(Debug, Clone, Serialize)]
+pub struct DiscoveredFeed {
+    pub discovered_title: Option<String>,
+    /// Items in the document
+    pub item_count: usize,
+    /// Newest items, at most `FEED_PREVIEW_ITEMS`, bodies cleaned as by `fetch_feed`
+    pub preview: Vec<FeedItem>,
+}
+
+/// Item elements whose text is read
+const FIELD_NAMES: &[&str] = &[
+    "title", "link", "guid", "id", "pubdate", "published", "updated", "dc:date", "description", "summary", "content:encoded", "content",
+];
+
+/// Qualified element name, lowercased (`title`, `content:encoded`, `media:content`)
+fn element_name(e: &BytesStart) -> String {
+    String::from_utf8_lossy(e.name().as_ref()).to_ascii_lowercase()
+}
+
+/// Root elements of RSS 2.0, Atom and RSS 1.0 (`rdf:RDF`), whatever their namespace prefix
+fn is_feed_root(name: &str) -> bool {
+    matches!(name.rsplit(':').next(), Some("rss" | "feed" | "rdf"))
+}
+
+fn attribute(e: &BytesStart, name: &str) -> Option<String> {
+    e.attributes()
+        .flatten()
+        .find(|attr| attr.key.as_ref().eq_ignore_ascii_case(name.as_bytes()))
+        .and_then(|attr| attr.unescape_value().ok().map(|value| value.trim().to_string()))
+        .filter(|value| !value.is_empty())
+}
+
+fn resolve_reference(reference: &BytesRef) -> String {
+    if let Ok(Some(c)) = reference.resolve_char_ref() {
+        return c.to_string();
+    }
+    let name = reference.decode().map(|name| name.into_owned()).unwrap_or_default();
+    match quick_xml::escape::resolve_predefined_entity(&name) {
+        Some(resolved) => resolved.to_string(),
+        // HTML entities (`&nbsp;`, ...) show up in feeds even though XML doesn't define them
+        None => unescape_html(&format!("&{};", name)),
+    }
+}
+
+/// Text of the element being read. Atom `type="xhtml"` content keeps its markup.
+struct Capture {
+    depth: usize,
+    xhtml: bool,
+    text: String,
+}
+
+/// Unix time (ms) of an item's date, whatever its format (see `dates::parse_date`)
+fn item_timestamp(item: &FeedItem) -> Option<i64> {
+    item.date.as_ref().map(|date| date.timestamp).or_else(|| timestamp_of_date(item.pub_date.as_deref()?))
+}
+
+/// Whether the items kept so far run newest first, so later items can be skipped unread once
+/// the limit is reached. Undated items are taken to be in feed order, newest first.
+fn newest_first(items: &[FeedItem]) -> bool {
+    let dates: Vec<i64> = items.iter().filter_map(item_timestamp).collect();
+    dates.windows(2).all(|pair| pair[0] >= pair[1])
+}
+
+/// Adds a finished item, keeping at most `max_items`: past the limit, an item only replaces
+/// the oldest dated item kept when it's newer (feeds listing their oldest items first).
+/// Items keep their document order.
+fn push_item(feed: &mut Feed, item: FeedItem, max_items: Option<usize>) {
+    if max_items.is_none_or(|max| feed.items.len() < max) {
+        feed.items.push(item);
+        return;
+    }
+    feed.dropped_items += 1;
+    let Some(timestamp) = item_timestamp(&item) else {
+        return;
+    };
+    let oldest = feed.items.iter().enumerate().filter_map(|(index, kept)| Some((index, item_timestamp(kept)?))).min_by_key(|(_, kept)| *kept);
+    if let Some((index, _)) = oldest.filter(|(_, oldest)| *oldest < timestamp) {
+        feed.items.remove(index);
+        feed.items.push(item);
+    }
+}
+
+/// Whether `text` is an RSS, RSS 1.0 or Atom document, from its root element. The BOM, XML
+/// declaration, comments and doctype before it are skipped; HTML pages stop at `<html>`.
+pub fn sniff_feed_root(text: &str) -> bool {
+    let mut reader = Reader::from_str(text.trim_start_matches('\u{feff}'));
+    loop {
+        match reader.read_event() {
+            Ok(Event::Start(ref e) | Event::Empty(ref e)) => return is_feed_root(&element_name(e)),
+            Ok(Event::Decl(_) | Event::PI(_) | Event::Comment(_) | Event::DocType(_)) => {}
+            Ok(Event::Text(ref text)) if text.iter().all(u8::is_ascii_whitespace) => {}
+            _ => return false,
+        }
+    }
+}
+
+/// Title, item count and preview of a feed opened as an article, fetched from `url`
+pub fn discover_feed(text: &str, url: &Url) -> Result<DiscoveredFeed, String> {
+    let mut feed = parse_feed(text, Some(FEED_PREVIEW_ITEMS))?;
+    let site = feed.link.as_deref().and_then(|link| url.join(link).ok()).unwrap_or_else(|| url.clone());
+    for item in feed.items.iter_mut() {
+        for body in item.content.iter_mut().chain(item.summary.iter_mut()).chain(item.alternatives.iter_mut().map(|candidate| &mut candidate.html)) {
+            *body = clean_embedded_html(body, &site);
+        }
+    }
+    assign_item_ids(&mut feed);
+    Ok(DiscoveredFeed { discovered_title: feed.title, item_count: feed.items.len() + feed.dropped_items, preview: feed.items })
+}
+
+/// Parses an RSS 2.0, RSS 1.0 (RDF), Atom or JSON Feed document.
+///
+/// With `max_items`, at most that many items are kept, the newest by date (or the first
+/// ones when undated); the others are dropped, not paginated, and counted in
+/// `dropped_items`. The channel's own fields are always read in full.
+pub fn parse_feed(text: &str, max_items: Option<usize>) -> Result<Feed, String> {
+    let text = text.trim_start_matches('\u{feff}');
+    if text.trim_start().starts_with('{') {
+        return parse_json_feed(text, max_items);
+    }
+
+    let mut reader = Reader::from_str(text);
+    reader.config_mut().check_end_names = false;
+
+    let mut feed = Feed::default();
+    let mut root_seen = false;
+    let mut stack: Vec<String> = Vec::new();
+    let mut item: Option<(usize, FeedItem)> = None;
+    let mut capture: Option<Capture> = None;
+
+    loop {
+        let event = reader.read_event().map_err(|e| format!("Invalid feed XML: {}", e))?;
+        match event {
+            Event::Start(ref e) | Event::Empty(ref e) => {
+                let empty = matches!(event, Event::Empty(_));
+                let name = element_name(e);
+
+                if !root_seen {
+                    root_seen = true;
+                    if !is_feed_root(&name) {
+                        return Err(format!("Not a feed: root element is <{}>", name));
+                    }
+                }
+
+                if let Some(capture) = capture.as_mut() {
+                    if capture.xhtml {
+                        capture.text.push('<');
+                        capture.text.push_str(&String::from_utf8_lossy(e));
+                        capture.text.push_str(if empty { "/>" } else { ">" });
+                    }
+                    if !empty {
+                        stack.push(name);
+                    }
+                    continue;
+                }
+
+                let depth = stack.len();
+                let parent = stack.last().map(String::as_str).unwrap_or("");
+                let mut capturable = false;
+
+                if matches!(name.as_str(), "item" | "entry") && !empty {
+                    // Past the limit of a newest-first feed, the rest of the items are older:
+                    // skip them without reading their fields
+                    let full = max_items.is_some_and(|max| feed.items.len() >= max);
+                    if full && newest_first(&feed.items) {
+                        feed.dropped_items += 1;
+                    } else {
+                        item = Some((depth, FeedItem::default()));
+                    }
+                } else if let Some((item_depth, current)) = item.as_mut() {
+                    let direct_child = depth == *item_depth + 1;
+                    capturable = (direct_child && (FIELD_NAMES.contains(&name.as_str()) || is_extra_body(&name))) || name == "media:description";
+                    match name.as_str() {
+                        // `media:content` may be nested in a `media:group`
+                        "enclosure" | "media:content" => {
+                            if let Some(url) = attribute(e, "url") {
+                                current.enclosures.push(Enclosure {
+                                    url,
+                                    mime_type: attribute(e, "type"),
+                                    medium: attribute(e, "medium"),
+                                    length: attribute(e, "length").or_else(|| attribute(e, "filesize")).and_then(|l| l.parse().ok()),
+                                });
+                            }
+                        }
+                        "link" if direct_child => {
+                            if let Some(href) = attribute(e, "href") {
+                                match attribute(e, "rel").as_deref() {
+                                    None | Some("alternate") => {
+                                        current.link.get_or_insert(href);
+                                    }
+                                    Some("enclosure") => current.enclosures.push(Enclosure {
+                                        url: href,
+                                        mime_type: attribute(e, "type"),
+                                        medium: None,
+                                        length: attribute(e, "length").and_then(|l| l.parse().ok()),
+                                    }),
+                                    _ => {}
+                                }
+                            }
+                        }
+                        _ => {}
+                    }
+                } else if matches!(parent, "channel" | "feed") {
+                    capturable = matches!(name.as_str(), "title" | "link" | "opensearch:totalresults");
+                    if matches!(name.as_str(), "link" | "atom:link") {
+                        if let Some(href) = attribute(e, "href") {
+                            match attribute(e, "rel").as_deref() {
+                                None | Some("alternate") if name == "link" => {
+                                    feed.link.get_or_insert(href);
+                                }
+                                Some("next") => feed.next_page_url = Some(href),
+                                _ => {}
+                            }
+                        }
+                    }
+                }
+
+                if !empty {
+                    if capturable {
+                        let xhtml = attribute(e, "type").as_deref() == Some("xhtml");
+                        capture = Some(Capture { depth, xhtml, text: String::new() });
+                    }
+                    stack.push(name);
+                }
+            }
+            Event::End(_) => {
+                let Some(name) = stack.pop() else { continue };
+                let depth = stack.len();
+
+                if capture.as_ref().is_some_and(|c| c.depth == depth) {
+                    let Capture { text, .. } = capture.take().unwrap();
+                    let text = text.trim().to_string();
+                    if !text.is_empty() {
+                        store_field(&mut feed, item.as_mut(), &name, text);
+                    }
+                } else if let Some(capture) = capture.as_mut() {
+                    if capture.xhtml {
+                        capture.text.push_str(&format!("</{}>", name));
+                    }
+                }
+
+                if item.as_ref().is_some_and(|(item_depth, _)| *item_depth == depth) {
+                    let (_, mut finished) = item.take().unwrap();
+                    select_content(&mut finished);
+                    push_item(&mut feed, finished, max_items);
+                }
+            }
+            Event::Text(e) => {
+                if let Some(capture) = capture.as_mut() {
+                    capture.text.push_str(&e.decode().map_err(|e| e.to_string())?);
+                }
+            }
+            Event::CData(e) => {
+                if let Some(capture) = capture.as_mut() {
+                    capture.text.push_str(&e.decode().map_err(|e| e.to_string())?);
+                }
+            }
+            Event::GeneralRef(e) => {
+                if let Some(capture) = capture.as_mut() {
+                    if capture.xhtml {
+                        capture.text.push_str(&format!("&{};", String::from_utf8_lossy(&e)));
+                    } else {
+                        capture.text.push_str(&resolve_reference(&e));
+                    }
+                }
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+
+    if !root_seen {
+        if text.trim().is_empty() {
+            return Err("Not a feed: empty document".into());
+        }
+        // Plain text, or markup quick-xml couldn't read as an element
+        return Err("Not a feed: the document is not XML".into());
+    }
+    feed.has_more = feed.next_page_url.is_some();
+    Ok(feed)
+}
+
+#[derive(Deserialize)]
+struct JsonFeed {
+    title: Option<String>,
+    home_page_url: Option<String>,
+    next_url: Option<String>,
+    #[serde(default)]
+    items: Vec<JsonFeedItem>,
+}
+
+#[derive(Deserialize)]
+struct JsonFeedItem {
+    id: Option<serde_json::Value>,
+    url: Option<String>,
+    external_url: Option<String>,
+    title: Option<String>,
+    content_html: Option<String>,
+    content_text: Option<String>,
+    summary: Option<String>,
+    date_published: Option<String>,
+    date_modified: Option<String>,
+    #[serde(default)]
+    attachments: Vec<JsonFeedAttachment>,
+}
+
+#[derive(Deserialize)]
+struct JsonFeedAttachment {
+    url: String,
+    mime_type: Option<String>,
+    size_in_bytes: Option<u64>,
+}
+
+/// Parses a JSON Feed (1.0 or 1.1) document
+fn parse_json_feed(json: &str, max_items: Option<usize>) -> Result<Feed, String> {
+    let json_feed: JsonFeed = serde_json::from_str(json).map_err(|e| format!("Invalid JSON Feed: {}", e))?;
+
+    let mut feed = Feed {
+        title: json_feed.title,
+        link: json_feed.home_page_url,
+        has_more: json_feed.next_url.is_some(),
+        next_page_url: json_feed.next_url,
+        ..Feed::default()
+    };
+    let items = json_feed
+        .items
+        .into_iter()
+        .map(|item| {
+            let mut item = FeedItem {
+                title: item.title,
+                link: item.url.or(item.external_url),
+                // ids are strings in 1.1, but numbers show up in the wild
+                guid: item.id.map(|id| id.as_str().map(str::to_string).unwrap_or_else(|| id.to_string())),
+                pub_date: item.date_published.or(item.date_modified),
+                summary: item.summary.clone(),
+                alternatives: [
+                    ("content_html", item.content_html),
+                    ("content_text", item.content_text.map(|text| escape_html(&text))),
+                    ("summary", item.summary),
+                ]
+                .into_iter()
+                .filter_map(|(source, html)| Some(ContentCandidate { source: source.to_string(), html: html?, words: 0 }))
+                .collect(),
+                enclosures: item
+                    .attachments
+                    .into_iter()
+                    .map(|attachment| Enclosure {
+                        url: attachment.url,
+                        mime_type: attachment.mime_type,
+                        medium: None,
+                        length: attachment.size_in_bytes,
+                    })
+                    .collect(),
+                ..FeedItem::default()
+            };
+            select_content(&mut item);
+            item
+        });
+    for item in items {
+        push_item(&mut feed, item, max_items);
+    }
+    Ok(feed)
+}
+
+/// Stores the text of a finished element on the item being read, or on the feed itself
+fn store_field(feed: &mut Feed, item: Option<&mut (usize, FeedItem)>, name: &str, text: String) {
+    if let Some((_, current)) = item {
+        match name {
+            "title" => current.title = Some(text),
+            "link" => {
+                current.link.get_or_insert(text);
+            }
+            "guid" | "id" => current.guid = Some(text),
+            "pubdate" | "published" | "dc:date" => current.pub_date = Some(text),
+            "updated" => {
+                current.pub_date.get_or_insert(text);
+            }
+            "description" | "summary" => {
+                current.summary = Some(text.clone());
+                current.alternatives.push(ContentCandidate { source: name.to_string(), html: text, words: 0 });
+            }
+            "content:encoded" | "content" | "media:description" => {
+                current.alternatives.push(ContentCandidate { source: name.to_string(), html: text, words: 0 })
+            }
+            _ if is_extra_body(name) => current.alternatives.push(ContentCandidate { source: name.to_string(), html: text, words: 0 }),
+            _ => {}
+        }
+        return;
+    }
+
+    match name {
+        "title" => feed.title = Some(text),
+        "link" => {
+            feed.link.get_or_insert(text);
+        }
+        "opensearch:totalresults" => feed.total_items = text.parse().ok(),
+        _ => {}
+    }
+}
+
+fn is_video_host(link: &str) -> bool {
+    let Some(host) = Url::parse(link).ok().and_then(|url| url.host_str().map(str::to_ascii_lowercase)) else {
+        return false;
+    };
+    VIDEO_HOSTS.iter().any(|video_host| host == *video_host || host.ends_with(&format!(".{}", video_host)))
+}
+
+/// Words of text in an item body, markup excluded
+fn body_words(html: Option<&str>) -> usize {
+    html.map(|html| {
+        let fragment = scraper::Html::parse_fragment(html);
+        fragment.root_element().text().map(count_words).sum()
+    })
+    .unwrap_or(0)
+}
+
+/// Media type of an enclosure: its `medium`, or the top-level type of its MIME type
+fn enclosure_medium(enclosure: &Enclosure) -> Option<String> {
+    enclosure
+        .medium
+        .clone()
+        .or_else(|| enclosure.mime_type.as_deref().and_then(|mime| mime.split('/').next()).map(str::to_string))
+        .map(|medium| medium.to_ascii_lowercase())
+}
+
+/// Classifies an item from its media, link host and amount of text. Ambiguous items are articles.
+pub fn classify_item(item: &FeedItem) -> FeedItemKind {
+    let media: Vec<String> = item.enclosures.iter().filter_map(enclosure_medium).collect();
+    let has = |medium: &str| media.iter().any(|m| m == medium);
+
+    if has("audio") {
+        return FeedItemKind::Podcast;
+    }
+    if has("video") || item.link.as_deref().is_some_and(is_video_host) {
+        return FeedItemKind::Video;
+    }
+
+    let words = body_words(item.content.as_deref()).max(body_words(item.summary.as_deref()));
+    let body_has_image = [&item.content, &item.summary]
+        .iter()
+        .any(|body| body.as_deref().is_some_and(|html| html.contains("<img")));
+
+    if (has("image") || body_has_image) && words <= PHOTO_MAX_WORDS {
+        return FeedItemKind::Photo;
+    }
+    if words == 0 && item.link.is_some() {
+        return FeedItemKind::Link;
+    }
+    FeedItemKind::Article
+}
+
+/// Bodies read from a non-standard element: `*:encoded`, `fulltext`, `*:body`, `*:content`
+fn is_extra_body(name: &str) -> bool {
+    let local = name.rsplit(':').next().unwrap_or(name);
+    let prefixed = name.contains(':') && !name.starts_with("media:");
+    matches!(local, "fulltext" | "full-text" | "full_text") || (prefixed && matches!(local, "encoded" | "body" | "content"))
+}
+
+fn looks_truncated(html: &str) -> bool {
+    let text = scraper::Html::parse_fragment(html).root_element().text().collect::<String>().to_lowercase();
+    let text = text.trim_end();
+    let ending: String = text.chars().rev().take(40).collect::<Vec<_>>().into_iter().rev().collect();
+    TRUNCATION_MARKERS.iter().any(|marker| ending.ends_with(marker) || (marker.len() > 5 && ending.contains(marker)))
+}
+
+/// How complete a body looks: its words, plus its paragraphs, headings and images, halved
+/// when it ends like a teaser
+fn body_score(candidate: &ContentCandidate) -> usize {
+    let lower = candidate.html.to_ascii_lowercase();
+    let blocks: usize = RICH_MARKUP.iter().map(|tag| lower.matches(tag).count()).sum();
+    let score = candidate.words + blocks * BLOCK_SCORE;
+    if looks_truncated(&candidate.html) {
+        score / 2
+    } else {
+        score
+    }
+}
+
+/// Picks the richest of the item's bodies as `content`, keeps the others as `alternatives`,
+/// classifies the item and flags teasers with `needs_fetch`
+fn select_content(item: &mut FeedItem) {
+    let mut candidates = std::mem::take(&mut item.alternatives);
+    candidates.retain(|candidate| !candidate.html.trim().is_empty());
+    for candidate in candidates.iter_mut() {
+        candidate.words = body_words(Some(&candidate.html));
+    }
+    // Earlier candidates win ties; full-content elements come before summaries in most feeds
+    let best = candidates.iter().enumerate().map(|(index, candidate)| (body_score(candidate), std::cmp::Reverse(index))).max().map(|(_, index)| index.0);
+    if let Some(best) = best {
+        let chosen = candidates.remove(best);
+        candidates.retain(|candidate| candidate.html != chosen.html && !matches!(candidate.source.as_str(), "description" | "summary"));
+        item.content_source = Some(chosen.source);
+        item.content = Some(chosen.html);
+    }
+    item.alternatives = candidates;
+    item.kind = classify_item(item);
+
+    let teaser = item.content.as_deref().is_none_or(|content| body_words(Some(content)) < NEEDS_FETCH_WORDS || looks_truncated(content));
+    item.needs_fetch = item.kind == FeedItemKind::Article && item.link.is_some() && teaser;
+}
+
+struct SeenItem {
+    /// Position in first-seen order across all feeds, higher is more recent
+    seq: u64,
+    /// Hash of the title and bodies the item was last seen with
+    content: String,
+    updated: bool,
+}
+
+/// Items seen per feed URL, by id, for ordering and spotting republished items
+#[derive(Default)]
+pub struct SeenItemStore {
+    next_seq: u64,
+    feeds: HashMap<String, HashMap<String, SeenItem>>,
+}
+
+fn fingerprint(parts: &[Option<&str>]) -> String {
+    let mut hasher = Sha256::new();
+    for part in parts {
+        hasher.update(part.unwrap_or_default().trim().as_bytes());
+        hasher.update([0]);
+    }
+    hasher.finalize().iter().map(|byte| format!("{:02x}", byte)).collect::<String>()[..FINGERPRINT_LEN].to_string()
+}
+
+/// Gives every item a stable id. Guids are kept when present once in the feed and not the
+/// feed's own link (some generators put the same guid on every item); other items get a
+/// fingerprint of their link and title. Items left sharing an id (exact duplicates) get
+/// `-2`, `-3`... in document order.
+pub fn assign_item_ids(feed: &mut Feed) {
+    let mut guid_counts: HashMap<String, usize> = HashMap::new();
+    for guid in feed.items.iter().filter_map(|item| item.guid.as_deref()).map(str::trim).filter(|guid| !guid.is_empty()) {
+        *guid_counts.entry(guid.to_string()).or_default() += 1;
+    }
+    let feed_link = feed.link.as_deref().map(str::trim);
+
+    let mut taken = HashSet::new();
+    for (index, item) in feed.items.iter_mut().enumerate() {
+        item.source_order = index;
+        let guid = item.guid.as_deref().map(str::trim).filter(|guid| guid_counts.get(*guid) == Some(&1) && Some(*guid) != feed_link);
+        let base = match guid {
+            Some(guid) => guid.to_string(),
+            None => fingerprint(&[item.link.as_deref(), item.title.as_deref()]),
+        };
+        let mut id = base.clone();
+        let mut n = 1;
+        while !taken.insert(id.clone()) {
+            n += 1;
+            id = format!("{}-{}", base, n);
+        }
+        item.id = id;
+    }
+}
+
+/// Records the items of a fetch in the seen-items store, marks those whose content changed
+/// since, and sorts them by date, newest first. Ties go to the item seen most recently, then
+/// to document order; undated items come last.
+fn order_items(feed_url: &str, items: &mut Vec<FeedItem>, state: &ProxyState) {
+    let mut store = state.seen_items.lock().unwrap();
+    let store = &mut *store;
+    let seen = store.feeds.entry(feed_url.to_string()).or_default();
+
+    // New items of a fetch are numbered last to first, so document order is first-seen order
+    let mut seqs = Vec::with_capacity(items.len());
+    for item in items.iter_mut().rev() {
+        let bodies: Vec<Option<&str>> = [item.title.as_deref(), item.content.as_deref(), item.summary.as_deref()].into();
+        let content = fingerprint(&bodies);
+        let entry = seen.entry(item.id.clone()).or_insert_with(|| {
+            store.next_seq += 1;
+            SeenItem { seq: store.next_seq, content: content.clone(), updated: false }
+        });
+        if entry.content != content {
+            entry.content = content;
+            entry.updated = true;
+        }
+        item.updated = entry.updated;
+        seqs.push(entry.seq);
+    }
+    seqs.reverse();
+    if seen.len() > MAX_SEEN_ITEMS_PER_FEED {
+        let current: HashSet<&str> = items.iter().map(|item| item.id.as_str()).collect();
+        seen.retain(|id, _| current.contains(id.as_str()));
+    }
+
+    let mut keyed: Vec<(Option<i64>, u64, FeedItem)> = std::mem::take(items).into_iter().zip(seqs).map(|(item, seq)| (item_timestamp(&item), seq, item)).collect();
+    keyed.sort_by(|(a_date, a_seq, a), (b_date, b_seq, b)| {
+        b_date.cmp(a_date).then(b_seq.cmp(a_seq)).then(a.source_order.cmp(&b.source_order))
+    });
+    items.extend(keyed.into_iter().map(|(_, _, item)| item));
+    for (index, item) in items.iter_mut().enumerate() {
+        item.sorted_order = index;
+    }
+}
+
+/// Forgets the items seen on feeds of `domain` (subdomains included)
+pub fn clear_seen_items_for_domain(domain: &str, dry_run: bool, state: &ProxyState) -> MutationReport {
+    let mut report = MutationReport::new(dry_run);
+    let host = host_of_domain_key(domain);
+    let on_domain = |feed: &str| Url::parse(feed).ok().and_then(|url| url.host_str().map(|feed_host| host_in_domain(feed_host, &host))).unwrap_or(false);
+    let mut store = state.seen_items.lock().unwrap();
+    let mut feeds: Vec<String> = store.feeds.keys().filter(|feed| on_domain(feed)).cloned().collect();
+    feeds.sort();
+    for feed in feeds {
+        report.record("seen_items", feed.clone(), None);
+        if !dry_run {
+            store.feeds.remove(&feed);
+        }
+    }
+    report
+}
+
+/// Fetches `url` through the shared fetch layer (cookies, auth, body limit) and parses it as a
+/// feed, keeping at most `max_items` items (see `parse_feed`)
+pub async fn logic_fetch_feed(url: String, max_items: Option<usize>, state: &ProxyState) -> Result<Feed, String> {
+    let text = logic_fetch_raw_html(url.clone(), None, None, state).await?;
+    let mut feed = parse_feed(&text, max_items)?;
+
+    // Localized dates ("5 mai 2024", "il y a 3 heures") are read in the languages asked of the site
+    let now = dates::now_millis();
+    let accept_language = Url::parse(&url).map_or_else(|_| DEFAULT_ARTICLE_ACCEPT_LANGUAGE.to_string(), |feed_url| {
+        accept_language_for(&feed_url, state, DEFAULT_ARTICLE_ACCEPT_LANGUAGE)
+    });
+    let languages = dates::languages_of(&accept_language);
+    for item in feed.items.iter_mut() {
+        item.date = item.pub_date.as_deref().and_then(|date| dates::parse_date(date, now, &languages));
+        if item.date.is_none() {
+            if let Some(key) = unread::item_id(item.guid.as_ref(), item.link.as_ref(), item.title.as_ref()) {
+                item.date = Some(first_seen(&url, key, item.pub_date.as_deref().unwrap_or(""), now, state));
+            }
+        }
+    }
+
+    // `rel="next"` links may be relative to the feed
+    if let (Some(next), Ok(base)) = (feed.next_page_url.as_mut(), Url::parse(&url)) {
+        if let Ok(absolute) = base.join(next) {
+            *next = absolute.to_string();
+        }
+    }
+
+    // Item bodies are shown as is: scripts and handlers go, URLs resolve against the site
+    if let Ok(feed_url) = Url::parse(&url) {
+        let site = feed.link.as_deref().and_then(|link| feed_url.join(link).ok()).unwrap_or(feed_url);
+        for item in feed.items.iter_mut() {
+            for body in item.content.iter_mut().chain(item.summary.iter_mut()).chain(item.alternatives.iter_mut().map(|candidate| &mut candidate.html)) {
+                *body = clean_embedded_html(body, &site);
+            }
+        }
+    }
+    assign_item_ids(&mut feed);
+    order_items(&url, &mut feed.items, state);
+    unread::record_feed_items(&url, feed.items.iter().filter_map(|item| unread::item_id(item.guid.as_ref(), item.link.as_ref(), item.title.as_ref())), state);
+    translate_feed_items(&url, &mut feed.items, state).await;
+    println!("[feed::fetch_feed] {} items in {} ({} dropped)", feed.items.len(), url, feed.dropped_items);
+    Ok(feed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FEED_URL: &str = "https://blog.example.com/feed";
+
+    fn rss(items: &[&str]) -> String {
+        format!(
+            "<?xml version=\"1.0\"?><rss version=\"2.0\"><channel><title>Blog</title><link>https://blog.example.com/</link>{}</channel></rss>",
+            items.concat()
+        )
+    }
+
+    fn item(guid: Option<&str>, title: &str, date: Option<&str>, body: &str) -> String {
+        let guid = guid.map(|guid| format!("<guid>{}</guid>", guid)).unwrap_or_default();
+        let date = date.map(|date| format!("<pubDate>{}</pubDate>", date)).unwrap_or_default();
+        format!("<item>{}<title>{}</title><link>https://blog.example.com/{}</link>{}<description>{}</description></item>", guid, title, title, date, body)
+    }
+
+    /// What `logic_fetch_feed` does after the download: parse, assign ids, order
+    fn fetch(xml: &str, state: &ProxyState) -> Vec<FeedItem> {
+        let mut feed = parse_feed(xml, None).unwrap();
+        assign_item_ids(&mut feed);
+        order_items(FEED_URL, &mut feed.items, state);
+        feed.items
+    }
+
+    fn titles(items: &[FeedItem]) -> Vec<&str> {
+        items.iter().map(|item| item.title.as_deref().unwrap_or("")).collect()
+    }
+
+    const WORDPRESS_FEED: &str = include_str!("../tests/fixtures/feeds/wordpress.xml");
+    const GHOST_FEED: &str = include_str!("../tests/fixtures/feeds/ghost.xml");
+    const BLOGGER_FEED: &str = include_str!("../tests/fixtures/feeds/blogger.xml");
+
+    /// Body each item of a feed got, the sources of its alternatives, and whether it's a teaser
+    fn selections(feed: &Feed) -> Vec<(&str, Vec<&str>, bool)> {
+        feed.items
+            .iter()
+            .map(|item| {
+                let alternatives = item.alternatives.iter().map(|candidate| candidate.source.as_str()).collect();
+                (item.content_source.as_deref().unwrap_or(""), alternatives, item.needs_fetch)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn guidless_items_get_stable_fingerprints() {
+        let xml = rss(&[item(None, "first", None, "a").as_str(), item(None, "second", None, "b").as_str()]);
+        let state = ProxyState::default();
+        let ids: Vec<String> = fetch(&xml, &state).into_iter().map(|item| item.id).collect();
+        let again: Vec<String> = fetch(&xml, &ProxyState::default()).into_iter().map(|item| item.id).collect();
+        assert_eq!(ids, again);
+        assert!(ids.iter().all(|id| id.len() == FINGERPRINT_LEN));
+        assert_ne!(ids[0], ids[1]);
+
+        // The body isn't part of the id: an edited item keeps it
+        let edited = rss(&[item(None, "first", None, "a, edited").as_str(), item(None, "second", None, "b").as_str()]);
+        let edited: Vec<String> = fetch(&edited, &state).into_iter().map(|item| item.id).collect();
+        assert_eq!(edited, ids);
+    }
+
+    #[test]
+    fn duplicate_guids_fall_back_to_fingerprints() {
+        let xml = rss(&[
+            item(Some("same"), "one", None, "a").as_str(),
+            item(Some("same"), "two", None, "b").as_str(),
+            item(Some("https://blog.example.com/"), "three", None, "c").as_str(),
+            item(Some("unique"), "four", None, "d").as_str(),
+            item(Some("  "), "five", None, "e").as_str(),
+        ]);
+        let mut feed = parse_feed(&xml, None).unwrap();
+        assign_item_ids(&mut feed);
+        let ids: Vec<&str> = feed.items.iter().map(|item| item.id.as_str()).collect();
+        assert_eq!(ids[0], fingerprint(&[Some("https://blog.example.com/one"), Some("one")]));
+        assert_eq!(ids[1], fingerprint(&[Some("https://blog.example.com/two"), Some("two")]));
+        // The feed's own link used as every item's guid
+        assert_eq!(ids[2], fingerprint(&[Some("https://blog.example.com/three"), Some("three")]));
+        assert_eq!(ids[3], "unique");
+        assert_eq!(ids[4].len(), FINGERPRINT_LEN);
+        assert_eq!(feed.items.iter().map(|item| item.source_order).collect::<Vec<_>>(), vec![0, 1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn exact_duplicates_are_numbered() {
+        let duplicate = item(None, "same", None, "a");
+        let xml = rss(&[duplicate.as_str(), duplicate.as_str(), duplicate.as_str()]);
+        let mut feed = parse_feed(&xml, None).unwrap();
+        assign_item_ids(&mut feed);
+        let base = feed.items[0].id.clone();
+        assert_eq!(feed.items[1].id, format!("{}-2", base));
+        assert_eq!(feed.items[2].id, format!("{}-3", base));
+    }
+
+    #[test]
+    fn items_sort_newest_first_with_undated_last() {
+        let xml = rss(&[
+            item(Some("a"), "undated-1", None, "a").as_str(),
+            item(Some("b"), "old", Some("Mon, 01 Jan 2024 10:00:00 GMT"), "b").as_str(),
+            item(Some("c"), "new", Some("2024-03-01T10:00:00Z"), "c").as_str(),
+            item(Some("d"), "undated-2", None, "d").as_str(),
+            item(Some("e"), "tie", Some("Fri, 01 Mar 2024 11:00:00 +0100"), "e").as_str(),
+        ]);
+        let items = fetch(&xml, &ProxyState::default());
+        // "new" and "tie" are the same instant: document order breaks the tie on a first fetch
+        assert_eq!(titles(&items), ["new", "tie", "old", "undated-1", "undated-2"]);
+        assert_eq!(items.iter().map(|item| item.sorted_order).collect::<Vec<_>>(), vec![0, 1, 2, 3, 4]);
+        assert_eq!(items.iter().map(|item| item.source_order).collect::<Vec<_>>(), vec![2, 4, 1, 0, 3]);
+    }
+
+    #[test]
+    fn undated_items_keep_first_seen_order_across_fetches() {
+        let state = ProxyState::default();
+        let first = rss(&[item(Some("b"), "b", None, "b").as_str(), item(Some("a"), "a", None, "a").as_str()]);
+        assert_eq!(titles(&fetch(&first, &state)), ["b", "a"]);
+
+        // A new item at the top is the most recently seen; a reshuffle doesn't move older items
+        let second = rss(&[item(Some("c"), "c", None, "c").as_str(), item(Some("a"), "a", None, "a").as_str(), item(Some("b"), "b", None, "b").as_str()]);
+        assert_eq!(titles(&fetch(&second, &state)), ["c", "b", "a"]);
+    }
+
+    #[test]
+    fn changed_items_are_marked_updated() {
+        let state = ProxyState::default();
+        let first = rss(&[item(Some("a"), "a", None, "original").as_str(), item(Some("b"), "b", None, "b").as_str()]);
+        assert!(fetch(&first, &state).iter().all(|item| !item.updated));
+
+        let edited = rss(&[item(Some("a"), "a", None, "edited").as_str(), item(Some("b"), "b", None, "b").as_str()]);
+        let items = fetch(&edited, &state);
+        assert_eq!(items.iter().map(|item| (item.id.as_str(), item.updated)).collect::<Vec<_>>(), [("a", true), ("b", false)]);
+        // Still updated on the next fetch, even without a further change
+        assert!(fetch(&edited, &state)[0].updated);
+
+        let report = clear_seen_items_for_domain("example.com", false, &state);
+        assert_eq!(report.count, 1);
+        assert!(fetch(&edited, &state).iter().all(|item| !item.updated));
+    }
+
+    #[test]
+    fn max_items_keeps_the_newest_of_an_oldest_first_feed() {
+        let xml = rss(&[
+            item(Some("1"), "jan", Some("2024-01-01"), "a").as_str(),
+            item(Some("2"), "feb", Some("2024-02-01"), "b").as_str(),
+            item(Some("3"), "undated", None, "c").as_str(),
+            item(Some("4"), "mar", Some("2024-03-01"), "d").as_str(),
+        ]);
+        let feed = parse_feed(&xml, Some(2)).unwrap();
+        // Document order is kept; the undated item can't replace a dated one
+        assert_eq!(titles(&feed.items), ["feb", "mar"]);
+        assert_eq!(feed.dropped_items, 2);
+    }
+
+    #[test]
+    fn newest_first_feed_skips_the_rest_unread() {
+        let xml = rss(&[
+            item(Some("3"), "mar", Some("2024-03-01"), "a").as_str(),
+            item(Some("2"), "feb", Some("2024-02-01"), "b").as_str(),
+            item(Some("1"), "jan", Some("2024-01-01"), "c").as_str(),
+        ]);
+        let feed = parse_feed(&xml, Some(2)).unwrap();
+        assert_eq!(titles(&feed.items), ["mar", "feb"]);
+        assert_eq!(feed.dropped_items, 1);
+    }
+
+    #[test]
+    fn wordpress_full_posts_use_content_encoded_and_excerpts_need_a_fetch() {
+        let feed = parse_feed(WORDPRESS_FEED, None).unwrap();
+        assert_eq!(selections(&feed), [("content:encoded", vec![], false), ("description", vec![], true)]);
+
+        // The excerpt stays the summary; the "appeared first on" footer doesn't make it the body
+        let post = &feed.items[0];
+        assert!(post.content.as_deref().unwrap().contains("<h2 class=\"wp-block-heading\">The lid</h2>"));
+        assert!(post.summary.as_deref().unwrap().starts_with("<p>Two sash windows"));
+        assert!(feed.items.iter().all(|item| item.kind == FeedItemKind::Article));
+    }
+
+    #[test]
+    fn ghost_posts_use_content_encoded_and_member_previews_need_a_fetch() {
+        let feed = parse_feed(GHOST_FEED, None).unwrap();
+        assert_eq!(selections(&feed), [("content:encoded", vec![], false), ("content:encoded", vec![], true)]);
+
+        // The feature image is an enclosure, but both posts have enough text to stay articles
+        assert!(feed.items.iter().all(|item| item.enclosures.len() == 1 && item.kind == FeedItemKind::Article));
+    }
+
+    #[test]
+    fn blogger_entries_use_content_and_summary_only_entries_need_a_fetch() {
+        let feed = parse_feed(BLOGGER_FEED, None).unwrap();
+        assert_eq!(selections(&feed), [("content", vec![], false), ("summary", vec![], true)]);
+
+        // Escaped `type="html"` content is read as markup
+        assert!(feed.items[0].content.as_deref().unwrap().starts_with("<p>The quarry line"));
+        assert_eq!(feed.items[0].link.as_deref(), Some("https://branchlinedays.blogspot.com/2024/06/a-wet-sunday-on-quarry-line.html"));
+        assert_eq!(feed.link.as_deref(), Some("https://branchlinedays.blogspot.com/"));
+        assert_eq!(feed.total_items, Some(212));
+        assert!(feed.has_more);
+    }
+
+    #[tokio::test]
+    async fn fetched_bodies_lose_scripts_and_resolve_against_the_site() {
+        let addr = crate::test_support::serve_html(&[("/wordpress", WORDPRESS_FEED.to_string()), ("/blogger", BLOGGER_FEED.to_string())]).await;
+        let state = ProxyState::default();
+
+        let wordpress = logic_fetch_feed(format!("http://{}/wordpress", addr), None, &state).await.unwrap();
+        let content = wordpress.items[0].content.as_deref().unwrap();
+        assert!(!content.contains("<script") && !content.contains("wpStats"), "{}", content);
+        assert!(content.contains("src=\"https://fieldnotes.example/wp-content/uploads/2024/06/frame-1024x768.jpg\""), "{}", content);
+
+        let blogger = logic_fetch_feed(format!("http://{}/blogger", addr), None, &state).await.unwrap();
+        let content = blogger.items[0].content.as_deref().unwrap();
+        assert!(content.contains("href=\"https://branchlinedays.blogspot.com/search/label/Narrow%20gauge\""), "{}", content);
+    }
+
+    #[test]
+    fn clears_the_seen_items_of_a_domain() {
+        crate::test_support::check_clear_for_domain(
+            |state, host| {
+                state.seen_items.lock().unwrap().feeds.insert(format!("https://{}/feed", host), HashMap::new());
+            },
+            clear_seen_items_for_domain,
+            |state| state.seen_items.lock().unwrap().feeds.keys().cloned().collect(),
+        );
+    }
+}
@@ -0,0 +1,253 @@
+use crate::messages::FeatureCounts;
+use crate::shared::{host_in_domain, host_of_domain_key, requires_rendering, MutationReport, ProxyState};
+use crate::site_config::config_key_for;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::LazyLock;
+use std::time::{SystemTime, UNIX_EPOCH};
+use url::Url;
+
+/// Version of the exported host stats
+pub const HOST_STATS_VERSION: u32 = 1;
+
+/// Hosts tracked; the one seen least recently is forgotten past this
+const MAX_TRACKED_HOSTS: usize = 2000;
+
+/// Fallbacks on a host before a fix is suggested
+const MIN_FALLBACKS_FOR_SUGGESTION: u64 = 3;
+
+/// Paywall markers: schema.org `isAccessibleForFree: false`, or a paywall class/id
+static PAYWALL_MARKERS: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r#"(?i)"isAccessibleForFree"\s*:\s*"?false|(?:class|id)\s*=\s*["'][^"']*\bpaywall"#).unwrap()
+});
+
+/// How an article extraction ended
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ExtractionOutcome {
+    Success,
+    /// Nothing extractable, the iframe fallback was used
+    Fallback,
+    /// Fallback on a page marked as paywalled
+    Paywall,
+    /// Fetching or extracting failed
+    Error,
+}
+
+/// Outcome counters of a host. Counters only, no history, so the table stays small.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct HostCounters {
+    pub successes: u64,
+    pub fallbacks: u64,
+    pub paywalls: u64,
+    pub errors: u64,
+    pub last_outcome: Option<ExtractionOutcome>,
+    /// Unix time (ms) of the last outcome or features report
+    pub last_seen: u64,
+    #[serde(default)]
+    pub features: FeatureEfficacy,
+}
+
+/// What the injected script's behaviors did on the host's pages, summed over page loads
+/// (latest `FEATURES_REPORT` of each)
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct FeatureEfficacy {
+    pub pages: u64,
+    /// Pages where some behavior threw
+    pub pages_with_errors: u64,
+    pub videos_found: u64,
+    pub overlays_installed: u64,
+    pub embeds_wrapped: u64,
+    pub scroll_passes: u64,
+    pub consent_overlays_removed: u64,
+    #[serde(default)]
+    pub lazy_backgrounds_promoted: u64,
+    #[serde(default)]
+    pub modal_overlays_removed: u64,
+    pub errors_caught: u64,
+}
+
+impl FeatureEfficacy {
+    fn add(&mut self, counts: &FeatureCounts, sign: i64) {
+        let apply = |total: &mut u64, value: u32| *total = total.saturating_add_signed(sign * value as i64);
+        apply(&mut self.pages, 1);
+        apply(&mut self.pages_with_errors, u32::from(counts.errors_caught > 0));
+        apply(&mut self.videos_found, counts.videos_found);
+        apply(&mut self.overlays_installed, counts.overlays_installed);
+        apply(&mut self.embeds_wrapped, counts.embeds_wrapped);
+        apply(&mut self.scroll_passes, counts.scroll_passes);
+        apply(&mut self.consent_overlays_removed, counts.consent_overlays_removed);
+        apply(&mut self.lazy_backgrounds_promoted, counts.lazy_backgrounds_promoted);
+        apply(&mut self.modal_overlays_removed, counts.modal_overlays_removed);
+        apply(&mut self.errors_caught, counts.errors_caught);
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HostSuggestion {
+    /// Flag the host with `set_host_requires_rendering`
+    EnableRendering,
+    /// Rendering is already on: site rules (`load_site_config`) are the remaining fix
+    AddSiteRules,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct HostStats {
+    pub host: String,
+    #[serde(flatten)]
+    pub counters: HostCounters,
+    pub attempts: u64,
+    /// `successes / attempts`, 0 without attempts
+    pub success_rate: f64,
+    /// Set when the host keeps falling back
+    pub suggestion: Option<HostSuggestion>,
+}
+
+/// Host stats as exported, so the app can keep them with its domain settings
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HostStatsExport {
+    pub version: u32,
+    pub hosts: HashMap<String, HostCounters>,
+}
+
+#[derive(Default)]
+pub struct HostStatsStore {
+    hosts: HashMap<String, HostCounters>,
+}
+
+fn now_millis() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_millis() as u64).unwrap_or(0)
+}
+
+/// Whether a page that couldn't be extracted is marked as paywalled
+pub fn looks_paywalled(html: &str) -> bool {
+    PAYWALL_MARKERS.is_match(html)
+}
+
+/// Counters of `host`, forgetting the least recently seen host to make room
+fn counters_for(store: &mut HostStatsStore, host: String) -> &mut HostCounters {
+    if !store.hosts.contains_key(&host) && store.hosts.len() >= MAX_TRACKED_HOSTS {
+        let oldest = store.hosts.iter().min_by_key(|(_, counters)| counters.last_seen).map(|(host, _)| host.clone());
+        if let Some(oldest) = oldest {
+            store.hosts.remove(&oldest);
+        }
+    }
+    let counters = store.hosts.entry(host).or_default();
+    counters.last_seen = now_millis();
+    counters
+}
+
+/// Counts `outcome` for the host of `url`
+pub fn record_outcome(url: &Url, outcome: ExtractionOutcome, state: &ProxyState) {
+    let Some(host) = url.host_str().map(str::to_ascii_lowercase) else {
+        return;
+    };
+    let mut store = state.host_stats.lock().unwrap();
+    let counters = counters_for(&mut store, host);
+    match outcome {
+        ExtractionOutcome::Success => counters.successes += 1,
+        ExtractionOutcome::Fallback => counters.fallbacks += 1,
+        ExtractionOutcome::Paywall => counters.paywalls += 1,
+        ExtractionOutcome::Error => counters.errors += 1,
+    }
+    counters.last_outcome = Some(outcome);
+}
+
+/// Adds a page load's feature counts to `host`, replacing the `previous` report of the same
+/// page load
+pub fn record_features(host: &str, previous: Option<&FeatureCounts>, counts: &FeatureCounts, state: &ProxyState) {
+    let mut store = state.host_stats.lock().unwrap();
+    let features = &mut counters_for(&mut store, host.to_string()).features;
+    if let Some(previous) = previous {
+        features.add(previous, -1);
+    }
+    features.add(counts, 1);
+}
+
+fn suggestion_for(host: &str, counters: &HostCounters, state: &ProxyState) -> Option<HostSuggestion> {
+    // Paywalls aren't fixed by rendering or rules, so they don't count
+    let attempts = counters.successes + counters.fallbacks + counters.errors;
+    if counters.fallbacks < MIN_FALLBACKS_FOR_SUGGESTION || counters.fallbacks * 2 < attempts {
+        return None;
+    }
+    let url = Url::parse(&format!("https://{}/", host)).ok()?;
+    if !requires_rendering(&url, state) {
+        Some(HostSuggestion::EnableRendering)
+    } else if config_key_for(host, state).is_none() {
+        Some(HostSuggestion::AddSiteRules)
+    } else {
+        None
+    }
+}
+
+/// Extraction outcomes of `host` (all zero when nothing was recorded), with a suggested fix
+/// for hosts that keep falling back
+pub fn logic_get_host_stats(host: String, state: &ProxyState) -> Result<HostStats, String> {
+    let host = host_of_domain_key(&host);
+    if host.is_empty() {
+        return Err("Host is required".into());
+    }
+    let counters = state.host_stats.lock().unwrap().hosts.get(&host).cloned().unwrap_or_default();
+    let attempts = counters.successes + counters.fallbacks + counters.paywalls + counters.errors;
+    Ok(HostStats {
+        suggestion: suggestion_for(&host, &counters, state),
+        success_rate: if attempts == 0 { 0.0 } else { counters.successes as f64 / attempts as f64 },
+        attempts,
+        counters,
+        host,
+    })
+}
+
+pub fn logic_export_host_stats(state: &ProxyState) -> HostStatsExport {
+    HostStatsExport { version: HOST_STATS_VERSION, hosts: state.host_stats.lock().unwrap().hosts.clone() }
+}
+
+/// Replaces the host stats, e.g. when restoring the domain settings at startup
+pub fn logic_import_host_stats(stats: HostStatsExport, state: &ProxyState) -> Result<usize, String> {
+    if stats.version > HOST_STATS_VERSION {
+        return Err(format!("Host stats version {} is newer than supported ({})", stats.version, HOST_STATS_VERSION));
+    }
+    let mut hosts: Vec<(String, HostCounters)> =
+        stats.hosts.into_iter().map(|(host, counters)| (host_of_domain_key(&host), counters)).filter(|(host, _)| !host.is_empty()).collect();
+    // Most recently seen kept when over the limit
+    hosts.sort_by_key(|(_, counters)| std::cmp::Reverse(counters.last_seen));
+    hosts.truncate(MAX_TRACKED_HOSTS);
+
+    let count = hosts.len();
+    state.host_stats.lock().unwrap().hosts = hosts.into_iter().collect();
+    println!("[host_stats::import_host_stats] Imported stats of {} hosts", count);
+    Ok(count)
+}
+
+/// Removes the outcome counters of `domain` and its subdomains
+pub fn clear_host_stats_for_domain(domain: &str, dry_run: bool, state: &ProxyState) -> MutationReport {
+    let mut report = MutationReport::new(dry_run);
+    let host = host_of_domain_key(domain);
+    let mut store = state.host_stats.lock().unwrap();
+
+    let matching: Vec<String> = store.hosts.keys().filter(|key| host_in_domain(key, &host)).cloned().collect();
+    for key in matching {
+        report.record("host_stats", key.clone(), None);
+        if !dry_run {
+            store.hosts.remove(&key);
+        }
+    }
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::check_clear_for_domain;
+
+    #[test]
+    fn clears_the_stats_of_a_domain() {
+        check_clear_for_domain(
+            |state, host| record_outcome(&Url::parse(&format!("https://{}/article", host)).unwrap(), ExtractionOutcome::Success, state),
+            clear_host_stats_for_domain,
+            |state| state.host_stats.lock().unwrap().hosts.keys().cloned().collect(),
+        );
+    }
+}
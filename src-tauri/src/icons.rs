@@ -0,0 +1,536 @@
+use crate::caching::{with_cache_class, CacheClass};
+use crate::connectivity;
+use crate::credentials;
+use crate::images::extract_image_urls;
+use crate::maintenance::StoreCheck;
+use crate::shared::{
+    absolutize_url, escape_html, extract_share_metadata, host_in_domain, host_of_domain_key, logic_fetch_raw_html,
+    origin_of, read_body_limited, MutationReport, ProxyState,
+};
+use axum::body::Body;
+use axum::extract::{Query, State};
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use base64::Engine;
+use reqwest::header;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::io::Cursor;
+use std::sync::Arc;
+use tokio::time::Duration;
+use url::Url;
+
+/// Largest favicon accepted, in bytes
+const MAX_ICON_BYTES: usize = 512 * 1024;
+
+/// Bytes downloaded from an image to sample its dominant color
+const COLOR_SAMPLE_BYTES: usize = 256 * 1024;
+
+/// Images larger than this (either dimension) are not decoded for color sampling
+const MAX_DECODE_DIMENSION: u32 = 4096;
+
+/// Allocation cap for a single color-sampling decode
+const MAX_DECODE_ALLOC: u64 = 64 * 1024 * 1024;
+
+/// Sides accepted by `/icon`, in pixels
+const MIN_ICON_SIZE: u32 = 16;
+const MAX_ICON_SIZE: u32 = 512;
+const DEFAULT_ICON_SIZE: u32 = 64;
+
+/// Side of the thumbnail the dominant color is computed on
+const COLOR_THUMBNAIL_SIZE: u32 = 32;
+
+/// Monogram backgrounds used when no image is available to pick a color from
+const MONOGRAM_PALETTE: &[(u8, u8, u8)] = &[
+    (0x25, 0x63, 0xeb),
+    (0x7c, 0x3a, 0xed),
+    (0xdb, 0x27, 0x77),
+    (0xdc, 0x26, 0x26),
+    (0xea, 0x58, 0x0c),
+    (0x16, 0xa3, 0x4a),
+    (0x08, 0x91, 0xb2),
+    (0x47, 0x55, 0x69),
+];
+
+/// Step of the fallback chain that produced an icon
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum IconTier {
+    /// The site's own icon (`<link rel="icon">`, apple-touch-icon or /favicon.ico)
+    Favicon,
+    /// Monogram colored after the site's og:image
+    OgImageColor,
+    /// Monogram colored after the article lead image
+    LeadImageColor,
+    /// Monogram on a color derived from the host name
+    Monogram,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct FeedIcon {
+    pub tier: IconTier,
+    pub content_type: String,
+    /// The icon as a `data:` URL
+    pub data_url: String,
+    /// Background color of generated monograms (`#rrggbb`)
+    pub color: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct FeedIconRequest {
+    pub site_url: String,
+    /// Feed title, whose first letter is used for monograms
+    pub title: String,
+    /// Lead image of a recent article, used when the site has no og:image
+    #[serde(default)]
+    pub lead_image_url: Option<String>,
+    /// Skip the cache, e.g. to retry the real icon after a generated one
+    #[serde(default)]
+    pub refresh: bool,
+}
+
+fn data_url(content_type: &str, bytes: &[u8]) -> String {
+    format!("data:{};base64,{}", content_type, base64::engine::general_purpose::STANDARD.encode(bytes))
+}
+
+fn http_client(state: &ProxyState) -> Result<reqwest::Client, String> {
+    reqwest::Client::builder()
+        .cookie_store(true)
+        .cookie_provider(state.cookie_jar.clone())
+        .redirect(credentials::redirect_policy(state))
+        .timeout(Duration::from_secs(15))
+        .build()
+        .map_err(|e| e.to_string())
+}
+
+/// Icon URLs declared by the page, best first, followed by `/favicon.ico`
+fn favicon_candidates(html: Option<&str>, site_url: &Url) -> Vec<String> {
+    let mut candidates = Vec::new();
+    if let Some(html) = html {
+        let document = scraper::Html::parse_document(html);
+        for selector in ["link[rel~=\"apple-touch-icon\" i]", "link[rel~=\"icon\" i]"] {
+            let selector = scraper::Selector::parse(selector).unwrap();
+            candidates.extend(
+                document
+                    .select(&selector)
+                    .filter_map(|link| link.value().attr("href"))
+                    .filter_map(|href| absolutize_url(href.trim(), site_url)),
+            );
+        }
+    }
+    candidates.push(format!("{}/favicon.ico", origin_of(site_url)));
+    candidates.dedup();
+    candidates
+}
+
+/// Downloads the first candidate that is actually served as an image, with the credentials
+/// stored for its origin. Returns its content type and bytes.
+async fn fetch_icon_bytes(client: &reqwest::Client, candidates: Vec<String>, state: &ProxyState) -> Option<(String, Vec<u8>)> {
+    for candidate in candidates {
+        let mut request = client
+            .get(&candidate)
+            .header(header::USER_AGENT, state.next_user_agent())
+            .header(header::ACCEPT, "image/*,*/*;q=0.8");
+        if let Some(credentials) = Url::parse(&candidate).ok().and_then(|url| state.auth_credentials.get(&origin_of(&url)).map(|entry| entry.value().clone())) {
+            request = credentials.apply(request);
+        }
+        let Ok(request) = request.build() else {
+            continue;
+        };
+        let Ok(response) = credentials::execute(client, request, state).await else {
+            continue;
+        };
+        if !response.status().is_success() {
+            continue;
+        }
+
+        let content_type = response
+            .headers()
+            .get(header::CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .map(|ct| ct.split(';').next().unwrap_or("").trim().to_ascii_lowercase())
+            .unwrap_or_default();
+        // Many servers send favicon.ico as octet-stream; HTML here means a soft 404
+        let content_type = match content_type.as_str() {
+            ct if ct.starts_with("image/") => ct.to_string(),
+            "" | "application/octet-stream" if candidate.ends_with(".ico") => "image/x-icon".to_string(),
+            _ => continue,
+        };
+
+        match read_body_limited(response, MAX_ICON_BYTES).await {
+            Ok(bytes) if !bytes.is_empty() => return Some((content_type, bytes)),
+            _ => continue,
+        }
+    }
+    None
+}
+
+async fn fetch_favicon(client: &reqwest::Client, candidates: Vec<String>, state: &ProxyState) -> Option<FeedIcon> {
+    let (content_type, bytes) = fetch_icon_bytes(client, candidates, state).await?;
+    Some(FeedIcon { tier: IconTier::Favicon, data_url: data_url(&content_type, &bytes), content_type, color: None })
+}
+
+/// Downloads at most `COLOR_SAMPLE_BYTES` of an image (ranged request, truncated if the
+/// server ignores the range)
+async fn fetch_image_prefix(client: &reqwest::Client, url: &str) -> Option<Vec<u8>> {
+    let mut response = client
+        .get(url)
+        .header(header::RANGE, format!("bytes=0-{}", COLOR_SAMPLE_BYTES - 1))
+        .header(header::ACCEPT, "image/avif,image/webp,image/*,*/*;q=0.8")
+        .send()
+        .await
+        .ok()?;
+    if !response.status().is_success() {
+        return None;
+    }
+
+    let mut bytes = Vec::new();
+    while let Ok(Some(chunk)) = response.chunk().await {
+        bytes.extend_from_slice(&chunk);
+        if bytes.len() >= COLOR_SAMPLE_BYTES {
+            bytes.truncate(COLOR_SAMPLE_BYTES);
+            break;
+        }
+    }
+    Some(bytes)
+}
+
+/// Most common color of an image, quantized to 4 bits per channel. Near-white, near-black
+/// and grey pixels are only used when the image has nothing more colorful.
+/// Decoding is CPU-bound: call it from a blocking thread.
+fn dominant_color(bytes: &[u8]) -> Option<(u8, u8, u8)> {
+    let mut reader = image::ImageReader::new(Cursor::new(bytes)).with_guessed_format().ok()?;
+    let mut limits = image::Limits::default();
+    limits.max_image_width = Some(MAX_DECODE_DIMENSION);
+    limits.max_image_height = Some(MAX_DECODE_DIMENSION);
+    limits.max_alloc = Some(MAX_DECODE_ALLOC);
+    reader.limits(limits);
+
+    let thumbnail = reader
+        .decode()
+        .ok()?
+        .thumbnail(COLOR_THUMBNAIL_SIZE, COLOR_THUMBNAIL_SIZE)
+        .to_rgb8();
+
+    // bucket -> (pixel count, channel sums)
+    let mut colorful: HashMap<u16, (u32, [u32; 3])> = HashMap::new();
+    let mut dull: HashMap<u16, (u32, [u32; 3])> = HashMap::new();
+
+    for pixel in thumbnail.pixels() {
+        let [r, g, b] = pixel.0;
+        let max = r.max(g).max(b);
+        let min = r.min(g).min(b);
+        let is_dull = max < 40 || min > 215 || max - min < 24;
+
+        let bucket = ((r as u16 >> 4) << 8) | ((g as u16 >> 4) << 4) | (b as u16 >> 4);
+        let entry = (if is_dull { &mut dull } else { &mut colorful }).entry(bucket).or_insert((0, [0; 3]));
+        entry.0 += 1;
+        entry.1[0] += r as u32;
+        entry.1[1] += g as u32;
+        entry.1[2] += b as u32;
+    }
+
+    let buckets = if colorful.is_empty() { dull } else { colorful };
+    let (count, sums) = buckets.into_values().max_by_key(|(count, _)| *count)?;
+    Some(((sums[0] / count) as u8, (sums[1] / count) as u8, (sums[2] / count) as u8))
+}
+
+/// Samples the dominant color of a remote image on a blocking thread
+async fn image_color(client: &reqwest::Client, url: &str) -> Option<(u8, u8, u8)> {
+    let bytes = fetch_image_prefix(client, url).await?;
+    tokio::task::spawn_blocking(move || dominant_color(&bytes)).await.ok()?
+}
+
+/// Stable fallback color for a host
+fn palette_color(key: &str) -> (u8, u8, u8) {
+    let digest = Sha256::digest(key.as_bytes());
+    MONOGRAM_PALETTE[digest[0] as usize % MONOGRAM_PALETTE.len()]
+}
+
+/// Square SVG with the first letter of `title` on `color`, `size` pixels wide
+fn monogram_svg(title: &str, color: (u8, u8, u8), size: u32) -> String {
+    let letter = title
+        .chars()
+        .find(|c| c.is_alphanumeric())
+        .map(|c| c.to_uppercase().to_string())
+        .unwrap_or_else(|| "?".to_string());
+
+    // Dark text on light backgrounds (relative luminance, sRGB weights)
+    let (r, g, b) = color;
+    let luminance = 0.2126 * r as f64 + 0.7152 * g as f64 + 0.0722 * b as f64;
+    let text_color = if luminance > 160.0 { "#111827" } else { "#ffffff" };
+
+    format!(
+        r##"<svg xmlns="http://www.w3.org/2000/svg" width="{}" height="{}" viewBox="0 0 64 64"><rect width="64" height="64" rx="12" fill="#{:02x}{:02x}{:02x}"/><text x="32" y="32" dy=".35em" text-anchor="middle" font-family="system-ui, -apple-system, sans-serif" font-size="34" font-weight="600" fill="{}">{}</text></svg>"##,
+        size, size, r, g, b, text_color, escape_html(&letter)
+    )
+}
+
+fn monogram_icon(tier: IconTier, title: &str, color: (u8, u8, u8)) -> FeedIcon {
+    let svg = monogram_svg(title, color, 64);
+    FeedIcon {
+        tier,
+        content_type: "image/svg+xml".to_string(),
+        data_url: data_url("image/svg+xml", svg.as_bytes()),
+        color: Some(format!("#{:02x}{:02x}{:02x}", color.0, color.1, color.2)),
+    }
+}
+
+/// Icon for a feed's site, walking the fallback chain: real favicon, then a monogram
+/// colored after the og:image, then after the lead image, then after the host name.
+/// Results are cached per site origin.
+pub async fn logic_fetch_feed_icon(request: FeedIconRequest, state: &ProxyState) -> Result<FeedIcon, String> {
+    let site_url = Url::parse(&request.site_url).map_err(|e| e.to_string())?;
+    let cache_key = origin_of(&site_url);
+
+    if !request.refresh {
+        if let Some(icon) = state.icon_cache.lock().unwrap().get(&cache_key) {
+            return Ok(icon.clone());
+        }
+    }
+    connectivity::ensure_online(state)?;
+
+    let client = http_client(state)?;
+    // The page is only needed for <link rel="icon"> and og:image: carry on without it
+    let html = logic_fetch_raw_html(site_url.to_string(), None, None, state).await.ok();
+
+    let icon = match fetch_favicon(&client, favicon_candidates(html.as_deref(), &site_url), state).await {
+        Some(icon) => icon,
+        None => {
+            let og_image = html.as_deref().and_then(|html| extract_share_metadata(html, &site_url).image);
+            let lead_image = request
+                .lead_image_url
+                .clone()
+                .or_else(|| html.as_deref().and_then(|html| extract_image_urls(html, &site_url).into_iter().next()));
+
+            let mut icon = None;
+            for (tier, image_url) in [(IconTier::OgImageColor, og_image), (IconTier::LeadImageColor, lead_image)] {
+                let Some(image_url) = image_url else { continue };
+                if let Some(color) = image_color(&client, &image_url).await {
+                    icon = Some(monogram_icon(tier, &request.title, color));
+                    break;
+                }
+            }
+            icon.unwrap_or_else(|| monogram_icon(IconTier::Monogram, &request.title, palette_color(&cache_key)))
+        }
+    };
+
+    println!("[icons::fetch_feed_icon] {} -> {:?}", cache_key, icon.tier);
+    state.icon_cache.lock().unwrap().insert(cache_key, icon.clone());
+    Ok(icon)
+}
+
+/// Icons a proxied page declared, captured while rewriting it
+#[derive(Debug, Clone)]
+pub struct PageIcons {
+    /// Origin of the page
+    pub origin: String,
+    /// Absolute URLs, apple-touch-icons first
+    pub icons: Vec<String>,
+}
+
+/// Records the icons of the page being proxied, so `/icon` needs no page fetch for its domain
+pub fn record_page_icons(page_url: &Url, mut touch_icons: Vec<String>, icons: Vec<String>, state: &ProxyState) {
+    touch_icons.extend(icons);
+    touch_icons.dedup();
+    state.page_icons.store(Some(Arc::new(PageIcons { origin: origin_of(page_url), icons: touch_icons })));
+}
+
+#[derive(Debug, Deserialize)]
+pub struct IconQuery {
+    /// Host (`example.com`) or any URL of the site
+    pub domain: String,
+    /// Side of the square icon, in pixels
+    #[serde(default = "default_icon_size")]
+    pub size: u32,
+}
+
+fn default_icon_size() -> u32 {
+    DEFAULT_ICON_SIZE
+}
+
+/// An icon served by `/icon`
+#[derive(Debug, Clone)]
+pub struct SiteIcon {
+    pub content_type: String,
+    pub bytes: Vec<u8>,
+    /// A monogram: the site has no icon, or none could be fetched
+    pub generated: bool,
+}
+
+/// `bytes` fit in a `size` square, as PNG. SVG and undecodable formats are returned as they
+/// are. Decoding is CPU-bound: call it from a blocking thread.
+fn resize_icon(content_type: &str, bytes: Vec<u8>, size: u32) -> (String, Vec<u8>) {
+    if content_type == "image/svg+xml" {
+        return (content_type.to_string(), bytes);
+    }
+    let resized = image::ImageReader::new(Cursor::new(&bytes)).with_guessed_format().ok().and_then(|mut reader| {
+        let mut limits = image::Limits::default();
+        limits.max_image_width = Some(MAX_DECODE_DIMENSION);
+        limits.max_image_height = Some(MAX_DECODE_DIMENSION);
+        limits.max_alloc = Some(MAX_DECODE_ALLOC);
+        reader.limits(limits);
+        let icon = reader.decode().ok()?;
+        let icon = if icon.width() == size && icon.height() == size { icon } else { icon.resize(size, size, image::imageops::FilterType::Lanczos3) };
+        let mut png = Vec::new();
+        icon.write_to(&mut Cursor::new(&mut png), image::ImageFormat::Png).ok()?;
+        Some(png)
+    });
+    match resized {
+        Some(png) => ("image/png".to_string(), png),
+        None => (content_type.to_string(), bytes),
+    }
+}
+
+/// Decodes a cached icon's `data:` URL
+fn data_url_bytes(data_url: &str) -> Option<(String, Vec<u8>)> {
+    let (content_type, data) = data_url.strip_prefix("data:")?.split_once(";base64,")?;
+    Some((content_type.to_string(), base64::engine::general_purpose::STANDARD.decode(data).ok()?))
+}
+
+/// Icon of a site, `size` pixels wide, resolved without the webview: the icon cached for the
+/// site, else the icons the proxied page declared (when it's on that site), else those of its
+/// home page and `/favicon.ico`, fetched with the site's cookies and credentials. Sites
+/// without an icon get a monogram.
+pub async fn logic_site_icon(domain: &str, size: u32, state: &ProxyState) -> Result<SiteIcon, String> {
+    if !(MIN_ICON_SIZE..=MAX_ICON_SIZE).contains(&size) {
+        return Err(format!("Icon size must be between {} and {}, got {}", MIN_ICON_SIZE, MAX_ICON_SIZE, size));
+    }
+    let host = host_of_domain_key(domain);
+    let site_url = Url::parse(&format!("https://{}/", host)).map_err(|e| format!("Invalid domain '{}': {}", domain, e))?;
+    let origin = origin_of(&site_url);
+
+    let cached = state.icon_cache.lock().unwrap().get(&origin).filter(|icon| icon.tier == IconTier::Favicon).and_then(|icon| data_url_bytes(&icon.data_url));
+    let found = match cached {
+        Some(icon) => Some(icon),
+        // Offline, uncached sites get their monogram
+        None if connectivity::is_offline(state) => None,
+        None => {
+            let declared = state.page_icons.load_full().filter(|page| page.origin == origin);
+            let candidates = match declared {
+                Some(page) => {
+                    let mut candidates = page.icons.clone();
+                    candidates.push(format!("{}/favicon.ico", origin));
+                    candidates
+                }
+                None => favicon_candidates(logic_fetch_raw_html(site_url.to_string(), None, None, state).await.ok().as_deref(), &site_url),
+            };
+            let icon = fetch_icon_bytes(&http_client(state)?, candidates, state).await;
+            if let Some((content_type, bytes)) = &icon {
+                let feed_icon = FeedIcon { tier: IconTier::Favicon, data_url: data_url(content_type, bytes), content_type: content_type.clone(), color: None };
+                state.icon_cache.lock().unwrap().insert(origin.clone(), feed_icon);
+            }
+            icon
+        }
+    };
+
+    let Some((content_type, bytes)) = found else {
+        println!("[icons::site_icon] {} has no icon, serving a monogram", origin);
+        let title = host.trim_start_matches("www.");
+        return Ok(SiteIcon { content_type: "image/svg+xml".to_string(), bytes: monogram_svg(title, palette_color(&origin), size).into_bytes(), generated: true });
+    };
+    let (content_type, bytes) = tokio::task::spawn_blocking(move || resize_icon(&content_type, bytes, size)).await.map_err(|e| e.to_string())?;
+    Ok(SiteIcon { content_type, bytes, generated: false })
+}
+
+/// Handler for `GET /icon?domain=...&size=...`. Real icons are cached for a week by the
+/// webview, monograms for a day so a newly fetchable icon replaces them.
+pub async fn icon_handler(Query(query): Query<IconQuery>, State(state): State<ProxyState>) -> Response {
+    match logic_site_icon(&query.domain, query.size, &state).await {
+        Ok(icon) => {
+            let cache_class = if icon.generated { CacheClass::Generated } else { CacheClass::Immutable };
+            with_cache_class(Response::builder(), cache_class)
+                .status(StatusCode::OK)
+                .header(axum::http::header::CONTENT_TYPE, icon.content_type)
+                .header(axum::http::header::ACCESS_CONTROL_ALLOW_ORIGIN, "*")
+                .body(Body::from(icon.bytes))
+                .unwrap_or_else(|_| StatusCode::INTERNAL_SERVER_ERROR.into_response())
+        }
+        Err(e) => {
+            println!("[icons::icon_handler] {}: {}", query.domain, e);
+            (StatusCode::BAD_REQUEST, e).into_response()
+        }
+    }
+}
+
+/// Whether a cached icon's `data:` URL decodes
+fn is_valid_data_url(data_url: &str) -> bool {
+    data_url
+        .strip_prefix("data:")
+        .and_then(|rest| rest.split_once(";base64,"))
+        .is_some_and(|(_, data)| base64::engine::general_purpose::STANDARD.decode(data).is_ok())
+}
+
+/// Maintenance: drops cached icons whose data URL doesn't decode
+pub fn check_icon_cache(dry_run: bool, state: &ProxyState) -> StoreCheck {
+    let mut check = StoreCheck::new("icon_cache");
+    let mut cache = state.icon_cache.lock().unwrap();
+    check.checked = cache.len();
+
+    let mut invalid: Vec<String> = cache.iter().filter(|(_, icon)| !is_valid_data_url(&icon.data_url)).map(|(key, _)| key.clone()).collect();
+    invalid.sort();
+    for key in invalid {
+        check.problem(format!("{}: icon data URL doesn't decode", key), dry_run);
+        if !dry_run {
+            cache.remove(&key);
+        }
+    }
+    check.size = cache.values().map(|icon| icon.data_url.len()).sum();
+    check
+}
+
+/// Drops cached icons of `domain` and its subdomains, so the next fetch walks the chain again
+pub fn clear_icons_for_domain(domain: &str, dry_run: bool, state: &ProxyState) -> MutationReport {
+    let mut report = MutationReport::new(dry_run);
+    let host = host_of_domain_key(domain);
+    let mut cache = state.icon_cache.lock().unwrap();
+
+    let matching: Vec<(String, usize)> = cache
+        .iter()
+        .filter(|(key, _)| host_in_domain(&host_of_domain_key(key), &host))
+        .map(|(key, icon)| (key.clone(), icon.data_url.len()))
+        .collect();
+
+    for (key, size) in matching {
+        report.record("icon_cache", key.clone(), Some(size));
+        if !dry_run {
+            cache.remove(&key);
+        }
+    }
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::check_clear_for_domain;
+
+    #[test]
+    fn clears_the_icons_of_a_domain() {
+        check_clear_for_domain(
+            |state, host| {
+                let icon = FeedIcon { tier: IconTier::Monogram, content_type: "image/svg+xml".into(), data_url: "data:image/svg+xml,".into(), color: None };
+                state.icon_cache.lock().unwrap().insert(format!("https://{}", host), icon);
+            },
+            clear_icons_for_domain,
+            |state| state.icon_cache.lock().unwrap().keys().cloned().collect(),
+        );
+    }
+
+    #[tokio::test]
+    async fn fetched_icons_are_immutable_and_monograms_are_kept_a_day() {
+        let state = ProxyState::default();
+        let svg = r#"<svg xmlns="http://www.w3.org/2000/svg" viewBox="0 0 1 1"/>"#;
+        let icon = FeedIcon { tier: IconTier::Favicon, content_type: "image/svg+xml".into(), data_url: data_url("image/svg+xml", svg.as_bytes()), color: None };
+        state.icon_cache.lock().unwrap().insert("https://harbour.example".to_string(), icon);
+        connectivity::logic_set_offline_mode(true, &state);
+
+        for (domain, expected) in [("harbour.example", "public, max-age=604800, immutable"), ("uncached.example", "public, max-age=86400")] {
+            let query = IconQuery { domain: domain.to_string(), size: default_icon_size() };
+            let response = icon_handler(Query(query), State(state.clone())).await;
+            assert_eq!(response.status(), StatusCode::OK);
+            assert_eq!(response.headers().get(header::CACHE_CONTROL).unwrap(), expected, "{}", domain);
+        }
+    }
+}
@@ -0,0 +1,318 @@
+use crate::chaos::{self, ChaosFault};
+use crate::connectivity;
+use crate::credentials;
+use crate::shared::{absolutize_url, logic_extract_article, origin_of, with_protocol_for, ArticleOptions, ProxyState, LAZY_IMAGE_ATTRIBUTES};
+use futures_util::stream::{self, StreamExt};
+use reqwest::header;
+use scraper::{ElementRef, Html, Selector};
+use serde::Serialize;
+use std::collections::HashSet;
+use tokio::time::Duration;
+use url::Url;
+
+/// Maximum number of image probes in flight at once
+pub const PROBE_CONCURRENCY: usize = 6;
+
+/// Per-request timeout for an image probe
+const PROBE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Outcome of probing an image through the same path the proxy uses
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ImageStatus {
+    Ok,
+    /// 404 / 410
+    NotFound,
+    /// Rejected with the article as Referer, but served with another one (hotlink protection)
+    RefererBlocked,
+    Timeout,
+    /// Any other non-success HTTP status
+    HttpError,
+    /// DNS, TLS or connection failure
+    NetworkError,
+    /// Served successfully, but not as an image (typically an HTML error page)
+    NotImage,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ImageProbe {
+    pub url: String,
+    pub status: ImageStatus,
+    pub http_status: Option<u16>,
+    pub content_type: Option<String>,
+    /// For `RefererBlocked`: the Referer that worked, to pass as `&referer=` to `/proxy`.
+    /// An empty string means the image loads without any Referer.
+    pub retry_referer: Option<String>,
+}
+
+/// An image of extracted content with the human-written text describing it. `None` means
+/// the caption or `alt` is absent, `Some("")` that it's present but empty (`alt=""` marks
+/// decorative images).
+#[derive(Debug, Clone, Serialize)]
+pub struct CaptionedImage {
+    pub url: String,
+    pub alt: Option<String>,
+    pub caption: Option<String>,
+    /// Where `caption` came from: `figcaption`, `aria-describedby` or `small`
+    pub caption_source: Option<String>,
+}
+
+/// URL of an `<img>`: lazy-loading attributes are preferred over placeholder `src` values,
+/// `data:` URIs are skipped
+fn image_source(img: &scraper::node::Element) -> Option<&str> {
+    LAZY_IMAGE_ATTRIBUTES
+        .iter()
+        .filter_map(|attr| img.attr(attr))
+        .chain(img.attr("src"))
+        .map(str::trim)
+        .find(|src| !src.is_empty() && !src.starts_with("data:"))
+}
+
+/// Image URLs of extracted content, absolutized against `base` and deduplicated in document order.
+/// Lazy-loading attributes are preferred over placeholder `src` values; `data:` URIs are skipped.
+pub fn extract_image_urls(html: &str, base: &Url) -> Vec<String> {
+    let fragment = scraper::Html::parse_fragment(html);
+    let images = scraper::Selector::parse("img").unwrap();
+    let mut seen = HashSet::new();
+
+    fragment
+        .select(&images)
+        .filter_map(|img| image_source(img.value()))
+        .filter_map(|src| absolutize_url(src, base))
+        .filter(|url| seen.insert(url.clone()))
+        .collect()
+}
+
+fn element_text(el: &ElementRef) -> String {
+    el.text().collect::<Vec<_>>().join(" ").split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// `<figcaption>` of the closest `<figure>` holding the image
+fn figure_caption(img: &ElementRef) -> Option<String> {
+    let figcaption = Selector::parse("figcaption").unwrap();
+    let figure = img.ancestors().filter_map(ElementRef::wrap).find(|el| el.value().name() == "figure")?;
+    figure.select(&figcaption).next().map(|caption| element_text(&caption))
+}
+
+/// Text of the elements `aria-describedby` names, when any of them exists
+fn described_by(img: &ElementRef, document: &Html) -> Option<String> {
+    let ids = img.value().attr("aria-describedby")?;
+    let texts: Vec<String> = ids
+        .split_whitespace()
+        .filter_map(|id| {
+            let selector = Selector::parse(&format!("[id=\"{}\"]", id.replace(['"', '\\'], ""))).ok()?;
+            document.select(&selector).next().map(|el| element_text(&el))
+        })
+        .collect();
+    (!texts.is_empty()).then(|| texts.join(" ").trim().to_string())
+}
+
+/// `<small>` right after the image, or after the link or `<picture>` wrapping it
+fn adjacent_small(img: &ElementRef) -> Option<String> {
+    let parent = img.parent().and_then(ElementRef::wrap);
+    let anchor = match parent {
+        Some(parent) if matches!(parent.value().name(), "a" | "picture") => parent,
+        _ => *img,
+    };
+    let next = anchor.next_siblings().find(|node| !node.value().as_text().is_some_and(|text| text.trim().is_empty()))?;
+    ElementRef::wrap(next).filter(|el| el.value().name() == "small").map(|small| element_text(&small))
+}
+
+/// Images of extracted content paired with their `alt` and caption, deduplicated by URL in
+/// document order. The caption is the figure's `<figcaption>`, else what `aria-describedby`
+/// points to, else an adjacent `<small>`.
+pub fn extract_captioned_images(html: &str, base: &Url) -> Vec<CaptionedImage> {
+    let fragment = Html::parse_fragment(html);
+    let images = Selector::parse("img").unwrap();
+    let mut seen = HashSet::new();
+
+    fragment
+        .select(&images)
+        .filter_map(|img| {
+            let url = absolutize_url(image_source(img.value())?, base)?;
+            if !seen.insert(url.clone()) {
+                return None;
+            }
+            let (caption, caption_source) = [
+                (figure_caption(&img), "figcaption"),
+                (described_by(&img, &fragment), "aria-describedby"),
+                (adjacent_small(&img), "small"),
+            ]
+            .into_iter()
+            .find_map(|(caption, source)| Some((caption?, source)))
+            .map_or((None, None), |(caption, source)| (Some(caption), Some(source.to_string())));
+            Some(CaptionedImage { url, alt: img.value().attr("alt").map(|alt| alt.trim().to_string()), caption, caption_source })
+        })
+        .collect()
+}
+
+/// Response summary of a single probe request
+struct ProbeResponse {
+    status: u16,
+    content_type: Option<String>,
+}
+
+/// HEAD request for `url`, falling back to a one-byte ranged GET for servers that reject HEAD
+async fn probe_request(
+    client: &reqwest::Client,
+    url: &Url,
+    referer: Option<&str>,
+    state: &ProxyState,
+) -> Result<ProbeResponse, reqwest::Error> {
+    let auth_credentials = state.auth_credentials.get(&origin_of(url)).map(|entry| entry.value().clone());
+
+    let mut response = None;
+    for method in [reqwest::Method::HEAD, reqwest::Method::GET] {
+        let mut request = client
+            .request(method.clone(), url.clone())
+            .header(
+                header::USER_AGENT,
+                state.next_user_agent(),
+            )
+            .header(header::ACCEPT, "image/avif,image/webp,image/*,*/*;q=0.8");
+        if method == reqwest::Method::GET {
+            request = request.header(header::RANGE, "bytes=0-0");
+        }
+        if let Some(referer) = referer {
+            request = request.header(header::REFERER, referer);
+        }
+        if let Some(auth) = &auth_credentials {
+            request = auth.apply(request);
+        }
+
+        let current = credentials::execute(client, request.build()?, state).await?;
+        let rejected_head = matches!(current.status().as_u16(), 405 | 501);
+        response = Some(current);
+        if !rejected_head {
+            break;
+        }
+    }
+
+    let response = response.expect("at least one probe request is sent");
+    Ok(ProbeResponse {
+        status: response.status().as_u16(),
+        content_type: response
+            .headers()
+            .get(header::CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .map(|ct| ct.to_string()),
+    })
+}
+
+fn is_image_content_type(content_type: Option<&str>) -> bool {
+    match content_type {
+        None => true,
+        Some(ct) => {
+            let ct = ct.to_ascii_lowercase();
+            ct.is_empty() || ct.starts_with("image/") || ct.starts_with("application/octet-stream") || ct.starts_with("binary/")
+        }
+    }
+}
+
+/// Probes one image with the article as Referer. On 401/403, retries with the image's own
+/// origin and then without Referer to detect hotlink protection.
+async fn probe_image(client: &reqwest::Client, image_url: String, article_url: &Url, state: &ProxyState) -> ImageProbe {
+    let mut probe = ImageProbe {
+        url: image_url.clone(),
+        status: ImageStatus::NetworkError,
+        http_status: None,
+        content_type: None,
+        retry_referer: None,
+    };
+
+    let Ok(url) = Url::parse(&image_url) else {
+        return probe;
+    };
+
+    if let Err(fault) = chaos::inject_request_faults(&url, state).await {
+        probe.status = match fault {
+            ChaosFault::ConnectionReset => ImageStatus::NetworkError,
+            ChaosFault::Status(status) => {
+                probe.http_status = Some(status);
+                ImageStatus::HttpError
+            }
+        };
+        return probe;
+    }
+
+    let response = match probe_request(client, &url, Some(article_url.as_str()), state).await {
+        Ok(response) => response,
+        Err(e) => {
+            probe.status = if e.is_timeout() { ImageStatus::Timeout } else { ImageStatus::NetworkError };
+            return probe;
+        }
+    };
+
+    probe.http_status = Some(response.status);
+    probe.content_type = response.content_type.clone();
+    probe.status = match response.status {
+        200..=299 if is_image_content_type(response.content_type.as_deref()) => ImageStatus::Ok,
+        200..=299 => ImageStatus::NotImage,
+        404 | 410 => ImageStatus::NotFound,
+        401 | 403 => {
+            let own_origin = format!("{}/", origin_of(&url));
+            for referer in [Some(own_origin.as_str()), None] {
+                if let Ok(retry) = probe_request(client, &url, referer, state).await {
+                    if (200..300).contains(&retry.status) && is_image_content_type(retry.content_type.as_deref()) {
+                        probe.retry_referer = Some(referer.unwrap_or("").to_string());
+                        probe.content_type = retry.content_type;
+                        break;
+                    }
+                }
+            }
+            if probe.retry_referer.is_some() { ImageStatus::RefererBlocked } else { ImageStatus::HttpError }
+        }
+        _ => ImageStatus::HttpError,
+    };
+    probe
+}
+
+/// Probes `urls` through the proxy's client setup (shared cookie jar), at most
+/// `PROBE_CONCURRENCY` at a time. Results keep the order of `urls`.
+pub async fn probe_images(urls: Vec<String>, article_url: &Url, state: &ProxyState) -> Result<Vec<ImageProbe>, String> {
+    connectivity::ensure_online(state)?;
+    let client = with_protocol_for(reqwest::Client::builder(), article_url, state)
+        .cookie_store(true)
+        .cookie_provider(state.cookie_jar.clone())
+        .redirect(credentials::redirect_policy(state))
+        .timeout(PROBE_TIMEOUT)
+        .connect_timeout(PROBE_TIMEOUT)
+        .build()
+        .map_err(|e| e.to_string())?;
+
+    let probes = stream::iter(urls)
+        .map(|url| probe_image(&client, url, article_url, state))
+        .buffered(PROBE_CONCURRENCY)
+        .collect::<Vec<_>>()
+        .await;
+
+    let broken = probes.iter().filter(|p| p.status != ImageStatus::Ok).count();
+    println!("[images::probe_images] {} images probed, {} not loading", probes.len(), broken);
+    Ok(probes)
+}
+
+/// Extracts the article at `url` and pairs each of its images with its caption and `alt`.
+/// Returns an empty list when extraction falls back to the iframe.
+pub async fn logic_fetch_image_captions(url: String, options: ArticleOptions, state: &ProxyState) -> Result<Vec<CaptionedImage>, String> {
+    let article_url = Url::parse(&url).map_err(|e| e.to_string())?;
+    let Some(content) = logic_extract_article(url, options, state).await?.content else {
+        return Ok(Vec::new());
+    };
+
+    let images = extract_captioned_images(&content, &article_url);
+    let captioned = images.iter().filter(|image| image.caption.is_some()).count();
+    println!("[images::fetch_image_captions] {} images, {} captioned", images.len(), captioned);
+    Ok(images)
+}
+
+/// Extracts the article at `url` and probes every image of the extracted content.
+/// Returns an empty list when extraction falls back to the iframe.
+pub async fn logic_probe_article_images(url: String, options: ArticleOptions, state: &ProxyState) -> Result<Vec<ImageProbe>, String> {
+    let article_url = Url::parse(&url).map_err(|e| e.to_string())?;
+    let Some(content) = logic_extract_article(url, options, state).await?.content else {
+        return Ok(Vec::new());
+    };
+
+    let urls = extract_image_urls(&content, &article_url);
+    probe_images(urls, &article_url, state).await
+}
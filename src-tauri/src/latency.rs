@@ -0,0 +1,291 @@
+use crate::shared::{host_in_domain, host_of_domain_key, MutationReport, ProxyState};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use url::Url;
+
+/// Version of the exported latency history
+pub const HOST_LATENCY_VERSION: u32 = 1;
+
+/// Timeout of a request to a host without enough history, or with adaptive timeouts off
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Bounds of a timeout a caller asks for (`timeout_secs`), in seconds
+const MIN_REQUESTED_TIMEOUT_SECS: u64 = 1;
+const MAX_REQUESTED_TIMEOUT_SECS: u64 = 120;
+
+/// Latencies kept per host, oldest dropped first
+const WINDOW_SIZE: usize = 50;
+
+/// Latencies needed before a host gets an adaptive timeout
+const MIN_SAMPLES: usize = 5;
+
+/// Hosts tracked; the one seen least recently is forgotten past this
+const MAX_TRACKED_HOSTS: usize = 2000;
+
+/// Who is waiting on a request, which decides how far its timeout may be stretched or cut
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RequestPriority {
+    /// The user opened the article and is waiting for it
+    Interactive,
+    /// Prefetching: nobody waits, a slow host shouldn't hold the queue
+    Background,
+}
+
+/// Bounds of an adaptive timeout, in milliseconds
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct TimeoutClamp {
+    pub floor_ms: u64,
+    pub ceiling_ms: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AdaptiveTimeoutConfig {
+    /// Off, every request gets `DEFAULT_TIMEOUT` (latencies are still recorded)
+    pub enabled: bool,
+    /// Multiplier applied to the host's p95 latency
+    pub factor: f64,
+    pub interactive: TimeoutClamp,
+    pub background: TimeoutClamp,
+}
+
+impl Default for AdaptiveTimeoutConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            factor: 3.0,
+            interactive: TimeoutClamp { floor_ms: 10_000, ceiling_ms: 60_000 },
+            background: TimeoutClamp { floor_ms: 5_000, ceiling_ms: 20_000 },
+        }
+    }
+}
+
+impl AdaptiveTimeoutConfig {
+    fn clamp_for(&self, priority: RequestPriority) -> TimeoutClamp {
+        match priority {
+            RequestPriority::Interactive => self.interactive,
+            RequestPriority::Background => self.background,
+        }
+    }
+}
+
+/// Recent latencies of a host: time until the response headers, in milliseconds. A request
+/// that timed out counts as taking its whole timeout.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct HostLatency {
+    pub samples: VecDeque<u64>,
+    pub timeouts: u64,
+    /// Unix time (ms) of the latest sample
+    pub last_seen: u64,
+}
+
+impl HostLatency {
+    /// Nearest-rank percentile of the window, `None` while it's empty
+    fn percentile(&self, percent: usize) -> Option<u64> {
+        let mut sorted: Vec<u64> = self.samples.iter().copied().collect();
+        sorted.sort_unstable();
+        let rank = (sorted.len() * percent).div_ceil(100).max(1);
+        sorted.get(rank - 1).copied()
+    }
+}
+
+/// Latency of a host for the diagnostics view, with the timeouts its requests currently get
+#[derive(Debug, Clone, Serialize)]
+pub struct HostLatencyStats {
+    pub host: String,
+    pub samples: usize,
+    pub timeouts: u64,
+    pub p50_ms: Option<u64>,
+    pub p95_ms: Option<u64>,
+    pub max_ms: Option<u64>,
+    pub interactive_timeout_ms: u64,
+    pub background_timeout_ms: u64,
+    pub last_seen: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct HostLatencyReport {
+    pub config: AdaptiveTimeoutConfig,
+    /// Sorted by host
+    pub hosts: Vec<HostLatencyStats>,
+}
+
+/// Latency history as exported, so the app can persist it between sessions
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HostLatencyExport {
+    pub version: u32,
+    pub config: AdaptiveTimeoutConfig,
+    pub hosts: HashMap<String, HostLatency>,
+}
+
+fn now_millis() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_millis() as u64).unwrap_or(0)
+}
+
+fn host_key(url: &Url) -> Option<String> {
+    url.host_str().map(str::to_ascii_lowercase)
+}
+
+fn timeout_from(history: Option<&HostLatency>, config: &AdaptiveTimeoutConfig, priority: RequestPriority) -> Duration {
+    let p95 = history.filter(|history| history.samples.len() >= MIN_SAMPLES).and_then(|history| history.percentile(95));
+    match p95 {
+        Some(p95) if config.enabled => {
+            let clamp = config.clamp_for(priority);
+            let adaptive = (p95 as f64 * config.factor.max(1.0)) as u64;
+            Duration::from_millis(adaptive.clamp(clamp.floor_ms, clamp.ceiling_ms.max(clamp.floor_ms)))
+        }
+        _ => DEFAULT_TIMEOUT,
+    }
+}
+
+/// Timeout of a request to `url`: the host's p95 latency times the configured factor,
+/// clamped to the priority's bounds. `DEFAULT_TIMEOUT` for hosts with too little history.
+pub fn timeout_for(url: &Url, priority: RequestPriority, state: &ProxyState) -> Duration {
+    let config = state.adaptive_timeouts.load();
+    let history = host_key(url).and_then(|host| state.host_latency.get(&host));
+    timeout_from(history.as_deref(), &config, priority)
+}
+
+/// `timeout_secs` clamped to 1-120 seconds when the caller gave one, else `timeout_for`
+pub fn requested_timeout(url: &Url, priority: RequestPriority, timeout_secs: Option<u64>, state: &ProxyState) -> Duration {
+    match timeout_secs {
+        Some(secs) => Duration::from_secs(secs.clamp(MIN_REQUESTED_TIMEOUT_SECS, MAX_REQUESTED_TIMEOUT_SECS)),
+        None => timeout_for(url, priority, state),
+    }
+}
+
+/// Adds the outcome of a request to `url` started at `started` to its host's window.
+/// Errors other than timeouts say nothing about the host's speed and aren't recorded.
+pub fn record_response<T>(url: &Url, started: Instant, timeout: Duration, result: &Result<T, reqwest::Error>, state: &ProxyState) {
+    let (elapsed, timed_out) = match result {
+        Ok(_) => (started.elapsed(), false),
+        Err(e) if e.is_timeout() => (timeout, true),
+        Err(_) => return,
+    };
+    let Some(host) = host_key(url) else {
+        return;
+    };
+
+    if !state.host_latency.contains_key(&host) && state.host_latency.len() >= MAX_TRACKED_HOSTS {
+        let oldest = state.host_latency.iter().min_by_key(|entry| entry.last_seen).map(|entry| entry.key().clone());
+        if let Some(oldest) = oldest {
+            state.host_latency.remove(&oldest);
+        }
+    }
+    let mut history = state.host_latency.entry(host).or_default();
+    if history.samples.len() >= WINDOW_SIZE {
+        history.samples.pop_front();
+    }
+    history.samples.push_back(elapsed.as_millis() as u64);
+    history.timeouts += u64::from(timed_out);
+    history.last_seen = now_millis();
+}
+
+pub fn logic_get_host_latency_stats(state: &ProxyState) -> HostLatencyReport {
+    let config = AdaptiveTimeoutConfig::clone(&state.adaptive_timeouts.load());
+    let mut hosts: Vec<HostLatencyStats> = state
+        .host_latency
+        .iter()
+        .map(|entry| {
+            let history = entry.value();
+            HostLatencyStats {
+                host: entry.key().clone(),
+                samples: history.samples.len(),
+                timeouts: history.timeouts,
+                p50_ms: history.percentile(50),
+                p95_ms: history.percentile(95),
+                max_ms: history.samples.iter().max().copied(),
+                interactive_timeout_ms: timeout_from(Some(history), &config, RequestPriority::Interactive).as_millis() as u64,
+                background_timeout_ms: timeout_from(Some(history), &config, RequestPriority::Background).as_millis() as u64,
+                last_seen: history.last_seen,
+            }
+        })
+        .collect();
+    hosts.sort_by(|a, b| a.host.cmp(&b.host));
+    HostLatencyReport { config, hosts }
+}
+
+pub fn logic_set_adaptive_timeouts(config: AdaptiveTimeoutConfig, state: &ProxyState) -> Result<(), String> {
+    if !config.factor.is_finite() || config.factor < 1.0 {
+        return Err(format!("Timeout factor must be at least 1, got {}", config.factor));
+    }
+    for clamp in [config.interactive, config.background] {
+        if clamp.floor_ms == 0 || clamp.floor_ms > clamp.ceiling_ms {
+            return Err(format!("Invalid timeout bounds {}..{} ms", clamp.floor_ms, clamp.ceiling_ms));
+        }
+    }
+    println!("[latency::set_adaptive_timeouts] Adaptive timeouts {}", if config.enabled { "enabled" } else { "disabled" });
+    state.adaptive_timeouts.store(std::sync::Arc::new(config));
+    Ok(())
+}
+
+pub fn logic_export_host_latency(state: &ProxyState) -> HostLatencyExport {
+    HostLatencyExport {
+        version: HOST_LATENCY_VERSION,
+        config: AdaptiveTimeoutConfig::clone(&state.adaptive_timeouts.load()),
+        hosts: state.host_latency.iter().map(|entry| (entry.key().clone(), entry.value().clone())).collect(),
+    }
+}
+
+/// Replaces the latency history and config, e.g. when restoring them at startup
+pub fn logic_import_host_latency(export: HostLatencyExport, state: &ProxyState) -> Result<usize, String> {
+    if export.version > HOST_LATENCY_VERSION {
+        return Err(format!("Latency history version {} is newer than supported ({})", export.version, HOST_LATENCY_VERSION));
+    }
+    logic_set_adaptive_timeouts(export.config, state)?;
+
+    let mut hosts: Vec<(String, HostLatency)> = export
+        .hosts
+        .into_iter()
+        .map(|(host, mut history)| {
+            while history.samples.len() > WINDOW_SIZE {
+                history.samples.pop_front();
+            }
+            (host_of_domain_key(&host), history)
+        })
+        .filter(|(host, _)| !host.is_empty())
+        .collect();
+    // Most recently seen kept when over the limit
+    hosts.sort_by_key(|(_, history)| std::cmp::Reverse(history.last_seen));
+    hosts.truncate(MAX_TRACKED_HOSTS);
+
+    let count = hosts.len();
+    state.host_latency.clear();
+    for (host, history) in hosts {
+        state.host_latency.insert(host, history);
+    }
+    println!("[latency::import_host_latency] Imported latencies of {} hosts", count);
+    Ok(count)
+}
+
+/// Removes the latency history of `domain` and its subdomains
+pub fn clear_latency_for_domain(domain: &str, dry_run: bool, state: &ProxyState) -> MutationReport {
+    let mut report = MutationReport::new(dry_run);
+    let host = host_of_domain_key(domain);
+    let matching: Vec<String> = state.host_latency.iter().filter(|entry| host_in_domain(entry.key(), &host)).map(|entry| entry.key().clone()).collect();
+    for key in matching {
+        report.record("host_latency", key.clone(), None);
+        if !dry_run {
+            state.host_latency.remove(&key);
+        }
+    }
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::check_clear_for_domain;
+
+    #[test]
+    fn clears_the_latency_of_a_domain() {
+        check_clear_for_domain(
+            |state, host| {
+                state.host_latency.insert(host.to_string(), HostLatency::default());
+            },
+            clear_latency_for_domain,
+            |state| state.host_latency.iter().map(|entry| entry.key().clone()).collect(),
+        );
+    }
+}
@@ -1,2 +1,46 @@
 pub mod shared;
 pub mod proxy;
+pub mod transfer;
+pub mod chaos;
+pub mod images;
+pub mod icons;
+pub mod site_config;
+pub mod domains;
+pub mod feed;
+pub mod mixed_content;
+pub mod versions;
+pub mod messages;
+pub mod export;
+pub mod catalog;
+pub mod rendered;
+pub mod structure;
+pub mod maintenance;
+pub mod prefetch;
+pub mod reading_level;
+pub mod startup;
+pub mod host_stats;
+pub mod page_reports;
+pub mod liveblog;
+pub mod callouts;
+pub mod provenance;
+pub mod latency;
+pub mod summary;
+pub mod element_filters;
+pub mod supervisor;
+pub mod compression;
+pub mod dates;
+pub mod single_file;
+pub mod translation;
+pub mod extractors;
+pub mod unread;
+pub mod credentials;
+pub mod actions;
+pub mod caching;
+pub mod charts;
+pub mod preconnect;
+pub mod connectivity;
+pub mod embeds;
+pub mod pagination;
+pub mod resource_cache;
+#[cfg(test)]
+mod test_support;
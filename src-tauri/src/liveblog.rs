@@ -0,0 +1,194 @@
+use crate::dates::timestamp_of_date;
+use crate::shared::{
+    clean_embedded_html, escape_html, json_ld_has_type, json_ld_nodes, json_ld_text, logic_fetch_article, logic_fetch_raw_html, ArticleOptions,
+    ArticleResult, ProxyState,
+};
+use scraper::{ElementRef, Html, Selector};
+use serde::Serialize;
+use std::collections::HashMap;
+use url::Url;
+
+/// Live-blog containers, most specific first
+const CONTAINER_SELECTORS: &[&str] = &[
+    r#"[itemtype*="LiveBlogPosting"]"#,
+    ".liveblog",
+    ".live-blog",
+    "[data-liveblog]",
+    "#liveblog",
+    "#live-blog",
+];
+
+/// Entries inside a container; the first selector matching enough entries wins
+const ENTRY_SELECTORS: &[&str] = &[
+    r#"[itemprop="liveBlogUpdate"]"#,
+    ".liveblog-entry",
+    ".live-blog-entry",
+    ".liveblog__entry",
+    ".live-blog__entry",
+    ".live-post",
+    "article",
+];
+
+/// Entries needed to call a marked container (or JSON-LD) a live blog
+const MIN_MARKED_ENTRIES: usize = 2;
+
+/// Timestamped sibling `<article>`s needed to call an unmarked page a live blog
+const MIN_ARTICLE_ENTRIES: usize = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LiveBlogSource {
+    /// Live-blog markup (`.liveblog`, `LiveBlogPosting` microdata, ...)
+    Container,
+    /// `liveBlogUpdate` of a JSON-LD `LiveBlogPosting`
+    JsonLd,
+    /// Sibling `<article>`s carrying a `<time datetime>`
+    Articles,
+}
+
+/// One update of a live blog
+#[derive(Debug, Clone, Serialize)]
+pub struct LiveBlogEntry {
+    pub id: Option<String>,
+    /// Timestamp as published by the page
+    pub published: Option<String>,
+    /// `published` as Unix time (ms), when `dates::parse_date` reads it
+    pub timestamp: Option<i64>,
+    pub headline: Option<String>,
+    /// Entry HTML, scripts and forms removed, links and images absolute
+    pub content: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct LiveBlog {
+    pub url: String,
+    /// A live-blog structure was found. When not, `article` holds the normal extraction.
+    pub live_blog: bool,
+    pub source: Option<LiveBlogSource>,
+    /// Newest first; entries without a timestamp keep their page order, last
+    pub entries: Vec<LiveBlogEntry>,
+    pub article: Option<ArticleResult>,
+}
+
+fn first_text(entry: &ElementRef, selector: &str) -> Option<String> {
+    let selector = Selector::parse(selector).ok()?;
+    entry.select(&selector).map(|el| el.text().collect::<String>().split_whitespace().collect::<Vec<_>>().join(" ")).find(|t| !t.is_empty())
+}
+
+/// Publication time of an entry: `<time datetime>`, `datePublished` microdata, or the text of
+/// a `<time>` ("14h32", "il y a 5 minutes")
+fn entry_published(entry: &ElementRef) -> Option<String> {
+    let selector = Selector::parse(r#"[itemprop="datePublished"], time[datetime]"#).unwrap();
+    entry
+        .select(&selector)
+        .find_map(|el| el.value().attr("datetime").or_else(|| el.value().attr("content")).map(str::trim).filter(|v| !v.is_empty()))
+        .map(str::to_string)
+        .or_else(|| first_text(entry, "time"))
+}
+
+fn html_entry(entry: ElementRef, base: &Url) -> LiveBlogEntry {
+    let published = entry_published(&entry);
+    LiveBlogEntry {
+        id: entry.value().id().map(str::to_string).or_else(|| entry.value().attr("data-id").map(str::to_string)),
+        timestamp: published.as_deref().and_then(timestamp_of_date),
+        published,
+        headline: first_text(&entry, r#"[itemprop="headline"], h2, h3, h4"#),
+        content: clean_embedded_html(&entry.inner_html(), base),
+    }
+}
+
+/// Entries of the first live-blog container holding enough of them
+fn container_entries(document: &Html, base: &Url) -> Option<Vec<LiveBlogEntry>> {
+    for container_selector in CONTAINER_SELECTORS {
+        let container_selector = Selector::parse(container_selector).unwrap();
+        for container in document.select(&container_selector) {
+            for entry_selector in ENTRY_SELECTORS {
+                let entry_selector = Selector::parse(entry_selector).unwrap();
+                let entries: Vec<ElementRef> = container.select(&entry_selector).collect();
+                // Entries nested in another entry (e.g. quoted posts) belong to their parent
+                let entries: Vec<ElementRef> = entries
+                    .iter()
+                    .filter(|entry| !entry.ancestors().any(|ancestor| entries.iter().any(|other| other.id() == ancestor.id())))
+                    .copied()
+                    .collect();
+                if entries.len() >= MIN_MARKED_ENTRIES {
+                    return Some(entries.into_iter().map(|entry| html_entry(entry, base)).collect());
+                }
+            }
+        }
+    }
+    None
+}
+
+/// `liveBlogUpdate` of a JSON-LD `LiveBlogPosting`
+fn json_ld_entries(document: &Html) -> Option<Vec<LiveBlogEntry>> {
+    let nodes = json_ld_nodes(document);
+    let posting = nodes.iter().find(|node| json_ld_has_type(node, &["LiveBlogPosting"]))?;
+    let updates = match posting.get("liveBlogUpdate")? {
+        serde_json::Value::Array(updates) => updates.iter().collect::<Vec<_>>(),
+        update @ serde_json::Value::Object(_) => vec![update],
+        _ => return None,
+    };
+    let entries: Vec<LiveBlogEntry> = updates
+        .into_iter()
+        .filter_map(|update| {
+            let body = json_ld_text(update.get("articleBody"))?;
+            let content = body.lines().map(str::trim).filter(|line| !line.is_empty()).map(|line| format!("<p>{}</p>", escape_html(line))).collect();
+            let published = json_ld_text(update.get("datePublished").or_else(|| update.get("dateModified")));
+            Some(LiveBlogEntry {
+                id: json_ld_text(update.get("@id")).or_else(|| json_ld_text(update.get("url"))),
+                timestamp: published.as_deref().and_then(timestamp_of_date),
+                published,
+                headline: json_ld_text(update.get("headline")),
+                content,
+            })
+        })
+        .collect();
+    (entries.len() >= MIN_MARKED_ENTRIES).then_some(entries)
+}
+
+/// Largest group of sibling `<article>`s that each carry a `<time datetime>`
+fn article_entries(document: &Html, base: &Url) -> Option<Vec<LiveBlogEntry>> {
+    let selector = Selector::parse("article").unwrap();
+    let time = Selector::parse("time[datetime]").unwrap();
+    let mut groups: HashMap<_, Vec<ElementRef>> = HashMap::new();
+    for article in document.select(&selector).filter(|article| article.select(&time).next().is_some()) {
+        if let Some(parent) = article.parent() {
+            groups.entry(parent.id()).or_default().push(article);
+        }
+    }
+    let entries = groups.into_values().max_by_key(Vec::len).filter(|entries| entries.len() >= MIN_ARTICLE_ENTRIES)?;
+    Some(entries.into_iter().map(|entry| html_entry(entry, base)).collect())
+}
+
+/// Live-blog entries of a page, with how they were found. Live-blog markup is preferred over
+/// JSON-LD since it carries the entries' HTML rather than their text.
+pub fn find_live_blog(html: &str, base: &Url) -> Option<(LiveBlogSource, Vec<LiveBlogEntry>)> {
+    let document = Html::parse_document(html);
+    let (source, mut entries) = container_entries(&document, base)
+        .map(|entries| (LiveBlogSource::Container, entries))
+        .or_else(|| json_ld_entries(&document).map(|entries| (LiveBlogSource::JsonLd, entries)))
+        .or_else(|| article_entries(&document, base).map(|entries| (LiveBlogSource::Articles, entries)))?;
+    // Stable: entries with the same (or no) timestamp keep their page order
+    entries.sort_by(|a, b| match (a.timestamp, b.timestamp) {
+        (Some(a), Some(b)) => b.cmp(&a),
+        (a, b) => b.is_some().cmp(&a.is_some()),
+    });
+    Some((source, entries))
+}
+
+/// Extracts the updates of a live blog, newest first. Pages without a live-blog structure
+/// go through the normal extraction, returned in `article`.
+pub async fn logic_fetch_live_blog(url: String, options: ArticleOptions, state: &ProxyState) -> Result<LiveBlog, String> {
+    let base = Url::parse(&url).map_err(|e| e.to_string())?;
+    let html = logic_fetch_raw_html(url.clone(), None, None, state).await?;
+
+    if let Some((source, entries)) = find_live_blog(&html, &base) {
+        println!("[liveblog::fetch_live_blog] {} entries in {} ({:?})", entries.len(), url, source);
+        return Ok(LiveBlog { url, live_blog: true, source: Some(source), entries, article: None });
+    }
+
+    println!("[liveblog::fetch_live_blog] No live blog in {}, extracting it as an article", url);
+    let article = logic_fetch_article(url.clone(), options, state).await?;
+    Ok(LiveBlog { url, live_blog: false, source: None, entries: Vec::new(), article: Some(article) })
+}
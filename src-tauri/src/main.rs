@@ -3,77 +3,918 @@
     windows_subsystem = "windows"
 )]
 
+use std::sync::atomic::Ordering;
 use std::sync::{Arc, Mutex};
-use tauri::{command, AppHandle, Manager, State};
+use tauri::{command, AppHandle, Emitter, Manager, State};
 use url::Url;
 use reqwest::header::USER_AGENT; // Keep for now if used locally, or remove if not
 use reqwest::cookie::Jar;
 use shadcn_feed_reader::shared::{
-    ProxyState, LoginRequest, LoginResponse,
-    logic_fetch_article, logic_fetch_raw_html, logic_perform_form_login
+    ProxyState, LoginRequest, LoginResponse, ShareMeta, MutationReport, ArticleOptions, ArticleMetadata, ArticleResult, ReadabilityConfig, OutlinedHtml, RevealedHtml, SegmentedArticle,
+    logic_clear_proxy_auth, logic_clear_cookies,
+    logic_extract_outline, logic_extract_metadata, logic_reveal_hidden_content, logic_fetch_article, logic_fetch_article_with_config, logic_fetch_article_classified, logic_fetch_article_segmented, logic_fetch_raw_html, logic_fetch_raw_html_transfer, logic_fetch_share_metadata,
+    logic_perform_form_login, logic_set_accept_language, logic_set_force_http1, logic_set_host_crawler_retry, logic_set_host_requires_rendering, logic_set_user_agent, logic_set_user_agent_pool, logic_set_user_agent_rotation,
+    logic_set_article_sanitization
 };
-use shadcn_feed_reader::proxy;
+use shadcn_feed_reader::proxy::{self, InjectionComparison, ProxyStatsReport, ReferrerPolicy, SnapshotConfig};
+use shadcn_feed_reader::transfer::{self, TransferMode, TransferPayload};
+use shadcn_feed_reader::chaos::{self, ChaosProfile, ChaosProfileSpec};
+use shadcn_feed_reader::charts;
+use shadcn_feed_reader::preconnect;
+use shadcn_feed_reader::connectivity::{self, ConnectivityStatus, QueuedAction};
+use shadcn_feed_reader::images::{self, CaptionedImage, ImageProbe};
+use shadcn_feed_reader::icons::{self, FeedIcon, FeedIconRequest};
+use shadcn_feed_reader::site_config::{self, SiteConfigLoadReport};
+use shadcn_feed_reader::domains::{self, DomainProfile};
+use shadcn_feed_reader::catalog::{self, CatalogCategory, CatalogEntry};
+use shadcn_feed_reader::export::{self, ExportFormat, FragmentFormat};
+use shadcn_feed_reader::single_file::{self, HtmlExport, HtmlExportOptions};
+use shadcn_feed_reader::feed::{self, Feed};
+use shadcn_feed_reader::messages::{self, MessageReply, MessageSchema, MessageStats, ProtocolMessage, ScriptMessage};
+use shadcn_feed_reader::mixed_content::{self, MixedContentReport};
+use shadcn_feed_reader::rendered::{self, RenderedDomainStats};
+use shadcn_feed_reader::structure::{self, StructureOutline};
+use shadcn_feed_reader::maintenance::{self, MaintenanceOptions, MaintenanceReport};
+use shadcn_feed_reader::prefetch::{self, PrefetchPlan, PrefetchRequest, ReadingStats};
+use shadcn_feed_reader::startup::{self, Component, ComponentStatus, StartupReport};
+use shadcn_feed_reader::supervisor::{self, TaskStatus};
+use shadcn_feed_reader::compression::{self, CompressionConfig};
+use shadcn_feed_reader::extractors::{self, ExtractorBackend, ExtractorComparison, ExtractorComparisonConfig};
+use shadcn_feed_reader::unread::{self, BadgeMode, UnreadBadge, UnreadEstimate};
+use shadcn_feed_reader::credentials::{self, AuthMethod, CredentialAudit, CredentialMigrationReport};
+use shadcn_feed_reader::actions::{self, ArticleAction, ArticleActionConfig, ArticleActionParams, ArticleActionResult};
+use shadcn_feed_reader::translation::{self, ArticleTranslation, FeedTranslation, FeedTranslationStatus, TranslationConfig, TranslationUsage};
+use shadcn_feed_reader::host_stats::{self, HostStats, HostStatsExport};
+use shadcn_feed_reader::summary;
+use shadcn_feed_reader::element_filters::{self, ElementFilter, ElementFiltersExport, FilterPreview};
+use shadcn_feed_reader::latency::{self, AdaptiveTimeoutConfig, HostLatencyExport, HostLatencyReport};
+use shadcn_feed_reader::page_reports::{self, PageReport};
+use shadcn_feed_reader::liveblog::{self, LiveBlog};
+use shadcn_feed_reader::versions::{self, ArticleVersion, ArticleVersionInfo};
 
 const FALLBACK_SIGNAL: &str = "READABILITY_FAILED_FALLBACK";
 
 
 
 #[command]
-async fn start_proxy(app_handle: AppHandle) -> Result<u16, String> {
-    let state: tauri::State<ProxyState> = app_handle.state();
-
-    // Check if proxy is already running
-    {
-        let port_guard = state.port.lock().unwrap();
-        if let Some(existing_port) = *port_guard {
-            return Ok(existing_port);
-        }
-    } // Lock is released here before await
+async fn start_proxy(state: State<'_, ProxyState>) -> Result<u16, String> {
+    startup::logic_start_proxy(&state).await
+}
 
-    // Start new proxy server
-    let port = proxy::start_proxy_server(state.inner().clone()).await;
+/// Components started with the app, and why the disabled ones failed
+#[command]
+fn get_startup_report(state: State<ProxyState>) -> StartupReport {
+    startup::logic_get_startup_report(&state)
+}
 
-    // Store the port in the state
-    let mut port_guard = state.port.lock().unwrap();
-    *port_guard = Some(port);
+/// Start a disabled component again without restarting the app
+#[command]
+async fn retry_component(name: Component, state: State<'_, ProxyState>) -> Result<ComponentStatus, String> {
+    Ok(startup::logic_retry_component(name, &state).await)
+}
 
-    Ok(port)
+/// Supervised background tasks: running, restarting or failed, with their last error
+#[command]
+fn get_task_status(state: State<ProxyState>) -> Vec<TaskStatus> {
+    supervisor::logic_get_task_status(&state)
 }
 
+/// Start a proxy session for `url`. `referrer_policy` (default `full`) decides the Referer
+/// sent to the article's third-party hosts until the next session.
 #[command]
-fn set_proxy_url(url: String, state: State<ProxyState>) -> Result<(), String> {
+fn set_proxy_url(url: String, referrer_policy: Option<ReferrerPolicy>, state: State<ProxyState>) -> Result<(), String> {
     let new_url = Url::parse(&url).map_err(|e| e.to_string())?;
-    let mut base_url = state.base_url.lock().unwrap();
-    *base_url = new_url;
+    state.base_url.store(Arc::new(new_url));
+    state.referrer_policy.store(Arc::new(referrer_policy.unwrap_or_default()));
     Ok(())
 }
 
 #[command]
 fn set_proxy_auth(domain: String, username: String, password: String, state: State<ProxyState>) -> Result<(), String> {
-    let mut credentials = state.auth_credentials.lock().unwrap();
-    credentials.insert(domain.clone(), (username, password));
-    println!("Set auth credentials for domain: {}", domain);
+    credentials::logic_set_proxy_auth(domain, username, password, &state)
+}
+
+/// Credentials of any method for `domain`: `{"basic": {"user", "pass"}}`, `{"bearer": "token"}`
+/// or `{"header": {"name", "value"}}`. Replaces whatever was set for it before.
+#[command]
+fn set_auth(domain: String, method: AuthMethod, state: State<ProxyState>) -> Result<(), String> {
+    credentials::logic_set_auth(domain, method, &state)
+}
+
+/// Re-key the credentials restored by the frontend to the origins requests are matched with.
+/// Conflicting keys are reported for the user to resolve; the old store is kept until
+/// `confirm_credential_migration` or `rollback_credential_migration`.
+#[command]
+fn migrate_credentials(state: State<ProxyState>) -> CredentialMigrationReport {
+    credentials::logic_migrate_credentials(&state)
+}
+
+/// Pick which old key's credentials a conflicting origin uses
+#[command]
+fn resolve_credential_conflict(origin: String, old_key: String, state: State<ProxyState>) -> Result<(), String> {
+    credentials::logic_resolve_credential_conflict(origin, old_key, &state)
+}
+
+#[command]
+fn confirm_credential_migration(state: State<ProxyState>) -> Result<(), String> {
+    credentials::logic_confirm_credential_migration(&state)
+}
+
+#[command]
+fn rollback_credential_migration(state: State<ProxyState>) -> Result<(), String> {
+    credentials::logic_rollback_credential_migration(&state)
+}
+
+/// Every transformation the credential migration made, with the conflicts left to resolve
+#[command]
+fn get_credential_audit(state: State<ProxyState>) -> CredentialAudit {
+    credentials::logic_get_credential_audit(&state)
+}
+
+/// Clear the credentials of a domain
+#[command]
+fn clear_proxy_auth(domain: String, dry_run: Option<bool>, state: State<ProxyState>) -> Result<MutationReport, String> {
+    Ok(logic_clear_proxy_auth(domain, dry_run.unwrap_or(false), &state))
+}
+
+/// Clear cookies for a domain (or all cookies when no domain is given)
+#[command]
+fn clear_cookies(domain: Option<String>, dry_run: Option<bool>, state: State<ProxyState>) -> Result<MutationReport, String> {
+    Ok(logic_clear_cookies(domain, dry_run.unwrap_or(false), &state))
+}
+
+/// Everything stored for a domain (credentials, cookies, site rules, cached icon), secrets redacted
+#[command]
+fn get_domain_profile(domain: String, state: State<ProxyState>) -> Result<DomainProfile, String> {
+    Ok(domains::logic_get_domain_profile(domain, &state))
+}
+
+/// Clear everything stored for a domain across all stores
+#[command]
+fn reset_domain(domain: String, dry_run: Option<bool>, state: State<ProxyState>) -> Result<MutationReport, String> {
+    Ok(domains::logic_reset_domain(domain, dry_run.unwrap_or(false), &state))
+}
+
+/// Remove a domain's stored credentials and cookies; `true` when anything was removed
+#[command]
+fn forget_domain(domain: String, state: State<ProxyState>) -> Result<bool, String> {
+    domains::logic_forget_domain(domain, &state)
+}
+
+/// Drop expired one-shot transfer handles
+#[command]
+fn prune_transfers(dry_run: Option<bool>, state: State<ProxyState>) -> Result<MutationReport, String> {
+    Ok(transfer::prune_expired_transfers(dry_run.unwrap_or(false), &state))
+}
+
+/// Enable failure injection (dev builds only): a preset name ("slow", "flaky", "hostile")
+/// or a full profile, optionally scoped to some domains
+#[command]
+fn enable_chaos(profile: ChaosProfileSpec, state: State<ProxyState>) -> Result<ChaosProfile, String> {
+    chaos::enable_chaos(profile, &state)
+}
+
+#[command]
+fn disable_chaos(state: State<ProxyState>) -> Result<(), String> {
+    chaos::disable_chaos(&state);
+    Ok(())
+}
+
+#[command]
+async fn fetch_raw_html(url: String, timeout_secs: Option<u64>, user_agent: Option<String>, state: State<'_, ProxyState>) -> Result<String, String> {
+    logic_fetch_raw_html(url, timeout_secs, user_agent, &state).await
+}
+
+/// Fetch raw HTML, returning large pages as a one-shot URL on the local proxy
+/// instead of a multi-megabyte IPC string (`transfer`: "inline" | "handle")
+#[command]
+async fn fetch_raw_html_transfer(url: String, transfer: Option<TransferMode>, state: State<'_, ProxyState>) -> Result<TransferPayload, String> {
+    logic_fetch_raw_html_transfer(url, transfer, &state).await
+}
+
+/// Extract the article: its content, plain text and word count, title, byline and other
+/// metadata, and a `fallback` flag when the page should be shown through the iframe instead
+#[command]
+async fn fetch_article(url: String, options: Option<ArticleOptions>, state: State<'_, ProxyState>) -> Result<ArticleResult, String> {
+    logic_fetch_article(url, options.unwrap_or_default(), &state).await
+}
+
+/// Like `fetch_article`, with fallback thresholds overriding the strictness preset for this
+/// fetch (e.g. a lower minimum for short-form blogs)
+#[command]
+async fn fetch_article_with_config(url: String, options: Option<ArticleOptions>, config: ReadabilityConfig, state: State<'_, ProxyState>) -> Result<ArticleResult, String> {
+    logic_fetch_article_with_config(url, options.unwrap_or_default(), config, &state).await
+}
+
+/// Extract the article and convert it to Markdown. Links and images get absolute URLs.
+#[command]
+async fn fetch_article_markdown(url: String, options: Option<ArticleOptions>, state: State<'_, ProxyState>) -> Result<String, String> {
+    export::logic_fetch_article_as(url, options.unwrap_or_default(), ExportFormat::Markdown, &state).await
+}
+
+/// Extract the article and convert it to AsciiDoc
+#[command]
+async fn fetch_article_asciidoc(url: String, options: Option<ArticleOptions>, state: State<'_, ProxyState>) -> Result<String, String> {
+    export::logic_fetch_article_as(url, options.unwrap_or_default(), ExportFormat::AsciiDoc, &state).await
+}
+
+/// Extract the article and convert it to reStructuredText
+#[command]
+async fn fetch_article_rst(url: String, options: Option<ArticleOptions>, state: State<'_, ProxyState>) -> Result<String, String> {
+    export::logic_fetch_article_as(url, options.unwrap_or_default(), ExportFormat::Rst, &state).await
+}
+
+/// Save the article as a single HTML file that opens in any browser without network access:
+/// images embedded, reader styles inlined, source URL and capture time in a footer
+#[command]
+async fn export_article_html(url: String, path: String, options: Option<HtmlExportOptions>, state: State<'_, ProxyState>) -> Result<HtmlExport, String> {
+    single_file::logic_export_article_html(url, path, options.unwrap_or_default(), &state).await
+}
+
+/// Convert HTML copied from a selection (reader view or proxied page) to clean HTML, Markdown
+/// or plain text for the clipboard, with proxy URLs turned back into the original ones
+#[command]
+fn convert_fragment(html: String, format: FragmentFormat, state: State<ProxyState>) -> Result<String, String> {
+    export::logic_convert_fragment(html, format, &state)
+}
+
+/// Extract the article with its pull-quotes, callouts and key-takeaway panels marked
+/// (`data-block-kind`) and listed in `blocks`, so the reader can style them
+#[command]
+async fn fetch_article_classified(url: String, options: Option<ArticleOptions>, state: State<'_, ProxyState>) -> Result<ArticleResult, String> {
+    logic_fetch_article_classified(url, options.unwrap_or_default(), &state).await
+}
+
+/// Extract the article and outline its structure for accessibility tooling: nested sections,
+/// landmarks (figures, quotes, lists, code), heading-level skips and alt-text coverage
+#[command]
+async fn fetch_article_structure(url: String, options: Option<ArticleOptions>, state: State<'_, ProxyState>) -> Result<StructureOutline, String> {
+    structure::logic_fetch_article_structure(url, options.unwrap_or_default(), &state).await
+}
+
+/// Extract the updates of a live blog, newest first, or the normal extraction (in `article`)
+/// when the page has no live-blog structure
+#[command]
+async fn fetch_live_blog(url: String, options: Option<ArticleOptions>, state: State<'_, ProxyState>) -> Result<LiveBlog, String> {
+    liveblog::logic_fetch_live_blog(url, options.unwrap_or_default(), &state).await
+}
+
+/// Extract the article and probe each of its images (HEAD through the proxy's client),
+/// classifying failures so the UI can drop dead images or retry with another Referer
+#[command]
+async fn probe_article_images(url: String, options: Option<ArticleOptions>, state: State<'_, ProxyState>) -> Result<Vec<ImageProbe>, String> {
+    images::logic_probe_article_images(url, options.unwrap_or_default(), &state).await
+}
+
+/// Extract the article and pair each of its images with its caption (`<figcaption>`,
+/// `aria-describedby`, adjacent `<small>`) and `alt`, for captioned galleries and alt-text audits
+#[command]
+async fn fetch_image_captions(url: String, options: Option<ArticleOptions>, state: State<'_, ProxyState>) -> Result<Vec<CaptionedImage>, String> {
+    images::logic_fetch_image_captions(url, options.unwrap_or_default(), &state).await
+}
+
+/// Fetch and parse an RSS/Atom feed, each item classified by `kind` (article, podcast, video, ...).
+/// With `max_items`, only the newest items are kept; the others are dropped, not paginated.
+#[command]
+async fn fetch_feed(url: String, max_items: Option<usize>, state: State<'_, ProxyState>) -> Result<Feed, String> {
+    feed::logic_fetch_feed(url, max_items, &state).await
+}
+
+/// Icon for a feed: the site's favicon, or a generated monogram when it has none.
+/// `tier` tells which step of the fallback chain produced it.
+#[command]
+async fn fetch_feed_icon(request: FeedIconRequest, state: State<'_, ProxyState>) -> Result<FeedIcon, String> {
+    icons::logic_fetch_feed_icon(request, &state).await
+}
+
+/// Load every ftr-site-config rule file (`<host>.txt`) of a directory
+#[command]
+fn load_site_configs(directory: String, state: State<ProxyState>) -> Result<SiteConfigLoadReport, String> {
+    site_config::logic_load_site_configs(directory, &state)
+}
+
+/// Load ftr-site-config rules for a single host (`.example.com` applies to every subdomain)
+#[command]
+fn load_site_config(host: String, rules: String, state: State<ProxyState>) -> Result<SiteConfigLoadReport, String> {
+    Ok(site_config::logic_load_site_config(host, rules, &state))
+}
+
+/// Outline (h1–h4) of already extracted HTML, with ids added to the headings
+#[command]
+fn extract_outline(html: String) -> Result<OutlinedHtml, String> {
+    logic_extract_outline(html)
+}
+
+/// Title, byline, publication date and preview fields of a page's HTML (e.g. a rendered
+/// fallback page), read from its tags
+#[command]
+fn extract_metadata(html: String, url: String) -> Result<ArticleMetadata, String> {
+    logic_extract_metadata(html, url)
+}
+
+/// Reveal the rest of an article hidden behind a "read more" toggle in raw page HTML
+#[command]
+fn reveal_hidden_content(html: String) -> Result<RevealedHtml, String> {
+    logic_reveal_hidden_content(html)
+}
+
+/// Like `fetch_article`, with every block tagged with its cumulative word offset
+/// (`data-offset`) and the total word count, for scroll-depth tracking
+#[command]
+async fn fetch_article_segmented(url: String, options: Option<ArticleOptions>, state: State<'_, ProxyState>) -> Result<SegmentedArticle, String> {
+    logic_fetch_article_segmented(url, options.unwrap_or_default(), &state).await
+}
+
+/// Extract the article and summarize it locally: its most representative sentences, in order
+#[command]
+async fn summarize_article(url: String, max_sentences: usize, state: State<'_, ProxyState>) -> Result<String, String> {
+    summary::logic_summarize_article(url, max_sentences, &state).await
+}
+
+/// Set the maximum decompressed body size (in bytes) accepted from upstream servers
+#[command]
+fn set_max_body_size(bytes: usize, state: State<ProxyState>) -> Result<(), String> {
+    if bytes == 0 {
+        return Err("Max body size must be greater than zero".into());
+    }
+    state.max_body_size.store(bytes, Ordering::Relaxed);
+    Ok(())
+}
+
+/// Set the `Content-Length` (in bytes) past which proxied responses are streamed as is
+/// instead of being buffered for HTML or CSS rewriting
+#[command]
+fn set_max_rewrite_size(bytes: usize, state: State<ProxyState>) -> Result<(), String> {
+    if bytes == 0 {
+        return Err("Max rewrite size must be greater than zero".into());
+    }
+    state.max_rewrite_size.store(bytes, Ordering::Relaxed);
+    Ok(())
+}
+
+/// Replace the User-Agent of upstream requests (e.g. to mimic a mobile browser for a site
+/// that misbehaves); omitted or empty restores the default
+#[command]
+fn set_user_agent(user_agent: Option<String>, state: State<ProxyState>) -> Result<(), String> {
+    logic_set_user_agent(user_agent, &state)
+}
+
+/// Replace the pool of User-Agent strings used when rotation is enabled
+#[command]
+fn set_user_agent_pool(agents: Vec<String>, state: State<ProxyState>) -> Result<(), String> {
+    logic_set_user_agent_pool(agents, &state);
+    Ok(())
+}
+
+/// Toggle round-robin User-Agent rotation across the fetch and proxy paths
+#[command]
+fn set_user_agent_rotation(enabled: bool, state: State<ProxyState>) -> Result<(), String> {
+    logic_set_user_agent_rotation(enabled, &state);
+    Ok(())
+}
+
+/// Strip scripts, unknown iframes, event handlers and tracking pixels from extracted articles
+/// (on by default). Turn off to keep readability's output as is, embeds of any origin included.
+#[command]
+fn set_article_sanitization(enabled: bool, state: State<ProxyState>) {
+    logic_set_article_sanitization(enabled, &state);
+}
+
+/// Set the preferred languages (most preferred first) sent to `domain` and its subdomains.
+/// An empty list restores the default. Returns the resulting `Accept-Language` header.
+#[command]
+fn set_accept_language(domain: String, languages: Vec<String>, state: State<ProxyState>) -> Result<Option<String>, String> {
+    logic_set_accept_language(domain, languages, &state)
+}
+
+/// Always use the JS-rendered path for `host` and its subdomains: `fetch_article` returns
+/// the fallback signal right away instead of trying direct extraction first
+#[command]
+fn set_host_requires_rendering(host: String, requires_rendering: bool, state: State<ProxyState>) -> Result<(), String> {
+    logic_set_host_requires_rendering(host, requires_rendering, &state)
+}
+
+/// Retry teaser-length extractions of `host` and its subdomains as Googlebot, without cookies
+/// (sites serving crawlers the full article). The article's provenance tells when it happened.
+#[command]
+fn set_host_crawler_retry(host: String, enabled: bool, state: State<ProxyState>) -> Result<(), String> {
+    logic_set_host_crawler_retry(host, enabled, &state)
+}
+
+/// Use HTTP/1.1 only for `domain` and its subdomains, or for every request when `domain` is
+/// omitted. For servers that hang or fail under HTTP/2.
+#[command]
+fn set_force_http1(domain: Option<String>, enabled: bool, state: State<ProxyState>) -> Result<(), String> {
+    logic_set_force_http1(domain, enabled, &state)
+}
+
+/// Replace Datawrapper and Flourish embeds by a static image linking to the live chart, for
+/// every article when `domain` is omitted, else opt `domain` and its subdomains in or out
+#[command]
+fn set_chart_snapshots(domain: Option<String>, enabled: bool, state: State<ProxyState>) -> Result<(), String> {
+    charts::logic_set_chart_snapshots(domain, enabled, &state)
+}
+
+/// Warm connections to an article's origin and its usual CDN hosts (on item hover or selection)
+#[command]
+async fn preconnect(url: String, state: State<'_, ProxyState>) -> Result<Vec<String>, String> {
+    preconnect::logic_preconnect(url, &state).await
+}
+
+/// Metered connection: skip work done ahead of the user, such as preconnects
+#[command]
+fn set_metered_mode(enabled: bool, state: State<ProxyState>) {
+    preconnect::logic_set_metered_mode(enabled, &state);
+}
+
+/// Go offline right away (caches only, no upstream requests), or back to automatic detection
+#[command]
+fn set_offline_mode(enabled: bool, state: State<ProxyState>) -> ConnectivityStatus {
+    connectivity::logic_set_offline_mode(enabled, &state)
+}
+
+#[command]
+fn get_connectivity(state: State<ProxyState>) -> ConnectivityStatus {
+    connectivity::logic_get_connectivity(&state)
+}
+
+/// Article actions recorded offline, run once the connection is back
+#[command]
+fn list_queued_actions(state: State<ProxyState>) -> Vec<QueuedAction> {
+    connectivity::logic_list_queued_actions(&state)
+}
+
+/// Refuse plain-http subresources of https pages instead of proxying them
+#[command]
+fn set_mixed_content_strict(enabled: bool, state: State<ProxyState>) -> Result<(), String> {
+    mixed_content::logic_set_mixed_content_strict(enabled, &state);
+    Ok(())
+}
+
+/// Plain-http subresources upgraded, proxied or blocked on the last https page loaded through the proxy
+#[command]
+fn get_mixed_content_report(state: State<ProxyState>) -> Option<MixedContentReport> {
+    mixed_content::logic_get_mixed_content_report(&state)
+}
+
+/// Keep dated versions of an article each time it's extracted (starred or archived articles).
+/// Starred articles always keep their first and latest versions when pruning.
+#[command]
+fn track_article_versions(url: String, starred: bool, state: State<ProxyState>) -> Result<(), String> {
+    versions::logic_track_article_versions(url, starred, &state);
     Ok(())
 }
 
+/// Stop keeping versions of an article and drop the stored ones
 #[command]
-fn clear_proxy_auth(domain: String, state: State<ProxyState>) -> Result<(), String> {
-    let mut credentials = state.auth_credentials.lock().unwrap();
-    credentials.remove(&domain);
-    println!("Cleared auth credentials for domain: {}", domain);
+fn untrack_article_versions(url: String, state: State<ProxyState>) -> Result<(), String> {
+    versions::logic_untrack_article_versions(url, &state);
     Ok(())
 }
 
+/// Stored versions of an article (timestamp, hash, size), oldest first
+#[command]
+fn list_article_versions(url: String, state: State<ProxyState>) -> Vec<ArticleVersionInfo> {
+    versions::logic_list_article_versions(url, &state)
+}
+
+/// Content of the version of an article stored at `timestamp`
+#[command]
+fn get_article_version(url: String, timestamp: u64, state: State<ProxyState>) -> Result<ArticleVersion, String> {
+    versions::logic_get_article_version(url, timestamp, &state)
+}
+
+/// Compression of stored article HTML and large transfers
+#[command]
+fn get_compression_config(state: State<ProxyState>) -> CompressionConfig {
+    compression::logic_get_compression_config(&state)
+}
+
+/// Turn compression on or off and set its level. Entries already stored are compressed
+/// lazily, when their store is next written to.
+#[command]
+fn set_compression_config(config: CompressionConfig, state: State<ProxyState>) -> Result<(), String> {
+    compression::logic_set_compression_config(config, &state)
+}
+
+/// Unread items as the backend estimates them: the frontend's last count, adjusted by the
+/// items found in fetched feeds and the ones marked read since
+#[command]
+fn get_unread_estimate(state: State<ProxyState>) -> UnreadEstimate {
+    unread::logic_get_unread_estimate(&state)
+}
+
+/// Items the user read (guid, else link, else title), taken off the badge
+#[command]
+fn mark_items_read(ids: Vec<String>, state: State<ProxyState>) -> UnreadEstimate {
+    unread::logic_mark_items_read(ids, &state)
+}
+
+/// The authoritative unread count, e.g. after syncing with a remote backend
 #[command]
-async fn fetch_raw_html(url: String, state: State<'_, ProxyState>) -> Result<String, String> {
-    logic_fetch_raw_html(url, &state).await
+fn set_unread_count(count: usize, state: State<ProxyState>) -> UnreadEstimate {
+    unread::logic_set_unread_count(count, &state)
 }
 
+/// What the dock badge, taskbar overlay and tray show: `count`, `dot` or `off`
 #[command]
-async fn fetch_article(url: String) -> Result<String, String> {
-    logic_fetch_article(url).await
+fn set_badge_mode(mode: BadgeMode, state: State<ProxyState>) {
+    unread::logic_set_badge_mode(mode, &state)
 }
 
+/// Id of the tray icon showing the unread count
+const TRAY_ID: &str = "main";
+
+/// Red dot for the Windows taskbar overlay, which can't show a number
+#[cfg(target_os = "windows")]
+fn overlay_dot() -> tauri::image::Image<'static> {
+    const SIZE: u32 = 16;
+    let mut rgba = Vec::with_capacity((SIZE * SIZE * 4) as usize);
+    for y in 0..SIZE {
+        for x in 0..SIZE {
+            let (dx, dy) = (x as f32 - 7.5, y as f32 - 7.5);
+            let alpha = if dx * dx + dy * dy <= 56.0 { 255 } else { 0 };
+            rgba.extend_from_slice(&[220, 38, 38, alpha]);
+        }
+    }
+    tauri::image::Image::new_owned(rgba, SIZE, SIZE)
+}
+
+/// Shows `badge` on the dock (macOS), launcher (Linux), taskbar overlay (Windows) and tray
+fn apply_unread_badge(app_handle: &AppHandle, badge: UnreadBadge) {
+    if let Some(tray) = app_handle.tray_by_id(TRAY_ID) {
+        let tooltip = badge.tooltip().map_or_else(|| "Shadcn Feed Reader".to_string(), |unread| format!("Shadcn Feed Reader: {}", unread));
+        let _ = tray.set_tooltip(Some(tooltip));
+    }
+    // Hidden windows keep their badge, so it updates while the window is closed to the tray
+    let Some(window) = app_handle.get_webview_window("main") else {
+        return;
+    };
+    let shown = match badge.mode {
+        _ if badge.count == 0 => None,
+        BadgeMode::Off => None,
+        BadgeMode::Count => Some(badge.count),
+        BadgeMode::Dot => Some(0),
+    };
+    #[cfg(target_os = "macos")]
+    let result = window.set_badge_label(shown.map(|count| if count == 0 { "•".to_string() } else { count.to_string() }));
+    #[cfg(target_os = "linux")]
+    let result = window.set_badge_count(shown.map(|count| count.max(1) as i64));
+    #[cfg(target_os = "windows")]
+    let result = window.set_overlay_icon(shown.map(|_| overlay_dot()));
+    #[cfg(not(any(target_os = "macos", target_os = "linux", target_os = "windows")))]
+    let result: tauri::Result<()> = Ok(());
+    if let Err(e) = result {
+        println!("[main::apply_unread_badge] {}", e);
+    }
+}
+
+/// Win rates of the extractor backends per domain, from the sampled comparisons, with the
+/// backend each domain uses
+#[command]
+fn get_extractor_comparison(state: State<ProxyState>) -> ExtractorComparison {
+    extractors::logic_get_extractor_comparison(&state)
+}
+
+/// Share of extractions on which both backends are compared, and whether domains switch to
+/// the backend winning their comparisons
+#[command]
+fn set_extractor_comparison_config(config: ExtractorComparisonConfig, state: State<ProxyState>) -> Result<(), String> {
+    extractors::logic_set_extractor_comparison_config(config, &state)
+}
+
+/// Force an extractor backend on a domain, or `null` to go back to the automatic choice
+#[command]
+fn set_domain_extractor(domain: String, backend: Option<ExtractorBackend>, state: State<ProxyState>) -> Result<(), String> {
+    extractors::logic_set_domain_extractor(domain, backend, &state)
+}
+
+/// Translation service, target (UI) language and daily character budget
+#[command]
+fn get_translation_config(state: State<ProxyState>) -> TranslationConfig {
+    translation::logic_get_translation_config(&state)
+}
+
+#[command]
+fn set_translation_config(config: TranslationConfig, state: State<ProxyState>) -> Result<(), String> {
+    translation::logic_set_translation_config(config, &state)
+}
+
+/// A feed's translation preference and the language detected on its items
+#[command]
+fn get_feed_translation(feed: String, state: State<ProxyState>) -> FeedTranslationStatus {
+    translation::logic_get_feed_translation(feed, &state)
+}
+
+/// Mark a feed "always translate": titles and summaries of its foreign-language items are
+/// translated when it's fetched
+#[command]
+fn set_feed_translation(feed: String, preference: FeedTranslation, state: State<ProxyState>) -> Result<(), String> {
+    translation::logic_set_feed_translation(feed, preference, &state)
+}
+
+/// Translate an article's content when it's opened. Fails with `TRANSLATION_BUDGET_EXCEEDED`
+/// once the day's character budget is spent.
+#[command]
+async fn translate_article(url: String, feed: Option<String>, target_language: Option<String>, state: State<'_, ProxyState>) -> Result<ArticleTranslation, String> {
+    translation::logic_translate_article(url, feed, target_language, &state).await
+}
+
+/// Characters sent to the translation service today, against the daily budget
+#[command]
+fn get_translation_usage(state: State<ProxyState>) -> TranslationUsage {
+    translation::logic_get_translation_usage(&state)
+}
+
+/// Set the storage budget (in bytes) shared by all article versions
+#[command]
+fn set_version_budget(bytes: usize, state: State<ProxyState>) -> Result<(), String> {
+    versions::logic_set_version_budget(bytes, &state);
+    Ok(())
+}
+
+/// Search the starter feed catalog (titles, tags, descriptions), optionally within a category.
+/// Entries should be checked with `fetch_feed` before subscribing.
+#[command]
+fn search_feed_catalog(query: String, category: Option<String>, state: State<ProxyState>) -> Result<Vec<CatalogEntry>, String> {
+    catalog::logic_search_feed_catalog(query, category, &state)
+}
+
+/// Categories of the starter feed catalog, with their number of feeds
+#[command]
+fn list_catalog_categories(state: State<ProxyState>) -> Result<Vec<CatalogCategory>, String> {
+    catalog::logic_list_catalog_categories(&state)
+}
+
+/// Replace the starter catalog with the signed catalog at `url`. Returns the number of feeds.
+#[command]
+async fn update_catalog(url: String, state: State<'_, ProxyState>) -> Result<usize, String> {
+    catalog::logic_update_catalog(url, &state).await
+}
+
+/// postMessage protocol between the injected script and the app: version, message types
+/// and the same constants as an ES module
+#[command]
+fn get_message_schema() -> MessageSchema {
+    messages::logic_get_message_schema()
+}
+
+/// Validate a message received from the injected script before acting on it. Unknown and
+/// malformed messages are rejected and counted. A `RENDERED_HTML` of the proxied article
+/// also starts a background extraction retry (see `extract_from_rendered`); with `transfer`
+/// ("inline" | "handle"), its HTML comes back as a transfer payload rather than a string.
+#[command]
+fn validate_message(app_handle: AppHandle, message: serde_json::Value, transfer: Option<TransferMode>, state: State<ProxyState>) -> Result<MessageReply, String> {
+    let message = messages::logic_validate_message(message, &state)?;
+    match &message {
+        ProtocolMessage::Script(ScriptMessage::RenderedHtml { html }) => {
+            spawn_rendered_extraction(app_handle, state.base_url.load().to_string(), html.clone(), ArticleOptions::default());
+        }
+        ProtocolMessage::Script(ScriptMessage::FeaturesReport { session_id, page_url, counts }) => {
+            page_reports::logic_record_features_report(session_id, page_url, *counts, &state)?;
+        }
+        _ => {}
+    }
+    Ok(messages::message_reply(message, transfer, &state))
+}
+
+/// Extracts a rendered fallback page off the main thread, emitting `extraction-now-available`
+/// when it turns out to be extractable
+fn spawn_rendered_extraction(app_handle: AppHandle, url: String, html: String, options: ArticleOptions) {
+    let state = app_handle.state::<ProxyState>().inner().clone();
+    tauri::async_runtime::spawn_blocking(move || {
+        match rendered::logic_extract_from_rendered(url, html, options, &state) {
+            Ok(Some(extraction)) => {
+                if let Err(e) = app_handle.emit(rendered::EXTRACTION_AVAILABLE_EVENT, extraction) {
+                    println!("[main::extract_from_rendered] Failed to emit event: {}", e);
+                }
+            }
+            Ok(None) => {}
+            Err(e) => println!("[main::extract_from_rendered] {}", e),
+        }
+    });
+}
+
+/// Retry extraction on the rendered HTML of a page shown through the iframe fallback (e.g. a
+/// hidden-webview capture) in the background. Emits `extraction-now-available` with the article
+/// if it passes the extraction thresholds; the article is then served by `fetch_article`.
+#[command]
+fn extract_from_rendered(app_handle: AppHandle, url: String, html: String, options: Option<ArticleOptions>) {
+    spawn_rendered_extraction(app_handle, url, html, options.unwrap_or_default());
+}
+
+/// Check every store and fix what can be fixed (unless `dry_run`), one store at a time.
+/// Emits `maintenance-progress` before each store.
+#[command]
+async fn run_maintenance(app_handle: AppHandle, options: Option<MaintenanceOptions>) -> Result<MaintenanceReport, String> {
+    let state = app_handle.state::<ProxyState>().inner().clone();
+    tauri::async_runtime::spawn_blocking(move || {
+        maintenance::logic_run_maintenance(options.unwrap_or_default(), &state, |progress| {
+            if let Err(e) = app_handle.emit(maintenance::MAINTENANCE_PROGRESS_EVENT, progress) {
+                println!("[main::run_maintenance] Failed to emit progress: {}", e);
+            }
+        })
+    })
+    .await
+    .map_err(|e| e.to_string())
+}
+
+/// Record that the user opened an article, to learn which feeds are worth prefetching
+#[command]
+fn article_opened(url: String, feed: String, local_hour: u8, state: State<ProxyState>) -> Result<(), String> {
+    prefetch::logic_article_opened(url, feed, local_hour, &state)
+}
+
+/// Which of the candidate items to prefetch, and why
+#[command]
+fn get_prefetch_plan(request: PrefetchRequest, state: State<ProxyState>) -> Result<PrefetchPlan, String> {
+    prefetch::logic_get_prefetch_plan(request, &state)
+}
+
+/// Turn smart prefetching off to prefetch the first items in order
+#[command]
+fn set_smart_prefetch(enabled: bool, state: State<ProxyState>) {
+    prefetch::logic_set_smart_prefetch(enabled, &state)
+}
+
+/// Reading stats learned for prefetching, for backups
+#[command]
+fn export_reading_stats(state: State<ProxyState>) -> ReadingStats {
+    prefetch::logic_export_reading_stats(&state)
+}
+
+/// Restore reading stats from a backup
+#[command]
+fn import_reading_stats(stats: ReadingStats, state: State<ProxyState>) -> Result<(), String> {
+    prefetch::logic_import_reading_stats(stats, &state)
+}
+
+/// Extraction outcomes (successes, fallbacks, paywalls, errors) of a host, with a suggested fix
+/// for hosts that keep falling back
+#[command]
+fn get_host_stats(host: String, state: State<ProxyState>) -> Result<HostStats, String> {
+    host_stats::logic_get_host_stats(host, &state)
+}
+
+/// Host stats to persist with the domain settings
+#[command]
+fn export_host_stats(state: State<ProxyState>) -> HostStatsExport {
+    host_stats::logic_export_host_stats(&state)
+}
+
+/// Restore persisted host stats. Returns the number of hosts.
+#[command]
+fn import_host_stats(stats: HostStatsExport, state: State<ProxyState>) -> Result<usize, String> {
+    host_stats::logic_import_host_stats(stats, &state)
+}
+
+/// Never show an element again on `domain`: `html` is the `outerHTML` of the element the user
+/// picked, from which a selector is derived. Returns the stored filter.
+#[command]
+fn add_element_filter(domain: String, html: String, state: State<ProxyState>) -> Result<ElementFilter, String> {
+    element_filters::logic_add_element_filter(domain, html, &state)
+}
+
+/// Remove the element filters of `domain` with `selector`
+#[command]
+fn remove_element_filter(domain: String, selector: String, state: State<ProxyState>) -> bool {
+    element_filters::logic_remove_element_filter(domain, selector, &state)
+}
+
+/// How many elements a filter would remove on a sample page, to catch overbroad selectors
+#[command]
+async fn preview_filter(
+    domain: String,
+    selector: String,
+    text_anchor: Option<String>,
+    sample_url: String,
+    state: State<'_, ProxyState>,
+) -> Result<FilterPreview, String> {
+    element_filters::logic_preview_filter(domain, selector, text_anchor, sample_url, &state).await
+}
+
+/// Element filters to persist with the domain settings
+#[command]
+fn export_element_filters(state: State<ProxyState>) -> ElementFiltersExport {
+    element_filters::logic_export_element_filters(&state)
+}
+
+/// Restore persisted element filters. Returns the number of filters.
+#[command]
+fn import_element_filters(filters: ElementFiltersExport, state: State<ProxyState>) -> Result<usize, String> {
+    element_filters::logic_import_element_filters(filters, &state)
+}
+
+/// Response latencies per host and the timeouts they currently get, for the diagnostics view
+#[command]
+fn get_host_latency_stats(state: State<ProxyState>) -> HostLatencyReport {
+    latency::logic_get_host_latency_stats(&state)
+}
+
+/// Turn adaptive timeouts on or off and set their bounds
+#[command]
+fn set_adaptive_timeouts(config: AdaptiveTimeoutConfig, state: State<ProxyState>) -> Result<(), String> {
+    latency::logic_set_adaptive_timeouts(config, &state)
+}
+
+/// Latency history and adaptive timeout settings to persist between sessions
+#[command]
+fn export_host_latency(state: State<ProxyState>) -> HostLatencyExport {
+    latency::logic_export_host_latency(&state)
+}
+
+/// Restore persisted latency history. Returns the number of hosts.
+#[command]
+fn import_host_latency(export: HostLatencyExport, state: State<ProxyState>) -> Result<usize, String> {
+    latency::logic_import_host_latency(export, &state)
+}
+
+/// How often rendered fallback pages turned out extractable, per domain
+#[command]
+fn get_rendered_extraction_stats(state: State<ProxyState>) -> Vec<RenderedDomainStats> {
+    rendered::logic_get_rendered_extraction_stats(&state)
+}
+
+/// What the injected script did on a page load (from its latest `FEATURES_REPORT`), with the
+/// feature efficacy of the page's host
+#[command]
+fn get_page_report(session_id: String, state: State<ProxyState>) -> Result<PageReport, String> {
+    page_reports::logic_get_page_report(session_id, &state)
+}
+
+/// Counts of accepted, unknown and malformed protocol messages, with the latest rejections
+#[command]
+fn get_message_stats(state: State<ProxyState>) -> MessageStats {
+    messages::logic_get_message_stats(&state)
+}
+
+/// Debug: fetch a page through the proxy with and without the injected listener script
+/// and compare, to tell whether the injection or the rewriting breaks it
+#[command]
+async fn proxy_compare_injection(url: String, state: State<'_, ProxyState>) -> Result<InjectionComparison, String> {
+    proxy::logic_proxy_compare_injection(url, &state).await
+}
+
+/// Proxy counters: which Referer strategy served signed (CDN query signature) resource URLs
+#[command]
+fn get_proxy_stats(state: State<ProxyState>) -> ProxyStatsReport {
+    proxy::logic_get_proxy_stats(&state)
+}
+
+/// Overlay removal settings of the listener script
+#[command]
+fn get_snapshot_config(state: State<ProxyState>) -> SnapshotConfig {
+    proxy::logic_get_snapshot_config(&state)
+}
+
+/// Tune how aggressively the listener script removes newsletter/paywall modals from snapshots
+#[command]
+fn set_snapshot_config(config: SnapshotConfig, state: State<ProxyState>) -> Result<(), String> {
+    proxy::logic_set_snapshot_config(config, &state)
+}
+
+/// Action bar injection, share service and download directory of the article actions
+#[command]
+fn get_article_action_config(state: State<ProxyState>) -> ArticleActionConfig {
+    actions::logic_get_article_action_config(&state)
+}
+
+#[command]
+fn set_article_action_config(config: ArticleActionConfig, state: State<ProxyState>) -> Result<(), String> {
+    actions::logic_set_article_action_config(config, &state)
+}
+
+/// Run an action of the action bar (an `ARTICLE_ACTION` message) on the original URL of the
+/// page load `session_id`. Emits `article-action-progress` as it goes; opening `open_url` and
+/// copying `clipboard` is left to the frontend.
+#[command]
+async fn perform_article_action(
+    app_handle: AppHandle,
+    session_id: String,
+    action: ArticleAction,
+    params: Option<ArticleActionParams>,
+    state: State<'_, ProxyState>,
+) -> Result<ArticleActionResult, String> {
+    actions::logic_perform_article_action(session_id, action, params.unwrap_or_default(), &state, |progress| {
+        if let Err(e) = app_handle.emit(actions::ARTICLE_ACTION_PROGRESS_EVENT, progress) {
+            println!("[main::perform_article_action] Failed to emit progress: {}", e);
+        }
+    })
+    .await
+}
+
+/// Rewrite an inline (`srcdoc`) document of a page at `base_url` so its relative resources
+/// load through the proxy, optionally with the listener script
+#[command]
+fn rewrite_srcdoc(srcdoc: String, base_url: String, inject: Option<bool>, state: State<ProxyState>) -> Result<String, String> {
+    proxy::logic_rewrite_srcdoc(srcdoc, base_url, inject.unwrap_or(false), &state)
+}
+
+/// Fetch the canonical share metadata (OpenGraph / Twitter Card / JSON-LD) for a URL
+#[command]
+async fn fetch_share_metadata(url: String, state: State<'_, ProxyState>) -> Result<ShareMeta, String> {
+    logic_fetch_share_metadata(url, &state).await
+}
 
 /// Perform a form-based login (POST) to authenticate on a website
 #[command]
@@ -92,14 +933,222 @@ fn main() {
         .plugin(tauri_plugin_dialog::init())
         .plugin(tauri_plugin_fs::init())
         .manage(proxy_state)
+        // Closing the main window sends it to the tray; the tray menu quits
+        .on_window_event(|window, event| {
+            if let tauri::WindowEvent::CloseRequested { api, .. } = event {
+                if window.label() == "main" {
+                    let _ = window.hide();
+                    api.prevent_close();
+                }
+            }
+        })
+        .setup(|app| {
+            // Supervised tasks' transitions go to the frontend as they happen
+            let mut transitions = app.state::<ProxyState>().supervisor.subscribe();
+            let events_handle = app.handle().clone();
+            tauri::async_runtime::spawn(async move {
+                loop {
+                    match transitions.recv().await {
+                        Ok(status) => {
+                            let _ = events_handle.emit(supervisor::TASK_STATUS_EVENT, status);
+                        }
+                        Err(tokio::sync::broadcast::error::RecvError::Lagged(_)) => continue,
+                        Err(tokio::sync::broadcast::error::RecvError::Closed) => break,
+                    }
+                }
+            });
+
+            // Connectivity changes go to the frontend; back online, actions recorded offline run
+            connectivity::start_probe(&app.state::<ProxyState>());
+            let mut connectivity_changes = connectivity::subscribe(&app.state::<ProxyState>());
+            let connectivity_handle = app.handle().clone();
+            tauri::async_runtime::spawn(async move {
+                let mut was_offline = connectivity_changes.borrow_and_update().offline;
+                while connectivity_changes.changed().await.is_ok() {
+                    let status = *connectivity_changes.borrow_and_update();
+                    let _ = connectivity_handle.emit(connectivity::CONNECTIVITY_CHANGED_EVENT, status);
+                    if was_offline && !status.offline {
+                        let state = connectivity_handle.state::<ProxyState>().inner().clone();
+                        actions::logic_retry_queued_actions(
+                            &state,
+                            |progress| {
+                                let _ = connectivity_handle.emit(actions::ARTICLE_ACTION_PROGRESS_EVENT, progress);
+                            },
+                            |outcome| {
+                                let _ = connectivity_handle.emit(actions::QUEUED_ACTION_DONE_EVENT, outcome);
+                            },
+                        )
+                        .await;
+                    }
+                    was_offline = status.offline;
+                }
+            });
+
+            // Tray icon carrying the unread count; clicking it brings the window back
+            let quit = tauri::menu::MenuItem::with_id(app, "quit", "Quit", true, None::<&str>)?;
+            tauri::tray::TrayIconBuilder::with_id(TRAY_ID)
+                .icon(app.default_window_icon().cloned().ok_or("No default window icon")?)
+                .tooltip("Shadcn Feed Reader")
+                .menu(&tauri::menu::Menu::with_items(app, &[&quit])?)
+                .show_menu_on_left_click(false)
+                .on_menu_event(|app_handle, event| {
+                    if event.id() == "quit" {
+                        app_handle.exit(0);
+                    }
+                })
+                .on_tray_icon_event(|tray, event| {
+                    if let tauri::tray::TrayIconEvent::Click { button: tauri::tray::MouseButton::Left, .. } = event {
+                        if let Some(window) = tray.app_handle().get_webview_window("main") {
+                            let _ = window.show();
+                            let _ = window.set_focus();
+                        }
+                    }
+                })
+                .build(app)?;
+
+            // The unread badge follows the backend's estimate, window shown or not
+            let mut badge = unread::subscribe_badge(&app.state::<ProxyState>());
+            let badge_handle = app.handle().clone();
+            tauri::async_runtime::spawn(async move {
+                loop {
+                    let current = *badge.borrow_and_update();
+                    apply_unread_badge(&badge_handle, current);
+                    if badge.changed().await.is_err() {
+                        break;
+                    }
+                }
+            });
+
+            // Components that fail are disabled rather than stopping the app
+            let app_handle = app.handle().clone();
+            tauri::async_runtime::spawn(async move {
+                let state: State<ProxyState> = app_handle.state();
+                let report = startup::logic_run_startup(&[Component::Proxy, Component::FeedCatalog], &state).await;
+                if report.degraded {
+                    let _ = app_handle.emit(startup::STARTUP_DEGRADED_EVENT, report);
+                }
+            });
+            Ok(())
+        })
         .invoke_handler(tauri::generate_handler![
             fetch_article,
+            fetch_article_with_config,
+            fetch_article_segmented,
+            summarize_article,
+            fetch_article_classified,
+            fetch_article_markdown,
+            fetch_article_asciidoc,
+            fetch_article_rst,
+            export_article_html,
+            convert_fragment,
+            fetch_article_structure,
+            fetch_live_blog,
+            extract_outline,
+            extract_metadata,
+            reveal_hidden_content,
+            probe_article_images,
+            fetch_image_captions,
+            fetch_feed,
+            fetch_feed_icon,
+            load_site_configs,
+            load_site_config,
+            fetch_share_metadata,
             fetch_raw_html,
+            fetch_raw_html_transfer,
             start_proxy,
+            get_startup_report,
+            get_task_status,
+            retry_component,
             set_proxy_url,
             set_proxy_auth,
+            set_auth,
             clear_proxy_auth,
-            perform_form_login
+            migrate_credentials,
+            resolve_credential_conflict,
+            confirm_credential_migration,
+            rollback_credential_migration,
+            get_credential_audit,
+            clear_cookies,
+            prune_transfers,
+            get_domain_profile,
+            reset_domain,
+            forget_domain,
+            perform_form_login,
+            set_max_body_size,
+            set_max_rewrite_size,
+            set_user_agent,
+            set_user_agent_pool,
+            set_user_agent_rotation,
+            set_article_sanitization,
+            set_accept_language,
+            set_host_requires_rendering,
+            set_host_crawler_retry,
+            set_force_http1,
+            set_chart_snapshots,
+            set_offline_mode,
+            get_connectivity,
+            list_queued_actions,
+            preconnect,
+            set_metered_mode,
+            set_mixed_content_strict,
+            get_mixed_content_report,
+            proxy_compare_injection,
+            rewrite_srcdoc,
+            get_proxy_stats,
+            get_snapshot_config,
+            set_snapshot_config,
+            get_article_action_config,
+            set_article_action_config,
+            perform_article_action,
+            search_feed_catalog,
+            list_catalog_categories,
+            update_catalog,
+            get_message_schema,
+            validate_message,
+            get_message_stats,
+            get_page_report,
+            extract_from_rendered,
+            get_rendered_extraction_stats,
+            run_maintenance,
+            article_opened,
+            get_prefetch_plan,
+            set_smart_prefetch,
+            export_reading_stats,
+            import_reading_stats,
+            get_host_stats,
+            export_host_stats,
+            import_host_stats,
+            get_host_latency_stats,
+            add_element_filter,
+            remove_element_filter,
+            preview_filter,
+            export_element_filters,
+            import_element_filters,
+            set_adaptive_timeouts,
+            export_host_latency,
+            import_host_latency,
+            track_article_versions,
+            untrack_article_versions,
+            list_article_versions,
+            get_article_version,
+            set_version_budget,
+            get_compression_config,
+            set_compression_config,
+            get_translation_config,
+            set_translation_config,
+            get_feed_translation,
+            set_feed_translation,
+            translate_article,
+            get_translation_usage,
+            get_unread_estimate,
+            mark_items_read,
+            set_unread_count,
+            set_badge_mode,
+            get_extractor_comparison,
+            set_extractor_comparison_config,
+            set_domain_extractor,
+            enable_chaos,
+            disable_chaos
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
@@ -0,0 +1,111 @@
+use crate::compression::compression_ratio;
+use crate::icons::check_icon_cache;
+use crate::rendered::check_rendered_store;
+use crate::resource_cache::check_resource_cache;
+use crate::shared::ProxyState;
+use crate::transfer::check_transfers;
+use crate::versions::check_article_versions;
+use serde::{Deserialize, Serialize};
+
+/// Event emitted before each store is checked. The payload is a `MaintenanceProgress`.
+pub const MAINTENANCE_PROGRESS_EVENT: &str = "maintenance-progress";
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct MaintenanceOptions {
+    /// Report problems without fixing them
+    pub dry_run: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct MaintenanceProgress {
+    pub store: &'static str,
+    /// Position of the store, starting at 0
+    pub index: usize,
+    pub total: usize,
+}
+
+/// Outcome of checking one store
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct StoreCheck {
+    pub store: &'static str,
+    /// Entries looked at
+    pub checked: usize,
+    /// What was wrong, one line per problem
+    pub problems: Vec<String>,
+    /// Problems fixed (always 0 with `dry_run`)
+    pub fixed: usize,
+    /// Bytes held by the store after maintenance, recomputed from its entries
+    pub size: usize,
+    /// Bytes its entries take decoded, for stores that compress them
+    pub raw_size: Option<usize>,
+    /// `raw_size` over `size`
+    pub compression_ratio: Option<f64>,
+}
+
+impl StoreCheck {
+    pub fn new(store: &'static str) -> Self {
+        Self { store, ..Self::default() }
+    }
+
+    /// Records the stored and decoded size of a compressing store
+    pub fn sizes(&mut self, stored: usize, raw: usize) {
+        self.size = stored;
+        self.raw_size = Some(raw);
+        self.compression_ratio = compression_ratio(stored, raw);
+    }
+
+    /// Records a problem, counted as fixed unless `dry_run`
+    pub fn problem(&mut self, description: String, dry_run: bool) {
+        self.problems.push(description);
+        if !dry_run {
+            self.fixed += 1;
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct MaintenanceReport {
+    pub dry_run: bool,
+    pub stores: Vec<StoreCheck>,
+    pub problems: usize,
+    pub fixed: usize,
+}
+
+type StoreChecker = fn(bool, &ProxyState) -> StoreCheck;
+
+/// Stores with an integrity check. Only in-memory stores exist in the backend; new ones
+/// (persistent ones included) must be added here.
+const STORE_CHECKS: &[(&str, StoreChecker)] = &[
+    ("transfers", check_transfers),
+    ("article_versions", check_article_versions),
+    ("rendered_articles", check_rendered_store),
+    ("icon_cache", check_icon_cache),
+    ("resource_cache", check_resource_cache),
+];
+
+/// Checks every store and fixes what it can (unless `dry_run`). Stores are checked one at a
+/// time, each under its own lock only, so the app keeps working meanwhile. `progress` is
+/// called before each store.
+pub fn logic_run_maintenance(options: MaintenanceOptions, state: &ProxyState, progress: impl Fn(MaintenanceProgress)) -> MaintenanceReport {
+    let mut stores = Vec::with_capacity(STORE_CHECKS.len());
+    for (index, (store, check)) in STORE_CHECKS.iter().enumerate() {
+        progress(MaintenanceProgress { store, index, total: STORE_CHECKS.len() });
+        let result = check(options.dry_run, state);
+        println!(
+            "[maintenance::run_maintenance] {}: {} checked, {} problems, {} fixed",
+            store,
+            result.checked,
+            result.problems.len(),
+            result.fixed
+        );
+        stores.push(result);
+    }
+
+    MaintenanceReport {
+        dry_run: options.dry_run,
+        problems: stores.iter().map(|store| store.problems.len()).sum(),
+        fixed: stores.iter().map(|store| store.fixed).sum(),
+        stores,
+    }
+}
@@ -0,0 +1,252 @@
+use crate::actions::{ArticleAction, ArticleActionParams};
+use crate::shared::ProxyState;
+use crate::transfer::{prepare_transfer, TransferMode, TransferPayload};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// Version of the postMessage protocol between the injected script and the app. Bump it when
+/// a message is removed or a field changes meaning; adding messages or optional fields doesn't.
+pub const MESSAGE_SCHEMA_VERSION: u32 = 1;
+
+/// Rejected messages kept for diagnostics, most recent last
+const MAX_RECENT_REJECTIONS: usize = 20;
+
+/// Messages the injected script posts to the parent window
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "SCREAMING_SNAKE_CASE", rename_all_fields = "camelCase")]
+pub enum ScriptMessage {
+    /// Rendered page, for extraction of JS-built content
+    RenderedHtml { html: String },
+    VideoDetected { url: String },
+    OpenVideo {
+        url: String,
+        #[serde(default)]
+        current_time: Option<f64>,
+    },
+    /// Fallback when the iframe can't go fullscreen itself; `url` is the embed to open instead
+    ToggleFullscreen {
+        #[serde(default)]
+        url: Option<String>,
+    },
+    TwitterFullscreenRequest,
+    /// Posted by the page served when upstream answers 401
+    ProxyAuthRequired { domain: String },
+    /// What the injected behaviors did on the page, posted at most twice per page load
+    FeaturesReport {
+        /// Random id of the page load
+        session_id: String,
+        /// Upstream URL of the page
+        page_url: String,
+        counts: FeatureCounts,
+    },
+    /// Button of the action bar, for the app to run with `perform_article_action`
+    ArticleAction {
+        /// Page load, as in `FEATURES_REPORT`
+        session_id: String,
+        action: ArticleAction,
+        #[serde(default)]
+        params: ArticleActionParams,
+    },
+}
+
+/// Counters of the injected script's behaviors on one page load
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct FeatureCounts {
+    pub videos_found: u32,
+    /// Fullscreen buttons added to `<video>` elements
+    pub overlays_installed: u32,
+    /// Embed iframes wrapped for the fullscreen relay
+    pub embeds_wrapped: u32,
+    /// Lazy-load reveal scrolls completed
+    pub scroll_passes: u32,
+    pub consent_overlays_removed: u32,
+    /// Lazy `data-bg`-style backgrounds copied into an inline `background-image`
+    pub lazy_backgrounds_promoted: u32,
+    /// Newsletter and paywall modals removed before sending the snapshot
+    pub modal_overlays_removed: u32,
+    /// Exceptions caught by the script's behaviors
+    pub errors_caught: u32,
+}
+
+/// Messages the parent window posts to the injected script
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "SCREAMING_SNAKE_CASE", rename_all_fields = "camelCase")]
+pub enum ParentMessage {
+    /// Asks for an immediate `RENDERED_HTML`. Sent as `{ action: 'REQUEST_RENDERED' }`.
+    RequestRendered,
+    RestoreVideoTime {
+        video_url: String,
+        #[serde(default)]
+        current_time: Option<f64>,
+    },
+    /// Shows or hides the action bar, when injected. Sent for the app's keyboard shortcut.
+    ToggleActionBar,
+}
+
+/// A validated message, in either direction
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(untagged)]
+pub enum ProtocolMessage {
+    Script(ScriptMessage),
+    Parent(ParentMessage),
+}
+
+/// A validated message as returned to the frontend
+#[derive(Debug, Serialize)]
+#[serde(untagged)]
+pub enum MessageReply {
+    Message(ProtocolMessage),
+    /// `RENDERED_HTML` when the caller picked a transfer mode: the snapshot went through
+    /// `prepare_transfer`, so a multi-megabyte page comes back as a one-shot URL
+    RenderedHtml {
+        #[serde(rename = "type")]
+        kind: &'static str,
+        html: TransferPayload,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MessageDirection {
+    ScriptToParent,
+    ParentToScript,
+}
+
+/// Schema entry of a message type
+#[derive(Debug, Clone, Serialize)]
+pub struct MessageTypeInfo {
+    pub name: &'static str,
+    pub direction: MessageDirection,
+    /// Payload fields as posted (camelCase), `?` marking optional ones
+    pub fields: &'static [&'static str],
+}
+
+/// Every message type, matching the variants of `ScriptMessage` and `ParentMessage`
+const MESSAGE_TYPES: &[MessageTypeInfo] = &[
+    MessageTypeInfo { name: "RENDERED_HTML", direction: MessageDirection::ScriptToParent, fields: &["html"] },
+    MessageTypeInfo { name: "VIDEO_DETECTED", direction: MessageDirection::ScriptToParent, fields: &["url"] },
+    MessageTypeInfo { name: "OPEN_VIDEO", direction: MessageDirection::ScriptToParent, fields: &["url", "currentTime?"] },
+    MessageTypeInfo { name: "TOGGLE_FULLSCREEN", direction: MessageDirection::ScriptToParent, fields: &["url?"] },
+    MessageTypeInfo { name: "TWITTER_FULLSCREEN_REQUEST", direction: MessageDirection::ScriptToParent, fields: &[] },
+    MessageTypeInfo { name: "PROXY_AUTH_REQUIRED", direction: MessageDirection::ScriptToParent, fields: &["domain"] },
+    MessageTypeInfo { name: "FEATURES_REPORT", direction: MessageDirection::ScriptToParent, fields: &["sessionId", "pageUrl", "counts"] },
+    MessageTypeInfo { name: "ARTICLE_ACTION", direction: MessageDirection::ScriptToParent, fields: &["sessionId", "action", "params?"] },
+    MessageTypeInfo { name: "REQUEST_RENDERED", direction: MessageDirection::ParentToScript, fields: &[] },
+    MessageTypeInfo { name: "RESTORE_VIDEO_TIME", direction: MessageDirection::ParentToScript, fields: &["videoUrl", "currentTime?"] },
+    MessageTypeInfo { name: "TOGGLE_ACTION_BAR", direction: MessageDirection::ParentToScript, fields: &[] },
+];
+
+/// Protocol description returned to the frontend
+#[derive(Debug, Clone, Serialize)]
+pub struct MessageSchema {
+    pub version: u32,
+    pub messages: Vec<MessageTypeInfo>,
+    /// Same constants as embedded in the injected script, as an ES module
+    pub js_module: String,
+}
+
+/// Validation counters, for diagnostics
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct MessageStats {
+    pub accepted: u64,
+    /// Messages whose type isn't part of the protocol
+    pub unknown: u64,
+    /// Known types with missing or mistyped fields, and payloads that aren't objects
+    pub malformed: u64,
+    /// Latest rejection reasons, oldest first
+    pub recent_rejections: Vec<String>,
+}
+
+/// `{ NAME: 'NAME', ... }` for every message type
+fn js_types_object() -> String {
+    let entries: Vec<String> = MESSAGE_TYPES.iter().map(|info| format!("{0}: '{0}'", info.name)).collect();
+    format!("Object.freeze({{ {} }})", entries.join(", "))
+}
+
+/// Constants for the injected script: `MESSAGE_SCHEMA_VERSION` and `MESSAGE_TYPES`
+pub fn js_constants() -> String {
+    format!(
+        "const MESSAGE_SCHEMA_VERSION = {};\nconst MESSAGE_TYPES = {};",
+        MESSAGE_SCHEMA_VERSION,
+        js_types_object()
+    )
+}
+
+pub fn logic_get_message_schema() -> MessageSchema {
+    MessageSchema {
+        version: MESSAGE_SCHEMA_VERSION,
+        messages: MESSAGE_TYPES.to_vec(),
+        js_module: format!(
+            "export const MESSAGE_SCHEMA_VERSION = {};\nexport const MESSAGE_TYPES = {};\n",
+            MESSAGE_SCHEMA_VERSION,
+            js_types_object()
+        ),
+    }
+}
+
+/// Parses a message posted in either direction. `REQUEST_RENDERED` is keyed by `action`
+/// rather than `type`, as the injected script expects.
+fn parse_message(mut message: Value) -> Result<ProtocolMessage, (bool, String)> {
+    let Some(object) = message.as_object_mut() else {
+        return Err((false, "Message is not an object".to_string()));
+    };
+    if !object.contains_key("type") {
+        if let Some(action) = object.get("action").cloned() {
+            object.insert("type".to_string(), action);
+        }
+    }
+
+    let name = object.get("type").and_then(Value::as_str).unwrap_or_default().to_string();
+    let Some(info) = MESSAGE_TYPES.iter().find(|info| info.name == name) else {
+        return Err((true, format!("Unknown message type '{}'", name)));
+    };
+
+    let parsed = match info.direction {
+        MessageDirection::ScriptToParent => serde_json::from_value(message).map(ProtocolMessage::Script),
+        MessageDirection::ParentToScript => serde_json::from_value(message).map(ProtocolMessage::Parent),
+    };
+    parsed.map_err(|e| (false, format!("Malformed {}: {}", name, e)))
+}
+
+/// Validates a message against the protocol, counting it in the diagnostics
+pub fn logic_validate_message(message: Value, state: &ProxyState) -> Result<ProtocolMessage, String> {
+    let result = parse_message(message);
+    let mut stats = state.message_stats.lock().unwrap();
+    match result {
+        Ok(message) => {
+            stats.accepted += 1;
+            Ok(message)
+        }
+        Err((unknown, reason)) => {
+            if unknown {
+                stats.unknown += 1;
+            } else {
+                stats.malformed += 1;
+            }
+            println!("[messages::validate_message] {}", reason);
+            stats.recent_rejections.push(reason.clone());
+            if stats.recent_rejections.len() > MAX_RECENT_REJECTIONS {
+                stats.recent_rejections.remove(0);
+            }
+            Err(reason)
+        }
+    }
+}
+
+/// Reply to a validated message. With `transfer`, the HTML of a `RENDERED_HTML` is handed back
+/// through `prepare_transfer`; without it, the message is echoed as is (`html` a string).
+pub fn message_reply(message: ProtocolMessage, transfer: Option<TransferMode>, state: &ProxyState) -> MessageReply {
+    match (message, transfer) {
+        (ProtocolMessage::Script(ScriptMessage::RenderedHtml { html }), Some(transfer)) => {
+            // Without a running local server there is nothing to serve the handle from
+            let transfer = if state.has_local_server() { transfer } else { TransferMode::Inline };
+            MessageReply::RenderedHtml { kind: "RENDERED_HTML", html: prepare_transfer(html, "text/html; charset=utf-8", Some(transfer), state) }
+        }
+        (message, _) => MessageReply::Message(message),
+    }
+}
+
+pub fn logic_get_message_stats(state: &ProxyState) -> MessageStats {
+    state.message_stats.lock().unwrap().clone()
+}
@@ -0,0 +1,259 @@
+use crate::shared::{host_in_domain, host_of_domain_key, MutationReport, ProxyState};
+use futures_util::stream::{self, StreamExt};
+use lol_html::{element, rewrite_str, RewriteStrSettings};
+use serde::Serialize;
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use tokio::time::Duration;
+use url::Url;
+
+/// Timeout of the HEAD request checking whether a host serves HTTPS
+const HTTPS_PROBE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Maximum number of HTTPS probes in flight at once
+const HTTPS_PROBE_CONCURRENCY: usize = 6;
+
+/// `rel` values of `<link>` elements that load a subresource
+const SUBRESOURCE_LINK_RELS: &[&str] = &["stylesheet", "icon", "shortcut", "apple-touch-icon", "preload", "modulepreload", "manifest"];
+
+/// Mixed-content settings and per-host HTTPS support, shared by the proxy handlers
+#[derive(Default)]
+pub struct MixedContentState {
+    /// Refuse plain-http subresources of https pages instead of proxying them
+    pub strict: bool,
+    /// Whether a host answered over HTTPS, keyed by host
+    https_support: HashMap<String, bool>,
+    /// Report of the last https page rewritten by the proxy
+    last_report: Option<MixedContentReport>,
+}
+
+/// Plain-http subresources found while rewriting an https page
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct MixedContentReport {
+    pub page_url: String,
+    /// Plain-http subresource references on the page
+    pub count: usize,
+    /// References rewritten to https because their host serves it
+    pub upgraded: usize,
+    /// References still loaded over http, through the proxy
+    pub proxied: usize,
+    /// References dropped in strict mode
+    pub blocked: usize,
+    /// Hosts that had to be loaded (or were blocked) over plain http
+    pub downgraded_hosts: Vec<String>,
+}
+
+/// What to do with a plain-http subresource of an https page
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum InsecureAction {
+    /// Load it from this https URL instead
+    Upgrade(String),
+    /// Load it over http through the proxy
+    Proxy,
+    /// Don't load it (strict mode)
+    Block,
+}
+
+/// Decisions for the plain-http subresources of one page, collecting the report as the
+/// rewriter asks for them
+pub struct MixedContentPlan {
+    strict: bool,
+    upgradable: HashSet<String>,
+    report: RefCell<MixedContentReport>,
+}
+
+impl MixedContentPlan {
+    /// Action for `url`, or `None` if it isn't a plain-http remote URL
+    pub fn action(&self, url: &str) -> Option<InsecureAction> {
+        let host = insecure_host(url)?;
+        let mut report = self.report.borrow_mut();
+        report.count += 1;
+
+        if self.upgradable.contains(&host) {
+            report.upgraded += 1;
+            return Some(InsecureAction::Upgrade(format!("https://{}", &url.trim()["http://".len()..])));
+        }
+
+        if !report.downgraded_hosts.contains(&host) {
+            report.downgraded_hosts.push(host);
+        }
+        if self.strict {
+            report.blocked += 1;
+            Some(InsecureAction::Block)
+        } else {
+            report.proxied += 1;
+            Some(InsecureAction::Proxy)
+        }
+    }
+
+    pub fn into_report(self) -> MixedContentReport {
+        self.report.into_inner()
+    }
+}
+
+/// Host of a plain-http URL, excluding the local server
+fn insecure_host(url: &str) -> Option<String> {
+    let url = url.trim();
+    if !url.get(..7).is_some_and(|scheme| scheme.eq_ignore_ascii_case("http://")) {
+        return None;
+    }
+    let parsed = Url::parse(url).ok()?;
+    let host = parsed.host_str()?.to_ascii_lowercase();
+    if host == "localhost" || host == "127.0.0.1" || host == "[::1]" {
+        return None;
+    }
+    Some(host)
+}
+
+/// Whether a `<link>` with this `rel` loads a subresource (as opposed to canonical/alternate links)
+pub fn is_subresource_link(rel: &str) -> bool {
+    rel.split_whitespace()
+        .any(|rel| SUBRESOURCE_LINK_RELS.iter().any(|known| rel.eq_ignore_ascii_case(known)))
+}
+
+/// Hosts referenced over plain http by `src`, `srcset` and subresource `<link>` attributes
+pub fn insecure_hosts(html: &str) -> Vec<String> {
+    let hosts = RefCell::new(Vec::<String>::new());
+    let add = |url: &str| {
+        if let Some(host) = insecure_host(url) {
+            let mut hosts = hosts.borrow_mut();
+            if !hosts.contains(&host) {
+                hosts.push(host);
+            }
+        }
+    };
+
+    let _ = rewrite_str(
+        html,
+        RewriteStrSettings {
+            element_content_handlers: vec![
+                element!("*[src]", |el| {
+                    add(&el.get_attribute("src").unwrap_or_default());
+                    Ok(())
+                }),
+                element!("*[srcset]", |el| {
+                    for candidate in el.get_attribute("srcset").unwrap_or_default().split(',') {
+                        add(candidate.split_whitespace().next().unwrap_or(""));
+                    }
+                    Ok(())
+                }),
+                element!("link[href]", |el| {
+                    if is_subresource_link(&el.get_attribute("rel").unwrap_or_default()) {
+                        add(&el.get_attribute("href").unwrap_or_default());
+                    }
+                    Ok(())
+                }),
+            ],
+            ..RewriteStrSettings::default()
+        },
+    );
+
+    hosts.into_inner()
+}
+
+/// Whether `host` answers over HTTPS. Checked once per host with a HEAD request, then cached.
+async fn supports_https(client: &reqwest::Client, host: String, state: &ProxyState) -> (String, bool) {
+    if let Some(known) = state.mixed_content.lock().unwrap().https_support.get(&host) {
+        return (host, *known);
+    }
+
+    // Any HTTP response, even an error status, means TLS works on the host
+    let supported = client.head(format!("https://{}/", host)).send().await.is_ok();
+    println!("[mixed_content::supports_https] {} -> {}", host, supported);
+    state.mixed_content.lock().unwrap().https_support.insert(host.clone(), supported);
+    (host, supported)
+}
+
+/// Plan for the plain-http subresources of `html`, or `None` when the page itself isn't https
+pub async fn plan_for(page_url: &Url, html: &str, state: &ProxyState) -> Option<MixedContentPlan> {
+    if page_url.scheme() != "https" {
+        return None;
+    }
+
+    let hosts = insecure_hosts(html);
+    let mut upgradable = HashSet::new();
+    if !hosts.is_empty() {
+        let client = reqwest::Client::builder()
+            .timeout(HTTPS_PROBE_TIMEOUT)
+            .connect_timeout(HTTPS_PROBE_TIMEOUT)
+            .redirect(reqwest::redirect::Policy::none())
+            .build()
+            .ok()?;
+        let results: Vec<(String, bool)> = stream::iter(hosts)
+            .map(|host| supports_https(&client, host, state))
+            .buffer_unordered(HTTPS_PROBE_CONCURRENCY)
+            .collect()
+            .await;
+        upgradable = results.into_iter().filter(|(_, supported)| *supported).map(|(host, _)| host).collect();
+    }
+
+    let strict = state.mixed_content.lock().unwrap().strict;
+    Some(MixedContentPlan {
+        strict,
+        upgradable,
+        report: RefCell::new(MixedContentReport { page_url: page_url.to_string(), ..MixedContentReport::default() }),
+    })
+}
+
+/// Stores the report of the page just rewritten
+pub fn record_report(report: MixedContentReport, state: &ProxyState) {
+    if report.count > 0 {
+        println!(
+            "[mixed_content] {}: {} plain-http subresources ({} upgraded, {} proxied, {} blocked), insecure hosts: {:?}",
+            report.page_url, report.count, report.upgraded, report.proxied, report.blocked, report.downgraded_hosts
+        );
+    }
+    state.mixed_content.lock().unwrap().last_report = Some(report);
+}
+
+/// In strict mode, plain-http subresources requested while an https page is proxied are refused
+pub fn is_blocked_subresource(url: &Url, state: &ProxyState) -> bool {
+    if url.scheme() != "http" || insecure_host(url.as_str()).is_none() {
+        return false;
+    }
+    let page_is_https = state.base_url.load().scheme() == "https";
+    page_is_https && state.mixed_content.lock().unwrap().strict
+}
+
+pub fn logic_set_mixed_content_strict(enabled: bool, state: &ProxyState) {
+    println!("[mixed_content] Strict mode {}", if enabled { "enabled" } else { "disabled" });
+    state.mixed_content.lock().unwrap().strict = enabled;
+}
+
+/// Report of the last https page rewritten by the proxy
+pub fn logic_get_mixed_content_report(state: &ProxyState) -> Option<MixedContentReport> {
+    state.mixed_content.lock().unwrap().last_report.clone()
+}
+
+/// Forgets whether `domain` and its subdomains serve HTTPS
+pub fn clear_https_support_for_domain(domain: &str, dry_run: bool, state: &ProxyState) -> MutationReport {
+    let mut report = MutationReport::new(dry_run);
+    let host = host_of_domain_key(domain);
+    let mut mixed_content = state.mixed_content.lock().unwrap();
+
+    let matching: Vec<String> = mixed_content.https_support.keys().filter(|key| host_in_domain(key, &host)).cloned().collect();
+    for key in matching {
+        report.record("https_support", key.clone(), None);
+        if !dry_run {
+            mixed_content.https_support.remove(&key);
+        }
+    }
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::check_clear_for_domain;
+
+    #[test]
+    fn clears_the_https_support_of_a_domain() {
+        check_clear_for_domain(
+            |state, host| {
+                state.mixed_content.lock().unwrap().https_support.insert(host.to_string(), true);
+            },
+            clear_https_support_for_domain,
+            |state| state.mixed_content.lock().unwrap().https_support.keys().cloned().collect(),
+        );
+    }
+}
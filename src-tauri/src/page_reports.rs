@@ -0,0 +1,111 @@
+use crate::host_stats::{self, FeatureEfficacy};
+use crate::messages::FeatureCounts;
+use crate::proxy::ReferrerPolicy;
+use crate::shared::ProxyState;
+use serde::Serialize;
+use std::collections::{HashMap, VecDeque};
+use std::time::{SystemTime, UNIX_EPOCH};
+use url::Url;
+
+/// Page loads whose latest report is kept, oldest forgotten first
+const MAX_PAGE_REPORTS: usize = 100;
+
+/// Longest session id accepted (the script sends ~18 characters)
+const MAX_SESSION_ID_LEN: usize = 64;
+
+/// Latest `FEATURES_REPORT` of a page load
+#[derive(Debug, Clone, Serialize)]
+pub struct PageReport {
+    pub session_id: String,
+    pub page_url: String,
+    pub host: String,
+    pub counts: FeatureCounts,
+    /// Reports received for the page load (the script sends at most two)
+    pub reports: u32,
+    /// Unix time (ms) of the latest report
+    pub received_at: u64,
+    /// Referrer policy of the proxy session when the latest report came in
+    pub referrer_policy: ReferrerPolicy,
+    /// Feature efficacy over every page load of the host, this one included
+    pub host_features: Option<FeatureEfficacy>,
+}
+
+#[derive(Default)]
+pub struct PageReportStore {
+    reports: HashMap<String, PageReport>,
+    /// Keys of `reports`, oldest first
+    order: VecDeque<String>,
+}
+
+impl PageReportStore {
+    /// Upstream URL reported by a page load
+    pub fn page_url(&self, session_id: &str) -> Option<String> {
+        self.reports.get(session_id).map(|report| report.page_url.clone())
+    }
+}
+
+/// Upstream URL of a reported page. Pages served by the proxy handler report their proxy URL,
+/// whose path is relative to the proxied site.
+fn upstream_url(page_url: &str, state: &ProxyState) -> Result<Url, String> {
+    let url = Url::parse(page_url).map_err(|e| format!("Invalid page URL '{}': {}", page_url, e))?;
+    match url.host_str() {
+        Some("localhost" | "127.0.0.1") => {
+            let path = &url[url::Position::BeforePath..];
+            state.base_url.load().join(path.trim_start_matches('/')).map_err(|e| e.to_string())
+        }
+        Some(_) => Ok(url),
+        None => Err(format!("Page URL '{}' has no host", page_url)),
+    }
+}
+
+/// Stores a `FEATURES_REPORT` as the latest of its page load and adds it to the host's
+/// feature efficacy (replacing the page load's previous report)
+pub fn logic_record_features_report(session_id: &str, page_url: &str, counts: FeatureCounts, state: &ProxyState) -> Result<(), String> {
+    if session_id.is_empty() || session_id.len() > MAX_SESSION_ID_LEN {
+        return Err("Invalid session id".into());
+    }
+    let url = upstream_url(page_url, state)?;
+    let host = url.host_str().unwrap_or_default().to_ascii_lowercase();
+
+    let mut store = state.page_reports.lock().unwrap();
+    let previous = store.reports.get(session_id).filter(|report| report.host == host).map(|report| (report.counts, report.reports));
+    host_stats::record_features(&host, previous.as_ref().map(|(counts, _)| counts), &counts, state);
+
+    if previous.is_none() && !store.reports.contains_key(session_id) {
+        store.order.push_back(session_id.to_string());
+    }
+    let received_at = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_millis() as u64).unwrap_or(0);
+    store.reports.insert(
+        session_id.to_string(),
+        PageReport {
+            session_id: session_id.to_string(),
+            page_url: url.to_string(),
+            host,
+            counts,
+            reports: previous.map_or(1, |(_, reports)| reports + 1),
+            received_at,
+            referrer_policy: **state.referrer_policy.load(),
+            host_features: None,
+        },
+    );
+    while store.order.len() > MAX_PAGE_REPORTS {
+        if let Some(oldest) = store.order.pop_front() {
+            store.reports.remove(&oldest);
+        }
+    }
+    Ok(())
+}
+
+/// Latest features report of a page load, with the feature efficacy of its host
+pub fn logic_get_page_report(session_id: String, state: &ProxyState) -> Result<PageReport, String> {
+    let mut report = state
+        .page_reports
+        .lock()
+        .unwrap()
+        .reports
+        .get(&session_id)
+        .cloned()
+        .ok_or_else(|| format!("No features report for session {}", session_id))?;
+    report.host_features = host_stats::logic_get_host_stats(report.host.clone(), state).ok().map(|stats| stats.counters.features);
+    Ok(report)
+}
@@ -0,0 +1,293 @@
+use crate::chaos;
+use crate::shared::{document_base_url, Deadline, PageRequest, ProxyState};
+use crate::site_config::{self, SiteConfig};
+use lol_html::html_content::TextType;
+use lol_html::{doc_text, element, rewrite_str, RewriteStrSettings};
+use std::cell::RefCell;
+use std::collections::HashSet;
+use std::rc::Rc;
+use url::Url;
+
+/// Pages of an article stitched together at most, the first one included
+pub const MAX_PAGES: usize = 10;
+
+/// Links to the next page of an article, most reliable first. Plain `a[rel="next"]` links are
+/// only taken inside pagination blocks: blogs use them for the next post too.
+const NEXT_PAGE_SELECTORS: &[&str] = &[
+    r#"link[rel~="next" i]"#,
+    r#".pagination a[rel~="next" i]"#,
+    ".pagination a.next",
+    ".pagination .next a",
+    ".pager a.next",
+    ".pager .next a",
+    "a.next-page",
+    ".post-page-numbers.next",
+];
+
+/// Blocks compared across pages to drop the header and footer paragraphs every page repeats
+const DEDUPLICATED_BLOCKS: &str = "p, h1, h2, h3, h4, h5, h6";
+
+/// Path of `url` without its trailing page number (`/story/2`, `/story-2.html`, `/story/page/2`
+/// all give `/story`)
+fn path_stem(url: &Url) -> String {
+    let path = url.path();
+    let path = path.rsplit_once('.').filter(|(_, extension)| !extension.contains('/')).map_or(path, |(stem, _)| stem);
+    let path = path.trim_end_matches(|c: char| c.is_ascii_digit() || matches!(c, '/' | '-' | '_'));
+    path.strip_suffix("/page").or_else(|| path.strip_suffix("-page")).unwrap_or(path).to_string()
+}
+
+/// Whether `next` looks like a following page of the article at `current` rather than
+/// another article: same host, and same path but for the page number
+fn is_following_page(current: &Url, next: &Url) -> bool {
+    if next.host_str() != current.host_str() || next == current {
+        return false;
+    }
+    let (current_stem, next_stem) = (path_stem(current), path_stem(next));
+    current_stem == next_stem || next_stem.starts_with(&format!("{}/", current_stem))
+}
+
+/// URL of the page following `page_url` in a paginated article: the site rules'
+/// `next_page_link`, else `<link rel="next">` or a pagination block's next link
+pub fn next_page_url(html: &str, page_url: &Url, site_config: Option<&SiteConfig>) -> Option<Url> {
+    let base = document_base_url(html, page_url);
+    let usable = |url: &Url| matches!(url.scheme(), "http" | "https" | chaos::FIXTURE_SCHEME) && url != page_url;
+
+    // Site rules name the link themselves, no need to second-guess them
+    if let Some(rules) = site_config.map(|config| config.next_page_link.as_slice()).filter(|rules| !rules.is_empty()) {
+        if let Some(next) = site_config::extract_field(html, rules).and_then(|href| base.join(&href).ok()).filter(usable) {
+            return Some(next);
+        }
+    }
+
+    let document = scraper::Html::parse_document(html);
+    NEXT_PAGE_SELECTORS.iter().find_map(|selector| {
+        let selector = scraper::Selector::parse(selector).ok()?;
+        document
+            .select(&selector)
+            .filter_map(|el| el.value().attr("href"))
+            .filter_map(|href| base.join(href.trim()).ok())
+            .find(|next| usable(next) && is_following_page(page_url, next))
+    })
+}
+
+/// Fetches the pages following an article's first page, from `next` on, and extracts each
+/// with `extract`. Stops at `MAX_PAGES`, at a page already visited (`visited` holds the
+/// first page's URLs), at a page that fails to load or extract, or once `deadline` is spent.
+pub async fn fetch_following_pages<F>(next: Url, mut visited: HashSet<String>, request: &PageRequest<'_>, deadline: &Deadline, state: &ProxyState, extract: F) -> Vec<String>
+where
+    F: Fn(String, &Url) -> Option<String>,
+{
+    let mut pages = Vec::new();
+    let mut next = Some(next);
+    while let Some(url) = next.take() {
+        if pages.len() + 1 >= MAX_PAGES {
+            println!("[pagination::fetch_following_pages] Stopping at {} pages", MAX_PAGES);
+            break;
+        }
+        if !visited.insert(url.to_string()) {
+            println!("[pagination::fetch_following_pages] {} already visited, stopping", url);
+            break;
+        }
+        if !deadline.allows("follow_pagination") {
+            break;
+        }
+
+        let page = match request.fetch(&url, state).await {
+            Ok(page) if page.content_type.contains("html") => page,
+            Ok(page) => {
+                println!("[pagination::fetch_following_pages] {} is not HTML: {}", url, page.content_type);
+                break;
+            }
+            Err(e) => {
+                println!("[pagination::fetch_following_pages] {} failed to load: {}", url, e);
+                break;
+            }
+        };
+        // Past the last page, some sites redirect back to the first one
+        let page_url = Url::parse(&page.url).unwrap_or_else(|_| url.clone());
+        if page_url != url && !visited.insert(page_url.to_string()) {
+            println!("[pagination::fetch_following_pages] {} redirected to {}, already visited", url, page_url);
+            break;
+        }
+        next = next_page_url(&page.html, &page_url, request.site_config);
+        match extract(page.html, &page_url) {
+            Some(content) => pages.push(content),
+            None => {
+                println!("[pagination::fetch_following_pages] Nothing extracted from {}, stopping", page_url);
+                break;
+            }
+        }
+    }
+    pages
+}
+
+/// Whitespace-collapsed text of each `DEDUPLICATED_BLOCKS` element of `html`, in document order
+fn block_texts(html: &str) -> Result<Vec<String>, String> {
+    let texts: Rc<RefCell<Vec<String>>> = Rc::new(RefCell::new(Vec::new()));
+    let open: Rc<RefCell<Vec<usize>>> = Rc::new(RefCell::new(Vec::new()));
+    rewrite_str(
+        html,
+        RewriteStrSettings {
+            element_content_handlers: vec![element!(DEDUPLICATED_BLOCKS, |el| {
+                let index = {
+                    let mut texts = texts.borrow_mut();
+                    texts.push(String::new());
+                    texts.len() - 1
+                };
+                if let Some(handlers) = el.end_tag_handlers() {
+                    open.borrow_mut().push(index);
+                    let open = open.clone();
+                    handlers.push(Box::new(move |_| {
+                        open.borrow_mut().pop();
+                        Ok(())
+                    }));
+                }
+                Ok(())
+            })],
+            document_content_handlers: vec![doc_text!(|t| {
+                if t.text_type() == TextType::Data {
+                    let mut texts = texts.borrow_mut();
+                    for &index in open.borrow().iter() {
+                        texts[index].push_str(t.as_str());
+                    }
+                }
+                Ok(())
+            })],
+            ..RewriteStrSettings::default()
+        },
+    )
+    .map_err(|e| e.to_string())?;
+    let texts = texts.take();
+    Ok(texts.into_iter().map(|text| text.split_whitespace().collect::<Vec<_>>().join(" ")).collect())
+}
+
+/// Removes the `DEDUPLICATED_BLOCKS` elements of `html` at `indexes` (document order)
+fn remove_blocks(html: &str, indexes: &HashSet<usize>) -> Result<String, String> {
+    let next_block = RefCell::new(0usize);
+    rewrite_str(
+        html,
+        RewriteStrSettings {
+            element_content_handlers: vec![element!(DEDUPLICATED_BLOCKS, |el| {
+                let index = next_block.replace_with(|index| *index + 1);
+                if indexes.contains(&index) {
+                    el.remove();
+                }
+                Ok(())
+            })],
+            ..RewriteStrSettings::default()
+        },
+    )
+    .map_err(|e| e.to_string())
+}
+
+/// Content of a paginated article: `first` followed by the `following` pages in order, with
+/// the paragraphs and headings a page repeats from an earlier one (site header, author box,
+/// footer) removed
+pub fn stitch_pages(first: String, following: &[String]) -> String {
+    let mut seen: HashSet<String> = block_texts(&first).unwrap_or_default().into_iter().collect();
+    let mut stitched = first;
+    for page in following {
+        let deduplicated = block_texts(page).and_then(|texts| {
+            let repeated: HashSet<usize> = texts.iter().enumerate().filter(|(_, text)| !text.is_empty() && seen.contains(*text)).map(|(index, _)| index).collect();
+            seen.extend(texts);
+            if repeated.is_empty() {
+                Ok(page.clone())
+            } else {
+                remove_blocks(page, &repeated)
+            }
+        });
+        match deduplicated {
+            Ok(page) => stitched.push_str(&page),
+            Err(e) => {
+                println!("[pagination::stitch_pages] Deduplication failed, keeping the page as is: {}", e);
+                stitched.push_str(page);
+            }
+        }
+    }
+    stitched
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::shared::{logic_fetch_article, ArticleOptions};
+    use crate::test_support::serve;
+    use axum::extract::Query;
+    use axum::response::Html;
+    use axum::routing::get;
+    use axum::Router;
+    use std::collections::HashMap;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    const PAGES: [&str; 3] = [
+        include_str!("../tests/fixtures/pagination/page1.html"),
+        include_str!("../tests/fixtures/pagination/page2.html"),
+        include_str!("../tests/fixtures/pagination/page3.html"),
+    ];
+
+    /// Serves the three pages of the fixture article under `?page=`, counting the requests of each
+    async fn paged_article() -> (String, Arc<[AtomicUsize; 3]>) {
+        let hits = Arc::new([AtomicUsize::new(0), AtomicUsize::new(0), AtomicUsize::new(0)]);
+        let counted = hits.clone();
+        let app = Router::new().route(
+            "/features/lighthouse-keepers",
+            get(move |Query(query): Query<HashMap<String, String>>| {
+                let hits = counted.clone();
+                async move {
+                    let page = query.get("page").map_or(1, |page| page.parse().unwrap());
+                    hits[page - 1].fetch_add(1, Ordering::SeqCst);
+                    Html(PAGES[page - 1])
+                }
+            }),
+        );
+        (format!("http://{}/features/lighthouse-keepers", serve(app).await), hits)
+    }
+
+    fn hit_counts(hits: &[AtomicUsize; 3]) -> Vec<usize> {
+        hits.iter().map(|hits| hits.load(Ordering::SeqCst)).collect()
+    }
+
+    #[tokio::test]
+    async fn three_pages_are_stitched_in_order_without_repeats() {
+        let (url, hits) = paged_article().await;
+        let options = ArticleOptions { follow_pagination: true, ..ArticleOptions::default() };
+        let article = logic_fetch_article(url, options, &ProxyState::default()).await.unwrap();
+
+        let first = article.content.find("For nearly two centuries").expect("page 1 missing");
+        let second = article.content.find("Supplies came by boat once a fortnight").expect("page 2 missing");
+        let third = article.content.find("When automation came").expect("page 3 missing");
+        assert!(first < second && second < third, "{}", article.content);
+        assert!(article.content.contains("it has never sounded right since"));
+        assert_eq!(article.content.matches("Working lives at sea").count(), 1, "{}", article.content);
+        assert!(!article.content.contains("the harbour pilots"), "{}", article.content);
+        assert_eq!(article.provenance.unwrap().processors.iter().filter(|step| *step == "follow_pagination").count(), 1);
+
+        // The last page's pager wraps around to the first one, which isn't fetched again
+        assert_eq!(hit_counts(&hits), [1, 1, 1]);
+    }
+
+    #[tokio::test]
+    async fn pagination_is_only_followed_when_asked() {
+        let (url, hits) = paged_article().await;
+        let article = logic_fetch_article(url, ArticleOptions::default(), &ProxyState::default()).await.unwrap();
+        assert!(article.content.contains("For nearly two centuries"));
+        assert!(!article.content.contains("Supplies came by boat"));
+        assert_eq!(hit_counts(&hits), [1, 0, 0]);
+    }
+
+    #[test]
+    fn next_page_links_stay_on_the_article() {
+        let page = Url::parse("https://news.example/features/lighthouse-keepers?page=3").unwrap();
+        // A blog's `rel="next"` outside a pager points to the next post
+        assert_eq!(next_page_url(r#"<a rel="next" href="/features/harbour-pilots">Next story</a>"#, &page, None), None);
+        assert_eq!(next_page_url(r#"<div class="pagination"><a class="next" href="https://other.example/features/lighthouse-keepers?page=4">4</a></div>"#, &page, None), None);
+        for (html, expected) in [
+            (r#"<link rel="next" href="?page=4">"#, "https://news.example/features/lighthouse-keepers?page=4"),
+            (r#"<div class="pagination"><a class="next" href="/features/lighthouse-keepers/page/4">4</a></div>"#, "https://news.example/features/lighthouse-keepers/page/4"),
+            (r#"<div class="pager"><span class="next"><a href="lighthouse-keepers-4.html">Next</a></span></div>"#, "https://news.example/features/lighthouse-keepers-4.html"),
+        ] {
+            assert_eq!(next_page_url(html, &page, None).map(String::from).as_deref(), Some(expected), "{}", html);
+        }
+    }
+}
@@ -0,0 +1,255 @@
+use crate::connectivity;
+use crate::credentials;
+use crate::shared::{host_in_domain, host_of_domain_key, origin_of, protocol_for, with_protocol_for, MutationReport, ProxyState};
+use futures_util::future::join_all;
+use reqwest::header::USER_AGENT;
+use std::collections::HashMap;
+use std::sync::atomic::Ordering;
+use std::time::{Duration, Instant};
+use url::Url;
+
+/// Origins kept warm at once; further preconnects are ignored until one expires
+const MAX_WARM_ORIGINS: usize = 6;
+
+/// How long a warmed connection stays idle in the pool before it's closed
+const WARM_IDLE: Duration = Duration::from_secs(30);
+
+/// Learned CDN origins warmed along with an article's origin, the most used first
+const MAX_CDN_ORIGINS: usize = 2;
+
+/// Subresource origins remembered per domain
+const MAX_LEARNED_ORIGINS: usize = 16;
+
+/// Domains whose subresource origins are remembered; forgotten all at once past this
+const MAX_LEARNED_DOMAINS: usize = 500;
+
+/// Time allowed to a warm-up request
+const PRECONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Connect timeout of the pooled client (request timeouts are set per request)
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Default)]
+pub struct PreconnectStore {
+    /// Origins warmed and when
+    warm: HashMap<String, Instant>,
+    /// Requests per subresource origin, keyed by the host of the article that loaded them
+    subresource_origins: HashMap<String, HashMap<String, u32>>,
+}
+
+/// Client shared by article fetches and proxied resources, so their connections (and those
+/// opened by `preconnect`) are pooled. One per protocol setting: HTTP/1.1-only hosts get
+/// their own. Timeouts are set on each request.
+pub fn pooled_client(url: &Url, state: &ProxyState) -> Result<reqwest::Client, String> {
+    let protocol = protocol_for(url, state);
+    if let Some(client) = state.pooled_clients.get(&protocol) {
+        return Ok(client.clone());
+    }
+    let client = with_protocol_for(reqwest::Client::builder(), url, state)
+        .cookie_store(true)
+        .cookie_provider(state.cookie_jar.clone())
+        .redirect(credentials::redirect_policy(state))
+        .connect_timeout(CONNECT_TIMEOUT)
+        .pool_idle_timeout(WARM_IDLE)
+        .gzip(true)
+        .brotli(true)
+        .deflate(true)
+        .build()
+        .map_err(|e| e.to_string())?;
+    Ok(state.pooled_clients.entry(protocol).or_insert(client).clone())
+}
+
+/// Remembers that the article at `article_url` loaded a resource from another origin, so
+/// the next preconnect to its domain warms that origin too
+pub fn record_subresource(article_url: &Url, resource_url: &Url, state: &ProxyState) {
+    let (Some(host), Some(resource_host)) = (article_url.host_str(), resource_url.host_str()) else {
+        return;
+    };
+    // No article proxied yet (the base URL is still `http://localhost`)
+    if host == "localhost" || resource_url.scheme() != "https" || resource_host.eq_ignore_ascii_case(host) {
+        return;
+    }
+
+    let mut store = state.preconnect.lock().unwrap();
+    let host = host.to_ascii_lowercase();
+    if !store.subresource_origins.contains_key(&host) && store.subresource_origins.len() >= MAX_LEARNED_DOMAINS {
+        store.subresource_origins.clear();
+    }
+    let origins = store.subresource_origins.entry(host).or_default();
+    let origin = origin_of(resource_url);
+    if origins.contains_key(&origin) || origins.len() < MAX_LEARNED_ORIGINS {
+        *origins.entry(origin).or_default() += 1;
+    }
+}
+
+/// Origin of `url` and the CDN origins its domain used most on previous page loads
+fn origins_to_warm(url: &Url, store: &PreconnectStore) -> Vec<String> {
+    let mut origins = vec![origin_of(url)];
+    let host = url.host_str().unwrap_or("").to_ascii_lowercase();
+    if let Some(learned) = store.subresource_origins.get(&host) {
+        let mut learned: Vec<(&String, &u32)> = learned.iter().collect();
+        learned.sort_by(|a, b| b.1.cmp(a.1).then_with(|| a.0.cmp(b.0)));
+        origins.extend(learned.into_iter().take(MAX_CDN_ORIGINS).map(|(origin, _)| origin.clone()));
+    }
+    origins
+}
+
+/// Warms the connection pool for the article at `url` (hovered or selected in the list):
+/// resolves and connects to its origin and the CDN origins learned for its domain, so the
+/// fetch that follows skips DNS and TLS setup. Origins still warm are skipped, as is
+/// everything in metered and offline mode or once `MAX_WARM_ORIGINS` are warm. Returns the origins warmed.
+pub async fn logic_preconnect(url: String, state: &ProxyState) -> Result<Vec<String>, String> {
+    let url_obj = Url::parse(&url).map_err(|e| e.to_string())?;
+    if !matches!(url_obj.scheme(), "http" | "https") {
+        return Err(format!("Cannot preconnect to '{}'", url));
+    }
+    if state.metered.load(Ordering::Relaxed) || connectivity::is_offline(state) {
+        return Ok(Vec::new());
+    }
+
+    let origins: Vec<String> = {
+        let mut store = state.preconnect.lock().unwrap();
+        let now = Instant::now();
+        store.warm.retain(|_, warmed| now.duration_since(*warmed) < WARM_IDLE);
+        let candidates = origins_to_warm(&url_obj, &store);
+        let mut origins = Vec::new();
+        for origin in candidates {
+            if store.warm.contains_key(&origin) || store.warm.len() >= MAX_WARM_ORIGINS {
+                continue;
+            }
+            store.warm.insert(origin.clone(), now);
+            origins.push(origin);
+        }
+        origins
+    };
+    if origins.is_empty() {
+        return Ok(origins);
+    }
+
+    let client = pooled_client(&url_obj, state)?;
+    let warm_ups = origins.iter().map(|origin| {
+        // A HEAD of the origin's root: reqwest has no connect-only call
+        let request = client.head(origin.as_str()).header(USER_AGENT, state.next_user_agent()).timeout(PRECONNECT_TIMEOUT);
+        async move {
+            let started = Instant::now();
+            match request.send().await {
+                Ok(_) => println!("[preconnect::preconnect] {} warm in {}ms", origin, started.elapsed().as_millis()),
+                Err(e) => println!("[preconnect::preconnect] {} failed: {}", origin, e),
+            }
+        }
+    });
+    join_all(warm_ups).await;
+    Ok(origins)
+}
+
+/// Metered connection: nothing is fetched ahead of the user (preconnects are skipped)
+pub fn logic_set_metered_mode(enabled: bool, state: &ProxyState) {
+    println!("[preconnect::set_metered_mode] {}", enabled);
+    state.metered.store(enabled, Ordering::Relaxed);
+}
+
+/// Forgets the subresource origins learned for `domain` and its subdomains
+pub fn clear_preconnect_for_domain(domain: &str, dry_run: bool, state: &ProxyState) -> MutationReport {
+    let mut report = MutationReport::new(dry_run);
+    let host = host_of_domain_key(domain);
+
+    let mut store = state.preconnect.lock().unwrap();
+    let matching: Vec<String> = store.subresource_origins.keys().filter(|key| host_in_domain(key, &host)).cloned().collect();
+    for key in matching {
+        report.record("subresource_origins", key.clone(), None);
+        if !dry_run {
+            store.subresource_origins.remove(&key);
+        }
+    }
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::shared::logic_fetch_raw_html;
+    use crate::test_support::check_clear_for_domain;
+    use axum::extract::ConnectInfo;
+    use axum::routing::get;
+    use axum::Router;
+    use std::net::SocketAddr;
+    use std::sync::{Arc, Mutex};
+
+    #[test]
+    fn clears_the_subresource_origins_of_a_domain() {
+        check_clear_for_domain(
+            |state, host| {
+                let article = Url::parse(&format!("https://{}/article", host)).unwrap();
+                record_subresource(&article, &Url::parse("https://cdn.example.net/app.css").unwrap(), state);
+            },
+            clear_preconnect_for_domain,
+            |state| state.preconnect.lock().unwrap().subresource_origins.keys().cloned().collect(),
+        );
+    }
+
+    /// Serves a page on a free loopback port, logging the client address of every request
+    async fn logged_origin() -> (Url, Arc<Mutex<Vec<SocketAddr>>>) {
+        let peers = Arc::new(Mutex::new(Vec::new()));
+        let log = peers.clone();
+        let app = Router::new().fallback(get(move |ConnectInfo(peer): ConnectInfo<SocketAddr>| {
+            log.lock().unwrap().push(peer);
+            async { "<html><body><p>article</p></body></html>" }
+        }));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app.into_make_service_with_connect_info::<SocketAddr>()).await.unwrap() });
+        (Url::parse(&format!("http://{}/story", addr)).unwrap(), peers)
+    }
+
+    #[tokio::test]
+    async fn fetch_after_preconnect_reuses_the_warm_connection() {
+        let (url, peers) = logged_origin().await;
+        let state = ProxyState::default();
+        assert_eq!(logic_preconnect(url.to_string(), &state).await.unwrap(), [origin_of(&url)]);
+        // Still warm: not warmed twice
+        assert!(logic_preconnect(url.to_string(), &state).await.unwrap().is_empty());
+        logic_fetch_raw_html(url.to_string(), Some(5), None, &state).await.unwrap();
+
+        let peers = peers.lock().unwrap().clone();
+        assert_eq!(peers.len(), 2);
+        assert_eq!(peers[0], peers[1], "the fetch opened a new connection");
+    }
+
+    #[tokio::test]
+    async fn nothing_is_warmed_in_metered_mode() {
+        let (url, peers) = logged_origin().await;
+        let state = ProxyState::default();
+        logic_set_metered_mode(true, &state);
+        assert!(logic_preconnect(url.to_string(), &state).await.unwrap().is_empty());
+        assert!(peers.lock().unwrap().is_empty());
+        assert!(logic_preconnect("ftp://example.com/".to_string(), &state).await.is_err());
+    }
+
+    /// Latency saved by a preconnect on a real origin, which the sandboxed test run can't
+    /// reach: `PRECONNECT_BENCH_URL=https://... cargo test --lib preconnect_latency -- --ignored --nocapture`
+    #[tokio::test]
+    #[ignore]
+    async fn preconnect_latency() {
+        let url = std::env::var("PRECONNECT_BENCH_URL").expect("PRECONNECT_BENCH_URL is not set");
+        let fetch_ms = |state: ProxyState| {
+            let url = url.clone();
+            async move {
+                let started = Instant::now();
+                logic_fetch_raw_html(url, Some(30), None, &state).await.unwrap();
+                started.elapsed().as_secs_f64() * 1000.0
+            }
+        };
+        let (mut cold, mut warm) = (Vec::new(), Vec::new());
+        for _ in 0..10 {
+            cold.push(fetch_ms(ProxyState::default()).await);
+            let state = ProxyState::default();
+            logic_preconnect(url.clone(), &state).await.unwrap();
+            warm.push(fetch_ms(state).await);
+        }
+        let median = |times: &mut Vec<f64>| {
+            times.sort_by(f64::total_cmp);
+            (times[times.len() / 2 - 1] + times[times.len() / 2]) / 2.0
+        };
+        println!("{}: fetch on a cold pool {:.1}ms, after preconnect {:.1}ms (medians of 10)", url, median(&mut cold), median(&mut warm));
+    }
+}
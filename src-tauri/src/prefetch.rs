@@ -0,0 +1,198 @@
+use crate::connectivity;
+use crate::shared::ProxyState;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+
+/// Version of the exported reading stats
+pub const READING_STATS_VERSION: u32 = 1;
+
+/// Items of a feed offered before a feed that's never opened is skipped
+const MIN_OFFERS: u64 = 10;
+
+/// Opens recorded before reading windows are inferred (until then every hour counts as one)
+const MIN_OPENS_FOR_WINDOWS: u64 = 20;
+
+/// Open rate above which items are prefetched outside reading windows too
+const HIGH_OPEN_RATE: f64 = 0.5;
+
+/// Item URLs remembered to count each item once; forgotten all at once past this
+const MAX_REMEMBERED_URLS: usize = 5000;
+
+/// How often a feed's items are opened once offered for prefetching
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct FeedReadingStats {
+    pub offered: u64,
+    pub opened: u64,
+}
+
+/// What the predictor learned. Kept locally only; exported and imported as a whole so it
+/// can be part of the app's backups.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReadingStats {
+    pub version: u32,
+    /// Keyed by feed URL
+    pub feeds: HashMap<String, FeedReadingStats>,
+    /// Articles opened per local hour of the day
+    pub hourly_opens: [u64; 24],
+    /// `false` prefetches the first items in order, ignoring the stats
+    pub smart: bool,
+}
+
+impl Default for ReadingStats {
+    fn default() -> Self {
+        Self { version: READING_STATS_VERSION, feeds: HashMap::new(), hourly_opens: [0; 24], smart: true }
+    }
+}
+
+#[derive(Default)]
+pub struct PrefetchStore {
+    stats: ReadingStats,
+    offered_urls: HashSet<String>,
+    opened_urls: HashSet<String>,
+}
+
+/// Inserts `url` in `urls`, forgetting every URL first when the set is full. Returns false
+/// when `url` was already there.
+fn remember(urls: &mut HashSet<String>, url: &str) -> bool {
+    if urls.contains(url) {
+        return false;
+    }
+    if urls.len() >= MAX_REMEMBERED_URLS {
+        urls.clear();
+    }
+    urls.insert(url.to_string())
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct PrefetchCandidate {
+    pub url: String,
+    /// URL of the feed the item comes from
+    pub feed: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct PrefetchRequest {
+    /// Unread items in display order
+    pub candidates: Vec<PrefetchCandidate>,
+    /// Maximum number of items to prefetch
+    pub limit: usize,
+    /// Current hour of the day in the user's time zone (0–23)
+    pub local_hour: u8,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PrefetchDecision {
+    pub url: String,
+    pub feed: String,
+    pub prefetch: bool,
+    /// Estimated open rate of the item's feed
+    pub score: f64,
+    pub reason: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PrefetchPlan {
+    pub smart: bool,
+    /// The current hour is one the user usually reads at
+    pub in_reading_window: bool,
+    /// Every candidate, items to prefetch first in priority order
+    pub items: Vec<PrefetchDecision>,
+}
+
+/// Open rate of a feed, smoothed so feeds with few samples start at 50%
+fn open_rate(stats: Option<&FeedReadingStats>) -> f64 {
+    let (opened, offered) = stats.map_or((0, 0), |s| (s.opened, s.offered.max(s.opened)));
+    (opened as f64 + 1.0) / (offered as f64 + 2.0)
+}
+
+/// Whether `hour` and its neighbours hold at least their uniform share of opens
+fn in_reading_window(hourly_opens: &[u64; 24], hour: u8) -> bool {
+    let total: u64 = hourly_opens.iter().sum();
+    if total < MIN_OPENS_FOR_WINDOWS {
+        return true;
+    }
+    let hour = hour as usize % 24;
+    let around: u64 = [23, 0, 1].iter().map(|offset| hourly_opens[(hour + offset) % 24]).sum();
+    around * 8 >= total
+}
+
+/// Records that the user opened `url` from `feed` at `local_hour`
+pub fn logic_article_opened(url: String, feed: String, local_hour: u8, state: &ProxyState) -> Result<(), String> {
+    if local_hour > 23 {
+        return Err(format!("Invalid hour {}", local_hour));
+    }
+    let mut store = state.prefetch.lock().unwrap();
+    if !remember(&mut store.opened_urls, &url) {
+        return Ok(());
+    }
+    store.stats.hourly_opens[local_hour as usize] += 1;
+    store.stats.feeds.entry(feed).or_default().opened += 1;
+    Ok(())
+}
+
+/// Decides which candidates to prefetch. With smart prefetching, feeds are ranked by open
+/// rate, feeds never opened are skipped, and outside the usual reading hours only feeds that
+/// are opened most of the time are prefetched. Candidates are counted as offered to their feed.
+pub fn logic_get_prefetch_plan(request: PrefetchRequest, state: &ProxyState) -> Result<PrefetchPlan, String> {
+    if request.local_hour > 23 {
+        return Err(format!("Invalid hour {}", request.local_hour));
+    }
+    let offline = connectivity::is_offline(state);
+    let mut store = state.prefetch.lock().unwrap();
+    for candidate in &request.candidates {
+        if remember(&mut store.offered_urls, &candidate.url) {
+            store.stats.feeds.entry(candidate.feed.clone()).or_default().offered += 1;
+        }
+    }
+
+    let smart = store.stats.smart;
+    let in_window = in_reading_window(&store.stats.hourly_opens, request.local_hour);
+    let mut items: Vec<PrefetchDecision> = request
+        .candidates
+        .into_iter()
+        .map(|candidate| {
+            let stats = store.stats.feeds.get(&candidate.feed);
+            let score = open_rate(stats);
+            let (prefetch, reason) = match stats {
+                _ if offline => (false, "offline".to_string()),
+                _ if !smart => (true, "smart prefetch is off".to_string()),
+                Some(s) if s.offered >= MIN_OFFERS && s.opened == 0 => (false, format!("none of {} items of this feed were opened", s.offered)),
+                _ if !in_window && score < HIGH_OPEN_RATE => (false, "outside the usual reading hours".to_string()),
+                _ => (true, format!("{:.0}% of this feed's items are opened", score * 100.0)),
+            };
+            PrefetchDecision { url: candidate.url, feed: candidate.feed, prefetch, score, reason }
+        })
+        .collect();
+
+    if smart {
+        // Stable: items of equally ranked feeds keep their display order
+        items.sort_by(|a, b| b.prefetch.cmp(&a.prefetch).then(b.score.total_cmp(&a.score)));
+    }
+    for item in items.iter_mut().filter(|item| item.prefetch).skip(request.limit) {
+        item.prefetch = false;
+        item.reason = format!("beyond the limit of {} items", request.limit);
+    }
+    Ok(PrefetchPlan { smart, in_reading_window: in_window, items })
+}
+
+/// Switches between smart prefetching and prefetching the first items in order
+pub fn logic_set_smart_prefetch(enabled: bool, state: &ProxyState) {
+    println!("[prefetch::set_smart_prefetch] {}", enabled);
+    state.prefetch.lock().unwrap().stats.smart = enabled;
+}
+
+pub fn logic_export_reading_stats(state: &ProxyState) -> ReadingStats {
+    state.prefetch.lock().unwrap().stats.clone()
+}
+
+/// Replaces the learned stats, e.g. when restoring a backup
+pub fn logic_import_reading_stats(stats: ReadingStats, state: &ProxyState) -> Result<(), String> {
+    if stats.version > READING_STATS_VERSION {
+        return Err(format!("Reading stats version {} is newer than supported ({})", stats.version, READING_STATS_VERSION));
+    }
+    let mut store = state.prefetch.lock().unwrap();
+    store.stats = ReadingStats { version: READING_STATS_VERSION, ..stats };
+    store.offered_urls.clear();
+    store.opened_urls.clear();
+    Ok(())
+}
@@ -0,0 +1,112 @@
+use serde::Serialize;
+use std::sync::Mutex;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+/// Where the text of an article came from
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ProvenanceSource {
+    /// Fetched from the site for this extraction
+    #[default]
+    LiveFetch,
+    /// Extracted earlier from the page rendered in the iframe fallback
+    RenderedIframe,
+    /// Body picked by the host's ftr-site-config rules instead of readability
+    ScrapeRule,
+    /// Fixture of the active chaos profile (development only)
+    Fixture,
+    /// Latest stored version of a starred or archived article, read in offline mode
+    StoredVersion,
+}
+
+/// How an article was obtained, for the reader's info popover
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Provenance {
+    pub source: ProvenanceSource,
+    /// URL the content was fetched from, after redirects
+    pub url: Option<String>,
+    /// Unix time (ms) of the fetch. `None` for content served from the rendered page cache.
+    pub fetched_at: Option<u64>,
+    pub http_status: Option<u16>,
+    /// Browser name and version the User-Agent claimed (e.g. `Firefox/75.0`)
+    pub user_agent: Option<String>,
+    /// Authentication method used, never the credentials
+    pub auth: Option<String>,
+    /// Fetched again as a search engine crawler (Googlebot's User-Agent, no cookies) because
+    /// the page served a teaser: the reader shows "retrieved via crawler UA"
+    pub crawler_retry: bool,
+    /// Processing steps that ran, in order
+    pub processors: Vec<String>,
+    pub elapsed_ms: u64,
+}
+
+/// Collects the provenance of an extraction as it goes through the pipeline. Shared by
+/// reference like the `Deadline`, hence the lock.
+#[derive(Debug)]
+pub struct ProvenanceBuilder {
+    started: Instant,
+    provenance: Mutex<Provenance>,
+}
+
+impl Default for ProvenanceBuilder {
+    fn default() -> Self {
+        Self { started: Instant::now(), provenance: Mutex::new(Provenance::default()) }
+    }
+}
+
+/// Product token of a User-Agent naming its browser, most specific first
+const USER_AGENT_PRODUCTS: &[&str] = &["Googlebot/", "bingbot/", "Edg/", "OPR/", "Firefox/", "Chrome/", "Version/", "Safari/"];
+
+/// Browser name of a User-Agent string, or its first product when none is known
+pub fn user_agent_name(user_agent: &str) -> String {
+    let tokens: Vec<&str> = user_agent.split([' ', ';', '(', ')']).filter(|token| !token.is_empty()).collect();
+    USER_AGENT_PRODUCTS
+        .iter()
+        .find_map(|product| tokens.iter().find(|token| token.starts_with(product)))
+        .map(|token| match token.strip_prefix("Version/") {
+            // Safari reports its version as `Version/x`
+            Some(version) => format!("Safari/{}", version),
+            None => token.to_string(),
+        })
+        .or_else(|| tokens.first().map(|token| token.to_string()))
+        .unwrap_or_default()
+}
+
+impl ProvenanceBuilder {
+    pub fn source(&self, source: ProvenanceSource) {
+        self.provenance.lock().unwrap().source = source;
+    }
+
+    /// URL the content belongs to, when it wasn't fetched for this extraction
+    pub fn url(&self, url: &str) {
+        self.provenance.lock().unwrap().url = Some(url.to_string());
+    }
+
+    /// Records the request that fetched the page
+    pub fn fetched(&self, url: &str, http_status: Option<u16>, user_agent: Option<&str>) {
+        let mut provenance = self.provenance.lock().unwrap();
+        provenance.url = Some(url.to_string());
+        provenance.fetched_at = Some(SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_millis() as u64).unwrap_or(0));
+        provenance.http_status = http_status;
+        provenance.user_agent = user_agent.map(user_agent_name);
+    }
+
+    pub fn auth(&self, method: &str) {
+        self.provenance.lock().unwrap().auth = Some(method.to_string());
+    }
+
+    pub fn crawler_retry(&self) {
+        self.provenance.lock().unwrap().crawler_retry = true;
+    }
+
+    /// Records that the processing step `name` ran
+    pub fn processor(&self, name: &str) {
+        self.provenance.lock().unwrap().processors.push(name.to_string());
+    }
+
+    pub fn finish(&self) -> Provenance {
+        let mut provenance = self.provenance.lock().unwrap().clone();
+        provenance.elapsed_ms = self.started.elapsed().as_millis() as u64;
+        provenance
+    }
+}
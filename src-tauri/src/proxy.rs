@@ -1,18 +1,40 @@
-use crate::shared::ProxyState;
+use crate::caching::{self, with_cache_class, CacheClass};
+use crate::chaos::{self, ChaosFault};
+use crate::latency::{self, RequestPriority};
+use crate::element_filters;
+use crate::preconnect;
+use crate::resource_cache::{self, BufferedBody};
+use crate::connectivity;
+use crate::credentials;
+use crate::icons;
+use crate::supervisor::{self, RestartPolicy};
+use crate::messages::{self, ScriptMessage};
+use crate::mixed_content::{self, InsecureAction, MixedContentPlan};
+use crate::transfer::transfer_handler;
+use crate::shared::{
+    accept_language_for, decode_text, escape_html, header_charset, host_header_of, js_value_literal, origin_of, read_html_limited, read_text_limited,
+    unescape_html, unwrap_noscript_images, with_protocol_for, ProxyState, BODY_TOO_LARGE, DEFAULT_PROXY_ACCEPT_LANGUAGE,
+};
 use axum::{
     body::{to_bytes, Body},
     extract::{Path, Query, State},
-    http::{header, StatusCode, Uri},
+    http::{header, HeaderMap, HeaderValue, Method, StatusCode, Uri},
     response::Response,
     routing::get,
     Router,
     middleware::{self, Next},
 };
 use axum::http::Request;
-use lol_html::{element, HtmlRewriter, Settings};
+use futures_util::StreamExt;
+use lol_html::html_content::{ContentType, Element, TextChunk};
+use lol_html::{element, rewrite_str, text, HtmlRewriter, RewriteStrSettings, Settings};
 use tokio::net::TcpListener;
 use tower_http::trace::TraceLayer;
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
 use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::LazyLock;
 use url::Url;
 
 // Middleware to log all incoming requests
@@ -24,15 +46,73 @@ async fn log_requests(uri: Uri, req: axum::http::Request<Body>, next: Next) -> R
 // The listener script that will be injected to handle communication.
 // It posts the fully rendered HTML back to the parent window via postMessage.
 // The parent can then run Readability on that HTML (which includes JS-rendered content).
-const LISTENER_SCRIPT: &str = r#"
+// Message type names come from `messages::js_constants`, substituted for `/*MESSAGE_CONSTANTS*/`.
+// Each injection gets its own nonce (see `listener_script`), substituted for `/*INJECTION_NONCE*/`.
+// The `SnapshotConfig` in effect is substituted for `/*SNAPSHOT_CONFIG*/`, and whether the
+// action bar is injected (`ArticleActionConfig::action_bar`) for `/*ACTION_BAR*/`.
+const LISTENER_SCRIPT_TEMPLATE: &str = r#"
 <script>
 
     (function(){
+        /*MESSAGE_CONSTANTS*/
+
+        // Only the first copy of the script runs: pages with duplicated <body> tags, or that
+        // clone their own markup, would otherwise install every behavior twice
+        const INJECTION_NONCE = '/*INJECTION_NONCE*/';
+        if (window.__proxyListenerNonce__) {
+            if (window.__proxyListenerNonce__ !== INJECTION_NONCE) {
+                console.warn('[Proxy Injected Script] Another injection is already active, skipping');
+            }
+            return;
+        }
+        window.__proxyListenerNonce__ = INJECTION_NONCE;
+
+        // Overlay removal settings (see `SnapshotConfig`)
+        const SNAPSHOT_CONFIG = /*SNAPSHOT_CONFIG*/;
+
+        // Opt-in action bar (see `ArticleActionConfig`)
+        const ACTION_BAR_ENABLED = /*ACTION_BAR*/;
+
         // Always allow posting messages to parent even if cross-origin
         // (postMessage doesn't require same-origin). We keep a flag in case
         // future logic needs to avoid parent access.
         let canAccessParent = !!(window.parent && window.parent !== window);
 
+        // What the injected behaviors did on this page, posted as FEATURES_REPORT (at most twice)
+        const featureCounts = {
+            videosFound: 0, overlaysInstalled: 0, embedsWrapped: 0,
+            scrollPasses: 0, consentOverlaysRemoved: 0, lazyBackgroundsPromoted: 0, modalOverlaysRemoved: 0,
+            errorsCaught: 0
+        };
+        const featureSessionId = Date.now().toString(36) + Math.random().toString(36).slice(2, 10);
+        let featureReportsSent = 0;
+        let lastFeatureReport = '';
+
+        function sendFeaturesReport() {
+            try {
+                const report = JSON.stringify(featureCounts);
+                if (featureReportsSent >= 2 || report === lastFeatureReport || !canAccessParent) return;
+                featureReportsSent++;
+                lastFeatureReport = report;
+                // Pages served by the resource handler carry their upstream URL in `url`
+                const pageUrl = new URLSearchParams(window.location.search).get('url') || window.location.href;
+                window.parent.postMessage({
+                    type: MESSAGE_TYPES.FEATURES_REPORT,
+                    sessionId: featureSessionId,
+                    pageUrl: pageUrl,
+                    counts: JSON.parse(report)
+                }, '*');
+            } catch (e) {
+                // ignore
+            }
+        }
+
+        // First report once load-time work is done, a last one if anything changed since
+        window.addEventListener('load', function() {
+            setTimeout(sendFeaturesReport, 5000);
+            setTimeout(sendFeaturesReport, 20000);
+        });
+
         // Intercept fullscreen errors and relay to parent for nested iframes (e.g., Twitter)
         // Since we can't intercept errors from cross-origin iframes directly,
         // we use multiple strategies: fullscreenerror events, unhandledrejection, and console.error proxy
@@ -44,7 +124,7 @@ const LISTENER_SCRIPT: &str = r#"
                     fullscreenRequested = true;
                     console.log('[Proxy] Relaying fullscreen request to parent');
                     window.parent.postMessage({ 
-                        type: 'TWITTER_FULLSCREEN_REQUEST' 
+                        type: MESSAGE_TYPES.TWITTER_FULLSCREEN_REQUEST 
                     }, '*');
                     // Reset flag after 2 seconds
                     setTimeout(function() {
@@ -118,6 +198,7 @@ const LISTENER_SCRIPT: &str = r#"
                     if (scrollPosition >= currentHeight || scrolls >= maxScrolls) {
                         // Scroll back to top when done
                         window.scrollTo(0, 0);
+                        featureCounts.scrollPasses++;
                         resolve();
                     } else {
                         setTimeout(doScroll, scrollDelay);
@@ -128,15 +209,224 @@ const LISTENER_SCRIPT: &str = r#"
             });
         }
 
+        // Remove consent/cookie overlays (OneTrust, Cookiebot, Quantcast, ...) that block
+        // the page, then restore body scrolling which these frameworks usually lock.
+        const CONSENT_SELECTORS = [
+            '#onetrust-consent-sdk', '#onetrust-banner-sdk',
+            '#CybotCookiebotDialog', '#CybotCookiebotDialogBodyUnderlay',
+            '#qc-cmp2-container', '.qc-cmp2-container', '#didomi-host',
+            'div[id^="sp_message_container"]',
+            '#cookie-banner', '.cookie-banner', '#cookie-consent', '.cookie-consent',
+            'div[aria-label*="cookie" i]', 'section[aria-label*="cookie" i]', 'aside[aria-label*="cookie" i]',
+            '[role="dialog"][aria-label*="consent" i]'
+        ];
+
+        function removeConsentOverlays() {
+            let removed = 0;
+            try {
+                document.querySelectorAll(CONSENT_SELECTORS.join(',')).forEach(function(el) {
+                    el.remove();
+                    removed++;
+                });
+
+                // Fixed overlays with a huge z-index: only look at likely candidates
+                // (direct body children and consent-ish names) to keep this cheap.
+                const candidates = new Set(document.body ? Array.from(document.body.children) : []);
+                document.querySelectorAll('[id*="consent" i], [class*="consent" i], [id*="cookie" i], [class*="cookie" i], [id*="gdpr" i], [class*="gdpr" i]')
+                    .forEach(function(el) { candidates.add(el); });
+                candidates.forEach(function(el) {
+                    if (!el.isConnected || el.tagName === 'SCRIPT' || el.tagName === 'STYLE') return;
+                    const style = window.getComputedStyle(el);
+                    const zIndex = parseInt(style.zIndex, 10);
+                    if (style.position === 'fixed' && zIndex >= 1000) {
+                        el.remove();
+                        removed++;
+                    }
+                });
+
+                if (removed > 0) {
+                    restoreScrolling();
+                    console.log('[Proxy Injected Script] Removed consent overlays:', removed);
+                }
+            } catch (e) {
+                featureCounts.errorsCaught++;
+            }
+            featureCounts.consentOverlaysRemoved += removed;
+            return removed;
+        }
+
+        // Undo the scroll lock overlays put on the page: `overflow: hidden`, or a `position:
+        // fixed` body that freezes it in place
+        function restoreScrolling() {
+            [document.documentElement, document.body].forEach(function(el) {
+                if (!el) return;
+                const style = window.getComputedStyle(el);
+                if (style.overflow === 'hidden' || style.overflowY === 'hidden') {
+                    el.style.setProperty('overflow', 'auto', 'important');
+                }
+                if (style.position === 'fixed') {
+                    el.style.setProperty('position', 'static', 'important');
+                }
+            });
+        }
+
+        // Newsletter sign-up and metered-paywall modals are injected by scripts after load,
+        // so nothing names them reliably: they are told apart by their shape. A candidate is
+        // fixed (or absolute), above SNAPSHOT_CONFIG.min_z_index, covers at least
+        // min_viewport_coverage of the viewport and doesn't hold the article. Overlays already
+        // covering the page at load are left alone unless include_initial_overlays is set.
+        const MODAL_NAME_PATTERN = /modal|overlay|paywall|newsletter|subscribe|signup|sign-up|popup|regwall|piano|tp-backdrop|tp-modal|meter/i;
+        const addedAfterLoad = new Set();
+        let initialOverlays = null;
+
+        function viewportCoverage(el) {
+            const rect = el.getBoundingClientRect();
+            const width = Math.max(0, Math.min(rect.right, window.innerWidth) - Math.max(rect.left, 0));
+            const height = Math.max(0, Math.min(rect.bottom, window.innerHeight) - Math.max(rect.top, 0));
+            const viewport = window.innerWidth * window.innerHeight;
+            return viewport > 0 ? (width * height) / viewport : 0;
+        }
+
+        function isCoveringOverlay(el) {
+            if (!el.isConnected || el === document.body || el === document.documentElement) return false;
+            if (/^(SCRIPT|STYLE|LINK|META|NOSCRIPT|TEMPLATE|VIDEO|IFRAME)$/.test(el.tagName)) return false;
+            const style = window.getComputedStyle(el);
+            if (style.position !== 'fixed' && style.position !== 'absolute') return false;
+            if (style.display === 'none' || style.visibility === 'hidden' || parseFloat(style.opacity) === 0) return false;
+            const zIndex = parseInt(style.zIndex, 10);
+            if (isNaN(zIndex) || zIndex < SNAPSHOT_CONFIG.min_z_index) return false;
+            return viewportCoverage(el) >= SNAPSHOT_CONFIG.min_viewport_coverage;
+        }
+
+        // Elements worth checking: body children, elements added since load, dialogs and
+        // anything named like a modal. Checking every element's style would be too slow.
+        function overlayCandidates() {
+            const candidates = new Set(document.body ? Array.from(document.body.children) : []);
+            addedAfterLoad.forEach(function(el) { candidates.add(el); });
+            document.querySelectorAll('[role="dialog"], [aria-modal="true"], dialog[open], [id], [class]').forEach(function(el) {
+                if (el.matches('[role="dialog"], [aria-modal="true"], dialog[open]') || MODAL_NAME_PATTERN.test((el.id || '') + ' ' + (el.getAttribute('class') || ''))) {
+                    candidates.add(el);
+                }
+            });
+            return candidates;
+        }
+
+        // Whether removing `el` would take the article with it
+        function holdsArticle(el) {
+            const main = document.querySelector('article, main, [role="main"]');
+            if (main && el.contains(main)) return true;
+            const pageText = document.body ? document.body.innerText.length : 0;
+            return pageText > 0 && (el.innerText || '').length > pageText / 2;
+        }
+
+        function coveringOverlays() {
+            const found = [];
+            overlayCandidates().forEach(function(el) {
+                if (isCoveringOverlay(el)) found.push(el);
+            });
+            return found;
+        }
+
+        function removeModalOverlays() {
+            let removed = 0;
+            if (!SNAPSHOT_CONFIG.remove_modal_overlays) return removed;
+            try {
+                coveringOverlays().forEach(function(el) {
+                    if (!SNAPSHOT_CONFIG.include_initial_overlays && initialOverlays && initialOverlays.has(el)) return;
+                    if (holdsArticle(el)) return;
+                    el.remove();
+                    removed++;
+                });
+                if (removed > 0) {
+                    restoreScrolling();
+                    console.log('[Proxy Injected Script] Removed modal overlays:', removed);
+                }
+            } catch (e) {
+                featureCounts.errorsCaught++;
+            }
+            featureCounts.modalOverlaysRemoved += removed;
+            return removed;
+        }
+
+        window.addEventListener('load', function() {
+            try {
+                initialOverlays = new Set(coveringOverlays());
+            } catch (e) {
+                initialOverlays = new Set();
+            }
+            try {
+                new MutationObserver(function(mutations) {
+                    mutations.forEach(function(mutation) {
+                        mutation.addedNodes.forEach(function(node) {
+                            if (node.nodeType === Node.ELEMENT_NODE) addedAfterLoad.add(node);
+                        });
+                    });
+                }).observe(document.documentElement, { childList: true, subtree: true });
+            } catch (e) {
+                // ignore if MutationObserver not available
+            }
+        });
+
+        // Background images that lazy-loading libraries (LazyLoad, lozad, lazysizes' bgset
+        // plugin...) only set once the element is seen by an IntersectionObserver, which a
+        // programmatic scroll doesn't always trigger. Values are a URL, a comma-separated list
+        // of URLs (lozad), or ready-made `url()`/`image-set()` values (LazyLoad's `data-bg-multi`).
+        const LAZY_BACKGROUND_ATTRIBUTES = [
+            'data-bg', 'data-bg-multi', 'data-background', 'data-background-image', 'data-bg-src', 'data-bgset'
+        ];
+
+        function lazyBackgroundValue(value) {
+            value = (value || '').trim();
+            if (!value) return null;
+            if (/^(url|image-set|-webkit-image-set|linear-gradient|radial-gradient)\(/i.test(value)) return value;
+            const candidates = value.split(',').map(function(candidate) { return candidate.trim().split(/\s+/); });
+            let urls = candidates.map(function(parts) { return parts[0]; })
+                .filter(function(url) { return url && !/^(javascript|about):/i.test(url); });
+            // lazysizes' bgset lists `url descriptor` alternatives: the first one is enough
+            if (candidates.some(function(parts) { return parts.length > 1; })) urls = urls.slice(0, 1);
+            if (urls.length === 0) return null;
+            return urls.map(function(url) { return 'url("' + url.replace(/["\\]/g, '\\$&') + '")'; }).join(', ');
+        }
+
+        // Copies lazy background attributes into an inline `background-image` on elements whose
+        // library hasn't set one yet, so the snapshot shows the hero and inline backgrounds
+        function promoteLazyBackgrounds() {
+            let promoted = 0;
+            try {
+                const selector = LAZY_BACKGROUND_ATTRIBUTES.map(function(attr) { return '[' + attr + ']'; }).join(',');
+                document.querySelectorAll(selector).forEach(function(el) {
+                    if (el.tagName === 'IMG' || el.tagName === 'SOURCE' || el.tagName === 'IFRAME') return;
+                    const current = el.style.backgroundImage;
+                    if (current && current !== 'none' && current !== 'initial') return;
+                    for (const attr of LAZY_BACKGROUND_ATTRIBUTES) {
+                        const value = lazyBackgroundValue(el.getAttribute(attr));
+                        if (!value) continue;
+                        el.style.setProperty('background-image', value);
+                        if (el.style.backgroundImage && el.style.backgroundImage !== 'none') {
+                            promoted++;
+                            break;
+                        }
+                    }
+                });
+            } catch (e) {
+                featureCounts.errorsCaught++;
+            }
+            featureCounts.lazyBackgroundsPromoted += promoted;
+            return promoted;
+        }
+
         // Helper to send the rendered HTML back to the parent window.
         function sendRenderedHTML() {
-            
+            removeConsentOverlays();
+            removeModalOverlays();
+            promoteLazyBackgrounds();
+
             try {
                 const html = document.documentElement.outerHTML;
                 // send as a message; parent should verify origin/source
-                window.parent.postMessage({ type: 'RENDERED_HTML', html: html }, '*');
+                window.parent.postMessage({ type: MESSAGE_TYPES.RENDERED_HTML, html: html }, '*');
             } catch (e) {
-                // ignore
+                featureCounts.errorsCaught++;
             }
         }
 
@@ -145,6 +435,8 @@ const LISTENER_SCRIPT: &str = r#"
             // Allow initial page scripts to run
             setTimeout(async function() {
                 try {
+                    // Consent walls lock scrolling, which defeats lazy-load reveal
+                    removeConsentOverlays();
                     await scrollToRevealContent();
                     // Give a moment for any final lazy-loaded content to settle
                     setTimeout(sendRenderedHTML, 800);
@@ -171,7 +463,7 @@ const LISTENER_SCRIPT: &str = r#"
         window.addEventListener('message', (event) => {
             try {
                 const { action } = event.data || {};
-                if (action === 'REQUEST_RENDERED') {
+                if (action === MESSAGE_TYPES.REQUEST_RENDERED) {
                     // Scroll first, then send
                     scrollToRevealContent().then(() => {
                         setTimeout(sendRenderedHTML, 500);
@@ -182,11 +474,82 @@ const LISTENER_SCRIPT: &str = r#"
             } catch (e) {}
         });
 
+        // Action bar: hidden until TOGGLE_ACTION_BAR (or Alt+Shift+A in the page). Buttons post
+        // ARTICLE_ACTION; the app runs the action on the page's original URL.
+        if (ACTION_BAR_ENABLED) {
+            const ACTION_BAR_ACTIONS = [
+                ['save_offline', 'Save offline'],
+                ['star_archive', 'Star & archive'],
+                ['share', 'Share'],
+                ['download_media', 'Download media'],
+                ['copy_clean_url', 'Copy clean URL']
+            ];
+            let actionBar = null;
+
+            function buildActionBar() {
+                const host = document.createElement('div');
+                host.id = '__proxy_action_bar__';
+                // A shadow root keeps page CSS off the bar and the bar's CSS off the page
+                const root = host.attachShadow ? host.attachShadow({ mode: 'closed' }) : host;
+                const style = document.createElement('style');
+                style.textContent = `
+                    .__proxy_action_bar__{position:fixed;top:12px;right:12px;z-index:2147483647;display:flex;gap:6px;padding:6px;background:rgba(20,20,20,0.92);border-radius:8px;box-shadow:0 4px 16px rgba(0,0,0,0.3);font:13px/1.2 system-ui,-apple-system,"Segoe UI",sans-serif;}
+                    .__proxy_action_bar_btn__{all:unset;cursor:pointer;color:#fff;padding:6px 10px;border-radius:5px;background:rgba(255,255,255,0.1);}
+                    .__proxy_action_bar_btn__:hover{background:rgba(255,255,255,0.25);}
+                `;
+                const bar = document.createElement('div');
+                bar.className = '__proxy_action_bar__';
+                bar.setAttribute('role', 'toolbar');
+                ACTION_BAR_ACTIONS.forEach(function(entry) {
+                    const button = document.createElement('button');
+                    button.className = '__proxy_action_bar_btn__';
+                    button.textContent = entry[1];
+                    button.addEventListener('click', function(e) {
+                        e.preventDefault(); e.stopPropagation();
+                        if (!canAccessParent) return;
+                        window.parent.postMessage({
+                            type: MESSAGE_TYPES.ARTICLE_ACTION,
+                            sessionId: featureSessionId,
+                            action: entry[0]
+                        }, '*');
+                    });
+                    bar.appendChild(button);
+                });
+                root.appendChild(style);
+                root.appendChild(bar);
+                host.style.display = 'none';
+                return host;
+            }
+
+            function toggleActionBar() {
+                try {
+                    if (!actionBar) {
+                        actionBar = buildActionBar();
+                        (document.body || document.documentElement).appendChild(actionBar);
+                    }
+                    actionBar.style.display = actionBar.style.display === 'none' ? '' : 'none';
+                } catch (e) {
+                    featureCounts.errorsCaught++;
+                }
+            }
+
+            window.addEventListener('message', function(event) {
+                if (event.data && event.data.type === MESSAGE_TYPES.TOGGLE_ACTION_BAR) toggleActionBar();
+            });
+            document.addEventListener('keydown', function(e) {
+                if (e.altKey && e.shiftKey && (e.code === 'KeyA' || e.key === 'A')) {
+                    e.preventDefault();
+                    toggleActionBar();
+                }
+            }, { capture: true });
+        }
+
         // Detect videos in the page and notify parent
         function detectVideos() {
             try {
                 const videos = document.querySelectorAll('video');
                 console.log('[Proxy Injected Script] Found videos:', videos.length);
+                featureCounts.videosFound = Math.max(featureCounts.videosFound, videos.length);
                 
                 if (videos.length > 0) {
                     const video = videos[0];
@@ -196,12 +559,13 @@ const LISTENER_SCRIPT: &str = r#"
                     if (videoUrl) {
                         console.log('[Proxy Injected Script] Detected video URL:', videoUrl);
                         window.parent.postMessage({
-                            type: 'VIDEO_DETECTED',
+                            type: MESSAGE_TYPES.VIDEO_DETECTED,
                             url: videoUrl
                         }, '*');
                     }
                 }
             } catch (e) {
+                featureCounts.errorsCaught++;
                 console.error('[Proxy Injected Script] Error detecting videos:', e);
             }
         }
@@ -230,6 +594,7 @@ const LISTENER_SCRIPT: &str = r#"
                 videos.forEach((video) => {
                     if (video.dataset.__proxyOverlayInstalled__) return;
                     video.dataset.__proxyOverlayInstalled__ = 'true';
+                    featureCounts.overlaysInstalled++;
 
                     if (!video.hasAttribute('controls')) video.setAttribute('controls', 'controls');
 
@@ -252,14 +617,14 @@ const LISTENER_SCRIPT: &str = r#"
                             video.requestFullscreen().catch(function(err) {
                                 // If direct fullscreen fails, use modal player
                                 if (videoUrl) {
-                                    window.parent.postMessage({ type: 'OPEN_VIDEO', url: videoUrl, currentTime: ct }, '*');
+                                    window.parent.postMessage({ type: MESSAGE_TYPES.OPEN_VIDEO, url: videoUrl, currentTime: ct }, '*');
                                 }
                             });
                         } else if (video.webkitRequestFullscreen) {
                             video.webkitRequestFullscreen();
                         } else if (videoUrl) {
                             // Fallback to modal player
-                            window.parent.postMessage({ type: 'OPEN_VIDEO', url: videoUrl, currentTime: ct }, '*');
+                            window.parent.postMessage({ type: MESSAGE_TYPES.OPEN_VIDEO, url: videoUrl, currentTime: ct }, '*');
                         }
                     });
                     actions.appendChild(fsBtn);
@@ -275,12 +640,12 @@ const LISTENER_SCRIPT: &str = r#"
                         if (video.requestFullscreen) {
                             video.requestFullscreen().catch(function() {
                                 // Fallback to parent iframe fullscreen
-                                window.parent.postMessage({ type: 'TOGGLE_FULLSCREEN' }, '*');
+                                window.parent.postMessage({ type: MESSAGE_TYPES.TOGGLE_FULLSCREEN }, '*');
                             });
                         } else if (video.webkitRequestFullscreen) {
                             video.webkitRequestFullscreen();
                         } else {
-                            window.parent.postMessage({ type: 'TOGGLE_FULLSCREEN' }, '*');
+                            window.parent.postMessage({ type: MESSAGE_TYPES.TOGGLE_FULLSCREEN }, '*');
                         }
                     }, { capture: true });
                 });
@@ -350,6 +715,7 @@ const LISTENER_SCRIPT: &str = r#"
                         if (needsWrapper) {
                             container = document.createElement('div');
                             container.className = '__proxy_twitter_wrapper__';
+                            featureCounts.embedsWrapped++;
                             container.style.position = 'relative';
                             container.style.display = 'inline-block';
                             iframe.parentNode.insertBefore(container, iframe);
@@ -374,7 +740,7 @@ const LISTENER_SCRIPT: &str = r#"
                                 e.stopPropagation();
                                 console.log('[Proxy] Twitter custom fullscreen button clicked');
                                 window.parent.postMessage({ 
-                                    type: 'TWITTER_FULLSCREEN_REQUEST' 
+                                    type: MESSAGE_TYPES.TWITTER_FULLSCREEN_REQUEST 
                                 }, '*');
                             });
                             container.appendChild(fsBtn);
@@ -409,6 +775,7 @@ const LISTENER_SCRIPT: &str = r#"
                     if (needsWrapper) {
                         container = document.createElement('div');
                         container.className = '__proxy_embed_wrapper__';
+                        featureCounts.embedsWrapped++;
                         container.style.position = 'relative';
                         container.style.display = 'inline-block';
                         iframe.parentNode.insertBefore(container, iframe);
@@ -458,14 +825,14 @@ const LISTENER_SCRIPT: &str = r#"
                                             // Final fallback: use postMessage with iframe URL
                                             console.log('[Proxy] Using postMessage fallback with URL:', iframeUrl);
                                             window.parent.postMessage({ 
-                                                type: 'TOGGLE_FULLSCREEN',
+                                                type: MESSAGE_TYPES.TOGGLE_FULLSCREEN,
                                                 url: iframeUrl || undefined
                                             }, '*');
                                         });
                                     } else {
                                         console.log('[Proxy] No container fullscreen, using postMessage with URL:', iframeUrl);
                                         window.parent.postMessage({ 
-                                            type: 'TOGGLE_FULLSCREEN',
+                                            type: MESSAGE_TYPES.TOGGLE_FULLSCREEN,
                                             url: iframeUrl || undefined
                                         }, '*');
                                     }
@@ -478,7 +845,7 @@ const LISTENER_SCRIPT: &str = r#"
                                 container.requestFullscreen().catch(function(err) {
                                     console.log('[Proxy] Container fullscreen failed:', err);
                                     window.parent.postMessage({ 
-                                        type: 'TOGGLE_FULLSCREEN',
+                                        type: MESSAGE_TYPES.TOGGLE_FULLSCREEN,
                                         url: iframeUrl || undefined
                                     }, '*');
                                 });
@@ -488,7 +855,7 @@ const LISTENER_SCRIPT: &str = r#"
                             if (!fullscreenAttempted) {
                                 console.log('[Proxy] No fullscreen API, using postMessage with URL:', iframeUrl);
                                 window.parent.postMessage({ 
-                                    type: 'TOGGLE_FULLSCREEN',
+                                    type: MESSAGE_TYPES.TOGGLE_FULLSCREEN,
                                     url: iframeUrl || undefined
                                 }, '*');
                             }
@@ -505,24 +872,26 @@ const LISTENER_SCRIPT: &str = r#"
                             iframe.requestFullscreen().catch(function() {
                                 if (container.requestFullscreen) {
                                     container.requestFullscreen().catch(function() {
-                                        window.parent.postMessage({ type: 'TOGGLE_FULLSCREEN' }, '*');
+                                        window.parent.postMessage({ type: MESSAGE_TYPES.TOGGLE_FULLSCREEN }, '*');
                                     });
                                 } else {
-                                    window.parent.postMessage({ type: 'TOGGLE_FULLSCREEN' }, '*');
+                                    window.parent.postMessage({ type: MESSAGE_TYPES.TOGGLE_FULLSCREEN }, '*');
                                 }
                             });
                         } else if (iframe.webkitRequestFullscreen) {
                             iframe.webkitRequestFullscreen();
                         } else if (container.requestFullscreen) {
                             container.requestFullscreen().catch(function() {
-                                window.parent.postMessage({ type: 'TOGGLE_FULLSCREEN' }, '*');
+                                window.parent.postMessage({ type: MESSAGE_TYPES.TOGGLE_FULLSCREEN }, '*');
                             });
                         } else {
-                            window.parent.postMessage({ type: 'TOGGLE_FULLSCREEN' }, '*');
+                            window.parent.postMessage({ type: MESSAGE_TYPES.TOGGLE_FULLSCREEN }, '*');
                         }
                     }, { capture: true });
                 });
-            } catch (_) {}
+            } catch (_) {
+                featureCounts.errorsCaught++;
+            }
         }
 
         // Detect videos after page load - run early to prevent other scripts from scrolling
@@ -573,7 +942,7 @@ const LISTENER_SCRIPT: &str = r#"
 
         // Listen for restore video time message
         window.addEventListener('message', function(event) {
-            if (event.data && event.data.type === 'RESTORE_VIDEO_TIME' && event.data.videoUrl) {
+            if (event.data && event.data.type === MESSAGE_TYPES.RESTORE_VIDEO_TIME && event.data.videoUrl) {
                 try {
                     const targetUrl = event.data.videoUrl;
                     const targetTime = event.data.currentTime || 0;
@@ -620,224 +989,813 @@ const LISTENER_SCRIPT: &str = r#"
 </script>
 "#;
 
-// Handler for CORS preflight requests
-pub async fn cors_options_handler() -> Response {
-    Response::builder()
-        .status(StatusCode::NO_CONTENT)
-        .header(header::ACCESS_CONTROL_ALLOW_ORIGIN, "*")
-        .header(header::ACCESS_CONTROL_ALLOW_METHODS, "GET, POST, OPTIONS")
-        .header(header::ACCESS_CONTROL_ALLOW_HEADERS, "Content-Type, Authorization")
-        .header(header::ACCESS_CONTROL_MAX_AGE, "86400")
-        .body(Body::empty())
-        .unwrap()
-}
-
-pub async fn start_proxy_server(state: ProxyState) -> u16 {
-    let port = portpicker::pick_unused_port().expect("failed to find a free port");
+static LISTENER_SCRIPT: LazyLock<String> =
+    LazyLock::new(|| LISTENER_SCRIPT_TEMPLATE.replace("/*MESSAGE_CONSTANTS*/", &messages::js_constants()));
 
-    let app = Router::new()
-        .route("/proxy", get(proxy_resource_handler).options(cors_options_handler))
-        .route("/*path", get(proxy_handler).options(cors_options_handler))
-        .with_state(state)
-        .layer(middleware::from_fn(log_requests))
-        .layer(TraceLayer::new_for_http());
+// Lines of the listener script holding substituted values, which differ between injections
+const INJECTION_VALUE_LINES: &[&str] = &["const INJECTION_NONCE = ", "const SNAPSHOT_CONFIG = ", "const ACTION_BAR_ENABLED = "];
 
-    tokio::spawn(async move {
-        let listener = TcpListener::bind(format!("localhost:{}", port))
-            .await
-            .unwrap();
-        axum::serve(listener, app).await.unwrap();
-    });
+/// What the listener script removes from the page before sending `RENDERED_HTML`. Substituted
+/// into each injection for `/*SNAPSHOT_CONFIG*/`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SnapshotConfig {
+    /// Newsletter sign-up and paywall modals covering the page are removed
+    pub remove_modal_overlays: bool,
+    /// Fraction of the viewport a fixed/absolute element must cover to count as a modal.
+    /// Sticky headers and cookie bars stay well below the default.
+    pub min_viewport_coverage: f64,
+    /// Lowest z-index of a removed modal
+    pub min_z_index: i32,
+    /// Modals already covering the page when it loaded are removed too, not only those shown
+    /// afterwards. Catches server-rendered paywalls, at the cost of more false positives.
+    pub include_initial_overlays: bool,
+}
 
-    port
+impl Default for SnapshotConfig {
+    fn default() -> Self {
+        Self { remove_modal_overlays: true, min_viewport_coverage: 0.5, min_z_index: 10, include_initial_overlays: false }
+    }
 }
 
-// Handler for proxying external resources via /proxy?url=...
-pub async fn proxy_resource_handler(
-    Query(params): Query<HashMap<String, String>>,
-    State(state): State<ProxyState>,
-    req: Request<Body>,
-) -> Result<Response, StatusCode> {
-    let target_url_str = params.get("url").ok_or_else(|| {
-        eprintln!("Proxy resource handler: No 'url' parameter provided");
-        StatusCode::BAD_REQUEST
-    })?;
-    
-    println!("Proxy resource handler - RAW URL parameter: '{}'", target_url_str);
-    
-    // Decode the URL parameter
-    let decoded_url = urlencoding::decode(target_url_str).map_err(|e| {
-        eprintln!("Proxy resource handler: Failed to decode URL '{}': {}", target_url_str, e);
-        StatusCode::BAD_REQUEST
-    })?;
-    
-    println!("Proxy resource handler - DECODED URL: '{}'", decoded_url);
-    println!("Proxy resource handler - all params: {:?}", params);
-    
-    let target_url = Url::parse(&decoded_url).map_err(|e| {
-        eprintln!("Proxy resource handler: Failed to parse decoded URL '{}': {}", decoded_url, e);
-        StatusCode::BAD_REQUEST
-    })?;
+pub fn logic_get_snapshot_config(state: &ProxyState) -> SnapshotConfig {
+    SnapshotConfig::clone(&state.snapshot_config.load())
+}
 
-    // Extract domain for auth lookup
-    let domain = format!("{}://{}", 
-        target_url.scheme(), 
-        target_url.host_str().unwrap_or("localhost")
+/// Applies to pages proxied from now on; pages already loaded keep the config they got
+pub fn logic_set_snapshot_config(config: SnapshotConfig, state: &ProxyState) -> Result<(), String> {
+    if !(config.min_viewport_coverage > 0.0 && config.min_viewport_coverage <= 1.0) {
+        return Err(format!("Viewport coverage must be in (0, 1], got {}", config.min_viewport_coverage));
+    }
+    println!(
+        "[proxy::set_snapshot_config] Modal overlays {}",
+        if config.remove_modal_overlays { format!("removed above {:.0}% coverage", config.min_viewport_coverage * 100.0) } else { "kept".to_string() }
     );
-    
-    // Check for auth credentials for this domain
-    let auth_credentials = {
-        let creds = state.auth_credentials.lock().unwrap();
-        creds.get(&domain).cloned()
-    };
+    state.snapshot_config.store(std::sync::Arc::new(config));
+    Ok(())
+}
 
-    let (parts, body) = req.into_parts();
-    let body_bytes = to_bytes(body, usize::MAX)
-        .await
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+// Listener script for one injection, with a fresh nonce for the script's idempotency guard
+fn listener_script(snapshot: &SnapshotConfig, action_bar: bool) -> String {
+    LISTENER_SCRIPT
+        .replace("/*INJECTION_NONCE*/", &uuid::Uuid::new_v4().simple().to_string())
+        .replace("/*SNAPSHOT_CONFIG*/", &js_value_literal(snapshot, "{}"))
+        .replace("/*ACTION_BAR*/", if action_bar { "true" } else { "false" })
+}
 
-    let client = reqwest::Client::builder()
-        .cookie_store(true)
-        .cookie_provider(state.cookie_jar.clone())
-        .redirect(reqwest::redirect::Policy::limited(10))
-        .timeout(std::time::Duration::from_secs(30))
-        .connect_timeout(std::time::Duration::from_secs(10))
-        .gzip(true)
-        .brotli(true)
-        .deflate(true)
-        .build()
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+// Page returned when upstream answers 401: asks the parent window to prompt for credentials.
+// The domain ends up both in a script and in markup, so it is escaped for each context.
+fn auth_required_response(domain: &str) -> Response {
+    let auth_html = format!(
+        r#"<!DOCTYPE html>
+<html>
+<head><meta charset="UTF-8"></head>
+<body>
+<script>
+window.parent.postMessage({}, '*');
+</script>
+<p style="font-family: system-ui; text-align: center; padding: 2rem;">
+Authentication required for {}
+</p>
+</body>
+</html>"#,
+        js_value_literal(&ScriptMessage::ProxyAuthRequired { domain: domain.to_string() }, "{}"),
+        escape_html(domain)
+    );
+    with_cache_class(Response::builder(), CacheClass::NoStore)
+        .status(StatusCode::OK)
+        .header(header::CONTENT_TYPE, "text/html; charset=utf-8")
+        .body(Body::from(auth_html))
+        .unwrap()
+}
+
+/// Redirects followed when fetching a page
+const MAX_PAGE_REDIRECTS: usize = 10;
 
-    let mut client_req_builder = client.request(parts.method, target_url.clone());
+/// Redirect error raised when an origin bounces a page between http and https
+#[derive(Debug)]
+struct SchemeRedirectLoop;
 
-    // Add HTTP Basic Auth if credentials are available
-    if let Some((username, password)) = auth_credentials {
-        println!("Adding HTTP Basic Auth for: {}", domain);
-        client_req_builder = client_req_builder.basic_auth(username, Some(password));
+impl std::fmt::Display for SchemeRedirectLoop {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("redirect loop between http and https")
     }
+}
+
+impl std::error::Error for SchemeRedirectLoop {}
 
-    // For images and other resources, use the base_url (article URL) as Referer
-    // This helps bypass hotlinking protection on CDNs
-    let referer_url = {
-        let base_url_guard = state.base_url.lock().unwrap();
-        base_url_guard.to_string()
+// A redirect to `next` bounces between schemes when `next` is the last URL with the other
+// scheme (same host, path and query) and was already requested in the chain
+fn is_scheme_redirect_loop(next: &Url, previous: &[Url]) -> bool {
+    let Some(last) = previous.last() else {
+        return false;
     };
-    println!("Proxy resource handler - Referer: {} -> Target: {}", referer_url, target_url);
+    next.scheme() != last.scheme()
+        && next.host_str() == last.host_str()
+        && next.path() == last.path()
+        && next.query() == last.query()
+        && previous.contains(next)
+}
 
-    let client_req = client_req_builder
-        .header(
-            header::USER_AGENT,
-            "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/120.0.0.0 Safari/537.36",
-        )
-        .header(header::ACCEPT, "*/*")
-        .header(header::ACCEPT_LANGUAGE, "en-US,en;q=0.9")
-        .header(header::CONNECTION, "keep-alive")
-        .header(header::REFERER, referer_url)
-        .header(header::HOST, target_url.host_str().unwrap_or("localhost"))
-        .body(body_bytes)
-        .build()
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-
-    let response = client
-        .execute(client_req)
-        .await
-        .map_err(|e| {
-            eprintln!("Proxy resource handler: Request failed for '{}': {}", target_url, e);
-            StatusCode::BAD_GATEWAY
-        })?;
+fn is_scheme_redirect_loop_error(error: &reqwest::Error) -> bool {
+    let mut source = std::error::Error::source(error);
+    while let Some(e) = source {
+        if e.is::<SchemeRedirectLoop>() {
+            return true;
+        }
+        source = e.source();
+    }
+    false
+}
 
-    println!("Proxy resource handler - response status: {} for URL: {} (content-length: {:?})",
-        response.status(),
-        target_url,
-        response.headers().get(header::CONTENT_LENGTH));
-    
-    // Check for 401 Unauthorized
-    if response.status() == StatusCode::UNAUTHORIZED {
-        println!("401 Unauthorized in resource handler - auth required for: {}", domain);
-        // Return HTML page with script that requests auth via postMessage
-        let domain_escaped = domain.replace('\'', "\\'");
-        let auth_html = format!(
-            r#"<!DOCTYPE html>
+// Redirect policy of the page handler. `pin_https` stops at redirects to http (the response
+// is then the redirect itself) instead of following them.
+fn page_redirect_policy(pin_https: bool, state: &ProxyState) -> reqwest::redirect::Policy {
+    let auth_credentials = state.auth_credentials.clone();
+    reqwest::redirect::Policy::custom(move |attempt| {
+        // A custom header credential isn't taken off its origin: `credentials::execute` follows
+        // that hop without it
+        if (pin_https && attempt.url().scheme() == "http") || credentials::stops_custom_header(&attempt, &auth_credentials) {
+            attempt.stop()
+        } else if is_scheme_redirect_loop(attempt.url(), attempt.previous()) {
+            attempt.error(SchemeRedirectLoop)
+        } else if attempt.previous().len() > MAX_PAGE_REDIRECTS {
+            attempt.error("too many redirects")
+        } else {
+            attempt.follow()
+        }
+    })
+}
+
+// Page returned when the origin keeps redirecting between http and https, instead of a
+// blank 502
+fn scheme_redirect_loop_response(url: &Url) -> Response {
+    let html = format!(
+        r#"<!DOCTYPE html>
 <html>
 <head><meta charset="UTF-8"></head>
 <body>
-<script>
-window.parent.postMessage({{
-  type: 'PROXY_AUTH_REQUIRED',
-  domain: '{}'
-}}, '*');
-</script>
 <p style="font-family: system-ui; text-align: center; padding: 2rem;">
-Authentication required for {}
+{} keeps redirecting between http and https, even when requested over https.
+The site's redirect rules are misconfigured; open it in a browser or try again later.
 </p>
 </body>
 </html>"#,
-            domain_escaped, domain
-        );
-        return Ok(Response::builder()
-            .status(StatusCode::OK)
-            .header(header::CONTENT_TYPE, "text/html; charset=utf-8")
-            .body(Body::from(auth_html))
-            .unwrap());
+        escape_html(url.host_str().unwrap_or_default())
+    );
+    with_cache_class(Response::builder(), CacheClass::NoStore)
+        .status(StatusCode::LOOP_DETECTED)
+        .header(header::CONTENT_TYPE, "text/html; charset=utf-8")
+        .header(header::ACCESS_CONTROL_ALLOW_ORIGIN, "*")
+        .body(Body::from(html))
+        .unwrap()
+}
+
+// Page returned in offline mode instead of waiting for the origin to time out
+fn offline_page_response(url: &Url) -> Response {
+    let html = format!(
+        r#"<!DOCTYPE html>
+<html>
+<head><meta charset="UTF-8"></head>
+<body>
+<p style="font-family: system-ui; text-align: center; padding: 2rem;">
+You are offline. {} will load once the connection is back.
+</p>
+</body>
+</html>"#,
+        escape_html(url.host_str().unwrap_or_default())
+    );
+    with_cache_class(Response::builder(), CacheClass::NoStore)
+        .status(StatusCode::SERVICE_UNAVAILABLE)
+        .header(header::CONTENT_TYPE, "text/html; charset=utf-8")
+        .header(header::ACCESS_CONTROL_ALLOW_ORIGIN, "*")
+        .body(Body::from(html))
+        .unwrap()
+}
+
+/// Extensions of resources answered with the image placeholder in offline mode
+const IMAGE_EXTENSIONS: &[&str] = &[".png", ".jpg", ".jpeg", ".gif", ".webp", ".avif", ".svg", ".ico"];
+
+/// Transparent 1×1 GIF served for images in offline mode
+const OFFLINE_IMAGE_PLACEHOLDER: &[u8] = b"GIF89a\x01\x00\x01\x00\x80\x00\x00\x00\x00\x00\x00\x00\x00!\xf9\x04\x01\x00\x00\x00\x00,\x00\x00\x00\x00\x01\x00\x01\x00\x00\x02\x02D\x01\x00;";
+
+// Resource returned in offline mode when neither the webview nor the resource cache has it:
+// images get a placeholder so the layout holds, anything else a 503
+fn offline_resource_response(url: &Url, accept: Option<&HeaderValue>) -> Response {
+    let wants_image = accept.and_then(|accept| accept.to_str().ok()).is_some_and(|accept| accept.starts_with("image/"))
+        || IMAGE_EXTENSIONS.iter().any(|extension| url.path().to_ascii_lowercase().ends_with(extension));
+    let builder = with_cache_class(Response::builder(), CacheClass::NoStore).header(header::ACCESS_CONTROL_ALLOW_ORIGIN, "*");
+    if wants_image {
+        builder.status(StatusCode::OK).header(header::CONTENT_TYPE, "image/gif").body(Body::from(OFFLINE_IMAGE_PLACEHOLDER)).unwrap()
+    } else {
+        builder.status(StatusCode::SERVICE_UNAVAILABLE).body(Body::from(connectivity::OFFLINE)).unwrap()
     }
+}
 
-    let content_type = response
-        .headers()
-        .get(header::CONTENT_TYPE)
-        .and_then(|v| v.to_str().ok())
-        .unwrap_or("")
-        .to_string();
+// Only forward header values made of visible ASCII, spaces and tabs. Upstream servers can
+// send obs-text or control bytes; dropping the header is safer than emitting it verbatim.
+fn is_forwardable_header_value(value: &HeaderValue) -> bool {
+    value.as_bytes().iter().all(|&b| b == b'\t' || (0x20..0x7f).contains(&b))
+}
 
-    let mut builder = Response::builder().status(response.status());
-    
-    // Add CORS headers to allow fetch from the frontend
-    builder = builder
+// Copy upstream response headers, excluding the ones that would break framing or embedding
+fn copy_upstream_headers(mut builder: axum::http::response::Builder, headers: &HeaderMap) -> axum::http::response::Builder {
+    for (key, value) in headers {
+        if key == header::CONTENT_LENGTH
+            || key == header::CONTENT_SECURITY_POLICY
+            || key == "x-frame-options"
+            || key == "transfer-encoding" // Let Axum handle this
+        {
+            continue;
+        }
+        if !is_forwardable_header_value(value) {
+            println!("Dropping upstream header '{}' with non-ASCII or control bytes", key);
+            continue;
+        }
+        builder = builder.header(key, value);
+    }
+    builder
+}
+
+// Response of the resource handler with the upstream headers and the CORS headers letting
+// the frontend fetch it
+fn resource_response_builder(status: StatusCode, headers: &HeaderMap) -> axum::http::response::Builder {
+    let builder = Response::builder()
+        .status(status)
         .header(header::ACCESS_CONTROL_ALLOW_ORIGIN, "*")
         .header(header::ACCESS_CONTROL_ALLOW_METHODS, "GET, POST, OPTIONS")
         .header(header::ACCESS_CONTROL_ALLOW_HEADERS, "Content-Type, Authorization");
-    
-    // Copy headers but exclude problematic ones
-    for (key, value) in response.headers() {
-        if key != header::CONTENT_LENGTH 
-            && key != header::CONTENT_SECURITY_POLICY
-            && key != "x-frame-options"
-            && key != "transfer-encoding" // Let Axum handle this
-        {
-            builder = builder.header(key, value);
-        }
+    copy_upstream_headers(builder, headers)
+}
+
+// A resource read whole (fresh or from the resource cache): stylesheets are rewritten like
+// streamed ones, anything else is served as is
+fn buffered_resource_response(headers: &HeaderMap, content_type: &str, body: axum::body::Bytes, target_url: &Url, state: &ProxyState) -> Result<Response, StatusCode> {
+    let builder = resource_response_builder(StatusCode::OK, headers);
+    if content_type.contains("text/css") {
+        let css = decode_text(&body, header_charset(headers).as_deref());
+        let css = rewrite_css_urls(&css, target_url, &state.local_base());
+        return with_cache_class(builder, CacheClass::Rewritten).body(Body::from(css)).map_err(|_| StatusCode::BAD_GATEWAY);
     }
+    with_cache_class(builder, CacheClass::Passthrough).body(Body::from(body)).map_err(|_| StatusCode::BAD_GATEWAY)
+}
 
-    // Get proxy base for building resource URLs
-    let proxy_base = {
-        let relative_guard = state.use_relative_paths.lock().unwrap();
-        if *relative_guard {
-            String::new()
-        } else {
-            let port_guard = state.port.lock().unwrap();
-            format!("http://localhost:{}", port_guard.unwrap_or(3000))
+// Rewritten pages are sent as UTF-8 whatever the charset they came in: the upstream
+// `Content-Type` copied over would make the browser decode them with the original one
+fn with_utf8_content_type(mut builder: axum::http::response::Builder) -> axum::http::response::Builder {
+    if let Some(headers) = builder.headers_mut() {
+        headers.insert(header::CONTENT_TYPE, HeaderValue::from_static("text/html; charset=utf-8"));
+    }
+    builder
+}
+
+// Stream an upstream body through, aborting once more than `limit` decompressed bytes
+// have been forwarded (protects passthrough resources against decompression bombs)
+fn limited_body_stream(
+    response: reqwest::Response,
+    limit: usize,
+) -> impl futures_util::Stream<Item = Result<axum::body::Bytes, std::io::Error>> {
+    let mut forwarded = 0usize;
+    response.bytes_stream().map(move |chunk| {
+        let chunk = chunk.map_err(std::io::Error::other)?;
+        forwarded += chunk.len();
+        if forwarded > limit {
+            eprintln!("Proxy: upstream body exceeded {} bytes, aborting stream", limit);
+            return Err(std::io::Error::other(format!("{}:{}", BODY_TOO_LARGE, limit)));
         }
-    };
+        Ok(chunk)
+    })
+}
 
-    if content_type.contains("text/html") {
-        let text = response.text().await.unwrap();
-        let mut output = Vec::new();
+// Whether an upstream response announces a body too large to buffer for rewriting: it's
+// streamed through whatever its type (a huge "HTML" page is most likely a mislabeled file)
+fn too_large_to_rewrite(headers: &HeaderMap, state: &ProxyState) -> bool {
+    let length = headers.get(header::CONTENT_LENGTH).and_then(|v| v.to_str().ok()).and_then(|v| v.trim().parse::<usize>().ok());
+    length.is_some_and(|length| length > state.max_rewrite_size())
+}
 
-        let final_script = LISTENER_SCRIPT.to_string();
+// Request body forwarded upstream (form posts, uploads), at most `max_body_size` bytes
+async fn read_request_body(headers: &HeaderMap, body: Body, state: &ProxyState) -> Result<axum::body::Bytes, StatusCode> {
+    let limit = state.max_body_size();
+    let declared = headers.get(header::CONTENT_LENGTH).and_then(|v| v.to_str().ok()).and_then(|v| v.trim().parse::<usize>().ok());
+    if declared.is_some_and(|length| length > limit) {
+        eprintln!("Proxy: request body of {:?} bytes exceeds {} bytes", declared, limit);
+        return Err(StatusCode::PAYLOAD_TOO_LARGE);
+    }
+    to_bytes(body, limit).await.map_err(|e| {
+        eprintln!("Proxy: failed to read request body (limit {} bytes): {}", limit, e);
+        StatusCode::PAYLOAD_TOO_LARGE
+    })
+}
 
-        let mut rewriter = HtmlRewriter::new(
-            Settings {
-                element_content_handlers: vec![
-                    // Rewrite all src attributes (images, scripts, etc.)
-                    element!("*[src]", |el| {
-                        if let Some(src) = el.get_attribute("src") {
+// Query parameters of CDN URL signatures (lowercase). Signed URLs are sent with their query
+// exactly as written, and retried without Referer when rejected.
+const SIGNATURE_PARAMS: &[&str] = &[
+    "x-amz-signature",
+    "x-goog-signature",
+    "signature",
+    "sig",
+    "token",
+    "expires",
+    "key-pair-id",
+    "policy",
+    "hdnts",
+    "hmac",
+];
+
+// Whether `url` carries a CDN signature in its query
+fn is_signed_url(url: &Url) -> bool {
+    url.query_pairs().any(|(key, _)| SIGNATURE_PARAMS.contains(&key.to_ascii_lowercase().as_str()))
+}
+
+/// How a signed resource URL was requested upstream
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RefererStrategy {
+    /// With the article (or `&referer=`) as Referer
+    Referer,
+    /// Without Referer, as asked with an empty `&referer=`
+    NoReferer,
+    /// Rejected with 403 with a Referer, then retried without one
+    RetryWithoutReferer,
+}
+
+/// Referer the proxy sends upstream, set per proxy session by `set_proxy_url`. Requests to
+/// the article's own origin get the full article URL under every policy but `none`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ReferrerPolicy {
+    /// The article URL everywhere, which gets past most CDN hotlink protection
+    #[default]
+    Full,
+    /// Only the article's origin to other origins
+    OriginOnly,
+    /// No Referer to other origins
+    SameOriginOnly,
+    /// No Referer at all
+    #[serde(rename = "none")]
+    NoReferrer,
+}
+
+impl ReferrerPolicy {
+    /// Referer for a request to `target` made on behalf of `article`
+    pub fn referer_for(self, article: &Url, target: &Url) -> Option<String> {
+        let mut full = article.clone();
+        full.set_fragment(None);
+        let same_origin = article.origin() == target.origin();
+        match self {
+            ReferrerPolicy::NoReferrer => None,
+            _ if same_origin => Some(full.to_string()),
+            ReferrerPolicy::Full => Some(full.to_string()),
+            ReferrerPolicy::OriginOnly => Some(format!("{}/", origin_of(article))),
+            ReferrerPolicy::SameOriginOnly => None,
+        }
+    }
+}
+
+/// Counters of the proxy, updated on the request path (hence atomics)
+#[derive(Debug, Default)]
+pub struct ProxyStats {
+    signed_with_referer: AtomicU64,
+    signed_without_referer: AtomicU64,
+    signed_retried_without_referer: AtomicU64,
+    signed_failed: AtomicU64,
+    cache_revalidations: AtomicU64,
+}
+
+/// Snapshot of `ProxyStats`
+#[derive(Debug, Clone, Serialize)]
+pub struct ProxyStatsReport {
+    /// Signed resource URLs served with a Referer
+    pub signed_with_referer: u64,
+    /// Signed resource URLs served when requested without Referer
+    pub signed_without_referer: u64,
+    /// Signed resource URLs rejected with a Referer, then served once retried without
+    pub signed_retried_without_referer: u64,
+    /// Signed resource URLs that weren't served with any strategy
+    pub signed_failed: u64,
+    /// Resources served from the resource cache after upstream answered `304`
+    pub cache_revalidations: u64,
+}
+
+impl ProxyStats {
+    /// Counts a signed URL under the strategy that served it, or as failed
+    pub fn record_signed_url(&self, strategy: RefererStrategy, success: bool) {
+        let counter = match (success, strategy) {
+            (false, _) => &self.signed_failed,
+            (true, RefererStrategy::Referer) => &self.signed_with_referer,
+            (true, RefererStrategy::NoReferer) => &self.signed_without_referer,
+            (true, RefererStrategy::RetryWithoutReferer) => &self.signed_retried_without_referer,
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Counts a resource served from the resource cache once revalidated
+    pub fn record_cache_revalidation(&self) {
+        self.cache_revalidations.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn report(&self) -> ProxyStatsReport {
+        ProxyStatsReport {
+            signed_with_referer: self.signed_with_referer.load(Ordering::Relaxed),
+            signed_without_referer: self.signed_without_referer.load(Ordering::Relaxed),
+            signed_retried_without_referer: self.signed_retried_without_referer.load(Ordering::Relaxed),
+            signed_failed: self.signed_failed.load(Ordering::Relaxed),
+            cache_revalidations: self.cache_revalidations.load(Ordering::Relaxed),
+        }
+    }
+}
+
+pub fn logic_get_proxy_stats(state: &ProxyState) -> ProxyStatsReport {
+    state.proxy_stats.report()
+}
+
+// Whether the listener script is injected, from the optional `inject` query parameter
+fn injection_enabled(value: Option<&str>) -> bool {
+    !matches!(value, Some("0") | Some("false"))
+}
+
+// Whether `<iframe srcdoc>` documents get the listener script too, from the optional
+// `inject_srcdoc` query parameter (off by default: their messages go to the proxied page)
+fn srcdoc_injection_enabled(value: Option<&str>) -> bool {
+    matches!(value, Some("1") | Some("true"))
+}
+
+// Status returned to the webview for a fault injected by chaos mode
+fn chaos_fault_status(fault: ChaosFault) -> StatusCode {
+    match fault {
+        ChaosFault::ConnectionReset => StatusCode::BAD_GATEWAY,
+        ChaosFault::Status(status) => StatusCode::from_u16(status).unwrap_or(StatusCode::SERVICE_UNAVAILABLE),
+    }
+}
+
+// Serve a built-in chaos:// fixture page as-is
+fn fixture_response(fixture: Result<String, String>) -> Result<Response, StatusCode> {
+    let html = fixture.map_err(|e| {
+        eprintln!("Proxy: chaos fixture unavailable: {}", e);
+        if e.starts_with(BODY_TOO_LARGE) {
+            StatusCode::PAYLOAD_TOO_LARGE
+        } else {
+            StatusCode::NOT_FOUND
+        }
+    })?;
+    with_cache_class(Response::builder(), CacheClass::NoStore)
+        .status(StatusCode::OK)
+        .header(header::CONTENT_TYPE, "text/html; charset=utf-8")
+        .header(header::ACCESS_CONTROL_ALLOW_ORIGIN, "*")
+        .body(Body::from(html))
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)
+}
+
+/// Sets `attribute` of `el` to `value`, given unescaped (as read through `unescape_html`).
+/// `set_attribute` only escapes quotes, so ampersands are escaped here: `&lt;` in a URL
+/// stays text instead of turning into `<` once the browser decodes the attribute.
+fn set_attribute_escaped(el: &mut Element, attribute: &str, value: &str) {
+    el.set_attribute(attribute, &value.replace('&', "&amp;")).unwrap();
+}
+
+/// Applies the page's mixed-content plan to a plain-http `attribute` value. Returns false when
+/// there is nothing to do (not a plain-http URL, or not an https page), leaving the value to the
+/// regular rewriting.
+fn rewrite_insecure_attribute(
+    el: &mut Element,
+    attribute: &str,
+    value: &str,
+    plan: Option<&MixedContentPlan>,
+    proxy_base: &str,
+) -> bool {
+    let Some(action) = plan.and_then(|plan| plan.action(value)) else {
+        return false;
+    };
+    match action {
+        InsecureAction::Upgrade(url) => set_attribute_escaped(el, attribute, &unescape_html(&url)),
+        InsecureAction::Proxy => {
+            let proxy_url = format!("{}/proxy?url={}", proxy_base, urlencoding::encode(&unescape_html(value.trim())));
+            set_attribute_escaped(el, attribute, &proxy_url);
+        }
+        InsecureAction::Block => el.remove_attribute(attribute),
+    }
+    true
+}
+
+// Handler for CORS preflight requests
+pub async fn cors_options_handler() -> Response {
+    Response::builder()
+        .status(StatusCode::NO_CONTENT)
+        .header(header::ACCESS_CONTROL_ALLOW_ORIGIN, "*")
+        .header(header::ACCESS_CONTROL_ALLOW_METHODS, "GET, POST, OPTIONS")
+        .header(header::ACCESS_CONTROL_ALLOW_HEADERS, "Content-Type, Authorization")
+        .header(header::ACCESS_CONTROL_MAX_AGE, "86400")
+        .body(Body::empty())
+        .unwrap()
+}
+
+/// Ports tried by `start_proxy_server` before giving up
+const PORT_ATTEMPTS: usize = 5;
+
+/// Binds a free local port, retrying with another one when binding fails (e.g. another
+/// process took the picked port meanwhile)
+async fn bind_free_port() -> Result<(u16, TcpListener), String> {
+    let mut last_error = "no unused port found".to_string();
+    for attempt in 1..=PORT_ATTEMPTS {
+        let Some(port) = portpicker::pick_unused_port() else {
+            continue;
+        };
+        match TcpListener::bind(format!("localhost:{}", port)).await {
+            Ok(listener) => return Ok((port, listener)),
+            Err(e) => {
+                println!("[proxy::start_proxy_server] Binding port {} failed (attempt {}/{}): {}", port, attempt, PORT_ATTEMPTS, e);
+                last_error = format!("port {}: {}", port, e);
+            }
+        }
+    }
+    Err(format!("Could not bind a local port after {} attempts ({})", PORT_ATTEMPTS, last_error))
+}
+
+/// Name of the proxy server's task in the supervisor
+pub const PROXY_TASK: &str = "proxy_server";
+
+/// Interval of the proxy server's heartbeats
+const PROXY_HEARTBEAT_INTERVAL: std::time::Duration = std::time::Duration::from_secs(30);
+
+/// Binds a local port and serves the proxy on it under the supervisor. A server that stops is
+/// restarted on the same port, which the frontend already uses.
+pub async fn start_proxy_server(state: ProxyState) -> Result<u16, String> {
+    let (port, listener) = bind_free_port().await?;
+    let supervisor = state.supervisor.clone();
+
+    let app = Router::new()
+        .route("/proxy", get(proxy_resource_handler).options(cors_options_handler))
+        .route("/transfer/:token", get(transfer_handler))
+        .route("/icon", get(icons::icon_handler))
+        .route("/*path", get(proxy_handler).options(cors_options_handler))
+        .with_state(state)
+        .layer(middleware::map_response(caching::no_store_by_default))
+        .layer(middleware::from_fn(log_requests))
+        .layer(TraceLayer::new_for_http());
+
+    let first_listener = std::sync::Mutex::new(Some(listener));
+    supervisor::supervise(&supervisor, PROXY_TASK, RestartPolicy::default(), move |heartbeat| {
+        let app = app.clone();
+        let listener = first_listener.lock().unwrap().take();
+        async move {
+            let listener = match listener {
+                Some(listener) => listener,
+                None => TcpListener::bind(format!("localhost:{}", port)).await.map_err(|e| format!("Rebinding port {}: {}", port, e))?,
+            };
+            heartbeat.while_running(axum::serve(listener, app), PROXY_HEARTBEAT_INTERVAL).await.map_err(|e| format!("Proxy server on port {} stopped: {}", port, e))
+        }
+    });
+
+    Ok(port)
+}
+
+// Handler for proxying external resources via /proxy?url=...
+pub async fn proxy_resource_handler(
+    Query(params): Query<HashMap<String, String>>,
+    State(state): State<ProxyState>,
+    req: Request<Body>,
+) -> Result<Response, StatusCode> {
+    let target_url_str = params.get("url").ok_or_else(|| {
+        eprintln!("Proxy resource handler: No 'url' parameter provided");
+        StatusCode::BAD_REQUEST
+    })?;
+    
+    println!("Proxy resource handler - RAW URL parameter: '{}'", target_url_str);
+    
+    // The query extractor already decoded the parameter once. Only a parameter that was
+    // encoded twice is decoded again: decoding a plain URL would turn the escapes of its
+    // own query (`%2B`, `%2F`, `%3D` in CDN signatures) into different characters.
+    let decoded_url = if target_url_str.contains("://") {
+        std::borrow::Cow::Borrowed(target_url_str.as_str())
+    } else {
+        urlencoding::decode(target_url_str).map_err(|e| {
+            eprintln!("Proxy resource handler: Failed to decode URL '{}': {}", target_url_str, e);
+            StatusCode::BAD_REQUEST
+        })?
+    };
+    
+    println!("Proxy resource handler - DECODED URL: '{}'", decoded_url);
+    println!("Proxy resource handler - all params: {:?}", params);
+    
+    let target_url = Url::parse(&decoded_url).map_err(|e| {
+        eprintln!("Proxy resource handler: Failed to parse decoded URL '{}': {}", decoded_url, e);
+        StatusCode::BAD_REQUEST
+    })?;
+
+    if let Some(fixture) = chaos::fixture(&target_url, &state) {
+        return fixture_response(fixture);
+    }
+
+    // Offline: the cached copy when there is one, else a placeholder
+    if connectivity::is_offline(&state) {
+        if let Some((headers, body)) = resource_cache::cached(&target_url, &state) {
+            println!("Proxy resource handler - offline, serving the cached copy of {} ({} bytes)", target_url, body.len());
+            let content_type = headers.get(header::CONTENT_TYPE).and_then(|v| v.to_str().ok()).unwrap_or("").to_string();
+            return buffered_resource_response(&headers, &content_type, body, &target_url, &state);
+        }
+        return Ok(offline_resource_response(&target_url, req.headers().get(header::ACCEPT)));
+    }
+
+    if mixed_content::is_blocked_subresource(&target_url, &state) {
+        println!("Proxy resource handler: refusing plain-http subresource of an https page (strict mode): {}", target_url);
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    // Extract domain for auth lookup
+    let domain = origin_of(&target_url);
+    
+    // Check for auth credentials for this domain
+    let auth_credentials = state.auth_credentials.get(&domain).map(|entry| entry.value().clone());
+
+    let (parts, body) = req.into_parts();
+    let body_bytes = read_request_body(&parts.headers, body, &state).await?;
+
+    // Pooled with article fetches: connections warmed by `preconnect` are reused
+    let client = preconnect::pooled_client(&target_url, &state).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    preconnect::record_subresource(&state.base_url.load(), &target_url, &state);
+
+    // Add the domain's credentials if any
+    if let Some(auth) = &auth_credentials {
+        println!("Adding {} for: {}", auth.kind(), domain);
+    }
+
+    // For images and other resources, the session's referrer policy decides whether the
+    // article URL (which helps bypass hotlinking protection on CDNs), its origin or nothing
+    // is sent as Referer.
+    // The frontend can override it with `&referer=` (e.g. from an image probe's
+    // `retry_referer`); an empty value sends no Referer at all
+    // `&inject=0` serves the page without the listener script (A/B debugging)
+    let inject = injection_enabled(params.get("inject").map(String::as_str));
+    let inject_srcdoc = inject && srcdoc_injection_enabled(params.get("inject_srcdoc").map(String::as_str));
+
+    let referer_url = match params.get("referer") {
+        Some(referer) => Some(referer.clone()).filter(|referer| !referer.is_empty()),
+        None => state.referrer_policy.load().referer_for(&state.base_url.load(), &target_url),
+    };
+    println!("Proxy resource handler - Referer: {} -> Target: {}", referer_url.as_deref().unwrap_or("(none)"), target_url);
+    let referer = referer_url.as_deref();
+
+    // GET responses are cached with their validators: a cached copy is revalidated upstream
+    // and served again on `304`
+    let cacheable = parts.method == Method::GET;
+    let validators = if cacheable { resource_cache::conditional_headers(&target_url, &state) } else { None };
+
+    let build_request = |referer: Option<&str>, validators: Option<&HeaderMap>| {
+        let mut client_req_builder = client.request(parts.method.clone(), target_url.clone()).timeout(std::time::Duration::from_secs(30));
+        if let Some(auth) = &auth_credentials {
+            client_req_builder = auth.apply(client_req_builder);
+        }
+        if let Some(referer) = referer {
+            client_req_builder = client_req_builder.header(header::REFERER, referer);
+        }
+        if let Some(validators) = validators {
+            client_req_builder = client_req_builder.headers(validators.clone());
+        }
+        client_req_builder
+            .header(
+                header::USER_AGENT,
+                state.next_user_agent(),
+            )
+            .header(header::ACCEPT, "*/*")
+            .header(header::ACCEPT_LANGUAGE, accept_language_for(&target_url, &state, DEFAULT_PROXY_ACCEPT_LANGUAGE))
+            .header(header::CONNECTION, "keep-alive")
+            .header(header::HOST, host_header_of(&target_url))
+            .body(body_bytes.clone())
+            .build()
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)
+    };
+    let request_failed = |e: reqwest::Error| {
+        eprintln!("Proxy resource handler: Request failed for '{}': {}", target_url, e);
+        StatusCode::BAD_GATEWAY
+    };
+
+    chaos::inject_request_faults(&target_url, &state).await.map_err(chaos_fault_status)?;
+
+    let mut response = credentials::execute(&client, build_request(referer, validators.as_ref())?, &state).await.map_err(request_failed)?;
+
+    // Signed CDN URLs that reject the spoofed Referer sometimes only accept no Referer at all
+    if is_signed_url(&target_url) {
+        let mut strategy = if referer.is_some() { RefererStrategy::Referer } else { RefererStrategy::NoReferer };
+        if response.status() == StatusCode::FORBIDDEN && referer.is_some() {
+            println!("Proxy resource handler - signed URL rejected with Referer, retrying without: {}", target_url);
+            response = credentials::execute(&client, build_request(None, validators.as_ref())?, &state).await.map_err(request_failed)?;
+            strategy = RefererStrategy::RetryWithoutReferer;
+        }
+        state.proxy_stats.record_signed_url(strategy, response.status().is_success());
+    }
+
+    println!("Proxy resource handler - response status: {} for URL: {} (content-length: {:?})",
+        response.status(),
+        target_url,
+        response.headers().get(header::CONTENT_LENGTH));
+    
+    // Check for 401 Unauthorized
+    if response.status() == StatusCode::UNAUTHORIZED {
+        println!("401 Unauthorized in resource handler - auth required for: {}", domain);
+        return Ok(auth_required_response(&domain));
+    }
+
+    if response.status() == StatusCode::NOT_MODIFIED && validators.is_some() {
+        match resource_cache::revalidate(&target_url, response.headers(), &state) {
+            Some((headers, body)) => {
+                println!("Proxy resource handler - {} not modified, serving the cached copy ({} bytes)", target_url, body.len());
+                state.proxy_stats.record_cache_revalidation();
+                let content_type = headers.get(header::CONTENT_TYPE).and_then(|v| v.to_str().ok()).unwrap_or("").to_string();
+                return buffered_resource_response(&headers, &content_type, body, &target_url, &state);
+            }
+            // Evicted since the conditional request was built
+            None => response = client.execute(build_request(referer, None)?).await.map_err(request_failed)?,
+        }
+    }
+    if cacheable && resource_cache::is_no_store(response.headers()) {
+        resource_cache::forget(&target_url, &state);
+    }
+
+    let max_body_size = state.max_body_size();
+
+    let content_type = response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or("")
+        .to_string();
+    let content_type = chaos::mangle_content_type(&target_url, content_type, &state);
+    let rewrite = !too_large_to_rewrite(response.headers(), &state);
+    if !rewrite {
+        println!("Proxy: {} is larger than {} bytes, streaming it as is", target_url, state.max_rewrite_size());
+    }
+
+    // Pages are rewritten with the session's script and settings: only other resources are cached
+    if cacheable && rewrite && !content_type.contains("text/html") && resource_cache::is_storable(response.status(), response.headers()) {
+        let headers = response.headers().clone();
+        match resource_cache::buffer_body(response, max_body_size).await {
+            Ok(BufferedBody::Whole(body)) => {
+                resource_cache::store(&target_url, headers.clone(), body.clone(), &state);
+                return buffered_resource_response(&headers, &content_type, body, &target_url, &state);
+            }
+            Ok(BufferedBody::TooLarge(head, rest)) => {
+                println!("Proxy: {} is larger than {} bytes, not cached", target_url, resource_cache::MAX_CACHED_RESOURCE_SIZE);
+                let builder = resource_response_builder(StatusCode::OK, &headers);
+                let remaining = max_body_size.saturating_sub(head.len());
+                let head = futures_util::stream::once(async move { Ok(axum::body::Bytes::from(head)) });
+                let body = Body::from_stream(head.chain(limited_body_stream(rest, remaining)));
+                return with_cache_class(builder, CacheClass::Passthrough).body(body).map_err(|_| StatusCode::BAD_GATEWAY);
+            }
+            Err(e) => {
+                eprintln!("Failed to read upstream body for '{}': {}", target_url, e);
+                return Err(StatusCode::BAD_GATEWAY);
+            }
+        }
+    }
+
+    let builder = resource_response_builder(response.status(), response.headers());
+
+    // Get proxy base for building resource URLs
+    let proxy_base = state.local_base();
+
+    if rewrite && content_type.contains("text/html") {
+        let text = read_html_limited(response, max_body_size).await.map_err(|e| {
+            eprintln!("Failed to read upstream HTML body for '{}': {}", target_url, e);
+            if e.starts_with(BODY_TOO_LARGE) {
+                StatusCode::PAYLOAD_TOO_LARGE
+            } else {
+                StatusCode::BAD_GATEWAY
+            }
+        })?;
+        let builder = with_cache_class(with_utf8_content_type(builder), CacheClass::Rewritten);
+        let text = chaos::mangle_body(&target_url, text, &state);
+        // Expose images hidden in <noscript> to the URL rewriting below
+        let text = unwrap_noscript_images(&text);
+        let text = element_filters::apply_element_filters(&text, &target_url, &state);
+        let mut output = Vec::new();
+
+        // Plain-http subresources of https pages: upgraded when their host serves https,
+        // otherwise proxied (or dropped in strict mode) and reported
+        let mixed_content = mixed_content::plan_for(&target_url, &text, &state).await;
+
+        let snapshot = state.snapshot_config.load_full();
+        let final_script = listener_script(&snapshot, state.article_actions.load().action_bar);
+        let style_buffer = RefCell::new(String::new());
+        // Malformed pages can have several <body> tags: the script goes in the first one only
+        let injected = std::cell::Cell::new(false);
+
+        let mut rewriter = HtmlRewriter::new(
+            Settings {
+                element_content_handlers: vec![
+                    // Rewrite all src attributes (images, scripts, etc.)
+                    element!("*[src]", |el| {
+                        if let Some(src) = el.get_attribute("src") {
+                            if rewrite_insecure_attribute(el, "src", &src, mixed_content.as_ref(), &proxy_base) {
+                                return Ok(());
+                            }
                             if !src.starts_with("data:") && !src.starts_with("blob:") && !src.starts_with("http://localhost:") && !src.starts_with("https://") && !src.starts_with("http://") {
                                 // Build absolute URL relative to current target
-                                let absolute_url = match target_url.join(&src) {
+                                let absolute_url = match target_url.join(&unescape_html(&src)) {
                                     Ok(url) => url.to_string(),
                                     Err(_) => return Ok(())
                                 };
                                 let proxy_url = format!("{}/proxy?url={}", proxy_base, urlencoding::encode(&absolute_url));
-                                el.set_attribute("src", &proxy_url).unwrap();
+                                set_attribute_escaped(el, "src", &proxy_url);
                             }
                         }
                         Ok(())
@@ -845,10 +1803,15 @@ Authentication required for {}
                     // Rewrite href attributes for stylesheets and other resources (not navigation links)
                     element!("link[href], area[href]", |el| {
                         if let Some(href) = el.get_attribute("href") {
+                            let is_subresource = el.tag_name() == "link"
+                                && mixed_content::is_subresource_link(&el.get_attribute("rel").unwrap_or_default());
+                            if is_subresource && rewrite_insecure_attribute(el, "href", &href, mixed_content.as_ref(), &proxy_base) {
+                                return Ok(());
+                            }
                             if !href.starts_with("data:") && !href.starts_with("blob:") && !href.starts_with("http://localhost:") && !href.starts_with("#") && !href.starts_with("javascript:") && !href.starts_with("mailto:") && !href.starts_with("https://") && !href.starts_with("http://") {
-                                let absolute_url = match target_url.join(&href) { Ok(url) => url.to_string(), Err(_) => return Ok(()) };
+                                let absolute_url = match target_url.join(&unescape_html(&href)) { Ok(url) => url.to_string(), Err(_) => return Ok(()) };
                                 let proxy_url = format!("{}/proxy?url={}", proxy_base, urlencoding::encode(&absolute_url));
-                                el.set_attribute("href", &proxy_url).unwrap();
+                                set_attribute_escaped(el, "href", &proxy_url);
                             }
                         }
                         Ok(())
@@ -857,20 +1820,31 @@ Authentication required for {}
                     element!("a[href]", |el| {
                         if let Some(href) = el.get_attribute("href") {
                             if !href.starts_with("data:") && !href.starts_with("blob:") && !href.starts_with("http://localhost:") && !href.starts_with("#") && !href.starts_with("javascript:") && !href.starts_with("mailto:") && !href.starts_with("https://") && !href.starts_with("http://") {
-                                let absolute_url = match target_url.join(&href) { Ok(url) => url.to_string(), Err(_) => return Ok(()) };
+                                let absolute_url = match target_url.join(&unescape_html(&href)) { Ok(url) => url.to_string(), Err(_) => return Ok(()) };
                                 let proxy_url = format!("{}/proxy?url={}", proxy_base, urlencoding::encode(&absolute_url));
-                                el.set_attribute("href", &proxy_url).unwrap();
+                                set_attribute_escaped(el, "href", &proxy_url);
                             }
                         }
                         Ok(())
                     }),
                     // Rewrite srcset attributes for responsive images
                     element!("*[srcset]", |el| {
-                        if let Some(srcset) = el.get_attribute("srcset") {
+                        if let Some(srcset) = el.get_attribute("srcset").map(|srcset| unescape_html(&srcset)) {
                             let mut new_srcset = String::new();
                             for src_descriptor in srcset.split(',') {
                                 let parts: Vec<&str> = src_descriptor.trim().split_whitespace().collect();
                                 if let Some(url) = parts.first() {
+                                    if let Some(action) = mixed_content.as_ref().and_then(|plan| plan.action(url)) {
+                                        let rewritten = match action {
+                                            InsecureAction::Upgrade(upgraded) => upgraded,
+                                            InsecureAction::Proxy => format!("{}/proxy?url={}", proxy_base, urlencoding::encode(url)),
+                                            InsecureAction::Block => continue,
+                                        };
+                                        new_srcset.push_str(&rewritten);
+                                        if parts.len() > 1 { new_srcset.push(' '); new_srcset.push_str(parts[1]); }
+                                        new_srcset.push_str(", ");
+                                        continue;
+                                    }
                                     if !url.starts_with("data:") && !url.starts_with("blob:") && !url.starts_with("http://localhost:") && !url.starts_with("https://") && !url.starts_with("http://") {
                                         if let Ok(absolute_url) = target_url.join(url) {
                                             let proxy_url = format!("{}/proxy?url={}", proxy_base, urlencoding::encode(absolute_url.as_str()));
@@ -885,13 +1859,30 @@ Authentication required for {}
                                 }
                             }
                             if new_srcset.ends_with(", ") { new_srcset.truncate(new_srcset.len() - 2); }
-                            el.set_attribute("srcset", &new_srcset).unwrap();
+                            set_attribute_escaped(el, "srcset", &new_srcset);
                         }
                         Ok(())
                     }),
+                    // Inline CSS resolves against the page; fetched stylesheets are rewritten
+                    // against their own URL when served below
+                    element!("*[style]", |el| {
+                        rewrite_style_attribute(el, &target_url, &proxy_base);
+                        Ok(())
+                    }),
+                    text!("style", |t| {
+                        rewrite_style_block(t, &style_buffer, &target_url, &proxy_base);
+                        Ok(())
+                    }),
+                    // Inline documents of srcdoc iframes inherit the proxy's URL as their base
+                    element!("iframe[srcdoc]", |el| {
+                        rewrite_srcdoc_attribute(el, &target_url, &proxy_base, inject_srcdoc.then_some(&*snapshot), 0);
+                        Ok(())
+                    }),
                     // Inject our script
                     element!("body", |el| {
-                        el.append(&final_script, lol_html::html_content::ContentType::Html);
+                        if inject && !injected.replace(true) {
+                            el.append(&final_script, lol_html::html_content::ContentType::Html);
+                        }
                         Ok(())
                     }),
                 ],
@@ -900,14 +1891,38 @@ Authentication required for {}
             |c: &[u8]| output.extend_from_slice(c),
         );
 
-        rewriter.write(text.as_bytes()).unwrap();
-        rewriter.end().unwrap();
+        rewriter
+            .write(text.as_bytes())
+            .and_then(|_| rewriter.end())
+            .map_err(|e| {
+                eprintln!("HTML rewriting failed for '{}': {}", target_url, e);
+                StatusCode::BAD_GATEWAY
+            })?;
+
+        if let Some(plan) = mixed_content {
+            mixed_content::record_report(plan.into_report(), &state);
+        }
+
+        return builder.body(Body::from(output)).map_err(|_| StatusCode::BAD_GATEWAY);
+    }
 
-        return Ok(builder.body(Body::from(output)).unwrap());
+    if rewrite && content_type.contains("text/css") {
+        let css = read_text_limited(response, max_body_size).await.map_err(|e| {
+            eprintln!("Failed to read upstream CSS body for '{}': {}", target_url, e);
+            if e.starts_with(BODY_TOO_LARGE) {
+                StatusCode::PAYLOAD_TOO_LARGE
+            } else {
+                StatusCode::BAD_GATEWAY
+            }
+        })?;
+        // The stylesheet is served from the proxy's URL, so its relative URLs are resolved
+        // against its upstream URL here
+        let css = rewrite_css_urls(&css, &target_url, &proxy_base);
+        return with_cache_class(builder, CacheClass::Rewritten).body(Body::from(css)).map_err(|_| StatusCode::BAD_GATEWAY);
     }
 
-    let body = Body::from_stream(response.bytes_stream());
-    Ok(builder.body(body).unwrap())
+    let body = Body::from_stream(limited_body_stream(response, max_body_size));
+    with_cache_class(builder, CacheClass::Passthrough).body(body).map_err(|_| StatusCode::BAD_GATEWAY)
 }
 
 pub async fn proxy_handler(
@@ -915,7 +1930,7 @@ pub async fn proxy_handler(
     State(state): State<ProxyState>,
     req: Request<Body>,
 ) -> Result<Response, StatusCode> {
-    let base_url = state.base_url.lock().unwrap().clone();
+    let base_url = state.base_url.load_full();
     
     // Check if this is a resource request (CSS, JS, images, etc.)
     let is_resource = path.ends_with(".css") || path.ends_with(".js") || path.ends_with(".png") || 
@@ -929,7 +1944,7 @@ pub async fn proxy_handler(
         // Build the full URL for the resource using domain root 
         // Note: Axum Path strips the leading '/' so we need to add it back for absolute paths
         // Most resources are absolute paths from domain root, not relative to current page
-        let resource_url = format!("{}://{}/{}", base_url.scheme(), base_url.host_str().unwrap_or("localhost"), path);
+        let resource_url = format!("{}/{}", origin_of(&base_url), path);
         println!("🔗 RESOURCE URL: {} -> {}", path, resource_url);
         
         // Create a new request with the url parameter for the resource handler
@@ -942,125 +1957,135 @@ pub async fn proxy_handler(
     
     let target_url = base_url.join(&path).map_err(|_| StatusCode::BAD_REQUEST)?;
 
+    // `?inject=0` serves the page without the listener script (A/B debugging)
+    let query: HashMap<String, String> = req
+        .uri()
+        .query()
+        .map(|query| url::form_urlencoded::parse(query.as_bytes()).into_owned().collect())
+        .unwrap_or_default();
+    let inject = injection_enabled(query.get("inject").map(String::as_str));
+    let inject_srcdoc = inject && srcdoc_injection_enabled(query.get("inject_srcdoc").map(String::as_str));
+
+    if let Some(fixture) = chaos::fixture(&target_url, &state) {
+        return fixture_response(fixture);
+    }
+
+    if connectivity::is_offline(&state) {
+        return Ok(offline_page_response(&target_url));
+    }
+
     // Get proxy base for building resource URLs
-    let proxy_base = {
-        let relative_guard = state.use_relative_paths.lock().unwrap();
-        if *relative_guard {
-            String::new()
-        } else {
-            let port_guard = state.port.lock().unwrap();
-            format!("http://localhost:{}", port_guard.unwrap_or(3000))
-        }
-    };
+    let proxy_base = state.local_base();
 
     // Extract domain for auth lookup
-    let domain = format!("{}://{}", 
-        target_url.scheme(), 
-        target_url.host_str().unwrap_or("localhost")
-    );
+    let domain = origin_of(&target_url);
     
     // Check for auth credentials for this domain
-    let auth_credentials = {
-        let creds = state.auth_credentials.lock().unwrap();
-        creds.get(&domain).cloned()
-    };
+    let auth_credentials = state.auth_credentials.get(&domain).map(|entry| entry.value().clone());
 
     let (parts, body) = req.into_parts();
-    let body_bytes = to_bytes(body, usize::MAX)
-        .await
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let body_bytes = read_request_body(&parts.headers, body, &state).await?;
 
-    let client = reqwest::Client::builder()
-        .cookie_store(true)
-        .cookie_provider(state.cookie_jar.clone())
-        .redirect(reqwest::redirect::Policy::limited(10))
-        .timeout(std::time::Duration::from_secs(30))
-        .connect_timeout(std::time::Duration::from_secs(10))
-        .gzip(true)
-        .brotli(true)
-        .deflate(true)
-        .build()
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let timeout = latency::timeout_for(&target_url, RequestPriority::Interactive, &state);
+    let build_client = |url: &Url, pin_https: bool| {
+        with_protocol_for(reqwest::Client::builder(), url, &state)
+            .cookie_store(true)
+            .cookie_provider(state.cookie_jar.clone())
+            .redirect(page_redirect_policy(pin_https, &state))
+            .timeout(timeout)
+            .connect_timeout(std::time::Duration::from_secs(10))
+            .gzip(true)
+            .brotli(true)
+            .deflate(true)
+            .build()
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)
+    };
+
+    if let Some(auth) = &auth_credentials {
+        println!("Adding {} for: {}", auth.kind(), domain);
+    }
+
+    let build_request = |client: &reqwest::Client, url: &Url| {
+        // Build request with filtered headers (exclude problematic ones)
+        let mut client_req_builder = client.request(parts.method.clone(), url.clone());
 
-    // Build request with filtered headers (exclude problematic ones)
-    let mut client_req_builder = client.request(parts.method, target_url.clone());
+        // Copy headers but exclude problematic ones (and the Referer, set by the referrer policy)
+        for (name, value) in parts.headers.iter() {
+            if name != header::HOST && name != header::CONNECTION && name != header::AUTHORIZATION && name != header::REFERER {
+                client_req_builder = client_req_builder.header(name, value);
+            }
+        }
 
-    // Copy headers but exclude problematic ones
-    for (name, value) in parts.headers.iter() {
-        if name != header::HOST && name != header::CONNECTION && name != header::AUTHORIZATION {
-            client_req_builder = client_req_builder.header(name, value);
+        // Add the domain's credentials if any
+        if let Some(auth) = &auth_credentials {
+            client_req_builder = auth.apply(client_req_builder);
         }
-    }
 
-    // Add HTTP Basic Auth if credentials are available
-    if let Some((username, password)) = auth_credentials {
-        println!("Adding HTTP Basic Auth for: {}", domain);
-        client_req_builder = client_req_builder.basic_auth(username, Some(password));
-    }
+        // The article URL (or what the session's referrer policy allows of it) as Referer.
+        // This helps bypass hotlinking protection on CDNs
+        if let Some(referer) = state.referrer_policy.load().referer_for(&base_url, url) {
+            client_req_builder = client_req_builder.header(header::REFERER, referer);
+        }
 
-    // For images and other resources, use the base_url (article URL) as Referer
-    // This helps bypass hotlinking protection on CDNs
-    let referer_url = {
-        let base_url_guard = state.base_url.lock().unwrap();
-        base_url_guard.to_string()
+        client_req_builder
+            .header(
+                header::USER_AGENT,
+                state.next_user_agent(),
+            )
+            .header(header::ACCEPT, "text/html,application/xhtml+xml,application/xml;q=0.9,image/webp,*/*;q=0.8")
+            .header(header::ACCEPT_LANGUAGE, accept_language_for(url, &state, DEFAULT_PROXY_ACCEPT_LANGUAGE))
+            .header(header::CONNECTION, "keep-alive")
+            .header("Upgrade-Insecure-Requests", "1")
+            .header(header::HOST, host_header_of(url))
+            .body(body_bytes.clone())
+            .build()
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)
+    };
+
+    chaos::inject_request_faults(&target_url, &state).await.map_err(chaos_fault_status)?;
+
+    let client = build_client(&target_url, false)?;
+    let started = std::time::Instant::now();
+    let response = credentials::execute(&client, build_request(&client, &target_url)?, &state).await;
+    latency::record_response(&target_url, started, timeout, &response, &state);
+    let (response, target_url) = match response {
+        Ok(response) => (response, target_url),
+        // Origins behind reverse proxies with bad redirect rules can bounce a page between
+        // http and https forever: retry once on https, without following redirects back to http
+        Err(e) if is_scheme_redirect_loop_error(&e) => {
+            let mut pinned = target_url.clone();
+            let _ = pinned.set_scheme("https");
+            println!("Redirect loop between http and https for {}, retrying pinned to {}", target_url, pinned);
+            let client = build_client(&pinned, true)?;
+            let response = client.execute(build_request(&client, &pinned)?).await.map_err(|_| StatusCode::BAD_GATEWAY)?;
+            if response.status().is_redirection() {
+                println!("{} still redirects to http, giving up", pinned);
+                return Ok(scheme_redirect_loop_response(&target_url));
+            }
+            (response, pinned)
+        }
+        Err(_) => return Err(StatusCode::BAD_GATEWAY),
     };
-    
-    let client_req = client_req_builder
-        .header(
-            header::USER_AGENT,
-            "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/120.0.0.0 Safari/537.36",
-        )
-        .header(header::ACCEPT, "text/html,application/xhtml+xml,application/xml;q=0.9,image/webp,*/*;q=0.8")
-        .header(header::ACCEPT_LANGUAGE, "en-US,en;q=0.9")
-        .header(header::CONNECTION, "keep-alive")
-        .header("Upgrade-Insecure-Requests", "1")
-        .header(header::REFERER, referer_url)
-        .header(header::HOST, target_url.host_str().unwrap_or("localhost"))
-        .body(body_bytes)
-        .build()
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
 
-    let response = client
-        .execute(client_req)
-        .await
-        .map_err(|_| StatusCode::BAD_GATEWAY)?;
-    
     // Check for 401 Unauthorized
     if response.status() == StatusCode::UNAUTHORIZED {
         println!("401 Unauthorized - auth required for: {}", domain);
-        // Return HTML page with script that requests auth via postMessage
-        let domain_escaped = domain.replace('\'', "\\'");
-        let auth_html = format!(
-            r#"<!DOCTYPE html>
-<html>
-<head><meta charset="UTF-8"></head>
-<body>
-<script>
-window.parent.postMessage({{
-  type: 'PROXY_AUTH_REQUIRED',
-  domain: '{}'
-}}, '*');
-</script>
-<p style="font-family: system-ui; text-align: center; padding: 2rem;">
-Authentication required for {}
-</p>
-</body>
-</html>"#,
-            domain_escaped, domain
-        );
-        return Ok(Response::builder()
-            .status(StatusCode::OK)
-            .header(header::CONTENT_TYPE, "text/html; charset=utf-8")
-            .body(Body::from(auth_html))
-            .unwrap());
+        return Ok(auth_required_response(&domain));
     }
 
+    let max_body_size = state.max_body_size();
+
     let content_type = response
         .headers()
         .get(header::CONTENT_TYPE)
         .and_then(|v| v.to_str().ok())
         .unwrap_or("")
         .to_string();
+    let content_type = chaos::mangle_content_type(&target_url, content_type, &state);
+    let rewrite = !too_large_to_rewrite(response.headers(), &state);
+    if !rewrite {
+        println!("Proxy: {} is larger than {} bytes, streaming it as is", target_url, state.max_rewrite_size());
+    }
 
     let mut builder = Response::builder().status(response.status());
     
@@ -1070,22 +2095,36 @@ Authentication required for {}
         .header(header::ACCESS_CONTROL_ALLOW_METHODS, "GET, POST, OPTIONS")
         .header(header::ACCESS_CONTROL_ALLOW_HEADERS, "Content-Type, Authorization");
     
-    // Copy headers but exclude problematic ones
-    for (key, value) in response.headers() {
-        if key != header::CONTENT_LENGTH 
-            && key != header::CONTENT_SECURITY_POLICY
-            && key != "x-frame-options"
-            && key != "transfer-encoding" // Let Axum handle this
-        {
-            builder = builder.header(key, value);
-        }
-    }
+    builder = copy_upstream_headers(builder, response.headers());
 
-    if content_type.contains("text/html") {
-        let text = response.text().await.unwrap();
+    if rewrite && content_type.contains("text/html") {
+        let text = read_html_limited(response, max_body_size).await.map_err(|e| {
+            eprintln!("Failed to read upstream HTML body for '{}': {}", target_url, e);
+            if e.starts_with(BODY_TOO_LARGE) {
+                StatusCode::PAYLOAD_TOO_LARGE
+            } else {
+                StatusCode::BAD_GATEWAY
+            }
+        })?;
+        let builder = with_cache_class(with_utf8_content_type(builder), CacheClass::Rewritten);
+        let text = chaos::mangle_body(&target_url, text, &state);
+        // Expose images hidden in <noscript> to the URL rewriting below
+        let text = unwrap_noscript_images(&text);
+        let text = element_filters::apply_element_filters(&text, &target_url, &state);
         let mut output = Vec::new();
 
-        let final_script = LISTENER_SCRIPT.to_string();
+        // Plain-http subresources of https pages: upgraded when their host serves https,
+        // otherwise proxied (or dropped in strict mode) and reported
+        let mixed_content = mixed_content::plan_for(&target_url, &text, &state).await;
+
+        let snapshot = state.snapshot_config.load_full();
+        let final_script = listener_script(&snapshot, state.article_actions.load().action_bar);
+        let style_buffer = RefCell::new(String::new());
+        // Malformed pages can have several <body> tags: the script goes in the first one only
+        let injected = std::cell::Cell::new(false);
+        // Icons the page declares, kept for `/icon`
+        let touch_icons = RefCell::new(Vec::new());
+        let declared_icons = RefCell::new(Vec::new());
 
         let mut rewriter = HtmlRewriter::new(
             Settings {
@@ -1093,19 +2132,22 @@ Authentication required for {}
                     // Rewrite all src attributes (images, scripts, etc.)
                     element!("*[src]", |el| {
                         if let Some(src) = el.get_attribute("src") {
+                            if rewrite_insecure_attribute(el, "src", &src, mixed_content.as_ref(), &proxy_base) {
+                                return Ok(());
+                            }
                             if src.contains("linuxfr2_plusieur.png") {
                                 println!("🖼️  FOUND TARGET IMAGE: src='{}'", src);
                             }
                             if !src.starts_with("data:") && !src.starts_with("blob:") && !src.starts_with("http://localhost:") && !src.starts_with("https://") && !src.starts_with("http://") {
                                 let absolute_url = if src.starts_with("//") {
                                     // Protocol-relative URL
-                                    format!("{}:{}", target_url.scheme(), src)
+                                    format!("{}:{}", target_url.scheme(), unescape_html(&src))
                                 } else if src.starts_with("/") {
                                     // Absolute path from domain root
-                                    format!("{}://{}{}", target_url.scheme(), target_url.host_str().unwrap_or("localhost"), src)
+                                    format!("{}{}", origin_of(&target_url), unescape_html(&src))
                                 } else {
                                     // Relative path
-                                    match target_url.join(&src) {
+                                    match target_url.join(&unescape_html(&src)) {
                                         Ok(url) => url.to_string(),
                                         Err(_) => {
                                             println!("Failed to join src '{}' with base '{}'", src, target_url);
@@ -1115,7 +2157,7 @@ Authentication required for {}
                                 };
                                 let proxy_url = format!("{}/proxy?url={}", proxy_base, urlencoding::encode(&absolute_url));
                                 println!("Rewriting src '{}' -> '{}' (base: {})", src, proxy_url, target_url);
-                                el.set_attribute("src", &proxy_url).unwrap();
+                                set_attribute_escaped(el, "src", &proxy_url);
                             } else {
                                 println!("Skipping src '{}' (data/blob/localhost/absolute)", src);
                             }
@@ -1125,16 +2167,28 @@ Authentication required for {}
                     // Rewrite href attributes for stylesheets and other resources (not navigation links)
                     element!("link[href], area[href]", |el| {
                         if let Some(href) = el.get_attribute("href") {
+                            let rel = el.get_attribute("rel").unwrap_or_default().to_ascii_lowercase();
+                            if let Ok(icon_url) = target_url.join(&unescape_html(href.trim())) {
+                                if rel.split_whitespace().any(|rel| rel == "apple-touch-icon") {
+                                    touch_icons.borrow_mut().push(icon_url.to_string());
+                                } else if rel.split_whitespace().any(|rel| rel == "icon") {
+                                    declared_icons.borrow_mut().push(icon_url.to_string());
+                                }
+                            }
+                            let is_subresource = el.tag_name() == "link" && mixed_content::is_subresource_link(&rel);
+                            if is_subresource && rewrite_insecure_attribute(el, "href", &href, mixed_content.as_ref(), &proxy_base) {
+                                return Ok(());
+                            }
                             if !href.starts_with("data:") && !href.starts_with("blob:") && !href.starts_with("http://localhost:") && !href.starts_with("#") && !href.starts_with("javascript:") && !href.starts_with("mailto:") && !href.starts_with("https://") && !href.starts_with("http://") {
                                 let absolute_url = if href.starts_with("//") {
                                     // Protocol-relative URL
-                                    format!("{}:{}", target_url.scheme(), href)
+                                    format!("{}:{}", target_url.scheme(), unescape_html(&href))
                                 } else if href.starts_with("/") {
                                     // Absolute path from domain root
-                                    format!("{}://{}{}", target_url.scheme(), target_url.host_str().unwrap_or("localhost"), href)
+                                    format!("{}{}", origin_of(&target_url), unescape_html(&href))
                                 } else {
                                     // Relative path
-                                    match target_url.join(&href) {
+                                    match target_url.join(&unescape_html(&href)) {
                                         Ok(url) => url.to_string(),
                                         Err(_) => {
                                             println!("Failed to join href '{}' with base '{}'", href, target_url);
@@ -1144,7 +2198,7 @@ Authentication required for {}
                                 };
                                 let proxy_url = format!("{}/proxy?url={}", proxy_base, urlencoding::encode(&absolute_url));
                                 println!("Rewriting resource href '{}' -> '{}' (base: {})", href, proxy_url, target_url);
-                                el.set_attribute("href", &proxy_url).unwrap();
+                                set_attribute_escaped(el, "href", &proxy_url);
                             } else {
                                 println!("Skipping href '{}' (data/blob/localhost/anchor/js/mailto/absolute)", href);
                             }
@@ -1160,7 +2214,7 @@ Authentication required for {}
                                     // Remove leading slash since Axum will add it
                                     let new_href = &href[1..];
                                     println!("Rewriting navigation href '{}' -> '{}' (direct)", href, new_href);
-                                    el.set_attribute("href", new_href).unwrap();
+                                    set_attribute_escaped(el, "href", &unescape_html(new_href));
                                 }
                                 // Keep relative paths as-is for navigation
                             }
@@ -1171,9 +2225,9 @@ Authentication required for {}
                     element!("form[action]", |el| {
                         if let Some(action) = el.get_attribute("action") {
                             if !action.starts_with("data:") && !action.starts_with("blob:") && !action.starts_with("http://localhost:") && !action.starts_with("#") && !action.starts_with("javascript:") {
-                                if let Ok(absolute_url) = target_url.join(&action) {
+                                if let Ok(absolute_url) = target_url.join(&unescape_html(&action)) {
                                     let proxy_url = format!("{}/proxy?url={}", proxy_base, urlencoding::encode(absolute_url.as_str()));
-                                    el.set_attribute("action", &proxy_url).unwrap();
+                                    set_attribute_escaped(el, "action", &proxy_url);
                                 }
                             }
                         }
@@ -1181,11 +2235,22 @@ Authentication required for {}
                     }),
                     // Rewrite srcset attributes for responsive images
                     element!("*[srcset]", |el| {
-                        if let Some(srcset) = el.get_attribute("srcset") {
+                        if let Some(srcset) = el.get_attribute("srcset").map(|srcset| unescape_html(&srcset)) {
                             let mut new_srcset = String::new();
                             for src_descriptor in srcset.split(',') {
                                 let parts: Vec<&str> = src_descriptor.trim().split_whitespace().collect();
                                 if let Some(url) = parts.first() {
+                                    if let Some(action) = mixed_content.as_ref().and_then(|plan| plan.action(url)) {
+                                        let rewritten = match action {
+                                            InsecureAction::Upgrade(upgraded) => upgraded,
+                                            InsecureAction::Proxy => format!("{}/proxy?url={}", proxy_base, urlencoding::encode(url)),
+                                            InsecureAction::Block => continue,
+                                        };
+                                        new_srcset.push_str(&rewritten);
+                                        if parts.len() > 1 { new_srcset.push(' '); new_srcset.push_str(parts[1]); }
+                                        new_srcset.push_str(", ");
+                                        continue;
+                                    }
                                     if !url.starts_with("data:") && !url.starts_with("blob:") && !url.starts_with("http://localhost:") {
                                         if let Ok(absolute_url) = target_url.join(url) {
                                             let proxy_url = format!("{}/proxy?url={}", proxy_base, urlencoding::encode(absolute_url.as_str()));
@@ -1205,34 +2270,1354 @@ Authentication required for {}
                             if new_srcset.ends_with(", ") {
                                 new_srcset.truncate(new_srcset.len() - 2);
                             }
-                            el.set_attribute("srcset", &new_srcset).unwrap();
+                            set_attribute_escaped(el, "srcset", &new_srcset);
                         }
                         Ok(())
                     }),
-                    // Inject our script
-                    element!("body", |el| {
-                        el.append(&final_script, lol_html::html_content::ContentType::Html);
+                    // Inline CSS resolves against the page; fetched stylesheets are rewritten
+                    // against their own URL when served below
+                    element!("*[style]", |el| {
+                        rewrite_style_attribute(el, &target_url, &proxy_base);
                         Ok(())
                     }),
-                ],
-                ..Settings::default()
-            },
+                    text!("style", |t| {
+                        rewrite_style_block(t, &style_buffer, &target_url, &proxy_base);
+                        Ok(())
+                    }),
+                    // Inline documents of srcdoc iframes inherit the proxy's URL as their base
+                    element!("iframe[srcdoc]", |el| {
+                        rewrite_srcdoc_attribute(el, &target_url, &proxy_base, inject_srcdoc.then_some(&*snapshot), 0);
+                        Ok(())
+                    }),
+                    // Inject our script
+                    element!("body", |el| {
+                        if inject && !injected.replace(true) {
+                            el.append(&final_script, lol_html::html_content::ContentType::Html);
+                        }
+                        Ok(())
+                    }),
+                ],
+                ..Settings::default()
+            },
             |c: &[u8]| output.extend_from_slice(c),
         );
 
-        rewriter.write(text.as_bytes()).unwrap();
-        rewriter.end().unwrap();
+        rewriter
+            .write(text.as_bytes())
+            .and_then(|_| rewriter.end())
+            .map_err(|e| {
+                eprintln!("HTML rewriting failed for '{}': {}", target_url, e);
+                StatusCode::BAD_GATEWAY
+            })?;
+
+        if let Some(plan) = mixed_content {
+            mixed_content::record_report(plan.into_report(), &state);
+        }
+        icons::record_page_icons(&target_url, touch_icons.into_inner(), declared_icons.into_inner(), &state);
 
         // Log a sample of navigation links in the final HTML for debugging
         let html_sample = String::from_utf8_lossy(&output);
         if let Some(start) = html_sample.find("<a href=") {
-            let end = (start + 100).min(html_sample.len());
-            println!("📄 NAVIGATION SAMPLE: {}", &html_sample[start..end]);
+            let sample: String = html_sample[start..].chars().take(100).collect();
+            println!("📄 NAVIGATION SAMPLE: {}", sample);
         }
 
-        Ok(builder.body(Body::from(output)).unwrap())
+        builder.body(Body::from(output)).map_err(|_| StatusCode::BAD_GATEWAY)
     } else {
-        let body = Body::from_stream(response.bytes_stream());
-        Ok(builder.body(body).unwrap())
+        let body = Body::from_stream(limited_body_stream(response, max_body_size));
+        with_cache_class(builder, CacheClass::Passthrough).body(body).map_err(|_| StatusCode::BAD_GATEWAY)
+    }
+}
+// --- CSS URLs ---
+
+// Functions whose string arguments are image URLs even without `url()`
+const CSS_IMAGE_SET_FUNCTIONS: &[&str] = &["image-set(", "-webkit-image-set("];
+
+fn starts_with_ignore_case(text: &str, prefix: &str) -> bool {
+    text.len() >= prefix.len() && text.as_bytes()[..prefix.len()].eq_ignore_ascii_case(prefix.as_bytes())
+}
+
+// Length of the CSS string starting with the quote at the start of `text`, quotes included
+// (or the rest of `text` when unterminated)
+fn css_string_len(text: &str) -> usize {
+    let quote = text.as_bytes()[0];
+    let mut escaped = false;
+    for (i, byte) in text.bytes().enumerate().skip(1) {
+        match byte {
+            _ if escaped => escaped = false,
+            b'\\' => escaped = true,
+            _ if byte == quote => return i + 1,
+            _ => {}
+        }
+    }
+    text.len()
+}
+
+// Proxy URL of a CSS string (quotes included) or unquoted `url()` value, keeping its quoting
+fn proxied_css_token(token: &str, base: &Url, proxy_base: &str) -> Option<String> {
+    let quote = token.chars().next().filter(|c| *c == '"' || *c == '\'');
+    let value = match quote {
+        Some(quote) => token[1..].strip_suffix(quote).unwrap_or(&token[1..]),
+        None => token,
+    };
+    let url = proxied_url(value, base, proxy_base)?;
+    Some(quote.map_or(url.clone(), |quote| format!("{}{}{}", quote, url, quote)))
+}
+
+/// Rewrites the URLs of a stylesheet (or `style` attribute) through the proxy: `url()` values
+/// anywhere (custom properties and `@media`/`@supports` blocks included), the strings of
+/// `image-set()`/`-webkit-image-set()` and `@import`. Relative URLs are resolved against
+/// `base`, the stylesheet's own URL for fetched CSS and the page's for inline CSS. Everything
+/// else, resolution descriptors of `image-set()` included, is copied as is.
+pub fn rewrite_css_urls(css: &str, base: &Url, proxy_base: &str) -> String {
+    let mut output = String::with_capacity(css.len());
+    let mut rest = css;
+    let mut depth = 0usize;
+    // Parenthesis depth of the innermost `image-set()`, whose direct strings are URLs
+    let mut image_set_depth: Option<usize> = None;
+
+    while let Some(c) = rest.chars().next() {
+        if rest.starts_with("/*") {
+            let end = rest[2..].find("*/").map_or(rest.len(), |end| end + 4);
+            output.push_str(&rest[..end]);
+            rest = &rest[end..];
+            continue;
+        }
+
+        let identifier_before = output.chars().last().is_some_and(|last| last.is_alphanumeric() || last == '-' || last == '_');
+        if !identifier_before && starts_with_ignore_case(rest, "url(") {
+            let inner = &rest[4..];
+            let leading = inner.len() - inner.trim_start().len();
+            let token_start = &inner[leading..];
+            let token_len = match token_start.chars().next() {
+                Some('"' | '\'') => css_string_len(token_start),
+                _ => token_start.find(')').unwrap_or(token_start.len()),
+            };
+            let token = token_start[..token_len].trim_end();
+            output.push_str(&rest[..4 + leading]);
+            output.push_str(&proxied_css_token(token, base, proxy_base).unwrap_or_else(|| token.to_string()));
+            rest = &token_start[token.len()..];
+            continue;
+        }
+        if !identifier_before {
+            if let Some(function) = CSS_IMAGE_SET_FUNCTIONS.iter().find(|function| starts_with_ignore_case(rest, function)) {
+                depth += 1;
+                image_set_depth = Some(depth);
+                output.push_str(&rest[..function.len()]);
+                rest = &rest[function.len()..];
+                continue;
+            }
+        }
+
+        match c {
+            '"' | '\'' => {
+                let token = &rest[..css_string_len(rest)];
+                let is_url = image_set_depth == Some(depth) || output.trim_end().to_ascii_lowercase().ends_with("@import");
+                match is_url.then(|| proxied_css_token(token, base, proxy_base)).flatten() {
+                    Some(url) => output.push_str(&url),
+                    None => output.push_str(token),
+                }
+                rest = &rest[token.len()..];
+                continue;
+            }
+            '(' => depth += 1,
+            ')' => {
+                if image_set_depth == Some(depth) {
+                    image_set_depth = None;
+                }
+                depth = depth.saturating_sub(1);
+            }
+            _ => {}
+        }
+        output.push(c);
+        rest = &rest[c.len_utf8()..];
+    }
+    output
+}
+
+// Rewrites the CSS of the `style` attribute of `el` in place against the page URL `base`
+fn rewrite_style_attribute(el: &mut Element, base: &Url, proxy_base: &str) {
+    let Some(style) = el.get_attribute("style") else {
+        return;
+    };
+    if !style.contains('(') && !style.contains("@import") {
+        return;
+    }
+    let rewritten = rewrite_css_urls(&unescape_html(&style), base, proxy_base);
+    set_attribute_escaped(el, "style", &rewritten);
+}
+
+// Rewrites a `<style>` block once its last text chunk arrives (chunks are buffered in `buffer`)
+fn rewrite_style_block(chunk: &mut TextChunk, buffer: &RefCell<String>, base: &Url, proxy_base: &str) {
+    buffer.borrow_mut().push_str(chunk.as_str());
+    if !chunk.last_in_text_node() {
+        chunk.remove();
+        return;
+    }
+    let css = buffer.take();
+    chunk.replace(&rewrite_css_urls(&css, base, proxy_base), ContentType::Html);
+}
+
+// --- srcdoc Documents ---
+
+// Depth of `<iframe srcdoc>` nested in srcdoc documents that still gets rewritten
+const MAX_SRCDOC_DEPTH: usize = 3;
+
+// Proxy URL of a relative or protocol-relative reference, resolved against `base`. Absolute
+// URLs and references that must stay as they are (data, blob, anchors...) give `None`.
+fn proxied_relative_url(value: &str, base: &Url, proxy_base: &str) -> Option<String> {
+    proxied_url(&unescape_html(value), base, proxy_base)
+}
+
+// `proxied_relative_url` of a value that isn't HTML-escaped (e.g. from CSS)
+fn proxied_url(value: &str, base: &Url, proxy_base: &str) -> Option<String> {
+    let value = value.trim();
+    let keep = ["data:", "blob:", "#", "javascript:", "mailto:", "about:", "http://", "https://"];
+    if value.is_empty() || keep.iter().any(|prefix| value.starts_with(prefix)) {
+        return None;
+    }
+    let absolute_url = base.join(value).ok()?;
+    Some(format!("{}/proxy?url={}", proxy_base, urlencoding::encode(absolute_url.as_str())))
+}
+
+/// Upstream URL of a URL rewritten by the proxy: the target of a `/proxy?url=` URL, or the
+/// upstream page of a page handler URL (relative to the article). Other URLs are returned as is.
+pub fn unproxied_url(value: &str, state: &ProxyState) -> String {
+    let value = value.trim();
+    let url = match Url::parse(value) {
+        Ok(url) if matches!(url.host_str(), Some("localhost" | "127.0.0.1")) && url.port() == state.port.get().copied() => url,
+        // Web App mode: proxy URLs are relative to the frontend's origin
+        Err(_) if value.starts_with("/proxy?") => match Url::parse("http://localhost").and_then(|local| local.join(value)) {
+            Ok(url) => url,
+            Err(_) => return value.to_string(),
+        },
+        _ => return value.to_string(),
+    };
+    if url.path() == "/proxy" {
+        return url.query_pairs().find(|(key, _)| key == "url").map_or(value.to_string(), |(_, target)| target.into_owned());
+    }
+    if url.path().starts_with("/transfer/") {
+        return value.to_string();
+    }
+    let path = &url[url::Position::BeforePath..];
+    state.base_url.load().join(path.trim_start_matches('/')).map_or(value.to_string(), |upstream| upstream.to_string())
+}
+
+/// Rewrites the HTML document of an `<iframe srcdoc>`. Such documents inherit the URL of the
+/// page embedding them, which through the proxy is the proxy's own, so their relative
+/// resource URLs are resolved against `base` (the upstream page) and proxied. Nested srcdoc
+/// iframes are rewritten too, up to `MAX_SRCDOC_DEPTH`. With `inject`, the listener script
+/// is appended to the document with that snapshot config.
+pub fn rewrite_srcdoc(srcdoc: &str, base: &Url, proxy_base: &str, inject: Option<&SnapshotConfig>, depth: usize) -> Result<String, String> {
+    let rewrite_attribute = |el: &mut Element, attribute: &str| {
+        if let Some(url) = el.get_attribute(attribute).and_then(|value| proxied_relative_url(&value, base, proxy_base)) {
+            set_attribute_escaped(el, attribute, &url);
+        }
+    };
+    let injected = std::cell::Cell::new(false);
+    // Nested documents never get an action bar of their own
+    let script = inject.map(|snapshot| listener_script(snapshot, false)).unwrap_or_default();
+    let style_buffer = RefCell::new(String::new());
+
+    let mut rewritten = rewrite_str(
+        srcdoc,
+        RewriteStrSettings {
+            element_content_handlers: vec![
+                element!("*[src]", |el| {
+                    rewrite_attribute(el, "src");
+                    Ok(())
+                }),
+                element!("*[style]", |el| {
+                    rewrite_style_attribute(el, base, proxy_base);
+                    Ok(())
+                }),
+                text!("style", |t| {
+                    rewrite_style_block(t, &style_buffer, base, proxy_base);
+                    Ok(())
+                }),
+                element!("link[href]", |el| {
+                    rewrite_attribute(el, "href");
+                    Ok(())
+                }),
+                element!("*[poster]", |el| {
+                    rewrite_attribute(el, "poster");
+                    Ok(())
+                }),
+                element!("*[srcset]", |el| {
+                    if let Some(srcset) = el.get_attribute("srcset").map(|srcset| unescape_html(&srcset)) {
+                        let candidates: Vec<String> = srcset
+                            .split(',')
+                            .map(|candidate| {
+                                let candidate = candidate.trim();
+                                let (url, descriptor) = candidate.split_once(char::is_whitespace).unwrap_or((candidate, ""));
+                                let url = proxied_url(url, base, proxy_base).unwrap_or_else(|| url.to_string());
+                                format!("{} {}", url, descriptor.trim()).trim_end().to_string()
+                            })
+                            .collect();
+                        set_attribute_escaped(el, "srcset", &candidates.join(", "));
+                    }
+                    Ok(())
+                }),
+                element!("iframe[srcdoc]", |el| {
+                    if depth + 1 < MAX_SRCDOC_DEPTH {
+                        rewrite_srcdoc_attribute(el, base, proxy_base, inject, depth + 1);
+                    }
+                    Ok(())
+                }),
+                element!("body", |el| {
+                    if inject.is_some() && !injected.replace(true) {
+                        el.append(&script, lol_html::html_content::ContentType::Html);
+                    }
+                    Ok(())
+                }),
+            ],
+            ..RewriteStrSettings::default()
+        },
+    )
+    .map_err(|e| e.to_string())?;
+
+    // Fragments without `<body>` (the usual widget markup) get the script at the end
+    if inject.is_some() && !injected.get() {
+        rewritten.push_str(&script);
+    }
+    Ok(rewritten)
+}
+
+// Rewrites the `srcdoc` attribute of `el` in place. The attribute value is raw markup, so it
+// is decoded before rewriting and re-encoded after; it's left untouched if rewriting fails.
+fn rewrite_srcdoc_attribute(el: &mut Element, base: &Url, proxy_base: &str, inject: Option<&SnapshotConfig>, depth: usize) {
+    let Some(srcdoc) = el.get_attribute("srcdoc") else {
+        return;
+    };
+    match rewrite_srcdoc(&unescape_html(&srcdoc), base, proxy_base, inject, depth) {
+        Ok(rewritten) => set_attribute_escaped(el, "srcdoc", &rewritten),
+        Err(e) => println!("[proxy::rewrite_srcdoc] Keeping srcdoc as is: {}", e),
+    }
+}
+
+/// Rewrites a srcdoc document for a page at `base_url` through the running proxy, for
+/// inline documents the frontend renders itself (e.g. email previews)
+pub fn logic_rewrite_srcdoc(srcdoc: String, base_url: String, inject: bool, state: &ProxyState) -> Result<String, String> {
+    let base = Url::parse(&base_url).map_err(|e| e.to_string())?;
+    let snapshot = state.snapshot_config.load();
+    rewrite_srcdoc(&srcdoc, &base, &state.local_base(), inject.then_some(&*snapshot), 0)
+}
+
+// --- Injection A/B Comparison ---
+
+// Page scripts checked through the proxy by `logic_proxy_compare_injection`
+const MAX_CHECKED_SCRIPTS: usize = 20;
+
+// Differing lines reported as samples in a diff summary
+const MAX_DIFF_SAMPLES: usize = 5;
+
+/// Line-level difference between the page served without and with the listener script
+#[derive(Debug, Clone, Serialize)]
+pub struct InjectionDiff {
+    pub added_lines: usize,
+    pub removed_lines: usize,
+    /// Every added line belongs to the listener script and nothing was removed
+    pub only_injection_differs: bool,
+    /// First differing lines outside the listener script, prefixed with `+` or `-`
+    pub samples: Vec<String>,
+}
+
+/// Same page fetched through the proxy with and without the listener script
+#[derive(Debug, Clone, Serialize)]
+pub struct InjectionComparison {
+    pub url: String,
+    pub status_with: u16,
+    pub status_without: u16,
+    pub bytes_with: usize,
+    pub bytes_without: usize,
+    /// `bytes_with - bytes_without`
+    pub size_delta: i64,
+    /// The listener script ended up in the page (it isn't injected into pages without `<body>`)
+    pub script_injected: bool,
+    /// A `<meta>` Content-Security-Policy disallows inline scripts, so the listener script can't run
+    pub csp_blocks_injection: bool,
+    /// Page scripts that don't load through the proxy
+    pub failing_scripts: Vec<String>,
+    /// Heuristic: the page's own scripts are likely to error (failing scripts, or a CSP
+    /// that blocks inline scripts the page relies on)
+    pub script_errors_likely: bool,
+    pub diff: InjectionDiff,
+}
+
+/// Whether a CSP policy string disallows inline scripts
+fn csp_blocks_inline_scripts(policy: &str) -> bool {
+    let directives: Vec<(String, String)> = policy
+        .split(';')
+        .filter_map(|directive| {
+            let mut parts = directive.trim().splitn(2, char::is_whitespace);
+            let name = parts.next()?.to_ascii_lowercase();
+            Some((name, parts.next().unwrap_or("").to_ascii_lowercase()))
+        })
+        .collect();
+    let sources = directives
+        .iter()
+        .find(|(name, _)| name == "script-src-elem" || name == "script-src")
+        .or_else(|| directives.iter().find(|(name, _)| name == "default-src"));
+
+    match sources {
+        None => false,
+        // Nonces, hashes and 'strict-dynamic' make browsers ignore 'unsafe-inline'
+        Some((_, sources)) => {
+            !sources.contains("'unsafe-inline'")
+                || sources.contains("'nonce-")
+                || sources.contains("'sha256-")
+                || sources.contains("'sha384-")
+                || sources.contains("'sha512-")
+                || sources.contains("'strict-dynamic'")
+        }
+    }
+}
+
+fn summarize_injection_diff(without: &str, with: &str) -> InjectionDiff {
+    let mut remaining: HashMap<&str, isize> = HashMap::new();
+    for line in without.lines() {
+        *remaining.entry(line).or_default() += 1;
+    }
+    let script_lines: std::collections::HashSet<&str> = LISTENER_SCRIPT.lines().map(str::trim).collect();
+
+    let mut added = Vec::new();
+    for line in with.lines() {
+        match remaining.get_mut(line) {
+            Some(count) if *count > 0 => *count -= 1,
+            _ => added.push(line),
+        }
+    }
+    let removed: Vec<&str> = without
+        .lines()
+        .filter(|line| {
+            let count = remaining.entry(line).or_default();
+            *count -= 1;
+            *count >= 0
+        })
+        .collect();
+
+    let foreign_added: Vec<&str> = added
+        .iter()
+        .copied()
+        .filter(|line| !script_lines.contains(line.trim()) && !INJECTION_VALUE_LINES.iter().any(|prefix| line.trim().starts_with(prefix)))
+        .collect();
+    let samples = foreign_added
+        .iter()
+        .map(|line| format!("+ {}", line.trim()))
+        .chain(removed.iter().map(|line| format!("- {}", line.trim())))
+        .take(MAX_DIFF_SAMPLES)
+        .map(|line| line.chars().take(160).collect())
+        .collect();
+
+    InjectionDiff {
+        added_lines: added.len(),
+        removed_lines: removed.len(),
+        only_injection_differs: removed.is_empty() && foreign_added.is_empty(),
+        samples,
+    }
+}
+
+/// Fetches `url` through the running proxy with and without the listener script and compares
+/// the results, to tell whether a broken page is due to the injection or to the rewriting.
+pub async fn logic_proxy_compare_injection(url: String, state: &ProxyState) -> Result<InjectionComparison, String> {
+    Url::parse(&url).map_err(|e| e.to_string())?;
+
+    let port = state
+        .port
+        .get()
+        .copied()
+        .or_else(|| std::env::var("PORT").ok()?.parse().ok())
+        .ok_or("Proxy server is not running")?;
+    let proxy_base = format!("http://127.0.0.1:{}", port);
+    let client = reqwest::Client::builder()
+        .timeout(std::time::Duration::from_secs(60))
+        .build()
+        .map_err(|e| e.to_string())?;
+
+    let mut pages = Vec::new();
+    for inject in [true, false] {
+        let proxy_url = format!("{}/proxy?url={}&inject={}", proxy_base, urlencoding::encode(&url), if inject { 1 } else { 0 });
+        let response = client.get(&proxy_url).send().await.map_err(|e| e.to_string())?;
+        let status = response.status().as_u16();
+        let body = response.text().await.map_err(|e| e.to_string())?;
+        pages.push((status, body));
+    }
+    let (status_without, without) = pages.pop().unwrap();
+    let (status_with, with) = pages.pop().unwrap();
+
+    let (csp_policies, script_urls) = {
+        let document = scraper::Html::parse_document(&without);
+        let csp = scraper::Selector::parse("meta[http-equiv]").unwrap();
+        let scripts = scraper::Selector::parse("script[src]").unwrap();
+        let policies: Vec<String> = document
+            .select(&csp)
+            .filter(|meta| meta.value().attr("http-equiv").is_some_and(|v| v.eq_ignore_ascii_case("content-security-policy")))
+            .filter_map(|meta| meta.value().attr("content").map(str::to_string))
+            .collect();
+        let script_urls: Vec<String> = document
+            .select(&scripts)
+            .filter_map(|script| script.value().attr("src"))
+            .filter_map(|src| {
+                if src.starts_with("/proxy?") {
+                    Some(format!("{}{}", proxy_base, src))
+                } else if src.starts_with("http://") || src.starts_with("https://") {
+                    Some(src.to_string())
+                } else {
+                    None
+                }
+            })
+            .take(MAX_CHECKED_SCRIPTS)
+            .collect();
+        (policies, script_urls)
+    };
+    let csp_blocks_injection = csp_policies.iter().any(|policy| csp_blocks_inline_scripts(policy));
+
+    let mut failing_scripts = Vec::new();
+    for script_url in script_urls {
+        let ok = client.get(&script_url).send().await.is_ok_and(|response| response.status().is_success());
+        if !ok {
+            failing_scripts.push(script_url);
+        }
+    }
+
+    let has_inline_page_scripts = without.contains("<script>") || without.contains("<script type=\"text/javascript\">");
+    let comparison = InjectionComparison {
+        script_injected: with.contains("canAccessParent"),
+        size_delta: with.len() as i64 - without.len() as i64,
+        bytes_with: with.len(),
+        bytes_without: without.len(),
+        csp_blocks_injection,
+        script_errors_likely: !failing_scripts.is_empty() || (csp_blocks_injection && has_inline_page_scripts),
+        failing_scripts,
+        diff: summarize_injection_diff(&without, &with),
+        status_with,
+        status_without,
+        url,
+    };
+    println!(
+        "[proxy::compare_injection] {}: delta {} bytes, injected: {}, CSP blocks: {}, failing scripts: {}",
+        comparison.url, comparison.size_delta, comparison.script_injected, comparison.csp_blocks_injection, comparison.failing_scripts.len()
+    );
+    Ok(comparison)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::credentials::AuthMethod;
+    use axum::extract::RawQuery;
+    use axum::response::{Html, IntoResponse};
+    use crate::test_support::{serve, Rng};
+    use std::collections::BTreeMap;
+    use std::sync::atomic::AtomicBool;
+    use std::sync::{Arc, Mutex};
+
+    const PROXY_BASE: &str = "http://localhost:3000";
+
+    async fn body_text(response: Response) -> String {
+        String::from_utf8_lossy(&to_bytes(response.into_body(), usize::MAX).await.unwrap()).into_owned()
+    }
+
+    /// Attributes of the elements with an `id`, values unescaped
+    fn attributes_by_id(html: &str) -> HashMap<String, HashMap<String, String>> {
+        let found = RefCell::new(HashMap::new());
+        rewrite_str(
+            html,
+            RewriteStrSettings {
+                element_content_handlers: vec![element!("*[id]", |el| {
+                    let attributes = el.attributes().iter().map(|attribute| (attribute.name(), unescape_html(&attribute.value()))).collect();
+                    found.borrow_mut().insert(el.get_attribute("id").unwrap(), attributes);
+                    Ok(())
+                })],
+                ..RewriteStrSettings::default()
+            },
+        )
+        .unwrap();
+        found.into_inner()
+    }
+
+    /// How many times each attribute name appears in `html`
+    fn attribute_counts(html: &str) -> BTreeMap<String, usize> {
+        let counts = RefCell::new(BTreeMap::new());
+        rewrite_str(
+            html,
+            RewriteStrSettings {
+                element_content_handlers: vec![element!("*", |el| {
+                    for attribute in el.attributes() {
+                        *counts.borrow_mut().entry(attribute.name()).or_default() += 1;
+                    }
+                    Ok(())
+                })],
+                ..RewriteStrSettings::default()
+            },
+        )
+        .unwrap();
+        counts.into_inner()
+    }
+
+    fn proxied(url: &str) -> String {
+        format!("{}/proxy?url={}", PROXY_BASE, urlencoding::encode(url))
+    }
+
+    /// Serves `page` at `/page` and returns the state of a proxy browsing it
+    async fn proxy_for(page: Arc<Mutex<(String, HeaderMap)>>) -> (ProxyState, Url) {
+        let app = Router::new().route(
+            "/page",
+            get(move || {
+                let page = page.clone();
+                async move {
+                    let (html, headers) = page.lock().unwrap().clone();
+                    let mut response = Response::new(Body::from(html));
+                    *response.headers_mut() = headers;
+                    response.headers_mut().insert(header::CONTENT_TYPE, HeaderValue::from_static("text/html; charset=utf-8"));
+                    response
+                }
+            }),
+        );
+        let addr = serve(app).await;
+        let base = Url::parse(&format!("http://{}/page", addr)).unwrap();
+        let state = ProxyState::default();
+        state.base_url.store(Arc::new(base.clone()));
+        (state, base)
+    }
+
+    async fn through_proxy(state: &ProxyState) -> Response {
+        let request = Request::builder().uri("/page?inject=0").body(Body::empty()).unwrap();
+        proxy_handler(Path("page".to_string()), State(state.clone()), request).await.unwrap()
+    }
+
+    const HOSTILE_PAGE: &str = r#"<html><head><title>t</title></head><body>
+<img id="amp" src="img.png?a=1&amp;lt=2">
+<img id="quote" src="pic.png?q=&quot;onerror=&quot;alert(1)">
+<a id="nav" href="/path?x=1&amp;y=&quot; onmouseover=alert(1)">x</a>
+<img id="set" srcset="a.png?w=1&amp;h=2 1x, https://cdn.example/b.png?q=&quot;&amp;lt 2x">
+<div id="style" style="background:url('bg.png?a=1&amp;b=2')"></div>
+<form id="form" action="submit?a=1&amp;b=&lt;x&gt;"></form>
+<iframe id="doc" srcdoc="&lt;img src=&quot;inner.png?a&amp;amp;b&quot;&gt;"></iframe>
+</body></html>"#;
+
+    #[tokio::test]
+    async fn hostile_page_attributes_round_trip() {
+        let page = Arc::new(Mutex::new((HOSTILE_PAGE.to_string(), HeaderMap::new())));
+        let (state, base) = proxy_for(page).await;
+        let html = body_text(through_proxy(&state).await).await;
+        let join = |path: &str| base.join(path).unwrap().to_string();
+        let found = attributes_by_id(&html);
+
+        assert_eq!(found["amp"]["src"], proxied(&join("img.png?a=1&lt=2")));
+        assert_eq!(found["quote"]["src"], proxied(&join("pic.png?q=\"onerror=\"alert(1)")));
+        assert_eq!(found["nav"]["href"], "path?x=1&y=\" onmouseover=alert(1)");
+        assert_eq!(found["set"]["srcset"], format!("{} 1x, {} 2x", proxied(&join("a.png?w=1&h=2")), proxied(&join("https://cdn.example/b.png?q=\"&lt"))));
+        assert!(found["style"]["style"].contains(&urlencoding::encode(&join("bg.png?a=1&b=2")).into_owned()), "{}", found["style"]["style"]);
+        assert_eq!(found["form"]["action"], proxied(&join("submit?a=1&b=<x>")));
+        let inner = attributes_by_id(&format!("<img id=\"inner\"{}", found["doc"]["srcdoc"].trim_start_matches("<img")));
+        assert_eq!(inner["inner"]["src"], proxied(&join("inner.png?a&b")));
+
+        // Quotes in values never turn into attributes
+        let counts = attribute_counts(&html);
+        assert!(!counts.contains_key("onerror") && !counts.contains_key("onmouseover"), "{:?}", counts);
+    }
+
+    #[tokio::test]
+    async fn hostile_upstream_headers_are_dropped() {
+        let mut headers = HeaderMap::new();
+        headers.insert("x-plain", HeaderValue::from_static("kept"));
+        headers.insert("x-latin", HeaderValue::from_bytes(b"caf\xc3\xa9").unwrap());
+        headers.insert("x-obs", HeaderValue::from_bytes(b"a\xffb").unwrap());
+        headers.insert(header::CONTENT_SECURITY_POLICY, HeaderValue::from_static("frame-ancestors 'none'"));
+        headers.insert("x-frame-options", HeaderValue::from_static("DENY"));
+        let page = Arc::new(Mutex::new(("<html><body>ok</body></html>".to_string(), headers)));
+        let (state, _) = proxy_for(page).await;
+        let response = through_proxy(&state).await;
+
+        let headers = response.headers();
+        assert_eq!(headers.get("x-plain").unwrap(), "kept");
+        for dropped in ["x-latin", "x-obs", "content-security-policy", "x-frame-options"] {
+            assert!(headers.get(dropped).is_none(), "{} forwarded", dropped);
+        }
+        assert_eq!(headers.get(header::CONTENT_TYPE).unwrap(), "text/html; charset=utf-8");
+    }
+
+    #[tokio::test]
+    async fn legacy_charset_pages_are_served_as_utf8() {
+        for (page, content_type, expected) in [
+            (&include_bytes!("../tests/fixtures/charsets/latin1.html")[..], "text/html", "La crème brûlée, une histoire française ?"),
+            (&include_bytes!("../tests/fixtures/charsets/shift_jis.html")[..], "text/html", "京都の古い喫茶店を訪ねて"),
+            (&include_bytes!("../tests/fixtures/charsets/shift_jis.html")[..], "text/html; charset=utf-8", "店主は「変わらないことが一番難しい」と笑う。"),
+            (&include_bytes!("../tests/fixtures/charsets/shift_jis_header_only.html")[..], "text/html; Charset=Shift_JIS", "京都の古い喫茶店を訪ねて"),
+        ] {
+            let app = Router::new().route("/page", get(move || async move { ([(header::CONTENT_TYPE, content_type)], page) }));
+            let state = ProxyState::default();
+            state.base_url.store(Arc::new(Url::parse(&format!("http://{}/page", serve(app).await)).unwrap()));
+
+            let response = through_proxy(&state).await;
+            assert_eq!(response.headers().get(header::CONTENT_TYPE).unwrap(), "text/html; charset=utf-8");
+            let html = String::from_utf8(to_bytes(response.into_body(), usize::MAX).await.unwrap().to_vec()).unwrap();
+            assert!(html.contains(expected) && !html.contains('\u{FFFD}'), "{}", html);
+        }
+    }
+
+    #[test]
+    fn copy_upstream_headers_filters_values() {
+        let mut headers = HeaderMap::new();
+        headers.insert("x-tab", HeaderValue::from_static("a\tb"));
+        headers.insert("x-high", HeaderValue::from_bytes(b"\x80").unwrap());
+        headers.insert(header::CONTENT_LENGTH, HeaderValue::from_static("12"));
+        headers.insert(header::TRANSFER_ENCODING, HeaderValue::from_static("chunked"));
+        let response = copy_upstream_headers(Response::builder(), &headers).body(Body::empty()).unwrap();
+        assert_eq!(response.headers().len(), 1);
+        assert_eq!(response.headers().get("x-tab").unwrap(), "a\tb");
+    }
+
+    #[tokio::test]
+    async fn auth_page_escapes_hostile_domains() {
+        let domain = "https://evil.example'\"</script><script>alert(1)</script><b>\u{2028}";
+        let response = auth_required_response(domain);
+        assert_eq!(response.headers().get(header::CACHE_CONTROL).unwrap(), "no-store");
+        let html = body_text(response).await;
+        assert_eq!(html.matches("<script>").count(), 1);
+        assert_eq!(html.matches("</script>").count(), 1);
+        assert!(!html.contains("<b>"));
+        let script = &html[html.find("<script>").unwrap()..html.find("</script>").unwrap()];
+        assert!(!script.contains('\u{2028}'));
+        assert!(html.contains("&lt;/script&gt;&lt;script&gt;alert(1)&lt;/script&gt;&lt;b&gt;"));
+    }
+
+    #[test]
+    fn set_attribute_escaped_round_trips() {
+        let mut rng = Rng::new(0x5eed);
+        let alphabet = ["a", "&", "&amp;", "&lt;", ";", "\"", "'", "<", ">", " ", "=", "#x27;", "&#34;", "é", "\u{2028}"];
+        for _ in 0..500 {
+            let value: String = (0..rng.below(12)).map(|_| *rng.pick(&alphabet)).collect();
+            let html = rewrite_str(
+                "<p id=\"x\" title=\"old\"></p>",
+                RewriteStrSettings {
+                    element_content_handlers: vec![element!("p", |el| {
+                        set_attribute_escaped(el, "title", &value);
+                        Ok(())
+                    })],
+                    ..RewriteStrSettings::default()
+                },
+            )
+            .unwrap();
+            assert_eq!(attributes_by_id(&html)["x"]["title"], value, "{:?} -> {}", value, html);
+        }
+    }
+
+    async fn fetch_resource(url: &str, accept: &str, state: &ProxyState) -> Response {
+        let query = HashMap::from([("url".to_string(), url.to_string())]);
+        let request = Request::builder().uri("/proxy").header(header::ACCEPT, accept).body(Body::empty()).unwrap();
+        proxy_resource_handler(Query(query), State(state.clone()), request).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn offline_mode_serves_cached_resources_first() {
+        let hits = Arc::new(AtomicU64::new(0));
+        let counter = hits.clone();
+        let app = Router::new().route(
+            "/style.css",
+            get(move || {
+                counter.fetch_add(1, Ordering::Relaxed);
+                async { ([(header::CONTENT_TYPE, "text/css"), (header::ETAG, "\"v1\"")], "body { background: url(bg.png) }") }
+            }),
+        );
+        let addr = serve(app).await;
+        let state = ProxyState::default();
+        let stylesheet = format!("http://{}/style.css", addr);
+
+        let online = body_text(fetch_resource(&stylesheet, "text/css", &state).await).await;
+        assert_eq!(hits.load(Ordering::Relaxed), 1);
+
+        connectivity::logic_set_offline_mode(true, &state);
+        let response = fetch_resource(&stylesheet, "text/css", &state).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers().get(header::CACHE_CONTROL).unwrap(), "private, max-age=60");
+        assert_eq!(body_text(response).await, online);
+        assert!(online.contains(&proxied(&format!("http://{}/bg.png", addr))), "{}", online);
+        assert_eq!(hits.load(Ordering::Relaxed), 1);
+
+        // Nothing cached: the image placeholder, or a 503
+        let image = fetch_resource(&format!("http://{}/bg.png", addr), "image/avif,image/*", &state).await;
+        assert_eq!(image.status(), StatusCode::OK);
+        assert_eq!(image.headers().get(header::CONTENT_TYPE).unwrap(), "image/gif");
+        assert_eq!(to_bytes(image.into_body(), usize::MAX).await.unwrap(), OFFLINE_IMAGE_PLACEHOLDER);
+        let script = fetch_resource(&format!("http://{}/app.js", addr), "*/*", &state).await;
+        assert_eq!(script.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(hits.load(Ordering::Relaxed), 1);
+    }
+
+    const FUZZ_TAGS: &[&str] = &["img", "a", "link", "iframe", "form", "div", "video", "source", "style"];
+    const FUZZ_ATTRIBUTES: &[&str] = &["src", "href", "srcset", "style", "srcdoc", "action", "poster", "rel", "title"];
+    const FUZZ_VALUE_PARTS: &[&str] = &[
+        "a.png", "/", "//cdn.example/", "http://", "https://x.example/", "data:", "javascript:", "#", "?", "&", "&amp;", "&lt;", "&quot;",
+        "&#39;", "&#x22;", "&", ";", "=", "\"", "'", "<", ">", " ", ",", " 2x", "url(", ")", "@import ", "\\", "%", "%2F", "é", "\u{0}", "\n",
+        "<img src=x.png>", "onerror=alert(1)",
+    ];
+
+    /// Unquoted attribute values can't hold these
+    const UNQUOTED_STOPS: &[char] = &[' ', '\n', '"', '\'', '=', '<', '>', '`'];
+
+    /// A random document made of the tags and attributes the rewriter handles, with hostile
+    /// values. Tags are often left open, but values stay tokenizable: lol_html rewrites a start
+    /// tag from its attribute list, so attribute soup (`a/ = 'b`) would not round-trip anyway.
+    fn fuzz_document(rng: &mut Rng) -> String {
+        let mut html = String::from("<html><body>");
+        for _ in 0..rng.below(8) + 1 {
+            let tag = *rng.pick(FUZZ_TAGS);
+            html.push('<');
+            html.push_str(tag);
+            for _ in 0..rng.below(4) {
+                let quote = *rng.pick(&["\"", "'", ""]);
+                let value: String = (0..rng.below(6))
+                    .map(|_| *rng.pick(FUZZ_VALUE_PARTS))
+                    .filter(|part| if quote.is_empty() { !part.contains(UNQUOTED_STOPS) } else { !part.contains(quote) })
+                    .collect();
+                html.push_str(&format!(" {}={}{}{}", rng.pick(FUZZ_ATTRIBUTES), quote, value, quote));
+            }
+            html.push('>');
+            if rng.below(3) == 0 {
+                let text: &&str = rng.pick(FUZZ_VALUE_PARTS);
+                html.push_str(text);
+            }
+            if rng.below(2) == 0 {
+                html.push_str(&format!("</{}>", tag));
+            }
+        }
+        html
+    }
+
+    /// The rewriter changes attribute values, never the document's structure: every attribute
+    /// of the input is still there, and no value spills into a new one
+    #[test]
+    fn fuzz_srcdoc_rewriter_keeps_attributes() {
+        let base = Url::parse("https://site.example/dir/page.html").unwrap();
+        for seed in 1..=2000 {
+            let mut rng = Rng::new(seed);
+            let html = fuzz_document(&mut rng);
+            let rewritten = rewrite_srcdoc(&html, &base, PROXY_BASE, None, 0).unwrap_or_else(|e| panic!("seed {}: {}\n{}", seed, e, html));
+            assert_eq!(attribute_counts(&rewritten), attribute_counts(&html), "seed {}\n{}\n{}", seed, html, rewritten);
+        }
+    }
+
+    #[tokio::test]
+    async fn fuzz_page_rewriter_keeps_attributes() {
+        let page = Arc::new(Mutex::new((String::new(), HeaderMap::new())));
+        let (state, _) = proxy_for(page.clone()).await;
+        for seed in 1..=100 {
+            let mut rng = Rng::new(seed);
+            let html = fuzz_document(&mut rng);
+            page.lock().unwrap().0 = html.clone();
+            let response = through_proxy(&state).await;
+            assert_eq!(response.status(), StatusCode::OK, "seed {}", seed);
+            let rewritten = body_text(response).await;
+            assert_eq!(attribute_counts(&rewritten), attribute_counts(&html), "seed {}\n{}\n{}", seed, html, rewritten);
+        }
+    }
+
+    /// Serves a page and an image on the IPv6 loopback, answering 401 to requests without the
+    /// Bearer token, and records the Host header of each request
+    async fn ipv6_site(hosts: Arc<Mutex<Vec<String>>>) -> (ProxyState, String) {
+        let guarded = |body: &'static str, content_type: &'static str| {
+            let hosts = hosts.clone();
+            get(move |headers: HeaderMap| async move {
+                hosts.lock().unwrap().push(headers.get(header::HOST).and_then(|v| v.to_str().ok()).unwrap_or_default().to_string());
+                if headers.get(header::AUTHORIZATION).and_then(|v| v.to_str().ok()) != Some("Bearer v6-token") {
+                    return StatusCode::UNAUTHORIZED.into_response();
+                }
+                ([(header::CONTENT_TYPE, content_type)], body).into_response()
+            })
+        };
+        let app = Router::new()
+            .route("/page", guarded(r#"<html><head><link id="css" rel="stylesheet" href="/style.css"></head><body><img id="root" src="/img.png"></body></html>"#, "text/html"))
+            .route("/img.png", guarded("png", "image/png"));
+        let addr = crate::test_support::serve_on("::1", app).await;
+        let origin = format!("http://[::1]:{}", addr.port());
+
+        let state = ProxyState::default();
+        state.base_url.store(Arc::new(Url::parse(&format!("{}/page", origin)).unwrap()));
+        state.auth_credentials.insert(origin.clone(), AuthMethod::Bearer("v6-token".into()));
+        (state, origin)
+    }
+
+    #[tokio::test]
+    async fn proxies_an_ipv6_origin_with_a_port() {
+        let hosts = Arc::new(Mutex::new(Vec::new()));
+        let (state, origin) = ipv6_site(hosts.clone()).await;
+        let host = origin.trim_start_matches("http://").to_string();
+
+        let response = through_proxy(&state).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let attributes = attributes_by_id(&body_text(response).await);
+        assert_eq!(attributes["root"]["src"], proxied(&format!("{}/img.png", origin)));
+        assert_eq!(attributes["css"]["href"], proxied(&format!("{}/style.css", origin)));
+
+        let response = fetch_resource(&format!("{}/img.png", origin), "image/*", &state).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(body_text(response).await, "png");
+
+        // Resources requested by root path are resolved against the page's origin
+        let request = Request::builder().uri("/img.png").body(Body::empty()).unwrap();
+        let response = proxy_handler(Path("img.png".to_string()), State(state.clone()), request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        assert_eq!(*hosts.lock().unwrap(), vec![host.clone(), host.clone(), host]);
+    }
+
+    /// Serves `/page`, naming `site`, and `/img.png`, which needs the `site` Bearer token
+    async fn stress_site(site: &'static str) -> String {
+        let app = Router::new()
+            .route("/page", get(move || async move { Html(format!(r#"<html><body><p>site {}</p><img id="img" src="/img.png"></body></html>"#, site)) }))
+            .route(
+                "/img.png",
+                get(move |headers: HeaderMap| async move {
+                    match headers.get(header::AUTHORIZATION).and_then(|v| v.to_str().ok()) == Some(&format!("Bearer {}", site)) {
+                        true => ([(header::CONTENT_TYPE, "image/png")], site).into_response(),
+                        false => StatusCode::UNAUTHORIZED.into_response(),
+                    }
+                }),
+            );
+        format!("http://{}", serve(app).await)
+    }
+
+    /// Concurrent page and resource requests while the session base URL and the credentials
+    /// change under them: every page is rewritten against the base URL it was fetched from,
+    /// and nothing deadlocks
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn concurrent_requests_see_consistent_state() {
+        let sites = [("a", stress_site("a").await), ("b", stress_site("b").await)];
+        let state = ProxyState::default();
+        state.base_url.store(Arc::new(Url::parse(&format!("{}/page", sites[0].1)).unwrap()));
+        let done = Arc::new(AtomicBool::new(false));
+
+        let mutator = {
+            let (state, sites, done) = (state.clone(), sites.clone(), done.clone());
+            tokio::spawn(async move {
+                let mut flips = 0usize;
+                while !done.load(Ordering::Relaxed) {
+                    let (site, origin) = &sites[flips % 2];
+                    state.base_url.store(Arc::new(Url::parse(&format!("{}/page", origin)).unwrap()));
+                    if flips.is_multiple_of(3) {
+                        state.auth_credentials.remove(origin);
+                    } else {
+                        state.auth_credentials.insert(origin.clone(), AuthMethod::Bearer(site.to_string()));
+                    }
+                    flips += 1;
+                    tokio::task::yield_now().await;
+                }
+                flips
+            })
+        };
+
+        let mut requesters = Vec::new();
+        for task in 0..8 {
+            let (state, sites) = (state.clone(), sites.clone());
+            requesters.push(tokio::spawn(async move {
+                for i in 0..10 {
+                    let response = through_proxy(&state).await;
+                    assert_eq!(response.status(), StatusCode::OK);
+                    let page = body_text(response).await;
+                    let (site, origin) = sites.iter().find(|(site, _)| page.contains(&format!("site {}", site))).expect("page of neither site");
+                    assert_eq!(attributes_by_id(&page)["img"]["src"], proxied(&format!("{}/img.png", origin)), "site {} page rewritten against another base", site);
+
+                    let (site, origin) = &sites[(task + i) % 2];
+                    let response = fetch_resource(&format!("{}/img.png", origin), "image/*", &state).await;
+                    // Either the image, or the auth page when its credentials were just removed
+                    assert_eq!(response.status(), StatusCode::OK);
+                    let body = body_text(response).await;
+                    assert!(body == *site || body.contains(&format!("Authentication required for {}", origin)), "{}", body);
+                }
+            }));
+        }
+
+        let all = async {
+            for requester in requesters {
+                requester.await.unwrap();
+            }
+        };
+        tokio::time::timeout(std::time::Duration::from_secs(60), all).await.expect("requests deadlocked");
+        done.store(true, Ordering::Relaxed);
+        assert!(mutator.await.unwrap() > 0);
+    }
+
+    #[test]
+    fn signed_urls_are_recognized_by_their_query() {
+        let cases = [
+            ("https://s3.example/a.png?X-Amz-Expires=300&X-Amz-Signature=ab12", true),
+            ("https://cdn.example/a.png?token=abc&expires=1700000000", true),
+            ("https://cdn.example/a.png?Policy=eyJ&Signature=Zm9v&Key-Pair-Id=K2", true),
+            ("https://akamai.example/a.png?hdnts=exp%3D1~acl%3D%2F*~hmac%3Dab", true),
+            ("https://cdn.example/a.png?SIG=1", true),
+            ("https://cdn.example/a.png?w=800&h=600", false),
+            ("https://cdn.example/a.png?signature_version=2", false),
+            ("https://cdn.example/token/a.png", false),
+            ("https://cdn.example/a.png#token=abc", false),
+        ];
+        for (url, signed) in cases {
+            assert_eq!(is_signed_url(&Url::parse(url).unwrap()), signed, "{}", url);
+        }
+    }
+
+    /// Query strings of signed URLs as CDNs write them: escapes of base64 signatures, `+`,
+    /// `~`, unescaped `=` and `/` in values
+    const SIGNED_QUERIES: &[&str] = &[
+        "X-Amz-Algorithm=AWS4-HMAC-SHA256&X-Amz-Credential=AKIA%2F20240101%2Fus-east-1%2Fs3%2Faws4_request&X-Amz-Date=20240101T000000Z&X-Amz-Expires=300&X-Amz-SignedHeaders=host&X-Amz-Signature=9f2c0e1d",
+        "token=a+b%2Bc%3D%3D&expires=1700000000",
+        "expires=1700000000&token=Zm9v%2FYmFy",
+        "Policy=eyJTdGF0ZW1lbnQiOlt7~&Signature=Zm9v~YmFy_&Key-Pair-Id=K2JCJMDEHXQW5F",
+        "hdnts=exp=1700000000~acl=/images/*~hmac=0a1b2c",
+        "sig=%7Eabc&w=800&W=801&sig=dup",
+        "x-goog-signature=AB%2fcd&X-Goog-Date=20240101T000000Z",
+    ];
+
+    /// Raw query and Referer of the requests a test server received
+    type Received = Arc<Mutex<Vec<(String, Option<String>)>>>;
+
+    /// Serves `page` at `/page` and `/signed.png`, recording the raw query and Referer of each
+    /// image request. With `reject_referer`, requests carrying a Referer get a 403.
+    async fn signed_cdn(page: String, requests: Received, reject_referer: bool) -> String {
+        let app = Router::new().route("/page", get(move || async move { Html(page) })).route(
+            "/signed.png",
+            get(move |RawQuery(query): RawQuery, headers: HeaderMap| async move {
+                let referer = headers.get(header::REFERER).and_then(|v| v.to_str().ok()).map(str::to_string);
+                let rejected = reject_referer && referer.is_some();
+                requests.lock().unwrap().push((query.unwrap_or_default(), referer));
+                match rejected {
+                    true => StatusCode::FORBIDDEN.into_response(),
+                    false => ([(header::CONTENT_TYPE, "image/png")], "png").into_response(),
+                }
+            }),
+        );
+        format!("http://{}", serve(app).await)
+    }
+
+    /// Requests a proxied URL as written in a rewritten page
+    async fn follow_proxied(src: &str, state: &ProxyState) -> Response {
+        let uri: axum::http::Uri = src.trim_start_matches(PROXY_BASE).parse().unwrap();
+        let Query(query) = Query::<HashMap<String, String>>::try_from_uri(&uri).unwrap();
+        let request = Request::builder().uri(uri).header(header::ACCEPT, "image/*").body(Body::empty()).unwrap();
+        proxy_resource_handler(Query(query), State(state.clone()), request).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn signed_queries_reach_upstream_byte_for_byte() {
+        let images: String = SIGNED_QUERIES
+            .iter()
+            .enumerate()
+            .map(|(i, query)| format!(r#"<img id="i{}" src="/signed.png?{}">"#, i, query.replace('&', "&amp;")))
+            .collect();
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let cdn = signed_cdn(format!("<html><body>{}</body></html>", images), requests.clone(), false).await;
+        let state = ProxyState::default();
+        state.base_url.store(Arc::new(Url::parse(&format!("{}/page", cdn)).unwrap()));
+
+        let attributes = attributes_by_id(&body_text(through_proxy(&state).await).await);
+        for (i, query) in SIGNED_QUERIES.iter().enumerate() {
+            let src = &attributes[&format!("i{}", i)]["src"];
+            let response = follow_proxied(src, &state).await;
+            assert_eq!(response.status(), StatusCode::OK, "{}", src);
+            let (received, referer) = requests.lock().unwrap().pop().unwrap();
+            assert_eq!(received, *query);
+            assert!(referer.is_some());
+        }
+        let report = state.proxy_stats.report();
+        assert_eq!((report.signed_with_referer, report.signed_retried_without_referer, report.signed_failed), (SIGNED_QUERIES.len() as u64, 0, 0));
+    }
+
+    #[tokio::test]
+    async fn signed_urls_rejected_with_a_referer_are_retried_without() {
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let cdn = signed_cdn(String::new(), requests.clone(), true).await;
+        let state = ProxyState::default();
+        state.base_url.store(Arc::new(Url::parse("https://news.example/article").unwrap()));
+        let signed = format!("{}/signed.png?{}", cdn, SIGNED_QUERIES[1]);
+
+        // Retried once without Referer, same query
+        let response = fetch_resource(&signed, "image/*", &state).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let sent = std::mem::take(&mut *requests.lock().unwrap());
+        assert_eq!(sent.len(), 2);
+        assert_eq!(sent[0], (SIGNED_QUERIES[1].to_string(), Some("https://news.example/article".to_string())));
+        assert_eq!(sent[1], (SIGNED_QUERIES[1].to_string(), None));
+        assert_eq!(state.proxy_stats.report().signed_retried_without_referer, 1);
+
+        // Asked for no Referer: a single request
+        let response = follow_proxied(&format!("{}&referer=", proxied(&signed)), &state).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(std::mem::take(&mut *requests.lock().unwrap()), vec![(SIGNED_QUERIES[1].to_string(), None)]);
+        assert_eq!(state.proxy_stats.report().signed_without_referer, 1);
+
+        // Unsigned URLs keep the 403, without a retry
+        let unsigned = format!("{}/signed.png?w=800", cdn);
+        let response = fetch_resource(&unsigned, "image/*", &state).await;
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        assert_eq!(requests.lock().unwrap().len(), 1);
+
+        let report = state.proxy_stats.report();
+        assert_eq!((report.signed_with_referer, report.signed_failed), (0, 0));
+    }
+
+    #[tokio::test]
+    async fn signed_urls_rejected_either_way_count_as_failed() {
+        let app = Router::new().route("/signed.png", get(|| async { StatusCode::FORBIDDEN }));
+        let cdn = format!("http://{}", serve(app).await);
+        let state = ProxyState::default();
+        state.base_url.store(Arc::new(Url::parse("https://news.example/article").unwrap()));
+
+        let response = fetch_resource(&format!("{}/signed.png?{}", cdn, SIGNED_QUERIES[0]), "image/*", &state).await;
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        let report = state.proxy_stats.report();
+        assert_eq!((report.signed_failed, report.signed_retried_without_referer), (1, 0));
+    }
+
+    #[test]
+    fn css_strings_end_at_their_own_unescaped_quote() {
+        let cases = [
+            (r#""a.png" 1x"#, 7),
+            (r#"'a.png'"#, 7),
+            (r#""it's" x"#, 6),
+            (r#"'say "hi"'"#, 10),
+            (r#""a\"b" x"#, 6),
+            (r#""a\\" x"#, 5),
+            (r#""unterminated"#, 13),
+            (r#""é.png" 2x"#, 8),
+        ];
+        for (text, len) in cases {
+            assert_eq!(css_string_len(text), len, "{}", text);
+        }
+    }
+
+    #[test]
+    fn css_tokens_keep_their_quotes() {
+        let base = Url::parse("https://cdn.example/css/site.css").unwrap();
+        let target = proxied("https://cdn.example/css/a.png");
+        assert_eq!(proxied_css_token("a.png", &base, PROXY_BASE), Some(target.clone()));
+        assert_eq!(proxied_css_token("\"a.png\"", &base, PROXY_BASE), Some(format!("\"{}\"", target)));
+        assert_eq!(proxied_css_token("'a.png'", &base, PROXY_BASE), Some(format!("'{}'", target)));
+        assert_eq!(proxied_css_token("'a.png", &base, PROXY_BASE), Some(format!("'{}'", target)));
+        for kept in ["data:image/png;base64,AAAA", "'#filter'", "\"https://other.example/a.png\"", "''"] {
+            assert_eq!(proxied_css_token(kept, &base, PROXY_BASE), None, "{}", kept);
+        }
+    }
+
+    #[test]
+    fn css_urls_are_rewritten_in_every_form() {
+        let base = Url::parse("https://cdn.example/assets/css/site.css").unwrap();
+        let p = proxied;
+        let cases = [
+            ("a{background:url(img/a.png)}".to_string(), format!("a{{background:url({})}}", p("https://cdn.example/assets/css/img/a.png"))),
+            ("a{background:url( \"../b.png\" )}".into(), format!("a{{background:url( \"{}\" )}}", p("https://cdn.example/assets/b.png"))),
+            ("a{background:URL('c.png')}".into(), format!("a{{background:URL('{}')}}", p("https://cdn.example/assets/css/c.png"))),
+            (
+                "a{background-image:image-set(url(a.png) 1x, url(b.png) 2x)}".into(),
+                format!("a{{background-image:image-set(url({}) 1x, url({}) 2x)}}", p("https://cdn.example/assets/css/a.png"), p("https://cdn.example/assets/css/b.png")),
+            ),
+            (
+                r#"a{background-image:image-set("a.avif" type("image/avif") 1x, 'b.png' 2x)}"#.into(),
+                format!(r#"a{{background-image:image-set("{}" type("image/avif") 1x, '{}' 2x)}}"#, p("https://cdn.example/assets/css/a.avif"), p("https://cdn.example/assets/css/b.png")),
+            ),
+            ("a{background:-webkit-image-set('a.png' 1x)}".into(), format!("a{{background:-webkit-image-set('{}' 1x)}}", p("https://cdn.example/assets/css/a.png"))),
+            (":root{--hero: url(/hero.jpg);--n:1}".into(), format!(":root{{--hero: url({});--n:1}}", p("https://cdn.example/hero.jpg"))),
+            (
+                "@media (min-width:600px){@supports (display:grid){.a{background:url(m.png)}}}".into(),
+                format!("@media (min-width:600px){{@supports (display:grid){{.a{{background:url({})}}}}}}", p("https://cdn.example/assets/css/m.png")),
+            ),
+            ("@import \"print.css\" print;".into(), format!("@import \"{}\" print;", p("https://cdn.example/assets/css/print.css"))),
+            ("@import url(//fonts.example/f.css);".into(), format!("@import url({});", p("https://fonts.example/f.css"))),
+            ("a{background:url(é.png)}".into(), format!("a{{background:url({})}}", p("https://cdn.example/assets/css/%C3%A9.png"))),
+            // Unterminated at the end of the input
+            ("a{background:url(x.png".into(), format!("a{{background:url({}", p("https://cdn.example/assets/css/x.png"))),
+        ];
+        for (css, expected) in cases {
+            assert_eq!(rewrite_css_urls(&css, &base, PROXY_BASE), expected, "{}", css);
+        }
+
+        // Copied as is
+        for css in [
+            "/* url(commented.png) */a{}",
+            r#"a::before{content:"url(x.png)"}"#,
+            "a{background:my-url(a.png)}",
+            "a{font-family:'image-set(a.png)'}",
+            "a{background:url(data:image/png;base64,AAAA)}",
+            "a{background:url(https://other.example/a.png)}",
+            "a{filter:url(#blur)}",
+            "a{background:url()}",
+            "a{width:calc(100% - (2 * 1px))}",
+            "a{background:url(",
+        ] {
+            assert_eq!(rewrite_css_urls(css, &base, PROXY_BASE), css);
+        }
     }
-}
\ No newline at end of file
+
+    #[tokio::test]
+    async fn inline_css_resolves_against_the_page_and_stylesheets_against_their_url() {
+        let html = r#"<html><head><style>.h{background:url(img/h.png)}</style></head>
+<body><div id="d" style="background-image:image-set('img/d.png' 1x, &quot;img/d2.png&quot; 2x)">x</div></body></html>"#;
+        let page = Arc::new(Mutex::new((html.to_string(), HeaderMap::new())));
+        let (state, base) = proxy_for(page).await;
+        let rewritten = body_text(through_proxy(&state).await).await;
+
+        let join = |path: &str| base.join(path).unwrap().to_string();
+        assert!(rewritten.contains(&format!(".h{{background:url({})}}", proxied(&join("img/h.png")))), "{}", rewritten);
+        let style = &attributes_by_id(&rewritten)["d"]["style"];
+        assert_eq!(*style, format!("background-image:image-set('{}' 1x, \"{}\" 2x)", proxied(&join("img/d.png")), proxied(&join("img/d2.png"))));
+
+        let app = Router::new().route("/static/css/site.css", get(|| async { ([(header::CONTENT_TYPE, "text/css")], "a{background:url(../img/a.png)}") }));
+        let origin = format!("http://{}", serve(app).await);
+        let response = fetch_resource(&format!("{}/static/css/site.css", origin), "text/css", &state).await;
+        assert_eq!(body_text(response).await, format!("a{{background:url({})}}", proxied(&format!("{}/static/img/a.png", origin))));
+    }
+
+    #[test]
+    fn referrer_policies_pick_the_referer() {
+        let article = Url::parse("https://news.example/2024/story?id=7#comments").unwrap();
+        let same_origin = Url::parse("https://news.example/img/a.png").unwrap();
+        let other_port = Url::parse("https://news.example:8443/img/a.png").unwrap();
+        let third_party = Url::parse("https://ads.example/pixel.gif").unwrap();
+        let full = Some("https://news.example/2024/story?id=7".to_string());
+        let origin = Some("https://news.example/".to_string());
+
+        let cases = [
+            (ReferrerPolicy::Full, [full.clone(), full.clone(), full.clone()]),
+            (ReferrerPolicy::OriginOnly, [full.clone(), origin.clone(), origin]),
+            (ReferrerPolicy::SameOriginOnly, [full.clone(), None, None]),
+            (ReferrerPolicy::NoReferrer, [None, None, None]),
+        ];
+        for (policy, [to_same_origin, to_other_port, to_third_party]) in cases {
+            assert_eq!(policy.referer_for(&article, &same_origin), to_same_origin, "{:?}", policy);
+            assert_eq!(policy.referer_for(&article, &other_port), to_other_port, "{:?}", policy);
+            assert_eq!(policy.referer_for(&article, &third_party), to_third_party, "{:?}", policy);
+        }
+        assert_eq!(ReferrerPolicy::default(), ReferrerPolicy::Full);
+        for (policy, name) in [(ReferrerPolicy::Full, "full"), (ReferrerPolicy::OriginOnly, "origin-only"), (ReferrerPolicy::SameOriginOnly, "same-origin-only"), (ReferrerPolicy::NoReferrer, "none")] {
+            assert_eq!(serde_json::from_value::<ReferrerPolicy>(serde_json::json!(name)).unwrap(), policy);
+        }
+    }
+
+    /// Serves `paths`, recording the path and Referer of each request
+    async fn referer_recorder(paths: &[&'static str], requests: Received) -> String {
+        let mut app = Router::new();
+        for &path in paths {
+            let requests = requests.clone();
+            app = app.route(
+                path,
+                get(move |headers: HeaderMap| async move {
+                    let referer = headers.get(header::REFERER).and_then(|v| v.to_str().ok()).map(str::to_string);
+                    requests.lock().unwrap().push((path.to_string(), referer));
+                    Html("<html><body>ok</body></html>")
+                }),
+            );
+        }
+        format!("http://{}", serve(app).await)
+    }
+
+    #[tokio::test]
+    async fn upstream_hosts_receive_the_referer_of_the_policy() {
+        let requests: Received = Arc::new(Mutex::new(Vec::new()));
+        let site = referer_recorder(&["/story", "/img/a.png"], requests.clone()).await;
+        let third_party = referer_recorder(&["/pixel.gif"], requests.clone()).await;
+        let article = format!("{}/story?id=7", site);
+
+        let full = Some(article.clone());
+        let origin = Some(format!("{}/", site));
+        let cases = [
+            (ReferrerPolicy::Full, [full.clone(), full.clone(), full.clone(), full.clone()]),
+            (ReferrerPolicy::OriginOnly, [full.clone(), full.clone(), full.clone(), origin]),
+            (ReferrerPolicy::SameOriginOnly, [full.clone(), full.clone(), full.clone(), None]),
+            (ReferrerPolicy::NoReferrer, [None, None, None, None]),
+        ];
+        for (policy, expected) in cases {
+            let state = ProxyState::default();
+            state.base_url.store(Arc::new(Url::parse(&format!("{}#comments", article)).unwrap()));
+            state.referrer_policy.store(Arc::new(policy));
+
+            let request = Request::builder().uri("/story?inject=0").header(header::REFERER, "http://localhost:3000/").body(Body::empty()).unwrap();
+            proxy_handler(Path("story".to_string()), State(state.clone()), request).await.unwrap();
+            let request = Request::builder().uri("/img/a.png").body(Body::empty()).unwrap();
+            proxy_handler(Path("img/a.png".to_string()), State(state.clone()), request).await.unwrap();
+            fetch_resource(&format!("{}/img/a.png", site), "image/*", &state).await;
+            fetch_resource(&format!("{}/pixel.gif", third_party), "image/*", &state).await;
+
+            let received = std::mem::take(&mut *requests.lock().unwrap());
+            let paths: Vec<&str> = received.iter().map(|(path, _)| path.as_str()).collect();
+            assert_eq!(paths, ["/story", "/img/a.png", "/img/a.png", "/pixel.gif"]);
+            let referers: Vec<Option<String>> = received.into_iter().map(|(_, referer)| referer).collect();
+            assert_eq!(referers, expected, "{:?}", policy);
+        }
+    }
+
+    #[tokio::test]
+    async fn each_response_class_gets_its_cache_control() {
+        let app = Router::new()
+            .route("/page", get(|| async { ([(header::ETAG, "\"page\"")], Html("<html><body><p>article</p></body></html>")) }))
+            .route("/style.css", get(|| async { ([(header::CONTENT_TYPE, "text/css"), (header::ETAG, "\"css\"")], "body { background: url(bg.png) }") }))
+            .route(
+                "/photo.png",
+                get(|| async { ([(header::CONTENT_TYPE, "image/png"), (header::CACHE_CONTROL, "public, max-age=31536000"), (header::ETAG, "\"photo\"")], "png") }),
+            )
+            .route("/plain.png", get(|| async { ([(header::CONTENT_TYPE, "image/png")], "png") }))
+            .route("/members.png", get(|| async { StatusCode::UNAUTHORIZED }));
+        let origin = format!("http://{}", serve(app).await);
+        let state = ProxyState::default();
+        state.base_url.store(Arc::new(Url::parse(&format!("{}/page", origin)).unwrap()));
+
+        let page = through_proxy(&state).await;
+        assert_eq!(page.headers().get(header::CACHE_CONTROL).unwrap(), "private, max-age=60");
+        assert!(page.headers().get(header::ETAG).is_none());
+
+        for (path, cache_control, etag) in [
+            ("style.css", "private, max-age=60", None),
+            ("photo.png", "public, max-age=31536000", Some("\"photo\"")),
+            ("plain.png", "private, max-age=300", None),
+            ("members.png", "no-store", None),
+        ] {
+            let response = fetch_resource(&format!("{}/{}", origin, path), "*/*", &state).await;
+            assert_eq!(response.headers().get(header::CACHE_CONTROL).unwrap(), cache_control, "{}", path);
+            assert_eq!(response.headers().get(header::ETAG).map(|etag| etag.to_str().unwrap()), etag, "{}", path);
+        }
+
+        // Responses of the server itself that pick no class, like this 400, aren't stored
+        let port = start_proxy_server(state.clone()).await.unwrap();
+        let response = reqwest::get(format!("http://localhost:{}/proxy", port)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        assert_eq!(response.headers().get(header::CACHE_CONTROL).unwrap(), "no-store");
+    }
+
+    #[tokio::test]
+    async fn killed_proxy_server_restarts_on_its_port() {
+        use crate::supervisor::{TaskState, TaskStatus};
+
+        let state = ProxyState::default();
+        let mut transitions = state.supervisor.subscribe();
+        let port = start_proxy_server(state.clone()).await.unwrap();
+        async fn next_transition(transitions: &mut tokio::sync::broadcast::Receiver<TaskStatus>) -> TaskStatus {
+            tokio::time::timeout(std::time::Duration::from_secs(10), transitions.recv()).await.expect("no transition").unwrap()
+        }
+        assert_eq!(next_transition(&mut transitions).await.state, TaskState::Running);
+
+        // `/proxy` without a `url` is a 400 from the proxy itself. Connections aren't reused:
+        // the ones already accepted outlive the killed server.
+        let client = reqwest::Client::builder().pool_max_idle_per_host(0).build().unwrap();
+        let probe = format!("http://localhost:{}/proxy", port);
+        assert_eq!(client.get(&probe).send().await.unwrap().status(), StatusCode::BAD_REQUEST);
+
+        assert!(state.supervisor.abort(PROXY_TASK));
+        let restarting = next_transition(&mut transitions).await;
+        assert_eq!((restarting.state, restarting.restarts), (TaskState::Restarting, 1));
+        assert!(client.get(&probe).send().await.is_err(), "the killed server still answers");
+
+        let running = next_transition(&mut transitions).await;
+        assert_eq!((running.state, running.restarts), (TaskState::Running, 1));
+        assert!(running.last_error.unwrap().contains("cancelled"));
+        // `Running` is reported as the restarted task starts, before it has rebound the port
+        let mut answer = client.get(&probe).send().await;
+        for _ in 0..50 {
+            if answer.is_ok() {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+            answer = client.get(&probe).send().await;
+        }
+        assert_eq!(answer.unwrap().status(), StatusCode::BAD_REQUEST);
+        assert_eq!(state.supervisor.statuses()[0].state, TaskState::Running);
+    }
+}
@@ -0,0 +1,104 @@
+use crate::export::{html_to_blocks, plain_text, Block};
+use url::Url;
+
+/// Words below which readability formulas aren't meaningful
+const MIN_WORDS: usize = 100;
+
+/// Share of letters that must be Latin for syllable counting to make sense
+const MIN_LATIN_SHARE: f64 = 0.9;
+
+const VOWELS: &str = "aeiouyàáâãäåæèéêëìíîïòóôõöøùúûüýÿœ";
+
+/// Flesch–Kincaid metrics of an article's prose
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ReadingLevel {
+    /// Flesch–Kincaid grade level (US school years), at least 0
+    pub grade: f64,
+    /// Flesch reading ease: higher is easier, 60–70 is plain English
+    pub flesch_score: f64,
+}
+
+/// Prose of the article, one entry per paragraph-like block. Headings, code and captions are
+/// left out since they aren't sentences.
+pub(crate) fn prose_blocks(blocks: &[Block], out: &mut Vec<String>) {
+    for block in blocks {
+        match block {
+            Block::Paragraph(content) => out.push(plain_text(content)),
+            Block::List { items, .. } => items.iter().for_each(|item| prose_blocks(item, out)),
+            Block::Quote(blocks) => prose_blocks(blocks, out),
+            Block::Heading { .. } | Block::Code { .. } | Block::Image { .. } | Block::Rule => {}
+        }
+    }
+}
+
+/// Syllables of an English-like word: vowel groups, minus a silent final `e`
+fn count_syllables(word: &str) -> usize {
+    let word = word.to_lowercase();
+    let mut groups = 0;
+    let mut previous_vowel = false;
+    for c in word.chars() {
+        let vowel = VOWELS.contains(c);
+        if vowel && !previous_vowel {
+            groups += 1;
+        }
+        previous_vowel = vowel;
+    }
+    let silent_e = word.ends_with('e') && !word.ends_with("le") && !word.ends_with("ee") && groups > 1;
+    (groups - usize::from(silent_e)).max(1)
+}
+
+/// Sentences of a block: runs of terminal punctuation, and at least one when it has words
+fn count_sentences(text: &str) -> usize {
+    let mut sentences = 0;
+    let mut in_terminator = false;
+    for c in text.chars() {
+        let terminator = matches!(c, '.' | '!' | '?' | '…');
+        if terminator && !in_terminator {
+            sentences += 1;
+        }
+        in_terminator = terminator;
+    }
+    // A block not ending with punctuation still ends its last sentence
+    let trailing = text.trim_end().chars().last().is_some_and(|c| !matches!(c, '.' | '!' | '?' | '…' | '"' | '\'' | ')' | '»' | '”'));
+    sentences + usize::from(trailing)
+}
+
+/// Reading level of extracted article HTML. `None` for content too short for the formulas
+/// to mean anything, or not mostly in Latin script.
+pub fn reading_level(html: &str) -> Option<ReadingLevel> {
+    let base = Url::parse("about:blank").ok()?;
+    let mut blocks = Vec::new();
+    prose_blocks(&html_to_blocks(html, &base), &mut blocks);
+
+    let letters: Vec<char> = blocks.iter().flat_map(|text| text.chars()).filter(|c| c.is_alphabetic()).collect();
+    let latin = letters.iter().filter(|c| (**c as u32) <= 0x024F).count();
+    if letters.is_empty() || (latin as f64) < letters.len() as f64 * MIN_LATIN_SHARE {
+        return None;
+    }
+
+    let (mut words, mut syllables, mut sentences) = (0usize, 0usize, 0usize);
+    for text in &blocks {
+        let block_words: Vec<&str> = text
+            .split_whitespace()
+            .map(|word| word.trim_matches(|c: char| !c.is_alphanumeric()))
+            .filter(|word| word.chars().any(char::is_alphabetic))
+            .collect();
+        if block_words.is_empty() {
+            continue;
+        }
+        words += block_words.len();
+        syllables += block_words.iter().map(|word| count_syllables(word)).sum::<usize>();
+        sentences += count_sentences(text).max(1);
+    }
+    if words < MIN_WORDS {
+        return None;
+    }
+
+    let words_per_sentence = words as f64 / sentences as f64;
+    let syllables_per_word = syllables as f64 / words as f64;
+    let round = |value: f64| (value * 10.0).round() / 10.0;
+    Some(ReadingLevel {
+        grade: round((0.39 * words_per_sentence + 11.8 * syllables_per_word - 15.59).max(0.0)),
+        flesch_score: round(206.835 - 1.015 * words_per_sentence - 84.6 * syllables_per_word),
+    })
+}
@@ -0,0 +1,239 @@
+use crate::compression::StoredText;
+use crate::maintenance::StoreCheck;
+use crate::shared::{
+    article_metadata, extract_content, extract_share_metadata, finish_extraction, host_in_domain, host_of_domain_key, structured_article, ArticleOptions, ArticleResult,
+    Deadline, MutationReport, ProxyState,
+};
+use crate::provenance::{ProvenanceBuilder, ProvenanceSource};
+use crate::site_config;
+use crate::extractors;
+use serde::Serialize;
+use std::collections::{HashMap, HashSet, VecDeque};
+use url::Url;
+
+/// Event emitted when a page shown through the iframe fallback turns out to be extractable
+/// once rendered. The payload is a `RenderedExtraction`.
+pub const EXTRACTION_AVAILABLE_EVENT: &str = "extraction-now-available";
+
+/// Articles extracted from rendered pages kept for `fetch_article`, oldest evicted first
+const MAX_RENDERED_ARTICLES: usize = 100;
+
+/// Extractions of rendered pages, and how often they succeed per host
+#[derive(Default)]
+pub struct RenderedStore {
+    /// Extracted content keyed by article URL
+    articles: HashMap<String, StoredText>,
+    /// Keys of `articles`, oldest first
+    order: VecDeque<String>,
+    hosts: HashMap<String, RenderedHostStats>,
+}
+
+impl RenderedStore {
+    fn evict_overflow(&mut self) {
+        while self.order.len() > MAX_RENDERED_ARTICLES {
+            if let Some(oldest) = self.order.pop_front() {
+                self.articles.remove(&oldest);
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+pub struct RenderedHostStats {
+    /// Rendered pages of fallback articles that went through extraction
+    pub attempts: u64,
+    /// Attempts that passed the extraction thresholds
+    pub successes: u64,
+}
+
+/// Per-domain outcome of rendered extractions, for suggesting reader view on domains where
+/// the fallback is usually unnecessary
+#[derive(Debug, Clone, Serialize)]
+pub struct RenderedDomainStats {
+    pub domain: String,
+    pub attempts: u64,
+    pub successes: u64,
+    /// `successes / attempts`
+    pub success_rate: f64,
+}
+
+/// Article extracted from the rendered page of `url`
+#[derive(Debug, Clone, Serialize)]
+pub struct RenderedExtraction {
+    pub url: String,
+    pub article: ArticleResult,
+}
+
+/// Extraction of the rendered page of `url`, if one succeeded
+pub fn cached_article(url: &str, state: &ProxyState) -> Option<String> {
+    let content = state.rendered.lock().unwrap().articles.get(url)?.decode();
+    content.map_err(|e| println!("[rendered::cached_article] Ignoring unreadable extraction of {}: {}", url, e)).ok()
+}
+
+fn record_outcome(url: &str, url_obj: &Url, content: Option<&String>, state: &ProxyState) {
+    let config = **state.compression.load();
+    let mut store = state.rendered.lock().unwrap();
+    if let Some(host) = url_obj.host_str() {
+        let stats = store.hosts.entry(host.to_ascii_lowercase()).or_default();
+        stats.attempts += 1;
+        stats.successes += u64::from(content.is_some());
+    }
+
+    let Some(content) = content else {
+        return;
+    };
+    if store.articles.insert(url.to_string(), StoredText::encode(content, &config)).is_none() {
+        store.order.push_back(url.to_string());
+    }
+    store.evict_overflow();
+}
+
+/// Runs extraction on the HTML of the rendered fallback page of `url` (after JS ran), with the
+/// same thresholds as `fetch_article`. On success the article is cached, so the next
+/// `fetch_article` of `url` returns it without going through the fallback again.
+pub fn logic_extract_from_rendered(
+    url: String,
+    html: String,
+    options: ArticleOptions,
+    state: &ProxyState,
+) -> Result<Option<RenderedExtraction>, String> {
+    let url_obj = Url::parse(&url).map_err(|e| e.to_string())?;
+    let site_config = site_config::config_for(&url_obj, state);
+
+    let deadline = Deadline::after_ms(options.deadline_ms);
+    let provenance = ProvenanceBuilder::default();
+    provenance.source(ProvenanceSource::RenderedIframe);
+    provenance.url(&url);
+    let share = extract_share_metadata(&html, &url_obj);
+    let extractor = extractors::run_for(&url_obj, state);
+    let content = extract_content(html, &url_obj, &options.readability_config(), site_config.as_ref(), &deadline, &provenance, &extractor)?;
+    extractors::record_comparison(&url_obj, &extractor, state);
+    record_outcome(&url, &url_obj, content.as_ref(), state);
+    if content.is_none() {
+        println!("[rendered::extract_from_rendered] Rendered page of {} is still not extractable", url);
+        return Ok(None);
+    }
+
+    println!("[rendered::extract_from_rendered] Rendered page of {} is extractable", url);
+    let mut extracted = finish_extraction(&url, content, None, &options, &deadline, &provenance, state)?;
+    extracted.metadata = article_metadata(share, extractor.title(), extracted.content.as_deref(), &url_obj);
+    Ok(Some(RenderedExtraction { url, article: structured_article(extracted)? }))
+}
+
+/// Rendered extraction outcomes per host, most attempted first
+pub fn logic_get_rendered_extraction_stats(state: &ProxyState) -> Vec<RenderedDomainStats> {
+    let store = state.rendered.lock().unwrap();
+    let mut stats: Vec<RenderedDomainStats> = store
+        .hosts
+        .iter()
+        .map(|(host, stats)| RenderedDomainStats {
+            domain: host.clone(),
+            attempts: stats.attempts,
+            successes: stats.successes,
+            success_rate: if stats.attempts == 0 { 0.0 } else { stats.successes as f64 / stats.attempts as f64 },
+        })
+        .collect();
+    stats.sort_by(|a, b| b.attempts.cmp(&a.attempts).then_with(|| a.domain.cmp(&b.domain)));
+    stats
+}
+
+/// Maintenance: checks that the eviction order indexes exactly the cached articles, and that
+/// no host has more successes than attempts
+pub fn check_rendered_store(dry_run: bool, state: &ProxyState) -> StoreCheck {
+    let mut check = StoreCheck::new("rendered_articles");
+    let mut guard = state.rendered.lock().unwrap();
+    let store = &mut *guard;
+    check.checked = store.articles.len() + store.hosts.len();
+
+    let mut seen = HashSet::new();
+    let mut order: VecDeque<String> =
+        store.order.iter().filter(|url| store.articles.contains_key(*url) && seen.insert((*url).clone())).cloned().collect();
+    let stale = store.order.len() - order.len();
+    let mut unindexed: Vec<String> = store.articles.keys().filter(|url| !seen.contains(*url)).cloned().collect();
+    unindexed.sort();
+
+    if stale > 0 {
+        check.problem(format!("{} eviction entries don't match a cached article", stale), dry_run);
+    }
+    if !unindexed.is_empty() {
+        check.problem(format!("{} cached articles are missing from the eviction order", unindexed.len()), dry_run);
+    }
+    if !dry_run && (stale > 0 || !unindexed.is_empty()) {
+        order.extend(unindexed);
+        store.order = order;
+        store.evict_overflow();
+    }
+
+    for (host, stats) in store.hosts.iter_mut() {
+        if stats.successes > stats.attempts {
+            check.problem(format!("{}: {} successes for {} attempts", host, stats.successes, stats.attempts), dry_run);
+            if !dry_run {
+                stats.successes = stats.attempts;
+            }
+        }
+    }
+
+    check.sizes(store.articles.values().map(StoredText::stored_len).sum(), store.articles.values().map(StoredText::raw_len).sum());
+    check
+}
+
+/// Removes cached rendered extractions and outcome counters of `domain` and its subdomains
+pub fn clear_rendered_for_domain(domain: &str, dry_run: bool, state: &ProxyState) -> MutationReport {
+    let mut report = MutationReport::new(dry_run);
+    let host = host_of_domain_key(domain);
+    let mut store = state.rendered.lock().unwrap();
+
+    let articles: Vec<String> = store
+        .articles
+        .keys()
+        .filter(|url| {
+            Url::parse(url)
+                .ok()
+                .and_then(|url| url.host_str().map(str::to_ascii_lowercase))
+                .is_some_and(|article_host| host_in_domain(&article_host, &host))
+        })
+        .cloned()
+        .collect();
+    for url in articles {
+        report.record("rendered_articles", url.clone(), Some(store.articles[&url].stored_len()));
+        if !dry_run {
+            store.articles.remove(&url);
+            store.order.retain(|key| key != &url);
+        }
+    }
+
+    let hosts: Vec<String> = store.hosts.keys().filter(|key| host_in_domain(key, &host)).cloned().collect();
+    for key in hosts {
+        report.record("rendered_stats", key.clone(), None);
+        if !dry_run {
+            store.hosts.remove(&key);
+        }
+    }
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::check_clear_for_domain;
+
+    #[test]
+    fn clears_the_rendered_articles_and_stats_of_a_domain() {
+        check_clear_for_domain(
+            |state, host| {
+                let url = format!("https://{}/article", host);
+                let mut store = state.rendered.lock().unwrap();
+                store.articles.insert(url.clone(), StoredText::encode("<p>text</p>", &state.compression.load()));
+                store.order.push_back(url);
+                store.hosts.insert(host.to_string(), RenderedHostStats::default());
+            },
+            clear_rendered_for_domain,
+            |state| {
+                let store = state.rendered.lock().unwrap();
+                assert_eq!(store.order.len(), store.articles.len());
+                let articles = store.articles.keys().map(|url| format!("article {}", url));
+                articles.chain(store.hosts.keys().map(|host| format!("stats {}", host))).collect()
+            },
+        );
+    }
+}
@@ -0,0 +1,210 @@
+use crate::maintenance::StoreCheck;
+use crate::shared::{host_in_domain, host_of_domain_key, MutationReport, ProxyState};
+use axum::body::Bytes;
+use reqwest::header::{self, HeaderMap, HeaderName};
+use reqwest::StatusCode;
+use std::collections::HashMap;
+use std::time::Instant;
+use url::Url;
+
+/// Largest body kept in the cache; bigger resources are streamed through uncached
+pub const MAX_CACHED_RESOURCE_SIZE: usize = 2 * 1024 * 1024;
+
+/// Bytes of cached bodies at most. The least recently used resources are evicted past this.
+const MAX_CACHE_SIZE: usize = 64 * 1024 * 1024;
+
+/// Headers of a `304` that replace the cached copy's
+const REVALIDATION_HEADERS: [HeaderName; 5] = [header::ETAG, header::LAST_MODIFIED, header::CACHE_CONTROL, header::EXPIRES, header::DATE];
+
+/// Upstream response of a proxied resource, kept to answer the next `304`
+pub struct CachedResource {
+    /// Upstream response headers, replayed when the copy is served
+    pub headers: HeaderMap,
+    pub body: Bytes,
+    last_used: Instant,
+}
+
+/// Proxied resources (stylesheets, fonts, images) keyed by upstream URL
+#[derive(Default)]
+pub struct ResourceCacheStore {
+    entries: HashMap<String, CachedResource>,
+    /// Bytes of the cached bodies
+    size: usize,
+}
+
+impl ResourceCacheStore {
+    fn remove(&mut self, key: &str) -> Option<CachedResource> {
+        let removed = self.entries.remove(key)?;
+        self.size -= removed.body.len();
+        Some(removed)
+    }
+
+    /// Drops the least recently used resources until `incoming` more bytes fit
+    fn make_room(&mut self, incoming: usize) {
+        while self.size + incoming > MAX_CACHE_SIZE {
+            let Some(oldest) = self.entries.iter().min_by_key(|(_, resource)| resource.last_used).map(|(key, _)| key.clone()) else {
+                break;
+            };
+            self.remove(&oldest);
+        }
+    }
+}
+
+/// Body of a response read for caching: whole when it fits in the cache, otherwise the bytes
+/// read so far and the rest of the response, to be streamed
+pub enum BufferedBody {
+    Whole(Bytes),
+    TooLarge(Vec<u8>, reqwest::Response),
+}
+
+fn cache_key(url: &Url) -> String {
+    let mut url = url.clone();
+    url.set_fragment(None);
+    url.to_string()
+}
+
+/// Whether upstream forbids keeping the response (`Cache-Control: no-store`)
+pub fn is_no_store(headers: &HeaderMap) -> bool {
+    headers
+        .get_all(header::CACHE_CONTROL)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .any(|directive| directive.trim().eq_ignore_ascii_case("no-store"))
+}
+
+/// Whether a response can be cached: a `200` with a validator to revalidate it with, not
+/// `no-store`, not varying on everything, and not announced larger than `MAX_CACHED_RESOURCE_SIZE`
+pub fn is_storable(status: StatusCode, headers: &HeaderMap) -> bool {
+    let has_validator = headers.contains_key(header::ETAG) || headers.contains_key(header::LAST_MODIFIED);
+    let varies_on_everything = headers.get_all(header::VARY).iter().any(|value| value.to_str().is_ok_and(|value| value.trim() == "*"));
+    let length = headers.get(header::CONTENT_LENGTH).and_then(|v| v.to_str().ok()).and_then(|v| v.trim().parse::<usize>().ok());
+    status == StatusCode::OK && has_validator && !is_no_store(headers) && !varies_on_everything && length.is_none_or(|length| length <= MAX_CACHED_RESOURCE_SIZE)
+}
+
+/// `If-None-Match` / `If-Modified-Since` headers revalidating the cached copy of `url`, if any
+pub fn conditional_headers(url: &Url, state: &ProxyState) -> Option<HeaderMap> {
+    let cache = state.resource_cache.lock().unwrap();
+    let resource = cache.entries.get(&cache_key(url))?;
+    let mut conditional = HeaderMap::new();
+    if let Some(etag) = resource.headers.get(header::ETAG) {
+        conditional.insert(header::IF_NONE_MATCH, etag.clone());
+    }
+    if let Some(last_modified) = resource.headers.get(header::LAST_MODIFIED) {
+        conditional.insert(header::IF_MODIFIED_SINCE, last_modified.clone());
+    }
+    Some(conditional).filter(|conditional| !conditional.is_empty())
+}
+
+/// Reads the body of a storable response, up to `MAX_CACHED_RESOURCE_SIZE` bytes (or `limit`,
+/// the proxy's body limit, when lower)
+pub async fn buffer_body(mut response: reqwest::Response, limit: usize) -> Result<BufferedBody, String> {
+    let limit = limit.min(MAX_CACHED_RESOURCE_SIZE);
+    let mut body = Vec::new();
+    while let Some(chunk) = response.chunk().await.map_err(|e| e.to_string())? {
+        body.extend_from_slice(&chunk);
+        if body.len() > limit {
+            return Ok(BufferedBody::TooLarge(body, response));
+        }
+    }
+    Ok(BufferedBody::Whole(Bytes::from(body)))
+}
+
+/// Caches a `200` response of `url`
+pub fn store(url: &Url, headers: HeaderMap, body: Bytes, state: &ProxyState) {
+    if body.len() > MAX_CACHED_RESOURCE_SIZE {
+        return;
+    }
+    let key = cache_key(url);
+    let mut cache = state.resource_cache.lock().unwrap();
+    cache.remove(&key);
+    cache.make_room(body.len());
+    cache.size += body.len();
+    cache.entries.insert(key, CachedResource { headers, body, last_used: Instant::now() });
+}
+
+/// Cached copy of `url` after upstream answered `304` to a conditional request, its headers
+/// updated with the validators and freshness the `304` carries. `None` if it was evicted meanwhile.
+pub fn revalidate(url: &Url, not_modified: &HeaderMap, state: &ProxyState) -> Option<(HeaderMap, Bytes)> {
+    let mut cache = state.resource_cache.lock().unwrap();
+    let resource = cache.entries.get_mut(&cache_key(url))?;
+    for name in &REVALIDATION_HEADERS {
+        if let Some(value) = not_modified.get(name) {
+            resource.headers.insert(name, value.clone());
+        }
+    }
+    resource.last_used = Instant::now();
+    Some((resource.headers.clone(), resource.body.clone()))
+}
+
+/// Cached copy of `url` as is, without revalidation (offline mode)
+pub fn cached(url: &Url, state: &ProxyState) -> Option<(HeaderMap, Bytes)> {
+    let mut cache = state.resource_cache.lock().unwrap();
+    let resource = cache.entries.get_mut(&cache_key(url))?;
+    resource.last_used = Instant::now();
+    Some((resource.headers.clone(), resource.body.clone()))
+}
+
+/// Drops the cached copy of `url` (upstream now says `no-store`)
+pub fn forget(url: &Url, state: &ProxyState) {
+    if state.resource_cache.lock().unwrap().remove(&cache_key(url)).is_some() {
+        println!("[resource_cache::forget] {} is no-store now, dropped", url);
+    }
+}
+
+/// Maintenance: recomputes the size of the cached bodies
+pub fn check_resource_cache(dry_run: bool, state: &ProxyState) -> StoreCheck {
+    let mut check = StoreCheck::new("resource_cache");
+    let mut cache = state.resource_cache.lock().unwrap();
+    check.checked = cache.entries.len();
+
+    let size: usize = cache.entries.values().map(|resource| resource.body.len()).sum();
+    if size != cache.size {
+        check.problem(format!("recorded size {} bytes, entries hold {} bytes", cache.size, size), dry_run);
+        if !dry_run {
+            cache.size = size;
+        }
+    }
+    check.size = size;
+    check
+}
+
+/// Drops the cached resources served from `domain` and its subdomains
+pub fn clear_resource_cache_for_domain(domain: &str, dry_run: bool, state: &ProxyState) -> MutationReport {
+    let mut report = MutationReport::new(dry_run);
+    let host = host_of_domain_key(domain);
+
+    let mut cache = state.resource_cache.lock().unwrap();
+    let matching: Vec<(String, usize)> = cache
+        .entries
+        .iter()
+        .filter(|(key, _)| Url::parse(key).ok().and_then(|url| url.host_str().map(|resource_host| host_in_domain(resource_host, &host))).unwrap_or(false))
+        .map(|(key, resource)| (key.clone(), resource.body.len()))
+        .collect();
+    for (key, size) in matching {
+        report.record("resource_cache", key.clone(), Some(size));
+        if !dry_run {
+            cache.remove(&key);
+        }
+    }
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::check_clear_for_domain;
+
+    #[test]
+    fn clears_the_resources_of_a_domain() {
+        check_clear_for_domain(
+            |state, host| store(&Url::parse(&format!("https://{}/style.css", host)).unwrap(), HeaderMap::new(), Bytes::from_static(b"body{}"), state),
+            clear_resource_cache_for_domain,
+            |state| {
+                let cache = state.resource_cache.lock().unwrap();
+                assert_eq!(cache.size, cache.entries.values().map(|resource| resource.body.len()).sum::<usize>());
+                cache.entries.keys().cloned().collect()
+            },
+        );
+    }
+}
@@ -3,17 +3,57 @@ use axum::{
     routing::{get, post},
     Router,
     response::IntoResponse,
-    http::StatusCode,
+    http::{header, StatusCode},
+    middleware,
 };
-use std::sync::{Arc, Mutex};
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
 use tower_http::services::{ServeDir, ServeFile};
 use tower_http::cors::CorsLayer;
 use serde::Deserialize;
 use shadcn_feed_reader::shared::{
-    ProxyState, LoginRequest,
-    logic_fetch_article, logic_fetch_raw_html, logic_perform_form_login
+    ProxyState, LoginRequest, ArticleOptions, ReadabilityConfig,
+    logic_clear_proxy_auth, logic_clear_cookies,
+    logic_extract_outline, logic_extract_metadata, logic_reveal_hidden_content, logic_fetch_article, logic_fetch_article_with_config, logic_fetch_article_classified, logic_fetch_article_segmented, logic_fetch_raw_html, logic_fetch_raw_html_transfer, logic_fetch_share_metadata,
+    logic_perform_form_login, logic_set_accept_language, logic_set_force_http1, logic_set_host_crawler_retry, logic_set_host_requires_rendering, logic_set_user_agent, logic_set_user_agent_pool, logic_set_user_agent_rotation,
+    logic_set_article_sanitization
 };
-use shadcn_feed_reader::proxy;
+use shadcn_feed_reader::proxy::{self, ReferrerPolicy, SnapshotConfig};
+use shadcn_feed_reader::transfer::{self, TransferMode};
+use shadcn_feed_reader::chaos::{self, ChaosProfileSpec};
+use shadcn_feed_reader::charts;
+use shadcn_feed_reader::preconnect;
+use shadcn_feed_reader::connectivity;
+use shadcn_feed_reader::images;
+use shadcn_feed_reader::icons::{self, FeedIconRequest};
+use shadcn_feed_reader::site_config;
+use shadcn_feed_reader::domains;
+use shadcn_feed_reader::catalog;
+use shadcn_feed_reader::export::{self, ExportFormat, FragmentFormat};
+use shadcn_feed_reader::single_file::{self, HtmlExportOptions};
+use shadcn_feed_reader::feed;
+use shadcn_feed_reader::messages::{self, ProtocolMessage, ScriptMessage};
+use shadcn_feed_reader::mixed_content;
+use shadcn_feed_reader::rendered;
+use shadcn_feed_reader::structure;
+use shadcn_feed_reader::maintenance::{self, MaintenanceOptions};
+use shadcn_feed_reader::prefetch::{self, PrefetchRequest, ReadingStats};
+use shadcn_feed_reader::startup::{self, Component};
+use shadcn_feed_reader::supervisor;
+use shadcn_feed_reader::compression::{self, CompressionConfig};
+use shadcn_feed_reader::extractors::{self, ExtractorBackend, ExtractorComparisonConfig};
+use shadcn_feed_reader::unread::{self, BadgeMode};
+use shadcn_feed_reader::credentials::{self, AuthMethod};
+use shadcn_feed_reader::caching;
+use shadcn_feed_reader::actions::{self, ArticleActionConfig};
+use shadcn_feed_reader::translation::{self, FeedTranslation, TranslationConfig, TRANSLATION_BUDGET_EXCEEDED};
+use shadcn_feed_reader::host_stats::{self, HostStatsExport};
+use shadcn_feed_reader::summary;
+use shadcn_feed_reader::element_filters::{self, ElementFiltersExport};
+use shadcn_feed_reader::latency::{self, AdaptiveTimeoutConfig, HostLatencyExport};
+use shadcn_feed_reader::page_reports;
+use shadcn_feed_reader::liveblog;
+use shadcn_feed_reader::versions;
 
 #[derive(Clone)]
 struct AppState {
@@ -26,6 +66,75 @@ struct UrlPayload {
     url: String,
 }
 
+#[derive(Deserialize)]
+struct FeedPayload {
+    url: String,
+    #[serde(default)]
+    max_items: Option<usize>,
+}
+
+#[derive(Deserialize)]
+struct ProxyUrlPayload {
+    url: String,
+    #[serde(default)]
+    referrer_policy: ReferrerPolicy,
+}
+
+#[derive(Deserialize)]
+struct ElementFilterPayload {
+    domain: String,
+    html: String,
+}
+
+#[derive(Deserialize)]
+struct RemoveElementFilterPayload {
+    domain: String,
+    selector: String,
+}
+
+#[derive(Deserialize)]
+struct PreviewFilterPayload {
+    domain: String,
+    selector: String,
+    #[serde(default)]
+    text_anchor: Option<String>,
+    sample_url: String,
+}
+
+#[derive(Deserialize)]
+struct SummaryPayload {
+    url: String,
+    max_sentences: usize,
+}
+
+#[derive(Deserialize)]
+struct FragmentPayload {
+    html: String,
+    format: FragmentFormat,
+}
+
+#[derive(Deserialize)]
+struct ArticlePayload {
+    url: String,
+    #[serde(default)]
+    options: ArticleOptions,
+}
+
+#[derive(Deserialize)]
+struct RawHtmlPayload {
+    url: String,
+    #[serde(default)]
+    timeout_secs: Option<u64>,
+    #[serde(default)]
+    user_agent: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct RawHtmlTransferPayload {
+    url: String,
+    transfer: Option<TransferMode>,
+}
+
 #[derive(Deserialize)]
 struct AuthPayload {
     domain: String,
@@ -33,9 +142,181 @@ struct AuthPayload {
     password: String,
 }
 
+#[derive(Deserialize)]
+struct AuthMethodPayload {
+    domain: String,
+    method: AuthMethod,
+}
+
+#[derive(Deserialize)]
+struct TrackVersionsPayload {
+    url: String,
+    #[serde(default)]
+    starred: bool,
+}
+
+#[derive(Deserialize)]
+struct SrcdocPayload {
+    srcdoc: String,
+    base_url: String,
+    #[serde(default)]
+    inject: bool,
+}
+
+#[derive(Deserialize)]
+struct RenderedHtmlPayload {
+    url: String,
+    html: String,
+    #[serde(default)]
+    options: ArticleOptions,
+}
+
+#[derive(Deserialize)]
+struct ArticleVersionPayload {
+    url: String,
+    timestamp: u64,
+}
+
+#[derive(Deserialize)]
+struct VersionBudgetPayload {
+    bytes: usize,
+}
+
 #[derive(Deserialize)]
 struct DomainPayload {
     domain: String,
+    #[serde(default)]
+    dry_run: bool,
+}
+
+#[derive(Deserialize)]
+struct ClearProxyAuthPayload {
+    domain: String,
+    #[serde(default)]
+    dry_run: bool,
+}
+
+#[derive(Deserialize)]
+struct ClearCookiesPayload {
+    domain: Option<String>,
+    #[serde(default)]
+    dry_run: bool,
+}
+
+#[derive(Deserialize)]
+struct DryRunPayload {
+    #[serde(default)]
+    dry_run: bool,
+}
+
+#[derive(Deserialize)]
+struct MaxBodySizePayload {
+    bytes: usize,
+}
+
+#[derive(Deserialize)]
+struct HtmlPayload {
+    html: String,
+}
+
+#[derive(Deserialize)]
+struct PageHtmlPayload {
+    html: String,
+    url: String,
+}
+
+#[derive(Deserialize)]
+struct SiteConfigPayload {
+    host: String,
+    rules: String,
+}
+
+#[derive(Deserialize)]
+struct UserAgentPayload {
+    #[serde(default)]
+    user_agent: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct UserAgentPoolPayload {
+    agents: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct AcceptLanguagePayload {
+    domain: String,
+    languages: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct HostPayload {
+    host: String,
+}
+
+#[derive(Deserialize)]
+struct HostRenderingPayload {
+    host: String,
+    requires_rendering: bool,
+}
+
+#[derive(Deserialize)]
+struct HostCrawlerRetryPayload {
+    host: String,
+    enabled: bool,
+}
+
+#[derive(Deserialize)]
+struct ForceHttp1Payload {
+    domain: Option<String>,
+    enabled: bool,
+}
+
+#[derive(Deserialize)]
+struct ChartSnapshotsPayload {
+    domain: Option<String>,
+    enabled: bool,
+}
+
+#[derive(Deserialize)]
+struct ArticleOpenedPayload {
+    url: String,
+    feed: String,
+    local_hour: u8,
+}
+
+#[derive(Deserialize)]
+struct EnabledPayload {
+    enabled: bool,
+}
+
+#[derive(Deserialize)]
+struct CatalogSearchPayload {
+    #[serde(default)]
+    query: String,
+    #[serde(default)]
+    category: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct MessagePayload {
+    message: serde_json::Value,
+    #[serde(default)]
+    transfer: Option<TransferMode>,
+}
+
+#[derive(Deserialize)]
+struct SessionPayload {
+    session_id: String,
+}
+
+#[derive(Deserialize)]
+struct ComponentPayload {
+    name: Component,
+}
+
+#[derive(Deserialize)]
+struct ChaosPayload {
+    profile: ChaosProfileSpec,
 }
 
 #[tokio::main]
@@ -45,26 +326,142 @@ async fn main() {
     let proxy_state = ProxyState::default();
     
     // Enable relative paths for the proxy since we serve it on the same origin
-    {
-        let mut relative_guard = proxy_state.use_relative_paths.lock().unwrap();
-        *relative_guard = true;
-    }
+    proxy_state.use_relative_paths.store(true, Ordering::Relaxed);
     
     // Note: We do NOT spawn a separate proxy server here.
     // Instead, we integrate the proxy logic directly into the main router.
 
+    // Components that fail are disabled rather than stopping the server (see /api/get_startup_report)
+    let report = startup::logic_run_startup(&[Component::FeedCatalog], &proxy_state).await;
+    if report.degraded {
+        eprintln!("Starting in degraded mode: {}", serde_json::to_string(&report.components).unwrap_or_default());
+    }
+
+    connectivity::start_probe(&proxy_state);
+
     let app_state = AppState {
         proxy_state,
     };
 
     let api_routes = Router::new()
         .route("/fetch_article", post(api_fetch_article))
+        .route("/fetch_article_with_config", post(api_fetch_article_with_config))
+        .route("/fetch_article_segmented", post(api_fetch_article_segmented))
+        .route("/summarize_article", post(api_summarize_article))
+        .route("/fetch_article_classified", post(api_fetch_article_classified))
+        .route("/fetch_article_markdown", post(api_fetch_article_markdown))
+        .route("/fetch_article_asciidoc", post(api_fetch_article_asciidoc))
+        .route("/fetch_article_rst", post(api_fetch_article_rst))
+        .route("/export_article_html", post(api_export_article_html))
+        .route("/convert_fragment", post(api_convert_fragment))
+        .route("/fetch_article_structure", post(api_fetch_article_structure))
+        .route("/fetch_live_blog", post(api_fetch_live_blog))
+        .route("/extract_outline", post(api_extract_outline))
+        .route("/extract_metadata", post(api_extract_metadata))
+        .route("/reveal_hidden_content", post(api_reveal_hidden_content))
+        .route("/probe_article_images", post(api_probe_article_images))
+        .route("/fetch_image_captions", post(api_fetch_image_captions))
+        .route("/fetch_feed", post(api_fetch_feed))
+        .route("/fetch_feed_icon", post(api_fetch_feed_icon))
+        .route("/load_site_configs", post(api_load_site_configs))
+        .route("/load_site_config", post(api_load_site_config))
+        .route("/fetch_share_metadata", post(api_fetch_share_metadata))
         .route("/fetch_raw_html", post(api_fetch_raw_html))
+        .route("/fetch_raw_html_transfer", post(api_fetch_raw_html_transfer))
         .route("/perform_form_login", post(api_perform_form_login))
         .route("/set_proxy_auth", post(api_set_proxy_auth))
+        .route("/set_auth", post(api_set_auth))
         .route("/clear_proxy_auth", post(api_clear_proxy_auth))
+        .route("/migrate_credentials", post(api_migrate_credentials))
+        .route("/resolve_credential_conflict", post(api_resolve_credential_conflict))
+        .route("/confirm_credential_migration", post(api_confirm_credential_migration))
+        .route("/rollback_credential_migration", post(api_rollback_credential_migration))
+        .route("/get_credential_audit", post(api_get_credential_audit))
+        .route("/clear_cookies", post(api_clear_cookies))
+        .route("/prune_transfers", post(api_prune_transfers))
+        .route("/get_domain_profile", post(api_get_domain_profile))
+        .route("/reset_domain", post(api_reset_domain))
+        .route("/forget_domain", post(api_forget_domain))
         .route("/start_proxy", post(api_start_proxy))
+        .route("/get_startup_report", post(api_get_startup_report))
+        .route("/retry_component", post(api_retry_component))
+        .route("/get_task_status", post(api_get_task_status))
         .route("/set_proxy_url", post(api_set_proxy_url))
+        .route("/set_max_body_size", post(api_set_max_body_size))
+        .route("/set_max_rewrite_size", post(api_set_max_rewrite_size))
+        .route("/set_user_agent", post(api_set_user_agent))
+        .route("/set_user_agent_pool", post(api_set_user_agent_pool))
+        .route("/set_user_agent_rotation", post(api_set_user_agent_rotation))
+        .route("/set_article_sanitization", post(api_set_article_sanitization))
+        .route("/set_accept_language", post(api_set_accept_language))
+        .route("/set_host_requires_rendering", post(api_set_host_requires_rendering))
+        .route("/set_host_crawler_retry", post(api_set_host_crawler_retry))
+        .route("/set_force_http1", post(api_set_force_http1))
+        .route("/set_chart_snapshots", post(api_set_chart_snapshots))
+        .route("/preconnect", post(api_preconnect))
+        .route("/set_offline_mode", post(api_set_offline_mode))
+        .route("/get_connectivity", post(api_get_connectivity))
+        .route("/list_queued_actions", post(api_list_queued_actions))
+        .route("/set_metered_mode", post(api_set_metered_mode))
+        .route("/set_mixed_content_strict", post(api_set_mixed_content_strict))
+        .route("/get_mixed_content_report", post(api_get_mixed_content_report))
+        .route("/proxy_compare_injection", post(api_proxy_compare_injection))
+        .route("/rewrite_srcdoc", post(api_rewrite_srcdoc))
+        .route("/get_proxy_stats", post(api_get_proxy_stats))
+        .route("/get_snapshot_config", post(api_get_snapshot_config))
+        .route("/set_snapshot_config", post(api_set_snapshot_config))
+        // No `perform_article_action`: it writes files on the host, so it stays desktop-only
+        .route("/get_article_action_config", post(api_get_article_action_config))
+        .route("/set_article_action_config", post(api_set_article_action_config))
+        .route("/search_feed_catalog", post(api_search_feed_catalog))
+        .route("/list_catalog_categories", post(api_list_catalog_categories))
+        .route("/update_catalog", post(api_update_catalog))
+        .route("/get_message_schema", post(api_get_message_schema))
+        .route("/validate_message", post(api_validate_message))
+        .route("/get_message_stats", post(api_get_message_stats))
+        .route("/get_page_report", post(api_get_page_report))
+        .route("/extract_from_rendered", post(api_extract_from_rendered))
+        .route("/get_rendered_extraction_stats", post(api_get_rendered_extraction_stats))
+        .route("/run_maintenance", post(api_run_maintenance))
+        .route("/article_opened", post(api_article_opened))
+        .route("/get_prefetch_plan", post(api_get_prefetch_plan))
+        .route("/set_smart_prefetch", post(api_set_smart_prefetch))
+        .route("/export_reading_stats", post(api_export_reading_stats))
+        .route("/import_reading_stats", post(api_import_reading_stats))
+        .route("/get_host_stats", post(api_get_host_stats))
+        .route("/export_host_stats", post(api_export_host_stats))
+        .route("/import_host_stats", post(api_import_host_stats))
+        .route("/get_host_latency_stats", post(api_get_host_latency_stats))
+        .route("/add_element_filter", post(api_add_element_filter))
+        .route("/remove_element_filter", post(api_remove_element_filter))
+        .route("/preview_filter", post(api_preview_filter))
+        .route("/export_element_filters", post(api_export_element_filters))
+        .route("/import_element_filters", post(api_import_element_filters))
+        .route("/set_adaptive_timeouts", post(api_set_adaptive_timeouts))
+        .route("/export_host_latency", post(api_export_host_latency))
+        .route("/import_host_latency", post(api_import_host_latency))
+        .route("/track_article_versions", post(api_track_article_versions))
+        .route("/untrack_article_versions", post(api_untrack_article_versions))
+        .route("/list_article_versions", post(api_list_article_versions))
+        .route("/get_article_version", post(api_get_article_version))
+        .route("/set_version_budget", post(api_set_version_budget))
+        .route("/get_compression_config", post(api_get_compression_config))
+        .route("/set_compression_config", post(api_set_compression_config))
+        .route("/get_translation_config", post(api_get_translation_config))
+        .route("/set_translation_config", post(api_set_translation_config))
+        .route("/get_feed_translation", post(api_get_feed_translation))
+        .route("/set_feed_translation", post(api_set_feed_translation))
+        .route("/translate_article", post(api_translate_article))
+        .route("/get_translation_usage", post(api_get_translation_usage))
+        .route("/get_unread_estimate", post(api_get_unread_estimate))
+        .route("/mark_items_read", post(api_mark_items_read))
+        .route("/set_unread_count", post(api_set_unread_count))
+        .route("/set_badge_mode", post(api_set_badge_mode))
+        .route("/get_extractor_comparison", post(api_get_extractor_comparison))
+        .route("/set_extractor_comparison_config", post(api_set_extractor_comparison_config))
+        .route("/set_domain_extractor", post(api_set_domain_extractor))
+        .route("/enable_chaos", post(api_enable_chaos))
+        .route("/disable_chaos", post(api_disable_chaos))
         .with_state(app_state.clone());
 
     let app = Router::new()
@@ -72,7 +469,14 @@ async fn main() {
         // Mount the proxy resource handler directly
         // This handles /proxy?url=... requests generated by the HTML rewriter
         .route("/proxy", get(proxy::proxy_resource_handler).options(proxy::cors_options_handler))
+        // One-shot retrieval of large payloads parked by *_transfer commands
+        .route("/transfer/:token", get(transfer::transfer_handler))
+        // Site icons resolved server-side (cookies, credentials), for the article header
+        .route("/icon", get(icons::icon_handler))
         .with_state(app_state.proxy_state.clone())
+        // Responses without a caching policy of their own (API results, errors) aren't stored;
+        // the frontend's static files below keep ServeDir's validators
+        .layer(middleware::map_response(caching::no_store_by_default))
         // Serve frontend static files
         .fallback_service(ServeDir::new("dist").fallback(ServeFile::new("dist/index.html")))
         .layer(CorsLayer::permissive());
@@ -86,75 +490,1104 @@ async fn main() {
 }
 
 async fn api_fetch_article(
-    Json(payload): Json<UrlPayload>,
+    State(state): State<AppState>,
+    Json(payload): Json<ArticlePayload>,
 ) -> impl IntoResponse {
-    match logic_fetch_article(payload.url).await {
-        Ok(content) => (StatusCode::OK, content),
-        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e),
+    match logic_fetch_article(payload.url, payload.options, &state.proxy_state).await {
+        Ok(article) => (StatusCode::OK, Json(article)).into_response(),
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e).into_response(),
     }
 }
 
-async fn api_fetch_raw_html(
+#[derive(Deserialize)]
+struct ArticleWithConfigPayload {
+    url: String,
+    #[serde(default)]
+    options: ArticleOptions,
+    #[serde(default)]
+    config: ReadabilityConfig,
+}
+
+async fn api_fetch_article_with_config(
     State(state): State<AppState>,
-    Json(payload): Json<UrlPayload>,
+    Json(payload): Json<ArticleWithConfigPayload>,
+) -> impl IntoResponse {
+    match logic_fetch_article_with_config(payload.url, payload.options, payload.config, &state.proxy_state).await {
+        Ok(article) => (StatusCode::OK, Json(article)).into_response(),
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e).into_response(),
+    }
+}
+
+async fn api_fetch_article_segmented(
+    State(state): State<AppState>,
+    Json(payload): Json<ArticlePayload>,
+) -> impl IntoResponse {
+    match logic_fetch_article_segmented(payload.url, payload.options, &state.proxy_state).await {
+        Ok(article) => (StatusCode::OK, Json(article)).into_response(),
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e).into_response(),
+    }
+}
+
+async fn api_summarize_article(
+    State(state): State<AppState>,
+    Json(payload): Json<SummaryPayload>,
+) -> impl IntoResponse {
+    match summary::logic_summarize_article(payload.url, payload.max_sentences, &state.proxy_state).await {
+        Ok(summary) => (StatusCode::OK, summary).into_response(),
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e).into_response(),
+    }
+}
+
+async fn api_fetch_article_classified(
+    State(state): State<AppState>,
+    Json(payload): Json<ArticlePayload>,
 ) -> impl IntoResponse {
-    match logic_fetch_raw_html(payload.url, &state.proxy_state).await {
+    match logic_fetch_article_classified(payload.url, payload.options, &state.proxy_state).await {
+        Ok(article) => (StatusCode::OK, Json(article)).into_response(),
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e).into_response(),
+    }
+}
+
+async fn fetch_article_as(payload: ArticlePayload, format: ExportFormat, state: &AppState) -> impl IntoResponse {
+    match export::logic_fetch_article_as(payload.url, payload.options, format, &state.proxy_state).await {
         Ok(content) => (StatusCode::OK, content),
         Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e),
     }
 }
 
-async fn api_perform_form_login(
+async fn api_fetch_article_markdown(
     State(state): State<AppState>,
-    Json(payload): Json<LoginRequest>,
+    Json(payload): Json<ArticlePayload>,
 ) -> impl IntoResponse {
-    match logic_perform_form_login(payload, &state.proxy_state).await {
-        Ok(response) => (StatusCode::OK, Json(response)).into_response(),
+    fetch_article_as(payload, ExportFormat::Markdown, &state).await
+}
+
+async fn api_fetch_article_asciidoc(
+    State(state): State<AppState>,
+    Json(payload): Json<ArticlePayload>,
+) -> impl IntoResponse {
+    fetch_article_as(payload, ExportFormat::AsciiDoc, &state).await
+}
+
+async fn api_fetch_article_rst(
+    State(state): State<AppState>,
+    Json(payload): Json<ArticlePayload>,
+) -> impl IntoResponse {
+    fetch_article_as(payload, ExportFormat::Rst, &state).await
+}
+
+#[derive(Deserialize)]
+struct HtmlExportPayload {
+    url: String,
+    #[serde(default)]
+    options: HtmlExportOptions,
+}
+
+/// The file is downloaded by the browser rather than written on the server
+async fn api_export_article_html(
+    State(state): State<AppState>,
+    Json(payload): Json<HtmlExportPayload>,
+) -> impl IntoResponse {
+    match single_file::logic_build_article_html(payload.url, payload.options, &state.proxy_state).await {
+        Ok(article) => (
+            StatusCode::OK,
+            [(header::CONTENT_TYPE, "text/html; charset=utf-8"), (header::CONTENT_DISPOSITION, "attachment; filename=\"article.html\"")],
+            article.html,
+        )
+            .into_response(),
         Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e).into_response(),
     }
 }
 
-async fn api_set_proxy_auth(
+async fn api_convert_fragment(
     State(state): State<AppState>,
-    Json(payload): Json<AuthPayload>,
+    Json(payload): Json<FragmentPayload>,
 ) -> impl IntoResponse {
-    let mut credentials = state.proxy_state.auth_credentials.lock().unwrap();
-    credentials.insert(payload.domain.clone(), (payload.username, payload.password));
-    println!("Set auth credentials for domain: {}", payload.domain);
-    StatusCode::OK
+    match export::logic_convert_fragment(payload.html, payload.format, &state.proxy_state) {
+        Ok(converted) => (StatusCode::OK, converted).into_response(),
+        Err(e) => (StatusCode::BAD_REQUEST, e).into_response(),
+    }
 }
 
-async fn api_clear_proxy_auth(
+async fn api_fetch_article_structure(
     State(state): State<AppState>,
-    Json(payload): Json<DomainPayload>,
+    Json(payload): Json<ArticlePayload>,
 ) -> impl IntoResponse {
-    let mut credentials = state.proxy_state.auth_credentials.lock().unwrap();
-    credentials.remove(&payload.domain);
-    println!("Cleared auth credentials for domain: {}", payload.domain);
-    StatusCode::OK
+    match structure::logic_fetch_article_structure(payload.url, payload.options, &state.proxy_state).await {
+        Ok(outline) => (StatusCode::OK, Json(outline)).into_response(),
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e).into_response(),
+    }
 }
 
-async fn api_start_proxy(
+async fn api_fetch_live_blog(
     State(state): State<AppState>,
+    Json(payload): Json<ArticlePayload>,
 ) -> impl IntoResponse {
-    // Return the port if already running
-    let port_guard = state.proxy_state.port.lock().unwrap();
-    if let Some(port) = *port_guard {
-        return (StatusCode::OK, port.to_string());
+    match liveblog::logic_fetch_live_blog(payload.url, payload.options, &state.proxy_state).await {
+        Ok(live_blog) => (StatusCode::OK, Json(live_blog)).into_response(),
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e).into_response(),
     }
-    // Should depend on the auto-start logic, but for now we assume it started
-    (StatusCode::OK, "0".to_string())
 }
 
-async fn api_set_proxy_url(
+async fn api_probe_article_images(
     State(state): State<AppState>,
-    Json(payload): Json<UrlPayload>,
+    Json(payload): Json<ArticlePayload>,
 ) -> impl IntoResponse {
-    if let Ok(new_url) = url::Url::parse(&payload.url) {
-        let mut base_url = state.proxy_state.base_url.lock().unwrap();
-        *base_url = new_url;
-        StatusCode::OK
-    } else {
-        StatusCode::BAD_REQUEST
+    match images::logic_probe_article_images(payload.url, payload.options, &state.proxy_state).await {
+        Ok(probes) => (StatusCode::OK, Json(probes)).into_response(),
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e).into_response(),
+    }
+}
+
+async fn api_fetch_image_captions(
+    State(state): State<AppState>,
+    Json(payload): Json<ArticlePayload>,
+) -> impl IntoResponse {
+    match images::logic_fetch_image_captions(payload.url, payload.options, &state.proxy_state).await {
+        Ok(images) => (StatusCode::OK, Json(images)).into_response(),
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e).into_response(),
+    }
+}
+
+async fn api_fetch_feed(
+    State(state): State<AppState>,
+    Json(payload): Json<FeedPayload>,
+) -> impl IntoResponse {
+    match feed::logic_fetch_feed(payload.url, payload.max_items, &state.proxy_state).await {
+        Ok(feed) => (StatusCode::OK, Json(feed)).into_response(),
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e).into_response(),
+    }
+}
+
+async fn api_fetch_feed_icon(
+    State(state): State<AppState>,
+    Json(payload): Json<FeedIconRequest>,
+) -> impl IntoResponse {
+    match icons::logic_fetch_feed_icon(payload, &state.proxy_state).await {
+        Ok(icon) => (StatusCode::OK, Json(icon)).into_response(),
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e).into_response(),
+    }
+}
+
+// The directory is the server's `SITE_CONFIG_DIR`, never one sent by a client: the files of
+// any directory the server can read would be parsed and partly echoed back
+async fn api_load_site_configs(
+    State(state): State<AppState>,
+) -> impl IntoResponse {
+    let Ok(directory) = std::env::var("SITE_CONFIG_DIR") else {
+        return (StatusCode::NOT_FOUND, "No site config directory configured (SITE_CONFIG_DIR)").into_response();
+    };
+    match site_config::logic_load_site_configs(directory, &state.proxy_state) {
+        Ok(report) => (StatusCode::OK, Json(report)).into_response(),
+        Err(e) => (StatusCode::BAD_REQUEST, e).into_response(),
+    }
+}
+
+async fn api_load_site_config(
+    State(state): State<AppState>,
+    Json(payload): Json<SiteConfigPayload>,
+) -> impl IntoResponse {
+    Json(site_config::logic_load_site_config(payload.host, payload.rules, &state.proxy_state))
+}
+
+async fn api_extract_outline(
+    Json(payload): Json<HtmlPayload>,
+) -> impl IntoResponse {
+    match logic_extract_outline(payload.html) {
+        Ok(outlined) => (StatusCode::OK, Json(outlined)).into_response(),
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e).into_response(),
+    }
+}
+
+async fn api_extract_metadata(
+    Json(payload): Json<PageHtmlPayload>,
+) -> impl IntoResponse {
+    match logic_extract_metadata(payload.html, payload.url) {
+        Ok(metadata) => (StatusCode::OK, Json(metadata)).into_response(),
+        Err(e) => (StatusCode::BAD_REQUEST, e).into_response(),
+    }
+}
+
+async fn api_reveal_hidden_content(
+    Json(payload): Json<HtmlPayload>,
+) -> impl IntoResponse {
+    match logic_reveal_hidden_content(payload.html) {
+        Ok(revealed) => (StatusCode::OK, Json(revealed)).into_response(),
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e).into_response(),
     }
 }
+
+async fn api_fetch_share_metadata(
+    State(state): State<AppState>,
+    Json(payload): Json<UrlPayload>,
+) -> impl IntoResponse {
+    match logic_fetch_share_metadata(payload.url, &state.proxy_state).await {
+        Ok(meta) => (StatusCode::OK, Json(meta)).into_response(),
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e).into_response(),
+    }
+}
+
+async fn api_fetch_raw_html(
+    State(state): State<AppState>,
+    Json(payload): Json<RawHtmlPayload>,
+) -> impl IntoResponse {
+    match logic_fetch_raw_html(payload.url, payload.timeout_secs, payload.user_agent, &state.proxy_state).await {
+        Ok(content) => (StatusCode::OK, content),
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e),
+    }
+}
+
+async fn api_fetch_raw_html_transfer(
+    State(state): State<AppState>,
+    Json(payload): Json<RawHtmlTransferPayload>,
+) -> impl IntoResponse {
+    match logic_fetch_raw_html_transfer(payload.url, payload.transfer, &state.proxy_state).await {
+        Ok(result) => (StatusCode::OK, Json(result)).into_response(),
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e).into_response(),
+    }
+}
+
+async fn api_perform_form_login(
+    State(state): State<AppState>,
+    Json(payload): Json<LoginRequest>,
+) -> impl IntoResponse {
+    match logic_perform_form_login(payload, &state.proxy_state).await {
+        Ok(response) => (StatusCode::OK, Json(response)).into_response(),
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e).into_response(),
+    }
+}
+
+async fn api_set_proxy_auth(
+    State(state): State<AppState>,
+    Json(payload): Json<AuthPayload>,
+) -> impl IntoResponse {
+    match credentials::logic_set_proxy_auth(payload.domain, payload.username, payload.password, &state.proxy_state) {
+        Ok(()) => StatusCode::OK.into_response(),
+        Err(e) => (StatusCode::BAD_REQUEST, e).into_response(),
+    }
+}
+
+async fn api_set_auth(
+    State(state): State<AppState>,
+    Json(payload): Json<AuthMethodPayload>,
+) -> impl IntoResponse {
+    match credentials::logic_set_auth(payload.domain, payload.method, &state.proxy_state) {
+        Ok(()) => StatusCode::OK.into_response(),
+        Err(e) => (StatusCode::BAD_REQUEST, e).into_response(),
+    }
+}
+
+async fn api_migrate_credentials(
+    State(state): State<AppState>,
+) -> impl IntoResponse {
+    Json(credentials::logic_migrate_credentials(&state.proxy_state))
+}
+
+#[derive(Deserialize)]
+struct CredentialConflictPayload {
+    origin: String,
+    old_key: String,
+}
+
+async fn api_resolve_credential_conflict(
+    State(state): State<AppState>,
+    Json(payload): Json<CredentialConflictPayload>,
+) -> impl IntoResponse {
+    match credentials::logic_resolve_credential_conflict(payload.origin, payload.old_key, &state.proxy_state) {
+        Ok(()) => StatusCode::OK.into_response(),
+        Err(e) => (StatusCode::BAD_REQUEST, e).into_response(),
+    }
+}
+
+async fn api_confirm_credential_migration(
+    State(state): State<AppState>,
+) -> impl IntoResponse {
+    match credentials::logic_confirm_credential_migration(&state.proxy_state) {
+        Ok(()) => StatusCode::OK.into_response(),
+        Err(e) => (StatusCode::BAD_REQUEST, e).into_response(),
+    }
+}
+
+async fn api_rollback_credential_migration(
+    State(state): State<AppState>,
+) -> impl IntoResponse {
+    match credentials::logic_rollback_credential_migration(&state.proxy_state) {
+        Ok(()) => StatusCode::OK.into_response(),
+        Err(e) => (StatusCode::BAD_REQUEST, e).into_response(),
+    }
+}
+
+async fn api_get_credential_audit(
+    State(state): State<AppState>,
+) -> impl IntoResponse {
+    Json(credentials::logic_get_credential_audit(&state.proxy_state))
+}
+
+async fn api_clear_proxy_auth(
+    State(state): State<AppState>,
+    Json(payload): Json<ClearProxyAuthPayload>,
+) -> impl IntoResponse {
+    Json(logic_clear_proxy_auth(payload.domain, payload.dry_run, &state.proxy_state))
+}
+
+async fn api_clear_cookies(
+    State(state): State<AppState>,
+    Json(payload): Json<ClearCookiesPayload>,
+) -> impl IntoResponse {
+    Json(logic_clear_cookies(payload.domain, payload.dry_run, &state.proxy_state))
+}
+
+async fn api_prune_transfers(
+    State(state): State<AppState>,
+    Json(payload): Json<DryRunPayload>,
+) -> impl IntoResponse {
+    Json(transfer::prune_expired_transfers(payload.dry_run, &state.proxy_state))
+}
+
+async fn api_get_domain_profile(
+    State(state): State<AppState>,
+    Json(payload): Json<DomainPayload>,
+) -> impl IntoResponse {
+    Json(domains::logic_get_domain_profile(payload.domain, &state.proxy_state))
+}
+
+async fn api_reset_domain(
+    State(state): State<AppState>,
+    Json(payload): Json<DomainPayload>,
+) -> impl IntoResponse {
+    Json(domains::logic_reset_domain(payload.domain, payload.dry_run, &state.proxy_state))
+}
+
+async fn api_forget_domain(
+    State(state): State<AppState>,
+    Json(payload): Json<DomainPayload>,
+) -> impl IntoResponse {
+    match domains::logic_forget_domain(payload.domain, &state.proxy_state) {
+        Ok(removed) => (StatusCode::OK, Json(removed)).into_response(),
+        Err(e) => (StatusCode::BAD_REQUEST, e).into_response(),
+    }
+}
+
+async fn api_start_proxy(
+    State(state): State<AppState>,
+) -> impl IntoResponse {
+    // Return the port if already running
+    if let Some(port) = state.proxy_state.port.get() {
+        return (StatusCode::OK, port.to_string());
+    }
+    // Should depend on the auto-start logic, but for now we assume it started
+    (StatusCode::OK, "0".to_string())
+}
+
+async fn api_get_task_status(
+    State(state): State<AppState>,
+) -> impl IntoResponse {
+    Json(supervisor::logic_get_task_status(&state.proxy_state))
+}
+
+async fn api_get_startup_report(
+    State(state): State<AppState>,
+) -> impl IntoResponse {
+    Json(startup::logic_get_startup_report(&state.proxy_state))
+}
+
+async fn api_retry_component(
+    State(state): State<AppState>,
+    Json(payload): Json<ComponentPayload>,
+) -> impl IntoResponse {
+    Json(startup::logic_retry_component(payload.name, &state.proxy_state).await)
+}
+
+async fn api_set_proxy_url(
+    State(state): State<AppState>,
+    Json(payload): Json<ProxyUrlPayload>,
+) -> impl IntoResponse {
+    if let Ok(new_url) = url::Url::parse(&payload.url) {
+        state.proxy_state.base_url.store(Arc::new(new_url));
+        state.proxy_state.referrer_policy.store(Arc::new(payload.referrer_policy));
+        StatusCode::OK
+    } else {
+        StatusCode::BAD_REQUEST
+    }
+}
+
+async fn api_set_max_body_size(
+    State(state): State<AppState>,
+    Json(payload): Json<MaxBodySizePayload>,
+) -> impl IntoResponse {
+    if payload.bytes == 0 {
+        return StatusCode::BAD_REQUEST;
+    }
+    state.proxy_state.max_body_size.store(payload.bytes, Ordering::Relaxed);
+    StatusCode::OK
+}
+
+async fn api_set_max_rewrite_size(
+    State(state): State<AppState>,
+    Json(payload): Json<MaxBodySizePayload>,
+) -> impl IntoResponse {
+    if payload.bytes == 0 {
+        return StatusCode::BAD_REQUEST;
+    }
+    state.proxy_state.max_rewrite_size.store(payload.bytes, Ordering::Relaxed);
+    StatusCode::OK
+}
+
+async fn api_set_user_agent(
+    State(state): State<AppState>,
+    Json(payload): Json<UserAgentPayload>,
+) -> impl IntoResponse {
+    match logic_set_user_agent(payload.user_agent, &state.proxy_state) {
+        Ok(()) => StatusCode::OK.into_response(),
+        Err(e) => (StatusCode::BAD_REQUEST, e).into_response(),
+    }
+}
+
+async fn api_set_user_agent_pool(
+    State(state): State<AppState>,
+    Json(payload): Json<UserAgentPoolPayload>,
+) -> impl IntoResponse {
+    logic_set_user_agent_pool(payload.agents, &state.proxy_state);
+    StatusCode::OK
+}
+
+async fn api_set_accept_language(
+    State(state): State<AppState>,
+    Json(payload): Json<AcceptLanguagePayload>,
+) -> impl IntoResponse {
+    match logic_set_accept_language(payload.domain, payload.languages, &state.proxy_state) {
+        Ok(header) => (StatusCode::OK, Json(header)).into_response(),
+        Err(e) => (StatusCode::BAD_REQUEST, e).into_response(),
+    }
+}
+
+async fn api_set_host_requires_rendering(
+    State(state): State<AppState>,
+    Json(payload): Json<HostRenderingPayload>,
+) -> impl IntoResponse {
+    match logic_set_host_requires_rendering(payload.host, payload.requires_rendering, &state.proxy_state) {
+        Ok(()) => StatusCode::OK.into_response(),
+        Err(e) => (StatusCode::BAD_REQUEST, e).into_response(),
+    }
+}
+
+async fn api_set_host_crawler_retry(
+    State(state): State<AppState>,
+    Json(payload): Json<HostCrawlerRetryPayload>,
+) -> impl IntoResponse {
+    match logic_set_host_crawler_retry(payload.host, payload.enabled, &state.proxy_state) {
+        Ok(()) => StatusCode::OK.into_response(),
+        Err(e) => (StatusCode::BAD_REQUEST, e).into_response(),
+    }
+}
+
+async fn api_set_force_http1(
+    State(state): State<AppState>,
+    Json(payload): Json<ForceHttp1Payload>,
+) -> impl IntoResponse {
+    match logic_set_force_http1(payload.domain, payload.enabled, &state.proxy_state) {
+        Ok(()) => StatusCode::OK.into_response(),
+        Err(e) => (StatusCode::BAD_REQUEST, e).into_response(),
+    }
+}
+
+async fn api_set_chart_snapshots(
+    State(state): State<AppState>,
+    Json(payload): Json<ChartSnapshotsPayload>,
+) -> impl IntoResponse {
+    match charts::logic_set_chart_snapshots(payload.domain, payload.enabled, &state.proxy_state) {
+        Ok(()) => StatusCode::OK.into_response(),
+        Err(e) => (StatusCode::BAD_REQUEST, e).into_response(),
+    }
+}
+
+async fn api_preconnect(
+    State(state): State<AppState>,
+    Json(payload): Json<UrlPayload>,
+) -> impl IntoResponse {
+    match preconnect::logic_preconnect(payload.url, &state.proxy_state).await {
+        Ok(origins) => (StatusCode::OK, Json(origins)).into_response(),
+        Err(e) => (StatusCode::BAD_REQUEST, e).into_response(),
+    }
+}
+
+async fn api_set_offline_mode(
+    State(state): State<AppState>,
+    Json(payload): Json<EnabledPayload>,
+) -> impl IntoResponse {
+    Json(connectivity::logic_set_offline_mode(payload.enabled, &state.proxy_state))
+}
+
+async fn api_get_connectivity(
+    State(state): State<AppState>,
+) -> impl IntoResponse {
+    Json(connectivity::logic_get_connectivity(&state.proxy_state))
+}
+
+async fn api_list_queued_actions(
+    State(state): State<AppState>,
+) -> impl IntoResponse {
+    Json(connectivity::logic_list_queued_actions(&state.proxy_state))
+}
+
+async fn api_set_metered_mode(
+    State(state): State<AppState>,
+    Json(payload): Json<EnabledPayload>,
+) -> impl IntoResponse {
+    preconnect::logic_set_metered_mode(payload.enabled, &state.proxy_state);
+    StatusCode::OK
+}
+
+async fn api_set_mixed_content_strict(
+    State(state): State<AppState>,
+    Json(payload): Json<EnabledPayload>,
+) -> impl IntoResponse {
+    mixed_content::logic_set_mixed_content_strict(payload.enabled, &state.proxy_state);
+    StatusCode::OK
+}
+
+async fn api_get_mixed_content_report(
+    State(state): State<AppState>,
+) -> impl IntoResponse {
+    Json(mixed_content::logic_get_mixed_content_report(&state.proxy_state))
+}
+
+async fn api_proxy_compare_injection(
+    State(state): State<AppState>,
+    Json(payload): Json<UrlPayload>,
+) -> impl IntoResponse {
+    match proxy::logic_proxy_compare_injection(payload.url, &state.proxy_state).await {
+        Ok(comparison) => (StatusCode::OK, Json(comparison)).into_response(),
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e).into_response(),
+    }
+}
+
+async fn api_rewrite_srcdoc(
+    State(state): State<AppState>,
+    Json(payload): Json<SrcdocPayload>,
+) -> impl IntoResponse {
+    match proxy::logic_rewrite_srcdoc(payload.srcdoc, payload.base_url, payload.inject, &state.proxy_state) {
+        Ok(html) => (StatusCode::OK, html).into_response(),
+        Err(e) => (StatusCode::BAD_REQUEST, e).into_response(),
+    }
+}
+
+async fn api_get_proxy_stats(
+    State(state): State<AppState>,
+) -> impl IntoResponse {
+    Json(proxy::logic_get_proxy_stats(&state.proxy_state))
+}
+
+async fn api_get_snapshot_config(
+    State(state): State<AppState>,
+) -> impl IntoResponse {
+    Json(proxy::logic_get_snapshot_config(&state.proxy_state))
+}
+
+async fn api_set_snapshot_config(
+    State(state): State<AppState>,
+    Json(config): Json<SnapshotConfig>,
+) -> impl IntoResponse {
+    match proxy::logic_set_snapshot_config(config, &state.proxy_state) {
+        Ok(()) => StatusCode::OK.into_response(),
+        Err(e) => (StatusCode::BAD_REQUEST, e).into_response(),
+    }
+}
+
+async fn api_get_article_action_config(
+    State(state): State<AppState>,
+) -> impl IntoResponse {
+    Json(actions::logic_get_article_action_config(&state.proxy_state))
+}
+
+async fn api_set_article_action_config(
+    State(state): State<AppState>,
+    Json(config): Json<ArticleActionConfig>,
+) -> impl IntoResponse {
+    match actions::logic_set_article_action_config(config, &state.proxy_state) {
+        Ok(()) => StatusCode::OK.into_response(),
+        Err(e) => (StatusCode::BAD_REQUEST, e).into_response(),
+    }
+}
+
+async fn api_search_feed_catalog(
+    State(state): State<AppState>,
+    Json(payload): Json<CatalogSearchPayload>,
+) -> impl IntoResponse {
+    match catalog::logic_search_feed_catalog(payload.query, payload.category, &state.proxy_state) {
+        Ok(entries) => (StatusCode::OK, Json(entries)).into_response(),
+        Err(e) => (StatusCode::SERVICE_UNAVAILABLE, e).into_response(),
+    }
+}
+
+async fn api_list_catalog_categories(
+    State(state): State<AppState>,
+) -> impl IntoResponse {
+    match catalog::logic_list_catalog_categories(&state.proxy_state) {
+        Ok(categories) => (StatusCode::OK, Json(categories)).into_response(),
+        Err(e) => (StatusCode::SERVICE_UNAVAILABLE, e).into_response(),
+    }
+}
+
+async fn api_update_catalog(
+    State(state): State<AppState>,
+    Json(payload): Json<UrlPayload>,
+) -> impl IntoResponse {
+    match catalog::logic_update_catalog(payload.url, &state.proxy_state).await {
+        Ok(count) => (StatusCode::OK, Json(count)).into_response(),
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e).into_response(),
+    }
+}
+
+async fn api_get_message_schema() -> impl IntoResponse {
+    Json(messages::logic_get_message_schema())
+}
+
+async fn api_validate_message(
+    State(state): State<AppState>,
+    Json(payload): Json<MessagePayload>,
+) -> impl IntoResponse {
+    let message = match messages::logic_validate_message(payload.message, &state.proxy_state) {
+        Ok(message) => message,
+        Err(e) => return (StatusCode::BAD_REQUEST, e).into_response(),
+    };
+    if let ProtocolMessage::Script(ScriptMessage::FeaturesReport { session_id, page_url, counts }) = &message {
+        if let Err(e) = page_reports::logic_record_features_report(session_id, page_url, *counts, &state.proxy_state) {
+            return (StatusCode::BAD_REQUEST, e).into_response();
+        }
+    }
+    (StatusCode::OK, Json(messages::message_reply(message, payload.transfer, &state.proxy_state))).into_response()
+}
+
+async fn api_get_page_report(
+    State(state): State<AppState>,
+    Json(payload): Json<SessionPayload>,
+) -> impl IntoResponse {
+    match page_reports::logic_get_page_report(payload.session_id, &state.proxy_state) {
+        Ok(report) => (StatusCode::OK, Json(report)).into_response(),
+        Err(e) => (StatusCode::NOT_FOUND, e).into_response(),
+    }
+}
+
+async fn api_get_message_stats(
+    State(state): State<AppState>,
+) -> impl IntoResponse {
+    Json(messages::logic_get_message_stats(&state.proxy_state))
+}
+
+/// Web mode has no events: the extraction (or `null`) is returned directly
+async fn api_extract_from_rendered(
+    State(state): State<AppState>,
+    Json(payload): Json<RenderedHtmlPayload>,
+) -> impl IntoResponse {
+    let proxy_state = state.proxy_state.clone();
+    let result = tokio::task::spawn_blocking(move || {
+        rendered::logic_extract_from_rendered(payload.url, payload.html, payload.options, &proxy_state)
+    })
+    .await
+    .map_err(|e| e.to_string())
+    .and_then(|result| result);
+    match result {
+        Ok(extraction) => (StatusCode::OK, Json(extraction)).into_response(),
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e).into_response(),
+    }
+}
+
+async fn api_get_rendered_extraction_stats(
+    State(state): State<AppState>,
+) -> impl IntoResponse {
+    Json(rendered::logic_get_rendered_extraction_stats(&state.proxy_state))
+}
+
+/// Web mode has no events: progress is only logged
+async fn api_run_maintenance(
+    State(state): State<AppState>,
+    Json(options): Json<MaintenanceOptions>,
+) -> impl IntoResponse {
+    let proxy_state = state.proxy_state.clone();
+    match tokio::task::spawn_blocking(move || maintenance::logic_run_maintenance(options, &proxy_state, |_| {})).await {
+        Ok(report) => (StatusCode::OK, Json(report)).into_response(),
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    }
+}
+
+async fn api_article_opened(
+    State(state): State<AppState>,
+    Json(payload): Json<ArticleOpenedPayload>,
+) -> impl IntoResponse {
+    match prefetch::logic_article_opened(payload.url, payload.feed, payload.local_hour, &state.proxy_state) {
+        Ok(()) => StatusCode::OK.into_response(),
+        Err(e) => (StatusCode::BAD_REQUEST, e).into_response(),
+    }
+}
+
+async fn api_get_prefetch_plan(
+    State(state): State<AppState>,
+    Json(request): Json<PrefetchRequest>,
+) -> impl IntoResponse {
+    match prefetch::logic_get_prefetch_plan(request, &state.proxy_state) {
+        Ok(plan) => (StatusCode::OK, Json(plan)).into_response(),
+        Err(e) => (StatusCode::BAD_REQUEST, e).into_response(),
+    }
+}
+
+async fn api_set_smart_prefetch(
+    State(state): State<AppState>,
+    Json(payload): Json<EnabledPayload>,
+) -> impl IntoResponse {
+    prefetch::logic_set_smart_prefetch(payload.enabled, &state.proxy_state);
+    StatusCode::OK
+}
+
+async fn api_export_reading_stats(
+    State(state): State<AppState>,
+) -> impl IntoResponse {
+    Json(prefetch::logic_export_reading_stats(&state.proxy_state))
+}
+
+async fn api_import_reading_stats(
+    State(state): State<AppState>,
+    Json(stats): Json<ReadingStats>,
+) -> impl IntoResponse {
+    match prefetch::logic_import_reading_stats(stats, &state.proxy_state) {
+        Ok(()) => StatusCode::OK.into_response(),
+        Err(e) => (StatusCode::BAD_REQUEST, e).into_response(),
+    }
+}
+
+async fn api_get_host_stats(
+    State(state): State<AppState>,
+    Json(payload): Json<HostPayload>,
+) -> impl IntoResponse {
+    match host_stats::logic_get_host_stats(payload.host, &state.proxy_state) {
+        Ok(stats) => (StatusCode::OK, Json(stats)).into_response(),
+        Err(e) => (StatusCode::BAD_REQUEST, e).into_response(),
+    }
+}
+
+async fn api_export_host_stats(
+    State(state): State<AppState>,
+) -> impl IntoResponse {
+    Json(host_stats::logic_export_host_stats(&state.proxy_state))
+}
+
+async fn api_import_host_stats(
+    State(state): State<AppState>,
+    Json(stats): Json<HostStatsExport>,
+) -> impl IntoResponse {
+    match host_stats::logic_import_host_stats(stats, &state.proxy_state) {
+        Ok(count) => (StatusCode::OK, Json(count)).into_response(),
+        Err(e) => (StatusCode::BAD_REQUEST, e).into_response(),
+    }
+}
+
+async fn api_add_element_filter(
+    State(state): State<AppState>,
+    Json(payload): Json<ElementFilterPayload>,
+) -> impl IntoResponse {
+    match element_filters::logic_add_element_filter(payload.domain, payload.html, &state.proxy_state) {
+        Ok(filter) => (StatusCode::OK, Json(filter)).into_response(),
+        Err(e) => (StatusCode::BAD_REQUEST, e).into_response(),
+    }
+}
+
+async fn api_remove_element_filter(
+    State(state): State<AppState>,
+    Json(payload): Json<RemoveElementFilterPayload>,
+) -> impl IntoResponse {
+    Json(element_filters::logic_remove_element_filter(payload.domain, payload.selector, &state.proxy_state))
+}
+
+async fn api_preview_filter(
+    State(state): State<AppState>,
+    Json(payload): Json<PreviewFilterPayload>,
+) -> impl IntoResponse {
+    match element_filters::logic_preview_filter(payload.domain, payload.selector, payload.text_anchor, payload.sample_url, &state.proxy_state).await {
+        Ok(preview) => (StatusCode::OK, Json(preview)).into_response(),
+        Err(e) => (StatusCode::BAD_REQUEST, e).into_response(),
+    }
+}
+
+async fn api_export_element_filters(
+    State(state): State<AppState>,
+) -> impl IntoResponse {
+    Json(element_filters::logic_export_element_filters(&state.proxy_state))
+}
+
+async fn api_import_element_filters(
+    State(state): State<AppState>,
+    Json(filters): Json<ElementFiltersExport>,
+) -> impl IntoResponse {
+    match element_filters::logic_import_element_filters(filters, &state.proxy_state) {
+        Ok(count) => (StatusCode::OK, Json(count)).into_response(),
+        Err(e) => (StatusCode::BAD_REQUEST, e).into_response(),
+    }
+}
+
+async fn api_get_host_latency_stats(
+    State(state): State<AppState>,
+) -> impl IntoResponse {
+    Json(latency::logic_get_host_latency_stats(&state.proxy_state))
+}
+
+async fn api_set_adaptive_timeouts(
+    State(state): State<AppState>,
+    Json(config): Json<AdaptiveTimeoutConfig>,
+) -> impl IntoResponse {
+    match latency::logic_set_adaptive_timeouts(config, &state.proxy_state) {
+        Ok(()) => StatusCode::OK.into_response(),
+        Err(e) => (StatusCode::BAD_REQUEST, e).into_response(),
+    }
+}
+
+async fn api_export_host_latency(
+    State(state): State<AppState>,
+) -> impl IntoResponse {
+    Json(latency::logic_export_host_latency(&state.proxy_state))
+}
+
+async fn api_import_host_latency(
+    State(state): State<AppState>,
+    Json(export): Json<HostLatencyExport>,
+) -> impl IntoResponse {
+    match latency::logic_import_host_latency(export, &state.proxy_state) {
+        Ok(count) => (StatusCode::OK, Json(count)).into_response(),
+        Err(e) => (StatusCode::BAD_REQUEST, e).into_response(),
+    }
+}
+
+async fn api_track_article_versions(
+    State(state): State<AppState>,
+    Json(payload): Json<TrackVersionsPayload>,
+) -> impl IntoResponse {
+    versions::logic_track_article_versions(payload.url, payload.starred, &state.proxy_state);
+    StatusCode::OK
+}
+
+async fn api_untrack_article_versions(
+    State(state): State<AppState>,
+    Json(payload): Json<UrlPayload>,
+) -> impl IntoResponse {
+    versions::logic_untrack_article_versions(payload.url, &state.proxy_state);
+    StatusCode::OK
+}
+
+async fn api_list_article_versions(
+    State(state): State<AppState>,
+    Json(payload): Json<UrlPayload>,
+) -> impl IntoResponse {
+    Json(versions::logic_list_article_versions(payload.url, &state.proxy_state))
+}
+
+async fn api_get_article_version(
+    State(state): State<AppState>,
+    Json(payload): Json<ArticleVersionPayload>,
+) -> impl IntoResponse {
+    match versions::logic_get_article_version(payload.url, payload.timestamp, &state.proxy_state) {
+        Ok(version) => (StatusCode::OK, Json(version)).into_response(),
+        Err(e) => (StatusCode::NOT_FOUND, e).into_response(),
+    }
+}
+
+async fn api_set_version_budget(
+    State(state): State<AppState>,
+    Json(payload): Json<VersionBudgetPayload>,
+) -> impl IntoResponse {
+    versions::logic_set_version_budget(payload.bytes, &state.proxy_state);
+    StatusCode::OK
+}
+
+async fn api_get_compression_config(
+    State(state): State<AppState>,
+) -> impl IntoResponse {
+    Json(compression::logic_get_compression_config(&state.proxy_state))
+}
+
+async fn api_set_compression_config(
+    State(state): State<AppState>,
+    Json(config): Json<CompressionConfig>,
+) -> impl IntoResponse {
+    match compression::logic_set_compression_config(config, &state.proxy_state) {
+        Ok(()) => StatusCode::OK.into_response(),
+        Err(e) => (StatusCode::BAD_REQUEST, e).into_response(),
+    }
+}
+
+async fn api_get_translation_config(
+    State(state): State<AppState>,
+) -> impl IntoResponse {
+    Json(translation::logic_get_translation_config(&state.proxy_state))
+}
+
+async fn api_set_translation_config(
+    State(state): State<AppState>,
+    Json(config): Json<TranslationConfig>,
+) -> impl IntoResponse {
+    match translation::logic_set_translation_config(config, &state.proxy_state) {
+        Ok(()) => StatusCode::OK.into_response(),
+        Err(e) => (StatusCode::BAD_REQUEST, e).into_response(),
+    }
+}
+
+#[derive(Deserialize)]
+struct FeedUrlPayload {
+    feed: String,
+}
+
+async fn api_get_feed_translation(
+    State(state): State<AppState>,
+    Json(payload): Json<FeedUrlPayload>,
+) -> impl IntoResponse {
+    Json(translation::logic_get_feed_translation(payload.feed, &state.proxy_state))
+}
+
+#[derive(Deserialize)]
+struct FeedTranslationPayload {
+    feed: String,
+    preference: FeedTranslation,
+}
+
+async fn api_set_feed_translation(
+    State(state): State<AppState>,
+    Json(payload): Json<FeedTranslationPayload>,
+) -> impl IntoResponse {
+    match translation::logic_set_feed_translation(payload.feed, payload.preference, &state.proxy_state) {
+        Ok(()) => StatusCode::OK.into_response(),
+        Err(e) => (StatusCode::BAD_REQUEST, e).into_response(),
+    }
+}
+
+#[derive(Deserialize)]
+struct TranslateArticlePayload {
+    url: String,
+    #[serde(default)]
+    feed: Option<String>,
+    #[serde(default)]
+    target_language: Option<String>,
+}
+
+async fn api_translate_article(
+    State(state): State<AppState>,
+    Json(payload): Json<TranslateArticlePayload>,
+) -> impl IntoResponse {
+    match translation::logic_translate_article(payload.url, payload.feed, payload.target_language, &state.proxy_state).await {
+        Ok(translated) => (StatusCode::OK, Json(translated)).into_response(),
+        Err(e) if e.starts_with(TRANSLATION_BUDGET_EXCEEDED) => (StatusCode::TOO_MANY_REQUESTS, e).into_response(),
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e).into_response(),
+    }
+}
+
+async fn api_get_translation_usage(
+    State(state): State<AppState>,
+) -> impl IntoResponse {
+    Json(translation::logic_get_translation_usage(&state.proxy_state))
+}
+
+async fn api_get_unread_estimate(
+    State(state): State<AppState>,
+) -> impl IntoResponse {
+    Json(unread::logic_get_unread_estimate(&state.proxy_state))
+}
+
+#[derive(Deserialize)]
+struct ItemIdsPayload {
+    ids: Vec<String>,
+}
+
+async fn api_mark_items_read(
+    State(state): State<AppState>,
+    Json(payload): Json<ItemIdsPayload>,
+) -> impl IntoResponse {
+    Json(unread::logic_mark_items_read(payload.ids, &state.proxy_state))
+}
+
+#[derive(Deserialize)]
+struct UnreadCountPayload {
+    count: usize,
+}
+
+async fn api_set_unread_count(
+    State(state): State<AppState>,
+    Json(payload): Json<UnreadCountPayload>,
+) -> impl IntoResponse {
+    Json(unread::logic_set_unread_count(payload.count, &state.proxy_state))
+}
+
+#[derive(Deserialize)]
+struct BadgeModePayload {
+    mode: BadgeMode,
+}
+
+async fn api_set_badge_mode(
+    State(state): State<AppState>,
+    Json(payload): Json<BadgeModePayload>,
+) -> impl IntoResponse {
+    unread::logic_set_badge_mode(payload.mode, &state.proxy_state);
+    StatusCode::OK
+}
+
+async fn api_get_extractor_comparison(
+    State(state): State<AppState>,
+) -> impl IntoResponse {
+    Json(extractors::logic_get_extractor_comparison(&state.proxy_state))
+}
+
+async fn api_set_extractor_comparison_config(
+    State(state): State<AppState>,
+    Json(config): Json<ExtractorComparisonConfig>,
+) -> impl IntoResponse {
+    match extractors::logic_set_extractor_comparison_config(config, &state.proxy_state) {
+        Ok(()) => StatusCode::OK.into_response(),
+        Err(e) => (StatusCode::BAD_REQUEST, e).into_response(),
+    }
+}
+
+#[derive(Deserialize)]
+struct DomainExtractorPayload {
+    domain: String,
+    #[serde(default)]
+    backend: Option<ExtractorBackend>,
+}
+
+async fn api_set_domain_extractor(
+    State(state): State<AppState>,
+    Json(payload): Json<DomainExtractorPayload>,
+) -> impl IntoResponse {
+    match extractors::logic_set_domain_extractor(payload.domain, payload.backend, &state.proxy_state) {
+        Ok(()) => StatusCode::OK.into_response(),
+        Err(e) => (StatusCode::BAD_REQUEST, e).into_response(),
+    }
+}
+
+async fn api_set_user_agent_rotation(
+    State(state): State<AppState>,
+    Json(payload): Json<EnabledPayload>,
+) -> impl IntoResponse {
+    logic_set_user_agent_rotation(payload.enabled, &state.proxy_state);
+    StatusCode::OK
+}
+
+async fn api_set_article_sanitization(
+    State(state): State<AppState>,
+    Json(payload): Json<EnabledPayload>,
+) -> impl IntoResponse {
+    logic_set_article_sanitization(payload.enabled, &state.proxy_state);
+    StatusCode::OK
+}
+
+async fn api_enable_chaos(
+    State(state): State<AppState>,
+    Json(payload): Json<ChaosPayload>,
+) -> impl IntoResponse {
+    match chaos::enable_chaos(payload.profile, &state.proxy_state) {
+        Ok(profile) => (StatusCode::OK, Json(profile)).into_response(),
+        Err(e) => (StatusCode::BAD_REQUEST, e).into_response(),
+    }
+}
+
+async fn api_disable_chaos(
+    State(state): State<AppState>,
+) -> impl IntoResponse {
+    chaos::disable_chaos(&state.proxy_state);
+    StatusCode::OK
+}
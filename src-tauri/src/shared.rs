@@ -1,38 +1,298 @@
-use std::sync::{Arc, Mutex};
-use std::io::Cursor;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, LazyLock, Mutex, OnceLock};
+use arc_swap::{ArcSwap, ArcSwapOption};
+use dashmap::{DashMap, DashSet};
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+use std::collections::HashSet;
 use url::Url;
-use reqwest::header::USER_AGENT;
-use reqwest::cookie::{Jar, CookieStore};
+use reqwest::header::{HeaderName, HeaderValue, USER_AGENT};
+use reqwest::cookie::CookieStore;
+use reqwest_cookie_store::CookieStoreMutex;
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use tokio::time::Duration;
+use lol_html::{doc_text, element, rewrite_str, text, RewriteStrSettings};
+use lol_html::html_content::{ContentType, TextType};
+use crate::transfer::{prepare_transfer, PendingTransfer, TransferMode, TransferPayload};
+use crate::chaos::{self, ActiveChaos};
+use crate::callouts::{self, ClassifiedBlock};
+use crate::charts;
+use crate::provenance::{Provenance, ProvenanceBuilder, ProvenanceSource};
+use crate::catalog::FeedCatalog;
+use crate::icons::{FeedIcon, PageIcons};
+use crate::messages::MessageStats;
+use crate::mixed_content::MixedContentState;
+use crate::prefetch::PrefetchStore;
+use crate::reading_level;
+use crate::proxy::{ProxyStats, ReferrerPolicy, SnapshotConfig};
+use crate::rendered::{self, RenderedStore};
+use crate::versions::{self, VersionStore};
+use crate::site_config::{self, SiteConfig};
+use crate::startup::StartupStore;
+use crate::host_stats::{self, ExtractionOutcome, HostStatsStore};
+use crate::page_reports::PageReportStore;
+use crate::latency::{self, AdaptiveTimeoutConfig, HostLatency, RequestPriority};
+use crate::element_filters::{self, ElementFilter};
+use crate::supervisor::Supervisor;
+use crate::compression::CompressionConfig;
+use crate::dates::{self, ParsedDate};
+use crate::translation::TranslationStore;
+use crate::extractors::{self, ExtractorRun, ExtractorStore};
+use crate::unread::UnreadStore;
+use crate::credentials::{self, AuthMethod, CredentialMigration};
+use crate::actions::ArticleActionConfig;
+use crate::feed::{self, DiscoveredFeed, SeenItemStore};
+use crate::preconnect::{self, PreconnectStore};
+use crate::embeds::{self, ProtectedEmbeds};
+use crate::pagination;
+use crate::connectivity::{self, ConnectivityStore};
+use crate::resource_cache::ResourceCacheStore;
 
 pub const FALLBACK_SIGNAL: &str = "READABILITY_FAILED_FALLBACK";
 
-// Shared state for the proxy's base URL, port, auth credentials, and cookie jar
+/// User-Agent sent upstream when neither `set_user_agent`, rotation nor the caller picks another
+pub const DEFAULT_USER_AGENT: &str = "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/120.0.0.0 Safari/537.36";
+
+/// Accept-Language sent by the article fetchers when no override applies
+pub const DEFAULT_ARTICLE_ACCEPT_LANGUAGE: &str = "fr-FR,fr;q=0.8,en-US;q=0.6,en;q=0.4";
+
+/// Accept-Language sent by the proxy handlers when no override applies
+pub const DEFAULT_PROXY_ACCEPT_LANGUAGE: &str = "en-US,en;q=0.9";
+
+/// Error prefix returned when a (decompressed) response body exceeds `max_body_size`
+pub const BODY_TOO_LARGE: &str = "BODY_TOO_LARGE";
+
+/// Default cap on decompressed response bodies: 50 MiB
+pub const DEFAULT_MAX_BODY_SIZE: usize = 50 * 1024 * 1024;
+
+/// Default `Content-Length` past which the proxy streams a response through instead of
+/// buffering it for rewriting: 10 MiB
+pub const DEFAULT_MAX_REWRITE_SIZE: usize = 10 * 1024 * 1024;
+
+// Shared state for the proxy's base URL, port, auth credentials, and cookie jar.
+// Everything read by the proxy handlers on each request is lock-free or sharded (`ArcSwap`,
+// atomics, `DashMap`), so concurrent resource requests don't serialize on it. Guards of the
+// remaining `Mutex`es must not be held across an `.await`.
 #[derive(Clone)]
 pub struct ProxyState {
-    pub base_url: Arc<Mutex<Url>>,
-    pub port: Arc<Mutex<Option<u16>>>,
-    pub auth_credentials: Arc<Mutex<std::collections::HashMap<String, (String, String)>>>,
+    /// Article being proxied. Load it once per request so every use sees the same URL.
+    pub base_url: Arc<ArcSwap<Url>>,
+    /// Referer sent upstream for the article's requests, reset with each `set_proxy_url`
+    pub referrer_policy: Arc<ArcSwap<ReferrerPolicy>>,
+    /// Port of the local proxy server, set once it's listening
+    pub port: Arc<OnceLock<u16>>,
+    /// Credentials per origin (keyed as the frontend gave them until `migrate_credentials`)
+    pub auth_credentials: Arc<DashMap<String, AuthMethod>>,
+    /// Version of the `auth_credentials` keys, with the migration's backup, conflicts and audit log
+    pub credential_migration: Arc<Mutex<CredentialMigration>>,
     /// If true, the proxy will rewrite URLs as relative paths (e.g. "/proxy?url=...")
     /// This is used when the proxy is running on the same origin as the frontend (Web App mode).
-    pub use_relative_paths: Arc<Mutex<bool>>,
-    /// Shared cookie jar for session persistence across requests
-    pub cookie_jar: Arc<Jar>,
+    pub use_relative_paths: Arc<AtomicBool>,
+    /// Shared cookie jar for session persistence across requests.
+    /// Backed by `cookie_store` so cookies can be enumerated and removed per domain.
+    pub cookie_jar: Arc<CookieStoreMutex>,
+    /// Maximum number of bytes read from an upstream body after decompression.
+    /// Guards every fetch path against gzip/brotli bombs.
+    pub max_body_size: Arc<AtomicUsize>,
+    /// Proxied responses announcing a larger `Content-Length` are streamed as is, never read
+    /// into memory for HTML or CSS rewriting
+    pub max_rewrite_size: Arc<AtomicUsize>,
+    /// Large results parked for one-shot retrieval through `/transfer/:token`
+    pub transfers: Arc<Mutex<std::collections::HashMap<String, PendingTransfer>>>,
+    /// Failure-injection profile for exercising the frontend's loading/error states (dev only)
+    pub chaos: Arc<Mutex<Option<ActiveChaos>>>,
+    /// User-Agent of upstream requests, `DEFAULT_USER_AGENT` until `set_user_agent` replaces it
+    pub user_agent: Arc<ArcSwap<String>>,
+    /// User-Agent strings cycled per request when rotation is enabled
+    pub user_agent_pool: Arc<Mutex<UserAgentPool>>,
+    /// Feed icons (real or generated) keyed by site origin
+    pub icon_cache: Arc<Mutex<std::collections::HashMap<String, FeedIcon>>>,
+    /// ftr-site-config extraction rules keyed by host (`.example.com` for every subdomain)
+    pub site_configs: Arc<DashMap<String, SiteConfig>>,
+    /// `Accept-Language` header values keyed by domain (subdomains included)
+    pub accept_languages: Arc<DashMap<String, String>>,
+    /// Hosts (subdomains included) whose articles always go through the rendered proxy path
+    pub rendering_hosts: Arc<DashSet<String>>,
+    /// Hosts (subdomains included) whose teaser-length extractions are retried as Googlebot
+    pub crawler_retry_hosts: Arc<DashSet<String>>,
+    /// Every upstream request uses HTTP/1.1 (no HTTP/2 negotiation)
+    pub force_http1: Arc<AtomicBool>,
+    /// Hosts (subdomains included) that are only requested over HTTP/1.1
+    pub http1_hosts: Arc<DashSet<String>>,
+    /// Datawrapper and Flourish embeds are replaced by a static image before extraction
+    pub chart_snapshots: Arc<AtomicBool>,
+    /// Hosts (subdomains included) whose chart embeds are left alone
+    pub chart_snapshot_opt_outs: Arc<DashSet<String>>,
+    /// Whether the static image of a chart exists, keyed by the chart's live URL
+    pub chart_images: Arc<DashMap<String, bool>>,
+    /// Strict mode, per-host HTTPS support and last report for plain-http subresources of https pages
+    pub mixed_content: Arc<Mutex<MixedContentState>>,
+    /// Dated versions of starred/archived articles
+    pub article_versions: Arc<Mutex<VersionStore>>,
+    /// Counters of postMessage protocol messages validated on the Rust side
+    pub message_stats: Arc<Mutex<MessageStats>>,
+    /// Catalog loaded by `update_catalog`; `None` means the bundled one is used
+    pub feed_catalog: Arc<ArcSwapOption<FeedCatalog>>,
+    /// Articles extracted from rendered fallback pages, and per-host success counters
+    pub rendered: Arc<Mutex<RenderedStore>>,
+    /// Counters of the proxy handlers (Referer strategies of signed resource URLs, cache revalidations)
+    pub proxy_stats: Arc<ProxyStats>,
+    /// Per-feed open rates and reading hours learned for prefetching
+    pub prefetch: Arc<Mutex<PrefetchStore>>,
+    /// Outcome of starting each component (see `startup`)
+    pub startup: Arc<Mutex<StartupStore>>,
+    /// Extraction outcome counters per host
+    pub host_stats: Arc<Mutex<HostStatsStore>>,
+    /// Latest `FEATURES_REPORT` of recent page loads
+    pub page_reports: Arc<Mutex<PageReportStore>>,
+    /// Recent response latencies per host, for adaptive timeouts
+    pub host_latency: Arc<DashMap<String, HostLatency>>,
+    pub adaptive_timeouts: Arc<ArcSwap<AdaptiveTimeoutConfig>>,
+    /// Elements the user asked never to see, keyed by domain (subdomains included)
+    pub element_filters: Arc<DashMap<String, Vec<ElementFilter>>>,
+    /// Overlay removal settings substituted into each listener script injection
+    pub snapshot_config: Arc<ArcSwap<SnapshotConfig>>,
+    /// Action bar injection and settings of the article actions
+    pub article_actions: Arc<ArcSwap<ArticleActionConfig>>,
+    /// Long-lived background tasks (proxy server...), restarted when they die
+    pub supervisor: Arc<Supervisor>,
+    /// Compression of stored article HTML and large transfers
+    pub compression: Arc<ArcSwap<CompressionConfig>>,
+    /// When feed items without a readable date were first seen, keyed by feed URL and item
+    pub first_seen: Arc<DashMap<String, i64>>,
+    /// Icons declared by the page being proxied, for `/icon`
+    pub page_icons: Arc<ArcSwapOption<PageIcons>>,
+    /// Translation service, per-feed preferences and detected languages, daily usage
+    pub translation: Arc<Mutex<TranslationStore>>,
+    /// Extractor comparisons per domain, sampling config and extractor overrides
+    pub extractors: Arc<Mutex<ExtractorStore>>,
+    /// Unread count estimate and badge mode
+    pub unread: Arc<Mutex<UnreadStore>>,
+    /// Feed items seen per feed, for stable ordering and spotting updated items
+    pub seen_items: Arc<Mutex<SeenItemStore>>,
+    /// Clients whose connection pools article fetches, proxied resources and preconnects share,
+    /// one per protocol setting
+    pub pooled_clients: Arc<DashMap<Protocol, reqwest::Client>>,
+    /// Origins warmed by `preconnect` and subresource origins learned per domain
+    pub preconnect: Arc<Mutex<PreconnectStore>>,
+    /// Metered connection: nothing is fetched ahead of the user
+    pub metered: Arc<AtomicBool>,
+    /// Extracted articles go through `sanitize_article_html` (on by default)
+    pub sanitize_articles: Arc<AtomicBool>,
+    /// Offline mode (forced or detected) and the article actions waiting for the network
+    pub connectivity: Arc<Mutex<ConnectivityStore>>,
+    /// Proxied resources with their validators, revalidated with conditional requests
+    pub resource_cache: Arc<Mutex<ResourceCacheStore>>,
 }
 
 impl Default for ProxyState {
     fn default() -> Self {
         Self {
-            base_url: Arc::new(Mutex::new(Url::parse("http://localhost").unwrap())),
-            port: Arc::new(Mutex::new(None)),
-            auth_credentials: Arc::new(Mutex::new(std::collections::HashMap::new())),
-            use_relative_paths: Arc::new(Mutex::new(false)),
-            cookie_jar: Arc::new(Jar::default()),
+            base_url: Arc::new(ArcSwap::from_pointee(Url::parse("http://localhost").unwrap())),
+            referrer_policy: Arc::new(ArcSwap::from_pointee(ReferrerPolicy::default())),
+            port: Arc::new(OnceLock::new()),
+            auth_credentials: Arc::new(DashMap::new()),
+            credential_migration: Arc::new(Mutex::new(CredentialMigration::default())),
+            use_relative_paths: Arc::new(AtomicBool::new(false)),
+            cookie_jar: Arc::new(CookieStoreMutex::default()),
+            max_body_size: Arc::new(AtomicUsize::new(DEFAULT_MAX_BODY_SIZE)),
+            max_rewrite_size: Arc::new(AtomicUsize::new(DEFAULT_MAX_REWRITE_SIZE)),
+            transfers: Arc::new(Mutex::new(std::collections::HashMap::new())),
+            chaos: Arc::new(Mutex::new(None)),
+            user_agent: Arc::new(ArcSwap::from_pointee(DEFAULT_USER_AGENT.to_string())),
+            user_agent_pool: Arc::new(Mutex::new(UserAgentPool::default())),
+            icon_cache: Arc::new(Mutex::new(std::collections::HashMap::new())),
+            site_configs: Arc::new(DashMap::new()),
+            accept_languages: Arc::new(DashMap::new()),
+            rendering_hosts: Arc::new(DashSet::new()),
+            crawler_retry_hosts: Arc::new(DashSet::new()),
+            force_http1: Arc::new(AtomicBool::new(false)),
+            http1_hosts: Arc::new(DashSet::new()),
+            chart_snapshots: Arc::new(AtomicBool::new(false)),
+            chart_snapshot_opt_outs: Arc::new(DashSet::new()),
+            chart_images: Arc::new(DashMap::new()),
+            mixed_content: Arc::new(Mutex::new(MixedContentState::default())),
+            article_versions: Arc::new(Mutex::new(VersionStore::default())),
+            message_stats: Arc::new(Mutex::new(MessageStats::default())),
+            feed_catalog: Arc::new(ArcSwapOption::empty()),
+            rendered: Arc::new(Mutex::new(RenderedStore::default())),
+            proxy_stats: Arc::new(ProxyStats::default()),
+            prefetch: Arc::new(Mutex::new(PrefetchStore::default())),
+            startup: Arc::new(Mutex::new(StartupStore::default())),
+            host_stats: Arc::new(Mutex::new(HostStatsStore::default())),
+            page_reports: Arc::new(Mutex::new(PageReportStore::default())),
+            host_latency: Arc::new(DashMap::new()),
+            adaptive_timeouts: Arc::new(ArcSwap::from_pointee(AdaptiveTimeoutConfig::default())),
+            element_filters: Arc::new(DashMap::new()),
+            snapshot_config: Arc::new(ArcSwap::from_pointee(SnapshotConfig::default())),
+            article_actions: Arc::new(ArcSwap::from_pointee(ArticleActionConfig::default())),
+            supervisor: Arc::new(Supervisor::default()),
+            compression: Arc::new(ArcSwap::from_pointee(CompressionConfig::default())),
+            first_seen: Arc::new(DashMap::new()),
+            page_icons: Arc::new(ArcSwapOption::empty()),
+            translation: Arc::new(Mutex::new(TranslationStore::default())),
+            extractors: Arc::new(Mutex::new(ExtractorStore::default())),
+            unread: Arc::new(Mutex::new(UnreadStore::default())),
+            seen_items: Arc::new(Mutex::new(SeenItemStore::default())),
+            pooled_clients: Arc::new(DashMap::new()),
+            preconnect: Arc::new(Mutex::new(PreconnectStore::default())),
+            metered: Arc::new(AtomicBool::new(false)),
+            sanitize_articles: Arc::new(AtomicBool::new(true)),
+            connectivity: Arc::new(Mutex::new(ConnectivityStore::default())),
+            resource_cache: Arc::new(Mutex::new(ResourceCacheStore::default())),
+        }
+    }
+}
+
+impl ProxyState {
+    /// Base URL of the local server for building proxy/transfer URLs:
+    /// empty in Web App mode (same origin), `http://localhost:{port}` otherwise.
+    pub fn local_base(&self) -> String {
+        if self.use_relative_paths.load(Ordering::Relaxed) {
+            String::new()
+        } else {
+            format!("http://localhost:{}", self.port.get().copied().unwrap_or(3000))
+        }
+    }
+
+    /// User-Agent for the next upstream request: the next pool entry (round-robin)
+    /// when rotation is on and the pool isn't empty, the configured one otherwise
+    pub fn next_user_agent(&self) -> String {
+        let mut pool = self.user_agent_pool.lock().unwrap();
+        if !pool.enabled || pool.agents.is_empty() {
+            return self.user_agent.load().to_string();
         }
+        let agent = pool.agents[pool.next % pool.agents.len()].clone();
+        pool.next = pool.next.wrapping_add(1);
+        agent
+    }
+
+    /// Maximum decompressed body size for upstream responses
+    pub fn max_body_size(&self) -> usize {
+        self.max_body_size.load(Ordering::Relaxed)
+    }
+
+    /// Largest announced body the proxy buffers for rewriting
+    pub fn max_rewrite_size(&self) -> usize {
+        self.max_rewrite_size.load(Ordering::Relaxed)
+    }
+
+    /// Whether a local server is available to serve `/transfer` handles
+    pub fn has_local_server(&self) -> bool {
+        self.use_relative_paths.load(Ordering::Relaxed) || self.port.get().is_some()
     }
 }
 
+/// Pool of User-Agent strings for anti-bot setups that throttle a single UA
+#[derive(Debug, Default)]
+pub struct UserAgentPool {
+    pub agents: Vec<String>,
+    pub enabled: bool,
+    /// Index of the next agent to hand out
+    next: usize,
+}
+
 // Types for form login
 #[derive(Debug, Deserialize)]
 pub struct FormField {
@@ -55,118 +315,2956 @@ pub struct LoginResponse {
     pub extracted_text: Option<String>,
 }
 
-// --- Core Logic Functions (Tauri/Axum Agnostic) ---
+/// How aggressively `fetch_article` accepts or rejects extraction results
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub enum ExtractionStrictness {
+    /// Require a substantial amount of extracted text, falling back to the iframe otherwise
+    Strict,
+    #[default]
+    Default,
+    /// Accept short documents, and fall back to the other extractor backend when the
+    /// active one returns too little
+    Lenient,
+}
 
-pub async fn logic_fetch_raw_html(url: String, state: &ProxyState) -> Result<String, String> {
-    println!("[shared::fetch_raw_html] ========================================");
-    println!("[shared::fetch_raw_html] Fetching URL: {}", url);
-    println!("[shared::fetch_raw_html] ========================================");
+/// Markup-only documents JS-heavy sites serve before rendering, matched after newlines are removed
+pub const DEFAULT_EMPTY_DOC_PATTERNS: &[&str] = &[
+    r"^<!DOCTYPE html><html><head></head><body></body></html>$",
+    r"^<!doctype html><html><head></head><body></body></html>$",
+    r"^<html><head></head><body></body></html>$",
+    r"^<!DOCTYPE html><html><head>\s*</head><body>\s*</body></html>$",
+];
 
-    let url_obj = Url::parse(&url).map_err(|e| e.to_string())?;
+/// Thresholds deciding when extraction gives up and `fetch_article` falls back to the iframe.
+/// `ExtractionStrictness` picks one of three presets; short-form sites (microblogs, link-blogs)
+/// can lower them per fetch with `ArticleOptions::readability`.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct ReadabilityConfig {
+    /// Raw documents shorter than this are inspected for the "empty shell" pattern
+    pub min_raw_html_len: usize,
+    /// Documents shorter than this without any content tag go straight to the fallback
+    pub min_document_len: usize,
+    /// Readability output shorter than this (in bytes of HTML) is checked for an empty shell
+    pub min_extracted_html_len: usize,
+    /// Minimum amount of plain text (in chars) the extractor must return to be accepted
+    pub min_extracted_len: usize,
+    /// Regexes of documents to treat as empty (matched with newlines removed)
+    pub empty_doc_patterns: Vec<String>,
+    /// When the active extractor backend returns too little, try the other one
+    pub lenient_fallback: bool,
+}
 
-    // Extract domain for auth lookup
-    let domain = format!("{}://{}",
-        url_obj.scheme(),
-        url_obj.host_str().unwrap_or("localhost")
+impl Default for ReadabilityConfig {
+    fn default() -> Self {
+        ExtractionStrictness::Default.into()
+    }
+}
+
+impl From<ExtractionStrictness> for ReadabilityConfig {
+    fn from(strictness: ExtractionStrictness) -> Self {
+        let (min_raw_html_len, min_document_len, min_extracted_len) = match strictness {
+            ExtractionStrictness::Strict => (300, 400, 500),
+            ExtractionStrictness::Default => (150, 200, 0),
+            ExtractionStrictness::Lenient => (50, 80, 200),
+        };
+        Self {
+            min_raw_html_len,
+            min_document_len,
+            min_extracted_html_len: 100,
+            min_extracted_len,
+            empty_doc_patterns: DEFAULT_EMPTY_DOC_PATTERNS.iter().map(|pattern| pattern.to_string()).collect(),
+            lenient_fallback: strictness == ExtractionStrictness::Lenient,
+        }
+    }
+}
+
+/// Per-fetch options for `fetch_article`. Every field has a default so callers
+/// only send what they want to override.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct ArticleOptions {
+    pub strictness: ExtractionStrictness,
+    /// Thresholds replacing the `strictness` preset; fields left out keep the `Default` preset's
+    pub readability: Option<ReadabilityConfig>,
+    /// Preferred languages for this fetch (e.g. the feed's language), most preferred first.
+    /// Overrides the domain's `accept_language` setting.
+    pub accept_language: Option<Vec<String>>,
+    /// Maximum display width of images in the extracted content, in CSS pixels
+    pub max_image_width: Option<u32>,
+    /// Time budget in milliseconds. Once spent, optional steps are skipped and the best
+    /// result so far is returned instead of failing; the fetch keeps its own timeout.
+    pub deadline_ms: Option<u64>,
+    /// Mark pull-quotes, callouts and key-takeaway panels with `data-block-kind` and list them
+    /// in `ArticleResult::blocks`
+    pub classify_blocks: bool,
+    /// Fetched by prefetching rather than for the user: adaptive timeouts use the tighter
+    /// background bounds
+    pub background: bool,
+    /// Timeout of the page request in seconds (1-120), replacing the adaptive timeout
+    pub timeout_secs: Option<u64>,
+    /// User-Agent of the page request (e.g. Googlebot's, or a mobile browser's), replacing the
+    /// configured or rotated one and any set by site rules
+    pub user_agent: Option<String>,
+    /// Keep YouTube, Vimeo, Dailymotion and Twitter embed iframes in the content (each wrapped
+    /// in a `data-embed` div); other iframes are still removed
+    pub keep_embeds: bool,
+    /// Follow `rel="next"` and pagination links of articles split across pages, up to
+    /// `pagination::MAX_PAGES`, and return the pages stitched together
+    pub follow_pagination: bool,
+    /// Reading speed behind `ArticleResult::reading_time_minutes`, `DEFAULT_WORDS_PER_MINUTE`
+    /// when not given
+    pub words_per_minute: Option<u32>,
+    /// Summary of the item in its feed (text or HTML). On hosts flagged with
+    /// `set_host_crawler_retry`, content not much longer than it is taken for a teaser.
+    pub feed_excerpt: Option<String>,
+}
+
+impl ArticleOptions {
+    /// `readability` if given, else the `strictness` preset
+    pub fn readability_config(&self) -> ReadabilityConfig {
+        self.readability.clone().unwrap_or_else(|| self.strictness.into())
+    }
+}
+
+/// Time budget of an extraction (`ArticleOptions::deadline_ms`). Optional steps ask it
+/// before running and are skipped once it's spent; fetching and readability always run.
+#[derive(Debug, Default)]
+pub struct Deadline {
+    at: Option<std::time::Instant>,
+    skipped: Mutex<Vec<&'static str>>,
+}
+
+impl Deadline {
+    pub fn after_ms(budget_ms: Option<u64>) -> Self {
+        Self { at: budget_ms.map(|ms| std::time::Instant::now() + Duration::from_millis(ms)), ..Self::default() }
+    }
+
+    /// Whether the optional `step` may run, recording it as skipped when the budget is spent
+    pub fn allows(&self, step: &'static str) -> bool {
+        if self.at.is_none_or(|at| std::time::Instant::now() < at) {
+            return true;
+        }
+        println!("[shared::deadline] Budget spent, skipping {}", step);
+        self.skipped.lock().unwrap().push(step);
+        false
+    }
+
+    pub fn skipped_steps(&self) -> Vec<String> {
+        self.skipped.lock().unwrap().iter().map(|step| step.to_string()).collect()
+    }
+}
+
+/// A downloaded article page
+pub struct FetchedPage {
+    pub html: String,
+    /// `Content-Language` returned by the server
+    pub content_language: Option<String>,
+    /// URL of the page after redirects
+    pub url: String,
+    pub status: Option<u16>,
+    pub user_agent: Option<String>,
+    /// Authentication method sent with the request
+    pub auth: Option<&'static str>,
+    /// `Content-Type` returned by the server: HTML, or XML that may be a feed
+    pub content_type: String,
+}
+
+impl FetchedPage {
+    /// A built-in `chaos://` fixture page
+    fn fixture(html: String, url: &Url) -> Self {
+        FetchedPage { html, content_language: None, url: url.to_string(), status: None, user_agent: None, auth: None, content_type: "text/html".into() }
+    }
+}
+
+/// Request settings of an article fetch, reused for its AMP version and following pages
+pub struct PageRequest<'a> {
+    pub site_config: Option<&'a SiteConfig>,
+    pub accept_language: &'a str,
+    pub priority: RequestPriority,
+    /// `ArticleOptions::timeout_secs`
+    pub timeout_secs: Option<u64>,
+    /// `ArticleOptions::user_agent`, validated
+    pub user_agent: Option<String>,
+    /// Sent without the shared cookie jar, nor cookies from site rules (crawler retry)
+    pub without_cookies: bool,
+}
+
+impl PageRequest<'_> {
+    /// Downloads the page at `url`, or serves the built-in fixture for `chaos://` URLs
+    pub async fn fetch(&self, url: &Url, state: &ProxyState) -> Result<FetchedPage, String> {
+        match chaos::fixture(url, state) {
+            Some(fixture) => fixture.map(|html| FetchedPage::fixture(html, url)),
+            None => fetch_article_html(url, self, state).await,
+        }
+    }
+}
+
+/// Outcome of `logic_extract_article`
+#[derive(Debug, Clone, Default)]
+pub struct ExtractedArticle {
+    /// Extracted HTML, `None` when the iframe fallback should be used
+    pub content: Option<String>,
+    /// `Content-Language` returned by the server
+    pub content_language: Option<String>,
+    /// Optional steps skipped because `deadline_ms` was spent
+    pub skipped_steps: Vec<String>,
+    pub provenance: Option<Provenance>,
+    /// Title, byline and preview fields, when the page itself was at hand
+    pub metadata: ArticleMetadata,
+    /// The URL is a feed rather than an article
+    pub feed: Option<DiscoveredFeed>,
+    /// `ArticleOptions::words_per_minute`
+    pub words_per_minute: Option<u32>,
+}
+
+/// Title, byline and preview fields of an article: the title from readability, the rest from
+/// the page's OpenGraph and meta tags, then from the content
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ArticleMetadata {
+    pub title: Option<String>,
+    pub byline: Option<String>,
+    /// Page description, else the start of the first paragraph
+    pub excerpt: Option<String>,
+    pub lead_image_url: Option<String>,
+    pub site_name: Option<String>,
+    /// URL of the page after redirects
+    pub final_url: Option<String>,
+    /// Publication date: JSON-LD, else OpenGraph and meta tags, else `<time>` elements
+    pub published: Option<ParsedDate>,
+}
+
+/// Extracted content annotated for scroll-depth tracking
+#[derive(Debug, Clone, Serialize)]
+pub struct SegmentedArticle {
+    /// Article HTML whose blocks carry `data-offset` (words preceding the block).
+    /// Empty when `fallback` is set.
+    pub content: String,
+    pub total_words: usize,
+    /// Extraction failed and the page should be shown through the iframe fallback
+    pub fallback: bool,
+}
+
+/// Heading of the article outline (table of contents)
+#[derive(Debug, Clone, Serialize)]
+pub struct OutlineEntry {
+    /// Heading level, 1 to 4
+    pub level: u8,
+    pub text: String,
+    /// Id of the heading element in the returned HTML, usable as a URL fragment
+    pub id: String,
+}
+
+/// Structured result of an article fetch
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ArticleResult {
+    /// Extracted article HTML. Empty when `fallback` is set.
+    pub content: String,
+    /// Text of `content`, one paragraph per block separated by blank lines
+    pub plain_text: String,
+    /// Words of `plain_text`, Chinese and Japanese counted by characters (see `count_words`)
+    pub word_count: usize,
+    /// Minutes `word_count` takes to read at the fetch's `words_per_minute`, at least 1 when
+    /// there is any text
+    pub reading_time_minutes: u32,
+    /// Extraction failed and the page should be shown through the iframe fallback
+    /// (`FALLBACK_SIGNAL` of the former string result)
+    pub fallback: bool,
+    /// Table of contents of the extracted content; every entry's id exists in `content`
+    pub outline: Vec<OutlineEntry>,
+    /// `Content-Language` the server returned, to spot a mismatch with the requested languages
+    pub content_language: Option<String>,
+    /// Structural fingerprint of `content` for spotting syndicated copies (see `layout_fingerprint`)
+    pub layout_fingerprint: Option<String>,
+    /// Flesch–Kincaid grade level of the prose. `None` for short or non-Latin-script content.
+    pub reading_grade: Option<f64>,
+    /// Flesch reading ease (higher is easier), `None` when `reading_grade` is
+    pub flesch_score: Option<f64>,
+    /// Optional steps skipped because `deadline_ms` was spent
+    pub skipped_steps: Vec<String>,
+    /// Pull-quotes, callouts and key-takeaway panels of `content` (with `classify_blocks` only)
+    pub blocks: Vec<ClassifiedBlock>,
+    /// Where the content came from and how it was processed
+    pub provenance: Option<Provenance>,
+    /// The URL is a feed rather than an article: `content` is empty and `fallback` unset, the
+    /// frontend offers to subscribe instead
+    pub is_feed: Option<DiscoveredFeed>,
+    /// Set in the fallback case too, so the frontend can title the iframe view
+    #[serde(flatten)]
+    pub metadata: ArticleMetadata,
+}
+
+/// Content with ids added to its headings, plus the matching outline
+#[derive(Debug, Clone, Serialize)]
+pub struct OutlinedHtml {
+    pub content: String,
+    pub outline: Vec<OutlineEntry>,
+}
+
+/// Metadata a share sheet needs to render a rich preview of an article
+#[derive(Debug, Clone, Serialize)]
+pub struct ShareMeta {
+    pub canonical_url: String,
+    pub title: Option<String>,
+    pub description: Option<String>,
+    pub image: Option<String>,
+    pub site_name: Option<String>,
+    pub author: Option<String>,
+    /// Publication date, read in the page's language
+    pub published: Option<ParsedDate>,
+}
+
+/// What a destructive command touched — or would touch, when run with `dry_run`.
+/// Every store reports through this type so the UI can preview any clear/prune action.
+#[derive(Debug, Default, Serialize)]
+pub struct MutationReport {
+    pub dry_run: bool,
+    pub count: usize,
+    pub entries: Vec<MutationEntry>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct MutationEntry {
+    /// Store the entry belongs to ("auth_credentials", "cookies", "transfers", ...)
+    pub store: String,
+    /// Human-readable key (domain, `name@domain/path`, token, ...)
+    pub key: String,
+    /// Size in bytes, when meaningful for the store
+    pub size: Option<usize>,
+}
+
+impl MutationReport {
+    pub fn new(dry_run: bool) -> Self {
+        Self { dry_run, ..Self::default() }
+    }
+
+    pub fn record(&mut self, store: &str, key: impl Into<String>, size: Option<usize>) {
+        self.entries.push(MutationEntry { store: store.to_string(), key: key.into(), size });
+        self.count += 1;
+    }
+
+    /// Appends the entries of another store's report
+    pub fn merge(&mut self, other: MutationReport) {
+        self.count += other.count;
+        self.entries.extend(other.entries);
+    }
+
+    /// Keys recorded for `store`
+    pub fn keys(&self, store: &str) -> Vec<String> {
+        self.entries.iter().filter(|entry| entry.store == store).map(|entry| entry.key.clone()).collect()
+    }
+}
+
+// --- Body Reading Helpers ---
+
+/// Reads a response body chunk by chunk, enforcing `limit` on the decompressed bytes.
+/// reqwest inflates gzip/brotli/deflate transparently, so each chunk is already
+/// decompressed: we abort as soon as the limit is crossed instead of inflating everything.
+pub async fn read_body_limited(mut response: reqwest::Response, limit: usize) -> Result<Vec<u8>, String> {
+    let mut body = Vec::new();
+    while let Some(chunk) = response.chunk().await.map_err(|e| e.to_string())? {
+        if body.len() + chunk.len() > limit {
+            println!("[shared::read_body_limited] Body exceeded {} bytes after decompression, aborting", limit);
+            return Err(format!("{}:{}", BODY_TOO_LARGE, limit));
+        }
+        body.extend_from_slice(&chunk);
+    }
+    Ok(body)
+}
+
+/// Charset parameter of a response's `Content-Type`. Parameter names are case-insensitive
+/// and legacy servers send `Charset=` or spaces around `=`.
+pub fn header_charset(headers: &reqwest::header::HeaderMap) -> Option<String> {
+    headers
+        .get(reqwest::header::CONTENT_TYPE)
+        .and_then(|ct| ct.to_str().ok())
+        .and_then(|ct| {
+            ct.split(';')
+                .skip(1)
+                .filter_map(|param| param.split_once('='))
+                .find(|(name, _)| name.trim().eq_ignore_ascii_case("charset"))
+                .map(|(_, charset)| charset.trim().trim_matches('"').to_string())
+        })
+}
+
+/// Like `read_body_limited`, decoding the bytes with the charset from `Content-Type`
+/// (UTF-8 when absent or unknown), as `Response::text` would.
+pub async fn read_text_limited(response: reqwest::Response, limit: usize) -> Result<String, String> {
+    let charset = header_charset(response.headers());
+    let bytes = read_body_limited(response, limit).await?;
+    Ok(decode_text(&bytes, charset.as_deref()))
+}
+
+/// Decodes `bytes` with `charset` (UTF-8 when absent or unknown)
+pub fn decode_text(bytes: &[u8], charset: Option<&str>) -> String {
+    let encoding = charset
+        .and_then(|label| encoding_rs::Encoding::for_label(label.as_bytes()))
+        .unwrap_or(encoding_rs::UTF_8);
+    let (text, _, _) = encoding.decode(bytes);
+    text.into_owned()
+}
+
+/// Bytes of an HTML document searched for a `<meta>` charset, as browsers do
+const META_CHARSET_PRESCAN_LEN: usize = 1024;
+
+// `<meta charset=x>` and `<meta http-equiv="Content-Type" content="text/html; charset=x">`
+static META_CHARSET: LazyLock<regex::bytes::Regex> =
+    LazyLock::new(|| regex::bytes::Regex::new(r#"(?i)<meta\s[^>]*?charset\s*=\s*["']?\s*([a-z0-9_:.\-]+)"#).unwrap());
+
+/// Charset declared by a `<meta>` tag at the start of an HTML document. UTF-16 declarations
+/// mean UTF-8, as for browsers (a real UTF-16 document has a BOM).
+fn meta_charset(bytes: &[u8]) -> Option<&'static encoding_rs::Encoding> {
+    let head = &bytes[..bytes.len().min(META_CHARSET_PRESCAN_LEN)];
+    let label = META_CHARSET.captures(head)?.get(1)?.as_bytes();
+    encoding_rs::Encoding::for_label(label).map(|encoding| encoding.output_encoding())
+}
+
+/// Encoding of an HTML document: its BOM, else the charset of the `Content-Type` header or of
+/// the `<meta>` tag. Declarations are often wrong (servers sending a default charset, pages
+/// copied from older ones), so the bytes have the last word: valid UTF-8 is UTF-8, and a
+/// declared charset is only kept if the bytes decode with it. Undeclared legacy text is read
+/// as Windows-1252.
+pub fn html_encoding(bytes: &[u8], header_charset: Option<&str>) -> &'static encoding_rs::Encoding {
+    if let Some((encoding, _)) = encoding_rs::Encoding::for_bom(bytes) {
+        return encoding;
+    }
+    let header = header_charset.and_then(|label| encoding_rs::Encoding::for_label(label.trim().as_bytes()));
+    let declared: Vec<&'static encoding_rs::Encoding> = [header, meta_charset(bytes)].into_iter().flatten().collect();
+
+    // ISO-2022-JP is 7-bit, so always valid UTF-8
+    if std::str::from_utf8(bytes).is_ok() && !declared.contains(&encoding_rs::ISO_2022_JP) {
+        return encoding_rs::UTF_8;
+    }
+    let legacy = declared.iter().copied().filter(|encoding| *encoding != encoding_rs::UTF_8);
+    legacy
+        .clone()
+        .find(|encoding| encoding.decode_without_bom_handling_and_without_replacement(bytes).is_some())
+        .or_else(|| legacy.clone().next())
+        .unwrap_or(encoding_rs::WINDOWS_1252)
+}
+
+/// Like `read_body_limited`, decoding an HTML document with the encoding `html_encoding` finds
+pub async fn read_html_limited(response: reqwest::Response, limit: usize) -> Result<String, String> {
+    let charset = header_charset(response.headers());
+    let bytes = read_body_limited(response, limit).await?;
+    let encoding = html_encoding(&bytes, charset.as_deref());
+    if encoding != encoding_rs::UTF_8 || charset.as_deref().is_some_and(|label| encoding_rs::Encoding::for_label(label.trim().as_bytes()) != Some(encoding)) {
+        println!("[shared::read_html_limited] Decoding as {} (header charset: {:?})", encoding.name(), charset);
+    }
+    let (text, _, _) = encoding.decode(&bytes);
+    Ok(text.into_owned())
+}
+
+// --- URL Helpers ---
+
+/// Origin key for a URL: scheme, host and port when non-default, e.g. `https://example.com`
+/// or `http://[fd00::12]:8080`. Used for auth keys, Origin/Referer values and cache keys.
+pub fn origin_of(url: &Url) -> String {
+    match url.origin() {
+        origin @ url::Origin::Tuple(..) => origin.ascii_serialization(),
+        // Opaque origins (file:, data:, ...) serialize as "null": keep something usable as a key
+        url::Origin::Opaque(_) => format!("{}://{}", url.scheme(), url.host_str().unwrap_or("localhost")),
+    }
+}
+
+/// Value for the `Host` header: host (IPv6 literals bracketed) plus the port when non-default
+pub fn host_header_of(url: &Url) -> String {
+    let host = url.host_str().unwrap_or("localhost");
+    match url.port() {
+        Some(port) => format!("{}:{}", host, port),
+        None => host.to_string(),
+    }
+}
+
+// --- Language Negotiation ---
+
+/// Well-formed language tag (`en`, `en-US`, `zh-Hant-TW`, ...) or the `*` wildcard
+fn is_language_tag(tag: &str) -> bool {
+    if tag == "*" {
+        return true;
+    }
+    let mut subtags = tag.split('-');
+    let primary = subtags.next().unwrap_or("");
+    (2..=8).contains(&primary.len())
+        && primary.chars().all(|c| c.is_ascii_alphabetic())
+        && subtags.all(|subtag| (1..=8).contains(&subtag.len()) && subtag.chars().all(|c| c.is_ascii_alphanumeric()))
+}
+
+/// Builds an `Accept-Language` value from languages in order of preference, with q-values
+/// decreasing by 0.1 (never below 0.1). Rejects malformed tags.
+pub fn accept_language_header(languages: &[String]) -> Result<String, String> {
+    let mut tags: Vec<String> = Vec::new();
+    for language in languages {
+        let tag = language.trim();
+        if !is_language_tag(tag) {
+            return Err(format!("Invalid language tag: '{}'", language));
+        }
+        if !tags.iter().any(|existing| existing.eq_ignore_ascii_case(tag)) {
+            tags.push(tag.to_string());
+        }
+    }
+    if tags.is_empty() {
+        return Err("At least one language is required".into());
+    }
+
+    Ok(tags
+        .iter()
+        .enumerate()
+        .map(|(index, tag)| match index {
+            0 => tag.clone(),
+            _ => format!("{};q={:.1}", tag, (10 - index.min(9)) as f64 / 10.0),
+        })
+        .collect::<Vec<_>>()
+        .join(","))
+}
+
+/// `Accept-Language` for `url`: the override of its host or closest parent domain, `default` otherwise
+pub fn accept_language_for(url: &Url, state: &ProxyState, default: &str) -> String {
+    let mut host = url.host_str().unwrap_or("").to_ascii_lowercase();
+    loop {
+        if let Some(value) = state.accept_languages.get(&host) {
+            return value.clone();
+        }
+        match host.split_once('.') {
+            Some((_, parent)) if parent.contains('.') => host = parent.to_string(),
+            _ => return default.to_string(),
+        }
+    }
+}
+
+/// Sets (or with an empty list, removes) the preferred languages for `domain`
+pub fn logic_set_accept_language(domain: String, languages: Vec<String>, state: &ProxyState) -> Result<Option<String>, String> {
+    let host = host_of_domain_key(&domain);
+    if host.is_empty() {
+        return Err("Domain is required".into());
+    }
+
+    if languages.is_empty() {
+        state.accept_languages.remove(&host);
+        return Ok(None);
+    }
+
+    let header = accept_language_header(&languages)?;
+    println!("[shared::set_accept_language] {} -> {}", host, header);
+    state.accept_languages.insert(host, header.clone());
+    Ok(Some(header))
+}
+
+/// Removes the language overrides of `domain` and its subdomains
+pub fn clear_accept_language_for_domain(domain: &str, dry_run: bool, state: &ProxyState) -> MutationReport {
+    let mut report = MutationReport::new(dry_run);
+    let host = host_of_domain_key(domain);
+
+    let matching: Vec<String> =
+        state.accept_languages.iter().map(|entry| entry.key().clone()).filter(|key| host_in_domain(key, &host)).collect();
+    for key in matching {
+        report.record("accept_languages", key.clone(), None);
+        if !dry_run {
+            state.accept_languages.remove(&key);
+        }
+    }
+    report
+}
+
+// --- Escaping Helpers ---
+
+/// Escapes text for safe inclusion in HTML element content or quoted attribute values.
+pub fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Decodes the character references commonly found in raw text chunks
+/// (named `&amp;`-style entities for markup characters, `&nbsp;`, and numeric references).
+pub fn unescape_html(text: &str) -> String {
+    let mut decoded = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('&') {
+        decoded.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let reference = rest.find(';').filter(|end| *end <= 10).map(|end| &rest[1..end]);
+        let character = reference.and_then(|name| match name {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            "nbsp" => Some('\u{a0}'),
+            _ => {
+                let code = match name.strip_prefix("#x").or_else(|| name.strip_prefix("#X")) {
+                    Some(hex) => u32::from_str_radix(hex, 16).ok(),
+                    None => name.strip_prefix('#').and_then(|dec| dec.parse().ok()),
+                };
+                code.and_then(char::from_u32)
+            }
+        });
+        match (reference, character) {
+            (Some(name), Some(c)) => {
+                decoded.push(c);
+                rest = &rest[name.len() + 2..];
+            }
+            _ => {
+                decoded.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    decoded.push_str(rest);
+    decoded
+}
+
+/// Encodes a value as a JavaScript string literal (including quotes) that is also
+/// safe inside an inline `<script>` block.
+pub fn js_string_literal(text: &str) -> String {
+    js_value_literal(&text, "\"\"")
+}
+
+/// `value` as a JS literal safe to embed in a `<script>` element, or `fallback` if it can't be serialized
+pub fn js_value_literal<T: Serialize + ?Sized>(value: &T, fallback: &str) -> String {
+    serde_json::to_string(value)
+        .unwrap_or_else(|_| fallback.to_string())
+        .replace('<', "\\u003c")
+        .replace('>', "\\u003e")
+        .replace('&', "\\u0026")
+        .replace('\u{2028}', "\\u2028")
+        .replace('\u{2029}', "\\u2029")
+}
+
+// --- HTML Preprocessing ---
+
+/// Containers injected by common consent-management platforms (OneTrust, Cookiebot,
+/// Quantcast, Didomi, Sourcepoint) and generic cookie banners.
+const CONSENT_SELECTORS: &[&str] = &[
+    "#onetrust-consent-sdk",
+    "#onetrust-banner-sdk",
+    "#CybotCookiebotDialog",
+    "#CybotCookiebotDialogBodyUnderlay",
+    "#qc-cmp2-container",
+    ".qc-cmp2-container",
+    "#didomi-host",
+    "div[id^=\"sp_message_container\"]",
+    "#cookie-banner",
+    ".cookie-banner",
+    "#cookie-consent",
+    ".cookie-consent",
+    "div[aria-label*=\"cookie\" i]",
+    "section[aria-label*=\"cookie\" i]",
+    "aside[aria-label*=\"cookie\" i]",
+    "[role=\"dialog\"][aria-label*=\"consent\" i]",
+];
+
+/// z-index from which a `position: fixed` element is considered an overlay
+const OVERLAY_MIN_Z_INDEX: i64 = 1000;
+
+/// Returns true if an inline style describes a fixed element stacked above the page
+/// (the typical shape of a consent wall or modal backdrop).
+fn is_fixed_overlay_style(style: &str) -> bool {
+    let normalized: String = style.to_ascii_lowercase().chars().filter(|c| !c.is_whitespace()).collect();
+    if !normalized.contains("position:fixed") {
+        return false;
+    }
+
+    normalized
+        .split(';')
+        .filter_map(|decl| decl.strip_prefix("z-index:"))
+        .filter_map(|value| value.trim_end_matches("!important").parse::<i64>().ok())
+        .any(|z| z >= OVERLAY_MIN_Z_INDEX)
+}
+
+/// Removes consent/cookie banners and high z-index fixed overlays from raw HTML.
+/// Runs before readability so that late-injected consent markup doesn't end up in
+/// (or replace) the extracted content. Returns the input unchanged if rewriting fails.
+pub fn strip_consent_banners(html: &str) -> String {
+    let selectors = CONSENT_SELECTORS.join(", ");
+
+    let result = rewrite_str(
+        html,
+        RewriteStrSettings {
+            element_content_handlers: vec![
+                element!(selectors, |el| {
+                    el.remove();
+                    Ok(())
+                }),
+                element!("*[style]", |el| {
+                    if let Some(style) = el.get_attribute("style") {
+                        if is_fixed_overlay_style(&style) {
+                            el.remove();
+                        }
+                    }
+                    Ok(())
+                }),
+            ],
+            ..RewriteStrSettings::default()
+        },
     );
 
-    // Check for auth credentials for this domain
-    let auth_credentials = {
-        let creds = state.auth_credentials.lock().unwrap();
-        creds.get(&domain).cloned()
+    match result {
+        Ok(cleaned) => cleaned,
+        Err(e) => {
+            println!("[shared::strip_consent_banners] Rewriting failed, keeping original HTML: {}", e);
+            html.to_string()
+        }
+    }
+}
+
+// --- Noscript Images ---
+
+/// URL fragments of tracking pixels and spacers
+const JUNK_IMAGE_MARKERS: &[&str] = &["pixel", "beacon", "spacer.gif", "blank.gif"];
+
+/// URL fragments of known tracker endpoints serving beacon images
+const TRACKER_IMAGE_MARKERS: &[&str] = &[
+    "facebook.com/tr",
+    "doubleclick.net",
+    "google-analytics.com",
+    "scorecardresearch.com",
+    "quantserve.com",
+    "/b/ss/",
+];
+
+/// Attributes lazy-loading scripts use to hold the real image URL
+pub const LAZY_IMAGE_ATTRIBUTES: &[&str] = &["data-src", "data-lazy-src", "data-original", "data-url"];
+
+/// `srcset` counterparts of `LAZY_IMAGE_ATTRIBUTES`
+const LAZY_SRCSET_ATTRIBUTES: &[&str] = &["data-srcset", "data-lazy-srcset"];
+
+/// Tracking pixels, spacers and other images not worth showing: tiny declared
+/// dimensions, or a URL matching a known tracker/spacer pattern
+pub fn is_junk_image(src: &str, width: Option<&str>, height: Option<&str>) -> bool {
+    let tiny = |value: Option<&str>| {
+        value
+            .and_then(|v| v.trim().trim_end_matches("px").parse::<u32>().ok())
+            .is_some_and(|v| v <= 2)
     };
+    let src = src.trim().to_ascii_lowercase();
+    src.is_empty() || tiny(width) || tiny(height) || JUNK_IMAGE_MARKERS.iter().chain(TRACKER_IMAGE_MARKERS).any(|marker| src.contains(marker))
+}
 
-    // Use shared cookie jar for session persistence (important for CSRF tokens)
-    let client = reqwest::Client::builder()
-        .cookie_store(true)
-        .cookie_provider(state.cookie_jar.clone())
-        .timeout(Duration::from_secs(30))
-        .redirect(reqwest::redirect::Policy::limited(10))
-        .gzip(true)
-        .brotli(true)
-        .deflate(true)
-        .build()
-        .map_err(|e| e.to_string())?;
+/// 1x1 beacons: both declared dimensions tiny, or a known tracker URL. Stricter than
+/// `is_junk_image`, whose generic markers could match real photos of the content.
+fn is_tracking_pixel(src: &str, width: Option<&str>, height: Option<&str>) -> bool {
+    let tiny = |value: Option<&str>| value.and_then(|v| v.trim().trim_end_matches("px").parse::<u32>().ok()).is_some_and(|v| v <= 1);
+    let src = src.trim().to_ascii_lowercase();
+    (tiny(width) && tiny(height)) || TRACKER_IMAGE_MARKERS.iter().any(|marker| src.contains(marker))
+}
 
-    // Headers matching the working Python implementation - no Sec-Fetch-* headers
-    let mut request_builder = client
-        .get(url_obj.clone())
-        .header(USER_AGENT, "Mozilla/5.0 (Windows NT 10.0; Win64; x64; rv:75.0) Gecko/20100101 Firefox/75.0")
-        .header("Accept", "text/html,application/xhtml+xml,application/xml;q=0.9,image/webp,image/apng,*/*;q=0.8")
-        .header("Accept-Encoding", "gzip, deflate, br")
-        .header("Accept-Language", "fr-FR,fr;q=0.8,en-US;q=0.6,en;q=0.4")
-        .header("Cache-Control", "no-cache")
-        .header("Pragma", "no-cache")
-        .header("Connection", "keep-alive")
-        .header("Upgrade-Insecure-Requests", "1");
+/// Whether `<noscript>` markup holds at least one real image (`<picture>` or a non-junk `<img>`)
+fn noscript_has_images(markup: &str) -> bool {
+    let fragment = scraper::Html::parse_fragment(markup);
+    let pictures = scraper::Selector::parse("picture").unwrap();
+    let images = scraper::Selector::parse("img").unwrap();
+    fragment.select(&pictures).next().is_some()
+        || fragment.select(&images).any(|img| {
+            let img = img.value();
+            !is_junk_image(img.attr("src").unwrap_or(""), img.attr("width"), img.attr("height"))
+        })
+}
 
-    // Add HTTP Basic Auth if credentials are available
-    if let Some((username, password)) = auth_credentials {
-        println!("Adding HTTP Basic Auth for domain: {}", domain);
-        request_builder = request_builder.basic_auth(username, Some(password));
+/// Replaces `<noscript>` blocks holding real images with their markup, so the proxy's URL
+/// rewriting and readability both see the images lazy-loading scripts would have inserted.
+/// Blocks with only tracking pixels (or no images) are left alone. Script placeholders made
+/// redundant by an unwrapped image (same URL in a lazy-loading attribute) are removed.
+/// Returns the input unchanged if rewriting fails.
+pub fn unwrap_noscript_images(html: &str) -> String {
+    if !html.contains("<noscript") && !html.contains("<NOSCRIPT") {
+        return html.to_string();
     }
 
-    let response = request_builder
-        .send()
-        .await
-        .map_err(|e| e.to_string())?;
+    // Noscript contents arrive as raw text; buffer them to decide once the block is complete
+    let buffer = RefCell::new(String::new());
+    let unwrapped_sources = RefCell::new(HashSet::new());
+    let images = scraper::Selector::parse("img").unwrap();
 
-    println!("[shared::fetch_raw_html] Response status: {} for URL: {}", response.status(), url);
+    let unwrapped = rewrite_str(
+        html,
+        RewriteStrSettings {
+            element_content_handlers: vec![
+                element!("noscript", |el| {
+                    el.remove_and_keep_content();
+                    Ok(())
+                }),
+                text!("noscript", |t| {
+                    buffer.borrow_mut().push_str(t.as_str());
+                    if !t.last_in_text_node() {
+                        t.remove();
+                        return Ok(());
+                    }
 
-    // Check for 401 Unauthorized
-    if response.status() == reqwest::StatusCode::UNAUTHORIZED {
-        println!("fetch_raw_html: 401 Unauthorized for URL: {}", url);
-        return Err(format!("AUTH_REQUIRED:{}", domain));
+                    let markup = buffer.take();
+                    if noscript_has_images(&markup) {
+                        let fragment = scraper::Html::parse_fragment(&markup);
+                        let mut sources = unwrapped_sources.borrow_mut();
+                        sources.extend(fragment.select(&images).filter_map(|img| img.value().attr("src")).map(str::to_string));
+                        t.replace(&markup, ContentType::Html);
+                    } else {
+                        t.replace(&format!("<noscript>{}</noscript>", markup), ContentType::Html);
+                    }
+                    Ok(())
+                }),
+            ],
+            ..RewriteStrSettings::default()
+        },
+    );
+
+    let unwrapped = match unwrapped {
+        Ok(unwrapped) => unwrapped,
+        Err(e) => {
+            println!("[shared::unwrap_noscript_images] Rewriting failed, keeping original HTML: {}", e);
+            return html.to_string();
+        }
+    };
+
+    let sources = unwrapped_sources.into_inner();
+    if sources.is_empty() {
+        return unwrapped;
+    }
+
+    let deduplicated = rewrite_str(
+        &unwrapped,
+        RewriteStrSettings {
+            element_content_handlers: vec![element!("img", |el| {
+                let placeholder = el.get_attribute("src").is_none_or(|src| src.is_empty() || src.starts_with("data:"));
+                let lazy_source = LAZY_IMAGE_ATTRIBUTES.iter().find_map(|attr| el.get_attribute(attr));
+                if placeholder && lazy_source.is_some_and(|src| sources.contains(&src)) {
+                    el.remove();
+                }
+                Ok(())
+            })],
+            ..RewriteStrSettings::default()
+        },
+    );
+
+    println!("[shared::unwrap_noscript_images] Unwrapped {} noscript images", sources.len());
+    deduplicated.unwrap_or(unwrapped)
+}
+
+/// Copies the real image URLs lazy-loading scripts would have set (`data-src`, `data-srcset` and
+/// their variants) onto `src` and `srcset`, replacing the placeholders readability would keep.
+/// Returns the input unchanged if rewriting fails.
+pub fn promote_lazy_images(html: &str) -> String {
+    if !html.contains("data-") {
+        return html.to_string();
+    }
+
+    let promoted = Cell::new(0usize);
+    // Values are copied as written in the page, entities included
+    let lazy_value = |el: &lol_html::html_content::Element, attributes: &[&str]| {
+        attributes.iter().filter_map(|attr| el.get_attribute(attr)).find(|value| {
+            let value = value.trim();
+            !value.is_empty() && !value.starts_with("data:")
+        })
+    };
+    let result = rewrite_str(
+        html,
+        RewriteStrSettings {
+            element_content_handlers: vec![element!("img, picture source", |el| {
+                let mut changed = false;
+                if el.tag_name() == "img" {
+                    if let Some(src) = lazy_value(el, LAZY_IMAGE_ATTRIBUTES).filter(|src| el.get_attribute("src").as_ref() != Some(src)) {
+                        el.set_attribute("src", src.trim())?;
+                        changed = true;
+                    }
+                }
+                if let Some(srcset) = lazy_value(el, LAZY_SRCSET_ATTRIBUTES).filter(|srcset| el.get_attribute("srcset").as_ref() != Some(srcset)) {
+                    el.set_attribute("srcset", srcset.trim())?;
+                    changed = true;
+                }
+                if changed {
+                    promoted.set(promoted.get() + 1);
+                }
+                Ok(())
+            })],
+            ..RewriteStrSettings::default()
+        },
+    );
+
+    match result {
+        Ok(output) => {
+            if promoted.get() > 0 {
+                println!("[shared::promote_lazy_images] Promoted {} lazy-loaded images", promoted.get());
+            }
+            output
+        }
+        Err(e) => {
+            println!("[shared::promote_lazy_images] Rewriting failed, keeping original HTML: {}", e);
+            html.to_string()
+        }
+    }
+}
+
+// --- AMP Elements ---
+
+/// Attributes carried over when an AMP media element becomes its HTML counterpart
+const AMP_MEDIA_ATTRIBUTES: &[&str] = &["src", "srcset", "sizes", "alt", "title", "width", "height", "poster"];
+
+/// AMP-only attributes dropped from renamed elements
+const AMP_LAYOUT_ATTRIBUTES: &[&str] = &["layout", "heights", "noloading", "lightbox", "autoplay"];
+
+/// `<tag ...>` with the AMP media attributes of `el`
+fn html_tag_from_amp(tag: &str, el: &lol_html::html_content::Element) -> String {
+    let mut markup = format!("<{}", tag);
+    for attr in AMP_MEDIA_ATTRIBUTES {
+        if let Some(value) = el.get_attribute(attr) {
+            markup.push_str(&format!(" {}=\"{}\"", attr, escape_html(&unescape_html(&value))));
+        }
+    }
+    markup.push('>');
+    markup
+}
+
+/// Renames an AMP element keeping its children, dropping AMP layout attributes
+fn rename_amp_element(el: &mut lol_html::html_content::Element, tag: &str) {
+    // Tag names only contain ASCII letters, which set_tag_name accepts
+    let _ = el.set_tag_name(tag);
+    for attr in AMP_LAYOUT_ATTRIBUTES {
+        el.remove_attribute(attr);
+    }
+}
+
+/// Converts AMP custom elements to the HTML elements readability understands: `amp-img` and
+/// `amp-anim` to `img`, `amp-video`/`amp-audio` to `video`/`audio` (with controls),
+/// `amp-iframe` to `iframe`, `amp-youtube` to a YouTube embed, and `amp-carousel` to a `div`
+/// of its slides. Placeholder and fallback images of AMP elements are dropped, the element
+/// itself carries the media. Returns the input unchanged if it has no AMP elements or
+/// rewriting fails.
+pub fn convert_amp_elements(html: &str) -> String {
+    if !html.contains("<amp-") {
+        return html.to_string();
+    }
+
+    let converted = Cell::new(0usize);
+    let result = rewrite_str(
+        html,
+        RewriteStrSettings {
+            element_content_handlers: vec![
+                element!("amp-img[placeholder], amp-img[fallback], amp-anim[placeholder]", |el| {
+                    el.remove();
+                    Ok(())
+                }),
+                element!("amp-img:not([placeholder]):not([fallback]), amp-anim:not([placeholder])", |el| {
+                    el.replace(&html_tag_from_amp("img", el), ContentType::Html);
+                    converted.set(converted.get() + 1);
+                    Ok(())
+                }),
+                element!("amp-video, amp-audio", |el| {
+                    let tag = if el.tag_name() == "amp-video" { "video" } else { "audio" };
+                    rename_amp_element(el, tag);
+                    el.set_attribute("controls", "").ok();
+                    converted.set(converted.get() + 1);
+                    Ok(())
+                }),
+                element!("amp-iframe", |el| {
+                    rename_amp_element(el, "iframe");
+                    converted.set(converted.get() + 1);
+                    Ok(())
+                }),
+                element!("amp-youtube[data-videoid]", |el| {
+                    let video_id = el.get_attribute("data-videoid").unwrap_or_default();
+                    let embed = format!(
+                        "<iframe src=\"https://www.youtube.com/embed/{}\" width=\"{}\" height=\"{}\" allowfullscreen></iframe>",
+                        urlencoding::encode(&unescape_html(&video_id)),
+                        escape_html(&el.get_attribute("width").unwrap_or_else(|| "560".into())),
+                        escape_html(&el.get_attribute("height").unwrap_or_else(|| "315".into())),
+                    );
+                    el.replace(&embed, ContentType::Html);
+                    converted.set(converted.get() + 1);
+                    Ok(())
+                }),
+                element!("amp-carousel", |el| {
+                    rename_amp_element(el, "div");
+                    el.remove_attribute("type");
+                    Ok(())
+                }),
+            ],
+            ..RewriteStrSettings::default()
+        },
+    );
+
+    match result {
+        Ok(result) => {
+            if converted.get() > 0 {
+                println!("[shared::convert_amp_elements] Converted {} AMP media elements", converted.get());
+            }
+            result
+        }
+        Err(e) => {
+            println!("[shared::convert_amp_elements] Rewriting failed, keeping original HTML: {}", e);
+            html.to_string()
+        }
+    }
+}
+
+// --- Read More Expanders ---
+
+/// Containers the article body is expected in. Hidden elements are only revealed inside them.
+const ARTICLE_REGION_SELECTORS: &[&str] = &[
+    "article",
+    "main",
+    "[role=\"main\"]",
+    "[itemprop=\"articleBody\"]",
+    ".article-body",
+    ".article-content",
+    ".entry-content",
+    ".post-content",
+    ".story-body",
+];
+
+/// Ways pages hide the rest of a truncated article
+const HIDDEN_CONTAINER_SELECTORS: &[&str] = &[
+    "[hidden]",
+    "[aria-hidden=\"true\"]",
+    "[style*=\"display:none\"]",
+    "[style*=\"display: none\"]",
+    "[class~=\"hidden\"]",
+    "[class~=\"is-hidden\"]",
+    "[class~=\"d-none\"]",
+    "[class~=\"collapsed\"]",
+    "[class~=\"truncated\"]",
+];
+
+/// Classes removed from revealed containers
+const HIDING_CLASSES: &[&str] = &["hidden", "is-hidden", "d-none", "collapsed", "truncated"];
+
+/// Elements that can act as a "read more" toggle
+const EXPANDER_TRIGGER_SELECTORS: &[&str] = &["a", "button", "label", "[role=\"button\"]"];
+
+/// Class/id fragments of "read more" toggles
+const EXPANDER_TRIGGER_MARKERS: &[&str] = &["read-more", "readmore", "read_more", "continue-reading", "show-more", "showmore"];
+
+/// Labels of "read more" toggles, lowercased
+const EXPANDER_TRIGGER_LABELS: &[&str] = &[
+    "read more",
+    "continue reading",
+    "read the full",
+    "show more",
+    "lire la suite",
+    "afficher la suite",
+    "weiterlesen",
+    "leer más",
+    "continua a leggere",
+];
+
+/// Minimum text (in non-whitespace chars) of a hidden container to be taken for the rest of the article
+const MIN_REVEALED_TEXT_LEN: usize = 200;
+
+/// Content with its "read more" containers revealed
+#[derive(Debug, Clone, Serialize)]
+pub struct RevealedHtml {
+    pub content: String,
+    /// Number of hidden containers that were revealed
+    pub revealed: usize,
+}
+
+/// `region hidden` selector list for every article region
+fn scoped_selectors(inner: &[&str]) -> String {
+    ARTICLE_REGION_SELECTORS
+        .iter()
+        .flat_map(|region| inner.iter().map(move |selector| format!("{} {}", region, selector)))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn is_expander_trigger(class_and_id: &str) -> bool {
+    let class_and_id = class_and_id.to_ascii_lowercase();
+    EXPANDER_TRIGGER_MARKERS.iter().any(|marker| class_and_id.contains(marker))
+}
+
+fn is_expander_label(text: &str) -> bool {
+    let text = text.trim().to_lowercase();
+    !text.is_empty() && text.len() <= 40 && EXPANDER_TRIGGER_LABELS.iter().any(|label| text.starts_with(label))
+}
+
+/// Removes `display: none` declarations from an inline style
+fn strip_display_none(style: &str) -> String {
+    style
+        .split(';')
+        .filter(|declaration| {
+            let compact: String = declaration.chars().filter(|c| !c.is_whitespace()).collect();
+            !compact.eq_ignore_ascii_case("display:none")
+        })
+        .collect::<Vec<_>>()
+        .join(";")
+}
+
+/// Reveals the rest of articles hidden behind a "read more" toggle. Only applies when a
+/// toggle is found inside the article region, and only to hidden containers in that region
+/// holding at least `MIN_REVEALED_TEXT_LEN` of text, so unrelated hidden content (menus,
+/// dialogs, off-article widgets) stays hidden. Toggles are removed once something is revealed.
+/// Text loaded on click from an endpoint is not fetched.
+pub fn reveal_hidden_content(html: &str) -> Result<(String, usize), String> {
+    let hidden_selectors = scoped_selectors(HIDDEN_CONTAINER_SELECTORS);
+    let trigger_selectors = scoped_selectors(EXPANDER_TRIGGER_SELECTORS);
+
+    // First pass: find triggers and measure the text of every hidden candidate, in document order
+    let has_trigger = Cell::new(false);
+    let text_lens: Rc<RefCell<Vec<usize>>> = Rc::new(RefCell::new(Vec::new()));
+    let open: Rc<RefCell<Vec<usize>>> = Rc::new(RefCell::new(Vec::new()));
+
+    rewrite_str(
+        html,
+        RewriteStrSettings {
+            element_content_handlers: vec![
+                element!(trigger_selectors, |el| {
+                    let class_and_id = format!(
+                        "{} {}",
+                        el.get_attribute("class").unwrap_or_default(),
+                        el.get_attribute("id").unwrap_or_default()
+                    );
+                    if is_expander_trigger(&class_and_id) {
+                        has_trigger.set(true);
+                    }
+                    Ok(())
+                }),
+                text!(trigger_selectors, |t| {
+                    if is_expander_label(t.as_str()) {
+                        has_trigger.set(true);
+                    }
+                    Ok(())
+                }),
+                element!(hidden_selectors, |el| {
+                    let index = {
+                        let mut text_lens = text_lens.borrow_mut();
+                        text_lens.push(0);
+                        text_lens.len() - 1
+                    };
+                    if let Some(handlers) = el.end_tag_handlers() {
+                        open.borrow_mut().push(index);
+                        let open = open.clone();
+                        handlers.push(Box::new(move |_| {
+                            open.borrow_mut().pop();
+                            Ok(())
+                        }));
+                    }
+                    Ok(())
+                }),
+            ],
+            document_content_handlers: vec![doc_text!(|t| {
+                if t.text_type() == TextType::Data {
+                    let len = t.as_str().chars().filter(|c| !c.is_whitespace()).count();
+                    let mut text_lens = text_lens.borrow_mut();
+                    for &index in open.borrow().iter() {
+                        text_lens[index] += len;
+                    }
+                }
+                Ok(())
+            })],
+            ..RewriteStrSettings::default()
+        },
+    )
+    .map_err(|e| e.to_string())?;
+
+    let text_lens = text_lens.take();
+    let revealable = text_lens.iter().filter(|len| **len >= MIN_REVEALED_TEXT_LEN).count();
+    if !has_trigger.get() || revealable == 0 {
+        return Ok((html.to_string(), 0));
+    }
+
+    // Second pass: same selectors, so candidates come in the same order
+    let next_candidate = Cell::new(0usize);
+    let content = rewrite_str(
+        html,
+        RewriteStrSettings {
+            element_content_handlers: vec![
+                element!(hidden_selectors, |el| {
+                    let index = next_candidate.get();
+                    next_candidate.set(index + 1);
+                    if text_lens.get(index).is_some_and(|len| *len >= MIN_REVEALED_TEXT_LEN) {
+                        el.remove_attribute("hidden");
+                        el.remove_attribute("aria-hidden");
+                        if let Some(style) = el.get_attribute("style") {
+                            el.set_attribute("style", &strip_display_none(&style))?;
+                        }
+                        if let Some(class) = el.get_attribute("class") {
+                            let kept: Vec<&str> = class.split_whitespace().filter(|c| !HIDING_CLASSES.contains(c)).collect();
+                            el.set_attribute("class", &kept.join(" "))?;
+                        }
+                    }
+                    Ok(())
+                }),
+                element!(trigger_selectors, |el| {
+                    let class_and_id = format!(
+                        "{} {}",
+                        el.get_attribute("class").unwrap_or_default(),
+                        el.get_attribute("id").unwrap_or_default()
+                    );
+                    if is_expander_trigger(&class_and_id) {
+                        el.remove();
+                    }
+                    Ok(())
+                }),
+                text!(trigger_selectors, |t| {
+                    if is_expander_label(t.as_str()) {
+                        t.remove();
+                    }
+                    Ok(())
+                }),
+            ],
+            ..RewriteStrSettings::default()
+        },
+    )
+    .map_err(|e| e.to_string())?;
+
+    println!("[shared::reveal_hidden_content] Revealed {} hidden containers", revealable);
+    Ok((content, revealable))
+}
+
+// --- Extraction Fallbacks ---
+
+/// Minimum paragraph text (in chars) for a container to count as the article body
+const MIN_CONTAINER_TEXT_LEN: usize = 140;
+
+/// Finds the container whose direct paragraph children hold the most text and returns its HTML.
+/// The `dense_container` extractor backend, and the lenient fallback of readability.
+pub fn largest_text_container(html: &str) -> Option<(String, usize)> {
+    let document = scraper::Html::parse_document(html);
+    let containers = scraper::Selector::parse("article, main, section, div, td").unwrap();
+    let paragraphs = scraper::Selector::parse(":scope > p, :scope > h2, :scope > h3, :scope > blockquote, :scope > ul, :scope > ol").unwrap();
+
+    document
+        .select(&containers)
+        .map(|container| {
+            let text_len: usize = container
+                .select(&paragraphs)
+                .map(|p| p.text().map(|t| t.trim().chars().count()).sum::<usize>())
+                .sum();
+            (container, text_len)
+        })
+        .filter(|(_, text_len)| *text_len >= MIN_CONTAINER_TEXT_LEN)
+        .max_by_key(|(_, text_len)| *text_len)
+        .map(|(container, text_len)| (container.html(), text_len))
+}
+
+// --- Rendering Overrides ---
+
+/// Whether `url` is on a host flagged with `set_host_requires_rendering`
+pub fn requires_rendering(url: &Url, state: &ProxyState) -> bool {
+    let host = url.host_str().unwrap_or("").to_ascii_lowercase();
+    state.rendering_hosts.iter().any(|flagged| host_in_domain(&host, &flagged))
+}
+
+/// Flags (or unflags) `host` and its subdomains as always needing the JS-rendered path,
+/// so extraction is skipped for them
+pub fn logic_set_host_requires_rendering(host: String, requires_rendering: bool, state: &ProxyState) -> Result<(), String> {
+    let host = host_of_domain_key(&host);
+    if host.is_empty() {
+        return Err("Host is required".into());
+    }
+
+    println!("[shared::set_host_requires_rendering] {} -> {}", host, requires_rendering);
+    if requires_rendering {
+        state.rendering_hosts.insert(host);
+    } else {
+        state.rendering_hosts.remove(&host);
+    }
+    Ok(())
+}
+
+/// Removes the rendering overrides of `domain` and its subdomains
+pub fn clear_rendering_override_for_domain(domain: &str, dry_run: bool, state: &ProxyState) -> MutationReport {
+    let mut report = MutationReport::new(dry_run);
+    let host = host_of_domain_key(domain);
+
+    let matching: Vec<String> = state.rendering_hosts.iter().map(|key| key.clone()).filter(|key| host_in_domain(key, &host)).collect();
+    for key in matching {
+        report.record("rendering_hosts", key.clone(), None);
+        if !dry_run {
+            state.rendering_hosts.remove(&key);
+        }
+    }
+    report
+}
+
+// --- Crawler Retry ---
+
+/// User-Agent of the crawler retry: Googlebot's, which soft paywalls let through
+pub const CRAWLER_USER_AGENT: &str = "Mozilla/5.0 (compatible; Googlebot/2.1; +http://www.google.com/bot.html)";
+
+/// Extracted text shorter than this many times the feed excerpt looks like a teaser
+const TEASER_EXCERPT_RATIO: usize = 3;
+
+/// Extracted text shorter than this (in chars) looks like a teaser when no feed excerpt is given
+const TEASER_MAX_LEN: usize = 600;
+
+/// Whether `url` is on a host flagged with `set_host_crawler_retry`
+pub fn retries_as_crawler(url: &Url, state: &ProxyState) -> bool {
+    let host = url.host_str().unwrap_or("").to_ascii_lowercase();
+    state.crawler_retry_hosts.iter().any(|flagged| host_in_domain(&host, &flagged))
+}
+
+/// Flags (or unflags) `host` and its subdomains as serving crawlers the full article and
+/// browsers a teaser: short extractions are retried as Googlebot, without cookies
+pub fn logic_set_host_crawler_retry(host: String, enabled: bool, state: &ProxyState) -> Result<(), String> {
+    let host = host_of_domain_key(&host);
+    if host.is_empty() {
+        return Err("Host is required".into());
+    }
+
+    println!("[shared::set_host_crawler_retry] {} -> {}", host, enabled);
+    if enabled {
+        state.crawler_retry_hosts.insert(host);
+    } else {
+        state.crawler_retry_hosts.remove(&host);
+    }
+    Ok(())
+}
+
+/// Removes the crawler retry flags of `domain` and its subdomains
+pub fn clear_crawler_retry_for_domain(domain: &str, dry_run: bool, state: &ProxyState) -> MutationReport {
+    let mut report = MutationReport::new(dry_run);
+    let host = host_of_domain_key(domain);
+
+    let matching: Vec<String> = state.crawler_retry_hosts.iter().map(|key| key.clone()).filter(|key| host_in_domain(key, &host)).collect();
+    for key in matching {
+        report.record("crawler_retry_hosts", key.clone(), None);
+        if !dry_run {
+            state.crawler_retry_hosts.remove(&key);
+        }
+    }
+    report
+}
+
+/// Whether `content_len` chars of extracted text look like the teaser a soft paywall serves:
+/// less than `TEASER_EXCERPT_RATIO` times the feed excerpt, or than `TEASER_MAX_LEN` without one
+fn looks_like_teaser(content_len: usize, feed_excerpt: Option<&str>) -> bool {
+    match feed_excerpt.map(|excerpt| plain_text(excerpt).chars().count()).filter(|len| *len > 0) {
+        Some(excerpt_len) => content_len < excerpt_len * TEASER_EXCERPT_RATIO,
+        None => content_len < TEASER_MAX_LEN,
+    }
+}
+
+// --- Protocol Overrides ---
+
+/// Whether requests to `url` must stay on HTTP/1.1, globally or because its host is flagged
+pub fn forces_http1(url: &Url, state: &ProxyState) -> bool {
+    if state.force_http1.load(Ordering::Relaxed) {
+        return true;
+    }
+    let host = url.host_str().unwrap_or("").to_ascii_lowercase();
+    state.http1_hosts.iter().any(|flagged| host_in_domain(&host, &flagged))
+}
+
+/// HTTP versions a client may use with an origin
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Protocol {
+    /// reqwest's usual negotiation (HTTP/2 when the origin offers it)
+    Negotiated,
+    /// HTTP/1.1 only, for origins flagged by `set_force_http1`
+    Http1Only,
+}
+
+/// Protocol setting of requests to `url`
+pub fn protocol_for(url: &Url, state: &ProxyState) -> Protocol {
+    if forces_http1(url, state) {
+        Protocol::Http1Only
+    } else {
+        Protocol::Negotiated
+    }
+}
+
+/// `builder` restricted to HTTP/1.1 when `url` requires it; reqwest's usual protocol
+/// negotiation otherwise
+pub fn with_protocol_for(builder: reqwest::ClientBuilder, url: &Url, state: &ProxyState) -> reqwest::ClientBuilder {
+    match protocol_for(url, state) {
+        Protocol::Http1Only => builder.http1_only(),
+        Protocol::Negotiated => builder,
+    }
+}
+
+/// Forces HTTP/1.1 for `domain` and its subdomains, or for every request when `domain` is
+/// `None`. A workaround for origins that hang or fail under HTTP/2.
+pub fn logic_set_force_http1(domain: Option<String>, enabled: bool, state: &ProxyState) -> Result<(), String> {
+    let Some(domain) = domain else {
+        println!("[shared::set_force_http1] all hosts -> {}", enabled);
+        state.force_http1.store(enabled, Ordering::Relaxed);
+        return Ok(());
+    };
+
+    let host = host_of_domain_key(&domain);
+    if host.is_empty() {
+        return Err("Domain is required".into());
+    }
+    println!("[shared::set_force_http1] {} -> {}", host, enabled);
+    if enabled {
+        state.http1_hosts.insert(host);
+    } else {
+        state.http1_hosts.remove(&host);
+    }
+    Ok(())
+}
+
+/// Removes the HTTP/1.1 overrides of `domain` and its subdomains
+pub fn clear_http1_override_for_domain(domain: &str, dry_run: bool, state: &ProxyState) -> MutationReport {
+    let mut report = MutationReport::new(dry_run);
+    let host = host_of_domain_key(domain);
+
+    let matching: Vec<String> = state.http1_hosts.iter().map(|key| key.clone()).filter(|key| host_in_domain(key, &host)).collect();
+    for key in matching {
+        report.record("http1_hosts", key.clone(), None);
+        if !dry_run {
+            state.http1_hosts.remove(&key);
+        }
+    }
+    report
+}
+
+// --- Image Sizing ---
+
+/// One `srcset` candidate; `width` is known for `w` descriptors, or derived from
+/// the image's `width` attribute for density descriptors
+struct SrcsetCandidate {
+    url: String,
+    descriptor: Option<String>,
+    width: Option<u32>,
+}
+
+fn parse_srcset(srcset: &str, intrinsic_width: Option<u32>) -> Vec<SrcsetCandidate> {
+    srcset
+        .split(',')
+        .filter_map(|candidate| {
+            let mut parts = candidate.split_whitespace();
+            let url = parts.next()?.to_string();
+            let descriptor = parts.next().map(str::to_string);
+            let width = match descriptor.as_deref() {
+                Some(d) if d.ends_with('w') => d[..d.len() - 1].parse().ok(),
+                Some(d) if d.ends_with('x') => d[..d.len() - 1]
+                    .parse::<f64>()
+                    .ok()
+                    .zip(intrinsic_width)
+                    .map(|(density, width)| (density * width as f64).round() as u32),
+                None => intrinsic_width,
+                _ => None,
+            };
+            Some(SrcsetCandidate { url, descriptor, width })
+        })
+        .collect()
+}
+
+/// Keeps the candidates at or below `max_width`, or the smallest one when none fits.
+/// Returns `None` when widths are unknown and the set can't be filtered.
+fn cap_srcset(candidates: Vec<SrcsetCandidate>, max_width: u32) -> Option<Vec<SrcsetCandidate>> {
+    if candidates.is_empty() || candidates.iter().any(|c| c.width.is_none()) {
+        return None;
+    }
+    let (fitting, larger): (Vec<_>, Vec<_>) = candidates.into_iter().partition(|c| c.width.unwrap_or(0) <= max_width);
+    if !fitting.is_empty() {
+        return Some(fitting);
+    }
+    larger.into_iter().min_by_key(|c| c.width).map(|smallest| vec![smallest])
+}
+
+fn format_srcset(candidates: &[SrcsetCandidate]) -> String {
+    candidates
+        .iter()
+        .map(|c| match &c.descriptor {
+            Some(descriptor) => format!("{} {}", c.url, descriptor),
+            None => c.url.clone(),
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn dimension(value: Option<String>) -> Option<u32> {
+    value?.trim().trim_end_matches("px").parse().ok()
+}
+
+/// Narrowest image of the content taken as the article's lead image (icons, avatars and
+/// tracking pixels are smaller)
+const MIN_LEAD_IMAGE_WIDTH: u32 = 200;
+
+/// Largest `srcset` candidate: by width, else by pixel density
+fn largest_srcset_candidate(candidates: Vec<SrcsetCandidate>) -> Option<SrcsetCandidate> {
+    let density = |candidate: &SrcsetCandidate| {
+        candidate.descriptor.as_deref().and_then(|d| d.strip_suffix('x')).and_then(|d| d.parse::<f64>().ok()).unwrap_or(1.0)
+    };
+    candidates
+        .into_iter()
+        .filter(|candidate| !candidate.url.starts_with("data:"))
+        .max_by(|a, b| a.width.unwrap_or(0).cmp(&b.width.unwrap_or(0)).then(density(a).total_cmp(&density(b))))
+}
+
+/// First image of extracted content wide enough to stand for the article, as its largest
+/// `srcset` variant. Images whose width isn't known are taken as wide enough.
+fn lead_content_image(document: &scraper::Html, base: &Url) -> Option<String> {
+    let selector = scraper::Selector::parse("img").unwrap();
+    document.select(&selector).find_map(|img| {
+        let img = img.value();
+        let intrinsic_width = dimension(img.attr("width").map(str::to_string));
+        let largest = img
+            .attr("srcset")
+            .or_else(|| img.attr("data-srcset"))
+            .and_then(|srcset| largest_srcset_candidate(parse_srcset(srcset, intrinsic_width)));
+        if largest.as_ref().and_then(|candidate| candidate.width).or(intrinsic_width).is_some_and(|width| width < MIN_LEAD_IMAGE_WIDTH) {
+            return None;
+        }
+        let src = match largest {
+            Some(candidate) => candidate.url,
+            None => LAZY_IMAGE_ATTRIBUTES
+                .iter()
+                .filter_map(|attr| img.attr(attr))
+                .chain(img.attr("src"))
+                .map(str::trim)
+                .find(|src| !src.is_empty() && !src.starts_with("data:"))?
+                .to_string(),
+        };
+        absolutize_url(&src, base)
+    })
+}
+
+/// Rewrites images for a column at most `max_width` pixels wide: `width`/`height` are scaled
+/// down (keeping the aspect ratio), and `srcset` candidates wider than the cap are dropped so
+/// the smaller variants are the ones fetched. `src` points to the largest remaining candidate.
+/// When no candidate fits, the smallest one is kept rather than dropping the image.
+pub fn cap_image_widths(html: &str, max_width: u32) -> Result<String, String> {
+    if max_width == 0 {
+        return Err("max_image_width must be greater than 0".into());
+    }
+
+    rewrite_str(
+        html,
+        RewriteStrSettings {
+            element_content_handlers: vec![
+                element!("img", |el| {
+                    let width = dimension(el.get_attribute("width"));
+                    let height = dimension(el.get_attribute("height"));
+
+                    if let Some(srcset) = el.get_attribute("srcset") {
+                        if let Some(kept) = cap_srcset(parse_srcset(&srcset, width), max_width) {
+                            if let Some(largest) = kept.iter().max_by_key(|c| c.width) {
+                                el.set_attribute("src", &largest.url)?;
+                            }
+                            el.set_attribute("srcset", &format_srcset(&kept))?;
+                            if el.has_attribute("sizes") {
+                                el.set_attribute("sizes", &format!("{}px", max_width))?;
+                            }
+                        }
+                    }
+
+                    if let Some(width) = width.filter(|width| *width > max_width) {
+                        el.set_attribute("width", &max_width.to_string())?;
+                        if let Some(height) = height {
+                            let scaled = (height as f64 * max_width as f64 / width as f64).round().max(1.0) as u32;
+                            el.set_attribute("height", &scaled.to_string())?;
+                        }
+                    }
+                    Ok(())
+                }),
+                element!("picture source[srcset]", |el| {
+                    let srcset = el.get_attribute("srcset").unwrap_or_default();
+                    if let Some(kept) = cap_srcset(parse_srcset(&srcset, None), max_width) {
+                        el.set_attribute("srcset", &format_srcset(&kept))?;
+                        if el.has_attribute("sizes") {
+                            el.set_attribute("sizes", &format!("{}px", max_width))?;
+                        }
+                    }
+                    Ok(())
+                }),
+            ],
+            ..RewriteStrSettings::default()
+        },
+    )
+    .map_err(|e| e.to_string())
+}
+
+// --- Content Annotation ---
+
+/// Block-level elements that receive a `data-offset` when segmenting extracted content
+const SEGMENT_BLOCK_SELECTORS: &str = "p, h1, h2, h3, h4, h5, h6, li, blockquote, pre, figcaption, dt, dd, td, th";
+
+/// Reading speed of `ArticleResult::reading_time_minutes` when the fetch doesn't set one
+pub const DEFAULT_WORDS_PER_MINUTE: u32 = 230;
+
+/// Chinese and Japanese characters taken as one word: neither separates words with spaces
+const CJK_CHARS_PER_WORD: usize = 2;
+
+/// Han ideographs, hiragana and katakana (Korean separates its words with spaces)
+fn is_cjk(c: char) -> bool {
+    matches!(
+        c,
+        '\u{3040}'..='\u{30FF}'
+            | '\u{31F0}'..='\u{31FF}'
+            | '\u{3400}'..='\u{4DBF}'
+            | '\u{4E00}'..='\u{9FFF}'
+            | '\u{F900}'..='\u{FAFF}'
+            | '\u{FF66}'..='\u{FF9F}'
+            | '\u{20000}'..='\u{2FA1F}'
+    )
+}
+
+/// Counts words: whitespace-separated runs holding a letter or digit, so the spaced
+/// punctuation of French (`« `, ` !`) doesn't count and `l'article` is one word. `&nbsp;` is a
+/// separator. Chinese and Japanese text counts one word per `CJK_CHARS_PER_WORD` characters.
+pub fn count_words(text: &str) -> usize {
+    let mut words = 0;
+    let mut cjk_chars: usize = 0;
+    for token in text.replace("&nbsp;", " ").split_whitespace() {
+        let mut in_word = false;
+        for c in token.chars() {
+            if is_cjk(c) {
+                cjk_chars += 1;
+                in_word = false;
+            } else if c.is_alphanumeric() && !in_word {
+                words += 1;
+                in_word = true;
+            }
+        }
+    }
+    words + cjk_chars.div_ceil(CJK_CHARS_PER_WORD)
+}
+
+/// Minutes `words` take to read at `words_per_minute`, rounded, at least 1 when there are any
+pub fn reading_time_minutes(words: usize, words_per_minute: u32) -> u32 {
+    if words == 0 || words_per_minute == 0 {
+        return 0;
+    }
+    ((words as f64 / words_per_minute as f64).round() as u32).max(1)
+}
+
+/// Elements whose text isn't part of the plain text of content
+const NON_TEXT_ELEMENTS: &[&str] = &["script", "style", "noscript", "template"];
+
+/// Elements ending a paragraph of the plain text of content
+const PLAIN_TEXT_BLOCKS: &[&str] = &[
+    "p", "div", "section", "article", "h1", "h2", "h3", "h4", "h5", "h6", "li", "blockquote", "pre", "figcaption", "dt", "dd", "tr", "br", "hr", "table", "ul", "ol",
+];
+
+fn collect_plain_text(el: scraper::ElementRef, out: &mut String) {
+    for child in el.children() {
+        match child.value() {
+            // Line breaks in text are only whitespace; blocks make the paragraphs
+            scraper::Node::Text(text) => out.push_str(&text.replace(['\n', '\r'], " ")),
+            scraper::Node::Element(element) if NON_TEXT_ELEMENTS.contains(&element.name()) => {}
+            scraper::Node::Element(element) => {
+                let block = PLAIN_TEXT_BLOCKS.contains(&element.name());
+                if block {
+                    out.push('\n');
+                }
+                if let Some(child) = scraper::ElementRef::wrap(child) {
+                    collect_plain_text(child, out);
+                }
+                if block {
+                    out.push('\n');
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Text of extracted content, one paragraph per block separated by blank lines, whitespace
+/// collapsed
+pub fn plain_text(html: &str) -> String {
+    let mut text = String::new();
+    collect_plain_text(scraper::Html::parse_fragment(html).root_element(), &mut text);
+    text.lines()
+        .map(|line| line.split_whitespace().collect::<Vec<_>>().join(" "))
+        .filter(|line| !line.is_empty())
+        .collect::<Vec<_>>()
+        .join("\n\n")
+}
+
+/// Annotates every block of extracted content with `data-offset="N"`, N being the number of
+/// words that precede the block. Returns the annotated HTML and the total word count, so
+/// reading progress can be computed as `offset / total` from whichever block is in view.
+pub fn annotate_word_offsets(html: &str) -> Result<(String, usize), String> {
+    let total = Cell::new(0usize);
+    // Whether the previous text chunk ended inside a word, so a word split
+    // across chunks isn't counted twice
+    let mid_word = Cell::new(false);
+
+    let annotated = rewrite_str(
+        html,
+        RewriteStrSettings {
+            element_content_handlers: vec![
+                element!(SEGMENT_BLOCK_SELECTORS, |el| {
+                    mid_word.set(false);
+                    el.set_attribute("data-offset", &total.get().to_string())?;
+                    Ok(())
+                }),
+            ],
+            document_content_handlers: vec![
+                doc_text!(|chunk| {
+                    if chunk.text_type() != TextType::Data {
+                        return Ok(());
+                    }
+                    let text = chunk.as_str().replace("&nbsp;", " ");
+                    let mut words = count_words(&text);
+                    if mid_word.get() && text.starts_with(|c: char| !c.is_whitespace()) && words > 0 {
+                        words -= 1;
+                    }
+                    total.set(total.get() + words);
+                    if !text.is_empty() {
+                        mid_word.set(!text.ends_with(char::is_whitespace));
+                    }
+                    Ok(())
+                }),
+            ],
+            ..RewriteStrSettings::default()
+        },
+    )
+    .map_err(|e| e.to_string())?;
+
+    Ok((annotated, total.get()))
+}
+
+// --- Layout Fingerprint ---
+
+/// Blocks making up the layout of extracted content
+const LAYOUT_BLOCK_SELECTORS: &str = "p, h1, h2, h3, h4, h5, h6, li, blockquote, pre, figure, table, img";
+
+/// Upper bounds (in words) of the length buckets; longer blocks go in a last bucket
+const LAYOUT_LENGTH_BUCKETS: &[usize] = &[0, 3, 10, 30, 80];
+
+fn layout_token(name: &str, words: usize) -> String {
+    let kind = match name {
+        "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => "h",
+        "li" => "l",
+        "blockquote" => "q",
+        "pre" => "c",
+        "figure" | "img" => "f",
+        "table" => "t",
+        _ => "p",
+    };
+    let bucket = LAYOUT_LENGTH_BUCKETS.iter().position(|max| words <= *max).unwrap_or(LAYOUT_LENGTH_BUCKETS.len());
+    format!("{}{}", kind, bucket)
+}
+
+/// 64-bit SimHash of the content's block sequence (block type + bucketed word count, in
+/// trigrams), as 16 hex chars. Bucketing absorbs small edits, and SimHash keeps fingerprints
+/// of near-identical layouts close: syndicated copies differ in only a few bits (compare with
+/// the Hamming distance), while differently structured articles are much further apart.
+/// Returns `None` for content without any block.
+pub fn layout_fingerprint(html: &str) -> Option<String> {
+    let fragment = scraper::Html::parse_fragment(html);
+    let blocks = scraper::Selector::parse(LAYOUT_BLOCK_SELECTORS).unwrap();
+
+    let tokens: Vec<String> = fragment
+        .select(&blocks)
+        .filter_map(|block| {
+            let name = block.value().name();
+            let words: usize = block.text().map(count_words).sum();
+            // Empty text blocks are layout noise; images and figures count without text
+            (words > 0 || matches!(name, "img" | "figure")).then(|| layout_token(name, words))
+        })
+        .collect();
+    if tokens.is_empty() {
+        return None;
+    }
+
+    let shingles: Vec<String> = if tokens.len() < 3 {
+        tokens.clone()
+    } else {
+        tokens.windows(3).map(|window| window.join(" ")).collect()
+    };
+
+    let mut weights = [0i64; 64];
+    for shingle in &shingles {
+        let digest = Sha256::digest(shingle.as_bytes());
+        let hash = u64::from_be_bytes(digest[..8].try_into().unwrap());
+        for (bit, weight) in weights.iter_mut().enumerate() {
+            *weight += if hash >> bit & 1 == 1 { 1 } else { -1 };
+        }
+    }
+
+    let fingerprint = weights
+        .iter()
+        .enumerate()
+        .filter(|(_, weight)| **weight > 0)
+        .fold(0u64, |fingerprint, (bit, _)| fingerprint | 1 << bit);
+    Some(format!("{:016x}", fingerprint))
+}
+
+// --- Article Outline ---
+
+/// Headings collected into the outline. Deeper levels are too fine-grained for navigation.
+const OUTLINE_HEADINGS: &str = "h1, h2, h3, h4";
+
+/// Builds a URL-fragment-friendly slug from heading text
+fn slugify(text: &str) -> String {
+    let mut slug = String::with_capacity(text.len());
+    for c in text.chars().flat_map(char::to_lowercase) {
+        if c.is_alphanumeric() {
+            slug.push(c);
+        } else if !slug.is_empty() && !slug.ends_with('-') {
+            slug.push('-');
+        }
+    }
+    let slug = slug.trim_end_matches('-');
+    if slug.is_empty() { "section".to_string() } else { slug.to_string() }
+}
+
+/// A heading as seen by the first rewriting pass
+#[derive(Default)]
+struct ScannedHeading {
+    level: u8,
+    text: String,
+    id: Option<String>,
+    quoted: bool,
+}
+
+/// Collects the outline (h1–h4) of extracted content and gives every outlined heading an id.
+/// Existing ids are kept; generated slugs are deduplicated against every id in the document.
+/// Headings inside blockquotes are ignored, and a lone leading h1 is treated as the title and skipped.
+/// Returns the HTML with ids added and the outline entries in document order.
+pub fn extract_outline(html: &str) -> Result<(String, Vec<OutlineEntry>), String> {
+    // First pass: collect headings and the ids already in use
+    let headings: RefCell<Vec<ScannedHeading>> = RefCell::new(Vec::new());
+    let used_ids: RefCell<HashSet<String>> = RefCell::new(HashSet::new());
+
+    rewrite_str(
+        html,
+        RewriteStrSettings {
+            element_content_handlers: vec![
+                element!("[id]", |el| {
+                    if let Some(id) = el.get_attribute("id") {
+                        used_ids.borrow_mut().insert(id);
+                    }
+                    Ok(())
+                }),
+                element!(OUTLINE_HEADINGS, |el| {
+                    let level = el.tag_name()[1..].parse().unwrap_or(1);
+                    let id = el.get_attribute("id").filter(|id| !id.trim().is_empty());
+                    headings.borrow_mut().push(ScannedHeading { level, id, ..ScannedHeading::default() });
+                    Ok(())
+                }),
+                element!("blockquote h1, blockquote h2, blockquote h3, blockquote h4", |_el| {
+                    if let Some(heading) = headings.borrow_mut().last_mut() {
+                        heading.quoted = true;
+                    }
+                    Ok(())
+                }),
+                text!(OUTLINE_HEADINGS, |chunk| {
+                    if let Some(heading) = headings.borrow_mut().last_mut() {
+                        heading.text.push_str(chunk.as_str());
+                    }
+                    Ok(())
+                }),
+            ],
+            ..RewriteStrSettings::default()
+        },
+    )
+    .map_err(|e| e.to_string())?;
+
+    let headings = headings.into_inner();
+    let mut used_ids = used_ids.into_inner();
+
+    // A single h1 ahead of every other heading is the article title, not a section
+    let h1_count = headings.iter().filter(|h| !h.quoted && h.level == 1).count();
+    let title_index = headings.iter().position(|h| !h.quoted).filter(|&i| h1_count == 1 && headings[i].level == 1);
+
+    let mut outline = Vec::new();
+    // Id to assign to each heading in document order, `None` when it is left untouched
+    let mut assigned: Vec<Option<String>> = Vec::with_capacity(headings.len());
+
+    for (index, heading) in headings.into_iter().enumerate() {
+        let text = unescape_html(&heading.text).split_whitespace().collect::<Vec<_>>().join(" ");
+        if heading.quoted || Some(index) == title_index || text.is_empty() {
+            assigned.push(None);
+            continue;
+        }
+
+        let id = match heading.id {
+            Some(id) => {
+                assigned.push(None);
+                id
+            }
+            None => {
+                let base = slugify(&text);
+                let mut id = base.clone();
+                let mut suffix = 2;
+                while used_ids.contains(&id) {
+                    id = format!("{}-{}", base, suffix);
+                    suffix += 1;
+                }
+                used_ids.insert(id.clone());
+                assigned.push(Some(id.clone()));
+                id
+            }
+        };
+        outline.push(OutlineEntry { level: heading.level, text, id });
+    }
+
+    // Second pass: write the generated ids
+    let index = Cell::new(0usize);
+    let annotated = rewrite_str(
+        html,
+        RewriteStrSettings {
+            element_content_handlers: vec![element!(OUTLINE_HEADINGS, |el| {
+                if let Some(Some(id)) = assigned.get(index.get()) {
+                    el.set_attribute("id", id)?;
+                }
+                index.set(index.get() + 1);
+                Ok(())
+            })],
+            ..RewriteStrSettings::default()
+        },
+    )
+    .map_err(|e| e.to_string())?;
+
+    Ok((annotated, outline))
+}
+
+// --- Metadata Extraction ---
+
+/// Returns the trimmed `content` of the first non-empty `<meta>` matching one of `selectors`
+fn meta_content(document: &scraper::Html, selectors: &[&str]) -> Option<String> {
+    selectors.iter().find_map(|selector| {
+        let selector = scraper::Selector::parse(selector).ok()?;
+        document
+            .select(&selector)
+            .filter_map(|el| el.value().attr("content"))
+            .map(|content| content.trim())
+            .find(|content| !content.is_empty())
+            .map(|content| content.to_string())
+    })
+}
+
+/// Returns the `href` of the first `<link>` matching `selector`, resolved against `base`
+fn link_href(document: &scraper::Html, selector: &str, base: &Url) -> Option<String> {
+    let selector = scraper::Selector::parse(selector).ok()?;
+    document
+        .select(&selector)
+        .filter_map(|el| el.value().attr("href"))
+        .find_map(|href| absolutize_url(href.trim(), base))
+}
+
+// Cheap check before parsing a page for its AMP link
+static AMPHTML: LazyLock<regex::Regex> = LazyLock::new(|| regex::Regex::new(r"(?i)amphtml").unwrap());
+
+/// AMP version of a page from its `<link rel="amphtml">`, resolved against `page_url`
+pub fn amp_url(html: &str, page_url: &Url) -> Option<Url> {
+    if !AMPHTML.is_match(html) {
+        return None;
+    }
+    let document = scraper::Html::parse_document(html);
+    link_href(&document, r#"link[rel~="amphtml" i]"#, page_url)
+        .and_then(|href| Url::parse(&href).ok())
+        .filter(|amp_url| amp_url != page_url && matches!(amp_url.scheme(), "http" | "https" | chaos::FIXTURE_SCHEME))
+}
+
+/// Resolves a possibly relative URL against `base`, returning None for empty or invalid values
+pub fn absolutize_url(value: &str, base: &Url) -> Option<String> {
+    if value.is_empty() {
+        return None;
+    }
+    base.join(value).ok().map(|url| url.to_string())
+}
+
+/// URL values left as they are by `absolutize_content_urls`
+const NON_RELATIVE_URL_PREFIXES: &[&str] = &["data:", "mailto:", "javascript:", "#"];
+
+// `<base href>` of a page
+static BASE_HREF: LazyLock<regex::Regex> = LazyLock::new(|| regex::Regex::new(r#"(?is)<base\s[^>]*?href\s*=\s*["']([^"']+)["']"#).unwrap());
+
+/// URL relative URLs of a page resolve against: its `<base href>`, else the page URL
+pub fn document_base_url(html: &str, page_url: &Url) -> Url {
+    BASE_HREF
+        .captures(html)
+        .and_then(|captures| page_url.join(unescape_html(captures[1].trim()).as_str()).ok())
+        .unwrap_or_else(|| page_url.clone())
+}
+
+/// Rewrites the relative URLs of `a[href]`, `img` and `source` (`src` and `srcset`) to
+/// absolute ones resolved against `base`, so content shown outside its page keeps working.
+/// `data:`, `mailto:`, `javascript:` and fragment-only values are left alone.
+pub fn absolutize_content_urls(html: &str, base: &Url) -> Result<String, String> {
+    let absolute = |value: &str| {
+        let value = value.trim();
+        let lowercase = value.to_ascii_lowercase();
+        if NON_RELATIVE_URL_PREFIXES.iter().any(|prefix| lowercase.starts_with(prefix)) {
+            return None;
+        }
+        absolutize_url(value, base).filter(|absolute| absolute != value)
+    };
+    let absolutize = |el: &mut lol_html::html_content::Element, attribute: &str| -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        if let Some(absolute) = el.get_attribute(attribute).and_then(|value| absolute(&value)) {
+            el.set_attribute(attribute, &absolute)?;
+        }
+        Ok(())
+    };
+    let absolutize_srcset = |el: &mut lol_html::html_content::Element| -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let Some(srcset) = el.get_attribute("srcset") else {
+            return Ok(());
+        };
+        let mut candidates = parse_srcset(&srcset, None);
+        for candidate in &mut candidates {
+            if let Some(absolute) = absolute(&candidate.url) {
+                candidate.url = absolute;
+            }
+        }
+        el.set_attribute("srcset", &format_srcset(&candidates))?;
+        Ok(())
+    };
+
+    rewrite_str(
+        html,
+        RewriteStrSettings {
+            element_content_handlers: vec![
+                element!("a[href]", |el| absolutize(el, "href")),
+                element!("img[src], source[src]", |el| absolutize(el, "src")),
+                element!("img[srcset], source[srcset]", |el| absolutize_srcset(el)),
+            ],
+            ..RewriteStrSettings::default()
+        },
+    )
+    .map_err(|e| format!("Failed to absolutize content URLs: {}", e))
+}
+
+/// Elements dropped from embedded HTML along with their content
+const EMBEDDED_DROPPED_ELEMENTS: &str = "script, style, noscript, template, form, button";
+
+/// HTML taken from a page or a feed for display (live-blog entries, feed item bodies) without
+/// scripts, forms and event handlers, its links and images made absolute
+pub fn clean_embedded_html(html: &str, base: &Url) -> String {
+    let absolutize = |el: &mut lol_html::html_content::Element, attribute: &str| {
+        if let Some(absolute) = el.get_attribute(attribute).and_then(|value| absolutize_url(value.trim(), base)) {
+            let _ = el.set_attribute(attribute, &absolute);
+        }
+    };
+    rewrite_str(
+        html,
+        RewriteStrSettings {
+            element_content_handlers: vec![
+                element!(EMBEDDED_DROPPED_ELEMENTS, |el| {
+                    el.remove();
+                    Ok(())
+                }),
+                element!("*", |el| {
+                    let handlers: Vec<String> = el.attributes().iter().map(|a| a.name()).filter(|name| name.starts_with("on")).collect();
+                    handlers.iter().for_each(|name| el.remove_attribute(name));
+                    Ok(())
+                }),
+                element!("a[href]", |el| {
+                    absolutize(el, "href");
+                    Ok(())
+                }),
+                element!("img[src]", |el| {
+                    absolutize(el, "src");
+                    Ok(())
+                }),
+            ],
+            ..RewriteStrSettings::default()
+        },
+    )
+    .unwrap_or_default()
+    .trim()
+    .to_string()
+}
+
+/// Removes what could run or track in the reader view from extracted content: scripts,
+/// iframes other than `embeds::KNOWN_EMBEDS` players, event handler attributes,
+/// `javascript:` links and tracking pixels. Returns the input unchanged if rewriting fails.
+pub fn sanitize_article_html(html: &str) -> String {
+    let removed = Cell::new(0usize);
+    let result = rewrite_str(
+        html,
+        RewriteStrSettings {
+            element_content_handlers: vec![
+                element!("script", |el| {
+                    el.remove();
+                    removed.set(removed.get() + 1);
+                    Ok(())
+                }),
+                element!("iframe", |el| {
+                    let known = el.get_attribute("src").and_then(|src| Url::parse(unescape_html(&src).trim()).ok()).is_some_and(|src| embeds::embed_provider(&src).is_some());
+                    if !known {
+                        el.remove();
+                        removed.set(removed.get() + 1);
+                    }
+                    Ok(())
+                }),
+                element!("img[src]", |el| {
+                    let src = unescape_html(&el.get_attribute("src").unwrap_or_default());
+                    if is_tracking_pixel(&src, el.get_attribute("width").as_deref(), el.get_attribute("height").as_deref()) {
+                        el.remove();
+                        removed.set(removed.get() + 1);
+                    }
+                    Ok(())
+                }),
+                element!("*", |el| {
+                    let handlers: Vec<String> = el.attributes().iter().map(|a| a.name()).filter(|name| name.starts_with("on")).collect();
+                    removed.set(removed.get() + handlers.len());
+                    handlers.iter().for_each(|name| el.remove_attribute(name));
+                    Ok(())
+                }),
+                element!("a[href], area[href]", |el| {
+                    let href = unescape_html(&el.get_attribute("href").unwrap_or_default());
+                    let scheme: String = href.chars().filter(|c| !c.is_ascii_whitespace() && !c.is_ascii_control()).take(11).collect();
+                    if scheme.to_ascii_lowercase().starts_with("javascript:") {
+                        el.remove_attribute("href");
+                        removed.set(removed.get() + 1);
+                    }
+                    Ok(())
+                }),
+            ],
+            ..RewriteStrSettings::default()
+        },
+    );
+
+    match result {
+        Ok(sanitized) => {
+            if removed.get() > 0 {
+                println!("[shared::sanitize_article_html] Removed {} scripts, frames, handlers or trackers", removed.get());
+            }
+            sanitized
+        }
+        Err(e) => {
+            println!("[shared::sanitize_article_html] Rewriting failed, keeping original HTML: {}", e);
+            html.to_string()
+        }
+    }
+}
+
+/// Parses every `application/ld+json` block and flattens arrays and `@graph` containers
+/// into a list of nodes. Malformed blocks are skipped.
+pub fn json_ld_nodes(document: &scraper::Html) -> Vec<serde_json::Value> {
+    fn flatten(value: serde_json::Value, nodes: &mut Vec<serde_json::Value>) {
+        match value {
+            serde_json::Value::Array(items) => items.into_iter().for_each(|item| flatten(item, nodes)),
+            serde_json::Value::Object(mut map) => {
+                if let Some(graph) = map.remove("@graph") {
+                    flatten(graph, nodes);
+                }
+                if !map.is_empty() {
+                    nodes.push(serde_json::Value::Object(map));
+                }
+            }
+            _ => {}
+        }
+    }
+
+    let selector = scraper::Selector::parse(r#"script[type="application/ld+json"]"#).unwrap();
+    let mut nodes = Vec::new();
+    for script in document.select(&selector) {
+        let raw = script.text().collect::<String>();
+        match serde_json::from_str::<serde_json::Value>(raw.trim()) {
+            Ok(value) => flatten(value, &mut nodes),
+            Err(e) => println!("[shared::json_ld_nodes] Skipping malformed JSON-LD block: {}", e),
+        }
+    }
+    nodes
+}
+
+/// Returns true if a JSON-LD node's `@type` (string or array) matches one of `types`
+pub fn json_ld_has_type(node: &serde_json::Value, types: &[&str]) -> bool {
+    match node.get("@type") {
+        Some(serde_json::Value::String(t)) => types.contains(&t.as_str()),
+        Some(serde_json::Value::Array(ts)) => ts.iter().filter_map(|t| t.as_str()).any(|t| types.contains(&t)),
+        _ => false,
+    }
+}
+
+/// Reads a JSON-LD property that may be a string, an object with `name`/`url`, or an array of either
+pub fn json_ld_text(value: Option<&serde_json::Value>) -> Option<String> {
+    match value? {
+        serde_json::Value::String(text) => Some(text.trim().to_string()).filter(|t| !t.is_empty()),
+        serde_json::Value::Object(map) => json_ld_text(map.get("name").or_else(|| map.get("url"))),
+        serde_json::Value::Array(items) => items.iter().find_map(|item| json_ld_text(Some(item))),
+        _ => None,
+    }
+}
+
+/// JSON-LD types that describe an article-like page
+const JSON_LD_ARTICLE_TYPES: &[&str] = &[
+    "Article", "NewsArticle", "BlogPosting", "Report", "ScholarlyArticle",
+    "TechArticle", "LiveBlogPosting", "WebPage",
+];
+
+/// JSON-LD types whose `articleBody` holds an article's full text
+const JSON_LD_BODY_TYPES: &[&str] = &["Article", "NewsArticle", "BlogPosting", "ReportageNewsArticle", "AnalysisNewsArticle"];
+
+/// Extracted content with less text than this (in chars) gives way to the page's JSON-LD
+/// `articleBody` when that holds more
+const JSON_LD_BODY_BELOW: usize = 400;
+
+/// Extracted content with less text than this (in chars), app shells and their loading
+/// messages included, gives way to the page's AMP version when that holds more
+const AMP_FALLBACK_BELOW: usize = 200;
+
+/// `articleBody` of the page's JSON-LD article node, as HTML paragraphs: script-rendered
+/// news sites often ship their whole text there. The text is split on blank lines, or on
+/// line breaks when it has none.
+pub fn json_ld_article_body(html: &str) -> Option<String> {
+    if !html.contains("articleBody") {
+        return None;
+    }
+    let document = scraper::Html::parse_document(html);
+    let body = json_ld_nodes(&document)
+        .iter()
+        .filter(|node| json_ld_has_type(node, JSON_LD_BODY_TYPES))
+        .find_map(|node| json_ld_text(node.get("articleBody")))?
+        .replace("\r\n", "\n");
+    let blocks: Vec<&str> = if body.contains("\n\n") { body.split("\n\n").collect() } else { body.lines().collect() };
+    let paragraphs: String = blocks
+        .iter()
+        .map(|block| block.split_whitespace().collect::<Vec<_>>().join(" "))
+        .filter(|block| !block.is_empty())
+        .map(|block| format!("<p>{}</p>", escape_html(&unescape_html(&block))))
+        .collect();
+    Some(format!("<div>{}</div>", paragraphs))
+}
+
+/// Byline elements of a page, read when no metadata names the author
+const BYLINE_SELECTORS: &[&str] = &[
+    r#"[itemprop="author"] [itemprop="name"]"#,
+    r#"[itemprop="author"]"#,
+    r#"a[rel~="author"]"#,
+    ".byline .author",
+    ".byline",
+    ".author-name",
+];
+
+/// Bylines longer than this are a whole author box rather than a name
+const MAX_BYLINE_LEN: usize = 100;
+
+/// "By" in the languages bylines are usually written in
+const BYLINE_PREFIXES: &[&str] = &["by ", "par ", "von ", "por ", "di ", "door "];
+
+/// Author named by the byline of the page (as readability would find it), "By" left out
+fn page_byline(document: &scraper::Html) -> Option<String> {
+    BYLINE_SELECTORS.iter().find_map(|selector| {
+        let selector = scraper::Selector::parse(selector).ok()?;
+        document.select(&selector).find_map(|el| {
+            let text = el.text().collect::<Vec<_>>().join(" ").split_whitespace().collect::<Vec<_>>().join(" ");
+            let lowercase = text.to_lowercase();
+            let name = match BYLINE_PREFIXES.iter().find(|prefix| lowercase.starts_with(*prefix)) {
+                Some(prefix) => text[prefix.len()..].trim(),
+                None => text.as_str(),
+            };
+            (!name.is_empty() && name.chars().count() <= MAX_BYLINE_LEN).then(|| name.to_string())
+        })
+    })
+}
+
+/// Author of a page: JSON-LD, then OpenGraph, then the standard meta tags, then the byline.
+/// Profile URLs, which `article:author` often holds, only count when nothing names the author.
+fn page_author(document: &scraper::Html, article_node: Option<&serde_json::Value>) -> Option<String> {
+    let candidates = [
+        article_node.and_then(|node| json_ld_text(node.get("author"))),
+        meta_content(document, &[r#"meta[property="article:author"]"#]),
+        meta_content(document, &[r#"meta[property="og:author"]"#, r#"meta[name="og:author"]"#]),
+        meta_content(document, &[r#"meta[name="author"]"#]),
+        meta_content(document, &[r#"meta[name="twitter:creator"]"#]),
+        page_byline(document),
+    ];
+    let is_url = |value: &String| value.starts_with("http://") || value.starts_with("https://");
+    candidates.iter().flatten().find(|value| !is_url(value)).or_else(|| candidates.iter().flatten().next()).cloned()
+}
+
+/// Publication date of a page. When several disagree, JSON-LD wins over OpenGraph and meta
+/// tags, which win over `<time>` elements; a source that doesn't parse, or gives a date before
+/// 1990 or more than a day ahead, is skipped. Dates without a zone are taken as UTC.
+fn page_published(document: &scraper::Html, article_node: Option<&serde_json::Value>) -> Option<ParsedDate> {
+    let html_selector = scraper::Selector::parse("html[lang]").unwrap();
+    let languages = document.select(&html_selector).next().and_then(|html| html.value().attr("lang")).map(dates::languages_of).unwrap_or_default();
+    let now = dates::now_millis();
+    let parse = |value: String| dates::parse_date(&value, now, &languages).filter(|date| dates::is_plausible_publication(date.timestamp, now));
+
+    let time_selector = scraper::Selector::parse(r#"[itemprop="datePublished"], time[datetime], time"#).unwrap();
+    let time = || {
+        document.select(&time_selector).find_map(|el| {
+            let value = el.value().attr("datetime").or_else(|| el.value().attr("content")).map(str::to_string);
+            value.or_else(|| Some(el.text().collect::<String>())).filter(|v| !v.trim().is_empty()).and_then(parse)
+        })
+    };
+    article_node
+        .and_then(|node| json_ld_text(node.get("datePublished")))
+        .and_then(parse)
+        .or_else(|| {
+            [
+                r#"meta[property="article:published_time"]"#,
+                r#"meta[property="og:published_time"]"#,
+                r#"meta[itemprop="datePublished"]"#,
+                r#"meta[name="date"]"#,
+                r#"meta[name="pubdate"]"#,
+                r#"meta[name="dc.date"]"#,
+            ]
+            .iter()
+            .find_map(|selector| meta_content(document, &[selector]).and_then(parse))
+        })
+        .or_else(time)
+}
+
+/// Assembles share metadata from OpenGraph, Twitter Cards, JSON-LD and standard meta tags.
+/// Relative canonical and image URLs are resolved against `page_url`.
+pub fn extract_share_metadata(html: &str, page_url: &Url) -> ShareMeta {
+    let document = scraper::Html::parse_document(html);
+    let nodes = json_ld_nodes(&document);
+    // `@graph`s often list a generic `WebPage` node before the article: it only counts when
+    // no node is an article
+    let is_web_page = |node: &&serde_json::Value| json_ld_has_type(node, &["WebPage"]);
+    let article_node = nodes.iter().find(|node| json_ld_has_type(node, JSON_LD_ARTICLE_TYPES) && !is_web_page(node)).or_else(|| nodes.iter().find(is_web_page));
+    let ld = |key: &str| article_node.and_then(|node| json_ld_text(node.get(key)));
+
+    let canonical_url = link_href(&document, r#"link[rel="canonical"]"#, page_url)
+        .or_else(|| meta_content(&document, &[r#"meta[property="og:url"]"#]).and_then(|u| absolutize_url(&u, page_url)))
+        .unwrap_or_else(|| page_url.to_string());
+
+    let title = meta_content(&document, &[r#"meta[property="og:title"]"#, r#"meta[name="twitter:title"]"#])
+        .or_else(|| ld("headline"))
+        .or_else(|| {
+            let selector = scraper::Selector::parse("title").unwrap();
+            document
+                .select(&selector)
+                .next()
+                .map(|t| t.text().collect::<String>().trim().to_string())
+                .filter(|t| !t.is_empty())
+        });
+
+    let description = meta_content(&document, &[
+        r#"meta[property="og:description"]"#,
+        r#"meta[name="twitter:description"]"#,
+        r#"meta[name="description"]"#,
+    ])
+    .or_else(|| ld("description"));
+
+    // Relative image URLs are relative to the fetched page, not to the canonical URL
+    let image = meta_content(&document, &[
+        r#"meta[property="og:image:secure_url"]"#,
+        r#"meta[property="og:image"]"#,
+        r#"meta[name="twitter:image"]"#,
+        r#"meta[name="twitter:image:src"]"#,
+    ])
+    .and_then(|image| absolutize_url(&image, page_url))
+    .or_else(|| link_href(&document, r#"link[rel="image_src"]"#, page_url))
+    .or_else(|| ld("image").and_then(|image| absolutize_url(&image, page_url)));
+
+    let site_name = meta_content(&document, &[r#"meta[property="og:site_name"]"#, r#"meta[name="application-name"]"#])
+        .or_else(|| ld("publisher"))
+        .or_else(|| page_url.host_str().map(|h| h.trim_start_matches("www.").to_string()));
+
+    let author = page_author(&document, article_node);
+    let published = page_published(&document, article_node);
+
+    ShareMeta {
+        canonical_url,
+        title,
+        description,
+        image,
+        site_name,
+        author,
+        published,
+    }
+}
+
+// --- Store Maintenance ---
+
+/// Host part of a domain key: accepts either an origin ("https://example.com") or a bare host
+pub fn host_of_domain_key(domain: &str) -> String {
+    if domain.contains("://") {
+        if let Ok(url) = Url::parse(domain) {
+            return url.host_str().unwrap_or_default().to_ascii_lowercase();
+        }
+    }
+    domain.trim().trim_start_matches('.').to_ascii_lowercase()
+}
+
+/// True if a cookie scoped to `cookie_domain` applies to `host` or one of its subdomains
+fn cookie_domain_matches(cookie_domain: &str, host: &str) -> bool {
+    let cookie_domain = cookie_domain.trim_start_matches('.').to_ascii_lowercase();
+    cookie_domain == host
+        || cookie_domain.ends_with(&format!(".{}", host))
+        || host.ends_with(&format!(".{}", cookie_domain))
+}
+
+/// True if `host` is `domain` or one of its subdomains
+pub fn host_in_domain(host: &str, domain: &str) -> bool {
+    host == domain || host.ends_with(&format!(".{}", domain))
+}
+
+/// Clears the credentials of every origin on `domain` (subdomains included)
+pub fn clear_auth_for_domain(domain: &str, dry_run: bool, state: &ProxyState) -> MutationReport {
+    let mut report = MutationReport::new(dry_run);
+    let host = host_of_domain_key(domain);
+
+    let matching: Vec<String> = state
+        .auth_credentials
+        .iter()
+        .map(|entry| entry.key().clone())
+        .filter(|key| host_in_domain(&host_of_domain_key(key), &host))
+        .collect();
+
+    for key in matching {
+        report.record("auth_credentials", key.clone(), None);
+        if !dry_run {
+            state.auth_credentials.remove(&key);
+        }
+    }
+    report
+}
+
+pub fn logic_clear_proxy_auth(domain: String, dry_run: bool, state: &ProxyState) -> MutationReport {
+    let mut report = MutationReport::new(dry_run);
+
+    // Migrated stores key credentials by origin, whatever form the frontend sends
+    let origin = credentials::origin_key(&domain).ok().filter(|origin| *origin != domain);
+    for key in std::iter::once(domain).chain(origin) {
+        if state.auth_credentials.contains_key(&key) {
+            report.record("auth_credentials", key.clone(), None);
+            if !dry_run {
+                state.auth_credentials.remove(&key);
+                println!("Cleared auth credentials for domain: {}", key);
+            }
+        }
+    }
+    report
+}
+
+/// Clears cookies for `domain` (origin or host, subdomains included), or every cookie when `None`
+pub fn logic_clear_cookies(domain: Option<String>, dry_run: bool, state: &ProxyState) -> MutationReport {
+    let mut report = MutationReport::new(dry_run);
+    let host = domain.as_deref().map(host_of_domain_key);
+    let mut store = state.cookie_jar.lock().unwrap();
+
+    let matching: Vec<(String, String, String, usize)> = store
+        .iter_any()
+        .filter_map(|cookie| {
+            let cookie_domain = cookie.domain.as_cow()?.into_owned();
+            if let Some(host) = &host {
+                if !cookie_domain_matches(&cookie_domain, host) {
+                    return None;
+                }
+            }
+            let path: &str = &cookie.path;
+            Some((cookie_domain, path.to_string(), cookie.name().to_string(), cookie.value().len()))
+        })
+        .collect();
+
+    for (cookie_domain, path, name, size) in matching {
+        report.record("cookies", format!("{}@{}{}", name, cookie_domain, path), Some(size));
+        if !dry_run {
+            store.remove(&cookie_domain, &path, &name);
+        }
+    }
+
+    if !dry_run && report.count > 0 {
+        println!("Cleared {} cookies for {}", report.count, domain.as_deref().unwrap_or("all domains"));
+    }
+    report
+}
+
+// --- Core Logic Functions (Tauri/Axum Agnostic) ---
+
+/// Downloads the page at `url` as is. `timeout_secs` (1-120) replaces the adaptive timeout,
+/// `user_agent` the configured or rotated User-Agent.
+pub async fn logic_fetch_raw_html(url: String, timeout_secs: Option<u64>, user_agent: Option<String>, state: &ProxyState) -> Result<String, String> {
+    println!("[shared::fetch_raw_html] ========================================");
+    println!("[shared::fetch_raw_html] Fetching URL: {}", url);
+    println!("[shared::fetch_raw_html] ========================================");
+
+    let url_obj = Url::parse(&url).map_err(|e| e.to_string())?;
+    let user_agent = user_agent_override(user_agent)?;
+
+    if let Some(fixture) = chaos::fixture(&url_obj, state) {
+        return fixture;
+    }
+    connectivity::ensure_online(state)?;
+
+    // Extract domain for auth lookup
+    let domain = origin_of(&url_obj);
+
+    // Check for auth credentials for this domain
+    let auth_credentials = state.auth_credentials.get(&domain).map(|entry| entry.value().clone());
+
+    // Pooled client on the shared cookie jar for session persistence (important for CSRF tokens)
+    let timeout = latency::requested_timeout(&url_obj, RequestPriority::Interactive, timeout_secs, state);
+    let client = preconnect::pooled_client(&url_obj, state)?;
+
+    // Headers matching the working Python implementation - no Sec-Fetch-* headers
+    let mut request_builder = client
+        .get(url_obj.clone())
+        .timeout(timeout)
+        .header(USER_AGENT, user_agent.unwrap_or_else(|| state.next_user_agent()))
+        .header("Accept", "text/html,application/xhtml+xml,application/xml;q=0.9,image/webp,image/apng,*/*;q=0.8")
+        .header("Accept-Encoding", "gzip, deflate, br")
+        .header("Accept-Language", accept_language_for(&url_obj, state, DEFAULT_ARTICLE_ACCEPT_LANGUAGE))
+        .header("Cache-Control", "no-cache")
+        .header("Pragma", "no-cache")
+        .header("Connection", "keep-alive")
+        .header("Upgrade-Insecure-Requests", "1");
+
+    // Add the domain's credentials if any
+    if let Some(auth) = auth_credentials {
+        println!("Adding {} for domain: {}", auth.kind(), domain);
+        request_builder = auth.apply(request_builder);
+    }
+
+    let request = request_builder.build().map_err(|e| e.to_string())?;
+    chaos::inject_request_faults(&url_obj, state).await.map_err(|fault| fault.to_string())?;
+
+    let started = std::time::Instant::now();
+    let response = credentials::execute(&client, request, state).await;
+    latency::record_response(&url_obj, started, timeout, &response, state);
+    let response = response.map_err(|e| e.to_string())?;
+
+    println!("[shared::fetch_raw_html] Response status: {} for URL: {}", response.status(), url);
+
+    // Check for 401 Unauthorized
+    if response.status() == reqwest::StatusCode::UNAUTHORIZED {
+        println!("fetch_raw_html: 401 Unauthorized for URL: {}", url);
+        return Err(format!("AUTH_REQUIRED:{}", domain));
+    }
+
+    let max_body_size = state.max_body_size();
+    let html = read_html_limited(response, max_body_size).await?;
+    let html = chaos::mangle_body(&url_obj, html, state);
+
+    // Log cookies after fetching (they should be stored in the jar now)
+    let cookies_after = state.cookie_jar.cookies(&url_obj);
+    println!("[shared::fetch_raw_html] Cookies in jar after fetch for {}: {:?}", url_obj, cookies_after);
+
+    Ok(html)
+}
+
+/// Same as `logic_fetch_raw_html`, but large pages can be handed back as a one-shot URL
+/// on the local server instead of being serialized through IPC.
+pub async fn logic_fetch_raw_html_transfer(url: String, transfer: Option<TransferMode>, state: &ProxyState) -> Result<TransferPayload, String> {
+    let html = logic_fetch_raw_html(url, None, None, state).await?;
+
+    // Without a running local server there is nothing to serve the handle from
+    let transfer = if state.has_local_server() { transfer } else { Some(TransferMode::Inline) };
+
+    Ok(prepare_transfer(html, "text/html; charset=utf-8", transfer, state))
+}
+
+pub async fn logic_fetch_share_metadata(url: String, state: &ProxyState) -> Result<ShareMeta, String> {
+    let url_obj = Url::parse(&url).map_err(|e| e.to_string())?;
+    let html = logic_fetch_raw_html(url, None, None, state).await?;
+    Ok(extract_share_metadata(&html, &url_obj))
+}
+
+/// Downloads the article page, rejecting non-HTML responses
+async fn fetch_article_html(url_obj: &Url, page_request: &PageRequest<'_>, state: &ProxyState) -> Result<FetchedPage, String> {
+    let domain = origin_of(url_obj);
+    let auth_credentials = state.auth_credentials.get(&domain).map(|entry| entry.value().clone());
+
+    let timeout = latency::requested_timeout(url_obj, page_request.priority, page_request.timeout_secs, state);
+    // Pooled, so connections warmed by `preconnect` are reused. Its cookie jar is the shared
+    // one, so sessions opened by `perform_form_login` apply to articles too.
+    let client = if page_request.without_cookies { cookieless_client(url_obj, state)? } else { preconnect::pooled_client(url_obj, state)? };
+
+    chaos::inject_request_faults(url_obj, state).await.map_err(|fault| fault.to_string())?;
+
+    // Headers matching the working Python implementation - no Sec-Fetch-* headers
+    let mut request_builder = client
+        .get(url_obj.clone())
+        .timeout(timeout)
+        .header(USER_AGENT, state.next_user_agent())
+        .header("Accept", "text/html,application/xhtml+xml,application/xml;q=0.9,image/webp,image/apng,*/*;q=0.8")
+        .header("Accept-Encoding", "gzip, deflate, br")
+        .header("Accept-Language", page_request.accept_language)
+        .header("Cache-Control", "no-cache")
+        .header("Pragma", "no-cache")
+        .header("Connection", "keep-alive")
+        .header("Upgrade-Insecure-Requests", "1");
+
+    let mut auth = None;
+    if let Some(credentials) = auth_credentials {
+        println!("[shared::fetch_article] Adding {} for domain: {}", credentials.kind(), domain);
+        request_builder = credentials.apply(request_builder);
+        auth = Some(credentials.kind());
+    }
+    let mut request = request_builder.build().map_err(|e| e.to_string())?;
+
+    // Site rules may require specific headers (usually a User-Agent or Referer)
+    for (name, value) in page_request.site_config.map(|config| config.http_headers.as_slice()).unwrap_or_default() {
+        if let (Ok(name), Ok(value)) = (HeaderName::from_bytes(name.as_bytes()), HeaderValue::from_str(value)) {
+            if name == reqwest::header::COOKIE && page_request.without_cookies {
+                continue;
+            }
+            if name == reqwest::header::COOKIE || name == reqwest::header::AUTHORIZATION {
+                auth = Some("site_rule_headers");
+            }
+            request.headers_mut().insert(name, value);
+        }
+    }
+    if let Some(user_agent) = page_request.user_agent.as_deref().and_then(|user_agent| HeaderValue::from_str(user_agent).ok()) {
+        request.headers_mut().insert(USER_AGENT, user_agent);
+    }
+    let user_agent = request.headers().get(USER_AGENT).and_then(|value| value.to_str().ok()).map(str::to_string);
+
+    let started = std::time::Instant::now();
+    let response = credentials::execute(&client, request, state).await;
+    latency::record_response(url_obj, started, timeout, &response, state);
+    let response = response.map_err(|e| e.to_string())?;
+    let final_url = response.url().to_string();
+    let status = response.status().as_u16();
+
+    if response.status() == reqwest::StatusCode::UNAUTHORIZED {
+        println!("[shared::fetch_article] 401 Unauthorized for URL: {}", url_obj);
+        return Err(format!("AUTH_REQUIRED:{}", domain));
+    }
+
+    // Check content type to ensure we're dealing with HTML
+    let content_type = response.headers()
+        .get("content-type")
+        .and_then(|ct| ct.to_str().ok())
+        .unwrap_or("")
+        .to_string();
+    let content_type = chaos::mangle_content_type(url_obj, content_type, state);
+
+    // XML may be a feed whose URL was opened as an article, `logic_extract_article` sniffs it
+    if !content_type.contains("text/html") && !content_type.contains("application/xhtml") && !is_xml_content_type(&content_type) {
+        return Err(non_html_error(&content_type));
+    }
+
+    let content_language = response.headers()
+        .get("content-language")
+        .and_then(|value| value.to_str().ok())
+        .map(|value| value.trim().to_string())
+        .filter(|value| !value.is_empty());
+
+    let max_body_size = state.max_body_size();
+    let html = read_html_limited(response, max_body_size).await?;
+    Ok(FetchedPage { html: chaos::mangle_body(url_obj, html, state), content_language, url: final_url, status: Some(status), user_agent, auth, content_type })
+}
+
+/// Client of requests that must not carry the session's cookies, nor keep the ones they get
+fn cookieless_client(url: &Url, state: &ProxyState) -> Result<reqwest::Client, String> {
+    with_protocol_for(reqwest::Client::builder(), url, state)
+        .redirect(credentials::redirect_policy(state))
+        .gzip(true)
+        .brotli(true)
+        .deflate(true)
+        .build()
+        .map_err(|e| e.to_string())
+}
+
+/// `application/xml`, `text/xml` and the `+xml` types of feeds (`application/rss+xml`, ...)
+fn is_xml_content_type(content_type: &str) -> bool {
+    let mime = content_type.split(';').next().unwrap_or("").trim().to_ascii_lowercase();
+    mime.ends_with("/xml") || mime.ends_with("+xml")
+}
+
+fn non_html_error(content_type: &str) -> String {
+    format!("Content type '{}' is not HTML", content_type)
+}
+
+/// Fetches `url` and runs readability on it. `content` is `None` when the page should be
+/// shown through the iframe fallback instead (empty shells, too little extracted text).
+pub async fn logic_extract_article(url: String, options: ArticleOptions, state: &ProxyState) -> Result<ExtractedArticle, String> {
+    let url_obj = Url::parse(&url).map_err(|e| e.to_string())?;
+    let deadline = Deadline::after_ms(options.deadline_ms);
+    let provenance = ProvenanceBuilder::default();
+
+    if let Some(content) = rendered::cached_article(&url, state) {
+        println!("[shared::fetch_article] Using the extraction of the rendered page for {}", url);
+        host_stats::record_outcome(&url_obj, ExtractionOutcome::Success, state);
+        provenance.source(ProvenanceSource::RenderedIframe);
+        provenance.url(&url);
+        return finish_extraction(&url, Some(content), None, &options, &deadline, &provenance, state);
+    }
+
+    if requires_rendering(&url_obj, state) {
+        println!("[shared::fetch_article] {} is flagged as requiring rendering, skipping extraction", url);
+        provenance.source(ProvenanceSource::RenderedIframe);
+        provenance.url(&url);
+        return Ok(ExtractedArticle { provenance: Some(provenance.finish()), ..ExtractedArticle::default() });
+    }
+
+    // Offline, only a version stored for a starred or archived article can be shown
+    if connectivity::is_offline(state) {
+        let content = versions::latest_version(&url, state).ok_or_else(|| connectivity::OFFLINE.to_string())?;
+        println!("[shared::fetch_article] Offline, using the latest stored version of {}", url);
+        provenance.source(ProvenanceSource::StoredVersion);
+        provenance.url(&url);
+        return finish_extraction(&url, Some(content), None, &options, &deadline, &provenance, state);
+    }
+
+    let site_config = site_config::config_for(&url_obj, state);
+    let user_agent = user_agent_override(options.user_agent.clone())?;
+    let accept_language = match &options.accept_language {
+        Some(languages) => accept_language_header(languages)?,
+        None => accept_language_for(&url_obj, state, DEFAULT_ARTICLE_ACCEPT_LANGUAGE),
+    };
+
+    let request = PageRequest {
+        site_config: site_config.as_ref(),
+        accept_language: &accept_language,
+        priority: if options.background { RequestPriority::Background } else { RequestPriority::Interactive },
+        timeout_secs: options.timeout_secs,
+        user_agent,
+        without_cookies: false,
+    };
+    if url_obj.scheme() == chaos::FIXTURE_SCHEME {
+        provenance.source(ProvenanceSource::Fixture);
+    }
+    let page = request.fetch(&url_obj, state).await;
+    let page = page.inspect_err(|_| host_stats::record_outcome(&url_obj, ExtractionOutcome::Error, state))?;
+    provenance.fetched(&page.url, page.status, page.user_agent.as_deref());
+    if let Some(auth) = page.auth {
+        provenance.auth(auth);
     }
 
-    let html = response.text().await.map_err(|e| e.to_string())?;
+    // Feeds are sniffed whatever their content type: some servers send RSS as text/html
+    let page_url = Url::parse(&page.url).unwrap_or_else(|_| url_obj.clone());
+    if feed::sniff_feed_root(&page.html) {
+        let discovered = feed::discover_feed(&page.html, &page_url)?;
+        println!("[shared::fetch_article] {} is a feed with {} items", url, discovered.item_count);
+        let metadata = ArticleMetadata { title: discovered.discovered_title.clone(), final_url: Some(page_url.to_string()), ..ArticleMetadata::default() };
+        return Ok(ExtractedArticle { provenance: Some(provenance.finish()), metadata, feed: Some(discovered), ..ExtractedArticle::default() });
+    }
+    if !page.content_type.contains("html") {
+        host_stats::record_outcome(&url_obj, ExtractionOutcome::Error, state);
+        return Err(non_html_error(&page.content_type));
+    }
 
-    // Log cookies after fetching (they should be stored in the jar now)
-    let cookies_after = state.cookie_jar.cookies(&url_obj);
-    println!("[shared::fetch_raw_html] Cookies in jar after fetch for {}: {:?}", url_obj, cookies_after);
+    let paywalled = host_stats::looks_paywalled(&page.html);
+    let amp_url = amp_url(&page.html, &page_url);
+    let json_ld_body = json_ld_article_body(&page.html);
+    let mut next_page = if options.follow_pagination { pagination::next_page_url(&page.html, &page_url, site_config.as_ref()) } else { None };
+    let content_base = document_base_url(&page.html, &page_url);
+    let share = extract_share_metadata(&page.html, &page_url);
+    let extractor = extractors::run_for(&url_obj, state);
+    let html = if charts::snapshots_enabled_for(&url_obj, state) && deadline.allows("replace_chart_embeds") {
+        provenance.processor("replace_chart_embeds");
+        charts::replace_chart_embeds(&page.html, state).await
+    } else {
+        page.html
+    };
+    let ProtectedEmbeds { html, embeds } = if options.keep_embeds {
+        provenance.processor("keep_embeds");
+        embeds::protect_embeds(&html, &url_obj)
+    } else {
+        ProtectedEmbeds { html, embeds: Vec::new() }
+    };
+    let content = extract_content(html, &url_obj, &options.readability_config(), site_config.as_ref(), &deadline, &provenance, &extractor)
+        .and_then(|content| content.map(|content| absolutize_content_urls(&content, &content_base)).transpose())
+        .and_then(|content| match content {
+            Some(content) if options.keep_embeds => embeds::restore_embeds(&content, &embeds).map(Some),
+            content => Ok(content),
+        })
+        .inspect_err(|_| host_stats::record_outcome(&url_obj, ExtractionOutcome::Error, state))?;
 
-    Ok(html)
+    // Other pages of the article (AMP version, following pages) are extracted on their own
+    let extract_page = |html: String, page_url: &Url| {
+        let base = document_base_url(&html, page_url);
+        extract_content(html, page_url, &options.readability_config(), site_config.as_ref(), &deadline, &provenance, &extractor)
+            .and_then(|content| content.map(|content| absolutize_content_urls(&content, &base)).transpose())
+            .unwrap_or_else(|e| {
+                println!("[shared::fetch_article] Extraction of {} failed: {}", page_url, e);
+                None
+            })
+    };
+    // Pages rendered by scripts often embed their whole text in JSON-LD, or link a static
+    // AMP version that extracts fine
+    let content = match (content, json_ld_body) {
+        (content, Some(body)) => {
+            let content_len = content.as_deref().map_or(0, |content| plain_text(content).chars().count());
+            if content_len < JSON_LD_BODY_BELOW && plain_text(&body).chars().count() > content_len && deadline.allows("json_ld_fallback") {
+                println!("[shared::fetch_article] {} chars extracted from {}, using its JSON-LD articleBody instead", content_len, url);
+                provenance.processor("json_ld_fallback");
+                // The articleBody is the whole article, following pages included
+                next_page = None;
+                Some(body)
+            } else {
+                content
+            }
+        }
+        (content, None) => content,
+    };
+    let content_len = content.as_deref().map_or(0, |content| plain_text(content).chars().count());
+    let content = match (content, amp_url) {
+        (content, Some(amp_url)) if content_len < AMP_FALLBACK_BELOW && deadline.allows("amp_fallback") => {
+            println!("[shared::fetch_article] {} chars extracted from {}, trying its AMP version {}", content_len, url, amp_url);
+            let amp_content = match request.fetch(&amp_url, state).await {
+                Ok(amp_page) if amp_page.content_type.contains("html") => {
+                    let amp_page_url = Url::parse(&amp_page.url).unwrap_or(amp_url);
+                    extract_page(amp_page.html, &amp_page_url)
+                }
+                Ok(amp_page) => {
+                    println!("[shared::fetch_article] AMP version is not HTML: {}", amp_page.content_type);
+                    None
+                }
+                Err(e) => {
+                    println!("[shared::fetch_article] AMP version failed to load: {}", e);
+                    None
+                }
+            };
+            match amp_content {
+                Some(amp_content) if plain_text(&amp_content).chars().count() > content_len => {
+                    provenance.processor("amp_fallback");
+                    Some(amp_content)
+                }
+                _ => content,
+            }
+        }
+        (content, _) => content,
+    };
+    // Soft paywalls serve browsers a teaser and search engine crawlers the whole article
+    let content = if retries_as_crawler(&url_obj, state) {
+        let content_len = content.as_deref().map_or(0, |content| plain_text(content).chars().count());
+        if looks_like_teaser(content_len, options.feed_excerpt.as_deref()) && deadline.allows("crawler_retry") {
+            println!("[shared::fetch_article] {} chars extracted from {}, retrying as a crawler", content_len, url);
+            let crawler_request = PageRequest { user_agent: Some(CRAWLER_USER_AGENT.to_string()), without_cookies: true, ..request };
+            let crawled = match crawler_request.fetch(&url_obj, state).await {
+                Ok(crawled) if crawled.content_type.contains("html") => {
+                    let crawled_url = Url::parse(&crawled.url).unwrap_or_else(|_| url_obj.clone());
+                    extract_page(crawled.html, &crawled_url).map(|content| (content, crawled.url, crawled.status))
+                }
+                Ok(crawled) => {
+                    println!("[shared::fetch_article] Crawler retry is not HTML: {}", crawled.content_type);
+                    None
+                }
+                Err(e) => {
+                    println!("[shared::fetch_article] Crawler retry failed: {}", e);
+                    None
+                }
+            };
+            match crawled {
+                Some((crawled, crawled_url, status)) if plain_text(&crawled).chars().count() > content_len => {
+                    provenance.fetched(&crawled_url, status, Some(CRAWLER_USER_AGENT));
+                    provenance.crawler_retry();
+                    provenance.processor("crawler_retry");
+                    Some(crawled)
+                }
+                _ => content,
+            }
+        } else {
+            content
+        }
+    } else {
+        content
+    };
+    let content = match (content, next_page) {
+        (Some(content), Some(next_page)) => {
+            let visited = HashSet::from([url.clone(), page_url.to_string()]);
+            let following = pagination::fetch_following_pages(next_page, visited, &request, &deadline, state, extract_page).await;
+            if following.is_empty() {
+                Some(content)
+            } else {
+                println!("[shared::fetch_article] Stitching {} pages of {}", following.len() + 1, url);
+                provenance.processor("follow_pagination");
+                Some(pagination::stitch_pages(content, &following))
+            }
+        }
+        (content, _) => content,
+    };
+    extractors::record_comparison(&url_obj, &extractor, state);
+    let outcome = match content {
+        Some(_) => ExtractionOutcome::Success,
+        None if paywalled => ExtractionOutcome::Paywall,
+        None => ExtractionOutcome::Fallback,
+    };
+    host_stats::record_outcome(&url_obj, outcome, state);
+    let mut extracted = finish_extraction(&url, content, page.content_language, &options, &deadline, &provenance, state)?;
+    extracted.metadata = article_metadata(share, extractor.title(), extracted.content.as_deref(), &page_url);
+    Ok(extracted)
 }
 
-pub async fn logic_fetch_article(url: String) -> Result<String, String> {
-    let url_obj = Url::parse(&url).map_err(|e| e.to_string())?;
+/// Characters of the first paragraph kept as an excerpt
+const EXCERPT_LEN: usize = 200;
 
-    let client = reqwest::Client::builder()
-        .timeout(Duration::from_secs(30))
-        .redirect(reqwest::redirect::Policy::limited(10))
-        .gzip(true)
-        .brotli(true)
-        .deflate(true)
-        .build()
-        .map_err(|e| e.to_string())?;
+/// Metadata of an article from the share metadata of its page, readability's `title` and the
+/// extracted `content`
+pub fn article_metadata(share: ShareMeta, title: Option<String>, content: Option<&str>, final_url: &Url) -> ArticleMetadata {
+    let document = content.map(scraper::Html::parse_fragment);
+    let first_paragraph = document.as_ref().and_then(|document| {
+        let selector = scraper::Selector::parse("p").unwrap();
+        document
+            .select(&selector)
+            .map(|p| p.text().collect::<Vec<_>>().join(" ").split_whitespace().collect::<Vec<_>>().join(" "))
+            .find(|text| !text.is_empty())
+    });
+    let excerpt = share.description.or_else(|| {
+        first_paragraph.map(|text| match text.char_indices().nth(EXCERPT_LEN) {
+            Some((end, _)) => format!("{}…", text[..end].trim_end()),
+            None => text,
+        })
+    });
+    let lead_image_url = share.image.or_else(|| lead_content_image(document.as_ref()?, final_url));
 
-    // Headers matching the working Python implementation - no Sec-Fetch-* headers
-    let response = client
-        .get(url_obj.clone())
-        .header(USER_AGENT, "Mozilla/5.0 (Windows NT 10.0; Win64; x64; rv:75.0) Gecko/20100101 Firefox/75.0")
-        .header("Accept", "text/html,application/xhtml+xml,application/xml;q=0.9,image/webp,image/apng,*/*;q=0.8")
-        .header("Accept-Encoding", "gzip, deflate, br")
-        .header("Accept-Language", "fr-FR,fr;q=0.8,en-US;q=0.6,en;q=0.4")
-        .header("Cache-Control", "no-cache")
-        .header("Pragma", "no-cache")
-        .header("Connection", "keep-alive")
-        .header("Upgrade-Insecure-Requests", "1")
-        .send()
-        .await
-        .map_err(|e| e.to_string())?;
+    ArticleMetadata {
+        title: title.or(share.title),
+        byline: share.author,
+        excerpt,
+        lead_image_url,
+        site_name: share.site_name,
+        final_url: Some(final_url.to_string()),
+        published: share.published,
+    }
+}
 
-    // Check content type to ensure we're dealing with HTML
-    let content_type = response.headers()
-        .get("content-type")
-        .and_then(|ct| ct.to_str().ok())
-        .unwrap_or("");
+/// Metadata of a page from its tags alone (no extracted content): for HTML that didn't go
+/// through `fetch_article`, such as a rendered fallback page
+pub fn extract_metadata(html: &str, page_url: &Url) -> ArticleMetadata {
+    article_metadata(extract_share_metadata(html, page_url), None, None, page_url)
+}
 
-    if !content_type.contains("text/html") && !content_type.contains("application/xhtml") {
-        return Err(format!("Content type '{}' is not HTML", content_type));
-    }
+pub fn logic_extract_metadata(html: String, url: String) -> Result<ArticleMetadata, String> {
+    let page_url = Url::parse(&url).map_err(|e| e.to_string())?;
+    Ok(extract_metadata(&html, &page_url))
+}
 
-    let html = response.text().await.map_err(|e| e.to_string())?;
+/// Records a version of extracted content and applies the display options to it
+pub fn finish_extraction(
+    url: &str,
+    mut content: Option<String>,
+    content_language: Option<String>,
+    options: &ArticleOptions,
+    deadline: &Deadline,
+    provenance: &ProvenanceBuilder,
+    state: &ProxyState,
+) -> Result<ExtractedArticle, String> {
+    if options.words_per_minute == Some(0) {
+        return Err("words_per_minute must be greater than 0".into());
+    }
+    if let (Some(html), true) = (content.as_mut(), state.sanitize_articles.load(Ordering::Relaxed)) {
+        *html = sanitize_article_html(html);
+        provenance.processor("sanitize");
+    }
+    if let Some(html) = &content {
+        versions::record_version(url, html, state);
+    }
+    if let (Some(html), Ok(url_obj)) = (content.as_mut(), Url::parse(url)) {
+        if !element_filters::filters_for(url_obj.host_str().unwrap_or_default(), state).is_empty() {
+            *html = element_filters::apply_element_filters(html, &url_obj, state);
+            provenance.processor("element_filters");
+        }
+    }
+    if let (Some(html), Some(max_width)) = (content.as_mut(), options.max_image_width) {
+        if deadline.allows("cap_image_widths") {
+            *html = cap_image_widths(html, max_width)?;
+            provenance.processor("cap_image_widths");
+        }
+    }
+    if let (Some(html), true) = (content.as_mut(), options.classify_blocks) {
+        *html = callouts::classify_blocks(html)?;
+        provenance.processor("classify_blocks");
+    }
+    Ok(ExtractedArticle {
+        content,
+        content_language,
+        skipped_steps: deadline.skipped_steps(),
+        provenance: Some(provenance.finish()),
+        metadata: ArticleMetadata::default(),
+        feed: None,
+        words_per_minute: options.words_per_minute,
+    })
+}
 
+/// The active extractor backend (readability unless `extractor` says otherwise) over an
+/// already fetched page, with the empty-shell checks, site rules and the fallbacks of
+/// `config`. `None` means the iframe fallback should be used. The
+/// clean-up passes and the lenient fallback are skipped once `deadline` is spent.
+pub fn extract_content(
+    html: String,
+    url_obj: &Url,
+    config: &ReadabilityConfig,
+    site_config: Option<&SiteConfig>,
+    deadline: &Deadline,
+    provenance: &ProvenanceBuilder,
+    extractor: &ExtractorRun,
+) -> Result<Option<String>, String> {
     if html.trim().is_empty() {
         return Err("Fetched HTML content is empty.".into());
     }
@@ -176,13 +3274,13 @@ pub async fn logic_fetch_article(url: String) -> Result<String, String> {
 
     // Check for exact match of empty HTML
     if trimmed == "<!DOCTYPE html><html><head></head><body></body></html>" {
-        return Ok(FALLBACK_SIGNAL.to_string());
+        return Ok(None);
     }
 
     // Check for variations and minimal content
-    if trimmed.len() < 150 {
+    if trimmed.len() < config.min_raw_html_len {
         if trimmed.contains("<head></head>") && trimmed.contains("<body></body>") {
-            return Ok(FALLBACK_SIGNAL.to_string());
+            return Ok(None);
         }
 
         // Check if it's essentially empty (no meaningful content tags)
@@ -192,7 +3290,7 @@ pub async fn logic_fetch_article(url: String) -> Result<String, String> {
                          trimmed.contains("<h2") || trimmed.contains("<span");
 
         if !has_content {
-            return Ok(FALLBACK_SIGNAL.to_string());
+            return Ok(None);
         }
     }
 
@@ -205,51 +3303,199 @@ pub async fn logic_fetch_article(url: String) -> Result<String, String> {
     let html_normalized = html.trim().replace('\n', "").replace('\r', "");
 
     // Multiple patterns to catch different variations of empty HTML
-    let patterns = [
-        r"^<!DOCTYPE html><html><head></head><body></body></html>$",
-        r"^<!doctype html><html><head></head><body></body></html>$",
-        r"^<html><head></head><body></body></html>$",
-        r"^<!DOCTYPE html><html><head>\s*</head><body>\s*</body></html>$",
-    ];
-
-    for pattern in &patterns {
-        let regex = regex::Regex::new(pattern).unwrap();
+    for pattern in &config.empty_doc_patterns {
+        let regex = regex::Regex::new(pattern).map_err(|e| format!("Invalid empty document pattern '{}': {}", pattern, e))?;
         if regex.is_match(&html_normalized) {
-            return Ok(FALLBACK_SIGNAL.to_string());
+            return Ok(None);
         }
     }
 
     // Additional check: if the body is essentially empty
-    if html.len() < 200 && !html.contains("<p") && !html.contains("<div") && !html.contains("<article") && !html.contains("<main") {
-        return Ok(FALLBACK_SIGNAL.to_string());
+    if html.len() < config.min_document_len && !html.contains("<p") && !html.contains("<div") && !html.contains("<article") && !html.contains("<main") {
+        return Ok(None);
     }
 
-    let mut content_cursor = Cursor::new(html.as_bytes());
-    match readability::extractor::extract(&mut content_cursor, &url_obj) {
-        Ok(product) => {
-            let extracted_content = product.content.trim();
+    // Drop consent walls and cookie banners so they can't hijack extraction
+    let html = strip_consent_banners(&html);
+    provenance.processor("strip_consent_banners");
+    let run = |step: &'static str| deadline.allows(step) && { provenance.processor(step); true };
+    let html = if run("unwrap_noscript_images") { unwrap_noscript_images(&html) } else { html };
+    let html = if run("promote_lazy_images") { promote_lazy_images(&html) } else { html };
+    let html = if run("convert_amp_elements") { convert_amp_elements(&html) } else { html };
+    let html = if !run("reveal_hidden_content") {
+        html
+    } else {
+        match reveal_hidden_content(&html) {
+            Ok((revealed, _)) => revealed,
+            Err(e) => {
+                println!("[shared::extract_content] Revealing hidden content failed, keeping original HTML: {}", e);
+                html
+            }
+        }
+    };
 
-            // Check if extracted content is meaningful
-            if extracted_content.is_empty() {
-                return Ok(FALLBACK_SIGNAL.to_string());
+    // ftr-site-config rules for this host take precedence over readability
+    let html = match site_config {
+        Some(config) => {
+            let html = site_config::apply_cleanup(&html, config);
+            provenance.processor("site_config_cleanup");
+            if let Some(body) = site_config::extract_body(&html, config) {
+                println!("[shared::fetch_article] Using site config body rules for {}", url_obj);
+                provenance.source(ProvenanceSource::ScrapeRule);
+                return Ok(Some(body));
+            }
+            if !config.autodetect_on_failure {
+                return Ok(None);
             }
+            html
+        }
+        None => html,
+    };
 
-            // Check if extracted content is just minimal HTML
-            if extracted_content.len() < 100 &&
-               (extracted_content.contains("<head></head>") ||
-                extracted_content == "<!DOCTYPE html><html><head></head><body></body></html>") {
-                return Ok(FALLBACK_SIGNAL.to_string());
+    provenance.processor(extractor.backend.step());
+    let extracted = extractors::extract_with(extractor, &html, url_obj, config);
+
+    match extracted {
+        Some((content, text_len)) if text_len >= config.min_extracted_len => Ok(Some(content)),
+        extracted if config.lenient_fallback => {
+            // The active backend returned too little: try the other one, keeping the first
+            // output if it still holds more text
+            let extracted_len = extracted.as_ref().map(|(_, len)| *len).unwrap_or(0);
+            if !deadline.allows("lenient_fallback") {
+                return Ok(extracted.map(|(content, _)| content));
+            }
+            match extractor.extract(extractor.backend.other(), &html, url_obj, config) {
+                Some((fallback, fallback_len)) if fallback_len > extracted_len => {
+                    println!("[shared::fetch_article] Lenient fallback: using {} ({} chars)", extractor.backend.other().step(), fallback_len);
+                    provenance.processor("lenient_fallback");
+                    Ok(Some(fallback))
+                }
+                _ => Ok(extracted.map(|(content, _)| content)),
             }
+        }
+        _ => Ok(None),
+    }
+}
 
-            Ok(product.content)
-        },
-        Err(_) => {
-            Ok(FALLBACK_SIGNAL.to_string())
+pub async fn logic_fetch_article(url: String, options: ArticleOptions, state: &ProxyState) -> Result<ArticleResult, String> {
+    structured_article(logic_extract_article(url, options, state).await?)
+}
+
+/// `fetch_article` with the fallback thresholds of `config` instead of the strictness preset
+pub async fn logic_fetch_article_with_config(url: String, options: ArticleOptions, config: ReadabilityConfig, state: &ProxyState) -> Result<ArticleResult, String> {
+    logic_fetch_article(url, ArticleOptions { readability: Some(config), ..options }, state).await
+}
+
+/// `fetch_article` with pull-quotes, callouts and key-takeaway panels classified
+pub async fn logic_fetch_article_classified(url: String, options: ArticleOptions, state: &ProxyState) -> Result<ArticleResult, String> {
+    logic_fetch_article(url, ArticleOptions { classify_blocks: true, ..options }, state).await
+}
+
+/// `ArticleResult` of an extraction: outline and fingerprint of the content, or the fallback flag
+pub fn structured_article(extracted: ExtractedArticle) -> Result<ArticleResult, String> {
+    match extracted.content {
+        Some(content) => {
+            let (content, outline) = extract_outline(&content)?;
+            let level = reading_level::reading_level(&content);
+            let plain_text = plain_text(&content);
+            let word_count = count_words(&plain_text);
+            Ok(ArticleResult {
+                word_count,
+                reading_time_minutes: reading_time_minutes(word_count, extracted.words_per_minute.unwrap_or(DEFAULT_WORDS_PER_MINUTE)),
+                plain_text,
+                blocks: callouts::classified_blocks(&content),
+                layout_fingerprint: layout_fingerprint(&content),
+                reading_grade: level.map(|level| level.grade),
+                flesch_score: level.map(|level| level.flesch_score),
+                content,
+                fallback: false,
+                is_feed: None,
+                outline,
+                content_language: extracted.content_language,
+                skipped_steps: extracted.skipped_steps,
+                provenance: extracted.provenance,
+                metadata: extracted.metadata,
+            })
+        }
+        None => Ok(ArticleResult {
+            fallback: extracted.feed.is_none(),
+            is_feed: extracted.feed,
+            content_language: extracted.content_language,
+            skipped_steps: extracted.skipped_steps,
+            provenance: extracted.provenance,
+            metadata: extracted.metadata,
+            ..ArticleResult::default()
+        }),
+    }
+}
+
+/// Outline of HTML that didn't go through `fetch_article` (e.g. content from a sync backend)
+pub fn logic_extract_outline(html: String) -> Result<OutlinedHtml, String> {
+    let (content, outline) = extract_outline(&html)?;
+    Ok(OutlinedHtml { content, outline })
+}
+
+pub fn logic_reveal_hidden_content(html: String) -> Result<RevealedHtml, String> {
+    let (content, revealed) = reveal_hidden_content(&html)?;
+    Ok(RevealedHtml { content, revealed })
+}
+
+pub async fn logic_fetch_article_segmented(url: String, options: ArticleOptions, state: &ProxyState) -> Result<SegmentedArticle, String> {
+    match logic_extract_article(url, options, state).await?.content {
+        Some(content) => {
+            let (content, total_words) = annotate_word_offsets(&content)?;
+            Ok(SegmentedArticle { content, total_words, fallback: false })
         }
+        None => Ok(SegmentedArticle { content: String::new(), total_words: 0, fallback: true }),
     }
 }
 
+pub fn logic_set_user_agent_pool(agents: Vec<String>, state: &ProxyState) {
+    let agents: Vec<String> = agents
+        .into_iter()
+        .map(|agent| agent.trim().to_string())
+        .filter(|agent| !agent.is_empty())
+        .collect();
+    println!("[shared::set_user_agent_pool] {} User-Agent strings in pool", agents.len());
+
+    let mut pool = state.user_agent_pool.lock().unwrap();
+    pool.agents = agents;
+    pool.next = 0;
+}
+
+/// A User-Agent given by the caller, trimmed. `None` when empty; an error when it can't be
+/// sent as a header.
+pub fn user_agent_override(user_agent: Option<String>) -> Result<Option<String>, String> {
+    let Some(user_agent) = user_agent.map(|user_agent| user_agent.trim().to_string()).filter(|user_agent| !user_agent.is_empty()) else {
+        return Ok(None);
+    };
+    HeaderValue::from_str(&user_agent).map_err(|_| format!("Invalid User-Agent '{}'", user_agent))?;
+    Ok(Some(user_agent))
+}
+
+/// Replaces the User-Agent of upstream requests; `None` or an empty string restores
+/// `DEFAULT_USER_AGENT`. Rotation, when on, still takes precedence.
+pub fn logic_set_user_agent(user_agent: Option<String>, state: &ProxyState) -> Result<(), String> {
+    let user_agent = user_agent_override(user_agent)?.unwrap_or_else(|| DEFAULT_USER_AGENT.to_string());
+    println!("[shared::set_user_agent] {}", user_agent);
+    state.user_agent.store(Arc::new(user_agent));
+    Ok(())
+}
+
+pub fn logic_set_user_agent_rotation(enabled: bool, state: &ProxyState) {
+    let mut pool = state.user_agent_pool.lock().unwrap();
+    pool.enabled = enabled;
+}
+
+/// Turns `sanitize_article_html` on or off for extracted articles. Off, readability's output is
+/// returned as is, iframes of any origin included.
+pub fn logic_set_article_sanitization(enabled: bool, state: &ProxyState) {
+    println!("[shared::set_article_sanitization] {}", enabled);
+    state.sanitize_articles.store(enabled, Ordering::Relaxed);
+}
+
 pub async fn logic_perform_form_login(request: LoginRequest, state: &ProxyState) -> Result<LoginResponse, String> {
+    connectivity::ensure_online(state)?;
     let login_url = Url::parse(&request.login_url).map_err(|e| e.to_string())?;
 
     println!("[shared::perform_form_login] ========================================");
@@ -270,16 +3516,13 @@ pub async fn logic_perform_form_login(request: LoginRequest, state: &ProxyState)
     println!("[shared::perform_form_login] Cookies in jar for POST URL: {:?}", cookies_for_url);
 
     // Also check cookies for the base domain (in case they're stored there)
-    if let Some(host) = login_url.host_str() {
-        let base_url = Url::parse(&format!("{}://{}", login_url.scheme(), host)).ok();
-        if let Some(base) = base_url {
-            let base_cookies = state.cookie_jar.cookies(&base);
-            println!("[shared::perform_form_login] Cookies for base domain {}: {:?}", host, base_cookies);
-        }
+    if let Ok(base) = Url::parse(&origin_of(&login_url)) {
+        let base_cookies = state.cookie_jar.cookies(&base);
+        println!("[shared::perform_form_login] Cookies for base domain {}: {:?}", host_header_of(&login_url), base_cookies);
     }
 
     // Create client with shared cookie jar
-    let client = reqwest::Client::builder()
+    let client = with_protocol_for(reqwest::Client::builder(), &login_url, state)
         .cookie_store(true)
         .cookie_provider(state.cookie_jar.clone())
         .timeout(Duration::from_secs(30))
@@ -289,9 +3532,9 @@ pub async fn logic_perform_form_login(request: LoginRequest, state: &ProxyState)
 
     // Perform POST request with headers matching the working Python implementation
     // Note: Do NOT use Sec-Fetch-* headers - they can cause 406 errors on some sites like Le Monde
-    let host = login_url.host_str().unwrap_or("");
+    let host = host_header_of(&login_url);
     // Origin should NOT have trailing slash for most sites
-    let origin = format!("{}://{}", login_url.scheme(), host);
+    let origin = origin_of(&login_url);
 
     println!("[shared::perform_form_login] Host: {}", host);
     println!("[shared::perform_form_login] Origin: {}", origin);
@@ -299,16 +3542,17 @@ pub async fn logic_perform_form_login(request: LoginRequest, state: &ProxyState)
     println!("[shared::perform_form_login] Content-Type: application/x-www-form-urlencoded");
     println!("[shared::perform_form_login] Form data count: {} fields", form_data.len());
 
+    // The UA pool is deliberately not used here: sessions can be tied to the UA that logged in
     let response = client
         .post(login_url.clone())
-        .header(USER_AGENT, "Mozilla/5.0 (Windows NT 10.0; Win64; x64; rv:75.0) Gecko/20100101 Firefox/75.0")
+        .header(USER_AGENT, state.user_agent.load().as_str())
         .header("Accept", "text/html,application/xhtml+xml,application/xml;q=0.9,image/webp,image/apng,*/*;q=0.8")
         .header("Accept-Encoding", "gzip, deflate, br")
         .header("Accept-Language", "fr-FR,fr;q=0.8,en-US;q=0.6,en;q=0.4")
         .header("Cache-Control", "no-cache")
         .header("Content-Type", "application/x-www-form-urlencoded")
         .header("Origin", &origin)
-        .header("Host", host)
+        .header("Host", &host)
         .header("Upgrade-Insecure-Requests", "1")
         .header("Connection", "keep-alive")
         .header("Pragma", "no-cache")
@@ -381,3 +3625,653 @@ pub async fn logic_perform_form_login(request: LoginRequest, state: &ProxyState)
         extracted_text,
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{check_clear_for_domain, serve};
+    use axum::http::HeaderMap;
+    use axum::response::{Html, IntoResponse};
+    use axum::routing::get;
+    use axum::Router;
+
+    fn page_request() -> PageRequest<'static> {
+        PageRequest { site_config: None, accept_language: "en", priority: RequestPriority::Interactive, timeout_secs: Some(5), user_agent: None, without_cookies: false }
+    }
+
+    /// Echoes the `Cookie` header of the request in an article page
+    async fn echo_cookie(headers: HeaderMap) -> Html<String> {
+        let cookie = headers.get("cookie").and_then(|value| value.to_str().ok()).unwrap_or("none");
+        Html(format!("<html><body><article><p>cookie: {}</p></article></body></html>", cookie))
+    }
+
+    #[tokio::test]
+    async fn article_fetches_send_the_session_cookie() {
+        let app = Router::new()
+            .route("/login", get(|| async { ([("set-cookie", "session=abc123; Path=/; HttpOnly")], Html("<html><body>welcome</body></html>")).into_response() }))
+            .route("/article", get(echo_cookie));
+        let addr = serve(app).await;
+        let state = ProxyState::default();
+        let url = |path: &str| Url::parse(&format!("http://{}{}", addr, path)).unwrap();
+
+        let before = page_request().fetch(&url("/article"), &state).await.unwrap();
+        assert!(before.html.contains("cookie: none"), "{}", before.html);
+
+        page_request().fetch(&url("/login"), &state).await.unwrap();
+        assert!(state.cookie_jar.cookies(&url("/article")).is_some());
+        let after = page_request().fetch(&url("/article"), &state).await.unwrap();
+        assert!(after.html.contains("cookie: session=abc123"), "{}", after.html);
+
+        // Raw fetches share the jar
+        let raw = logic_fetch_raw_html(url("/article").to_string(), Some(5), None, &state).await.unwrap();
+        assert!(raw.contains("cookie: session=abc123"), "{}", raw);
+
+        // Crawler retries go without it
+        let cookieless = PageRequest { without_cookies: true, ..page_request() };
+        let crawler = cookieless.fetch(&url("/article"), &state).await.unwrap();
+        assert!(crawler.html.contains("cookie: none"), "{}", crawler.html);
+    }
+
+    #[test]
+    fn origins_and_host_headers_keep_ipv6_brackets_and_ports() {
+        let cases = [
+            ("https://example.com/feed.xml", "https://example.com", "example.com"),
+            ("https://example.com:443/a", "https://example.com", "example.com"),
+            ("http://example.com:8080/a", "http://example.com:8080", "example.com:8080"),
+            ("http://[fd00::12]:8080/feed.xml", "http://[fd00::12]:8080", "[fd00::12]:8080"),
+            ("https://[::1]/a", "https://[::1]", "[::1]"),
+            ("http://192.168.1.2:81/", "http://192.168.1.2:81", "192.168.1.2:81"),
+        ];
+        for (url, origin, host) in cases {
+            let url = Url::parse(url).unwrap();
+            assert_eq!(origin_of(&url), origin, "{}", url);
+            assert_eq!(host_header_of(&url), host, "{}", url);
+        }
+    }
+
+    /// Echoes the `Host`, `Origin` and `Authorization` headers of the request in an article page
+    async fn echo_request_headers(headers: HeaderMap) -> Html<String> {
+        let header = |name: &str| headers.get(name).and_then(|value| value.to_str().ok()).unwrap_or("none").to_string();
+        Html(format!(
+            "<html><body><article><p>host={} origin={} auth={}</p></article></body></html>",
+            header("host"),
+            header("origin"),
+            header("authorization")
+        ))
+    }
+
+    #[tokio::test]
+    async fn fetches_and_logs_in_to_an_ipv6_origin_with_a_port() {
+        let app = Router::new().route("/feed", get(echo_request_headers)).route("/login", axum::routing::post(echo_request_headers));
+        let addr = crate::test_support::serve_on("::1", app).await;
+        let host = format!("[::1]:{}", addr.port());
+        let state = ProxyState::default();
+        state.auth_credentials.insert(format!("http://{}", host), AuthMethod::Bearer("v6-token".into()));
+
+        let raw = logic_fetch_raw_html(format!("http://{}/feed", host), Some(5), None, &state).await.unwrap();
+        assert!(raw.contains(&format!("host={} origin=none auth=Bearer v6-token", host)), "{}", raw);
+
+        let login = LoginRequest {
+            login_url: format!("http://{}/login", host),
+            fields: vec![FormField { name: "user".into(), value: "me".into() }],
+            response_selector: Some("article p".into()),
+        };
+        let response = logic_perform_form_login(login, &state).await.unwrap();
+        assert!(response.success);
+        let echoed = response.extracted_text.unwrap_or_default();
+        assert!(echoed.starts_with(&format!("host={} origin=http://{} ", host, host)), "{}", echoed);
+        let page = page_request().fetch(&Url::parse(&format!("http://{}/feed", host)).unwrap(), &state).await.unwrap();
+        assert!(page.html.contains(&format!("host={} origin=none auth=Bearer v6-token", host)), "{}", page.html);
+    }
+
+    #[test]
+    fn clears_the_accept_languages_of_a_domain() {
+        check_clear_for_domain(
+            |state, host| {
+                state.accept_languages.insert(host.to_string(), "fr".into());
+            },
+            clear_accept_language_for_domain,
+            |state| state.accept_languages.iter().map(|entry| entry.key().clone()).collect(),
+        );
+    }
+
+    #[test]
+    fn clears_the_rendering_overrides_of_a_domain() {
+        check_clear_for_domain(
+            |state, host| {
+                state.rendering_hosts.insert(host.to_string());
+            },
+            clear_rendering_override_for_domain,
+            |state| state.rendering_hosts.iter().map(|key| key.clone()).collect(),
+        );
+    }
+
+    #[test]
+    fn clears_the_crawler_retries_of_a_domain() {
+        check_clear_for_domain(
+            |state, host| {
+                state.crawler_retry_hosts.insert(host.to_string());
+            },
+            clear_crawler_retry_for_domain,
+            |state| state.crawler_retry_hosts.iter().map(|key| key.clone()).collect(),
+        );
+    }
+
+    #[test]
+    fn clears_the_credentials_of_a_domain() {
+        check_clear_for_domain(
+            |state, host| {
+                state.auth_credentials.insert(format!("https://{}", host), AuthMethod::Bearer("token".into()));
+            },
+            clear_auth_for_domain,
+            |state| state.auth_credentials.iter().map(|entry| entry.key().clone()).collect(),
+        );
+    }
+
+    #[test]
+    fn clear_proxy_auth_removes_one_origin() {
+        let state = ProxyState::default();
+        for key in ["https://example.com", "https://news.example.com"] {
+            state.auth_credentials.insert(key.to_string(), AuthMethod::Bearer("token".into()));
+        }
+
+        // A bare host finds the origin the migrated store keys it by
+        let preview = logic_clear_proxy_auth("example.com".into(), true, &state);
+        assert_eq!((preview.dry_run, preview.count), (true, 1));
+        assert_eq!(preview.entries[0].key, "https://example.com");
+        assert_eq!(state.auth_credentials.len(), 2);
+
+        let report = logic_clear_proxy_auth("example.com".into(), false, &state);
+        assert_eq!(report.count, 1);
+        assert!(state.auth_credentials.contains_key("https://news.example.com"));
+        assert!(!state.auth_credentials.contains_key("https://example.com"));
+        assert_eq!(logic_clear_proxy_auth("example.com".into(), false, &state).count, 0);
+    }
+
+    const WITH_OG: &str = include_str!("../tests/fixtures/articles/with_og.html");
+    const WITHOUT_OG: &str = include_str!("../tests/fixtures/articles/without_og.html");
+    const APP_SHELL: &str = include_str!("../tests/fixtures/articles/app_shell.html");
+    const LAZY_IMAGES: &str = include_str!("../tests/fixtures/articles/lazy_images.html");
+    const AMP_SHELL: &str = include_str!("../tests/fixtures/articles/amp_shell.html");
+    const AMP_ARTICLE: &str = include_str!("../tests/fixtures/articles/amp_article.html");
+    const JSON_LD_BODY: &str = include_str!("../tests/fixtures/articles/json_ld_body.html");
+    const PAYWALL_TEASER: &str = include_str!("../tests/fixtures/articles/paywall_teaser.html");
+    const PAYWALL_FULL: &str = include_str!("../tests/fixtures/articles/paywall_full.html");
+
+    /// Serves the article fixtures, the OpenGraph one also behind a redirect
+    async fn article_site() -> String {
+        let app = Router::new()
+            .route("/travel/night-ferry", get(|| async { Html(WITH_OG) }))
+            .route("/t/8841", get(|| async { axum::response::Redirect::permanent("/travel/night-ferry") }))
+            .route("/blog/dry-stone-wall", get(|| async { Html(WITHOUT_OG) }))
+            .route("/dashboard", get(|| async { Html(APP_SHELL) }))
+            .route("/2026/06/coast-path", get(|| async { Html(LAZY_IMAGES) }))
+            .route("/news/harbour-pilots-strike", get(|| async { Html(AMP_SHELL) }))
+            .route("/news/harbour-pilots-strike/amp", get(|| async { Html(AMP_ARTICLE) }))
+            .route("/news/market-hall", get(|| async { Html(JSON_LD_BODY) }))
+            // The article node's JSON is cut short: no body to fall back on
+            .route("/news/market-hall-broken", get(|| async { Html(JSON_LD_BODY.replacen("\"headline\"", "\"headline\" \"", 1)) }))
+            .route("/news/ferry-timetable", get(|| async { Html(AMP_SHELL.replace("harbour-pilots-strike/amp", "ferry-timetable/amp")) }));
+        format!("http://{}", serve(app).await)
+    }
+
+    #[tokio::test]
+    async fn structured_articles_take_their_metadata_from_opengraph() {
+        let site = article_site().await;
+        let article = logic_fetch_article(format!("{}/t/8841", site), ArticleOptions::default(), &ProxyState::default()).await.unwrap();
+        assert!(!article.fallback);
+        assert!(article.content.contains("the overnight crossing between the harbour and the islands"));
+
+        // The title is readability's, the rest OpenGraph's, the final URL after the redirect
+        let metadata = article.metadata;
+        assert_eq!(metadata.title.as_deref(), Some("The night ferry is back after six years | Harbour Gazette"));
+        assert_eq!(metadata.byline.as_deref(), Some("Priya Natarajan"));
+        assert_eq!(metadata.excerpt.as_deref(), Some("After six years, the overnight crossing returns this spring with a refitted ship and a new timetable."));
+        assert_eq!(metadata.lead_image_url, Some(format!("{}/media/2024/03/ferry-lead.jpg", site)));
+        assert_eq!(metadata.site_name.as_deref(), Some("Harbour Gazette"));
+        assert_eq!(metadata.final_url, Some(format!("{}/travel/night-ferry", site)));
+    }
+
+    #[tokio::test]
+    async fn structured_articles_without_opengraph_fall_back_on_the_page() {
+        let site = article_site().await;
+        let state = ProxyState::default();
+        let article = logic_fetch_article(format!("{}/blog/dry-stone-wall", site), ArticleOptions::default(), &state).await.unwrap();
+        assert!(!article.fallback);
+
+        // Excerpt and lead image come from the content, the site name from the host
+        let metadata = article.metadata;
+        assert_eq!(metadata.title.as_deref(), Some("Repairing a dry stone wall"));
+        assert_eq!(metadata.byline, None);
+        let excerpt = metadata.excerpt.unwrap();
+        assert!(excerpt.starts_with("The wall along the top of the lower field came down"), "{}", excerpt);
+        assert_eq!(excerpt.chars().count(), EXCERPT_LEN + 1);
+        assert!(excerpt.ends_with('…'));
+        assert_eq!(metadata.lead_image_url, Some(format!("{}/blog/images/wall-before.jpg", site)));
+        assert_eq!(metadata.site_name.as_deref(), Some("127.0.0.1"));
+        assert_eq!(metadata.final_url, Some(format!("{}/blog/dry-stone-wall", site)));
+
+        // A JavaScript app shell has nothing to extract: the fallback result still has the
+        // page's title and site name
+        let options = ArticleOptions { strictness: ExtractionStrictness::Strict, ..ArticleOptions::default() };
+        let shell = logic_fetch_article(format!("{}/dashboard", site), options, &state).await.unwrap();
+        assert!(shell.fallback);
+        assert_eq!(shell.content, "");
+        assert_eq!(shell.metadata.title.as_deref(), Some("Dashboard · Tidewatch"));
+        assert_eq!(shell.metadata.site_name.as_deref(), Some("Tidewatch"));
+        assert_eq!((shell.metadata.excerpt, shell.metadata.lead_image_url), (None, None));
+    }
+
+    const LATIN1_PAGE: &[u8] = include_bytes!("../tests/fixtures/charsets/latin1.html");
+    const SHIFT_JIS_PAGE: &[u8] = include_bytes!("../tests/fixtures/charsets/shift_jis.html");
+    /// Declares its charset in `Content-Type` only
+    const SHIFT_JIS_HEADER_ONLY_PAGE: &[u8] = include_bytes!("../tests/fixtures/charsets/shift_jis_header_only.html");
+    const LATIN1_TEXT: &str = "Été comme hiver, c'est l'un des desserts les plus commandés des brasseries";
+    const SHIFT_JIS_TEXT: &str = "京都には、昭和の初めから続く喫茶店がいくつも残っている。";
+
+    /// `page` without its `<meta http-equiv="Content-Type">`
+    fn without_meta_charset(page: &[u8]) -> Vec<u8> {
+        regex::bytes::Regex::new(r"(?i)<meta\s[^>]*charset[^>]*>").unwrap().replace(page, &b""[..]).into_owned()
+    }
+
+    #[test]
+    fn html_encoding_trusts_the_bytes_over_wrong_declarations() {
+        let utf8_with_stale_meta = "<meta charset=\"iso-8859-1\"><p>crème brûlée</p>".as_bytes();
+        let utf16 = [&[0xFF, 0xFE][..], "<p>é</p>".encode_utf16().flat_map(u16::to_le_bytes).collect::<Vec<u8>>().as_slice()].concat();
+        let cases: [(&[u8], Option<&str>, &encoding_rs::Encoding); 11] = [
+            (LATIN1_PAGE, None, encoding_rs::WINDOWS_1252),
+            (LATIN1_PAGE, Some("utf-8"), encoding_rs::WINDOWS_1252),
+            (LATIN1_PAGE, Some("ISO-8859-1"), encoding_rs::WINDOWS_1252),
+            (&without_meta_charset(LATIN1_PAGE), None, encoding_rs::WINDOWS_1252),
+            (SHIFT_JIS_PAGE, None, encoding_rs::SHIFT_JIS),
+            (SHIFT_JIS_PAGE, Some("utf-8"), encoding_rs::SHIFT_JIS),
+            (SHIFT_JIS_PAGE, Some("\"shift_jis\""), encoding_rs::SHIFT_JIS),
+            (&without_meta_charset(SHIFT_JIS_PAGE), Some("Shift_JIS"), encoding_rs::SHIFT_JIS),
+            (utf8_with_stale_meta, None, encoding_rs::UTF_8),
+            (utf8_with_stale_meta, Some("windows-1252"), encoding_rs::UTF_8),
+            (&utf16, Some("utf-8"), encoding_rs::UTF_16LE),
+        ];
+        for (index, (bytes, header, expected)) in cases.into_iter().enumerate() {
+            assert_eq!(html_encoding(bytes, header).name(), expected.name(), "case {}", index);
+        }
+        assert!(without_meta_charset(SHIFT_JIS_PAGE).len() < SHIFT_JIS_PAGE.len());
+    }
+
+    #[test]
+    fn header_charset_reads_any_spelling_of_the_parameter() {
+        let charset = |content_type: &str| {
+            let mut headers = reqwest::header::HeaderMap::new();
+            headers.insert(reqwest::header::CONTENT_TYPE, content_type.parse().unwrap());
+            header_charset(&headers)
+        };
+        for content_type in ["text/html; charset=Shift_JIS", "text/html; Charset=Shift_JIS", "text/html;CHARSET = \"Shift_JIS\"", "text/html; format=flowed; charset=Shift_JIS"] {
+            assert_eq!(charset(content_type).as_deref(), Some("Shift_JIS"), "{}", content_type);
+        }
+        assert_eq!(charset("text/html"), None);
+        assert_eq!(charset("text/html; xcharset=utf-8"), None);
+    }
+
+    /// Serves the legacy-charset fixtures, without a charset in `Content-Type`, with a wrong one,
+    /// and (for a page without `<meta>`) with the `Charset=` spelling of older servers
+    async fn charset_site() -> String {
+        let page = |bytes: &'static [u8], content_type: &'static str| get(move || async move { ([(axum::http::header::CONTENT_TYPE, content_type)], bytes) });
+        let app = Router::new()
+            .route("/latin1", page(LATIN1_PAGE, "text/html"))
+            .route("/latin1-mislabeled", page(LATIN1_PAGE, "text/html; charset=utf-8"))
+            .route("/shift-jis", page(SHIFT_JIS_PAGE, "text/html"))
+            .route("/shift-jis-mislabeled", page(SHIFT_JIS_PAGE, "text/html; charset=utf-8"))
+            .route("/shift-jis-header-only", page(SHIFT_JIS_HEADER_ONLY_PAGE, "text/html; Charset = Shift_JIS"));
+        format!("http://{}", serve(app).await)
+    }
+
+    #[tokio::test]
+    async fn legacy_charset_pages_are_decoded_to_utf8() {
+        let site = charset_site().await;
+        let state = ProxyState::default();
+        for (path, expected, title) in [
+            ("latin1", LATIN1_TEXT, "La crème brûlée, une histoire française ?"),
+            ("latin1-mislabeled", LATIN1_TEXT, "La crème brûlée, une histoire française ?"),
+            ("shift-jis", SHIFT_JIS_TEXT, "京都の古い喫茶店を訪ねて"),
+            ("shift-jis-mislabeled", SHIFT_JIS_TEXT, "京都の古い喫茶店を訪ねて"),
+            ("shift-jis-header-only", SHIFT_JIS_TEXT, "京都の古い喫茶店を訪ねて"),
+        ] {
+            let url = format!("{}/{}", site, path);
+            let raw = logic_fetch_raw_html(url.clone(), None, None, &state).await.unwrap();
+            assert!(raw.contains(expected) && raw.contains(title), "{}: {}", path, raw);
+            assert!(!raw.contains('\u{FFFD}'), "{}", path);
+
+            let article = logic_fetch_article(url, ArticleOptions::default(), &state).await.unwrap();
+            assert!(article.content.contains(expected), "{}: {}", path, article.content);
+            assert!(!article.content.contains('\u{FFFD}'), "{}", path);
+        }
+    }
+
+    /// Lead image of an article at `https://news.example/travel/night-ferry`, from the `<head>`
+    /// of its page and its extracted content
+    fn lead_image(head: &str, content: &str) -> Option<String> {
+        let url = Url::parse("https://news.example/travel/night-ferry").unwrap();
+        let page = format!("<html><head>{}</head><body>{}</body></html>", head, content);
+        article_metadata(extract_share_metadata(&page, &url), None, Some(content), &url).lead_image_url
+    }
+
+    #[test]
+    fn lead_image_urls_are_resolved_against_the_article() {
+        let cases = [
+            (r#"<meta property="og:image" content="/img/ferry.jpg">"#, "", "https://news.example/img/ferry.jpg"),
+            (r#"<meta name="twitter:image" content="../img/ferry.jpg">"#, "", "https://news.example/img/ferry.jpg"),
+            (r#"<meta property="og:image" content="//cdn.example/ferry.jpg">"#, "", "https://cdn.example/ferry.jpg"),
+            (r#"<link rel="image_src" href="ferry.jpg">"#, "", "https://news.example/travel/ferry.jpg"),
+            (r#"<link rel="image_src" href="//cdn.example/ferry.jpg">"#, "", "https://cdn.example/ferry.jpg"),
+            ("", r#"<p>text</p><img src="photos/deck.jpg" width="800">"#, "https://news.example/travel/photos/deck.jpg"),
+            ("", r#"<img src="//cdn.example/deck.jpg">"#, "https://cdn.example/deck.jpg"),
+        ];
+        for (head, content, expected) in cases {
+            assert_eq!(lead_image(head, content).as_deref(), Some(expected), "{} {}", head, content);
+        }
+    }
+
+    #[test]
+    fn lead_content_image_is_the_largest_srcset_variant() {
+        let cases = [
+            (r#"<img src="deck.jpg" srcset="deck.jpg 1x, deck@2x.jpg 2x">"#, "https://news.example/travel/deck@2x.jpg"),
+            (r#"<img src="deck.jpg" width="400" srcset="deck@2x.jpg 2x, deck.jpg 1x">"#, "https://news.example/travel/deck@2x.jpg"),
+            (r#"<img src="deck.jpg" srcset="//cdn.example/deck@2x.jpg 2x, //cdn.example/deck@3x.jpg 3x">"#, "https://cdn.example/deck@3x.jpg"),
+            (r#"<img src="deck.jpg" srcset="/s/deck-480.jpg 480w, /s/deck-1600.jpg 1600w, /s/deck-960.jpg 960w">"#, "https://news.example/s/deck-1600.jpg"),
+            (r#"<img data-src="deck.jpg" data-srcset="data:image/gif;base64,R0lGOD 4x, deck-1200.jpg 1200w">"#, "https://news.example/travel/deck-1200.jpg"),
+            (r#"<img src="avatar.jpg" width="48"><img src="icon.png" srcset="icon.png 64w, icon-2.png 128w"><img src="deck.jpg">"#, "https://news.example/travel/deck.jpg"),
+        ];
+        for (content, expected) in cases {
+            assert_eq!(lead_image("", content).as_deref(), Some(expected), "{}", content);
+        }
+    }
+
+    #[test]
+    fn no_lead_image_when_nothing_qualifies() {
+        for content in [
+            "<p>Only text.</p>",
+            r#"<img src="avatar.jpg" width="48" height="48">"#,
+            r#"<img src="data:image/gif;base64,R0lGODlhAQABAAAAACw=">"#,
+            r#"<img src="" srcset="thumb.jpg 120w, thumb-2.jpg 180w">"#,
+        ] {
+            assert_eq!(lead_image("<title>t</title>", content), None, "{}", content);
+        }
+    }
+
+    #[test]
+    fn capped_srcsets_keep_relative_and_protocol_relative_urls() {
+        let html = r#"<img id="density" src="deck.jpg" width="400" height="300" srcset="deck.jpg 1x, deck@2x.jpg 2x, //cdn.example/deck@3x.jpg 3x">
+<img id="wide" src="/s/deck-1200.jpg" width="1200" height="800" sizes="100vw" srcset="/s/deck-600.jpg 600w, ../s/deck-1200.jpg 1200w">
+<img id="none-fits" src="//cdn.example/map-1600.jpg" srcset="//cdn.example/map-800.jpg 800w, //cdn.example/map-1600.jpg 1600w">
+<img id="unknown-width" src="chart.png" srcset="chart.png 1x, chart@2x.png 2x">
+<picture><source id="source" srcset="//cdn.example/hero-480.webp 480w, hero-960.webp 960w" sizes="50vw"></picture>"#;
+        let capped = cap_image_widths(html, 600).unwrap();
+        let document = scraper::Html::parse_fragment(&capped);
+        let attr = |id: &str, name: &str| {
+            let selector = scraper::Selector::parse(&format!("#{}", id)).unwrap();
+            document.select(&selector).next().unwrap().value().attr(name).map(str::to_string)
+        };
+
+        assert_eq!(attr("density", "srcset").as_deref(), Some("deck.jpg 1x"));
+        assert_eq!(attr("density", "src").as_deref(), Some("deck.jpg"));
+        assert_eq!((attr("density", "width").as_deref(), attr("density", "height").as_deref()), (Some("400"), Some("300")));
+
+        assert_eq!(attr("wide", "srcset").as_deref(), Some("/s/deck-600.jpg 600w"));
+        assert_eq!(attr("wide", "src").as_deref(), Some("/s/deck-600.jpg"));
+        assert_eq!(attr("wide", "sizes").as_deref(), Some("600px"));
+        assert_eq!((attr("wide", "width").as_deref(), attr("wide", "height").as_deref()), (Some("600"), Some("400")));
+
+        assert_eq!(attr("none-fits", "srcset").as_deref(), Some("//cdn.example/map-800.jpg 800w"));
+        assert_eq!(attr("none-fits", "src").as_deref(), Some("//cdn.example/map-800.jpg"));
+
+        // Densities without a `width` to multiply can't be compared with the cap
+        assert_eq!(attr("unknown-width", "srcset").as_deref(), Some("chart.png 1x, chart@2x.png 2x"));
+
+        assert_eq!(attr("source", "srcset").as_deref(), Some("//cdn.example/hero-480.webp 480w"));
+        assert_eq!(attr("source", "sizes").as_deref(), Some("600px"));
+        assert!(cap_image_widths(html, 0).is_err());
+    }
+
+    /// `src` and `srcset` of the images and `<source>`s of `html`, outside `<noscript>`
+    fn image_sources(html: &str) -> Vec<(Option<String>, Option<String>)> {
+        let selector = scraper::Selector::parse("img, source").unwrap();
+        scraper::Html::parse_document(html)
+            .select(&selector)
+            .map(|el| (el.value().attr("src").map(str::to_string), el.value().attr("srcset").map(str::to_string)))
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn lazy_loaded_images_survive_extraction() {
+        // As served, every image is a placeholder until a script swaps the real URLs in
+        let placeholders = image_sources(LAZY_IMAGES);
+        assert_eq!(placeholders.len(), 4);
+        assert!(placeholders.iter().all(|(src, _)| src.as_ref().is_none_or(|src| src.starts_with("data:") || src.ends_with("placeholder.gif"))));
+
+        let site = article_site().await;
+        let article = logic_fetch_article(format!("{}/2026/06/coast-path", site), ArticleOptions::default(), &ProxyState::default()).await.unwrap();
+        let uploads = format!("{}/wp-content/uploads/2026/06", site);
+        assert_eq!(
+            image_sources(&article.content),
+            [
+                (Some(format!("{}/cliffs-1024x683.jpg", uploads)), None),
+                (Some(format!("{}/chapel.jpg", uploads)), Some(format!("{0}/chapel.jpg 900w, {0}/chapel-450x300.jpg 450w", uploads))),
+                (None, Some(format!("{}/seals.webp 1200w", uploads))),
+                (Some(format!("{}/seals.jpg", uploads)), None),
+            ]
+        );
+        assert!(!article.content.contains("data:image/gif") && !article.content.contains("placeholder.gif"));
+        assert_eq!(article.metadata.lead_image_url, Some(format!("{}/cliffs-1024x683.jpg", uploads)));
+    }
+
+    #[tokio::test]
+    async fn script_rendered_pages_fall_back_on_their_amp_version() {
+        let site = article_site().await;
+        let state = ProxyState::default();
+        for strictness in [ExtractionStrictness::Default, ExtractionStrictness::Strict] {
+            let options = ArticleOptions { strictness, ..ArticleOptions::default() };
+            let article = logic_fetch_article(format!("{}/news/harbour-pilots-strike", site), options, &state).await.unwrap();
+            assert!(!article.fallback, "{:?}", strictness);
+            assert!(article.content.contains("stopped work at midnight") && article.content.contains("Talks are due to resume on Thursday"), "{}", article.content);
+            assert!(!article.content.contains("enable JavaScript") && !article.content.contains("amp-img"), "{}", article.content);
+            assert!(article.content.contains(&format!(r#"<img src="{}/media/pilot-boat.jpg""#, site)), "{}", article.content);
+            assert!(article.provenance.unwrap().processors.iter().any(|step| step == "amp_fallback"));
+        }
+
+        // The iframe fallback is left for pages whose AMP version fails too
+        let options = ArticleOptions { strictness: ExtractionStrictness::Strict, ..ArticleOptions::default() };
+        let article = logic_fetch_article(format!("{}/news/ferry-timetable", site), options, &state).await.unwrap();
+        assert!(article.fallback);
+
+        let article = logic_fetch_article("chaos://amp-shell/".to_string(), ArticleOptions::default(), &state).await.unwrap();
+        assert!(article.content.contains("Read through the AMP version") && article.content.contains(r#"<img src="chaos://amp-article/photo.jpg""#), "{}", article.content);
+    }
+
+    #[test]
+    fn words_are_counted_in_english_french_and_japanese() {
+        let cases = [
+            ("The ferry's back: six years on, it's running again \u{2014} twice a week.", 12),
+            ("Well-known  routes\n\tand 3.5 knots&nbsp;headwind", 6),
+            ("\u{ab}\u{a0}L'\u{e9}t\u{e9}, c'est aujourd'hui\u{a0}!\u{a0}\u{bb} Qu'en pense-t-on ?", 5),
+            ("\u{ab} L\u{2019}\u{e9}t\u{e9} \u{bb} : l\u{2019}article d\u{2019}aujourd\u{2019}hui !", 3),
+            ("京都の古い喫茶店を訪ねて", 6),
+            ("東京タワーは333メートル。", 6),
+            ("店主は「変わらないことが一番難しい」と笑う。", 10),
+            ("\u{ab} \u{2014} ! \u{bb} 。", 0),
+            ("", 0),
+        ];
+        for (text, expected) in cases {
+            assert_eq!(count_words(text), expected, "{}", text);
+        }
+    }
+
+    #[test]
+    fn reading_time_rounds_to_whole_minutes() {
+        for (words, words_per_minute, expected) in [(0, 230, 0), (1, 230, 1), (344, 230, 1), (345, 230, 2), (1610, 230, 7), (1610, 100, 16), (50, 0, 0)] {
+            assert_eq!(reading_time_minutes(words, words_per_minute), expected, "{} words at {}", words, words_per_minute);
+        }
+    }
+
+    #[tokio::test]
+    async fn articles_report_their_word_count_and_reading_time() {
+        let site = charset_site().await;
+        let english = article_site().await;
+        let state = ProxyState::default();
+        // The Japanese page has only a few whitespace-separated runs: its words come from characters
+        let pages = [(format!("{}/blog/dry-stone-wall", english), 250..350), (format!("{}/latin1", site), 150..200), (format!("{}/shift-jis", site), 150..250)];
+        for (url, expected_words) in pages {
+            let options = ArticleOptions { words_per_minute: Some(40), ..ArticleOptions::default() };
+            let article = logic_fetch_article(url.clone(), options, &state).await.unwrap();
+            assert!(expected_words.contains(&article.word_count), "{}: {} words", url, article.word_count);
+            assert_eq!(article.word_count, count_words(&article.plain_text), "{}", url);
+            assert_eq!(article.reading_time_minutes, reading_time_minutes(article.word_count, 40), "{}", url);
+            assert!(article.reading_time_minutes >= 4, "{}", url);
+        }
+
+        let options = ArticleOptions { words_per_minute: Some(0), ..ArticleOptions::default() };
+        assert!(logic_fetch_article(format!("{}/latin1", site), options, &state).await.is_err());
+    }
+
+    const METADATA_PAGES: [(&str, &str); 4] = [
+        ("meta-tags", include_str!("../tests/fixtures/metadata/meta_tags.html")),
+        ("json-ld", include_str!("../tests/fixtures/metadata/json_ld.html")),
+        ("time-byline", include_str!("../tests/fixtures/metadata/time_byline.html")),
+        ("implausible-dates", include_str!("../tests/fixtures/metadata/implausible_dates.html")),
+    ];
+
+    /// Author and publication date (RFC 3339) expected of each of `METADATA_PAGES`
+    const METADATA_EXPECTED: [(&str, &str); 4] = [
+        // `meta[name=author]` over the `article:author` profile URL; the offset is applied
+        ("Ingrid Solberg", "2026-03-14T08:30:00Z"),
+        // JSON-LD over the meta tags and `<time>`
+        ("Kwame Mensah", "2025-11-02T23:05:00Z"),
+        // Byline without its "Par", and a `<time>` in French whose byte 10 is inside "é"
+        ("Élodie Marchand", "2024-02-05T00:00:00Z"),
+        // 1970, 2099 and year 1 are skipped; the zoneless `<time>` is taken as UTC
+        ("Sam O'Neill", "2024-07-09T14:00:00Z"),
+    ];
+
+    #[test]
+    fn published_date_and_author_come_from_each_metadata_style() {
+        let url = Url::parse("https://news.example/2026/story").unwrap();
+        for ((name, html), (author, published)) in METADATA_PAGES.into_iter().zip(METADATA_EXPECTED) {
+            let share = extract_share_metadata(html, &url);
+            assert_eq!(share.author.as_deref(), Some(author), "{}", name);
+            assert_eq!(share.published.map(|date| date.utc).as_deref(), Some(published), "{}", name);
+        }
+    }
+
+    #[tokio::test]
+    async fn extracted_articles_carry_the_published_date_and_author() {
+        let app = METADATA_PAGES.into_iter().fold(Router::new(), |app, (name, html)| app.route(&format!("/{}", name), get(move || async move { Html(html) })));
+        let site = format!("http://{}", serve(app).await);
+        for ((name, _), (author, published)) in METADATA_PAGES.into_iter().zip(METADATA_EXPECTED) {
+            let article = logic_fetch_article(format!("{}/{}", site, name), ArticleOptions::default(), &ProxyState::default()).await.unwrap();
+            assert_eq!(article.metadata.byline.as_deref(), Some(author), "{}", name);
+            assert_eq!(article.metadata.published.map(|date| date.utc).as_deref(), Some(published), "{}", name);
+        }
+    }
+
+    #[tokio::test]
+    async fn script_rendered_pages_fall_back_on_their_json_ld_article_body() {
+        let site = article_site().await;
+        let state = ProxyState::default();
+        for strictness in [ExtractionStrictness::Default, ExtractionStrictness::Strict] {
+            let options = ArticleOptions { strictness, ..ArticleOptions::default() };
+            let article = logic_fetch_article(format!("{}/news/market-hall", site), options, &state).await.unwrap();
+            assert!(!article.fallback, "{:?}", strictness);
+            let paragraphs: Vec<&str> = article.content.split("</p>").filter(|p| p.contains("<p>")).map(|p| p.split("<p>").nth(1).unwrap()).collect();
+            assert_eq!(paragraphs.len(), 4, "{}", article.content);
+            assert!(paragraphs[0].starts_with("The city council voted on Tuesday night"));
+            assert!(paragraphs[1].contains("&quot;It&#39;s the only way to keep the market going,&quot;"));
+            assert!(paragraphs[2].contains("the roof &amp; lantern") && paragraphs[2].contains("the cellars &lt; 1 m below"));
+            assert!(paragraphs[3].ends_with("more than two thousand signatures in a week."));
+            assert!(!article.content.contains("enable JavaScript"));
+            assert!(article.provenance.unwrap().processors.iter().any(|step| step == "json_ld_fallback"));
+            assert_eq!(article.metadata.byline.as_deref(), Some("Owen Pritchard"));
+        }
+
+        // Malformed JSON-LD is skipped rather than failing the extraction
+        let options = ArticleOptions { strictness: ExtractionStrictness::Strict, ..ArticleOptions::default() };
+        let article = logic_fetch_article(format!("{}/news/market-hall-broken", site), options, &state).await.unwrap();
+        assert!(article.fallback);
+    }
+
+    /// Soft paywall: Googlebot gets the whole article, anyone else the teaser and a metering
+    /// cookie. `/no-crawler-access` serves the teaser to everyone. Requests are logged as
+    /// (path, User-Agent, Cookie).
+    async fn soft_paywall_site() -> (String, Arc<std::sync::Mutex<Vec<(String, String, Option<String>)>>>) {
+        let requests = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let log = requests.clone();
+        let app = Router::new().route(
+            "/*path",
+            get(move |axum::extract::Path(path): axum::extract::Path<String>, headers: HeaderMap| {
+                let log = log.clone();
+                async move {
+                    let header = |name| headers.get(name).map(|value: &axum::http::HeaderValue| value.to_str().unwrap().to_string());
+                    let user_agent = header(axum::http::header::USER_AGENT).unwrap_or_default();
+                    log.lock().unwrap().push((path.clone(), user_agent.clone(), header(axum::http::header::COOKIE)));
+                    if user_agent.contains("Googlebot") && path != "no-crawler-access" {
+                        Html(PAYWALL_FULL).into_response()
+                    } else {
+                        ([(axum::http::header::SET_COOKIE, "metered=1; Path=/")], Html(PAYWALL_TEASER)).into_response()
+                    }
+                }
+            }),
+        );
+        (format!("http://{}", serve(app).await), requests)
+    }
+
+    #[tokio::test]
+    async fn flagged_hosts_retry_teasers_as_a_crawler() {
+        let (site, requests) = soft_paywall_site().await;
+        let state = ProxyState::default();
+        let url = format!("{}/boatyard", site);
+
+        // Unflagged hosts keep the teaser
+        let article = logic_fetch_article(url.clone(), ArticleOptions::default(), &state).await.unwrap();
+        assert!(article.content.contains("Subscribe to keep reading"));
+        assert!(!article.provenance.unwrap().crawler_retry);
+        assert_eq!(requests.lock().unwrap().len(), 1);
+
+        logic_set_host_crawler_retry("127.0.0.1".to_string(), true, &state).unwrap();
+        requests.lock().unwrap().clear();
+        let article = logic_fetch_article(url.clone(), ArticleOptions::default(), &state).await.unwrap();
+        assert!(article.content.contains("The trawler on the slip now should be back in the water next spring"), "{}", article.content);
+        assert!(!article.content.contains("Subscribe to keep reading"));
+        let provenance = article.provenance.unwrap();
+        assert!(provenance.crawler_retry && provenance.processors.iter().any(|step| step == "crawler_retry"));
+        assert_eq!(provenance.user_agent.as_deref(), Some("Googlebot/2.1"));
+
+        // The browser request carries the metering cookie, the crawler's goes without it
+        let requests = requests.lock().unwrap().clone();
+        assert_eq!(requests.len(), 2, "{:?}", requests);
+        assert!(!requests[0].1.contains("Googlebot") && requests[0].2.as_deref() == Some("metered=1"), "{:?}", requests[0]);
+        assert_eq!((requests[1].1.as_str(), requests[1].2.as_deref()), (CRAWLER_USER_AGENT, None));
+    }
+
+    #[tokio::test]
+    async fn crawler_retry_only_replaces_short_content_with_longer_content() {
+        let (site, requests) = soft_paywall_site().await;
+        let state = ProxyState::default();
+        logic_set_host_crawler_retry(site.clone(), true, &state).unwrap();
+
+        // Content `TEASER_EXCERPT_RATIO` times as long as the feed's excerpt isn't a teaser
+        let options = ArticleOptions { feed_excerpt: Some("<p>Three shipwrights rebuild a trawler.</p>".to_string()), ..ArticleOptions::default() };
+        let article = logic_fetch_article(format!("{}/boatyard", site), options, &state).await.unwrap();
+        assert!(!article.provenance.unwrap().crawler_retry);
+        assert_eq!(requests.lock().unwrap().len(), 1);
+
+        // The crawler gets the same teaser: the first extraction stays
+        requests.lock().unwrap().clear();
+        let article = logic_fetch_article(format!("{}/no-crawler-access", site), ArticleOptions::default(), &state).await.unwrap();
+        let provenance = article.provenance.unwrap();
+        assert!(!provenance.crawler_retry);
+        assert_ne!(provenance.user_agent.as_deref(), Some("Googlebot/2.1"));
+        assert!(article.content.contains("Subscribe to keep reading"));
+        assert_eq!(requests.lock().unwrap().len(), 2);
+
+        assert!(looks_like_teaser(599, None) && !looks_like_teaser(600, None));
+        assert!(looks_like_teaser(89, Some("Thirty characters of excerpt..")) && !looks_like_teaser(90, Some("Thirty characters of <b>excerpt</b>..")));
+        assert!(looks_like_teaser(599, Some("<img src=x.jpg>")));
+    }
+}
@@ -0,0 +1,475 @@
+use crate::connectivity;
+use crate::dates;
+use crate::proxy::unproxied_url;
+use crate::shared::{
+    absolutize_url, escape_html, extract_share_metadata, logic_extract_article, logic_fetch_raw_html, origin_of, read_body_limited,
+    ArticleOptions, ProxyState, ShareMeta, FALLBACK_SIGNAL, LAZY_IMAGE_ATTRIBUTES,
+};
+use base64::Engine;
+use futures_util::future::join_all;
+use lol_html::html_content::ContentType;
+use lol_html::{element, rewrite_str, RewriteStrSettings};
+use reqwest::header;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::io::Cursor;
+use tokio::time::Duration;
+use url::Url;
+
+/// Largest image downloaded for embedding, in bytes
+const MAX_IMAGE_BYTES: usize = 8 * 1024 * 1024;
+
+/// Images are not downscaled below this width to meet the size budget; past it they are
+/// dropped instead, largest first
+const MIN_IMAGE_WIDTH: u32 = 320;
+
+/// Images larger than this (either dimension) are embedded as they are, not decoded
+const MAX_DECODE_DIMENSION: u32 = 8192;
+
+/// Allocation cap for a single image decode
+const MAX_DECODE_ALLOC: u64 = 256 * 1024 * 1024;
+
+/// Nothing is loaded from the network, even if an URL slipped through
+const CONTENT_SECURITY_POLICY: &str = "default-src 'none'; img-src data:; style-src 'unsafe-inline'";
+
+/// Reader typography, inlined so the file looks like the reader view in any browser
+const READER_CSS: &str = r#"
+:root { color-scheme: light dark; --text: #1f2937; --muted: #6b7280; --rule: #e5e7eb; --background: #ffffff; --accent: #2563eb; }
+@media (prefers-color-scheme: dark) { :root { --text: #e5e7eb; --muted: #9ca3af; --rule: #374151; --background: #111827; --accent: #60a5fa; } }
+html { background: var(--background); color: var(--text); }
+body { margin: 0 auto; max-width: 42rem; padding: 2rem 1.25rem 3rem; font: 1.125rem/1.7 Georgia, "Times New Roman", serif; }
+header h1 { font: 700 2rem/1.25 system-ui, -apple-system, "Segoe UI", sans-serif; margin: 0 0 0.5rem; }
+.byline, footer { font: 0.875rem/1.5 system-ui, -apple-system, "Segoe UI", sans-serif; color: var(--muted); }
+h2, h3, h4 { font-family: system-ui, -apple-system, "Segoe UI", sans-serif; line-height: 1.3; margin: 2rem 0 0.75rem; }
+a { color: var(--accent); }
+img, video { max-width: 100%; height: auto; }
+figure { margin: 1.5rem 0; }
+figcaption { font-size: 0.875rem; color: var(--muted); }
+blockquote { margin: 1.5rem 0; padding-left: 1rem; border-left: 3px solid var(--rule); color: var(--muted); }
+pre { overflow-x: auto; padding: 1rem; background: rgba(127, 127, 127, 0.1); border-radius: 6px; font-size: 0.875rem; }
+code { font-family: ui-monospace, "SFMono-Regular", Menlo, monospace; }
+table { border-collapse: collapse; width: 100%; }
+td, th { border: 1px solid var(--rule); padding: 0.375rem 0.5rem; }
+.missing-image { font-style: italic; color: var(--muted); }
+footer { margin-top: 3rem; padding-top: 1rem; border-top: 1px solid var(--rule); }
+"#;
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(default)]
+pub struct HtmlExportOptions {
+    /// Largest file written, in bytes. Images are downscaled, then dropped, to fit.
+    pub max_bytes: usize,
+    /// Images wider than this are downscaled, in pixels
+    pub max_image_width: u32,
+}
+
+impl Default for HtmlExportOptions {
+    fn default() -> Self {
+        Self { max_bytes: 10 * 1024 * 1024, max_image_width: 1600 }
+    }
+}
+
+/// What `export_article_html` wrote
+#[derive(Debug, Clone, Serialize)]
+pub struct HtmlExport {
+    pub path: String,
+    pub bytes: usize,
+    pub images_embedded: usize,
+    /// Embedded at a smaller size than downloaded
+    pub images_downscaled: usize,
+    /// Left out (download failed, or too large for `max_bytes`), replaced by their alt text
+    pub images_dropped: usize,
+}
+
+/// A standalone document and what happened to its images
+pub struct StandaloneArticle {
+    pub html: String,
+    pub images_embedded: usize,
+    pub images_downscaled: usize,
+    pub images_dropped: usize,
+}
+
+struct FetchedImage {
+    content_type: String,
+    bytes: Vec<u8>,
+}
+
+/// An image ready to embed
+struct EmbeddedImage {
+    data_url: String,
+    downscaled: bool,
+}
+
+fn http_client(state: &ProxyState) -> Result<reqwest::Client, String> {
+    reqwest::Client::builder()
+        .cookie_store(true)
+        .cookie_provider(state.cookie_jar.clone())
+        .redirect(reqwest::redirect::Policy::limited(10))
+        .timeout(Duration::from_secs(30))
+        .build()
+        .map_err(|e| e.to_string())
+}
+
+async fn fetch_image(client: &reqwest::Client, url: &str, article_url: &Url, state: &ProxyState) -> Option<FetchedImage> {
+    let mut request = client
+        .get(url)
+        .header(header::USER_AGENT, state.next_user_agent())
+        .header(header::ACCEPT, "image/avif,image/webp,image/*,*/*;q=0.8")
+        .header(header::REFERER, article_url.as_str());
+    if let Some(auth) = Url::parse(url).ok().and_then(|url| state.auth_credentials.get(&origin_of(&url)).map(|entry| entry.value().clone())) {
+        request = auth.apply(request);
+    }
+    let response = request.send().await.ok().filter(|response| response.status().is_success())?;
+    let content_type = response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .map(|ct| ct.split(';').next().unwrap_or("").trim().to_ascii_lowercase())
+        .filter(|ct| ct.starts_with("image/"))?;
+    let bytes = read_body_limited(response, MAX_IMAGE_BYTES).await.ok().filter(|bytes| !bytes.is_empty())?;
+    Some(FetchedImage { content_type, bytes })
+}
+
+fn data_url(content_type: &str, bytes: &[u8]) -> String {
+    format!("data:{};base64,{}", content_type, base64::engine::general_purpose::STANDARD.encode(bytes))
+}
+
+/// `image` as a data URL at most `max_width` wide. Narrower images, SVG and images that don't
+/// decode are kept as they are (animations included). CPU-bound: call it from a blocking thread.
+fn embed_image(image: &FetchedImage, max_width: u32) -> EmbeddedImage {
+    let original = || EmbeddedImage { data_url: data_url(&image.content_type, &image.bytes), downscaled: false };
+    if image.content_type == "image/svg+xml" {
+        return original();
+    }
+    let Some(mut reader) = image::ImageReader::new(Cursor::new(&image.bytes)).with_guessed_format().ok() else {
+        return original();
+    };
+    let mut limits = image::Limits::default();
+    limits.max_image_width = Some(MAX_DECODE_DIMENSION);
+    limits.max_image_height = Some(MAX_DECODE_DIMENSION);
+    limits.max_alloc = Some(MAX_DECODE_ALLOC);
+    reader.limits(limits);
+    let Ok(decoded) = reader.decode() else {
+        return original();
+    };
+    if decoded.width() <= max_width {
+        return original();
+    }
+
+    let resized = decoded.resize(max_width, decoded.height(), image::imageops::FilterType::Lanczos3);
+    let mut bytes = Vec::new();
+    let (content_type, encoded) = if resized.color().has_alpha() {
+        ("image/png", resized.write_to(&mut Cursor::new(&mut bytes), image::ImageFormat::Png))
+    } else {
+        ("image/jpeg", image::DynamicImage::from(resized.to_rgb8()).write_to(&mut Cursor::new(&mut bytes), image::ImageFormat::Jpeg))
+    };
+    match encoded {
+        Ok(()) if bytes.len() < image.bytes.len() => EmbeddedImage { data_url: data_url(content_type, &bytes), downscaled: true },
+        _ => original(),
+    }
+}
+
+/// Source of an image of extracted content, absolute and without proxying
+fn image_url(el: &lol_html::html_content::Element, base: &Url, state: &ProxyState) -> Option<String> {
+    let src = std::iter::once("src").chain(LAZY_IMAGE_ATTRIBUTES.iter().copied()).find_map(|attribute| el.get_attribute(attribute)).filter(|src| !src.starts_with("data:"))?;
+    absolutize_url(&unproxied_url(&src, state), base)
+}
+
+fn is_local(url: &str) -> bool {
+    Url::parse(url).is_ok_and(|url| matches!(url.host_str(), Some("localhost" | "127.0.0.1" | "[::1]")))
+}
+
+/// Image URLs of extracted content, in order
+fn content_images(content: &str, base: &Url, state: &ProxyState) -> Result<Vec<String>, String> {
+    let urls = std::cell::RefCell::new(Vec::new());
+    rewrite_str(
+        content,
+        RewriteStrSettings {
+            element_content_handlers: vec![element!("img", |el| {
+                if let Some(url) = image_url(el, base, state) {
+                    urls.borrow_mut().push(url);
+                }
+                Ok(())
+            })],
+            ..RewriteStrSettings::default()
+        },
+    )
+    .map_err(|e| e.to_string())?;
+    let mut urls = urls.into_inner();
+    urls.dedup();
+    Ok(urls)
+}
+
+/// Content with its images replaced by `images` (by URL), links made absolute and unproxied,
+/// and everything that would load from the network removed. Images missing from `images`
+/// become their alt text.
+fn standalone_content(content: &str, base: &Url, images: &HashMap<String, String>, state: &ProxyState) -> Result<String, String> {
+    rewrite_str(
+        content,
+        RewriteStrSettings {
+            element_content_handlers: vec![
+                element!("script, style, link, noscript, object, embed, picture > source, video > source, audio > source, track", |el| {
+                    el.remove();
+                    Ok(())
+                }),
+                // Embeds need the network: keep a link to them
+                element!("iframe, video, audio", |el| {
+                    let src = el.get_attribute("src").map(|src| unproxied_url(&src, state)).and_then(|src| absolutize_url(&src, base));
+                    match src.filter(|src| !is_local(src)) {
+                        Some(src) => el.replace(&format!(r#"<p><a href="{}">{}</a></p>"#, escape_html(&src), escape_html(&src)), ContentType::Html),
+                        None => el.remove(),
+                    }
+                    Ok(())
+                }),
+                element!("img", |el| {
+                    match image_url(el, base, state).and_then(|url| images.get(&url)) {
+                        Some(data_url) => {
+                            el.set_attribute("src", data_url)?;
+                            for attribute in ["srcset", "sizes", "loading", "decoding"].iter().chain(LAZY_IMAGE_ATTRIBUTES) {
+                                el.remove_attribute(attribute);
+                            }
+                        }
+                        None => {
+                            let alt = el.get_attribute("alt").unwrap_or_default();
+                            let alt = alt.trim();
+                            if alt.is_empty() {
+                                el.remove();
+                            } else {
+                                el.replace(&format!(r#"<span class="missing-image">[{}]</span>"#, escape_html(alt)), ContentType::Html);
+                            }
+                        }
+                    }
+                    Ok(())
+                }),
+                element!("[href]", |el| {
+                    let href = el.get_attribute("href").unwrap_or_default();
+                    if href.starts_with('#') {
+                        return Ok(());
+                    }
+                    match absolutize_url(&unproxied_url(&href, state), base).filter(|href| !is_local(href)) {
+                        Some(href) => el.set_attribute("href", &href)?,
+                        None => el.remove_attribute("href"),
+                    }
+                    Ok(())
+                }),
+                element!("[style]", |el| {
+                    // Background images would be fetched; everything else of the style is kept
+                    if el.get_attribute("style").is_some_and(|style| style.contains("url(")) {
+                        el.remove_attribute("style");
+                    }
+                    Ok(())
+                }),
+            ],
+            ..RewriteStrSettings::default()
+        },
+    )
+    .map_err(|e| e.to_string())
+}
+
+fn meta_tag(attribute: &str, name: &str, value: Option<&str>) -> String {
+    value.map_or(String::new(), |value| format!("<meta {}=\"{}\" content=\"{}\">\n", attribute, name, escape_html(value)))
+}
+
+/// The whole document around `body`
+fn document(url: &str, meta: &ShareMeta, body: &str, captured_at: &str) -> String {
+    let title = meta.title.as_deref().unwrap_or(url);
+    let published = meta.published.as_ref().map(|date| date.utc.as_str());
+    let byline: Vec<String> = [meta.author.as_deref(), meta.site_name.as_deref(), published.map(|date| &date[..10])].into_iter().flatten().map(escape_html).collect();
+
+    let mut head = String::new();
+    head.push_str(&meta_tag("name", "description", meta.description.as_deref()));
+    head.push_str(&meta_tag("name", "author", meta.author.as_deref()));
+    head.push_str(&meta_tag("property", "og:title", meta.title.as_deref()));
+    head.push_str(&meta_tag("property", "og:description", meta.description.as_deref()));
+    head.push_str(&meta_tag("property", "og:site_name", meta.site_name.as_deref()));
+    head.push_str(&meta_tag("property", "og:url", Some(&meta.canonical_url)));
+    head.push_str(&meta_tag("property", "og:type", Some("article")));
+    head.push_str(&meta_tag("property", "article:published_time", published));
+
+    format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<meta name=\"viewport\" content=\"width=device-width, initial-scale=1\">\n<meta http-equiv=\"Content-Security-Policy\" content=\"{csp}\">\n<title>{title}</title>\n<link rel=\"canonical\" href=\"{canonical}\">\n{head}<style>{css}</style>\n</head>\n<body>\n<article>\n<header>\n<h1>{title}</h1>\n{byline}</header>\n{body}\n</article>\n<footer>\n<p>Source: <a href=\"{url}\">{url}</a><br>Captured on {captured_at}</p>\n</footer>\n</body>\n</html>\n",
+        csp = CONTENT_SECURITY_POLICY,
+        title = escape_html(title),
+        canonical = escape_html(&meta.canonical_url),
+        head = head,
+        css = READER_CSS,
+        byline = if byline.is_empty() { String::new() } else { format!("<p class=\"byline\">{}</p>\n", byline.join(" · ")) },
+        body = body,
+        url = escape_html(url),
+        captured_at = captured_at,
+    )
+}
+
+/// Builds a single HTML file of extracted `content` that renders without network access:
+/// images embedded as data URLs, reader styles inlined, metadata in standard meta tags and a
+/// footer with the source and capture time. Images are downscaled, then dropped largest
+/// first, to keep the file under `options.max_bytes`.
+pub async fn build_standalone_article(url: &str, content: &str, meta: &ShareMeta, options: HtmlExportOptions, state: &ProxyState) -> Result<StandaloneArticle, String> {
+    let base = Url::parse(url).map_err(|e| e.to_string())?;
+    // Offline, images are dropped rather than waited for
+    let urls = if connectivity::is_offline(state) { Vec::new() } else { content_images(content, &base, state)? };
+    let client = http_client(state)?;
+    let fetched: Vec<(String, FetchedImage)> = join_all(urls.iter().map(|image_url| fetch_image(&client, image_url, &base, state)))
+        .await
+        .into_iter()
+        .zip(urls.iter())
+        .filter_map(|(image, image_url)| Some((image_url.clone(), image?)))
+        .collect();
+    let images_dropped = urls.len() - fetched.len();
+    let captured_at = dates::format_utc(dates::now_millis());
+
+    // Halve the image width until the document fits, down to MIN_IMAGE_WIDTH
+    let mut width = options.max_image_width.max(MIN_IMAGE_WIDTH);
+    let fetched = std::sync::Arc::new(fetched);
+    loop {
+        let batch = fetched.clone();
+        let embedded: Vec<(String, EmbeddedImage)> =
+            tokio::task::spawn_blocking(move || batch.iter().map(|(url, image)| (url.clone(), embed_image(image, width))).collect()).await.map_err(|e| e.to_string())?;
+        let downscaled = embedded.iter().filter(|(_, image)| image.downscaled).count();
+        let mut images: HashMap<String, String> = embedded.into_iter().map(|(url, image)| (url, image.data_url)).collect();
+        let mut html = document(url, meta, &standalone_content(content, &base, &images, state)?, &captured_at);
+
+        if html.len() > options.max_bytes && width > MIN_IMAGE_WIDTH {
+            width = (width / 2).max(MIN_IMAGE_WIDTH);
+            continue;
+        }
+        let mut dropped = images_dropped;
+        while html.len() > options.max_bytes {
+            let Some(largest) = images.iter().max_by_key(|(_, data_url)| data_url.len()).map(|(url, _)| url.clone()) else {
+                return Err(format!("The article is {} bytes without its images, over the {} bytes allowed", html.len(), options.max_bytes));
+            };
+            images.remove(&largest);
+            dropped += 1;
+            html = document(url, meta, &standalone_content(content, &base, &images, state)?, &captured_at);
+        }
+        return Ok(StandaloneArticle { html, images_embedded: images.len(), images_downscaled: downscaled, images_dropped: dropped });
+    }
+}
+
+/// Extracts the article at `url` as a standalone HTML document (see `build_standalone_article`)
+pub async fn logic_build_article_html(url: String, options: HtmlExportOptions, state: &ProxyState) -> Result<StandaloneArticle, String> {
+    if options.max_bytes == 0 || options.max_image_width == 0 {
+        return Err("max_bytes and max_image_width must be positive".into());
+    }
+    let page_url = Url::parse(&url).map_err(|e| e.to_string())?;
+    let content = logic_extract_article(url.clone(), ArticleOptions::default(), state).await?.content.ok_or_else(|| FALLBACK_SIGNAL.to_string())?;
+    // Metadata is a nicety: without the page, the title falls back to the URL
+    let meta = match logic_fetch_raw_html(url.clone(), None, None, state).await {
+        Ok(html) => extract_share_metadata(&html, &page_url),
+        Err(_) => extract_share_metadata("", &page_url),
+    };
+    build_standalone_article(&url, &content, &meta, options, state).await
+}
+
+/// Writes the article at `url` to `path` as a single HTML file a plain browser renders offline
+pub async fn logic_export_article_html(url: String, path: String, options: HtmlExportOptions, state: &ProxyState) -> Result<HtmlExport, String> {
+    let article = logic_build_article_html(url.clone(), options, state).await?;
+    tokio::fs::write(&path, &article.html).await.map_err(|e| format!("Writing {}: {}", path, e))?;
+    println!(
+        "[single_file::export_article_html] {} -> {} ({} bytes, {} images, {} downscaled, {} dropped)",
+        url,
+        path,
+        article.html.len(),
+        article.images_embedded,
+        article.images_downscaled,
+        article.images_dropped
+    );
+    Ok(HtmlExport {
+        path,
+        bytes: article.html.len(),
+        images_embedded: article.images_embedded,
+        images_downscaled: article.images_downscaled,
+        images_dropped: article.images_dropped,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::serve;
+    use axum::routing::get;
+    use axum::Router;
+    use image::{DynamicImage, ImageFormat, Rgb, RgbImage, Rgba, RgbaImage};
+    use std::sync::Arc;
+
+    const PAGE: &str = include_str!("../tests/fixtures/single_file/page.html");
+    /// Extracted content as the reader has it: proxied URLs, lazy images, embeds and scripts
+    const CONTENT: &str = include_str!("../tests/fixtures/single_file/content.html");
+    const GOLDEN: &str = include_str!("../tests/fixtures/single_file/article.outline");
+    const ARTICLE_URL: &str = "https://news.example/travel/night-ferry-returns";
+
+    /// One line per element, indented by depth, with its attributes sorted. Text is left out
+    /// and data URLs are cut to their media type, so captures at other times or by other
+    /// encoders have the same outline.
+    fn outline(html: &str) -> String {
+        fn walk(element: scraper::ElementRef, depth: usize, out: &mut String) {
+            let mut attributes: Vec<String> = element
+                .value()
+                .attrs()
+                .map(|(name, value)| {
+                    let value = if value.starts_with("data:") { value.split([';', ',']).next().unwrap_or(value) } else { value };
+                    format!(" {}=\"{}\"", name, value)
+                })
+                .collect();
+            attributes.sort();
+            out.push_str(&format!("{}{}{}\n", "  ".repeat(depth), element.value().name(), attributes.concat()));
+            for child in element.children().filter_map(scraper::ElementRef::wrap) {
+                walk(child, depth + 1, out);
+            }
+        }
+        let mut out = String::new();
+        walk(scraper::Html::parse_document(html).root_element(), 0, &mut out);
+        out
+    }
+
+    fn encode(image: DynamicImage, format: ImageFormat) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        image.write_to(&mut Cursor::new(&mut bytes), format).unwrap();
+        bytes
+    }
+
+    /// Serves an 1800px wide JPEG (downscaled on export) and a 64px PNG (kept as is)
+    async fn image_server() -> std::net::SocketAddr {
+        let deck = encode(RgbImage::from_fn(1800, 900, |x, y| Rgb([(x ^ y) as u8, (x.wrapping_mul(y) >> 3) as u8, 128])).into(), ImageFormat::Jpeg);
+        let logo = encode(RgbaImage::from_pixel(64, 64, Rgba([20, 60, 120, 255])).into(), ImageFormat::Png);
+        let app = Router::new()
+            .route("/img/deck.jpg", get(move || async move { ([(header::CONTENT_TYPE, "image/jpeg")], deck) }))
+            .route("/img/logo.png", get(move || async move { ([(header::CONTENT_TYPE, "image/png")], logo) }));
+        serve(app).await
+    }
+
+    async fn export(options: HtmlExportOptions) -> (StandaloneArticle, String) {
+        let images = format!("http://{}", image_server().await);
+        let state = ProxyState::default();
+        state.port.set(3000).unwrap();
+        state.base_url.store(Arc::new(Url::parse(ARTICLE_URL).unwrap()));
+        let meta = extract_share_metadata(PAGE, &Url::parse(ARTICLE_URL).unwrap());
+        let content = CONTENT.replace("{images}", &images);
+        (build_standalone_article(ARTICLE_URL, &content, &meta, options, &state).await.unwrap(), images)
+    }
+
+    #[tokio::test]
+    async fn exported_article_matches_the_golden_outline() {
+        let (article, images) = export(HtmlExportOptions::default()).await;
+        assert_eq!(outline(&article.html), GOLDEN);
+        // The deck photo is downscaled, the logo kept, the proxied cabin photo can't be fetched
+        assert_eq!((article.images_embedded, article.images_downscaled, article.images_dropped), (2, 1, 1));
+        assert!(article.html.contains("<span class=\"missing-image\">[A two-berth cabin]</span>"));
+        for local in ["localhost", "127.0.0.1", images.as_str()] {
+            assert!(!article.html.contains(local), "{} left in the file", local);
+        }
+        assert!(article.html.contains("Source: <a href=\"https://news.example/travel/night-ferry-returns\">"));
+        assert!(article.html.contains("Captured on 20"));
+    }
+
+    #[tokio::test]
+    async fn images_are_dropped_to_fit_max_bytes() {
+        let (full, _) = export(HtmlExportOptions::default()).await;
+        let max_bytes = full.html.len() - 1000;
+        let (article, _) = export(HtmlExportOptions { max_bytes, ..HtmlExportOptions::default() }).await;
+        assert!(article.html.len() <= max_bytes, "{} bytes", article.html.len());
+        // Downscaling to MIN_IMAGE_WIDTH keeps the deck photo before anything is dropped
+        assert_eq!((article.images_embedded, article.images_dropped), (2, 1));
+        assert_eq!(outline(&article.html), GOLDEN);
+    }
+}
@@ -0,0 +1,499 @@
+use crate::shared::{host_in_domain, host_of_domain_key, MutationReport, ProxyState};
+use lol_html::{element, rewrite_str, RewriteStrSettings};
+use serde::Serialize;
+use url::Url;
+
+/// A translated XPath expression: elements to select, and optionally the attribute to read
+#[derive(Debug, Clone, Serialize)]
+pub struct SelectorRule {
+    pub selector: String,
+    pub attribute: Option<String>,
+}
+
+/// Extraction rules for one host
+#[derive(Debug, Clone, Serialize)]
+pub struct SiteConfig {
+    pub title: Vec<SelectorRule>,
+    pub body: Vec<SelectorRule>,
+    pub date: Vec<SelectorRule>,
+    pub author: Vec<SelectorRule>,
+    /// Elements removed before extraction (`strip`, `strip_id_or_class`, `strip_image_src`)
+    pub strip: Vec<String>,
+    pub next_page_link: Vec<SelectorRule>,
+    /// `find_string` / `replace_string` pairs applied to the raw HTML
+    pub replacements: Vec<(String, String)>,
+    /// Extra request headers (`http_header(name): value`)
+    pub http_headers: Vec<(String, String)>,
+    /// Fall back to readability when the body rules match nothing
+    pub autodetect_on_failure: bool,
+}
+
+impl Default for SiteConfig {
+    fn default() -> Self {
+        Self {
+            title: Vec::new(),
+            body: Vec::new(),
+            date: Vec::new(),
+            author: Vec::new(),
+            strip: Vec::new(),
+            next_page_link: Vec::new(),
+            replacements: Vec::new(),
+            http_headers: Vec::new(),
+            autodetect_on_failure: true,
+        }
+    }
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct SiteConfigLoadReport {
+    /// Number of hosts with rules loaded
+    pub loaded: usize,
+    /// XPath expressions that couldn't be translated, as `host: expression`
+    pub skipped: Vec<String>,
+    pub errors: Vec<String>,
+}
+
+// --- XPath Translation ---
+
+/// Splits `input` on `separator` where it appears outside quotes, brackets and parentheses
+fn split_top_level<'a>(input: &'a str, separator: &str) -> Vec<&'a str> {
+    let mut parts = Vec::new();
+    let mut depth = 0i32;
+    let mut quote: Option<char> = None;
+    let mut start = 0;
+    let mut i = 0;
+
+    while i < input.len() {
+        let c = input[i..].chars().next().unwrap();
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), _) => {}
+            (None, '\'' | '"') => quote = Some(c),
+            (None, '[' | '(') => depth += 1,
+            (None, ']' | ')') => depth -= 1,
+            (None, _) if depth == 0 && input[i..].starts_with(separator) => {
+                parts.push(&input[start..i]);
+                i += separator.len();
+                start = i;
+                continue;
+            }
+            _ => {}
+        }
+        i += c.len_utf8();
+    }
+    parts.push(&input[start..]);
+    parts
+}
+
+/// Index of the `]` closing a predicate, `s` starting right after the opening `[`
+fn closing_bracket(s: &str) -> Option<usize> {
+    let mut depth = 0;
+    let mut quote: Option<char> = None;
+    for (i, c) in s.char_indices() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), _) => {}
+            (None, '\'' | '"') => quote = Some(c),
+            (None, '[') => depth += 1,
+            (None, ']') if depth == 0 => return Some(i),
+            (None, ']') => depth -= 1,
+            _ => {}
+        }
+    }
+    None
+}
+
+fn unquote(value: &str) -> Option<&str> {
+    let value = value.trim();
+    let quote = value.chars().next()?;
+    if (quote == '\'' || quote == '"') && value.len() >= 2 && value.ends_with(quote) {
+        Some(&value[1..value.len() - 1])
+    } else {
+        None
+    }
+}
+
+fn css_string(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+fn is_name(value: &str) -> bool {
+    !value.is_empty() && value.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == ':')
+}
+
+/// Translates one predicate condition (`@class='x'`, `contains(@id, 'x')`, ...) to a CSS
+/// attribute selector
+fn translate_condition(condition: &str) -> Option<String> {
+    let condition = condition.trim();
+
+    if let Some(inner) = condition.strip_prefix("not(").and_then(|c| c.strip_suffix(')')) {
+        return translate_condition(inner).map(|css| format!(":not({})", css));
+    }
+
+    // Attribute existence: @attr
+    if let Some(name) = condition.strip_prefix('@').filter(|name| is_name(name)) {
+        return Some(format!("[{}]", name));
+    }
+
+    // Equality: @attr='value' or normalize-space(@attr)='value'
+    if let Some((left, right)) = condition.split_once('=') {
+        let left = left.trim();
+        let attr = left
+            .strip_prefix("normalize-space(")
+            .and_then(|l| l.strip_suffix(')'))
+            .unwrap_or(left)
+            .trim()
+            .strip_prefix('@')
+            .filter(|attr| is_name(attr));
+        if let (Some(attr), Some(value)) = (attr, unquote(right)) {
+            return Some(format!("[{}={}]", attr, css_string(value.trim())));
+        }
+    }
+
+    // Functions: contains(...), starts-with(...)
+    let (function, args) = condition.strip_suffix(')')?.split_once('(')?;
+    let args = split_top_level(args, ",");
+    if args.len() != 2 {
+        return None;
+    }
+    let value = unquote(args[1])?;
+    let target = args[0].trim();
+
+    // contains(concat(' ', normalize-space(@class), ' '), ' name ') is the XPath idiom for a class token
+    if function.trim() == "contains" && target.starts_with("concat(") && target.contains("@class") {
+        return Some(format!("[class~={}]", css_string(value.trim())));
+    }
+
+    let attr = target.strip_prefix('@').filter(|name| is_name(name))?;
+    match function.trim() {
+        "contains" => Some(format!("[{}*={}]", attr, css_string(value))),
+        "starts-with" => Some(format!("[{}^={}]", attr, css_string(value))),
+        _ => None,
+    }
+}
+
+/// Translates a location step (`div[@class='x']`) to a compound CSS selector
+fn translate_step(step: &str) -> Option<String> {
+    let step = step.trim();
+    let (name, predicates) = match step.find('[') {
+        Some(index) => (&step[..index], &step[index..]),
+        None => (step, ""),
+    };
+    if name != "*" && !is_name(name) {
+        return None;
+    }
+
+    let mut css = if name == "*" { String::new() } else { name.to_ascii_lowercase() };
+    let mut rest = predicates;
+    while let Some(inner) = rest.strip_prefix('[') {
+        let end = closing_bracket(inner)?;
+        for condition in split_top_level(&inner[..end], " and ") {
+            css.push_str(&translate_condition(condition)?);
+        }
+        rest = &inner[end + 1..];
+    }
+    if !rest.is_empty() {
+        return None;
+    }
+
+    Some(if css.is_empty() { "*".to_string() } else { css })
+}
+
+/// Translates one XPath path (no unions) to a CSS selector plus an optional trailing attribute
+fn translate_path(path: &str) -> Option<SelectorRule> {
+    let mut path = path.trim();
+    let mut attribute = None;
+
+    // Trailing /@attr reads an attribute, trailing /text() reads the text
+    if let Some((head, tail)) = path.rsplit_once('/') {
+        let tail = tail.trim();
+        if let Some(name) = tail.strip_prefix('@').filter(|name| is_name(name)) {
+            attribute = Some(name.to_string());
+            path = head.trim_end_matches('/');
+        } else if tail == "text()" {
+            path = head.trim_end_matches('/');
+        }
+    }
+
+    // Paths are evaluated against the document: `//x` and `/x` are the only anchors we accept
+    let mut rest = if let Some(rest) = path.strip_prefix("//") {
+        rest
+    } else {
+        path.strip_prefix('/')?
+    };
+
+    let mut css = String::new();
+    loop {
+        let step = split_top_level(rest, "/")[0];
+        let remainder = &rest[step.len()..];
+        css.push_str(&translate_step(step)?);
+
+        if let Some(after) = remainder.strip_prefix("//") {
+            css.push(' ');
+            rest = after;
+        } else if let Some(after) = remainder.strip_prefix('/') {
+            css.push_str(" > ");
+            rest = after;
+        } else {
+            break;
+        }
+    }
+
+    Some(SelectorRule { selector: css, attribute })
+}
+
+/// Translates an XPath expression to CSS. Unions become selector lists, which only works
+/// when every branch reads the same attribute.
+pub fn xpath_to_css(xpath: &str) -> Option<SelectorRule> {
+    let rules = split_top_level(xpath, "|")
+        .into_iter()
+        .map(translate_path)
+        .collect::<Option<Vec<_>>>()?;
+
+    let attribute = rules.first()?.attribute.clone();
+    if rules.iter().any(|rule| rule.attribute != attribute) {
+        return None;
+    }
+    Some(SelectorRule {
+        selector: rules.into_iter().map(|rule| rule.selector).collect::<Vec<_>>().join(", "),
+        attribute,
+    })
+}
+
+// --- Parsing ---
+
+/// Parses an ftr-site-config rule file (`directive: value` lines). XPath expressions are
+/// translated to CSS selectors; returns the config and the expressions that couldn't be.
+pub fn parse_site_config(text: &str) -> (SiteConfig, Vec<String>) {
+    let mut config = SiteConfig::default();
+    let mut skipped = Vec::new();
+    let mut pending_find: Option<String> = None;
+
+    for line in text.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let Some((directive, value)) = line.split_once(':') else { continue };
+        let directive = directive.trim();
+        let value = value.trim();
+
+        let mut translate = |target: &mut Vec<SelectorRule>| match xpath_to_css(value) {
+            Some(rule) => target.push(rule),
+            None => skipped.push(value.to_string()),
+        };
+
+        match directive {
+            "title" => translate(&mut config.title),
+            "body" => translate(&mut config.body),
+            "date" => translate(&mut config.date),
+            "author" => translate(&mut config.author),
+            "next_page_link" => translate(&mut config.next_page_link),
+            "strip" => {
+                let mut rules = Vec::new();
+                translate(&mut rules);
+                config.strip.extend(rules.into_iter().map(|rule| rule.selector));
+            }
+            "strip_id_or_class" => {
+                let value = value.trim_matches(|c| c == '\'' || c == '"');
+                config.strip.push(format!("[id*={0}], [class*={0}]", css_string(value)));
+            }
+            "strip_image_src" => {
+                let value = value.trim_matches(|c| c == '\'' || c == '"');
+                config.strip.push(format!("img[src*={}]", css_string(value)));
+            }
+            "find_string" => pending_find = Some(value.to_string()),
+            "replace_string" => {
+                if let Some(find) = pending_find.take() {
+                    config.replacements.push((find, value.to_string()));
+                }
+            }
+            "autodetect_on_failure" => config.autodetect_on_failure = value != "no",
+            _ => {
+                // replace_string(find): replace
+                if let Some(find) = directive.strip_prefix("replace_string(").and_then(|d| d.strip_suffix(')')) {
+                    config.replacements.push((find.to_string(), value.to_string()));
+                } else if let Some(name) = directive.strip_prefix("http_header(").and_then(|d| d.strip_suffix(')')) {
+                    config.http_headers.push((name.trim().to_ascii_lowercase(), value.to_string()));
+                }
+                // Everything else (tidy, prune, test_url, single_page_link, ...) is not used
+            }
+        }
+    }
+
+    (config, skipped)
+}
+
+fn load_into(host: &str, text: &str, state: &ProxyState, report: &mut SiteConfigLoadReport) {
+    let (config, skipped) = parse_site_config(text);
+    report.skipped.extend(skipped.into_iter().map(|xpath| format!("{}: {}", host, xpath)));
+    report.loaded += 1;
+    state.site_configs.insert(host.to_ascii_lowercase(), config);
+}
+
+/// Loads rules for one host (`example.com`, or `.example.com` for every subdomain)
+pub fn logic_load_site_config(host: String, rules: String, state: &ProxyState) -> SiteConfigLoadReport {
+    let mut report = SiteConfigLoadReport::default();
+    load_into(host.trim(), &rules, state, &mut report);
+    report
+}
+
+/// Loads every `<host>.txt` file of a directory (e.g. a checkout of the ftr-site-config repository)
+pub fn logic_load_site_configs(directory: String, state: &ProxyState) -> Result<SiteConfigLoadReport, String> {
+    let mut report = SiteConfigLoadReport::default();
+    let entries = std::fs::read_dir(&directory).map_err(|e| format!("Cannot read {}: {}", directory, e))?;
+
+    for entry in entries.flatten() {
+        let path = entry.path();
+        let Some(host) = path.file_name().and_then(|n| n.to_str()).and_then(|n| n.strip_suffix(".txt")) else {
+            continue;
+        };
+        match std::fs::read_to_string(&path) {
+            Ok(text) => load_into(host, &text, state, &mut report),
+            Err(e) => report.errors.push(format!("{}: {}", path.display(), e)),
+        }
+    }
+
+    println!(
+        "[site_config::load_site_configs] Loaded {} hosts from {} ({} expressions skipped)",
+        report.loaded,
+        directory,
+        report.skipped.len()
+    );
+    Ok(report)
+}
+
+// --- Application ---
+
+/// Key of the rules applying to `host`: exact host, host without `www.`, then `.parent` wildcard files
+pub fn config_key_for(host: &str, state: &ProxyState) -> Option<String> {
+    let host = host.to_ascii_lowercase();
+    let configs = &state.site_configs;
+    if configs.is_empty() {
+        return None;
+    }
+
+    let bare = host.strip_prefix("www.").unwrap_or(&host);
+    for key in [host.as_str(), bare] {
+        if configs.contains_key(key) {
+            return Some(key.to_string());
+        }
+    }
+
+    let mut suffix = host.as_str();
+    while let Some((_, parent)) = suffix.split_once('.') {
+        let key = format!(".{}", suffix);
+        if configs.contains_key(&key) {
+            return Some(key);
+        }
+        suffix = parent;
+    }
+    None
+}
+
+/// Rules for `url`, see `config_key_for`
+pub fn config_for(url: &Url, state: &ProxyState) -> Option<SiteConfig> {
+    let key = config_key_for(url.host_str()?, state)?;
+    state.site_configs.get(&key).map(|entry| entry.value().clone())
+}
+
+/// Removes the rules loaded for `domain` and its subdomains. Wildcard rules of parent
+/// domains are left alone since they apply to other hosts too.
+pub fn clear_site_configs_for_domain(domain: &str, dry_run: bool, state: &ProxyState) -> MutationReport {
+    let mut report = MutationReport::new(dry_run);
+    let host = host_of_domain_key(domain);
+
+    let matching: Vec<String> = state
+        .site_configs
+        .iter()
+        .map(|entry| entry.key().clone())
+        .filter(|key| host_in_domain(key.trim_start_matches('.'), &host))
+        .collect();
+
+    for key in matching {
+        report.record("site_configs", key.clone(), None);
+        if !dry_run {
+            state.site_configs.remove(&key);
+        }
+    }
+    report
+}
+
+/// Applies `find_string`/`replace_string` pairs and removes `strip` elements
+pub fn apply_cleanup(html: &str, config: &SiteConfig) -> String {
+    let mut html = html.to_string();
+    for (find, replace) in &config.replacements {
+        html = html.replace(find, replace);
+    }
+    if config.strip.is_empty() {
+        return html;
+    }
+
+    let handlers = config
+        .strip
+        .iter()
+        .map(|selector| {
+            element!(selector, |el| {
+                el.remove();
+                Ok(())
+            })
+        })
+        .collect();
+
+    // lol_html rejects selectors it doesn't support: fall back to the unstripped HTML
+    match rewrite_str(&html, RewriteStrSettings { element_content_handlers: handlers, ..RewriteStrSettings::default() }) {
+        Ok(stripped) => stripped,
+        Err(e) => {
+            println!("[site_config::apply_cleanup] Strip rules not applied: {}", e);
+            html
+        }
+    }
+}
+
+/// Article body according to the `body` rules: the matches of the first rule that matches
+/// anything with text, wrapped in a single `<div>`
+pub fn extract_body(html: &str, config: &SiteConfig) -> Option<String> {
+    let document = scraper::Html::parse_document(html);
+    config.body.iter().find_map(|rule| {
+        let selector = scraper::Selector::parse(&rule.selector).ok()?;
+        let parts: Vec<String> = document
+            .select(&selector)
+            .filter(|el| el.text().any(|t| !t.trim().is_empty()))
+            .map(|el| el.html())
+            .collect();
+        (!parts.is_empty()).then(|| format!("<div>{}</div>", parts.concat()))
+    })
+}
+
+/// First non-empty value for one of the rules: the attribute when the rule names one, the text otherwise
+pub fn extract_field(html: &str, rules: &[SelectorRule]) -> Option<String> {
+    let document = scraper::Html::parse_document(html);
+    rules.iter().find_map(|rule| {
+        let selector = scraper::Selector::parse(&rule.selector).ok()?;
+        document.select(&selector).find_map(|el| {
+            let value = match &rule.attribute {
+                Some(attribute) => el.value().attr(attribute).map(|v| v.to_string()),
+                None => Some(el.text().collect::<Vec<_>>().join(" ")),
+            }?;
+            let value = value.split_whitespace().collect::<Vec<_>>().join(" ");
+            (!value.is_empty()).then_some(value)
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::check_clear_for_domain;
+
+    #[test]
+    fn clears_the_site_configs_of_a_domain() {
+        check_clear_for_domain(
+            |state, host| {
+                logic_load_site_config(host.to_string(), "body: //article".into(), state);
+            },
+            clear_site_configs_for_domain,
+            |state| state.site_configs.iter().map(|entry| entry.key().clone()).collect(),
+        );
+    }
+}
@@ -0,0 +1,154 @@
+use crate::catalog;
+use crate::proxy;
+use crate::shared::ProxyState;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::atomic::Ordering;
+
+/// Event emitted after startup when some components failed. The payload is a `StartupReport`.
+pub const STARTUP_DEGRADED_EVENT: &str = "startup-degraded";
+
+/// Error prefix returned by features whose component is disabled (see `get_startup_report`)
+pub const FEATURE_UNAVAILABLE: &str = "FEATURE_UNAVAILABLE";
+
+/// Parts of the backend started with the app. A component that fails to start is disabled
+/// instead of stopping the app, until `retry_component` starts it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Component {
+    /// Local proxy server used by the iframe fallback (desktop only)
+    Proxy,
+    /// Bundled starter feed catalog
+    FeedCatalog,
+}
+
+impl Component {
+    pub fn name(self) -> &'static str {
+        match self {
+            Component::Proxy => "proxy",
+            Component::FeedCatalog => "feed_catalog",
+        }
+    }
+
+    fn suggestion(self) -> &'static str {
+        match self {
+            Component::Proxy => "Close applications holding many local ports or allow the app to listen on localhost, then retry the proxy",
+            Component::FeedCatalog => "Reinstall the app to restore the bundled catalog, or load a remote one with update_catalog",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ComponentStatus {
+    pub component: Component,
+    pub available: bool,
+    /// Why the last start failed
+    pub error: Option<String>,
+    /// What the user can do about it
+    pub suggestion: Option<String>,
+    /// Starts attempted, at startup and through `retry_component`
+    pub attempts: u32,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct StartupReport {
+    /// Some component is disabled
+    pub degraded: bool,
+    /// Components started so far, in start order
+    pub components: Vec<ComponentStatus>,
+}
+
+/// Outcome of the last start of each component
+#[derive(Default)]
+pub struct StartupStore {
+    statuses: HashMap<Component, ComponentStatus>,
+    order: Vec<Component>,
+}
+
+/// `FEATURE_UNAVAILABLE` error for a feature of `component`
+pub fn unavailable(component: Component, reason: &str) -> String {
+    format!("{}: {} is disabled ({})", FEATURE_UNAVAILABLE, component.name(), reason)
+}
+
+/// Records the outcome of starting `component`
+pub fn record(component: Component, result: &Result<(), String>, state: &ProxyState) {
+    let mut store = state.startup.lock().unwrap();
+    if !store.order.contains(&component) {
+        store.order.push(component);
+    }
+    let attempts = store.statuses.get(&component).map_or(0, |status| status.attempts) + 1;
+    let status = ComponentStatus {
+        component,
+        available: result.is_ok(),
+        error: result.as_ref().err().cloned(),
+        suggestion: result.is_err().then(|| component.suggestion().to_string()),
+        attempts,
+    };
+    store.statuses.insert(component, status);
+}
+
+/// Fails with `FEATURE_UNAVAILABLE` when the last start of `component` failed
+pub fn require(component: Component, state: &ProxyState) -> Result<(), String> {
+    let store = state.startup.lock().unwrap();
+    match store.statuses.get(&component) {
+        Some(ComponentStatus { available: false, error, .. }) => Err(unavailable(component, error.as_deref().unwrap_or("failed to start"))),
+        _ => Ok(()),
+    }
+}
+
+async fn start_proxy(state: &ProxyState) -> Result<u16, String> {
+    if state.use_relative_paths.load(Ordering::Relaxed) {
+        return Err("The proxy is served by the web server in web mode".into());
+    }
+    if let Some(port) = state.port.get() {
+        return Ok(*port);
+    }
+    let port = proxy::start_proxy_server(state.clone()).await?;
+    // If a concurrent call won the race, keep its port
+    Ok(*state.port.get_or_init(|| port))
+}
+
+async fn start(component: Component, state: &ProxyState) -> Result<(), String> {
+    match component {
+        Component::Proxy => start_proxy(state).await.map(|_| ()),
+        Component::FeedCatalog => catalog::current_catalog(state).map(|_| ()),
+    }
+}
+
+/// Port of the local proxy, started on first use. Fails with `FEATURE_UNAVAILABLE` while the
+/// proxy is disabled.
+pub async fn logic_start_proxy(state: &ProxyState) -> Result<u16, String> {
+    if let Some(port) = state.port.get() {
+        return Ok(*port);
+    }
+    require(Component::Proxy, state)?;
+    let result = start_proxy(state).await;
+    record(Component::Proxy, &result.as_ref().map(|_| ()).map_err(String::clone), state);
+    result.map_err(|e| unavailable(Component::Proxy, &e))
+}
+
+/// Starts `components`, disabling the ones that fail
+pub async fn logic_run_startup(components: &[Component], state: &ProxyState) -> StartupReport {
+    for &component in components {
+        let result = start(component, state).await;
+        if let Err(e) = &result {
+            eprintln!("[startup::run_startup] {} disabled: {}", component.name(), e);
+        }
+        record(component, &result, state);
+    }
+    logic_get_startup_report(state)
+}
+
+pub fn logic_get_startup_report(state: &ProxyState) -> StartupReport {
+    let store = state.startup.lock().unwrap();
+    let components: Vec<ComponentStatus> = store.order.iter().filter_map(|component| store.statuses.get(component).cloned()).collect();
+    StartupReport { degraded: components.iter().any(|status| !status.available), components }
+}
+
+/// Starts `component` again, e.g. after the user fixed what made it fail
+pub async fn logic_retry_component(component: Component, state: &ProxyState) -> ComponentStatus {
+    let result = start(component, state).await;
+    println!("[startup::retry_component] {}: {}", component.name(), result.as_ref().err().map_or("available", String::as_str));
+    record(component, &result, state);
+    state.startup.lock().unwrap().statuses[&component].clone()
+}
@@ -0,0 +1,221 @@
+use crate::export::{html_to_blocks, plain_text, Block, Inline};
+use crate::shared::{count_words, extract_outline, logic_extract_article, ArticleOptions, ProxyState, FALLBACK_SIGNAL};
+use serde::Serialize;
+use url::Url;
+
+/// Characters of text kept in a landmark label
+const MAX_LABEL_CHARS: usize = 80;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LandmarkKind {
+    Figure,
+    Quote,
+    List,
+    Code,
+}
+
+/// Region of a section a screen reader can jump to
+#[derive(Debug, Clone, Serialize)]
+pub struct Landmark {
+    pub kind: LandmarkKind,
+    /// Start of the figure caption (or alt text), quote or list
+    pub label: Option<String>,
+    /// Number of items, for lists
+    pub items: Option<usize>,
+}
+
+/// A heading and everything up to the next heading of the same or a higher level
+#[derive(Debug, Clone, Serialize)]
+pub struct OutlineSection {
+    pub level: u8,
+    pub text: String,
+    /// Id of the heading in `fetch_article`'s content (outlined headings only, h1–h4)
+    pub id: Option<String>,
+    /// Words of the section, subsections excluded
+    pub word_count: usize,
+    pub landmarks: Vec<Landmark>,
+    pub children: Vec<OutlineSection>,
+}
+
+/// A heading more than one level deeper than the previous one (e.g. h2 then h4)
+#[derive(Debug, Clone, Serialize)]
+pub struct HeadingSkip {
+    pub from_level: u8,
+    pub to_level: u8,
+    pub text: String,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct AltTextCoverage {
+    pub images: usize,
+    /// Images with no or empty alt text
+    pub missing_alt: usize,
+}
+
+/// Structure of an extracted article for accessibility tooling: nested sections with their
+/// landmarks, heading-level skips and image alt-text coverage
+#[derive(Debug, Clone, Serialize)]
+pub struct StructureOutline {
+    /// Words before the first heading
+    pub preamble_word_count: usize,
+    /// Landmarks before the first heading
+    pub preamble_landmarks: Vec<Landmark>,
+    pub sections: Vec<OutlineSection>,
+    /// Jumps in heading levels, a sign that extraction lost part of the hierarchy
+    pub heading_skips: Vec<HeadingSkip>,
+    pub alt_text: AltTextCoverage,
+}
+
+fn label(text: &str) -> Option<String> {
+    let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    match text.char_indices().nth(MAX_LABEL_CHARS) {
+        _ if text.is_empty() => None,
+        Some((end, _)) => Some(format!("{}…", text[..end].trim_end())),
+        None => Some(text),
+    }
+}
+
+/// Text of blocks, for quote and list labels and word counts
+fn blocks_text(blocks: &[Block]) -> String {
+    let texts: Vec<String> = blocks
+        .iter()
+        .map(|block| match block {
+            Block::Heading { content, .. } | Block::Paragraph(content) => plain_text(content),
+            Block::List { items, .. } => items.iter().map(|item| blocks_text(item)).collect::<Vec<_>>().join(" "),
+            Block::Code { code, .. } => code.clone(),
+            Block::Quote(blocks) => blocks_text(blocks),
+            Block::Image { caption, .. } => plain_text(caption),
+            Block::Rule => String::new(),
+        })
+        .collect();
+    texts.join(" ")
+}
+
+fn count_inline_images(inlines: &[Inline], coverage: &mut AltTextCoverage) {
+    for inline in inlines {
+        match inline {
+            Inline::Image { alt, .. } => {
+                coverage.images += 1;
+                coverage.missing_alt += usize::from(alt.trim().is_empty());
+            }
+            Inline::Strong(content) | Inline::Emphasis(content) | Inline::Link { content, .. } => count_inline_images(content, coverage),
+            _ => {}
+        }
+    }
+}
+
+fn count_images(blocks: &[Block], coverage: &mut AltTextCoverage) {
+    for block in blocks {
+        match block {
+            Block::Heading { content, .. } | Block::Paragraph(content) => count_inline_images(content, coverage),
+            Block::List { items, .. } => items.iter().for_each(|item| count_images(item, coverage)),
+            Block::Quote(blocks) => count_images(blocks, coverage),
+            Block::Image { alt, caption, .. } => {
+                coverage.images += 1;
+                coverage.missing_alt += usize::from(alt.trim().is_empty());
+                count_inline_images(caption, coverage);
+            }
+            Block::Code { .. } | Block::Rule => {}
+        }
+    }
+}
+
+fn landmark_of(block: &Block) -> Option<Landmark> {
+    match block {
+        Block::Image { alt, caption, .. } => {
+            let caption = plain_text(caption);
+            Some(Landmark { kind: LandmarkKind::Figure, label: label(&caption).or_else(|| label(alt)), items: None })
+        }
+        Block::Quote(blocks) => Some(Landmark { kind: LandmarkKind::Quote, label: label(&blocks_text(blocks)), items: None }),
+        Block::List { items, .. } => {
+            let first = items.first().map(|item| blocks_text(item)).unwrap_or_default();
+            Some(Landmark { kind: LandmarkKind::List, label: label(&first), items: Some(items.len()) })
+        }
+        Block::Code { language, .. } => Some(Landmark { kind: LandmarkKind::Code, label: language.clone(), items: None }),
+        _ => None,
+    }
+}
+
+/// Nests `sections` (in document order, flat) under the closest preceding lower-level heading
+fn nest_sections(sections: Vec<OutlineSection>) -> Vec<OutlineSection> {
+    let mut roots: Vec<OutlineSection> = Vec::new();
+    // Open sections from the outermost to the innermost
+    let mut stack: Vec<OutlineSection> = Vec::new();
+    for section in sections {
+        while stack.last().is_some_and(|open| open.level >= section.level) {
+            let closed = stack.pop().unwrap();
+            match stack.last_mut() {
+                Some(parent) => parent.children.push(closed),
+                None => roots.push(closed),
+            }
+        }
+        stack.push(section);
+    }
+    while let Some(closed) = stack.pop() {
+        match stack.last_mut() {
+            Some(parent) => parent.children.push(closed),
+            None => roots.push(closed),
+        }
+    }
+    roots
+}
+
+/// Structural outline of extracted article HTML, built from the export block model. Section
+/// ids match the ones `extract_outline` gives the same content.
+pub fn structure_outline(html: &str, base: &Url) -> Result<StructureOutline, String> {
+    let (_, toc) = extract_outline(html)?;
+    let mut toc = toc.into_iter().peekable();
+    let blocks = html_to_blocks(html, base);
+
+    let mut alt_text = AltTextCoverage::default();
+    count_images(&blocks, &mut alt_text);
+
+    let mut preamble_word_count = 0;
+    let mut preamble_landmarks = Vec::new();
+    let mut sections: Vec<OutlineSection> = Vec::new();
+    let mut heading_skips = Vec::new();
+
+    for block in &blocks {
+        if let Block::Heading { level, content } = block {
+            let text = plain_text(content).split_whitespace().collect::<Vec<_>>().join(" ");
+            if let Some(previous) = sections.last().map(|section| section.level) {
+                if *level > previous + 1 {
+                    heading_skips.push(HeadingSkip { from_level: previous, to_level: *level, text: text.clone() });
+                }
+            }
+            let id = toc.next_if(|entry| entry.level == *level && entry.text == text).map(|entry| entry.id);
+            sections.push(OutlineSection { level: *level, text, id, word_count: 0, landmarks: Vec::new(), children: Vec::new() });
+            continue;
+        }
+
+        let words = count_words(&blocks_text(std::slice::from_ref(block)));
+        let landmark = landmark_of(block);
+        match sections.last_mut() {
+            Some(section) => {
+                section.word_count += words;
+                section.landmarks.extend(landmark);
+            }
+            None => {
+                preamble_word_count += words;
+                preamble_landmarks.extend(landmark);
+            }
+        }
+    }
+
+    Ok(StructureOutline {
+        preamble_word_count,
+        preamble_landmarks,
+        sections: nest_sections(sections),
+        heading_skips,
+        alt_text,
+    })
+}
+
+/// Extracts the article at `url` and returns its structural outline. Fails with
+/// `FALLBACK_SIGNAL` when the page can't be extracted.
+pub async fn logic_fetch_article_structure(url: String, options: ArticleOptions, state: &ProxyState) -> Result<StructureOutline, String> {
+    let base = Url::parse(&url).map_err(|e| e.to_string())?;
+    let content = logic_extract_article(url, options, state).await?.content.ok_or_else(|| FALLBACK_SIGNAL.to_string())?;
+    structure_outline(&content, &base)
+}
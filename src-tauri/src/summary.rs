@@ -0,0 +1,150 @@
+use crate::export::html_to_blocks;
+use crate::reading_level::prose_blocks;
+use crate::shared::{logic_extract_article, ArticleOptions, ProxyState, FALLBACK_SIGNAL};
+use lol_html::{element, rewrite_str, RewriteStrSettings};
+use std::collections::HashMap;
+use url::Url;
+
+/// Words a sentence needs to be picked; shorter ones are usually datelines or asides
+const MIN_SENTENCE_WORDS: usize = 6;
+
+/// Words shorter than this carry no topic
+const MIN_WORD_CHARS: usize = 3;
+
+/// Frequent English and French words that say nothing about the topic
+const STOP_WORDS: &[&str] = &[
+    "the", "and", "for", "are", "but", "not", "you", "all", "any", "can", "had", "her", "was", "one", "our", "out", "has", "his",
+    "how", "its", "who", "did", "yes", "she", "him", "they", "them", "their", "there", "then", "than", "this", "that", "these",
+    "those", "with", "from", "have", "been", "were", "will", "would", "could", "should", "about", "into", "over", "after",
+    "before", "also", "more", "most", "some", "such", "what", "when", "where", "which", "while", "your", "just", "said", "says",
+    "les", "des", "une", "est", "dans", "pour", "par", "sur", "pas", "plus", "qui", "que", "mais", "ont", "sont", "avec", "son",
+    "ses", "aux", "cette", "ces", "comme", "elle", "ils", "elles", "nous", "vous", "leur", "leurs", "été", "être", "avoir", "fait",
+    "tout", "tous", "aussi", "entre", "sans", "sous", "selon", "dont", "très", "même",
+];
+
+/// Words ending with a period that don't end a sentence
+const ABBREVIATIONS: &[&str] = &["mr", "mrs", "ms", "dr", "prof", "st", "vs", "no", "fig", "e.g", "i.e", "cf", "mme", "mlle", "jr", "sr", "inc", "ltd", "co"];
+
+fn is_terminator(c: char) -> bool {
+    matches!(c, '.' | '!' | '?' | '…')
+}
+
+/// Whether the period ending `before` belongs to an abbreviation or an initial
+fn is_abbreviation(before: &str) -> bool {
+    let word = before.rsplit(char::is_whitespace).next().unwrap_or("").trim_start_matches(['(', '"', '«', '“']).to_lowercase();
+    word.chars().count() == 1 || ABBREVIATIONS.contains(&word.as_str())
+}
+
+/// Sentences of a paragraph: cut after terminal punctuation (and closing quotes) followed by
+/// a space and an uppercase letter, digit or opening quote
+fn split_sentences(text: &str) -> Vec<String> {
+    let chars: Vec<(usize, char)> = text.char_indices().collect();
+    let mut sentences = Vec::new();
+    let mut start = 0;
+    let mut i = 0;
+    while i < chars.len() {
+        let (offset, c) = chars[i];
+        if !is_terminator(c) {
+            i += 1;
+            continue;
+        }
+        let mut end = i + 1;
+        while end < chars.len() && (is_terminator(chars[end].1) || matches!(chars[end].1, '"' | '\'' | ')' | '»' | '”' | '’')) {
+            end += 1;
+        }
+        let mut next = end;
+        while next < chars.len() && chars[next].1.is_whitespace() {
+            next += 1;
+        }
+        let starts_sentence = next < chars.len()
+            && next > end
+            && (chars[next].1.is_uppercase() || chars[next].1.is_ascii_digit() || matches!(chars[next].1, '"' | '«' | '“' | '('));
+        let abbreviation = c == '.' && end == i + 1 && is_abbreviation(&text[start..offset]);
+        if starts_sentence && !abbreviation {
+            let cut = chars.get(end).map_or(text.len(), |(offset, _)| *offset);
+            sentences.push(text[start..cut].trim().to_string());
+            start = chars[next].0;
+        }
+        i = next.max(i + 1);
+    }
+    let rest = text[start..].trim();
+    if !rest.is_empty() {
+        sentences.push(rest.to_string());
+    }
+    sentences
+}
+
+fn words(sentence: &str) -> impl Iterator<Item = String> + '_ {
+    sentence.split(|c: char| !c.is_alphanumeric()).filter(|word| !word.is_empty()).map(str::to_lowercase)
+}
+
+fn is_content_word(word: &str) -> bool {
+    word.chars().count() >= MIN_WORD_CHARS && !word.chars().all(|c| c.is_ascii_digit()) && !STOP_WORDS.contains(&word)
+}
+
+/// Extractive summary of prose paragraphs: the `max_sentences` sentences whose content words
+/// are the most frequent in the text, in their original order. Text with no more sentences
+/// than that is returned as is.
+pub fn summarize_paragraphs(paragraphs: &[String], max_sentences: usize) -> String {
+    let sentences: Vec<String> = paragraphs.iter().flat_map(|paragraph| split_sentences(paragraph)).collect();
+    if sentences.len() <= max_sentences {
+        return paragraphs.iter().map(|paragraph| paragraph.trim()).filter(|paragraph| !paragraph.is_empty()).collect::<Vec<_>>().join("\n\n");
+    }
+
+    let mut frequencies: HashMap<String, usize> = HashMap::new();
+    for word in sentences.iter().flat_map(|sentence| words(sentence)).filter(|word| is_content_word(word)) {
+        *frequencies.entry(word).or_default() += 1;
+    }
+    let max_frequency = frequencies.values().copied().max().unwrap_or(1) as f64;
+
+    let scores: Vec<f64> = sentences
+        .iter()
+        .map(|sentence| {
+            let all: Vec<String> = words(sentence).collect();
+            let content: Vec<&String> = all.iter().filter(|word| is_content_word(word)).collect();
+            if all.len() < MIN_SENTENCE_WORDS || content.is_empty() {
+                return 0.0;
+            }
+            content.iter().map(|word| frequencies[*word] as f64 / max_frequency).sum::<f64>() / content.len() as f64
+        })
+        .collect();
+
+    // Highest scores first, earlier sentences winning ties
+    let mut ranked: Vec<usize> = (0..sentences.len()).collect();
+    ranked.sort_by(|a, b| scores[*b].total_cmp(&scores[*a]).then(a.cmp(b)));
+    ranked.truncate(max_sentences);
+    ranked.sort_unstable();
+    ranked.iter().map(|index| sentences[*index].as_str()).collect::<Vec<_>>().join(" ")
+}
+
+/// Summary of extracted article HTML. Headings, code blocks, images and their captions are
+/// left out.
+pub fn summarize_html(html: &str, max_sentences: usize) -> Result<String, String> {
+    // Captions of figures without a usable image would otherwise read as paragraphs
+    let html = rewrite_str(
+        html,
+        RewriteStrSettings {
+            element_content_handlers: vec![element!("figcaption, caption", |el| {
+                el.remove();
+                Ok(())
+            })],
+            ..RewriteStrSettings::default()
+        },
+    )
+    .map_err(|e| e.to_string())?;
+    let base = Url::parse("about:blank").unwrap();
+    let mut paragraphs = Vec::new();
+    prose_blocks(&html_to_blocks(&html, &base), &mut paragraphs);
+    Ok(summarize_paragraphs(&paragraphs, max_sentences))
+}
+
+/// Extracts the article and summarizes it locally, for the TL;DR view
+pub async fn logic_summarize_article(url: String, max_sentences: usize, state: &ProxyState) -> Result<String, String> {
+    if max_sentences == 0 {
+        return Err("max_sentences must be at least 1".into());
+    }
+    let content = logic_extract_article(url.clone(), ArticleOptions::default(), state).await?.content.ok_or_else(|| FALLBACK_SIGNAL.to_string())?;
+    let summary = summarize_html(&content, max_sentences)?;
+    println!("[summary::summarize_article] {} summarized in {} chars", url, summary.len());
+    Ok(summary)
+}
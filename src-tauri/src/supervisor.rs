@@ -0,0 +1,279 @@
+use crate::shared::ProxyState;
+use dashmap::DashMap;
+use serde::Serialize;
+use std::any::Any;
+use std::future::{Future, IntoFuture};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tokio::sync::broadcast;
+use tokio::task::AbortHandle;
+
+/// Event emitted when a supervised task changes state. The payload is a `TaskStatus`.
+pub const TASK_STATUS_EVENT: &str = "task-status-changed";
+
+/// Transitions buffered for a subscriber that falls behind, older ones dropped first
+const EVENT_CAPACITY: usize = 64;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TaskState {
+    Running,
+    /// The last run panicked or ended, the next one starts after a backoff
+    Restarting,
+    /// Restarted too many times in a row: given up on until the app restarts
+    Failed,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TaskStatus {
+    pub name: String,
+    pub state: TaskState,
+    /// Restarts in a row, reset by a run lasting `RestartPolicy::stable_after`
+    pub restarts: u32,
+    /// Panic message or error that ended the last run
+    pub last_error: Option<String>,
+    /// Unix time (ms) of the task's latest heartbeat, or of its latest start
+    pub last_heartbeat: u64,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct RestartPolicy {
+    /// Restarts in a row before the task is marked failed
+    pub max_restarts: u32,
+    /// Delay before the first restart, doubled for each following one
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+    /// A run lasting this long resets the restart count
+    pub stable_after: Duration,
+}
+
+impl Default for RestartPolicy {
+    fn default() -> Self {
+        Self { max_restarts: 5, initial_backoff: Duration::from_secs(1), max_backoff: Duration::from_secs(60), stable_after: Duration::from_secs(300) }
+    }
+}
+
+impl RestartPolicy {
+    fn backoff(&self, restarts: u32) -> Duration {
+        self.initial_backoff.saturating_mul(2u32.saturating_pow(restarts)).min(self.max_backoff)
+    }
+}
+
+/// Long-lived background tasks by name, with the channel their transitions are published on.
+/// The app forwards transitions to the frontend as `TASK_STATUS_EVENT`.
+pub struct Supervisor {
+    tasks: DashMap<String, TaskStatus>,
+    /// Current run of each running task
+    runs: DashMap<String, AbortHandle>,
+    events: broadcast::Sender<TaskStatus>,
+}
+
+impl Default for Supervisor {
+    fn default() -> Self {
+        Self { tasks: DashMap::new(), runs: DashMap::new(), events: broadcast::channel(EVENT_CAPACITY).0 }
+    }
+}
+
+/// Lets a supervised task report that it's alive
+#[derive(Clone)]
+pub struct Heartbeat {
+    name: String,
+    supervisor: Arc<Supervisor>,
+}
+
+impl Heartbeat {
+    pub fn beat(&self) {
+        if let Some(mut status) = self.supervisor.tasks.get_mut(&self.name) {
+            status.last_heartbeat = now_millis();
+        }
+    }
+
+    /// Awaits `future`, beating every `interval` meanwhile
+    pub async fn while_running<F: IntoFuture>(&self, future: F, interval: Duration) -> F::Output {
+        let future = future.into_future();
+        tokio::pin!(future);
+        let mut ticks = tokio::time::interval(interval);
+        loop {
+            tokio::select! {
+                output = &mut future => return output,
+                _ = ticks.tick() => self.beat(),
+            }
+        }
+    }
+}
+
+fn now_millis() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_millis() as u64).unwrap_or(0)
+}
+
+fn panic_message(payload: Box<dyn Any + Send>) -> String {
+    payload
+        .downcast_ref::<&str>()
+        .map(|message| message.to_string())
+        .or_else(|| payload.downcast_ref::<String>().cloned())
+        .unwrap_or_else(|| "unknown panic".to_string())
+}
+
+impl Supervisor {
+    /// Receives every transition from now on
+    pub fn subscribe(&self) -> broadcast::Receiver<TaskStatus> {
+        self.events.subscribe()
+    }
+
+    /// Supervised tasks, sorted by name
+    pub fn statuses(&self) -> Vec<TaskStatus> {
+        let mut statuses: Vec<TaskStatus> = self.tasks.iter().map(|entry| entry.value().clone()).collect();
+        statuses.sort_by(|a, b| a.name.cmp(&b.name));
+        statuses
+    }
+
+    /// Aborts the current run of `name`, which is then restarted like a run that failed.
+    /// `false` if the task isn't running.
+    pub fn abort(&self, name: &str) -> bool {
+        match self.runs.get(name) {
+            Some(run) => {
+                run.abort();
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn transition(&self, name: &str, state: TaskState, restarts: u32, last_error: Option<String>) {
+        let status = TaskStatus { name: name.to_string(), state, restarts, last_error, last_heartbeat: now_millis() };
+        match &status.last_error {
+            Some(e) if state != TaskState::Running => println!("[supervisor::supervise] {} {:?} after {} restarts: {}", name, state, restarts, e),
+            _ => println!("[supervisor::supervise] {} {:?}", name, state),
+        }
+        self.tasks.insert(name.to_string(), status.clone());
+        // No subscriber is fine: the status stays queryable
+        let _ = self.events.send(status);
+    }
+}
+
+/// Runs the task `make` builds under supervision, named `name`. A run that panics, fails or
+/// ends is replaced by a new one after a backoff, until `policy.max_restarts` restarts in a
+/// row, after which the task is marked failed.
+pub fn supervise<F, Fut>(supervisor: &Arc<Supervisor>, name: &str, policy: RestartPolicy, make: F)
+where
+    F: Fn(Heartbeat) -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = Result<(), String>> + Send + 'static,
+{
+    let supervisor = supervisor.clone();
+    let name = name.to_string();
+    tokio::spawn(async move {
+        let mut restarts = 0;
+        let mut last_error = None;
+        loop {
+            supervisor.transition(&name, TaskState::Running, restarts, last_error.take());
+            let started = Instant::now();
+            let run = tokio::spawn(make(Heartbeat { name: name.clone(), supervisor: supervisor.clone() }));
+            supervisor.runs.insert(name.clone(), run.abort_handle());
+            let result = run.await;
+            supervisor.runs.remove(&name);
+            let error = match result {
+                Ok(Ok(())) => "exited".to_string(),
+                Ok(Err(e)) => e,
+                Err(e) if e.is_panic() => format!("panicked: {}", panic_message(e.into_panic())),
+                Err(e) => e.to_string(),
+            };
+            if started.elapsed() >= policy.stable_after {
+                restarts = 0;
+            }
+            if restarts >= policy.max_restarts {
+                supervisor.transition(&name, TaskState::Failed, restarts, Some(error));
+                return;
+            }
+            let backoff = policy.backoff(restarts);
+            restarts += 1;
+            supervisor.transition(&name, TaskState::Restarting, restarts, Some(error.clone()));
+            last_error = Some(error);
+            tokio::time::sleep(backoff).await;
+        }
+    });
+}
+
+pub fn logic_get_task_status(state: &ProxyState) -> Vec<TaskStatus> {
+    state.supervisor.statuses()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    const QUICK: RestartPolicy =
+        RestartPolicy { max_restarts: 2, initial_backoff: Duration::from_millis(10), max_backoff: Duration::from_millis(40), stable_after: Duration::from_secs(60) };
+
+    async fn next_transition(transitions: &mut broadcast::Receiver<TaskStatus>) -> (TaskState, u32, Option<String>) {
+        let status = tokio::time::timeout(Duration::from_secs(10), transitions.recv()).await.expect("no transition").unwrap();
+        (status.state, status.restarts, status.last_error)
+    }
+
+    #[tokio::test]
+    async fn panicked_task_is_restarted_and_reported() {
+        let supervisor = Arc::new(Supervisor::default());
+        let mut transitions = supervisor.subscribe();
+        let runs = Arc::new(AtomicU32::new(0));
+        let counter = runs.clone();
+        supervise(&supervisor, "flaky", QUICK, move |_| {
+            let run = counter.fetch_add(1, Ordering::SeqCst);
+            async move {
+                if run == 0 {
+                    panic!("first run");
+                }
+                std::future::pending::<Result<(), String>>().await
+            }
+        });
+
+        assert_eq!(next_transition(&mut transitions).await, (TaskState::Running, 0, None));
+        assert_eq!(next_transition(&mut transitions).await, (TaskState::Restarting, 1, Some("panicked: first run".into())));
+        assert_eq!(next_transition(&mut transitions).await, (TaskState::Running, 1, Some("panicked: first run".into())));
+        assert_eq!(runs.load(Ordering::SeqCst), 2);
+
+        // Killing the run restarts it too
+        assert!(supervisor.abort("flaky"));
+        let (state, restarts, error) = next_transition(&mut transitions).await;
+        assert_eq!((state, restarts), (TaskState::Restarting, 2));
+        assert!(error.unwrap().contains("cancelled"));
+        assert_eq!(next_transition(&mut transitions).await.0, TaskState::Running);
+        assert_eq!(runs.load(Ordering::SeqCst), 3);
+        assert_eq!(supervisor.statuses().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn task_failing_in_a_row_is_given_up_on() {
+        let supervisor = Arc::new(Supervisor::default());
+        let mut transitions = supervisor.subscribe();
+        supervise(&supervisor, "broken", QUICK, |_| async { Err("no config".to_string()) });
+
+        let mut states = Vec::new();
+        loop {
+            let (state, restarts, _) = next_transition(&mut transitions).await;
+            states.push((state, restarts));
+            if state == TaskState::Failed {
+                break;
+            }
+        }
+        assert_eq!(
+            states,
+            [
+                (TaskState::Running, 0),
+                (TaskState::Restarting, 1),
+                (TaskState::Running, 1),
+                (TaskState::Restarting, 2),
+                (TaskState::Running, 2),
+                (TaskState::Failed, 2),
+            ]
+        );
+        let status = &supervisor.statuses()[0];
+        assert_eq!((status.state, status.last_error.as_deref()), (TaskState::Failed, Some("no config")));
+        assert!(!supervisor.abort("broken"));
+    }
+
+    #[test]
+    fn backoff_doubles_up_to_the_maximum() {
+        let backoffs: Vec<u128> = (0..5).map(|restarts| QUICK.backoff(restarts).as_millis()).collect();
+        assert_eq!(backoffs, [10, 20, 40, 40, 40]);
+    }
+}
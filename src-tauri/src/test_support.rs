@@ -0,0 +1,94 @@
+//! Helpers shared by the unit tests
+
+use crate::shared::{MutationReport, ProxyState};
+use axum::Router;
+use std::net::SocketAddr;
+use tokio::net::TcpListener;
+
+/// Serves `app` on a free port of `ip` for the rest of the test, and returns its address
+pub async fn serve_on(ip: &str, app: Router) -> SocketAddr {
+    let listener = TcpListener::bind((ip, 0)).await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+    addr
+}
+
+/// Serves `app` on a free loopback port for the rest of the test
+pub async fn serve(app: Router) -> SocketAddr {
+    serve_on("127.0.0.1", app).await
+}
+
+/// Deterministic xorshift generator for randomized tests: a failing seed reproduces
+pub struct Rng(u64);
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        Rng(seed.max(1))
+    }
+
+    pub fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    /// A number in `0..bound`
+    pub fn below(&mut self, bound: usize) -> usize {
+        (self.next() % bound as u64) as usize
+    }
+
+    pub fn pick<'a, T>(&mut self, items: &'a [T]) -> &'a T {
+        &items[self.below(items.len())]
+    }
+}
+
+/// Serves each `(path, html)` page as `text/html` on a free loopback port
+pub async fn serve_html(pages: &[(&'static str, String)]) -> SocketAddr {
+    let mut app = Router::new();
+    for (path, html) in pages {
+        let html = html.clone();
+        app = app.route(path, axum::routing::get(move || async move { axum::response::Html(html) }));
+    }
+    serve(app).await
+}
+
+/// Hosts the `clear_*_for_domain` tests seed one entry for: the first two are on `example.com`,
+/// the others only look alike
+pub const DOMAIN_HOSTS: [&str; 4] = ["example.com", "news.example.com", "notexample.com", "example.org"];
+
+/// Checks a `clear_*_for_domain` on a state seeded for every host of `DOMAIN_HOSTS`: a dry run
+/// reports the `example.com` entries and keeps them, a real run (given an origin) removes those
+/// and nothing else. `keys` lists what the store holds.
+pub fn check_clear_for_domain(
+    seed: impl Fn(&ProxyState, &str),
+    clear: fn(&str, bool, &ProxyState) -> MutationReport,
+    keys: impl Fn(&ProxyState) -> Vec<String>,
+) {
+    let sorted_keys = |state: &ProxyState| {
+        let mut keys = keys(state);
+        keys.sort();
+        keys
+    };
+    let state = ProxyState::default();
+    let others = ProxyState::default();
+    for (i, host) in DOMAIN_HOSTS.iter().enumerate() {
+        seed(&state, host);
+        if i >= 2 {
+            seed(&others, host);
+        }
+    }
+    let before = sorted_keys(&state);
+    let kept = sorted_keys(&others);
+    assert!(before.len() > kept.len(), "nothing seeded on example.com");
+
+    let preview = clear("example.com", true, &state);
+    assert!(preview.dry_run);
+    assert_eq!(preview.count, before.len() - kept.len(), "{:?}", preview.entries);
+    assert_eq!(sorted_keys(&state), before, "dry run removed entries");
+
+    let report = clear("https://example.com", false, &state);
+    assert!(!report.dry_run);
+    assert_eq!(report.count, preview.count);
+    assert_eq!(sorted_keys(&state), kept);
+}
@@ -0,0 +1,270 @@
+use crate::caching::{with_cache_class, CacheClass};
+use crate::compression::{gunzip, gzip_transfer};
+use crate::maintenance::StoreCheck;
+use crate::shared::{MutationReport, ProxyState};
+use axum::{
+    body::Body,
+    extract::{Path, State},
+    http::{header, HeaderMap, StatusCode},
+    response::Response,
+};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::time::{Duration, Instant};
+
+/// Payloads at or above this size are handed over through a one-shot URL when the
+/// caller doesn't pick a transfer mode explicitly.
+pub const INLINE_TRANSFER_THRESHOLD: usize = 1024 * 1024;
+
+/// How long an unclaimed handle stays available before it is dropped
+const TRANSFER_TTL: Duration = Duration::from_secs(60);
+
+/// How a command result should travel back to the webview
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TransferMode {
+    /// Serialize the body into the IPC/JSON response
+    Inline,
+    /// Park the body on the local server and return a one-shot URL to fetch it
+    Handle,
+}
+
+/// A command result, either inline or as a handle to a one-shot route
+#[derive(Debug, Serialize)]
+#[serde(tag = "transfer", rename_all = "lowercase")]
+pub enum TransferPayload {
+    Inline {
+        body: String,
+    },
+    Handle {
+        /// URL of the one-shot route (relative in Web App mode)
+        url: String,
+        /// Body length in bytes
+        length: usize,
+        /// Hex-encoded SHA-256 of the body, so the frontend can verify what it fetched
+        sha256: String,
+        /// `Content-Encoding` the route serves the body with (`gzip`), which `fetch` decodes
+        /// transparently. `length` and `sha256` are those of the decoded body.
+        encoding: Option<String>,
+    },
+}
+
+/// A body waiting to be fetched once through `/transfer/:token`
+pub struct PendingTransfer {
+    body: Vec<u8>,
+    /// `body` is gzipped
+    gzipped: bool,
+    /// Length of the decoded body
+    raw_len: usize,
+    content_type: String,
+    created_at: Instant,
+}
+
+/// Wrap `body` according to the requested mode. Without an explicit mode, bodies
+/// below `INLINE_TRANSFER_THRESHOLD` stay inline.
+pub fn prepare_transfer(
+    body: String,
+    content_type: &str,
+    mode: Option<TransferMode>,
+    state: &ProxyState,
+) -> TransferPayload {
+    let mode = mode.unwrap_or(if body.len() < INLINE_TRANSFER_THRESHOLD {
+        TransferMode::Inline
+    } else {
+        TransferMode::Handle
+    });
+
+    if mode == TransferMode::Inline {
+        return TransferPayload::Inline { body };
+    }
+
+    let length = body.len();
+    let sha256 = format!("{:x}", Sha256::digest(body.as_bytes()));
+    let gzipped = gzip_transfer(body.as_bytes(), &state.compression.load());
+    let encoding = gzipped.is_some().then(|| "gzip".to_string());
+    let token = uuid::Uuid::new_v4().simple().to_string();
+
+    let pruned = prune_expired_transfers(false, state);
+    if pruned.count > 0 {
+        println!("[transfer] Pruned {} expired handles", pruned.count);
+    }
+
+    {
+        let mut transfers = state.transfers.lock().unwrap();
+        transfers.insert(
+            token.clone(),
+            PendingTransfer {
+                gzipped: gzipped.is_some(),
+                body: gzipped.unwrap_or_else(|| body.into_bytes()),
+                raw_len: length,
+                content_type: content_type.to_string(),
+                created_at: Instant::now(),
+            },
+        );
+    }
+
+    let url = format!("{}/transfer/{}", state.local_base(), token);
+    println!("[transfer] Parked {} bytes behind {}{}", length, url, if encoding.is_some() { " (gzipped)" } else { "" });
+
+    TransferPayload::Handle { url, length, sha256, encoding }
+}
+
+/// Drops handles that were never claimed within `TRANSFER_TTL`
+pub fn prune_expired_transfers(dry_run: bool, state: &ProxyState) -> MutationReport {
+    let mut report = MutationReport::new(dry_run);
+    let mut transfers = state.transfers.lock().unwrap();
+
+    let expired: Vec<String> = transfers
+        .iter()
+        .filter(|(_, pending)| pending.created_at.elapsed() >= TRANSFER_TTL)
+        .map(|(token, pending)| {
+            report.record("transfers", token.clone(), Some(pending.body.len()));
+            token.clone()
+        })
+        .collect();
+
+    if !dry_run {
+        for token in expired {
+            transfers.remove(&token);
+        }
+    }
+    report
+}
+
+/// Maintenance: drops expired handles and recomputes the size of the parked bodies
+pub fn check_transfers(dry_run: bool, state: &ProxyState) -> StoreCheck {
+    let mut check = StoreCheck::new("transfers");
+    check.checked = state.transfers.lock().unwrap().len();
+
+    let expired = prune_expired_transfers(dry_run, state);
+    if expired.count > 0 {
+        check.problem(format!("{} expired handles were never claimed", expired.count), dry_run);
+    }
+    let transfers = state.transfers.lock().unwrap();
+    check.sizes(transfers.values().map(|pending| pending.body.len()).sum(), transfers.values().map(|pending| pending.raw_len).sum());
+    check
+}
+
+// Handler for /transfer/:token — serves a parked body exactly once. Gzipped bodies are served
+// as is to clients accepting gzip, decoded for the others.
+pub async fn transfer_handler(
+    Path(token): Path<String>,
+    State(state): State<ProxyState>,
+    headers: HeaderMap,
+) -> Result<Response, StatusCode> {
+    let pending = {
+        let mut transfers = state.transfers.lock().unwrap();
+        transfers.remove(&token)
+    };
+
+    let pending = pending
+        .filter(|pending| pending.created_at.elapsed() < TRANSFER_TTL)
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    let accepts_gzip = headers
+        .get(header::ACCEPT_ENCODING)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.split(',').any(|coding| coding.split(';').next().unwrap_or("").trim().eq_ignore_ascii_case("gzip")));
+    let (body, gzipped) = match pending.gzipped {
+        true if !accepts_gzip => (gunzip(&pending.body).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?, false),
+        gzipped => (pending.body, gzipped),
+    };
+
+    let mut response = with_cache_class(Response::builder(), CacheClass::NoStore)
+        .status(StatusCode::OK)
+        .header(header::CONTENT_TYPE, pending.content_type)
+        .header(header::CONTENT_LENGTH, body.len())
+        .header(header::ACCESS_CONTROL_ALLOW_ORIGIN, "*")
+        .header(header::VARY, "Accept-Encoding");
+    if gzipped {
+        response = response.header(header::CONTENT_ENCODING, "gzip");
+    }
+    response.body(Body::from(body)).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::messages::{message_reply, ProtocolMessage, ScriptMessage};
+    use crate::test_support::serve;
+    use axum::{routing::get, Router};
+
+    /// A rendered page of `size` bytes with the quotes, markup and non-ASCII text that make JSON
+    /// escaping expensive
+    fn rendered_page(size: usize) -> String {
+        let paragraph = "<p class=\"body\">L'été dernier, «\u{a0}le \"proxy\"\u{a0}» a servi 10\u{a0}Mo — 東京 <a href=\"/a?b=1&c=2\">lien</a></p>\n";
+        let mut html = String::from("<!DOCTYPE html><html><body><article>\n");
+        while html.len() < size {
+            html.push_str(paragraph);
+        }
+        html.push_str("</article></body></html>");
+        html
+    }
+
+    fn rendered_html(html: &str) -> ProtocolMessage {
+        ProtocolMessage::Script(ScriptMessage::RenderedHtml { html: html.to_string() })
+    }
+
+    #[test]
+    fn rendered_html_keeps_its_shape_without_transfer() {
+        let state = ProxyState::default();
+        let reply = serde_json::to_value(message_reply(rendered_html("<p>hi</p>"), None, &state)).unwrap();
+        assert_eq!(reply, serde_json::json!({ "type": "RENDERED_HTML", "html": "<p>hi</p>" }));
+
+        // No local server to serve a handle from: inline
+        let reply = serde_json::to_value(message_reply(rendered_html("<p>hi</p>"), Some(TransferMode::Handle), &state)).unwrap();
+        assert_eq!(reply, serde_json::json!({ "type": "RENDERED_HTML", "html": { "transfer": "inline", "body": "<p>hi</p>" } }));
+        assert!(state.transfers.lock().unwrap().is_empty());
+    }
+
+    /// A 10 MB `RENDERED_HTML` inline versus behind a handle. Run with `--nocapture` (and
+    /// `--release`) for the timings.
+    #[tokio::test]
+    async fn ten_megabyte_rendered_html() {
+        let state = ProxyState::default();
+        let app = Router::new().route("/transfer/:token", get(transfer_handler)).with_state(state.clone());
+        let addr = serve(app).await;
+        state.port.set(addr.port()).unwrap();
+
+        let html = rendered_page(10 * 1024 * 1024);
+        let client = reqwest::Client::new();
+
+        let started = Instant::now();
+        let inline = serde_json::to_string(&message_reply(rendered_html(&html), Some(TransferMode::Inline), &state)).unwrap();
+        let parsed: serde_json::Value = serde_json::from_str(&inline).unwrap();
+        let inline_time = started.elapsed();
+        assert_eq!(parsed["html"]["body"].as_str(), Some(html.as_str()));
+
+        let started = Instant::now();
+        let handle = serde_json::to_string(&message_reply(rendered_html(&html), Some(TransferMode::Handle), &state)).unwrap();
+        let reply_time = started.elapsed();
+        let parsed: serde_json::Value = serde_json::from_str(&handle).unwrap();
+        let payload = &parsed["html"];
+        assert_eq!(payload["transfer"], "handle");
+        assert_eq!(payload["length"], html.len());
+
+        let started = Instant::now();
+        let response = client.get(payload["url"].as_str().unwrap()).send().await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let fetched = response.text().await.unwrap();
+        let fetch_time = started.elapsed();
+        assert_eq!(fetched, html);
+        assert_eq!(payload["sha256"].as_str().unwrap(), format!("{:x}", Sha256::digest(fetched.as_bytes())));
+
+        // One shot
+        let again = client.get(payload["url"].as_str().unwrap()).send().await.unwrap();
+        assert_eq!(again.status(), StatusCode::NOT_FOUND);
+
+        assert!(handle.len() < 512, "handle reply is {} bytes", handle.len());
+        assert!(inline.len() > html.len());
+        println!(
+            "[transfer::tests] {} byte page: inline reply {} bytes in {:?}; handle reply {} bytes in {:?}, body fetched in {:?}",
+            html.len(),
+            inline.len(),
+            inline_time,
+            handle.len(),
+            reply_time,
+            fetch_time
+        );
+    }
+}
@@ -0,0 +1,427 @@
+use crate::connectivity;
+use crate::dates;
+use crate::feed::FeedItem;
+use crate::shared::{
+    escape_html, host_in_domain, host_of_domain_key, logic_extract_article, unescape_html, ArticleOptions, MutationReport, ProxyState,
+    FALLBACK_SIGNAL,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use tokio::time::Duration;
+use url::Url;
+
+/// Error prefix of translations refused because the day's character budget is spent
+pub const TRANSLATION_BUDGET_EXCEEDED: &str = "TRANSLATION_BUDGET_EXCEEDED";
+
+/// Items detected before a feed's language is trusted for the following ones
+const FEED_LANGUAGE_SAMPLES: u32 = 5;
+
+/// Share of the samples that must agree for a feed's language to be trusted
+const FEED_LANGUAGE_AGREEMENT: f64 = 0.8;
+
+/// Stop words recognized before a text's language is guessed
+const MIN_DETECTED_WORDS: usize = 3;
+
+/// Characters of an article sent to the detector
+const DETECTION_SAMPLE_CHARS: usize = 4000;
+
+/// Frequent words of each language the detector knows, picked to overlap as little as possible
+const STOP_WORDS: &[(&str, &[&str])] = &[
+    ("en", &["the", "and", "of", "to", "is", "that", "with", "for", "was", "are", "this", "have", "from", "it", "by", "be", "which", "but", "they", "has"]),
+    ("fr", &["le", "les", "des", "et", "est", "dans", "une", "pour", "pas", "qui", "sur", "au", "avec", "sont", "ce", "du", "mais", "nous", "aux", "été"]),
+    ("de", &["der", "die", "und", "das", "ist", "nicht", "mit", "den", "ein", "eine", "auf", "sich", "auch", "dem", "für", "von", "wird", "sind", "wurde", "zu"]),
+    ("es", &["el", "los", "las", "y", "es", "del", "por", "con", "una", "para", "que", "se", "su", "al", "como", "más", "pero", "fue", "este", "está"]),
+    ("it", &["il", "di", "che", "è", "della", "per", "non", "con", "sono", "gli", "nel", "alla", "anche", "come", "più", "dei", "delle", "questo", "ha", "lo"]),
+    ("pt", &["o", "os", "as", "do", "da", "em", "não", "uma", "com", "para", "que", "é", "dos", "das", "mais", "foi", "mas", "como", "pelo", "seu"]),
+    ("nl", &["de", "het", "een", "en", "van", "is", "niet", "dat", "op", "te", "zijn", "met", "voor", "ook", "maar", "worden", "naar", "wordt", "deze", "bij"]),
+];
+
+/// Translation service and budget. The service speaks the LibreTranslate API
+/// (`POST {endpoint}/translate`), which self-hosted instances and most proxies implement.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TranslationConfig {
+    /// Base URL of the service; nothing is translated without one
+    pub endpoint: Option<String>,
+    pub api_key: Option<String>,
+    /// Language items are translated to: the UI language (`en`, `fr`...)
+    pub target_language: String,
+    /// Characters sent to the service per UTC day before translations are refused
+    pub daily_character_cap: usize,
+}
+
+impl Default for TranslationConfig {
+    fn default() -> Self {
+        Self { endpoint: None, api_key: None, target_language: "en".to_string(), daily_character_cap: 100_000 }
+    }
+}
+
+/// Translation preference of a feed
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct FeedTranslation {
+    /// Items in another language than the target get their title and summary translated as
+    /// soon as the feed is fetched
+    pub always_translate: bool,
+    /// Overrides `TranslationConfig::target_language` for this feed
+    #[serde(default)]
+    pub target_language: Option<String>,
+}
+
+/// Languages detected on a feed's items, until they agree enough to be trusted
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct FeedLanguage {
+    /// Most detected language so far
+    pub language: Option<String>,
+    pub samples: u32,
+    /// Samples in `language`
+    pub agreeing: u32,
+    #[serde(skip)]
+    counts: HashMap<String, u32>,
+}
+
+impl FeedLanguage {
+    /// Enough samples agree: items are no longer detected one by one
+    pub fn is_settled(&self) -> bool {
+        self.samples >= FEED_LANGUAGE_SAMPLES && self.agreeing as f64 >= self.samples as f64 * FEED_LANGUAGE_AGREEMENT
+    }
+
+    fn record(&mut self, language: &str) {
+        self.samples += 1;
+        let count = self.counts.entry(language.to_string()).or_default();
+        *count += 1;
+        if *count > self.agreeing || self.language.as_deref() == Some(language) {
+            self.agreeing = *count;
+            self.language = Some(language.to_string());
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TranslationUsage {
+    /// UTC day the counter is for (`2024-05-01`)
+    pub day: String,
+    pub characters_used: usize,
+    pub daily_character_cap: usize,
+    pub remaining: usize,
+}
+
+#[derive(Default)]
+pub struct TranslationStore {
+    config: TranslationConfig,
+    /// Keyed by feed URL
+    feeds: HashMap<String, FeedTranslation>,
+    /// Keyed by feed URL
+    languages: HashMap<String, FeedLanguage>,
+    /// Day (since the epoch, UTC) and characters sent that day
+    usage: (i64, usize),
+}
+
+/// A feed's preference with what was detected of its language
+#[derive(Debug, Clone, Serialize)]
+pub struct FeedTranslationStatus {
+    pub feed: String,
+    pub preference: FeedTranslation,
+    pub detected: Option<FeedLanguage>,
+}
+
+/// Title and summary of a feed item in the target language
+#[derive(Debug, Clone, Serialize)]
+pub struct ItemTranslation {
+    pub source_language: String,
+    pub target_language: String,
+    pub title: Option<String>,
+    pub summary: Option<String>,
+}
+
+/// An article's content, translated unless it's already in the target language
+#[derive(Debug, Clone, Serialize)]
+pub struct ArticleTranslation {
+    pub content: String,
+    pub source_language: Option<String>,
+    pub target_language: String,
+    pub translated: bool,
+    /// Characters charged to the daily budget
+    pub characters: usize,
+}
+
+/// Language of `text` among the ones in `STOP_WORDS`, when enough of its words tell
+pub fn detect_language(text: &str) -> Option<String> {
+    let mut counts: HashMap<&str, usize> = HashMap::new();
+    for word in text.split(|c: char| !c.is_alphabetic()).filter(|word| !word.is_empty()) {
+        let word = word.to_lowercase();
+        for (language, words) in STOP_WORDS {
+            if words.contains(&word.as_str()) {
+                *counts.entry(language).or_default() += 1;
+            }
+        }
+    }
+    let mut ranked: Vec<(&str, usize)> = counts.into_iter().collect();
+    ranked.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+    let (language, count) = *ranked.first()?;
+    let runner_up = ranked.get(1).map_or(0, |(_, count)| *count);
+    // A clear lead: related languages share a few stop words
+    (count >= MIN_DETECTED_WORDS && count * 2 > runner_up * 3).then(|| language.to_string())
+}
+
+/// Visible text of an HTML fragment
+fn html_text(html: &str) -> String {
+    scraper::Html::parse_fragment(html).root_element().text().collect::<Vec<_>>().join(" ")
+}
+
+fn today() -> i64 {
+    dates::now_millis().div_euclid(86_400_000)
+}
+
+fn usage_of(store: &TranslationStore) -> TranslationUsage {
+    let day = today();
+    let used = if store.usage.0 == day { store.usage.1 } else { 0 };
+    TranslationUsage {
+        day: dates::format_utc(day * 86_400_000)[..10].to_string(),
+        characters_used: used,
+        daily_character_cap: store.config.daily_character_cap,
+        remaining: store.config.daily_character_cap.saturating_sub(used),
+    }
+}
+
+/// Charges `characters` to today's budget, or fails with `TRANSLATION_BUDGET_EXCEEDED`
+fn reserve(characters: usize, state: &ProxyState) -> Result<(), String> {
+    let mut store = state.translation.lock().unwrap();
+    let usage = usage_of(&store);
+    if characters > usage.remaining {
+        return Err(format!(
+            "{}: {} characters needed, {} of {} left today",
+            TRANSLATION_BUDGET_EXCEEDED, characters, usage.remaining, usage.daily_character_cap
+        ));
+    }
+    store.usage = (today(), usage.characters_used + characters);
+    Ok(())
+}
+
+/// Gives back characters reserved for a request that failed
+fn refund(characters: usize, state: &ProxyState) {
+    let mut store = state.translation.lock().unwrap();
+    if store.usage.0 == today() {
+        store.usage.1 = store.usage.1.saturating_sub(characters);
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum TranslatedText {
+    One(String),
+    Many(Vec<String>),
+}
+
+#[derive(Deserialize)]
+struct TranslateResponse {
+    #[serde(rename = "translatedText")]
+    translated_text: TranslatedText,
+}
+
+/// Translates `texts` (HTML) from `source` to `target` in one request, charged to the budget
+async fn translate_html(texts: Vec<String>, source: &str, target: &str, state: &ProxyState) -> Result<Vec<String>, String> {
+    connectivity::ensure_online(state)?;
+    let config = state.translation.lock().unwrap().config.clone();
+    let endpoint = config.endpoint.ok_or("No translation service is configured")?;
+    let characters: usize = texts.iter().map(|text| text.chars().count()).sum();
+    reserve(characters, state)?;
+
+    let mut body = serde_json::json!({ "q": texts, "source": source, "target": target, "format": "html" });
+    if let Some(api_key) = &config.api_key {
+        body["api_key"] = serde_json::Value::String(api_key.clone());
+    }
+    let result = async {
+        let client = reqwest::Client::builder().timeout(Duration::from_secs(60)).build().map_err(|e| e.to_string())?;
+        let response = client
+            .post(format!("{}/translate", endpoint.trim_end_matches('/')))
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .body(body.to_string())
+            .send()
+            .await
+            .map_err(|e| e.to_string())?;
+        if !response.status().is_success() {
+            return Err(format!("Translation service returned {}", response.status()));
+        }
+        let text = response.text().await.map_err(|e| e.to_string())?;
+        let parsed: TranslateResponse = serde_json::from_str(&text).map_err(|e| format!("Invalid translation response: {}", e))?;
+        match parsed.translated_text {
+            TranslatedText::Many(translated) if translated.len() == texts.len() => Ok(translated),
+            TranslatedText::One(translated) if texts.len() == 1 => Ok(vec![translated]),
+            _ => Err("Translation service returned a different number of texts".to_string()),
+        }
+    }
+    .await;
+    if result.is_err() {
+        refund(characters, state);
+    }
+    result
+}
+
+/// Language of a feed item: the feed's once settled, else detected on the item (and recorded
+/// for the feed)
+fn item_language(feed_url: &str, text: &str, state: &ProxyState) -> Option<String> {
+    let mut store = state.translation.lock().unwrap();
+    let feed_language = store.languages.entry(feed_url.to_string()).or_default();
+    if feed_language.is_settled() {
+        return feed_language.language.clone();
+    }
+    let language = detect_language(text)?;
+    feed_language.record(&language);
+    Some(language)
+}
+
+/// Target language of `feed` when it's set to always translate
+fn always_translate_target(feed_url: &str, state: &ProxyState) -> Option<String> {
+    let store = state.translation.lock().unwrap();
+    let preference = store.feeds.get(feed_url).filter(|preference| preference.always_translate)?;
+    store.config.endpoint.as_ref()?;
+    Some(preference.target_language.clone().unwrap_or_else(|| store.config.target_language.clone()))
+}
+
+/// Translates the titles and summaries of `items` when `feed_url` is set to always translate.
+/// Items detected in the target language are skipped; once the budget is spent the remaining
+/// items are left as they are.
+pub async fn translate_feed_items(feed_url: &str, items: &mut [FeedItem], state: &ProxyState) {
+    let Some(target) = always_translate_target(feed_url, state) else {
+        return;
+    };
+    let mut translated = 0;
+    for item in items.iter_mut() {
+        let summary = item.summary.as_deref().or(item.content.as_deref());
+        let text = format!("{} {}", item.title.as_deref().unwrap_or(""), summary.map(html_text).unwrap_or_default());
+        let Some(source) = item_language(feed_url, &text, state) else {
+            continue;
+        };
+        if source == target {
+            continue;
+        }
+        let texts = vec![escape_html(item.title.as_deref().unwrap_or("")), item.summary.clone().unwrap_or_default()];
+        match translate_html(texts, &source, &target, state).await {
+            Ok(mut texts) => {
+                let summary = texts.pop().filter(|summary| !summary.is_empty());
+                let title = texts.pop().map(|title| unescape_html(&title)).filter(|title| !title.is_empty());
+                item.translation = Some(ItemTranslation { source_language: source, target_language: target.clone(), title, summary });
+                translated += 1;
+            }
+            Err(e) => {
+                println!("[translation::translate_feed_items] {} stopped after {} items: {}", feed_url, translated, e);
+                break;
+            }
+        }
+    }
+    if translated > 0 {
+        println!("[translation::translate_feed_items] {} items of {} translated to {}", translated, feed_url, target);
+    }
+}
+
+/// Extracts the article at `url` and translates its content, for feeds set to always
+/// translate when an item is opened. Content already in the target language is returned as
+/// is. Fails with `TRANSLATION_BUDGET_EXCEEDED` once the daily budget is spent.
+pub async fn logic_translate_article(url: String, feed: Option<String>, target_language: Option<String>, state: &ProxyState) -> Result<ArticleTranslation, String> {
+    let content = logic_extract_article(url.clone(), ArticleOptions::default(), state).await?.content.ok_or_else(|| FALLBACK_SIGNAL.to_string())?;
+    let target = target_language
+        .or_else(|| feed.as_deref().and_then(|feed| state.translation.lock().unwrap().feeds.get(feed).and_then(|preference| preference.target_language.clone())))
+        .unwrap_or_else(|| state.translation.lock().unwrap().config.target_language.clone());
+
+    let sample: String = html_text(&content).chars().take(DETECTION_SAMPLE_CHARS).collect();
+    let source = match &feed {
+        Some(feed) => item_language(feed, &sample, state),
+        None => detect_language(&sample),
+    };
+    let Some(source) = source.clone().filter(|source| *source != target) else {
+        return Ok(ArticleTranslation { content, source_language: source, target_language: target, translated: false, characters: 0 });
+    };
+
+    let characters = content.chars().count();
+    let content = translate_html(vec![content], &source, &target, state).await?.remove(0);
+    println!("[translation::translate_article] {} translated from {} to {} ({} characters)", url, source, target, characters);
+    Ok(ArticleTranslation { content, source_language: Some(source), target_language: target, translated: true, characters })
+}
+
+pub fn logic_get_translation_config(state: &ProxyState) -> TranslationConfig {
+    state.translation.lock().unwrap().config.clone()
+}
+
+pub fn logic_set_translation_config(config: TranslationConfig, state: &ProxyState) -> Result<(), String> {
+    if let Some(endpoint) = &config.endpoint {
+        Url::parse(endpoint).map_err(|e| format!("Invalid translation endpoint '{}': {}", endpoint, e))?;
+    }
+    if config.target_language.trim().is_empty() {
+        return Err("target_language is required".into());
+    }
+    println!("[translation::set_translation_config] Target {}, {} characters a day", config.target_language, config.daily_character_cap);
+    state.translation.lock().unwrap().config = config;
+    Ok(())
+}
+
+pub fn logic_set_feed_translation(feed: String, preference: FeedTranslation, state: &ProxyState) -> Result<(), String> {
+    Url::parse(&feed).map_err(|e| format!("Invalid feed URL '{}': {}", feed, e))?;
+    println!("[translation::set_feed_translation] {} always_translate={}", feed, preference.always_translate);
+    let mut store = state.translation.lock().unwrap();
+    if preference.always_translate || preference.target_language.is_some() {
+        store.feeds.insert(feed, preference);
+    } else {
+        store.feeds.remove(&feed);
+    }
+    Ok(())
+}
+
+pub fn logic_get_feed_translation(feed: String, state: &ProxyState) -> FeedTranslationStatus {
+    let store = state.translation.lock().unwrap();
+    FeedTranslationStatus { preference: store.feeds.get(&feed).cloned().unwrap_or_default(), detected: store.languages.get(&feed).cloned(), feed }
+}
+
+pub fn logic_get_translation_usage(state: &ProxyState) -> TranslationUsage {
+    usage_of(&state.translation.lock().unwrap())
+}
+
+/// Forgets the translation preferences and detected languages of feeds on `domain`
+/// (subdomains included)
+pub fn clear_translation_for_domain(domain: &str, dry_run: bool, state: &ProxyState) -> MutationReport {
+    let mut report = MutationReport::new(dry_run);
+    let host = host_of_domain_key(domain);
+    let on_domain = |feed: &str| Url::parse(feed).ok().and_then(|url| url.host_str().map(|feed_host| host_in_domain(feed_host, &host))).unwrap_or(false);
+    let mut store = state.translation.lock().unwrap();
+
+    let mut preferences: Vec<String> = store.feeds.keys().filter(|feed| on_domain(feed)).cloned().collect();
+    preferences.sort();
+    for feed in preferences {
+        report.record("feed_translations", feed.clone(), None);
+        if !dry_run {
+            store.feeds.remove(&feed);
+        }
+    }
+    let mut languages: Vec<String> = store.languages.keys().filter(|feed| on_domain(feed)).cloned().collect();
+    languages.sort();
+    for feed in languages {
+        report.record("feed_languages", feed.clone(), None);
+        if !dry_run {
+            store.languages.remove(&feed);
+        }
+    }
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::check_clear_for_domain;
+
+    #[test]
+    fn clears_the_translation_state_of_a_domain() {
+        check_clear_for_domain(
+            |state, host| {
+                let feed = format!("https://{}/feed", host);
+                let preference = FeedTranslation { always_translate: true, ..FeedTranslation::default() };
+                logic_set_feed_translation(feed.clone(), preference, state).unwrap();
+                state.translation.lock().unwrap().languages.insert(feed, FeedLanguage::default());
+            },
+            clear_translation_for_domain,
+            |state| {
+                let store = state.translation.lock().unwrap();
+                let preferences = store.feeds.keys().map(|feed| format!("preference {}", feed));
+                preferences.chain(store.languages.keys().map(|feed| format!("language {}", feed))).collect()
+            },
+        );
+    }
+}
@@ -0,0 +1,229 @@
+use crate::dates;
+use crate::shared::{host_in_domain, host_of_domain_key, MutationReport, ProxyState};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use tokio::sync::watch;
+use url::Url;
+
+/// Items remembered per feed; past this only the ones still in the feed are kept
+const MAX_KNOWN_ITEMS_PER_FEED: usize = 1000;
+
+/// What the dock badge, taskbar overlay and tray show of the unread count
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BadgeMode {
+    /// The number of unread items
+    #[default]
+    Count,
+    /// A dot when anything is unread
+    Dot,
+    Off,
+}
+
+/// Unread indicator the app applies to the window and tray
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct UnreadBadge {
+    pub mode: BadgeMode,
+    pub count: usize,
+}
+
+impl UnreadBadge {
+    /// Tray tooltip line, `None` when the badge is off
+    pub fn tooltip(&self) -> Option<String> {
+        match (self.mode, self.count) {
+            (BadgeMode::Off, _) => None,
+            (_, 0) => Some("No unread items".to_string()),
+            (_, 1) => Some("1 unread item".to_string()),
+            (_, count) => Some(format!("{} unread items", count)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct UnreadEstimate {
+    /// Synced count, minus items read since, plus new items found since
+    pub count: usize,
+    /// Last count reported by the frontend with `set_unread_count`
+    pub synced_count: usize,
+    /// Unix time (ms) of that report
+    pub synced_at: Option<i64>,
+    pub new_since_sync: usize,
+    pub read_since_sync: usize,
+    pub mode: BadgeMode,
+}
+
+/// Unread count estimated between two counts from the frontend. Feeds fetched by the backend
+/// add the items it hadn't seen; `mark_items_read` takes them off.
+pub struct UnreadStore {
+    mode: BadgeMode,
+    synced_count: usize,
+    synced_at: Option<i64>,
+    /// Items found since the sync, keyed by item id, with their feed
+    new_items: HashMap<String, String>,
+    /// Ids read since the sync that weren't new, so counted in `synced_count`
+    read: HashSet<String>,
+    /// Item ids seen per feed URL
+    known: HashMap<String, HashSet<String>>,
+    badge: watch::Sender<UnreadBadge>,
+}
+
+impl Default for UnreadStore {
+    fn default() -> Self {
+        Self {
+            mode: BadgeMode::default(),
+            synced_count: 0,
+            synced_at: None,
+            new_items: HashMap::new(),
+            read: HashSet::new(),
+            known: HashMap::new(),
+            badge: watch::channel(UnreadBadge::default()).0,
+        }
+    }
+}
+
+impl UnreadStore {
+    fn count(&self) -> usize {
+        self.synced_count.saturating_sub(self.read.len()) + self.new_items.len()
+    }
+
+    /// Publishes the badge if it changed
+    fn publish(&self) {
+        let badge = UnreadBadge { mode: self.mode, count: self.count() };
+        self.badge.send_if_modified(|current| std::mem::replace(current, badge) != badge);
+    }
+}
+
+/// Id of a feed item, as the frontend refers to it: guid, else link, else title
+pub fn item_id<'a>(guid: Option<&'a String>, link: Option<&'a String>, title: Option<&'a String>) -> Option<&'a str> {
+    guid.or(link).or(title).map(String::as_str)
+}
+
+/// Badge changes from now on, starting with the current one. The app applies them to the
+/// window and tray, whether the window is shown or not.
+pub fn subscribe_badge(state: &ProxyState) -> watch::Receiver<UnreadBadge> {
+    state.unread.lock().unwrap().badge.subscribe()
+}
+
+/// Diffs the items of a fetched feed against the ones seen before: unseen ones count as unread.
+/// The first fetch of a feed only learns its items, which the frontend's count already covers.
+pub fn record_feed_items<'a>(feed_url: &str, ids: impl IntoIterator<Item = &'a str>, state: &ProxyState) {
+    let mut store = state.unread.lock().unwrap();
+    let ids: Vec<&str> = ids.into_iter().collect();
+    let store = &mut *store;
+    let known = store.known.entry(feed_url.to_string()).or_default();
+    let first_fetch = known.is_empty();
+
+    let mut added = 0;
+    for id in &ids {
+        if known.insert(id.to_string()) && !first_fetch && !store.read.contains(*id) {
+            store.new_items.insert(id.to_string(), feed_url.to_string());
+            added += 1;
+        }
+    }
+    if known.len() > MAX_KNOWN_ITEMS_PER_FEED {
+        known.retain(|id| ids.contains(&id.as_str()));
+    }
+    if added > 0 {
+        println!("[unread::record_feed_items] {} new items in {}", added, feed_url);
+        store.publish();
+    }
+}
+
+/// Takes items the user read off the estimate. Ids that weren't new since the sync come off
+/// the synced count, once each.
+pub fn logic_mark_items_read(ids: Vec<String>, state: &ProxyState) -> UnreadEstimate {
+    let mut store = state.unread.lock().unwrap();
+    for id in ids {
+        if store.new_items.remove(&id).is_none() {
+            store.read.insert(id);
+        }
+    }
+    store.publish();
+    estimate_of(&store)
+}
+
+/// Replaces the estimate with the authoritative count of the frontend (after syncing with a
+/// remote backend, for instance)
+pub fn logic_set_unread_count(count: usize, state: &ProxyState) -> UnreadEstimate {
+    let mut store = state.unread.lock().unwrap();
+    let drift = store.count() as i64 - count as i64;
+    if drift != 0 {
+        println!("[unread::set_unread_count] Estimate was off by {}, now {}", drift, count);
+    }
+    store.synced_count = count;
+    store.synced_at = Some(dates::now_millis());
+    store.new_items.clear();
+    store.read.clear();
+    store.publish();
+    estimate_of(&store)
+}
+
+pub fn logic_set_badge_mode(mode: BadgeMode, state: &ProxyState) {
+    println!("[unread::set_badge_mode] {:?}", mode);
+    let mut store = state.unread.lock().unwrap();
+    store.mode = mode;
+    store.publish();
+}
+
+fn estimate_of(store: &UnreadStore) -> UnreadEstimate {
+    UnreadEstimate {
+        count: store.count(),
+        synced_count: store.synced_count,
+        synced_at: store.synced_at,
+        new_since_sync: store.new_items.len(),
+        read_since_sync: store.read.len(),
+        mode: store.mode,
+    }
+}
+
+pub fn logic_get_unread_estimate(state: &ProxyState) -> UnreadEstimate {
+    estimate_of(&state.unread.lock().unwrap())
+}
+
+/// Forgets the items seen on feeds of `domain` (subdomains included) and takes their new items
+/// off the estimate
+pub fn clear_unread_for_domain(domain: &str, dry_run: bool, state: &ProxyState) -> MutationReport {
+    let mut report = MutationReport::new(dry_run);
+    let host = host_of_domain_key(domain);
+    let on_domain = |feed: &str| Url::parse(feed).ok().and_then(|url| url.host_str().map(|feed_host| host_in_domain(feed_host, &host))).unwrap_or(false);
+    let mut store = state.unread.lock().unwrap();
+
+    let mut feeds: Vec<String> = store.known.keys().filter(|feed| on_domain(feed)).cloned().collect();
+    feeds.sort();
+    for feed in &feeds {
+        report.record("unread_feeds", feed.clone(), None);
+    }
+    if !dry_run && !feeds.is_empty() {
+        for feed in &feeds {
+            store.known.remove(feed);
+        }
+        store.new_items.retain(|_, feed| !feeds.contains(feed));
+        store.publish();
+    }
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::check_clear_for_domain;
+
+    #[test]
+    fn clears_the_unread_state_of_a_domain() {
+        check_clear_for_domain(
+            |state, host| {
+                let feed = format!("https://{}/feed", host);
+                let mut store = state.unread.lock().unwrap();
+                store.known.insert(feed.clone(), HashSet::from([format!("{}-1", host)]));
+                store.new_items.insert(format!("{}-1", host), feed);
+            },
+            clear_unread_for_domain,
+            |state| {
+                let store = state.unread.lock().unwrap();
+                // New items go with their feed, without an entry of their own
+                assert!(store.new_items.values().all(|feed| store.known.contains_key(feed)));
+                store.known.keys().cloned().collect()
+            },
+        );
+    }
+}
@@ -0,0 +1,303 @@
+use crate::compression::StoredText;
+use crate::maintenance::StoreCheck;
+use crate::shared::{host_in_domain, host_of_domain_key, MutationReport, ProxyState};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::time::{SystemTime, UNIX_EPOCH};
+use url::Url;
+
+/// Versions kept per article before the oldest ones are pruned
+pub const MAX_VERSIONS_PER_ARTICLE: usize = 10;
+
+/// Default storage budget for all versions: 20 MiB of (compressed) extracted HTML
+pub const DEFAULT_VERSION_BUDGET: usize = 20 * 1024 * 1024;
+
+/// An extracted article as it was at `timestamp`
+#[derive(Debug, Clone, Serialize)]
+pub struct ArticleVersion {
+    /// Milliseconds since the Unix epoch
+    pub timestamp: u64,
+    /// SHA-256 of `content`, hex-encoded
+    pub content_hash: String,
+    pub content: String,
+}
+
+/// Version listing entry, without the content
+#[derive(Debug, Clone, Serialize)]
+pub struct ArticleVersionInfo {
+    pub timestamp: u64,
+    pub content_hash: String,
+    pub size: usize,
+}
+
+/// A version as stored, its content compressed per the compression config it was written with
+struct StoredVersion {
+    timestamp: u64,
+    content_hash: String,
+    content: StoredText,
+}
+
+struct TrackedArticle {
+    /// Starred articles always keep their first and latest versions when pruning
+    starred: bool,
+    /// Oldest first
+    versions: Vec<StoredVersion>,
+}
+
+/// Dated versions of the articles the frontend asked to keep (starred or archived)
+pub struct VersionStore {
+    articles: HashMap<String, TrackedArticle>,
+    /// Maximum total size of stored versions, in bytes (compressed when they are)
+    pub budget: usize,
+}
+
+impl Default for VersionStore {
+    fn default() -> Self {
+        Self { articles: HashMap::new(), budget: DEFAULT_VERSION_BUDGET }
+    }
+}
+
+impl VersionStore {
+    fn total_size(&self) -> usize {
+        self.articles.values().flat_map(|a| &a.versions).map(|v| v.content.stored_len()).sum()
+    }
+
+    fn raw_size(&self) -> usize {
+        self.articles.values().flat_map(|a| &a.versions).map(|v| v.content.raw_len()).sum()
+    }
+
+    /// Drops the oldest prunable version, preferring articles that aren't starred.
+    /// Returns false when nothing can be pruned any more.
+    fn prune_one(&mut self) -> bool {
+        let candidate = self
+            .articles
+            .iter()
+            .filter_map(|(url, article)| {
+                let index = prunable_index(article)?;
+                Some((article.starred, article.versions[index].timestamp, url.clone(), index))
+            })
+            .min_by_key(|(starred, timestamp, _, _)| (*starred, *timestamp));
+
+        let Some((_, _, url, index)) = candidate else {
+            return false;
+        };
+        if let Some(article) = self.articles.get_mut(&url) {
+            article.versions.remove(index);
+        }
+        true
+    }
+}
+
+/// Index of the version to prune first in `article`: the oldest one, except that starred
+/// articles keep their first and latest versions
+fn prunable_index(article: &TrackedArticle) -> Option<usize> {
+    match (article.starred, article.versions.len()) {
+        (_, 0) => None,
+        (true, len) if len <= 2 => None,
+        (true, _) => Some(1),
+        (false, _) => Some(0),
+    }
+}
+
+fn now_millis() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_millis() as u64).unwrap_or(0)
+}
+
+/// Starts (or updates) version tracking for `url`. Called when an article is starred or archived.
+pub fn logic_track_article_versions(url: String, starred: bool, state: &ProxyState) {
+    let mut store = state.article_versions.lock().unwrap();
+    store
+        .articles
+        .entry(url)
+        .and_modify(|article| article.starred = starred)
+        .or_insert(TrackedArticle { starred, versions: Vec::new() });
+}
+
+/// Stops tracking `url` and drops its versions
+pub fn logic_untrack_article_versions(url: String, state: &ProxyState) {
+    state.article_versions.lock().unwrap().articles.remove(&url);
+}
+
+/// Stores `content` as a new version of `url` if the article is tracked and the content
+/// differs from the latest version, then prunes to the per-article cap and the storage budget.
+/// Versions of the article stored uncompressed are compressed meanwhile if compression is on.
+pub fn record_version(url: &str, content: &str, state: &ProxyState) {
+    let config = **state.compression.load();
+    let mut store = state.article_versions.lock().unwrap();
+    let Some(article) = store.articles.get_mut(url) else {
+        return;
+    };
+
+    let content_hash = content_hash(content);
+    if article.versions.last().is_some_and(|latest| latest.content_hash == content_hash) {
+        return;
+    }
+
+    // Keep timestamps unique so they can be used as version ids
+    let timestamp = now_millis().max(article.versions.last().map_or(0, |latest| latest.timestamp + 1));
+    let migrated = article.versions.iter_mut().map(|v| v.content.migrate(&config)).filter(|&changed| changed).count();
+    if migrated > 0 {
+        println!("[versions::record_version] Compressed {} earlier versions of {}", migrated, url);
+    }
+    article.versions.push(StoredVersion { timestamp, content_hash, content: StoredText::encode(content, &config) });
+    println!("[versions::record_version] Version {} of {} stored", article.versions.len(), url);
+
+    while article.versions.len() > MAX_VERSIONS_PER_ARTICLE {
+        match prunable_index(article) {
+            Some(index) => {
+                article.versions.remove(index);
+            }
+            None => break,
+        }
+    }
+
+    while store.total_size() > store.budget {
+        if !store.prune_one() {
+            println!("[versions::record_version] Storage budget exceeded, but only protected versions are left");
+            break;
+        }
+    }
+}
+
+/// Content of the latest version of `url`, for reading it offline
+pub fn latest_version(url: &str, state: &ProxyState) -> Option<String> {
+    let store = state.article_versions.lock().unwrap();
+    store.articles.get(url)?.versions.last()?.content.decode().ok()
+}
+
+/// Versions of `url`, oldest first
+pub fn logic_list_article_versions(url: String, state: &ProxyState) -> Vec<ArticleVersionInfo> {
+    let store = state.article_versions.lock().unwrap();
+    store
+        .articles
+        .get(&url)
+        .map(|article| {
+            article
+                .versions
+                .iter()
+                .map(|v| ArticleVersionInfo { timestamp: v.timestamp, content_hash: v.content_hash.clone(), size: v.content.raw_len() })
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+pub fn logic_get_article_version(url: String, timestamp: u64, state: &ProxyState) -> Result<ArticleVersion, String> {
+    let store = state.article_versions.lock().unwrap();
+    let version = store
+        .articles
+        .get(&url)
+        .and_then(|article| article.versions.iter().find(|v| v.timestamp == timestamp))
+        .ok_or_else(|| format!("No version of {} at {}", url, timestamp))?;
+    Ok(ArticleVersion { timestamp, content_hash: version.content_hash.clone(), content: version.content.decode()? })
+}
+
+/// Sets the storage budget for all versions, pruning right away if it's exceeded
+pub fn logic_set_version_budget(bytes: usize, state: &ProxyState) {
+    let mut store = state.article_versions.lock().unwrap();
+    store.budget = bytes;
+    while store.total_size() > store.budget && store.prune_one() {}
+}
+
+fn content_hash(content: &str) -> String {
+    format!("{:x}", Sha256::digest(content.as_bytes()))
+}
+
+/// Whether a stored version still decodes to the content it was hashed from
+fn is_intact(version: &StoredVersion) -> bool {
+    version.content.decode().is_ok_and(|content| content_hash(&content) == version.content_hash)
+}
+
+/// Maintenance: checks every version against its hash and the ordering of timestamps, then
+/// re-applies the per-article cap and the storage budget. Versions that don't match their
+/// hash are dropped.
+pub fn check_article_versions(dry_run: bool, state: &ProxyState) -> StoreCheck {
+    let mut check = StoreCheck::new("article_versions");
+    let mut store = state.article_versions.lock().unwrap();
+
+    for (url, article) in store.articles.iter_mut() {
+        check.checked += article.versions.len();
+
+        let corrupted = article.versions.iter().filter(|v| !is_intact(v)).count();
+        if corrupted > 0 {
+            check.problem(format!("{}: {} versions don't match their hash", url, corrupted), dry_run);
+            if !dry_run {
+                article.versions.retain(is_intact);
+            }
+        }
+
+        if !article.versions.windows(2).all(|pair| pair[0].timestamp < pair[1].timestamp) {
+            check.problem(format!("{}: version timestamps are out of order", url), dry_run);
+            if !dry_run {
+                article.versions.sort_by_key(|v| v.timestamp);
+                article.versions.dedup_by_key(|v| v.timestamp);
+            }
+        }
+
+        if article.versions.len() > MAX_VERSIONS_PER_ARTICLE {
+            check.problem(format!("{}: {} versions stored, over the cap of {}", url, article.versions.len(), MAX_VERSIONS_PER_ARTICLE), dry_run);
+            while !dry_run && article.versions.len() > MAX_VERSIONS_PER_ARTICLE {
+                match prunable_index(article) {
+                    Some(index) => {
+                        article.versions.remove(index);
+                    }
+                    None => break,
+                }
+            }
+        }
+    }
+
+    let total_size = store.total_size();
+    if total_size > store.budget {
+        check.problem(format!("{} bytes stored, over the budget of {}", total_size, store.budget), dry_run);
+        while !dry_run && store.total_size() > store.budget && store.prune_one() {}
+    }
+    check.sizes(store.total_size(), store.raw_size());
+    check
+}
+
+/// Removes the versions of articles hosted on `domain` and its subdomains
+pub fn clear_versions_for_domain(domain: &str, dry_run: bool, state: &ProxyState) -> MutationReport {
+    let mut report = MutationReport::new(dry_run);
+    let host = host_of_domain_key(domain);
+    let mut store = state.article_versions.lock().unwrap();
+
+    let matching: Vec<String> = store
+        .articles
+        .keys()
+        .filter(|url| {
+            Url::parse(url)
+                .ok()
+                .and_then(|url| url.host_str().map(str::to_ascii_lowercase))
+                .is_some_and(|article_host| host_in_domain(&article_host, &host))
+        })
+        .cloned()
+        .collect();
+    for url in matching {
+        let size = store.articles[&url].versions.iter().map(|v| v.content.stored_len()).sum();
+        report.record("article_versions", url.clone(), Some(size));
+        if !dry_run {
+            store.articles.remove(&url);
+        }
+    }
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::check_clear_for_domain;
+
+    #[test]
+    fn clears_the_versions_of_a_domain() {
+        check_clear_for_domain(
+            |state, host| {
+                let url = format!("https://{}/article", host);
+                logic_track_article_versions(url.clone(), false, state);
+                record_version(&url, "<p>first</p>", state);
+            },
+            clear_versions_for_domain,
+            |state| state.article_versions.lock().unwrap().articles.keys().cloned().collect(),
+        );
+    }
+}